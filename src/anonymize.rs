@@ -0,0 +1,1204 @@
+//! Metadata de-identification following the DICOM PS3.15 Annex E
+//! Basic Application Level Confidentiality Profile.
+//!
+//! Actions come from Table E.1-1 (Basic Profile column). Where the table allows a
+//! choice (`X/Z`, `X/D`, `Z/D`, ...) the least destructive option that still keeps
+//! the IOD valid is used: `X/Z` → Z, anything with a `D` → D. `U*` sequences are
+//! kept and recursed into so the UIDs they reference get replaced. Standard attributes
+//! the table doesn't list are kept; non-private attributes the standard dictionary
+//! doesn't know, such as those of a newer edition, are removed.
+//!
+//! A [`Profile`] can override the table per attribute and change how dates and UIDs
+//! are handled, and keep the content of SR documents ([`crate::sr`]), scrubbed.
+
+use dicom::core::dictionary::DataDictionary;
+use dicom::core::header::Header;
+use dicom::core::smallvec::smallvec;
+use dicom::core::value::DataSetSequence;
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::{StandardDataDictionary, tags};
+use dicom::object::{FileDicomObject, InMemDicomObject};
+
+use crate::profile::{DatePolicy, Profile, UidPolicy};
+use crate::uids::{UidRemapper, hashed_uid, salted_sha1};
+
+/// What to do with an attribute, using the PS3.15 action code names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// K: keep (sequences are still recursed into).
+    Keep,
+    /// X: remove.
+    Remove,
+    /// Z: replace with a zero-length value.
+    Empty,
+    /// D: replace with a non-zero-length dummy value of the same VR.
+    Dummy,
+    /// U: replace with a new UID, consistently within this [`Anonymizer`].
+    ReplaceUid,
+    /// Not in PS3.15: replace with a salted hash of the value (profiles only).
+    Hash,
+    /// Not in PS3.15: move dates by the profile's date shift (profiles only).
+    ShiftDate,
+}
+
+use Action::*;
+
+/// (group, element, action). See [`basic_profile_action`] for attributes not listed.
+const BASIC_PROFILE: &[(u16, u16, Action)] = &[
+    (0x0008, 0x0014, ReplaceUid), // InstanceCreatorUID
+    (0x0008, 0x0015, Remove),     // InstanceCoercionDateTime
+    (0x0008, 0x0017, ReplaceUid), // AcquisitionUID
+    (0x0008, 0x0018, ReplaceUid), // SOPInstanceUID
+    (0x0008, 0x0019, ReplaceUid), // PyramidUID
+    (0x0008, 0x0020, Empty),      // StudyDate
+    (0x0008, 0x0021, Dummy),      // SeriesDate
+    (0x0008, 0x0022, Empty),      // AcquisitionDate
+    (0x0008, 0x0023, Dummy),      // ContentDate
+    (0x0008, 0x0024, Remove),     // OverlayDate
+    (0x0008, 0x0025, Remove),     // CurveDate
+    (0x0008, 0x002A, Dummy),      // AcquisitionDateTime
+    (0x0008, 0x0030, Empty),      // StudyTime
+    (0x0008, 0x0031, Dummy),      // SeriesTime
+    (0x0008, 0x0032, Empty),      // AcquisitionTime
+    (0x0008, 0x0033, Dummy),      // ContentTime
+    (0x0008, 0x0034, Remove),     // OverlayTime
+    (0x0008, 0x0035, Remove),     // CurveTime
+    (0x0008, 0x0050, Empty),      // AccessionNumber
+    (0x0008, 0x0058, ReplaceUid), // FailedSOPInstanceUIDList
+    (0x0008, 0x0080, Dummy),      // InstitutionName
+    (0x0008, 0x0081, Remove),     // InstitutionAddress
+    (0x0008, 0x0082, Empty),      // InstitutionCodeSequence
+    (0x0008, 0x0090, Empty),      // ReferringPhysicianName
+    (0x0008, 0x0092, Remove),     // ReferringPhysicianAddress
+    (0x0008, 0x0094, Remove),     // ReferringPhysicianTelephoneNumbers
+    (0x0008, 0x0096, Remove),     // ReferringPhysicianIdentificationSequence
+    (0x0008, 0x0201, Remove),     // TimezoneOffsetFromUTC
+    (0x0008, 0x1010, Dummy),      // StationName
+    (0x0008, 0x1030, Remove),     // StudyDescription
+    (0x0008, 0x103E, Remove),     // SeriesDescription
+    (0x0008, 0x1040, Remove),     // InstitutionalDepartmentName
+    (0x0008, 0x1048, Remove),     // PhysiciansOfRecord
+    (0x0008, 0x1049, Remove),     // PhysiciansOfRecordIdentificationSequence
+    (0x0008, 0x1050, Remove),     // PerformingPhysicianName
+    (0x0008, 0x1052, Remove),     // PerformingPhysicianIdentificationSequence
+    (0x0008, 0x1060, Remove),     // NameOfPhysiciansReadingStudy
+    (0x0008, 0x1062, Remove),     // PhysiciansReadingStudyIdentificationSequence
+    (0x0008, 0x1070, Dummy),      // OperatorsName
+    (0x0008, 0x1072, Dummy),      // OperatorIdentificationSequence
+    (0x0008, 0x1080, Remove),     // AdmittingDiagnosesDescription
+    (0x0008, 0x1084, Remove),     // AdmittingDiagnosesCodeSequence
+    (0x0008, 0x1110, Empty),      // ReferencedStudySequence
+    (0x0008, 0x1111, Empty),      // ReferencedPerformedProcedureStepSequence
+    (0x0008, 0x1120, Remove),     // ReferencedPatientSequence
+    (0x0008, 0x1155, ReplaceUid), // ReferencedSOPInstanceUID
+    (0x0008, 0x1195, ReplaceUid), // TransactionUID
+    (0x0008, 0x2111, Remove),     // DerivationDescription
+    (0x0008, 0x3010, ReplaceUid), // IrradiationEventUID
+    (0x0008, 0x4000, Remove),     // IdentifyingComments
+    (0x0008, 0x9123, ReplaceUid), // CreatorVersionUID
+    (0x0010, 0x0010, Empty),      // PatientName
+    (0x0010, 0x0020, Empty),      // PatientID
+    (0x0010, 0x0021, Remove),     // IssuerOfPatientID
+    (0x0010, 0x0024, Remove),     // IssuerOfPatientIDQualifiersSequence
+    (0x0010, 0x0030, Empty),      // PatientBirthDate
+    (0x0010, 0x0032, Remove),     // PatientBirthTime
+    (0x0010, 0x0040, Empty),      // PatientSex
+    (0x0010, 0x0050, Remove),     // PatientInsurancePlanCodeSequence
+    (0x0010, 0x0101, Remove),     // PatientPrimaryLanguageCodeSequence
+    (0x0010, 0x0102, Remove),     // PatientPrimaryLanguageModifierCodeSequence
+    (0x0010, 0x1000, Remove),     // OtherPatientIDs
+    (0x0010, 0x1001, Remove),     // OtherPatientNames
+    (0x0010, 0x1002, Remove),     // OtherPatientIDsSequence
+    (0x0010, 0x1005, Remove),     // PatientBirthName
+    (0x0010, 0x1010, Remove),     // PatientAge
+    (0x0010, 0x1020, Remove),     // PatientSize
+    (0x0010, 0x1030, Remove),     // PatientWeight
+    (0x0010, 0x1040, Remove),     // PatientAddress
+    (0x0010, 0x1050, Remove),     // InsurancePlanIdentification
+    (0x0010, 0x1060, Remove),     // PatientMotherBirthName
+    (0x0010, 0x1080, Remove),     // MilitaryRank
+    (0x0010, 0x1081, Remove),     // BranchOfService
+    (0x0010, 0x1090, Remove),     // MedicalRecordLocator
+    (0x0010, 0x1100, Remove),     // ReferencedPatientPhotoSequence
+    (0x0010, 0x2000, Remove),     // MedicalAlerts
+    (0x0010, 0x2110, Remove),     // Allergies
+    (0x0010, 0x2150, Remove),     // CountryOfResidence
+    (0x0010, 0x2152, Remove),     // RegionOfResidence
+    (0x0010, 0x2154, Remove),     // PatientTelephoneNumbers
+    (0x0010, 0x2160, Remove),     // EthnicGroup
+    (0x0010, 0x2180, Remove),     // Occupation
+    (0x0010, 0x21A0, Remove),     // SmokingStatus
+    (0x0010, 0x21B0, Remove),     // AdditionalPatientHistory
+    (0x0010, 0x21C0, Remove),     // PregnancyStatus
+    (0x0010, 0x21D0, Remove),     // LastMenstrualDate
+    (0x0010, 0x21F0, Remove),     // PatientReligiousPreference
+    (0x0010, 0x2203, Empty),      // PatientSexNeutered
+    (0x0010, 0x2297, Empty),      // ResponsiblePerson
+    (0x0010, 0x2299, Empty),      // ResponsibleOrganization
+    (0x0010, 0x4000, Remove),     // PatientComments
+    (0x0018, 0x1000, Dummy),      // DeviceSerialNumber
+    (0x0018, 0x1002, ReplaceUid), // DeviceUID
+    (0x0018, 0x1004, Remove),     // PlateID
+    (0x0018, 0x1005, Remove),     // GeneratorID
+    (0x0018, 0x1007, Remove),     // CassetteID
+    (0x0018, 0x1008, Remove),     // GantryID
+    (0x0018, 0x100B, ReplaceUid), // ManufacturerDeviceClassUID
+    (0x0018, 0x1030, Dummy),      // ProtocolName
+    (0x0018, 0x4000, Remove),     // AcquisitionComments
+    (0x0018, 0x700A, Dummy),      // DetectorID
+    (0x0018, 0x9424, Remove),     // AcquisitionProtocolDescription
+    (0x0020, 0x000D, ReplaceUid), // StudyInstanceUID
+    (0x0020, 0x000E, ReplaceUid), // SeriesInstanceUID
+    (0x0020, 0x0010, Empty),      // StudyID
+    (0x0020, 0x0052, ReplaceUid), // FrameOfReferenceUID
+    (0x0020, 0x0200, ReplaceUid), // SynchronizationFrameOfReferenceUID
+    (0x0020, 0x3401, Remove),     // ModifyingDeviceID
+    (0x0020, 0x3404, Remove),     // ModifyingDeviceManufacturer
+    (0x0020, 0x3406, Remove),     // ModifiedImageDescription
+    (0x0020, 0x4000, Remove),     // ImageComments
+    (0x0020, 0x9158, Remove),     // FrameComments
+    (0x0020, 0x9161, ReplaceUid), // ConcatenationUID
+    (0x0020, 0x9164, ReplaceUid), // DimensionOrganizationUID
+    (0x0028, 0x1199, ReplaceUid), // PaletteColorLookupTableUID
+    (0x0028, 0x1214, ReplaceUid), // LargePaletteColorLookupTableUID
+    (0x0028, 0x4000, Remove),     // ImagePresentationComments
+    (0x0032, 0x0012, Remove),     // StudyIDIssuer
+    (0x0032, 0x1020, Remove),     // ScheduledStudyLocation
+    (0x0032, 0x1021, Remove),     // ScheduledStudyLocationAETitle
+    (0x0032, 0x1030, Remove),     // ReasonForStudy
+    (0x0032, 0x1032, Remove),     // RequestingPhysician
+    (0x0032, 0x1033, Remove),     // RequestingService
+    (0x0032, 0x1060, Empty),      // RequestedProcedureDescription
+    (0x0032, 0x1070, Remove),     // RequestedContrastAgent
+    (0x0032, 0x4000, Remove),     // StudyComments
+    (0x0038, 0x0010, Remove),     // AdmissionID
+    (0x0038, 0x0011, Remove),     // IssuerOfAdmissionID
+    (0x0038, 0x001E, Remove),     // ScheduledPatientInstitutionResidence
+    (0x0038, 0x0020, Remove),     // AdmittingDate
+    (0x0038, 0x0021, Remove),     // AdmittingTime
+    (0x0038, 0x0040, Remove),     // DischargeDiagnosisDescription
+    (0x0038, 0x0050, Remove),     // SpecialNeeds
+    (0x0038, 0x0060, Remove),     // ServiceEpisodeID
+    (0x0038, 0x0061, Remove),     // IssuerOfServiceEpisodeID
+    (0x0038, 0x0062, Remove),     // ServiceEpisodeDescription
+    (0x0038, 0x0300, Remove),     // CurrentPatientLocation
+    (0x0038, 0x0400, Remove),     // PatientInstitutionResidence
+    (0x0038, 0x0500, Remove),     // PatientState
+    (0x0038, 0x4000, Remove),     // VisitComments
+    (0x0040, 0x0001, Remove),     // ScheduledStationAETitle
+    (0x0040, 0x0002, Remove),     // ScheduledProcedureStepStartDate
+    (0x0040, 0x0003, Remove),     // ScheduledProcedureStepStartTime
+    (0x0040, 0x0004, Remove),     // ScheduledProcedureStepEndDate
+    (0x0040, 0x0005, Remove),     // ScheduledProcedureStepEndTime
+    (0x0040, 0x0006, Remove),     // ScheduledPerformingPhysicianName
+    (0x0040, 0x0007, Remove),     // ScheduledProcedureStepDescription
+    (0x0040, 0x000B, Remove),     // ScheduledPerformingPhysicianIdentificationSequence
+    (0x0040, 0x0010, Remove),     // ScheduledStationName
+    (0x0040, 0x0011, Remove),     // ScheduledProcedureStepLocation
+    (0x0040, 0x0012, Remove),     // PreMedication
+    (0x0040, 0x0241, Remove),     // PerformedStationAETitle
+    (0x0040, 0x0242, Remove),     // PerformedStationName
+    (0x0040, 0x0243, Remove),     // PerformedLocation
+    (0x0040, 0x0244, Remove),     // PerformedProcedureStepStartDate
+    (0x0040, 0x0245, Remove),     // PerformedProcedureStepStartTime
+    (0x0040, 0x0250, Remove),     // PerformedProcedureStepEndDate
+    (0x0040, 0x0251, Remove),     // PerformedProcedureStepEndTime
+    (0x0040, 0x0253, Remove),     // PerformedProcedureStepID
+    (0x0040, 0x0254, Remove),     // PerformedProcedureStepDescription
+    (0x0040, 0x0275, Remove),     // RequestAttributesSequence
+    (0x0040, 0x0280, Remove),     // CommentsOnThePerformedProcedureStep
+    (0x0040, 0x0554, ReplaceUid), // SpecimenUID
+    (0x0040, 0x0555, Remove),     // AcquisitionContextSequence
+    (0x0040, 0x1001, Remove),     // RequestedProcedureID
+    (0x0040, 0x1004, Remove),     // PatientTransportArrangements
+    (0x0040, 0x1005, Remove),     // RequestedProcedureLocation
+    (0x0040, 0x1010, Remove),     // NamesOfIntendedRecipientsOfResults
+    (0x0040, 0x1011, Remove),     // IntendedRecipientsOfResultsIdentificationSequence
+    (0x0040, 0x1102, Remove),     // PersonAddress
+    (0x0040, 0x1103, Remove),     // PersonTelephoneNumbers
+    (0x0040, 0x1400, Remove),     // RequestedProcedureComments
+    (0x0040, 0x2001, Remove),     // ReasonForTheImagingServiceRequest
+    (0x0040, 0x2008, Remove),     // OrderEnteredBy
+    (0x0040, 0x2009, Remove),     // OrderEntererLocation
+    (0x0040, 0x2010, Remove),     // OrderCallbackPhoneNumber
+    (0x0040, 0x2016, Empty),      // PlacerOrderNumberImagingServiceRequest
+    (0x0040, 0x2017, Empty),      // FillerOrderNumberImagingServiceRequest
+    (0x0040, 0x2400, Remove),     // ImagingServiceRequestComments
+    (0x0040, 0x3001, Remove),     // ConfidentialityConstraintOnPatientDataDescription
+    (0x0040, 0xA027, Remove),     // VerifyingOrganization
+    (0x0040, 0xA073, Dummy),      // VerifyingObserverSequence
+    (0x0040, 0xA075, Dummy),      // VerifyingObserverName
+    (0x0040, 0xA078, Remove),     // AuthorObserverSequence
+    (0x0040, 0xA07A, Remove),     // ParticipantSequence
+    (0x0040, 0xA07C, Remove),     // CustodialOrganizationSequence
+    (0x0040, 0xA088, Empty),      // VerifyingObserverIdentificationCodeSequence
+    (0x0040, 0xA123, Dummy),      // PersonName
+    (0x0040, 0xA124, ReplaceUid), // UID
+    (0x0040, 0xA171, ReplaceUid), // ObservationUID
+    (0x0040, 0xA730, Remove),     // ContentSequence
+    (0x0040, 0xDB0C, ReplaceUid), // TemplateExtensionOrganizationUID
+    (0x0040, 0xDB0D, ReplaceUid), // TemplateExtensionCreatorUID
+    (0x0062, 0x0021, ReplaceUid), // TrackingUID
+    (0x0070, 0x0084, Empty),      // ContentCreatorName
+    (0x0070, 0x0086, Remove),     // ContentCreatorIdentificationCodeSequence
+    (0x0070, 0x031A, ReplaceUid), // FiducialUID
+    (0x0088, 0x0140, ReplaceUid), // StorageMediaFileSetUID
+    (0x0088, 0x0200, Remove),     // IconImageSequence
+    (0x0088, 0x0904, Remove),     // TopicTitle
+    (0x0088, 0x0906, Remove),     // TopicSubject
+    (0x0088, 0x0910, Remove),     // TopicAuthor
+    (0x0088, 0x0912, Remove),     // TopicKeywords
+    (0x0400, 0x0100, Remove),     // DigitalSignatureUID
+    (0x0400, 0x0402, Remove),     // ReferencedDigitalSignatureSequence
+    (0x0400, 0x0403, Remove),     // ReferencedSOPInstanceMACSequence
+    (0x0400, 0x0404, Remove),     // MAC
+    (0x0400, 0x0550, Remove),     // ModifiedAttributesSequence
+    (0x0400, 0x0561, Remove),     // OriginalAttributesSequence
+    (0x2030, 0x0020, Remove),     // TextString
+    (0x3006, 0x0002, Dummy),      // StructureSetLabel
+    (0x3006, 0x0004, Remove),     // StructureSetName
+    (0x3006, 0x0006, Remove),     // StructureSetDescription
+    (0x3006, 0x0008, Dummy),      // StructureSetDate
+    (0x3006, 0x0009, Dummy),      // StructureSetTime
+    (0x3006, 0x0024, ReplaceUid), // ReferencedFrameOfReferenceUID
+    (0x3006, 0x0028, Remove),     // ROIDescription
+    (0x3006, 0x0038, Remove),     // ROIGenerationDescription
+    (0x3006, 0x004D, Remove),     // ROICreatorSequence
+    (0x3006, 0x004E, Remove),     // ROIInterpreterSequence
+    (0x3006, 0x0085, Remove),     // ROIObservationLabel
+    (0x3006, 0x0088, Remove),     // ROIObservationDescription
+    (0x3006, 0x00A6, Empty),      // ROIInterpreter
+    (0x3006, 0x00C2, ReplaceUid), // RelatedFrameOfReferenceUID
+    (0x3008, 0x0054, Dummy),      // FirstTreatmentDate
+    (0x3008, 0x0056, Dummy),      // MostRecentTreatmentDate
+    (0x3008, 0x0105, Empty),      // SourceSerialNumber
+    (0x3008, 0x0250, Dummy),      // TreatmentDate
+    (0x3008, 0x0251, Dummy),      // TreatmentTime
+    (0x300A, 0x0002, Dummy),      // RTPlanLabel
+    (0x300A, 0x0003, Remove),     // RTPlanName
+    (0x300A, 0x0004, Remove),     // RTPlanDescription
+    (0x300A, 0x0006, Dummy),      // RTPlanDate
+    (0x300A, 0x0007, Dummy),      // RTPlanTime
+    (0x300A, 0x000E, Remove),     // PrescriptionDescription
+    (0x300A, 0x0013, ReplaceUid), // DoseReferenceUID
+    (0x300A, 0x0016, Remove),     // DoseReferenceDescription
+    (0x300A, 0x0072, Remove),     // FractionGroupDescription
+    (0x300A, 0x0083, ReplaceUid), // ReferencedDoseReferenceUID
+    (0x300A, 0x00B2, Dummy),      // TreatmentMachineName
+    (0x300A, 0x00C3, Remove),     // BeamDescription
+    (0x300A, 0x0650, ReplaceUid), // PatientSetupUID
+    (0x300A, 0x0700, ReplaceUid), // TreatmentSessionUID
+    (0x300C, 0x0113, Remove),     // ReasonForOmissionDescription
+    (0x300E, 0x0004, Dummy),      // ReviewDate
+    (0x300E, 0x0005, Dummy),      // ReviewTime
+    (0x300E, 0x0008, Empty),      // ReviewerName
+    (0x3010, 0x0006, ReplaceUid), // ConceptualVolumeUID
+    (0x3010, 0x000B, ReplaceUid), // ReferencedConceptualVolumeUID
+    (0x3010, 0x0013, ReplaceUid), // ConstituentConceptualVolumeUID
+    (0x3010, 0x0015, ReplaceUid), // SourceConceptualVolumeUID
+    (0x3010, 0x0036, Remove),     // EntityName
+    (0x3010, 0x0037, Remove),     // EntityDescription
+    (0x3010, 0x0038, Dummy),      // EntityLabel
+    (0x3010, 0x003B, ReplaceUid), // RTTreatmentPhaseUID
+    (0x3010, 0x0054, Dummy),      // RTPrescriptionLabel
+    (0x3010, 0x005A, Remove),     // RTPhysicianIntentNarrative
+    (0x3010, 0x005C, Remove),     // ReasonForSuperseding
+    (0x3010, 0x0061, Remove),     // PriorTreatmentDoseDescription
+    (0x3010, 0x006E, ReplaceUid), // DosimetricObjectiveUID
+    (0x3010, 0x006F, ReplaceUid), // ReferencedDosimetricObjectiveUID
+    (0x3010, 0x0077, Dummy),      // TreatmentSite
+    (0x3010, 0x007A, Remove),     // TreatmentTechniqueNotes
+    (0x4000, 0x0010, Remove),     // Arbitrary
+    (0x4000, 0x4000, Remove),     // TextComments
+    (0xFFFA, 0xFFFA, Remove),     // DigitalSignaturesSequence
+    (0xFFFC, 0xFFFC, Remove),     // DataSetTrailingPadding
+];
+
+/// Basic Profile action for a tag. Attributes not in the table are kept if they are
+/// standard or private (private tags have their own option), and removed otherwise:
+/// an attribute this build doesn't know may well hold PHI.
+pub fn basic_profile_action(tag: Tag) -> Action {
+    BASIC_PROFILE
+        .iter()
+        .find(|(g, e, _)| Tag(*g, *e) == tag)
+        .map(|(_, _, a)| *a)
+        .unwrap_or_else(|| {
+            if is_private(tag) || StandardDataDictionary.by_tag(tag).is_some() {
+                Keep
+            } else {
+                Remove
+            }
+        })
+}
+
+/// Private data elements live in odd groups.
+pub fn is_private(tag: Tag) -> bool {
+    tag.group() % 2 == 1
+}
+
+/// Private creator elements, (gggg,0010-00FF), reserve the block (gggg,xx00-xxFF).
+fn is_private_creator(tag: Tag) -> bool {
+    is_private(tag) && (0x0010..=0x00FF).contains(&tag.element())
+}
+
+/// Remove the private attributes directly in `obj`, except the blocks reserved by a
+/// private creator in `keep_creators` (compared case-insensitively).
+fn strip_private_level(obj: &mut InMemDicomObject, keep_creators: &[String]) {
+    let kept: Vec<(u16, u16)> = obj
+        .iter()
+        .filter(|e| is_private_creator(e.tag()))
+        .filter(|e| {
+            e.to_str().is_ok_and(|creator| {
+                keep_creators
+                    .iter()
+                    .any(|k| k.trim().eq_ignore_ascii_case(creator.trim()))
+            })
+        })
+        .map(|e| (e.tag().group(), e.tag().element()))
+        .collect();
+    let private: Vec<Tag> = obj
+        .iter()
+        .map(|e| e.tag())
+        .filter(|t| is_private(*t))
+        .collect();
+    for tag in private {
+        let (group, element) = (tag.group(), tag.element());
+        let block = if is_private_creator(tag) {
+            element
+        } else {
+            element >> 8
+        };
+        if !kept.contains(&(group, block)) {
+            obj.remove_element(tag);
+        }
+    }
+}
+
+/// Remove private attributes at every nesting level, keeping the blocks of the
+/// private creators in `keep_creators`.
+pub fn strip_private(obj: &mut InMemDicomObject, keep_creators: &[String]) {
+    strip_private_level(obj, keep_creators);
+    let tags: Vec<Tag> = obj.iter().map(|e| e.tag()).collect();
+    for tag in tags {
+        obj.update_value(tag, |value| {
+            if let Some(items) = value.items_mut() {
+                for item in items.iter_mut() {
+                    strip_private(item, keep_creators);
+                }
+            }
+        });
+    }
+}
+
+pub use crate::uids::new_uid;
+
+/// De-identification methods from CID 7050, as (code value, code meaning).
+pub mod methods {
+    pub const BASIC_PROFILE: (&str, &str) = ("113100", "Basic Application Confidentiality Profile");
+    pub const CLEAN_PIXEL_DATA: (&str, &str) = ("113101", "Clean Pixel Data Option");
+    pub const CLEAN_GRAPHICS: (&str, &str) = ("113103", "Clean Graphics Option");
+    pub const RETAIN_FULL_DATES: (&str, &str) = (
+        "113106",
+        "Retain Longitudinal Temporal Information Full Dates Option",
+    );
+    pub const RETAIN_MODIFIED_DATES: (&str, &str) = (
+        "113107",
+        "Retain Longitudinal Temporal Information Modified Dates Option",
+    );
+    pub const RETAIN_UIDS: (&str, &str) = ("113110", "Retain UIDs Option");
+    pub const RETAIN_SAFE_PRIVATE: (&str, &str) = ("113111", "Retain Safe Private Option");
+}
+
+/// Mark `obj` as de-identified: PatientIdentityRemoved = YES, with
+/// DeidentificationMethod and DeidentificationMethodCodeSequence listing `methods`
+/// (see [`methods`]).
+pub fn record_deidentification(obj: &mut InMemDicomObject, methods: &[(&str, &str)]) {
+    obj.put(DataElement::new(
+        tags::PATIENT_IDENTITY_REMOVED,
+        VR::CS,
+        PrimitiveValue::from("YES"),
+    ));
+    let mut description = vec![crate::uids::IMPLEMENTATION_VERSION_NAME.to_string()];
+    description.extend(methods.iter().map(|(_, meaning)| meaning.to_string()));
+    obj.put(DataElement::new(
+        tags::DEIDENTIFICATION_METHOD,
+        VR::LO,
+        PrimitiveValue::Strs(description.into()),
+    ));
+    let items: Vec<InMemDicomObject> = methods
+        .iter()
+        .map(|(value, meaning)| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::CODE_VALUE, VR::SH, PrimitiveValue::from(*value)),
+                DataElement::new(
+                    tags::CODING_SCHEME_DESIGNATOR,
+                    VR::SH,
+                    PrimitiveValue::from("DCM"),
+                ),
+                DataElement::new(tags::CODE_MEANING, VR::LO, PrimitiveValue::from(*meaning)),
+            ])
+        })
+        .collect();
+    obj.put(DataElement::new(
+        tags::DEIDENTIFICATION_METHOD_CODE_SEQUENCE,
+        VR::SQ,
+        DataSetSequence::from(items),
+    ));
+}
+
+/// Add `text` to DerivationDescription (ST, at most 1024 characters), after any
+/// description already there.
+pub(crate) fn append_derivation_description(obj: &mut InMemDicomObject, text: &str) {
+    let existing = obj
+        .element(tags::DERIVATION_DESCRIPTION)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default();
+    if existing.contains(text) {
+        return;
+    }
+    let mut description = if existing.is_empty() {
+        text.to_string()
+    } else {
+        format!("{existing}; {text}")
+    };
+    if let Some((cut, _)) = description.char_indices().nth(1024) {
+        description.truncate(cut);
+    }
+    obj.put(DataElement::new(
+        tags::DERIVATION_DESCRIPTION,
+        VR::ST,
+        PrimitiveValue::from(description),
+    ));
+}
+
+/// Mark `obj` as derived from its source: the first value of ImageType becomes
+/// DERIVED and `description` is added to DerivationDescription.
+pub fn mark_derived(obj: &mut InMemDicomObject, description: &str) {
+    if let Some(mut values) = obj
+        .element(tags::IMAGE_TYPE)
+        .ok()
+        .and_then(|e| e.to_multi_str().ok())
+        .map(|v| v.iter().map(|s| s.trim().to_string()).collect::<Vec<_>>())
+        && !values.is_empty()
+    {
+        values[0] = "DERIVED".to_string();
+        obj.put(DataElement::new(
+            tags::IMAGE_TYPE,
+            VR::CS,
+            PrimitiveValue::Strs(values.into()),
+        ));
+    }
+    append_derivation_description(obj, description);
+}
+
+/// Mark `obj` as derived from its source by editing the pixel data, as
+/// [`mark_derived`] does, and add a Clean Pixel Data item to DerivationCodeSequence
+/// (the CID is extensible, and no Image Derivation code says "burned-in information
+/// removed").
+pub fn record_derivation(obj: &mut InMemDicomObject, description: &str) {
+    mark_derived(obj, description);
+
+    let (value, meaning) = methods::CLEAN_PIXEL_DATA;
+    let mut items: Vec<InMemDicomObject> = obj
+        .element(tags::DERIVATION_CODE_SEQUENCE)
+        .ok()
+        .and_then(|e| e.items())
+        .map(<[_]>::to_vec)
+        .unwrap_or_default();
+    let has_code = items.iter().any(|item| {
+        item.element(tags::CODE_VALUE)
+            .ok()
+            .and_then(|e| e.to_str().ok())
+            .is_some_and(|v| v.trim() == value)
+    });
+    if !has_code {
+        items.push(InMemDicomObject::from_element_iter([
+            DataElement::new(tags::CODE_VALUE, VR::SH, PrimitiveValue::from(value)),
+            DataElement::new(
+                tags::CODING_SCHEME_DESIGNATOR,
+                VR::SH,
+                PrimitiveValue::from("DCM"),
+            ),
+            DataElement::new(tags::CODE_MEANING, VR::LO, PrimitiveValue::from(meaning)),
+        ]));
+    }
+    obj.put(DataElement::new(
+        tags::DERIVATION_CODE_SEQUENCE,
+        VR::SQ,
+        DataSetSequence::from(items),
+    ));
+}
+
+/// 16 hex digits of the salted SHA-1 of `value`: fits every string VR (SH is 16).
+fn hash_value(salt: &str, value: &str) -> String {
+    salted_sha1(salt, value.trim())[..8]
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect()
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Inverse of [`days_from_civil`].
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(m <= 2), m, d)
+}
+
+/// Move a DA (`YYYYMMDD`) or DT (`YYYYMMDD...`) value by `days`, keeping any time part.
+/// `None` if it doesn't start with a full date.
+fn shift_date(value: &str, days: i64) -> Option<String> {
+    let value = value.trim();
+    let date = value.get(..8)?;
+    if !date.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (y, m, d) = (
+        date[..4].parse().ok()?,
+        date[4..6].parse().ok()?,
+        date[6..].parse().ok()?,
+    );
+    let (y, m, d) = civil_from_days(days_from_civil(y, m, d) + days);
+    Some(format!("{y:04}{m:02}{d:02}{}", &value[8..]))
+}
+
+/// What patient identifiers found in free text are replaced with.
+const IDENTIFIER_REPLACEMENT: &str = "ANON";
+
+/// Name parts and IDs shorter than this aren't looked for in free text; initials and
+/// short IDs match too much that isn't them.
+const MIN_IDENTIFIER_LEN: usize = 3;
+
+/// The patient's identifiers in `source`, lowercase, longest first: the parts of
+/// their names and their IDs, for [`replace_identifiers`].
+pub fn patient_identifiers(source: &InMemDicomObject) -> Vec<String> {
+    let text = |tag| {
+        source
+            .element(tag)
+            .ok()
+            .and_then(|e| e.to_str().ok())
+            .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+            .unwrap_or_default()
+    };
+    let mut ids = Vec::new();
+    for tag in [tags::PATIENT_NAME, tags::OTHER_PATIENT_NAMES] {
+        ids.extend(
+            text(tag)
+                .split(['^', '=', '\\', ' ', ','])
+                .map(str::to_string),
+        );
+    }
+    for tag in [tags::PATIENT_ID, tags::OTHER_PATIENT_IDS] {
+        ids.extend(text(tag).split('\\').map(|s| s.trim().to_string()));
+    }
+    let mut ids: Vec<String> = ids
+        .into_iter()
+        .map(|id| id.to_ascii_lowercase())
+        .filter(|id| id.len() >= MIN_IDENTIFIER_LEN)
+        .collect();
+    // Longest first, so a whole ID is replaced before a name part inside it
+    ids.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    ids.dedup();
+    ids
+}
+
+/// `value` with every occurrence of `ids` (from [`patient_identifiers`], ignoring
+/// ASCII case) replaced with `ANON`, or `None` if none occur.
+pub fn replace_identifiers(value: &str, ids: &[String]) -> Option<String> {
+    let mut out = value.to_string();
+    let mut changed = false;
+    for id in ids {
+        let mut from = 0;
+        while let Some(at) = out.to_ascii_lowercase()[from..].find(id.as_str()) {
+            let at = from + at;
+            out.replace_range(at..at + id.len(), IDENTIFIER_REPLACEMENT);
+            from = at + IDENTIFIER_REPLACEMENT.len();
+            changed = true;
+        }
+    }
+    changed.then_some(out)
+}
+
+/// Values of SR content items (PNAME, TEXT, DATE, TIME, DATETIME). They are required
+/// in their item, so they are replaced rather than removed or emptied.
+const CONTENT_VALUES: [Tag; 5] = [
+    tags::PERSON_NAME,
+    tags::TEXT_VALUE,
+    tags::DATE,
+    tags::TIME,
+    tags::DATE_TIME,
+];
+
+/// Type 2 dates and times of the common modules: a date policy that removes dates
+/// empties these instead, since the attribute itself is required.
+const TYPE_2_DATES: [Tag; 4] = [
+    tags::STUDY_DATE,
+    tags::STUDY_TIME,
+    tags::CONTENT_DATE,
+    tags::CONTENT_TIME,
+];
+
+fn dummy_value(vr: VR) -> PrimitiveValue {
+    match vr {
+        VR::DA => PrimitiveValue::from("19000101"),
+        VR::TM => PrimitiveValue::from("000000.00"),
+        VR::DT => PrimitiveValue::from("19000101000000.00"),
+        VR::AS => PrimitiveValue::from("000Y"),
+        VR::IS | VR::DS => PrimitiveValue::from("0"),
+        VR::US => PrimitiveValue::from(0u16),
+        VR::SS => PrimitiveValue::from(0i16),
+        VR::UL => PrimitiveValue::from(0u32),
+        VR::SL => PrimitiveValue::from(0i32),
+        VR::FL => PrimitiveValue::F32(smallvec![0.0]),
+        VR::FD => PrimitiveValue::F64(smallvec![0.0]),
+        VR::UI => PrimitiveValue::from(new_uid()),
+        _ => PrimitiveValue::from("ANONYMIZED"),
+    }
+}
+
+/// Applies the Basic Profile to datasets. UIDs are replaced through a [`UidRemapper`],
+/// so the same source UID always maps to the same new one, across every file that
+/// shares the remapper.
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    /// Remove private (odd-group) attributes, as the Basic Profile requires.
+    pub remove_private: bool,
+    /// Private creators whose blocks are known to be safe and kept anyway.
+    pub keep_private_creators: Vec<String>,
+    uids: UidRemapper,
+    profile: Profile,
+}
+
+impl Default for Anonymizer {
+    fn default() -> Self {
+        Self {
+            remove_private: true,
+            keep_private_creators: Vec::new(),
+            uids: UidRemapper::new(),
+            profile: Profile::default(),
+        }
+    }
+}
+
+impl Anonymizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An anonymizer replacing UIDs through `uids` (e.g. one shared by a whole study).
+    pub fn with_remapper(uids: UidRemapper) -> Self {
+        Self {
+            uids,
+            ..Self::default()
+        }
+    }
+
+    /// Replace UIDs through `uids` from now on, keeping the other settings.
+    pub fn set_remapper(&mut self, uids: UidRemapper) {
+        self.uids = uids;
+    }
+
+    /// Old → new UID replacements made so far.
+    pub fn uid_map(&self) -> &UidRemapper {
+        &self.uids
+    }
+
+    /// Replacement for `uid`, generating (and remembering) one on first use.
+    pub fn map_uid(&mut self, uid: &str) -> String {
+        match self.profile.uids {
+            UidPolicy::Hash => {
+                let salt = &self.profile.hash_salt;
+                self.uids.map_with(uid, |uid| hashed_uid(salt, uid))
+            }
+            UidPolicy::Remap | UidPolicy::Keep => self.uids.map(uid),
+        }
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = profile;
+    }
+
+    /// The action for `tag`: the profile's explicit lists first, then its date and UID
+    /// policies applied on top of the Basic Profile.
+    ///
+    /// The date policy only changes dates and times the Basic Profile empties, replaces
+    /// or keeps; those it removes stay removed. PatientBirthDate always follows the
+    /// Basic Profile, as none of the PS3.15 options for retaining dates covers it.
+    pub fn action(&self, tag: Tag, vr: VR) -> Action {
+        let p = &self.profile;
+        if p.keep.contains(&tag) {
+            return Keep;
+        }
+        if p.remove.contains(&tag) {
+            return Remove;
+        }
+        if p.blank.contains(&tag) {
+            return Empty;
+        }
+        if p.hash.contains(&tag) {
+            return Hash;
+        }
+        let base = if p.basic_profile {
+            basic_profile_action(tag)
+        } else {
+            Keep
+        };
+        let temporal = matches!(vr, VR::DA | VR::DT | VR::TM)
+            && matches!(base, Keep | Empty | Dummy)
+            && tag != tags::PATIENT_BIRTH_DATE;
+        if temporal {
+            match (vr, p.dates) {
+                (_, DatePolicy::Profile) => {}
+                (_, DatePolicy::Keep) => return Keep,
+                // Required attributes are emptied rather than removed
+                (_, DatePolicy::Remove) => {
+                    return match base {
+                        Keep if !TYPE_2_DATES.contains(&tag) => Remove,
+                        Keep | Empty => Empty,
+                        _ => base,
+                    };
+                }
+                (VR::TM, DatePolicy::Shift(_)) => return Keep,
+                (_, DatePolicy::Shift(_)) => return ShiftDate,
+            }
+        }
+        match (base, p.uids) {
+            (ReplaceUid, UidPolicy::Keep) => Keep,
+            _ => base,
+        }
+    }
+
+    /// The action for `tag` in an SR content item. Item values the profile would remove
+    /// or empty get dummy values instead, and so do those the Basic Profile only keeps
+    /// because it removes the whole ContentSequence, unless the profile keeps them.
+    fn content_action(&self, tag: Tag, vr: VR) -> Action {
+        let p = &self.profile;
+        if !CONTENT_VALUES.contains(&tag) {
+            return self.action(tag, vr);
+        }
+        let dates_kept = matches!(
+            (vr, p.dates),
+            (VR::DA | VR::DT | VR::TM, DatePolicy::Keep) | (VR::TM, DatePolicy::Shift(_))
+        );
+        match self.action(tag, vr) {
+            Remove | Empty => Dummy,
+            Keep if p.basic_profile && !p.keep.contains(&tag) && !dates_kept => Dummy,
+            action => action,
+        }
+    }
+
+    /// Replace every ReferencedSOPInstanceUID (and ReferencedSOPInstanceUIDInFile) in
+    /// `obj`, at any depth, through the UID map. For when only SOP Instance UIDs are
+    /// regenerated: the referenced instances get the same new UIDs when they are saved
+    /// through the same map, so ReferencedImageSequence, SourceImageSequence and other
+    /// references between them still resolve.
+    pub fn remap_references(&mut self, obj: &mut InMemDicomObject) {
+        let tags: Vec<Tag> = obj.iter().map(|e| e.tag()).collect();
+        for tag in tags {
+            if tag == tags::REFERENCED_SOP_INSTANCE_UID
+                || tag == tags::REFERENCED_SOP_INSTANCE_UID_IN_FILE
+            {
+                let Some(old) = obj.element(tag).ok().and_then(|e| e.to_multi_str().ok()) else {
+                    continue;
+                };
+                let new: Vec<String> = old.iter().map(|u| self.map_uid(u)).collect();
+                obj.put(DataElement::new(
+                    tag,
+                    VR::UI,
+                    PrimitiveValue::Strs(new.into()),
+                ));
+                continue;
+            }
+            obj.update_value(tag, |value| {
+                if let Some(items) = value.items_mut() {
+                    for item in items.iter_mut() {
+                        self.remap_references(item);
+                    }
+                }
+            });
+        }
+    }
+
+    /// De-identify a whole file, keeping the file meta SOP Instance UID in sync.
+    pub fn anonymize(&mut self, obj: &mut FileDicomObject<InMemDicomObject>) {
+        self.anonymize_dataset(obj);
+        if let Some(uid) = obj
+            .element(tags::SOP_INSTANCE_UID)
+            .ok()
+            .and_then(|e| e.to_str().ok())
+            .map(|s| s.into_owned())
+        {
+            obj.meta_mut().media_storage_sop_instance_uid = uid;
+        }
+    }
+
+    /// De-identify a dataset in place, recursing into sequence items.
+    pub fn anonymize_dataset(&mut self, obj: &mut InMemDicomObject) {
+        self.anonymize_level(obj, false);
+    }
+
+    /// [`Anonymizer::anonymize_dataset`] for `obj`, an item of a ContentSequence if
+    /// `content_item`.
+    fn anonymize_level(&mut self, obj: &mut InMemDicomObject, content_item: bool) {
+        if self.remove_private {
+            strip_private_level(obj, &self.keep_private_creators);
+        }
+        let tags: Vec<Tag> = obj.iter().map(|e| e.tag()).collect();
+        for tag in tags {
+            let Ok(elem) = obj.element(tag) else {
+                continue;
+            };
+            let vr = elem.vr();
+            let action = if content_item {
+                self.content_action(tag, vr)
+            } else {
+                self.action(tag, vr)
+            };
+            // SR content is kept and scrubbed item by item rather than removed
+            let content = tag == tags::CONTENT_SEQUENCE;
+            let action = match action {
+                Remove if content && self.profile.structured_reports => Keep,
+                action => action,
+            };
+            match action {
+                Keep => {
+                    obj.update_value(tag, |value| {
+                        if let Some(items) = value.items_mut() {
+                            for item in items.iter_mut() {
+                                self.anonymize_level(item, content);
+                            }
+                        }
+                    });
+                }
+                Remove => {
+                    obj.remove_element(tag);
+                }
+                Empty | Dummy | Hash if vr == VR::SQ => {
+                    obj.put(DataElement::new(
+                        tag,
+                        VR::SQ,
+                        DataSetSequence::<InMemDicomObject>::from(Vec::new()),
+                    ));
+                }
+                Empty => {
+                    obj.put(DataElement::new(tag, vr, PrimitiveValue::Empty));
+                }
+                Dummy => {
+                    obj.put(DataElement::new(tag, vr, dummy_value(vr)));
+                }
+                ReplaceUid => {
+                    let Ok(old) = elem.to_multi_str() else {
+                        continue;
+                    };
+                    let new: Vec<String> = old.iter().map(|u| self.map_uid(u)).collect();
+                    obj.put(DataElement::new(
+                        tag,
+                        VR::UI,
+                        PrimitiveValue::Strs(new.into()),
+                    ));
+                }
+                Hash => {
+                    let salt = &self.profile.hash_salt;
+                    let value = match (vr, elem.to_multi_str()) {
+                        (VR::UI, Ok(old)) => {
+                            PrimitiveValue::Strs(old.iter().map(|u| hashed_uid(salt, u)).collect())
+                        }
+                        (
+                            VR::AE
+                            | VR::CS
+                            | VR::LO
+                            | VR::LT
+                            | VR::PN
+                            | VR::SH
+                            | VR::ST
+                            | VR::UC
+                            | VR::UT,
+                            Ok(old),
+                        ) => {
+                            PrimitiveValue::Strs(old.iter().map(|v| hash_value(salt, v)).collect())
+                        }
+                        // Numbers, dates, binary: a hash wouldn't be a valid value
+                        _ => dummy_value(vr),
+                    };
+                    obj.put(DataElement::new(tag, vr, value));
+                }
+                ShiftDate => {
+                    let DatePolicy::Shift(days) = self.profile.dates else {
+                        continue;
+                    };
+                    let shifted = elem.to_multi_str().ok().and_then(|old| {
+                        old.iter()
+                            .map(|v| shift_date(v, days))
+                            .collect::<Option<Vec<String>>>()
+                    });
+                    let value = match shifted {
+                        Some(new) => PrimitiveValue::Strs(new.into()),
+                        None => PrimitiveValue::Empty,
+                    };
+                    obj.put(DataElement::new(tag, vr, value));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(obj: &InMemDicomObject, tag: Tag) -> Option<String> {
+        obj.element(tag)
+            .ok()
+            .and_then(|e| e.to_str().ok())
+            .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+    }
+
+    fn patient() -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::PATIENT_NAME, VR::PN, PrimitiveValue::from("Doe^Jane")),
+            DataElement::new(tags::PATIENT_ID, VR::LO, PrimitiveValue::from("MRN12345")),
+            DataElement::new(tags::STUDY_DATE, VR::DA, PrimitiveValue::from("20240301")),
+            DataElement::new(
+                tags::INSTITUTION_NAME,
+                VR::LO,
+                PrimitiveValue::from("General Hospital"),
+            ),
+            DataElement::new(
+                tags::PATIENT_COMMENTS,
+                VR::LT,
+                PrimitiveValue::from("Seen by Dr. Smith"),
+            ),
+            DataElement::new(
+                tags::STUDY_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from("1.2.3.4"),
+            ),
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(512u16)),
+        ])
+    }
+
+    #[test]
+    fn listed_attributes_take_their_basic_profile_action() {
+        assert_eq!(basic_profile_action(tags::PATIENT_NAME), Empty);
+        assert_eq!(basic_profile_action(tags::INSTITUTION_NAME), Dummy);
+        assert_eq!(basic_profile_action(tags::PATIENT_COMMENTS), Remove);
+        assert_eq!(basic_profile_action(tags::STUDY_INSTANCE_UID), ReplaceUid);
+        assert_eq!(basic_profile_action(Tag(0x0010, 0x2297)), Empty);
+        assert_eq!(basic_profile_action(Tag(0x0018, 0x1002)), ReplaceUid);
+    }
+
+    #[test]
+    fn unlisted_attributes_are_kept_only_if_known() {
+        assert_eq!(basic_profile_action(tags::ROWS), Keep);
+        assert_eq!(basic_profile_action(Tag(0x0009, 0x1001)), Keep);
+        assert_eq!(basic_profile_action(Tag(0x0024, 0xFFF0)), Remove);
+    }
+
+    #[test]
+    fn private_creators_reserve_their_block() {
+        assert!(is_private(Tag(0x0009, 0x1001)));
+        assert!(!is_private(tags::PATIENT_NAME));
+        assert!(is_private_creator(Tag(0x0029, 0x0010)));
+        assert!(!is_private_creator(Tag(0x0029, 0x1010)));
+        assert!(!is_private_creator(Tag(0x0028, 0x0010)));
+    }
+
+    #[test]
+    fn strip_private_keeps_whitelisted_blocks() {
+        let mut obj = InMemDicomObject::from_element_iter([
+            DataElement::new(
+                Tag(0x0029, 0x0010),
+                VR::LO,
+                PrimitiveValue::from("SIEMENS CSA HEADER"),
+            ),
+            DataElement::new(
+                Tag(0x0029, 0x0011),
+                VR::LO,
+                PrimitiveValue::from("OTHER VENDOR"),
+            ),
+            DataElement::new(
+                Tag(0x0029, 0x1008),
+                VR::CS,
+                PrimitiveValue::from("IMAGE NUM 4"),
+            ),
+            DataElement::new(
+                Tag(0x0029, 0x1108),
+                VR::LO,
+                PrimitiveValue::from("Doe^Jane"),
+            ),
+            DataElement::new(tags::PATIENT_NAME, VR::PN, PrimitiveValue::from("Doe^Jane")),
+        ]);
+        strip_private(&mut obj, &["siemens csa header".to_string()]);
+        assert!(obj.element(Tag(0x0029, 0x0010)).is_ok());
+        assert!(obj.element(Tag(0x0029, 0x1008)).is_ok());
+        assert!(obj.element(Tag(0x0029, 0x0011)).is_err());
+        assert!(obj.element(Tag(0x0029, 0x1108)).is_err());
+        assert!(obj.element(tags::PATIENT_NAME).is_ok());
+    }
+
+    #[test]
+    fn profile_lists_and_policies_override_the_basic_profile() {
+        let mut anonymizer = Anonymizer::new();
+        anonymizer.set_profile(Profile {
+            keep: vec![tags::PATIENT_COMMENTS],
+            hash: vec![tags::PATIENT_ID],
+            dates: DatePolicy::Shift(-10),
+            uids: UidPolicy::Keep,
+            ..Profile::default()
+        });
+        assert_eq!(anonymizer.action(tags::PATIENT_COMMENTS, VR::LT), Keep);
+        assert_eq!(anonymizer.action(tags::PATIENT_ID, VR::LO), Hash);
+        assert_eq!(anonymizer.action(tags::STUDY_DATE, VR::DA), ShiftDate);
+        assert_eq!(anonymizer.action(tags::STUDY_TIME, VR::TM), Keep);
+        assert_eq!(anonymizer.action(tags::STUDY_INSTANCE_UID, VR::UI), Keep);
+    }
+
+    #[test]
+    fn date_policies_never_keep_what_the_basic_profile_removes() {
+        let mut anonymizer = Anonymizer::new();
+        for dates in [
+            DatePolicy::Profile,
+            DatePolicy::Keep,
+            DatePolicy::Remove,
+            DatePolicy::Shift(-10),
+        ] {
+            anonymizer.set_profile(Profile {
+                dates,
+                ..Profile::default()
+            });
+            let birth_date = anonymizer.action(tags::PATIENT_BIRTH_DATE, VR::DA);
+            assert!(matches!(birth_date, Remove | Empty), "{dates:?}");
+            assert_eq!(
+                anonymizer.action(tags::INSTANCE_COERCION_DATE_TIME, VR::DT),
+                Remove,
+                "{dates:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn removing_dates_keeps_required_attributes() {
+        let mut anonymizer = Anonymizer::new();
+        anonymizer.set_profile(Profile {
+            dates: DatePolicy::Remove,
+            ..Profile::default()
+        });
+        assert_eq!(anonymizer.action(tags::STUDY_DATE, VR::DA), Empty);
+        assert_eq!(anonymizer.action(tags::CONTENT_DATE, VR::DA), Dummy);
+
+        anonymizer.set_profile(Profile {
+            dates: DatePolicy::Remove,
+            basic_profile: false,
+            ..Profile::default()
+        });
+        assert_eq!(anonymizer.action(tags::STUDY_DATE, VR::DA), Empty);
+        assert_eq!(anonymizer.action(tags::ACQUISITION_DATE, VR::DA), Remove);
+
+        anonymizer.set_profile(Profile {
+            dates: DatePolicy::Keep,
+            ..Profile::default()
+        });
+        assert_eq!(anonymizer.action(tags::STUDY_DATE, VR::DA), Keep);
+    }
+
+    #[test]
+    fn anonymize_applies_each_action() {
+        let mut anonymizer = Anonymizer::new();
+        let mut obj = patient();
+        anonymizer.anonymize_dataset(&mut obj);
+        assert_eq!(text(&obj, tags::PATIENT_NAME).as_deref(), Some(""));
+        assert_eq!(text(&obj, tags::STUDY_DATE).as_deref(), Some(""));
+        assert_eq!(
+            text(&obj, tags::INSTITUTION_NAME).as_deref(),
+            Some("ANONYMIZED")
+        );
+        assert!(obj.element(tags::PATIENT_COMMENTS).is_err());
+        assert_eq!(
+            obj.element(tags::ROWS).unwrap().to_int::<u16>().unwrap(),
+            512
+        );
+        let uid = text(&obj, tags::STUDY_INSTANCE_UID).unwrap();
+        assert_ne!(uid, "1.2.3.4");
+        assert_eq!(anonymizer.uid_map().get("1.2.3.4"), Some(uid));
+    }
+
+    #[test]
+    fn uids_map_the_same_across_files() {
+        let mut anonymizer = Anonymizer::new();
+        let (mut a, mut b) = (patient(), patient());
+        anonymizer.anonymize_dataset(&mut a);
+        anonymizer.anonymize_dataset(&mut b);
+        assert_eq!(
+            text(&a, tags::STUDY_INSTANCE_UID),
+            text(&b, tags::STUDY_INSTANCE_UID)
+        );
+    }
+
+    #[test]
+    fn hashed_uids_agree_between_anonymizers() {
+        let profile = Profile {
+            uids: UidPolicy::Hash,
+            hash_salt: "site".to_string(),
+            ..Profile::default()
+        };
+        let mut a = Anonymizer::new();
+        a.set_profile(profile.clone());
+        let mut b = Anonymizer::new();
+        b.set_profile(profile);
+        assert_eq!(a.map_uid("1.2.3.4"), b.map_uid("1.2.3.4"));
+        assert_eq!(a.map_uid("1.2.3.4"), hashed_uid("site", "1.2.3.4"));
+    }
+
+    #[test]
+    fn dates_shift_across_months_and_leap_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(days_from_civil(2000, 2, 29)), (2000, 2, 29));
+        assert_eq!(shift_date("20240301", -1).as_deref(), Some("20240229"));
+        assert_eq!(shift_date("20231231", 1).as_deref(), Some("20240101"));
+        assert_eq!(
+            shift_date("20240115083000.000", -20).as_deref(),
+            Some("20231226083000.000")
+        );
+        assert_eq!(shift_date("2024", 1), None);
+        assert_eq!(shift_date("2024-01-01", 1), None);
+    }
+
+    #[test]
+    fn identifiers_are_replaced_ignoring_case() {
+        let ids = vec!["mrn12345".to_string(), "doe".to_string()];
+        assert_eq!(
+            replace_identifiers("Jane DOE, MRN12345", &ids).as_deref(),
+            Some("Jane ANON, ANON")
+        );
+        assert_eq!(replace_identifiers("Chest PA", &ids), None);
+    }
+
+    #[test]
+    fn hashes_fit_short_string_vrs() {
+        let hash = hash_value("salt", "MRN12345");
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, hash_value("salt", " MRN12345 "));
+        assert_ne!(hash, hash_value("other", "MRN12345"));
+    }
+}
@@ -0,0 +1,85 @@
+//! ZIP archives of DICOM files, as studies are commonly shared. An archive is
+//! extracted to a folder and processed like one; the outputs are zipped back up with
+//! the same internal layout.
+
+use crate::DCMRedactErrors;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Whether `path` names a ZIP archive, by its extension.
+pub fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Extract every file of `archive` into `dir`, keeping the layout inside it. Entries
+/// whose names would land outside `dir` (`../`, absolute paths) are skipped. Returns
+/// the extracted files.
+pub fn extract(archive: &Path, dir: &Path) -> Result<Vec<PathBuf>, DCMRedactErrors> {
+    let failed = |e| DCMRedactErrors::decode(format!("Invalid ZIP {}", archive.display()), e);
+    let file = File::open(archive)
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to open {}", archive.display()), e))?;
+    let mut zip = ZipArchive::new(file).map_err(failed)?;
+    let mut files = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(failed)?;
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                DCMRedactErrors::io(format!("Failed to create {}", parent.display()), e)
+            })?;
+        }
+        let mut out = File::create(&path)
+            .map_err(|e| DCMRedactErrors::io(format!("Failed to create {}", path.display()), e))?;
+        std::io::copy(&mut entry, &mut out).map_err(|e| {
+            DCMRedactErrors::io(
+                format!(
+                    "Failed to extract {} from {}",
+                    path.display(),
+                    archive.display()
+                ),
+                e,
+            )
+        })?;
+        files.push(path);
+    }
+    Ok(files)
+}
+
+/// Write `files`, which are under the folder `base`, to a new ZIP at `archive`,
+/// named by their path below `base`.
+pub fn create(archive: &Path, base: &Path, files: &[PathBuf]) -> Result<(), DCMRedactErrors> {
+    let failed = |e| DCMRedactErrors::encode(format!("Failed to write {}", archive.display()), e);
+    if let Some(parent) = archive.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to create {}", parent.display()), e)
+        })?;
+    }
+    let out = File::create(archive)
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to create {}", archive.display()), e))?;
+    let mut zip = ZipWriter::new(BufWriter::new(out));
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+    for path in files {
+        let relative = path.strip_prefix(base).unwrap_or(path);
+        let name: Vec<&str> = relative.iter().filter_map(|part| part.to_str()).collect();
+        zip.start_file(name.join("/"), options).map_err(failed)?;
+        let mut file = File::open(path)
+            .map_err(|e| DCMRedactErrors::io(format!("Failed to open {}", path.display()), e))?;
+        std::io::copy(&mut file, &mut zip).map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to write {}", archive.display()), e)
+        })?;
+    }
+    zip.finish().map_err(failed)?;
+    Ok(())
+}
@@ -0,0 +1,190 @@
+//! Audit trail of de-identification work: one JSON object per saved file, appended to
+//! a `.jsonl` file, recording what was read and written, the regions burned in, the
+//! attributes changed, the UIDs replaced, when, and by whom.
+//!
+//! ```json
+//! {"timestamp":"2025-03-04T12:30:00Z","operator":"jdoe","input":"in/a.dcm",
+//!  "output":"out/a.dcm","regions":[{"shape":{"rect":{"x0":0,"y0":0,"x1":512,"y1":64}},
+//!  "fill":"black"}],"tags_modified":[{"tag":"(0010,0010)","name":"PatientName",
+//!  "before":"DOE^JOHN","after":null}],"uid_mappings":[...]}
+//! ```
+
+use crate::metadata::{diff_rows, format_tag};
+use crate::shape::Region;
+use crate::{DCMRedactErrors, RedactFill, uids};
+use dicom::object::InMemDicomObject;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A region as it was burned into the pixels, with the fill it was burned with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedRegion {
+    #[serde(flatten)]
+    pub region: Region,
+    pub fill: RedactFill,
+}
+
+/// An attribute whose value differs between the source and the output; `None` where
+/// it is absent.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagChange {
+    pub tag: String,
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UidChange {
+    pub attribute: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub operator: String,
+    pub input: Option<PathBuf>,
+    pub output: PathBuf,
+    pub regions: Vec<AppliedRegion>,
+    pub tags_modified: Vec<TagChange>,
+    pub uid_mappings: Vec<UidChange>,
+}
+
+impl AuditEntry {
+    /// The entry for writing `written` (the output header, `None` for non-DICOM) to
+    /// `output` from `source`, stamped with the current time.
+    pub fn new(
+        operator: &str,
+        input: Option<&Path>,
+        output: &Path,
+        regions: &[AppliedRegion],
+        source: Option<&InMemDicomObject>,
+        written: Option<&InMemDicomObject>,
+    ) -> Self {
+        let (tags_modified, uid_mappings) = match (source, written) {
+            (Some(source), Some(written)) => (
+                tag_changes(source, written),
+                uids::changed_uids(source, written)
+                    .into_iter()
+                    .map(|(attribute, old, new)| UidChange {
+                        attribute,
+                        old,
+                        new,
+                    })
+                    .collect(),
+            ),
+            _ => (Vec::new(), Vec::new()),
+        };
+        Self {
+            timestamp: utc_timestamp(SystemTime::now()),
+            operator: operator.to_string(),
+            input: input.map(Path::to_path_buf),
+            output: output.to_path_buf(),
+            regions: regions.to_vec(),
+            tags_modified,
+            uid_mappings,
+        }
+    }
+}
+
+/// Every attribute, sequence items included, that differs between `source` and
+/// `written`.
+pub(crate) fn tag_changes(source: &InMemDicomObject, written: &InMemDicomObject) -> Vec<TagChange> {
+    diff_rows(source, written)
+        .into_iter()
+        .filter(|row| row.changed())
+        .map(|row| TagChange {
+            tag: format_tag(row.tag),
+            name: row.name,
+            before: row.before,
+            after: row.after,
+        })
+        .collect()
+}
+
+/// The login name of whoever runs the program, for [`AuditLog::new`].
+pub fn default_operator() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` for `time`, in UTC.
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Appends an [`AuditEntry`] per saved file to a JSON lines file.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    operator: String,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>, operator: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            operator: operator.into(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn operator(&self) -> &str {
+        &self.operator
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> Result<(), DCMRedactErrors> {
+        let mut line = serde_json::to_string(entry).map_err(|e| {
+            DCMRedactErrors::encode(
+                format!(
+                    "Failed to encode audit entry for {}",
+                    entry.output.display()
+                ),
+                e,
+            )
+        })?;
+        line.push('\n');
+        // Parallel batch runs log from several threads; keep lines whole
+        static APPEND: Mutex<()> = Mutex::new(());
+        let _guard = APPEND.lock().unwrap_or_else(|e| e.into_inner());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| {
+                DCMRedactErrors::io(
+                    format!("Failed to write audit log {}", self.path.display()),
+                    e,
+                )
+            })
+    }
+}
@@ -0,0 +1,637 @@
+//! Headless batch redaction over a directory tree.
+
+use crate::audit::{AppliedRegion, AuditLog};
+use crate::borders::Borders;
+use crate::deface::{self, Deface, DefaceOptions};
+use crate::encapsulated::{self, Documents};
+use crate::folder::dicomdir_files;
+use crate::naming::NamePattern;
+use crate::plugin::Plugins;
+use crate::presets::{self, Preset};
+use crate::profile::{ProfileRule, matching_rule};
+use crate::pseudonym::PseudonymTable;
+use crate::report::DryRunReport;
+use crate::shape::keep_only;
+use crate::uids::UidRemapper;
+use crate::wsi::SlideLabels;
+use crate::{
+    DCMRedactErrors, OutputTransferSyntax, Profile, RedactFill, RedactRect, Redactor, Region,
+    Shape, is_dicom_file,
+};
+use dicom::object::InMemDicomObject;
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// A directory-to-directory redaction run.
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub input_dir: PathBuf,
+    pub output_dir: PathBuf,
+    /// Regions to redact in every file; frame-specific regions skip files that don't
+    /// have that frame.
+    pub regions: Vec<Region>,
+    /// Fill everything outside `regions` instead of inside them ([`keep_only`]).
+    pub keep_inside: bool,
+    /// Save only this part of every image ([`Redactor::set_crop`]).
+    pub crop: Option<RedactRect>,
+    /// Header, footer and border bands redacted in every file, sized to it.
+    pub borders: Borders,
+    /// Also black out everything outside the ultrasound regions of US images.
+    pub ultrasound_mask: bool,
+    /// Vendor presets whose regions are redacted in every file they match.
+    pub presets: Vec<Preset>,
+    /// Site detectors, whose rectangles are redacted on every frame they are found
+    /// on, and tag transformers, run over every output header.
+    pub plugins: Plugins,
+    /// Run for every file first, to add regions or leave the file out.
+    #[cfg(feature = "scripting")]
+    pub script: Option<std::sync::Arc<crate::script::FileScript>>,
+    /// Wipe the face and ears from head CT and MR series. Every file of a series is
+    /// loaded at once for it, and a series that can't be defaced fails as a whole.
+    pub deface: Deface,
+    /// How redacted pixels are filled.
+    pub fill: RedactFill,
+    /// De-identify metadata (PS3.15 Basic Profile) on save.
+    pub deidentify: bool,
+    /// Anonymization profile used when de-identifying.
+    pub profile: Profile,
+    /// Evaluated for every file: the first rule a file matches adds its template's
+    /// regions and replaces `profile` for it, if it names one.
+    pub rules: Vec<ProfileRule>,
+    /// Remove overlay planes, curve data and icon images on save.
+    pub strip_overlays: bool,
+    /// Remove private attributes on save, except blocks of `private_whitelist` creators.
+    pub remove_private: bool,
+    pub private_whitelist: Vec<String>,
+    pub transfer_syntax: OutputTransferSyntax,
+    /// Give every output a new SOP Instance UID even when metadata is kept.
+    pub regenerate_uids: bool,
+    /// CSV file to append old → new UID mappings to.
+    pub uid_map: Option<PathBuf>,
+    /// Persisted old → new UID map: loaded before the run (if it exists) and written
+    /// back after, so later runs remap the same studies to the same UIDs.
+    pub uid_store: Option<PathBuf>,
+    /// Replace PatientID/PatientName with persistent pseudonyms from this table.
+    pub pseudonyms: Option<PseudonymTable>,
+    /// Name outputs from this pattern (relative to `output_dir`) instead of mirroring
+    /// the input layout.
+    pub name_pattern: Option<NamePattern>,
+    /// Files processed at once; 0 uses every core.
+    pub threads: usize,
+    /// Log every saved file here.
+    pub audit_log: Option<AuditLog>,
+    /// Write a `.regions.json` sidecar next to every output.
+    pub regions_sidecar: bool,
+    /// Write the redaction mask as a DICOM SEG (`.seg.dcm`) next to every output.
+    pub write_seg: bool,
+    /// Save every output as a new Secondary Capture instance.
+    pub secondary_capture: bool,
+    /// Text burned into a corner of every output.
+    pub stamp: Option<crate::stamp::Stamp>,
+    /// What to do with the label and overview images of slides.
+    pub slide_labels: SlideLabels,
+    /// What to do with encapsulated PDF and CDA documents.
+    pub documents: Documents,
+    /// Only report what would be done: nothing is written, not even the UID store.
+    pub dry_run: bool,
+}
+
+/// Result of processing a single file.
+#[derive(Debug)]
+pub struct FileOutcome {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub result: Result<(), DCMRedactErrors>,
+    /// What would have been done, in a dry run.
+    pub report: Option<DryRunReport>,
+    /// A slide label, overview image or encapsulated document left out of the output.
+    pub removed: bool,
+    /// Why the job's script left the file out of the output, if it did.
+    pub skipped: Option<String>,
+    /// Files finished so far in this run, including this one, out of `total`.
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BatchSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Of those succeeded, slide label and overview images and encapsulated documents
+    /// left out.
+    pub removed: usize,
+    /// Of those succeeded, files the job's script left out.
+    pub skipped: usize,
+    /// Files not started because the run was cancelled.
+    pub cancelled: usize,
+}
+
+/// Progress of a run, in the order it happens on the worker threads.
+#[derive(Debug)]
+pub enum BatchEvent<'a> {
+    Started(&'a Path),
+    Finished(&'a FileOutcome),
+}
+
+/// Pause and cancel switches for a running batch, shared with the threads doing it.
+/// Files already being redacted always finish.
+#[derive(Debug, Default)]
+pub struct BatchControl {
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+impl BatchControl {
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Block while paused; returns whether to go on with the next file.
+    fn proceed(&self) -> bool {
+        while self.is_paused() && !self.is_cancelled() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        !self.is_cancelled()
+    }
+}
+
+/// Recursively collect every DICOM file under `dir`, sorted for a stable processing order.
+pub fn collect_dicom_files(dir: &Path) -> Result<Vec<PathBuf>, DCMRedactErrors> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(d) = pending.pop() {
+        let entries = fs::read_dir(&d).map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to read directory {}", d.display()), e)
+        })?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.file_name() != Some("DICOMDIR".as_ref()) && is_dicom_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+impl BatchJob {
+    /// Where `input` is written: `name_pattern` expanded from the output header `obj`,
+    /// or else its path relative to `input_dir`, under `output_dir`.
+    pub fn output_path(&self, input: &Path, obj: Option<&InMemDicomObject>) -> PathBuf {
+        if let Some(pattern) = &self.name_pattern {
+            return self.output_dir.join(pattern.expand(input, obj));
+        }
+        let rel = input.strip_prefix(&self.input_dir).unwrap_or(input);
+        match rel.file_name() {
+            // Inputs outside `input_dir` land directly in `output_dir`
+            Some(name) if rel.is_absolute() => self.output_dir.join(name),
+            _ => self.output_dir.join(rel),
+        }
+    }
+
+    /// Apply this job's save settings to `redactor`.
+    pub fn configure(&self, redactor: &mut Redactor, uids: &UidRemapper) {
+        redactor.set_uid_remapper(uids.clone());
+        redactor.set_deidentify(self.deidentify);
+        redactor.set_profile(self.profile.clone());
+        redactor.set_pseudonym_table(self.pseudonyms.clone());
+        redactor.set_strip_overlays(self.strip_overlays);
+        redactor.set_remove_private(self.remove_private);
+        redactor.set_private_whitelist(self.private_whitelist.clone());
+        redactor.set_transfer_syntax(self.transfer_syntax.clone());
+        redactor.set_regenerate_uids(self.regenerate_uids);
+        redactor.set_uid_mapping_file(self.uid_map.clone());
+        redactor.set_audit_log(self.audit_log.clone());
+        redactor.set_regions_sidecar(self.regions_sidecar);
+        redactor.set_write_seg(self.write_seg);
+        redactor.set_secondary_capture(self.secondary_capture);
+        redactor.set_stamp(self.stamp.clone());
+        redactor.set_crop(self.crop);
+        redactor.set_fill(self.fill);
+        redactor.set_plugins(self.plugins.clone());
+    }
+
+    /// Blank `redactor` if it is a slide label or overview image and this job says
+    /// so. Returns `false` if it should be left out of the output instead.
+    pub fn handle_slide_label(&self, redactor: &mut Redactor) -> bool {
+        if redactor.associated_image().is_none() {
+            return true;
+        }
+        match self.slide_labels {
+            SlideLabels::Keep => true,
+            SlideLabels::Blank => {
+                redactor.blank();
+                true
+            }
+            SlideLabels::Remove => false,
+        }
+    }
+
+    /// Replace the document in `redactor` with a placeholder if it is an encapsulated
+    /// document and this job says so. Returns `false` if it should be left out of the
+    /// output instead.
+    pub fn handle_document(&self, redactor: &mut Redactor) -> bool {
+        if redactor
+            .dicom()
+            .is_none_or(|d| !encapsulated::is_encapsulated(d))
+        {
+            return true;
+        }
+        match self.documents {
+            Documents::Keep => true,
+            Documents::Replace => {
+                redactor.strip_document();
+                true
+            }
+            Documents::Remove => false,
+        }
+    }
+}
+
+/// What became of a file.
+enum Handled {
+    Saved,
+    /// Left out as a slide label, overview image or encapsulated document.
+    Removed,
+    /// Left out by the job's script, for this reason.
+    #[cfg(feature = "scripting")]
+    Skipped(String),
+    DryRun(DryRunReport),
+}
+
+/// Create the parent directory of `output` and save `redactor` there.
+pub fn save_to(redactor: &mut Redactor, output: &Path) -> Result<(), DCMRedactErrors> {
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            DCMRedactErrors::io(
+                format!("Failed to create output directory {}", parent.display()),
+                e,
+            )
+        })?;
+    }
+    redactor.save(output)
+}
+
+/// Redact `input` and save it; returns where it was (or would have been) written, and
+/// what became of it.
+fn redact_one(
+    input: &Path,
+    job: &BatchJob,
+    uids: &UidRemapper,
+) -> (PathBuf, Result<Handled, DCMRedactErrors>) {
+    match Redactor::open_dicom(input) {
+        Ok(redactor) => finish_one(redactor, input, job, uids),
+        Err(e) => (job.output_path(input, None), Err(e)),
+    }
+}
+
+/// Deface the series made of `inputs` if it is a head scan, then redact and save each
+/// file like [`redact_one`]. Nothing of the series is saved unless every file opens
+/// and defacing succeeds, so no slice goes out with the face still on it.
+fn redact_series(
+    inputs: &[PathBuf],
+    job: &BatchJob,
+    uids: &UidRemapper,
+) -> Vec<(PathBuf, Result<Handled, DCMRedactErrors>)> {
+    let fail_all = |why: String| {
+        inputs
+            .iter()
+            .map(|input| {
+                let e = DCMRedactErrors::ValueError(why.clone());
+                (job.output_path(input, None), Err(e))
+            })
+            .collect()
+    };
+    let mut slices = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        match Redactor::open_dicom(input) {
+            Ok(redactor) => slices.push(redactor),
+            Err(e) => return fail_all(format!("Series not defaced: {e}")),
+        }
+    }
+    let head = slices
+        .first()
+        .and_then(Redactor::dicom)
+        .is_some_and(|d| deface::is_head_scan(d));
+    if head
+        && !job.dry_run
+        && let Err(e) = deface::deface(&mut slices, job.deface, &DefaceOptions::default())
+    {
+        return fail_all(e.to_string());
+    }
+    slices
+        .into_iter()
+        .zip(inputs)
+        .map(|(redactor, input)| finish_one(redactor, input, job, uids))
+        .collect()
+}
+
+/// The rest of [`redact_one`], once `input` is open.
+fn finish_one(
+    mut redactor: Redactor,
+    input: &Path,
+    job: &BatchJob,
+    uids: &UidRemapper,
+) -> (PathBuf, Result<Handled, DCMRedactErrors>) {
+    job.configure(&mut redactor, uids);
+    #[cfg(feature = "scripting")]
+    let scripted = match job.script.as_ref().map(|s| s.run(&redactor)).transpose() {
+        Ok(Some(outcome)) if outcome.skip.is_some() => {
+            let reason = outcome.skip.unwrap_or_default();
+            return (job.output_path(input, None), Ok(Handled::Skipped(reason)));
+        }
+        Ok(outcome) => outcome.map(|o| o.regions).unwrap_or_default(),
+        Err(e) => return (job.output_path(input, None), Err(e)),
+    };
+    if !job.handle_slide_label(&mut redactor) || !job.handle_document(&mut redactor) {
+        let output = job.output_path(input, redactor.output_metadata().as_deref());
+        return (output, Ok(Handled::Removed));
+    }
+    let (width, height) = redactor.dimensions();
+    let mut regions = if job.keep_inside {
+        keep_only(&job.regions, redactor.frame_count(), width, height)
+    } else {
+        job.regions.clone()
+    };
+    #[cfg(feature = "scripting")]
+    regions.extend(scripted);
+    if let Some(rule) = redactor.dicom().and_then(|d| matching_rule(&job.rules, d)) {
+        regions.extend(rule.regions.iter().cloned());
+        if let Some(profile) = &rule.profile {
+            redactor.set_profile(profile.clone());
+        }
+    }
+    regions.extend(job.borders.regions(width, height));
+    if let Some(dcm) = redactor.dicom() {
+        for preset in presets::matching(&job.presets, dcm) {
+            regions.extend(preset.regions(width, height));
+        }
+    }
+    if job.ultrasound_mask {
+        regions.extend(
+            redactor
+                .ultrasound_mask()
+                .into_iter()
+                .map(|r| Region::all_frames(Shape::Rect(r))),
+        );
+    }
+    // SR documents have no pixels, only their placeholder frame
+    if redactor.is_document() {
+        regions.clear();
+    } else if job.plugins.has_detectors() {
+        for frame in 0..redactor.frame_count() {
+            let found = match redactor.detect_regions(frame) {
+                Ok(found) => found,
+                Err(e) => return (job.output_path(input, None), Err(e)),
+            };
+            regions.extend(found.into_iter().map(|r| Region {
+                shape: Shape::Rect(r),
+                frame: Some(frame),
+                last_frame: None,
+            }));
+        }
+    }
+    if job.dry_run {
+        let frames = redactor.frame_count();
+        let pending: Vec<AppliedRegion> = regions
+            .iter()
+            .filter(|r| !r.target_frames(frames).is_empty())
+            .map(|region| AppliedRegion {
+                region: region.clone(),
+                fill: job.fill,
+            })
+            .collect();
+        let output = job.output_path(input, redactor.output_metadata().as_deref());
+        let result = redactor.dry_run(&output, &pending).map(Handled::DryRun);
+        return (output, result);
+    }
+    for region in &regions {
+        redactor.apply_region(region);
+    }
+    let output = job.output_path(input, redactor.naming_metadata().as_deref());
+    let result = save_to(&mut redactor, &output).map(|()| Handled::Saved);
+    (output, result)
+}
+
+/// Redact every DICOM file under `job.input_dir` (those its DICOMDIR lists, if it has
+/// one), mirroring the directory layout into `job.output_dir`. Files are processed on
+/// `job.threads` threads; `on_event` reports each file as it starts and finishes.
+///
+/// Every file shares one UID map, so Study/Series UIDs are remapped consistently across
+/// the whole tree. A failing file never aborts the run; only an unreadable input
+/// directory (or UID store) does.
+pub fn run_batch(
+    job: &BatchJob,
+    on_event: impl FnMut(BatchEvent) + Send,
+) -> Result<BatchSummary, DCMRedactErrors> {
+    let uids = match &job.uid_store {
+        Some(path) => UidRemapper::load(path)?,
+        None => UidRemapper::new(),
+    };
+    let dicomdir = job.input_dir.join("DICOMDIR");
+    let files = if dicomdir.is_file() {
+        // Files the DICOMDIR lists that aren't there fail like unreadable ones
+        dicomdir_files(&dicomdir)?
+    } else {
+        collect_dicom_files(&job.input_dir)?
+    };
+    let summary = run_batch_files(job, &files, &uids, &BatchControl::default(), on_event);
+    if let Some(path) = &job.uid_store
+        && !job.dry_run
+    {
+        uids.save(path)?;
+    }
+    Ok(summary)
+}
+
+/// Like [`run_batch`] but over an explicit list of DICOM files (e.g. the instances
+/// open in the GUI), remapping UIDs through `uids` and stopping or pausing between
+/// files as `control` says. `job.uid_store` is not used. When defacing, the files of
+/// a series are processed together, so pausing waits for the whole series.
+pub fn run_batch_files(
+    job: &BatchJob,
+    files: &[PathBuf],
+    uids: &UidRemapper,
+    control: &BatchControl,
+    on_event: impl FnMut(BatchEvent) + Send,
+) -> BatchSummary {
+    let progress = Mutex::new((BatchSummary::default(), on_event));
+    let lock = || progress.lock().unwrap_or_else(|e| e.into_inner());
+    // Files processed together: whole series when defacing, else one by one
+    let units: Vec<Vec<PathBuf>> = if job.deface == Deface::Off {
+        files.iter().map(|f| vec![f.clone()]).collect()
+    } else {
+        deface::group_series(files)
+    };
+    let process = |unit: &Vec<PathBuf>| {
+        if !control.proceed() {
+            lock().0.cancelled += unit.len();
+            return;
+        }
+        for input in unit {
+            (lock().1)(BatchEvent::Started(input));
+        }
+        let results: Vec<_> = if job.deface == Deface::Off {
+            unit.iter()
+                .map(|input| redact_one(input, job, uids))
+                .collect()
+        } else {
+            redact_series(unit, job, uids)
+        };
+        for (input, (output, result)) in unit.iter().zip(results) {
+            let (result, report, removed, skipped) = match result {
+                Ok(Handled::Saved) => (Ok(()), None, false, None),
+                Ok(Handled::Removed) => (Ok(()), None, true, None),
+                #[cfg(feature = "scripting")]
+                Ok(Handled::Skipped(reason)) => (Ok(()), None, false, Some(reason)),
+                Ok(Handled::DryRun(report)) => (Ok(()), Some(report), false, None),
+                Err(e) => (Err(e), None, false, None),
+            };
+            let mut progress = lock();
+            let (summary, on_event) = &mut *progress;
+            if result.is_ok() {
+                summary.succeeded += 1;
+            } else {
+                summary.failed += 1;
+            }
+            if removed {
+                summary.removed += 1;
+            }
+            if skipped.is_some() {
+                summary.skipped += 1;
+            }
+            on_event(BatchEvent::Finished(&FileOutcome {
+                input: input.clone(),
+                output,
+                result,
+                report,
+                removed,
+                skipped,
+                done: summary.succeeded + summary.failed,
+                total: files.len(),
+            }));
+        }
+    };
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(job.threads)
+        .build()
+    {
+        Ok(pool) => pool.install(|| units.par_iter().for_each(process)),
+        // No threads to be had: still process everything, one file at a time
+        Err(_) => units.iter().for_each(process),
+    }
+    progress.into_inner().unwrap_or_else(|e| e.into_inner()).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(name_pattern: Option<&str>) -> BatchJob {
+        BatchJob {
+            input_dir: PathBuf::from("/data/in"),
+            output_dir: PathBuf::from("/data/out"),
+            regions: Vec::new(),
+            keep_inside: false,
+            crop: None,
+            borders: Borders::default(),
+            ultrasound_mask: false,
+            presets: Vec::new(),
+            plugins: Plugins::default(),
+            #[cfg(feature = "scripting")]
+            script: None,
+            deface: Deface::default(),
+            fill: RedactFill::default(),
+            deidentify: true,
+            profile: Profile::default(),
+            rules: Vec::new(),
+            strip_overlays: true,
+            remove_private: true,
+            private_whitelist: Vec::new(),
+            transfer_syntax: OutputTransferSyntax::default(),
+            regenerate_uids: true,
+            uid_map: None,
+            uid_store: None,
+            pseudonyms: None,
+            name_pattern: name_pattern.map(|p| p.parse().unwrap()),
+            threads: 1,
+            audit_log: None,
+            regions_sidecar: false,
+            write_seg: false,
+            secondary_capture: false,
+            stamp: None,
+            slide_labels: SlideLabels::default(),
+            documents: Documents::default(),
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn outputs_mirror_the_input_layout() {
+        let job = job(None);
+        assert_eq!(
+            job.output_path(Path::new("/data/in/study/series/IM1.dcm"), None),
+            PathBuf::from("/data/out/study/series/IM1.dcm")
+        );
+    }
+
+    #[test]
+    fn inputs_outside_the_input_folder_land_at_the_top() {
+        let job = job(None);
+        assert_eq!(
+            job.output_path(Path::new("/elsewhere/deep/IM2.dcm"), None),
+            PathBuf::from("/data/out/IM2.dcm")
+        );
+    }
+
+    #[test]
+    fn name_patterns_replace_the_layout() {
+        let job = job(Some("{stem}_redacted.dcm"));
+        assert_eq!(
+            job.output_path(Path::new("/data/in/study/IM1.dcm"), None),
+            PathBuf::from("/data/out/IM1_redacted.dcm")
+        );
+    }
+
+    #[test]
+    fn collection_finds_dicom_files_by_content_and_extension() {
+        let dir = std::env::temp_dir().join(format!("dcm-redact-batch-{}", crate::uids::new_uid()));
+        let nested = dir.join("sub").join("deeper");
+        fs::create_dir_all(&nested).unwrap();
+        let mut dicm = vec![0u8; 128];
+        dicm.extend_from_slice(b"DICM");
+        fs::write(dir.join("b.dcm"), b"by extension only").unwrap();
+        fs::write(dir.join("DICOMDIR"), &dicm).unwrap();
+        fs::write(dir.join("notes.txt"), b"not an image").unwrap();
+        fs::write(nested.join("IM0001"), &dicm).unwrap();
+        fs::write(dir.join("sub").join("a.IMA"), b"").unwrap();
+
+        let files = collect_dicom_files(&dir).unwrap();
+        let expected = vec![
+            dir.join("b.dcm"),
+            dir.join("sub").join("a.IMA"),
+            nested.join("IM0001"),
+        ];
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, expected);
+        assert!(collect_dicom_files(&dir).is_err());
+    }
+}
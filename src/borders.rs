@@ -0,0 +1,71 @@
+//! Header and footer bands: most burned-in PHI sits in a fixed strip along the top or
+//! bottom of the image, so these are quicker to give as a row count or a percentage
+//! than to draw, and adapt to every image size in a batch.
+
+use crate::{RedactRect, Region, Shape};
+
+/// Bands along the edges of the image; zero turns a band off.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Borders {
+    /// Rows from the top.
+    pub top: u32,
+    /// Rows from the bottom.
+    pub bottom: u32,
+    /// Border on all four sides, in percent of the width (left and right) and of the
+    /// height (top and bottom).
+    pub percent: f32,
+}
+
+impl Borders {
+    pub fn is_empty(&self) -> bool {
+        self.top == 0 && self.bottom == 0 && self.percent <= 0.0
+    }
+
+    /// The bands on a `width`×`height` image, clipped to it. Overlapping bands are
+    /// left as they are; filling a pixel twice does no harm.
+    pub fn rects(&self, width: u32, height: u32) -> Vec<RedactRect> {
+        let full_width = |y0: u32, y1: u32| RedactRect {
+            x0: 0,
+            y0,
+            x1: width,
+            y1,
+        };
+        let mut rects = Vec::new();
+        if self.top > 0 {
+            rects.push(full_width(0, self.top.min(height)));
+        }
+        if self.bottom > 0 {
+            rects.push(full_width(height.saturating_sub(self.bottom), height));
+        }
+        if self.percent > 0.0 {
+            let part = |size: u32| {
+                ((size as f64 * f64::from(self.percent.min(50.0)) / 100.0).ceil() as u32).min(size)
+            };
+            let (dx, dy) = (part(width), part(height));
+            rects.push(full_width(0, dy));
+            rects.push(full_width(height - dy, height));
+            rects.push(RedactRect {
+                x0: 0,
+                y0: dy,
+                x1: dx,
+                y1: height - dy,
+            });
+            rects.push(RedactRect {
+                x0: width - dx,
+                y0: dy,
+                x1: width,
+                y1: height - dy,
+            });
+        }
+        rects.retain(|r| r.x0 < r.x1 && r.y0 < r.y1);
+        rects
+    }
+
+    /// [`Borders::rects`] as regions on every frame.
+    pub fn regions(&self, width: u32, height: u32) -> Vec<Region> {
+        self.rects(width, height)
+            .into_iter()
+            .map(|r| Region::all_frames(Shape::Rect(r)))
+            .collect()
+    }
+}
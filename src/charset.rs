@@ -0,0 +1,487 @@
+//! Character sets of header text: decoding values written with ISO 2022 code
+//! extensions (Japanese, Korean and Chinese names), and encoding them again on save.
+//!
+//! dicom-rs decodes text with one codec per dataset, so a SpecificCharacterSet that
+//! switches between character sets with escape sequences (`\ISO 2022 IR 87`,
+//! `\ISO 2022 IR 149`, ...) is read as Latin-1: byte for byte, escapes included, and
+//! written back the same way. [`decode_text`] turns those bytes into text when a file
+//! is opened, so names display, match and get edited as what they are, and
+//! [`encode_text`] turns them back into bytes (escapes and all) for writing. Text that
+//! the declared character sets can't hold, such as a name typed in another script, is
+//! saved with the whole dataset switched to UTF-8 (`ISO_IR 192`) instead.
+//!
+//! Files that declare no character set but hold UTF-8, a common mistake, are read as
+//! UTF-8 and marked as such.
+
+use dicom::core::header::Header;
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::{FileDicomObject, InMemDicomObject};
+use encoding_rs::{EUC_JP, EUC_KR, Encoding, GBK};
+
+const ESC: u8 = 0x1B;
+
+/// VRs whose values are in the SpecificCharacterSet; the others are plain ASCII.
+const TEXT_VRS: [VR; 7] = [VR::SH, VR::LO, VR::ST, VR::LT, VR::PN, VR::UC, VR::UT];
+
+/// Terms that dicom-rs can't decode: code extensions with double-byte sets, or with
+/// JIS X 0201 katakana.
+const EXTENDED: [&str; 5] = [
+    "ISO 2022 IR 13",
+    "ISO 2022 IR 87",
+    "ISO 2022 IR 159",
+    "ISO 2022 IR 149",
+    "ISO 2022 IR 58",
+];
+
+/// A graphic character set that escape sequences designate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Set {
+    /// ISO 646 (ISO 2022 IR 6), designated to G0.
+    Ascii,
+    /// JIS X 0201 romaji (ISO 2022 IR 13), designated to G0.
+    Romaji,
+    /// JIS X 0201 katakana (ISO 2022 IR 13), designated to G1.
+    Katakana,
+    /// JIS X 0208 (ISO 2022 IR 87), double-byte, designated to G0.
+    Jisx0208,
+    /// JIS X 0212 (ISO 2022 IR 159), double-byte, designated to G0.
+    Jisx0212,
+    /// KS X 1001 (ISO 2022 IR 149), double-byte, designated to G1.
+    Ksx1001,
+    /// GB 2312 (ISO 2022 IR 58), double-byte, designated to G1.
+    Gb2312,
+    /// ISO 8859-1 (ISO 2022 IR 100), designated to G1.
+    Latin1,
+}
+
+/// The escape sequence (after ESC) designating each set.
+const ESCAPES: [(&[u8], Set); 8] = [
+    (b"(B", Set::Ascii),
+    (b"(J", Set::Romaji),
+    (b")I", Set::Katakana),
+    (b"$B", Set::Jisx0208),
+    (b"$(D", Set::Jisx0212),
+    (b"$)C", Set::Ksx1001),
+    (b"$)A", Set::Gb2312),
+    (b"-A", Set::Latin1),
+];
+
+impl Set {
+    fn is_g1(self) -> bool {
+        matches!(
+            self,
+            Set::Katakana | Set::Ksx1001 | Set::Gb2312 | Set::Latin1
+        )
+    }
+
+    fn is_double(self) -> bool {
+        matches!(
+            self,
+            Set::Jisx0208 | Set::Jisx0212 | Set::Ksx1001 | Set::Gb2312
+        )
+    }
+
+    fn escape(self) -> &'static [u8] {
+        ESCAPES
+            .iter()
+            .find(|(_, set)| *set == self)
+            .map_or(&[], |(seq, _)| seq)
+    }
+
+    /// The set declared by a SpecificCharacterSet term, if it is a code extension
+    /// this module encodes.
+    fn from_term(term: &str) -> Option<Set> {
+        match term {
+            "ISO 2022 IR 13" => Some(Set::Katakana),
+            "ISO 2022 IR 87" => Some(Set::Jisx0208),
+            "ISO 2022 IR 149" => Some(Set::Ksx1001),
+            "ISO 2022 IR 58" => Some(Set::Gb2312),
+            "ISO 2022 IR 100" => Some(Set::Latin1),
+            _ => None,
+        }
+    }
+
+    /// The bytes for `c` in this set, for sets [`Set::from_term`] returns.
+    fn encode(self, c: char) -> Option<Vec<u8>> {
+        let code = u32::from(c);
+        match self {
+            Set::Katakana if (0xFF61..=0xFF9F).contains(&code) => {
+                Some(vec![(code - 0xFF61) as u8 + 0xA1])
+            }
+            Set::Latin1 if (0xA0..=0xFF).contains(&code) => Some(vec![code as u8]),
+            // Stored in G0, without the high bits EUC-JP sets
+            Set::Jisx0208 => pair(EUC_JP, c).map(|[a, b]| vec![a & 0x7F, b & 0x7F]),
+            Set::Ksx1001 => pair(EUC_KR, c).map(Vec::from),
+            Set::Gb2312 => pair(GBK, c).map(Vec::from),
+            _ => None,
+        }
+    }
+}
+
+/// `c` in the two-byte, high-bit range of `encoding` (the EUC form of a 94×94 set),
+/// or `None` if it has no such code.
+fn pair(encoding: &'static Encoding, c: char) -> Option<[u8; 2]> {
+    let mut buf = [0; 4];
+    let (bytes, _, unmappable) = encoding.encode(c.encode_utf8(&mut buf));
+    let high = |b: u8| (0xA1..=0xFE).contains(&b);
+    match *bytes {
+        [a, b] if !unmappable && high(a) && high(b) => Some([a, b]),
+        _ => None,
+    }
+}
+
+/// The two bytes `a`, `b` of double-byte set `set` as text.
+fn decode_pair(set: Set, a: u8, b: u8) -> String {
+    let (encoding, bytes): (&'static Encoding, Vec<u8>) = match set {
+        Set::Jisx0208 => (EUC_JP, vec![a | 0x80, b | 0x80]),
+        Set::Jisx0212 => (EUC_JP, vec![0x8F, a | 0x80, b | 0x80]),
+        Set::Ksx1001 => (EUC_KR, vec![a | 0x80, b | 0x80]),
+        _ => (GBK, vec![a | 0x80, b | 0x80]),
+    };
+    encoding.decode_without_bom_handling(&bytes).0.into_owned()
+}
+
+/// SpecificCharacterSet of `obj`, one uppercase term per value. An empty first term
+/// means the default repertoire.
+fn terms(obj: &InMemDicomObject) -> Vec<String> {
+    obj.element(tags::SPECIFIC_CHARACTER_SET)
+        .ok()
+        .and_then(|e| e.to_multi_str().ok())
+        .map(|terms| terms.iter().map(|t| t.trim().to_uppercase()).collect())
+        .unwrap_or_default()
+}
+
+/// The G0 and G1 sets each value starts with.
+fn initial(terms: &[String]) -> (Set, Option<Set>) {
+    match terms.first().map(String::as_str) {
+        Some("ISO 2022 IR 13") => (Set::Romaji, Some(Set::Katakana)),
+        Some("ISO 2022 IR 100") => (Set::Ascii, Some(Set::Latin1)),
+        Some("ISO 2022 IR 149") => (Set::Ascii, Some(Set::Ksx1001)),
+        Some("ISO 2022 IR 58") => (Set::Ascii, Some(Set::Gb2312)),
+        _ => (Set::Ascii, None),
+    }
+}
+
+/// Whether `b` ends a run of text after which the initial sets apply again: control
+/// characters, and the component and group separators of person names.
+fn is_delimiter(b: u8, pn: bool) -> bool {
+    matches!(b, b'\r' | b'\n' | b'\t' | 0x0C) || pn && matches!(b, b'^' | b'=')
+}
+
+/// One value written with ISO 2022 code extensions, as text.
+fn decode(bytes: &[u8], start: (Set, Option<Set>), pn: bool) -> String {
+    let (mut g0, mut g1) = start;
+    let mut out = String::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == ESC {
+            let rest = &bytes[i + 1..];
+            match ESCAPES.iter().find(|(seq, _)| rest.starts_with(seq)) {
+                Some((seq, set)) => {
+                    if set.is_g1() {
+                        g1 = Some(*set);
+                    } else {
+                        g0 = *set;
+                    }
+                    i += 1 + seq.len();
+                }
+                None => i += 1,
+            }
+            continue;
+        }
+        if b < 0x80 {
+            if g0.is_double() && b > 0x20 && i + 1 < bytes.len() {
+                out.push_str(&decode_pair(g0, b, bytes[i + 1]));
+                i += 2;
+                continue;
+            }
+            out.push(char::from(b));
+            if is_delimiter(b, pn) {
+                (g0, g1) = start;
+            }
+            i += 1;
+            continue;
+        }
+        match g1 {
+            Some(set) if set.is_double() && i + 1 < bytes.len() => {
+                out.push_str(&decode_pair(set, b, bytes[i + 1]));
+                i += 2;
+                continue;
+            }
+            Some(Set::Katakana) if (0xA1..=0xDF).contains(&b) => {
+                out.extend(char::from_u32(0xFF61 + u32::from(b - 0xA1)));
+            }
+            Some(Set::Latin1) => out.push(char::from(b)),
+            _ => out.push(char::REPLACEMENT_CHARACTER),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// `text` as one value written with ISO 2022 code extensions to `sets`, or `None` if
+/// some character isn't in any of them.
+fn encode(text: &str, sets: &[Set], start: (Set, Option<Set>), pn: bool) -> Option<Vec<u8>> {
+    let (mut g0, mut g1) = start;
+    let mut out = Vec::new();
+    let designate = |out: &mut Vec<u8>, set: Set| {
+        out.push(ESC);
+        out.extend_from_slice(set.escape());
+    };
+    for c in text.chars() {
+        if c.is_ascii() {
+            // Romaji has a yen sign and overline where ASCII has these
+            if g0 != Set::Ascii && (g0 != Set::Romaji || matches!(c, '\\' | '~')) {
+                designate(&mut out, Set::Ascii);
+                g0 = Set::Ascii;
+            }
+            out.push(c as u8);
+            if is_delimiter(c as u8, pn) {
+                (g0, g1) = start;
+            }
+            continue;
+        }
+        let (set, bytes) = sets
+            .iter()
+            .find_map(|&set| set.encode(c).map(|bytes| (set, bytes)))?;
+        if set.is_g1() && g1 != Some(set) {
+            designate(&mut out, set);
+            g1 = Some(set);
+        } else if !set.is_g1() && g0 != set {
+            designate(&mut out, set);
+            g0 = set;
+        }
+        out.extend(bytes);
+    }
+    if g0 != start.0 {
+        designate(&mut out, start.0);
+    }
+    Some(out)
+}
+
+/// The bytes of text read (or to be written) as Latin-1, or `None` if it holds
+/// characters beyond it.
+fn latin1_bytes(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+fn latin1_text(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(char::from).collect()
+}
+
+/// Replace every text value of `obj`, at any depth, for which `f` returns one.
+fn rewrite(obj: &mut InMemDicomObject, f: &mut impl FnMut(&str, VR) -> Option<String>) {
+    let tags: Vec<Tag> = obj.iter().map(|e| e.tag()).collect();
+    for tag in tags {
+        let Ok(elem) = obj.element(tag) else {
+            continue;
+        };
+        let vr = elem.vr();
+        if TEXT_VRS.contains(&vr) {
+            let Ok(values) = elem.to_multi_str() else {
+                continue;
+            };
+            let new: Vec<Option<String>> = values.iter().map(|v| f(v, vr)).collect();
+            if new.iter().any(Option::is_some) {
+                let new: Vec<String> = new
+                    .into_iter()
+                    .zip(values.iter())
+                    .map(|(new, old)| new.unwrap_or_else(|| old.clone()))
+                    .collect();
+                obj.put(DataElement::new(tag, vr, PrimitiveValue::Strs(new.into())));
+            }
+            continue;
+        }
+        obj.update_value(tag, |value| {
+            if let Some(items) = value.items_mut() {
+                for item in items.iter_mut() {
+                    rewrite(item, f);
+                }
+            }
+        });
+    }
+}
+
+/// Whether `f` holds for every text value of `obj`, at any depth.
+fn all_text(obj: &InMemDicomObject, f: &impl Fn(&str) -> bool) -> bool {
+    obj.iter().all(|elem| {
+        if TEXT_VRS.contains(&elem.vr()) {
+            return elem
+                .to_multi_str()
+                .map_or(true, |values| values.iter().all(|v| f(v)));
+        }
+        elem.items()
+            .unwrap_or_default()
+            .iter()
+            .all(|item| all_text(item, f))
+    })
+}
+
+fn set_utf8(obj: &mut InMemDicomObject) {
+    obj.put(DataElement::new(
+        tags::SPECIFIC_CHARACTER_SET,
+        VR::CS,
+        PrimitiveValue::from("ISO_IR 192"),
+    ));
+}
+
+/// Decode the text values of `obj`, just read, that dicom-rs left as Latin-1 bytes:
+/// those in ISO 2022 code extensions it doesn't support, and UTF-8 in files that
+/// declare no character set (which are then marked as UTF-8).
+pub fn decode_text(obj: &mut InMemDicomObject) {
+    let terms = terms(obj);
+    if terms.iter().any(|t| EXTENDED.contains(&t.as_str())) {
+        let start = initial(&terms);
+        rewrite(obj, &mut |value, vr| {
+            let bytes = latin1_bytes(value)?;
+            let plain = bytes.iter().all(|&b| b != ESC && b < 0x80);
+            (!plain).then(|| decode(&bytes, start, vr == VR::PN))
+        });
+    } else if terms.iter().all(|t| t.is_empty() || t == "ISO_IR 6") {
+        let mut utf8 = false;
+        rewrite(obj, &mut |value, _| {
+            let bytes = latin1_bytes(value).filter(|b| !b.is_ascii())?;
+            let text = String::from_utf8(bytes).ok()?;
+            utf8 = true;
+            Some(text)
+        });
+        if utf8 {
+            set_utf8(obj);
+        }
+    }
+}
+
+/// A copy of `obj` ready for writing when its text needs encoding: values in ISO 2022
+/// code extensions turned back into bytes, or, where the declared character set can't
+/// hold some value, the dataset switched to UTF-8. `None` if `obj` can be written as
+/// it is.
+pub fn encode_text(
+    obj: &FileDicomObject<InMemDicomObject>,
+) -> Option<FileDicomObject<InMemDicomObject>> {
+    let terms = terms(obj);
+    if terms.iter().any(|t| EXTENDED.contains(&t.as_str())) {
+        let start = initial(&terms);
+        let sets: Vec<Set> = terms.iter().filter_map(|t| Set::from_term(t)).collect();
+        if all_text(obj, &str::is_ascii) {
+            return None;
+        }
+        let mut out = obj.clone();
+        let mut failed = false;
+        rewrite(&mut out, &mut |value, vr| {
+            if failed || value.is_ascii() {
+                return None;
+            }
+            let bytes = encode(value, &sets, start, vr == VR::PN);
+            failed = bytes.is_none();
+            bytes.map(|b| latin1_text(&b))
+        });
+        if !failed {
+            return Some(out);
+        }
+    } else {
+        let limit = match terms.first().map(String::as_str) {
+            None | Some("" | "ISO_IR 6") if terms.len() <= 1 => 0x7F,
+            Some("ISO_IR 100") => 0xFF,
+            // UTF-8 and GB18030 hold anything; other sets are left to dicom-rs
+            _ => return None,
+        };
+        if all_text(obj, &|v| v.chars().all(|c| u32::from(c) <= limit)) {
+            return None;
+        }
+    }
+    let mut out = obj.clone();
+    set_utf8(&mut out);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term_list(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn round_trip(text: &str, declared: &[&str]) -> Vec<u8> {
+        let declared = term_list(declared);
+        let start = initial(&declared);
+        let sets: Vec<Set> = declared.iter().filter_map(|t| Set::from_term(t)).collect();
+        let bytes = encode(text, &sets, start, true).expect("text fits the sets");
+        assert_eq!(decode(&bytes, start, true), text);
+        bytes
+    }
+
+    #[test]
+    fn japanese_names_round_trip_as_in_ps3_5() {
+        // PS3.5 H.3.1, with the escapes switching back to ASCII at each delimiter
+        let bytes = round_trip(
+            "Yamada^Tarou=山田^太郎=やまだ^たろう",
+            &["", "ISO 2022 IR 87"],
+        );
+        let mut expected = b"Yamada^Tarou=\x1b$B;3ED\x1b(B^\x1b$BB@O:\x1b(B=".to_vec();
+        expected.extend_from_slice(b"\x1b$B$d$^$@\x1b(B^\x1b$B$?$m$&\x1b(B");
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn korean_names_round_trip_as_in_ps3_5() {
+        // PS3.5 H.3.2: G1 is designated again after each delimiter
+        let bytes = round_trip("Hong^Gildong=洪^吉洞=홍^길동", &["", "ISO 2022 IR 149"]);
+        let mut expected = b"Hong^Gildong=\x1b$)C\xfb\xf3^\x1b$)C\xd1\xce\xd4\xd7=".to_vec();
+        expected.extend_from_slice(b"\x1b$)C\xc8\xab^\x1b$)C\xb1\xe6\xb5\xbf");
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn katakana_and_chinese_round_trip() {
+        round_trip("ﾔﾏﾀﾞ^ﾀﾛｳ=山田^太郎", &["ISO 2022 IR 13", "ISO 2022 IR 87"]);
+        round_trip("Wang^XiaoDong=王^小东", &["", "ISO 2022 IR 58"]);
+        round_trip("Müller=山田", &["ISO 2022 IR 100", "ISO 2022 IR 87"]);
+    }
+
+    #[test]
+    fn text_outside_the_sets_does_not_encode() {
+        let start = initial(&term_list(&["", "ISO 2022 IR 87"]));
+        assert_eq!(encode("홍", &[Set::Jisx0208], start, true), None);
+        assert_eq!(
+            encode("Doe^John", &[Set::Jisx0208], start, true),
+            Some(b"Doe^John".to_vec())
+        );
+    }
+
+    #[test]
+    fn unknown_escapes_are_skipped() {
+        let start = (Set::Ascii, None);
+        assert_eq!(decode(b"A\x1b%GB", start, false), "A%GB");
+    }
+
+    #[test]
+    fn latin1_bytes_hold_only_latin1() {
+        assert_eq!(latin1_bytes("Mü"), Some(vec![b'M', 0xFC]));
+        assert_eq!(latin1_bytes("山"), None);
+        assert_eq!(latin1_text(&[b'M', 0xFC]), "Mü");
+    }
+
+    #[test]
+    fn decode_text_reads_code_extensions() {
+        let mut obj = InMemDicomObject::from_element_iter([
+            DataElement::new(
+                tags::SPECIFIC_CHARACTER_SET,
+                VR::CS,
+                PrimitiveValue::Strs(vec!["".to_string(), "ISO 2022 IR 87".to_string()].into()),
+            ),
+            DataElement::new(
+                tags::PATIENT_NAME,
+                VR::PN,
+                PrimitiveValue::from(latin1_text(
+                    b"Yamada^Tarou=\x1b$B;3ED\x1b(B^\x1b$BB@O:\x1b(B",
+                )),
+            ),
+        ]);
+        decode_text(&mut obj);
+        let name = obj.element(tags::PATIENT_NAME).unwrap().to_str().unwrap();
+        assert_eq!(name, "Yamada^Tarou=山田^太郎");
+    }
+}
@@ -0,0 +1,190 @@
+//! Object storage: reading input from and writing output to `s3://`, `gs://` and
+//! `az://` URLs, so studies archived in the cloud can be redacted without syncing them
+//! by hand first.
+//!
+//! Objects are streamed to and from local files chunk by chunk, so a large series is
+//! never held in memory, and large files are uploaded in parts. Credentials, regions
+//! and endpoints come from the environment variables each service's own tools use
+//! (`AWS_ACCESS_KEY_ID`, `AWS_REGION`, `AWS_ENDPOINT`, `GOOGLE_SERVICE_ACCOUNT`,
+//! `AZURE_STORAGE_ACCOUNT_NAME`, ...).
+
+use crate::DCMRedactErrors;
+use crate::pacs::StoreSummary;
+use futures::{StreamExt, TryStreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::azure::MicrosoftAzureBuilder;
+use object_store::buffered::BufWriter;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectMeta, ObjectStore};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+/// URL schemes of the supported object stores.
+const SCHEMES: [&str; 5] = ["s3", "s3a", "gs", "az", "azure"];
+/// Bytes read from a local file at a time when uploading.
+const CHUNK: usize = 1024 * 1024;
+
+/// Whether `s` is an object storage URL rather than a local path.
+pub fn is_url(s: &str) -> bool {
+    s.split_once("://")
+        .is_some_and(|(scheme, _)| SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()))
+}
+
+/// `path` as an object storage URL, if it is one (`--input s3://...` arrives as a path).
+pub fn as_url(path: &Path) -> Option<&str> {
+    path.to_str().filter(|s| is_url(s))
+}
+
+/// The store a URL points into, and the key or prefix within it.
+fn open(url: &str) -> Result<(Arc<dyn ObjectStore>, ObjectPath), DCMRedactErrors> {
+    let invalid = || DCMRedactErrors::ValueError(format!("Not an object storage URL: {url}"));
+    let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+    let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(invalid());
+    }
+    let failed = |e| DCMRedactErrors::network(format!("Failed to open {url}"), e);
+    let store: Arc<dyn ObjectStore> = match scheme.to_ascii_lowercase().as_str() {
+        "s3" | "s3a" => Arc::new(
+            AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .build()
+                .map_err(failed)?,
+        ),
+        "gs" => Arc::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_bucket_name(bucket)
+                .build()
+                .map_err(failed)?,
+        ),
+        "az" | "azure" => Arc::new(
+            MicrosoftAzureBuilder::from_env()
+                .with_container_name(bucket)
+                .build()
+                .map_err(failed)?,
+        ),
+        _ => return Err(invalid()),
+    };
+    Ok((store, ObjectPath::from(key)))
+}
+
+/// A runtime for one download or upload; object_store is async, the rest of the crate
+/// is not.
+fn runtime() -> Result<tokio::runtime::Runtime, DCMRedactErrors> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| DCMRedactErrors::io("Failed to start the object storage runtime", e))
+}
+
+/// Download the object at `url`, or every object under it when it is a prefix, into
+/// `dir`, keeping the layout below the prefix. Returns the local files.
+pub fn download(url: &str, dir: &Path) -> Result<Vec<PathBuf>, DCMRedactErrors> {
+    let (store, prefix) = open(url)?;
+    let failed = |e| DCMRedactErrors::network(format!("Failed to download {url}"), e);
+    runtime()?.block_on(async {
+        // A single object, or a prefix to list
+        let single = match prefix.as_ref() {
+            "" => None,
+            _ => store.head(&prefix).await.ok(),
+        };
+        let objects: Vec<ObjectMeta> = match single {
+            Some(meta) => vec![meta],
+            None => store
+                .list(Some(&prefix))
+                .try_collect()
+                .await
+                .map_err(failed)?,
+        };
+        let mut files = Vec::with_capacity(objects.len());
+        for meta in objects {
+            let mut local = dir.to_path_buf();
+            match meta.location.prefix_match(&prefix) {
+                Some(parts) if meta.location != prefix => {
+                    for part in parts {
+                        local.push(part.as_ref());
+                    }
+                }
+                _ => local.push(meta.location.filename().unwrap_or("object")),
+            }
+            if let Some(parent) = local.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| {
+                    DCMRedactErrors::io(format!("Failed to create {}", parent.display()), e)
+                })?;
+            }
+            let mut file = File::create(&local).map_err(|e| {
+                DCMRedactErrors::io(format!("Failed to create {}", local.display()), e)
+            })?;
+            let mut stream = store
+                .get(&meta.location)
+                .await
+                .map_err(failed)?
+                .into_stream();
+            while let Some(chunk) = stream.next().await {
+                file.write_all(&chunk.map_err(failed)?).map_err(|e| {
+                    DCMRedactErrors::io(format!("Failed to write {}", local.display()), e)
+                })?;
+            }
+            files.push(local);
+        }
+        Ok(files)
+    })
+}
+
+/// Upload `files`, which are under the local folder `base`, to the prefix `url`,
+/// keeping their layout below `base`. `on_uploaded` reports each file as it is
+/// uploaded or fails; a file failing never stops the rest. Fails as a whole only when
+/// the store can't be opened.
+pub fn upload(
+    files: &[PathBuf],
+    base: &Path,
+    url: &str,
+    mut on_uploaded: impl FnMut(&Path, Result<(), DCMRedactErrors>),
+) -> Result<StoreSummary, DCMRedactErrors> {
+    let (store, prefix) = open(url)?;
+    let runtime = runtime()?;
+    let mut summary = StoreSummary::default();
+    for path in files {
+        let relative = path.strip_prefix(base).unwrap_or(path);
+        let key = relative
+            .iter()
+            .filter_map(|part| part.to_str())
+            .fold(prefix.clone(), |key, part| key.child(part));
+        let failed =
+            |e| DCMRedactErrors::network(format!("Failed to upload {}", path.display()), e);
+        let result = runtime.block_on(async {
+            let mut file = File::open(path).map_err(|e| {
+                DCMRedactErrors::io(format!("Failed to open {}", path.display()), e)
+            })?;
+            let mut writer = BufWriter::new(store.clone(), key);
+            let mut chunk = vec![0; CHUNK];
+            loop {
+                let n = match file.read(&mut chunk) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        let _ = writer.abort().await;
+                        return Err(DCMRedactErrors::io(
+                            format!("Failed to read {}", path.display()),
+                            e,
+                        ));
+                    }
+                };
+                if n == 0 {
+                    break;
+                }
+                if let Err(e) = writer.write_all(&chunk[..n]).await {
+                    let _ = writer.abort().await;
+                    return Err(failed(e));
+                }
+            }
+            writer.shutdown().await.map_err(failed)
+        });
+        summary.record(&result);
+        on_uploaded(path, result);
+    }
+    Ok(summary)
+}
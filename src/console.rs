@@ -0,0 +1,183 @@
+//! The error console: every error of the session, kept until cleared, each with the
+//! chain of causes behind it and a button to copy them into a bug report.
+
+use crate::i18n::tr;
+use dcm_redact::DCMRedactErrors;
+use dcm_redact::audit::utc_timestamp;
+use eframe::egui;
+use std::error::Error;
+use std::time::SystemTime;
+
+/// An error as shown in the console: what failed, then what caused it, outermost
+/// first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub message: String,
+    pub causes: Vec<String>,
+}
+
+impl Report {
+    fn from_error(error: &(dyn Error + 'static)) -> Self {
+        let mut chain = vec![error.to_string()];
+        let mut source = error.source();
+        while let Some(e) = source {
+            chain.push(e.to_string());
+            source = e.source();
+        }
+        // Errors such as DCMRedactErrors print their source after a colon: keep only
+        // their own context, so no cause is shown twice
+        for i in 1..chain.len() {
+            let context = chain[i - 1]
+                .strip_suffix(chain[i].as_str())
+                .and_then(|c| c.strip_suffix(": "))
+                .map(str::to_string);
+            if let Some(context) = context {
+                chain[i - 1] = context;
+            }
+        }
+        let message = chain.remove(0);
+        Self {
+            message,
+            causes: chain,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Report {
+    fn from(error: anyhow::Error) -> Self {
+        Self::from_error(error.as_ref())
+    }
+}
+
+impl From<DCMRedactErrors> for Report {
+    fn from(error: DCMRedactErrors) -> Self {
+        Self::from_error(&error)
+    }
+}
+
+impl From<std::io::Error> for Report {
+    fn from(error: std::io::Error) -> Self {
+        Self::from_error(&error)
+    }
+}
+
+impl From<String> for Report {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            causes: Vec::new(),
+        }
+    }
+}
+
+impl From<&str> for Report {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    time: String,
+    report: Report,
+}
+
+impl Entry {
+    /// The entry as copied: its time and message, then a line per cause.
+    fn text(&self) -> String {
+        let mut text = format!("{} {}\n", self.time, self.report.message);
+        for cause in &self.report.causes {
+            text += &format!("    caused by: {cause}\n");
+        }
+        text
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Console {
+    entries: Vec<Entry>,
+    open: bool,
+}
+
+impl Console {
+    /// Add an error and open the console on it.
+    pub fn push(&mut self, report: impl Into<Report>) {
+        self.entries.push(Entry {
+            time: utc_timestamp(SystemTime::now()),
+            report: report.into(),
+        });
+        self.open = true;
+    }
+
+    /// Toolbar button opening and closing the console, once there is something in it.
+    pub fn button(&mut self, ui: &mut egui::Ui) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let label = egui::RichText::new(tr!("console.button", count = self.entries.len()))
+            .color(ui.visuals().error_fg_color);
+        ui.toggle_value(&mut self.open, label)
+            .on_hover_text(tr!("console.button_hover"));
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !self.open {
+            return;
+        }
+        let mut open = true;
+        let mut clear = false;
+        egui::Window::new(tr!("console.title"))
+            .id(egui::Id::new("error_console"))
+            .open(&mut open)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(tr!("console.copy_all"))
+                        .on_hover_text(tr!("console.copy_all_hover"))
+                        .clicked()
+                    {
+                        let text: String = self.entries.iter().map(Entry::text).collect();
+                        ctx.output_mut(|o| o.copied_text = text);
+                    }
+                    if ui.button(tr!("console.clear")).clicked() {
+                        clear = true;
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(360.0)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        let newest = self.entries.len().saturating_sub(1);
+                        for (i, entry) in self.entries.iter().enumerate() {
+                            ui.horizontal_top(|ui| {
+                                if ui.small_button(tr!("console.copy")).clicked() {
+                                    ctx.output_mut(|o| o.copied_text = entry.text());
+                                }
+                                ui.weak(&entry.time);
+                                let message = egui::RichText::new(&entry.report.message)
+                                    .color(ui.visuals().error_fg_color);
+                                if entry.report.causes.is_empty() {
+                                    ui.label(message);
+                                } else {
+                                    egui::CollapsingHeader::new(message)
+                                        .id_source(("console_entry", i))
+                                        .default_open(i == newest)
+                                        .show(ui, |ui| {
+                                            for cause in &entry.report.causes {
+                                                ui.label(tr!("console.caused_by", cause = cause));
+                                            }
+                                        });
+                                }
+                            });
+                        }
+                    });
+            });
+        self.open = open;
+        if clear {
+            self.entries.clear();
+            self.open = false;
+        }
+    }
+}
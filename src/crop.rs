@@ -0,0 +1,302 @@
+//! Cropping the output to a rectangle of the image, as an alternative to blackening
+//! wide borders: the saved frames keep only the pixels inside it, and the header says
+//! where they now start.
+
+use crate::orientation::ds;
+use crate::volume::{floats, functional_group};
+use crate::{RedactRect, Shape};
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+/// `r` limited to a `width` x `height` image; `None` if nothing of it is left, or all
+/// of the image is.
+pub fn clip(r: RedactRect, width: u32, height: u32) -> Option<RedactRect> {
+    let r = RedactRect {
+        x0: r.x0.min(width),
+        y0: r.y0.min(height),
+        x1: r.x1.min(width),
+        y1: r.y1.min(height),
+    };
+    let whole = r.x0 == 0 && r.y0 == 0 && r.x1 == width && r.y1 == height;
+    (r.x1 > r.x0 && r.y1 > r.y0 && !whole).then_some(r)
+}
+
+/// `position` of the first pixel moved to pixel (`r.x0`, `r.y0`), along `orientation`
+/// by `spacing`, or `None` unless all three are well-formed.
+fn moved_position(
+    position: &[f64],
+    orientation: &[f64],
+    spacing: &[f64],
+    r: RedactRect,
+) -> Option<[f64; 3]> {
+    let (&[px, py, pz], &[rx, ry, rz, cx, cy, cz], &[dy, dx]) = (position, orientation, spacing)
+    else {
+        return None;
+    };
+    let (x, y) = (r.x0 as f64 * dx, r.y0 as f64 * dy);
+    Some([
+        px + rx * x + cx * y,
+        py + ry * x + cy * y,
+        pz + rz * x + cz * y,
+    ])
+}
+
+fn put_position(obj: &mut InMemDicomObject, position: [f64; 3]) {
+    obj.put(DataElement::new(
+        tags::IMAGE_POSITION_PATIENT,
+        VR::DS,
+        PrimitiveValue::Strs(position.iter().map(|&v| ds(v)).collect()),
+    ));
+}
+
+/// Update the image plane attributes of `obj` for its pixels cropped to `r`: Rows and
+/// Columns, and ImagePositionPatient moved to the new first pixel, along
+/// ImageOrientationPatient by PixelSpacing, both at the top level and in the
+/// per-frame functional groups of enhanced images. The spacing itself stays.
+/// Ultrasound regions are moved along and cut to the crop.
+pub fn crop_header(obj: &mut InMemDicomObject, r: RedactRect) {
+    for (tag, value) in [(tags::ROWS, r.y1 - r.y0), (tags::COLUMNS, r.x1 - r.x0)] {
+        obj.put(DataElement::new(
+            tag,
+            VR::US,
+            PrimitiveValue::from(value as u16),
+        ));
+    }
+    let top = |obj: &InMemDicomObject, tag| floats(obj, tag).unwrap_or_default();
+    if let Some(position) = moved_position(
+        &top(obj, tags::IMAGE_POSITION_PATIENT),
+        &top(obj, tags::IMAGE_ORIENTATION_PATIENT),
+        &top(obj, tags::PIXEL_SPACING),
+        r,
+    ) {
+        put_position(obj, position);
+    }
+
+    // Each frame's position, with the orientation and spacing of that frame, the
+    // shared groups or the top level
+    let frames = obj
+        .element(tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE)
+        .ok()
+        .and_then(|e| e.items())
+        .map_or(0, <[_]>::len);
+    let positions: Vec<Option<[f64; 3]>> = (0..frames)
+        .map(|i| {
+            let attr = |macro_tag, tag| {
+                functional_group(obj, i, macro_tag)
+                    .and_then(|g| floats(g, tag))
+                    .or_else(|| floats(obj, tag))
+                    .unwrap_or_default()
+            };
+            let own_position = obj
+                .element(tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE)
+                .ok()?
+                .items()?
+                .get(i)?
+                .element(tags::PLANE_POSITION_SEQUENCE)
+                .ok()?
+                .items()?
+                .first()
+                .and_then(|p| floats(p, tags::IMAGE_POSITION_PATIENT))?;
+            moved_position(
+                &own_position,
+                &attr(
+                    tags::PLANE_ORIENTATION_SEQUENCE,
+                    tags::IMAGE_ORIENTATION_PATIENT,
+                ),
+                &attr(tags::PIXEL_MEASURES_SEQUENCE, tags::PIXEL_SPACING),
+                r,
+            )
+        })
+        .collect();
+    if positions.iter().any(Option::is_some) {
+        obj.update_value(tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE, |value| {
+            let Some(items) = value.items_mut() else {
+                return;
+            };
+            for (item, position) in items.iter_mut().zip(&positions) {
+                let Some(position) = *position else {
+                    continue;
+                };
+                item.update_value(tags::PLANE_POSITION_SEQUENCE, |value| {
+                    if let Some(plane) = value.items_mut().and_then(|p| p.first_mut()) {
+                        put_position(plane, position);
+                    }
+                });
+            }
+        });
+    }
+    crop_ultrasound_regions(obj, r);
+}
+
+/// Move the SequenceOfUltrasoundRegions of `obj` into the image cropped to `r`: region
+/// locations are cut to the crop and shifted to its origin, along with reference
+/// pixels given relative to them, and regions outside it are dropped.
+fn crop_ultrasound_regions(obj: &mut InMemDicomObject, r: RedactRect) {
+    if obj.element(tags::SEQUENCE_OF_ULTRASOUND_REGIONS).is_err() {
+        return;
+    }
+    obj.update_value(tags::SEQUENCE_OF_ULTRASOUND_REGIONS, |value| {
+        let Some(items) = value.items_mut() else {
+            return;
+        };
+        items.retain(|item| {
+            let int = |item: &InMemDicomObject, tag: Tag| {
+                item.element(tag).ok().and_then(|e| e.to_int::<i64>().ok())
+            };
+            let (Some(x0), Some(y0), Some(x1), Some(y1)) = (
+                int(item, tags::REGION_LOCATION_MIN_X0),
+                int(item, tags::REGION_LOCATION_MIN_Y0),
+                int(item, tags::REGION_LOCATION_MAX_X1),
+                int(item, tags::REGION_LOCATION_MAX_Y1),
+            ) else {
+                return true;
+            };
+            // The max corner is inclusive
+            let (cx0, cy0) = (i64::from(r.x0), i64::from(r.y0));
+            let (cx1, cy1) = (i64::from(r.x1) - 1, i64::from(r.y1) - 1);
+            let (nx0, ny0, nx1, ny1) = (x0.max(cx0), y0.max(cy0), x1.min(cx1), y1.min(cy1));
+            if nx0 > nx1 || ny0 > ny1 {
+                return false;
+            }
+            for (tag, v) in [
+                (tags::REGION_LOCATION_MIN_X0, nx0 - cx0),
+                (tags::REGION_LOCATION_MIN_Y0, ny0 - cy0),
+                (tags::REGION_LOCATION_MAX_X1, nx1 - cx0),
+                (tags::REGION_LOCATION_MAX_Y1, ny1 - cy0),
+            ] {
+                item.put(DataElement::new(
+                    tag,
+                    VR::UL,
+                    PrimitiveValue::from(v as u32),
+                ));
+            }
+            // Reference pixels count from the region's top left corner
+            for (tag, cut) in [
+                (tags::REFERENCE_PIXEL_X0, nx0 - x0),
+                (tags::REFERENCE_PIXEL_Y0, ny0 - y0),
+            ] {
+                if let Some(v) = int(item, tag).filter(|_| cut != 0) {
+                    item.put(DataElement::new(
+                        tag,
+                        VR::SL,
+                        PrimitiveValue::from((v - cut) as i32),
+                    ));
+                }
+            }
+            true
+        });
+    });
+}
+
+/// `shape` in the coordinates of the image cropped to `r`, or `None` if it lies
+/// outside `r`. Rectangles are cut to the crop; ellipses and polygons reaching past
+/// its top or left edge have their coordinates stopped there.
+pub fn crop_shape(shape: &Shape, r: RedactRect) -> Option<Shape> {
+    let b = shape.bounds()?;
+    if b.x1 <= r.x0 || b.x0 >= r.x1 || b.y1 <= r.y0 || b.y0 >= r.y1 {
+        return None;
+    }
+    let moved = shape.translated(-(r.x0 as i64), -(r.y0 as i64));
+    Some(match moved {
+        Shape::Rect(m) => Shape::Rect(RedactRect {
+            x0: m.x0,
+            y0: m.y0,
+            x1: m.x1.min(r.x1 - r.x0),
+            y1: m.y1.min(r.y1 - r.y0),
+        }),
+        moved => moved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x0: u32, y0: u32, x1: u32, y1: u32) -> RedactRect {
+        RedactRect { x0, y0, x1, y1 }
+    }
+
+    #[test]
+    fn clip_limits_the_crop_to_the_image() {
+        assert_eq!(
+            clip(rect(10, 20, 900, 300), 512, 256),
+            Some(rect(10, 20, 512, 256))
+        );
+        assert_eq!(clip(rect(0, 0, 512, 256), 512, 256), None);
+        assert_eq!(clip(rect(0, 0, 1000, 1000), 512, 256), None);
+        assert_eq!(clip(rect(600, 0, 700, 100), 512, 256), None);
+        assert_eq!(clip(rect(10, 10, 10, 100), 512, 256), None);
+    }
+
+    #[test]
+    fn shapes_move_into_the_crop() {
+        let r = rect(10, 20, 110, 120);
+        assert_eq!(
+            crop_shape(&Shape::Rect(rect(15, 25, 200, 50)), r),
+            Some(Shape::Rect(rect(5, 5, 100, 30)))
+        );
+        assert_eq!(
+            crop_shape(&Shape::Ellipse(rect(0, 0, 30, 40)), r),
+            Some(Shape::Ellipse(rect(0, 0, 20, 20)))
+        );
+        assert_eq!(
+            crop_shape(&Shape::Polygon(vec![[20, 30], [40, 30], [30, 50]]), r),
+            Some(Shape::Polygon(vec![[10, 10], [30, 10], [20, 30]]))
+        );
+    }
+
+    #[test]
+    fn shapes_outside_the_crop_are_dropped() {
+        let r = rect(10, 20, 110, 120);
+        assert_eq!(crop_shape(&Shape::Rect(rect(0, 0, 10, 200)), r), None);
+        assert_eq!(crop_shape(&Shape::Rect(rect(110, 20, 200, 50)), r), None);
+        assert_eq!(crop_shape(&Shape::Rect(rect(20, 30, 20, 40)), r), None);
+    }
+
+    #[test]
+    fn the_first_pixel_moves_along_the_image_plane() {
+        // Rows run along +x, columns along +y, 0.5 mm between columns and 2 mm
+        // between rows
+        let orientation = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let moved = moved_position(
+            &[-100.0, -50.0, 7.0],
+            &orientation,
+            &[2.0, 0.5],
+            rect(10, 20, 30, 40),
+        );
+        assert_eq!(moved, Some([-95.0, -10.0, 7.0]));
+        assert_eq!(
+            moved_position(&[], &orientation, &[2.0, 0.5], rect(1, 1, 2, 2)),
+            None
+        );
+    }
+
+    #[test]
+    fn crop_header_sets_size_and_position() {
+        let ds_values =
+            |values: &[&str]| PrimitiveValue::Strs(values.iter().map(|v| v.to_string()).collect());
+        let mut obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(256u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(512u16)),
+            DataElement::new(tags::PIXEL_SPACING, VR::DS, ds_values(&["2", "0.5"])),
+            DataElement::new(
+                tags::IMAGE_ORIENTATION_PATIENT,
+                VR::DS,
+                ds_values(&["1", "0", "0", "0", "1", "0"]),
+            ),
+            DataElement::new(
+                tags::IMAGE_POSITION_PATIENT,
+                VR::DS,
+                ds_values(&["-100", "-50", "7"]),
+            ),
+        ]);
+        crop_header(&mut obj, rect(10, 20, 110, 70));
+        let int = |tag| obj.element(tag).unwrap().to_int::<u16>().unwrap();
+        assert_eq!((int(tags::COLUMNS), int(tags::ROWS)), (100, 50));
+        assert_eq!(
+            floats(&obj, tags::IMAGE_POSITION_PATIENT),
+            Some(vec![-95.0, -10.0, 7.0])
+        );
+    }
+}
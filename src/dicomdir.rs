@@ -0,0 +1,515 @@
+//! Writing a DICOMDIR for a folder of redacted files, so a CD/DVD export that came with
+//! one still has one afterwards. Records are built from the files as written, so they
+//! carry the new UIDs and the de-identified patient details.
+
+use crate::batch::collect_dicom_files;
+use crate::{DCMRedactErrors, gz, uids};
+use dicom::core::value::DataSetSequence;
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::dictionary_std::uids::MEDIA_STORAGE_DIRECTORY_STORAGE;
+use dicom::object::mem::InMemElement;
+use dicom::object::{FileDicomObject, FileMetaTableBuilder, InMemDicomObject, OpenFileOptions};
+use dicom::transfer_syntax::entries;
+use std::path::{Path, PathBuf};
+
+/// Value of FileSetID.
+const FILE_SET_ID: &str = "REDACTED";
+
+/// Keys of the PATIENT, STUDY and SERIES records, copied from the first file of each;
+/// missing ones are written empty.
+const PATIENT_KEYS: [(Tag, VR); 4] = [
+    (tags::PATIENT_NAME, VR::PN),
+    (tags::PATIENT_ID, VR::LO),
+    (tags::PATIENT_BIRTH_DATE, VR::DA),
+    (tags::PATIENT_SEX, VR::CS),
+];
+const STUDY_KEYS: [(Tag, VR); 6] = [
+    (tags::STUDY_DATE, VR::DA),
+    (tags::STUDY_TIME, VR::TM),
+    (tags::ACCESSION_NUMBER, VR::SH),
+    (tags::STUDY_DESCRIPTION, VR::LO),
+    (tags::STUDY_INSTANCE_UID, VR::UI),
+    (tags::STUDY_ID, VR::SH),
+];
+const SERIES_KEYS: [(Tag, VR); 4] = [
+    (tags::MODALITY, VR::CS),
+    (tags::SERIES_INSTANCE_UID, VR::UI),
+    (tags::SERIES_NUMBER, VR::IS),
+    (tags::SERIES_DESCRIPTION, VR::LO),
+];
+/// Keys of the instance records, copied when the file has them.
+const INSTANCE_KEYS: [Tag; 12] = [
+    tags::INSTANCE_NUMBER,
+    tags::CONTENT_DATE,
+    tags::CONTENT_TIME,
+    tags::CONTENT_LABEL,
+    tags::CONTENT_DESCRIPTION,
+    tags::PRESENTATION_CREATION_DATE,
+    tags::PRESENTATION_CREATION_TIME,
+    tags::COMPLETION_FLAG,
+    tags::VERIFICATION_FLAG,
+    tags::DOCUMENT_TITLE,
+    tags::MIME_TYPE_OF_ENCAPSULATED_DOCUMENT,
+    tags::STRUCTURE_SET_LABEL,
+];
+
+/// One file to list: its header, and its path below the DICOMDIR's folder.
+struct Instance {
+    file_id: Vec<String>,
+    obj: FileDicomObject<InMemDicomObject>,
+}
+
+struct Series {
+    uid: String,
+    instances: Vec<Instance>,
+}
+
+struct Study {
+    uid: String,
+    series: Vec<Series>,
+}
+
+struct Patient {
+    key: (String, String), // PatientID, PatientName
+    studies: Vec<Study>,
+}
+
+/// A directory record, with the records it links to by index.
+struct Record {
+    item: InMemDicomObject,
+    next: Option<usize>,
+    lower: Option<usize>,
+}
+
+fn text(obj: &InMemDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+        .unwrap_or_default()
+}
+
+fn int(obj: &InMemDicomObject, tag: Tag) -> Option<i32> {
+    obj.element(tag).ok().and_then(|e| e.to_int().ok())
+}
+
+/// Record type of the lowest-level record for an instance of `sop_class`.
+fn record_type(sop_class: &str) -> &'static str {
+    match sop_class {
+        c if c.starts_with("1.2.840.10008.5.1.4.1.1.88.") => "SR DOCUMENT",
+        c if c.starts_with("1.2.840.10008.5.1.4.1.1.11.") => "PRESENTATION",
+        c if c.starts_with("1.2.840.10008.5.1.4.1.1.104.") => "ENCAP DOC",
+        "1.2.840.10008.5.1.4.1.1.481.2" => "RT DOSE",
+        "1.2.840.10008.5.1.4.1.1.481.3" => "RT STRUCTURE SET",
+        "1.2.840.10008.5.1.4.1.1.481.5" => "RT PLAN",
+        _ => "IMAGE",
+    }
+}
+
+/// A record of `kind` with `keys` from `obj`, and its character set.
+fn record(kind: &str, obj: &InMemDicomObject, keys: &[(Tag, VR)]) -> InMemDicomObject {
+    let mut item = InMemDicomObject::from_element_iter([
+        DataElement::new(
+            tags::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD,
+            VR::UL,
+            PrimitiveValue::from(0u32),
+        ),
+        DataElement::new(
+            tags::RECORD_IN_USE_FLAG,
+            VR::US,
+            PrimitiveValue::from(0xFFFFu16),
+        ),
+        DataElement::new(
+            tags::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY,
+            VR::UL,
+            PrimitiveValue::from(0u32),
+        ),
+        DataElement::new(
+            tags::DIRECTORY_RECORD_TYPE,
+            VR::CS,
+            PrimitiveValue::from(kind),
+        ),
+    ]);
+    if let Ok(charset) = obj.element(tags::SPECIFIC_CHARACTER_SET) {
+        item.put(charset.clone());
+    }
+    for &(tag, vr) in keys {
+        match obj.element(tag) {
+            Ok(e) => item.put(e.clone()),
+            Err(_) => item.put(DataElement::new(tag, vr, PrimitiveValue::Empty)),
+        };
+    }
+    item
+}
+
+/// The lowest-level record for `instance`: what it is and where to find it.
+fn instance_record(instance: &Instance) -> InMemDicomObject {
+    let meta = instance.obj.meta();
+    let class = meta.media_storage_sop_class_uid().trim_end_matches('\0');
+    let mut item = record(record_type(class), &instance.obj, &[]);
+    for tag in INSTANCE_KEYS {
+        if let Ok(e) = instance.obj.element(tag) {
+            item.put(e.clone());
+        }
+    }
+    let uid = |tag, value: &str| {
+        DataElement::new(
+            tag,
+            VR::UI,
+            PrimitiveValue::from(value.trim_end_matches('\0')),
+        )
+    };
+    // ReferencedFileID is a multi-valued CS, one value per path component
+    item.put(DataElement::new(
+        tags::REFERENCED_FILE_ID,
+        VR::CS,
+        PrimitiveValue::Strs(instance.file_id.iter().cloned().collect()),
+    ));
+    item.put(uid(tags::REFERENCED_SOP_CLASS_UID_IN_FILE, class));
+    item.put(uid(
+        tags::REFERENCED_SOP_INSTANCE_UID_IN_FILE,
+        meta.media_storage_sop_instance_uid(),
+    ));
+    item.put(uid(
+        tags::REFERENCED_TRANSFER_SYNTAX_UID_IN_FILE,
+        meta.transfer_syntax(),
+    ));
+    item
+}
+
+/// Group `instances` by patient, study and series, in the order each first appears,
+/// with each series sorted by InstanceNumber.
+fn hierarchy(instances: Vec<Instance>) -> Vec<Patient> {
+    let mut patients: Vec<Patient> = Vec::new();
+    for instance in instances {
+        let key = (
+            text(&instance.obj, tags::PATIENT_ID),
+            text(&instance.obj, tags::PATIENT_NAME),
+        );
+        let study_uid = text(&instance.obj, tags::STUDY_INSTANCE_UID);
+        let series_uid = text(&instance.obj, tags::SERIES_INSTANCE_UID);
+        let patient = match patients.iter().position(|p| p.key == key) {
+            Some(i) => &mut patients[i],
+            None => {
+                patients.push(Patient {
+                    key,
+                    studies: Vec::new(),
+                });
+                patients.last_mut().unwrap()
+            }
+        };
+        let study = match patient.studies.iter().position(|s| s.uid == study_uid) {
+            Some(i) => &mut patient.studies[i],
+            None => {
+                patient.studies.push(Study {
+                    uid: study_uid,
+                    series: Vec::new(),
+                });
+                patient.studies.last_mut().unwrap()
+            }
+        };
+        let series = match study.series.iter().position(|s| s.uid == series_uid) {
+            Some(i) => &mut study.series[i],
+            None => {
+                study.series.push(Series {
+                    uid: series_uid,
+                    instances: Vec::new(),
+                });
+                study.series.last_mut().unwrap()
+            }
+        };
+        series.instances.push(instance);
+    }
+    for series in patients
+        .iter_mut()
+        .flat_map(|p| &mut p.studies)
+        .flat_map(|s| &mut s.series)
+    {
+        series
+            .instances
+            .sort_by_key(|i| int(&i.obj, tags::INSTANCE_NUMBER).unwrap_or(i32::MAX));
+    }
+    patients
+}
+
+/// Append the records of one level to `records`, depth first, linking each to the
+/// next of its level. Returns the index of the first.
+fn push_level<T>(
+    records: &mut Vec<Record>,
+    entries: &[T],
+    mut push: impl FnMut(&mut Vec<Record>, &T) -> usize,
+) -> Option<usize> {
+    let mut first = None;
+    let mut previous: Option<usize> = None;
+    for entry in entries {
+        let index = push(records, entry);
+        match previous {
+            Some(p) => records[p].next = Some(index),
+            None => first = Some(index),
+        }
+        previous = Some(index);
+    }
+    first
+}
+
+/// The directory records of `patients`, depth first: each patient, then its studies,
+/// each study then its series, each series then its instances.
+fn records(patients: &[Patient]) -> Vec<Record> {
+    let mut records = Vec::new();
+    let new = |records: &mut Vec<Record>, item| {
+        records.push(Record {
+            item,
+            next: None,
+            lower: None,
+        });
+        records.len() - 1
+    };
+    push_level(&mut records, patients, |records, patient| {
+        let first = &patient.studies[0].series[0].instances[0].obj;
+        let index = new(records, record("PATIENT", first, &PATIENT_KEYS));
+        records[index].lower = push_level(records, &patient.studies, |records, study| {
+            let first = &study.series[0].instances[0].obj;
+            let index = new(records, record("STUDY", first, &STUDY_KEYS));
+            records[index].lower = push_level(records, &study.series, |records, series| {
+                let first = &series.instances[0].obj;
+                let index = new(records, record("SERIES", first, &SERIES_KEYS));
+                records[index].lower = push_level(records, &series.instances, |records, i| {
+                    new(records, instance_record(i))
+                });
+                index
+            });
+            index
+        });
+        index
+    });
+    records
+}
+
+/// The DICOMDIR file `instance_uid` for `records`, with every offset set to `offsets`
+/// (by record index) and those of the root directory to `root`.
+fn dicomdir(
+    records: &[Record],
+    offsets: &[u32],
+    root: (u32, u32),
+    instance_uid: &str,
+) -> Result<Vec<u8>, DCMRedactErrors> {
+    let offset = |i: Option<usize>| i.map_or(0, |i| offsets[i]);
+    let items: Vec<InMemDicomObject> = records
+        .iter()
+        .map(|r| {
+            let mut item = r.item.clone();
+            item.put(DataElement::new(
+                tags::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD,
+                VR::UL,
+                PrimitiveValue::from(offset(r.next)),
+            ));
+            item.put(DataElement::new(
+                tags::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY,
+                VR::UL,
+                PrimitiveValue::from(offset(r.lower)),
+            ));
+            item
+        })
+        .collect();
+    let obj = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::FILE_SET_ID, VR::CS, PrimitiveValue::from(FILE_SET_ID)),
+        DataElement::new(
+            tags::OFFSET_OF_THE_FIRST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY,
+            VR::UL,
+            PrimitiveValue::from(root.0),
+        ),
+        DataElement::new(
+            tags::OFFSET_OF_THE_LAST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY,
+            VR::UL,
+            PrimitiveValue::from(root.1),
+        ),
+        DataElement::new(
+            tags::FILE_SET_CONSISTENCY_FLAG,
+            VR::US,
+            PrimitiveValue::from(0u16),
+        ),
+        InMemElement::new(
+            tags::DIRECTORY_RECORD_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(items),
+        ),
+    ]);
+    let mut file = obj
+        .with_meta(
+            FileMetaTableBuilder::new()
+                .transfer_syntax(entries::EXPLICIT_VR_LITTLE_ENDIAN.uid())
+                .media_storage_sop_class_uid(MEDIA_STORAGE_DIRECTORY_STORAGE)
+                .media_storage_sop_instance_uid(instance_uid),
+        )
+        .map_err(|e| DCMRedactErrors::encode("Failed to build the DICOMDIR file meta", e))?;
+    uids::stamp_file_meta(&mut file);
+    let mut bytes = Vec::new();
+    file.write_all(&mut bytes)
+        .map_err(|e| DCMRedactErrors::encode("Failed to encode the DICOMDIR", e))?;
+    Ok(bytes)
+}
+
+/// Byte offset of each record of `records` in `bytes`, a DICOMDIR encoded from them:
+/// where its item starts, counted from the start of the file.
+fn record_offsets(bytes: &[u8], records: &[Record]) -> Option<Vec<u32>> {
+    // (0004,1220) SQ, explicit VR little endian: tag, VR, 2 reserved bytes, length
+    const HEADER: [u8; 8] = [0x04, 0x00, 0x20, 0x12, b'S', b'Q', 0, 0];
+    let undefined = u32::MAX;
+    let mut at = bytes.windows(HEADER.len()).position(|w| w == HEADER)? + 12;
+    let ts = entries::EXPLICIT_VR_LITTLE_ENDIAN.erased();
+    let mut offsets = Vec::with_capacity(records.len());
+    for record in records {
+        offsets.push(u32::try_from(at).ok()?);
+        let len = u32::from_le_bytes(bytes.get(at + 4..at + 8)?.try_into().ok()?);
+        at += 8 + if len == undefined {
+            // The item runs to its delimitation item, 8 bytes after the content
+            let mut content = Vec::new();
+            record.item.write_dataset_with_ts(&mut content, &ts).ok()?;
+            content.len() + 8
+        } else {
+            len as usize
+        };
+    }
+    Some(offsets)
+}
+
+/// Most components a ReferencedFileID may have (PS3.10 §8.2).
+const MAX_FILE_ID_COMPONENTS: usize = 8;
+
+/// Whether `component` may be part of a ReferencedFileID (PS3.10 §8.2): a CS value of
+/// 1 to 8 upper-case letters, digits and `_`, so no extension.
+pub fn is_file_id_component(component: &str) -> bool {
+    (1..=8).contains(&component.len())
+        && component
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+}
+
+/// Write a DICOMDIR in `dir` listing every DICOM file below it, replacing any there.
+/// Gzipped files and files whose header can't be read are left out, since a DICOMDIR
+/// reader couldn't open them. Returns the number of files listed; with none, no
+/// DICOMDIR is written.
+///
+/// Files are referenced by their path below `dir`, which must be a valid File ID
+/// (see [`is_file_id_component`]), as CD/DVD exports use and the input layout keeps;
+/// otherwise nothing is written and the first path that isn't is reported.
+pub fn write_dicomdir(dir: &Path) -> Result<usize, DCMRedactErrors> {
+    let mut instances = Vec::new();
+    for path in collect_dicom_files(dir)? {
+        if gz::is_gz(&path) {
+            continue;
+        }
+        let Ok(obj) = gz::open_file(OpenFileOptions::new().read_until(tags::PIXEL_DATA), &path)
+        else {
+            continue;
+        };
+        let relative = path.strip_prefix(dir).unwrap_or(&path);
+        let file_id: Vec<String> = relative
+            .iter()
+            .map(|part| part.to_string_lossy().into_owned())
+            .collect();
+        if file_id.len() > MAX_FILE_ID_COMPONENTS
+            || !file_id.iter().all(|c| is_file_id_component(c))
+        {
+            return Err(DCMRedactErrors::ValueError(format!(
+                "Can't list {} in a DICOMDIR: File IDs take up to {MAX_FILE_ID_COMPONENTS} \
+                 folders and names of 1 to 8 upper-case letters, digits and _ (keep the \
+                 input layout rather than a name pattern)",
+                relative.display()
+            )));
+        }
+        instances.push(Instance { file_id, obj });
+    }
+    let count = instances.len();
+    if count == 0 {
+        return Ok(0);
+    }
+    let patients = hierarchy(instances);
+    let records = records(&patients);
+    let roots: Vec<usize> = {
+        let mut roots = vec![0];
+        while let Some(next) = records[*roots.last().unwrap()].next {
+            roots.push(next);
+        }
+        roots
+    };
+
+    // Offsets are fixed-size, so the layout found with them zeroed holds once they are
+    // set; the file meta must not change in between, so both use the same UID
+    let instance_uid = uids::new_uid();
+    let placeholder = vec![0; records.len()];
+    let draft = dicomdir(&records, &placeholder, (0, 0), &instance_uid)?;
+    let offsets = record_offsets(&draft, &records).ok_or_else(|| {
+        DCMRedactErrors::ValueError("Failed to lay out the DICOMDIR records".to_string())
+    })?;
+    let root = (offsets[roots[0]], offsets[*roots.last().unwrap()]);
+    let bytes = dicomdir(&records, &offsets, root, &instance_uid)?;
+    let path: PathBuf = dir.join("DICOMDIR");
+    std::fs::write(&path, bytes)
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to write {}", path.display()), e))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEM: [u8; 4] = [0xFE, 0xFF, 0x00, 0xE0];
+
+    fn patient_record(name: &str, next: Option<usize>) -> Record {
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::PATIENT_NAME, VR::PN, PrimitiveValue::from(name)),
+            DataElement::new(tags::PATIENT_ID, VR::LO, PrimitiveValue::from("PSEUDO")),
+        ]);
+        Record {
+            item: record("PATIENT", &obj, &PATIENT_KEYS),
+            next,
+            lower: None,
+        }
+    }
+
+    #[test]
+    fn file_id_components_are_short_upper_case_names() {
+        assert!(is_file_id_component("IM000001"));
+        assert!(is_file_id_component("SE_1"));
+        assert!(!is_file_id_component(""));
+        assert!(!is_file_id_component("IM0000001"));
+        assert!(!is_file_id_component("im0001"));
+        assert!(!is_file_id_component("IM1.DCM"));
+        assert!(!is_file_id_component(".."));
+    }
+
+    #[test]
+    fn records_are_typed_by_sop_class() {
+        assert_eq!(record_type("1.2.840.10008.5.1.4.1.1.2"), "IMAGE");
+        assert_eq!(record_type("1.2.840.10008.5.1.4.1.1.88.22"), "SR DOCUMENT");
+        assert_eq!(record_type("1.2.840.10008.5.1.4.1.1.11.1"), "PRESENTATION");
+        assert_eq!(record_type("1.2.840.10008.5.1.4.1.1.104.1"), "ENCAP DOC");
+        assert_eq!(
+            record_type("1.2.840.10008.5.1.4.1.1.481.3"),
+            "RT STRUCTURE SET"
+        );
+    }
+
+    #[test]
+    fn offsets_point_at_each_record_item() {
+        let records = vec![
+            patient_record("A", Some(1)),
+            patient_record("Longer^Name", Some(2)),
+            patient_record("C", None),
+        ];
+        let uid = uids::new_uid();
+        let draft = dicomdir(&records, &[0; 3], (0, 0), &uid).unwrap();
+        let offsets = record_offsets(&draft, &records).unwrap();
+        assert!(offsets.windows(2).all(|w| w[0] < w[1]));
+        let root = (offsets[0], offsets[2]);
+        let bytes = dicomdir(&records, &offsets, root, &uid).unwrap();
+        assert_eq!(bytes.len(), draft.len());
+        assert_eq!(record_offsets(&bytes, &records).unwrap(), offsets);
+        for &offset in &offsets {
+            let at = offset as usize;
+            assert_eq!(bytes[at..at + 4], ITEM);
+        }
+    }
+}
@@ -0,0 +1,630 @@
+//! DICOMweb, for cloud PACS that take HTTP instead of DIMSE: QIDO-RS to look for
+//! studies and series, WADO-RS to retrieve them, and STOW-RS to post redacted
+//! instances back.
+//!
+//! Requests carry a bearer token when one is given. It is never written to the
+//! settings file; the CLI and the GUI read it from [`TOKEN_VAR`] unless told otherwise.
+//! Queries take the same keys and give the same matches as the DIMSE ones in
+//! [`query`](crate::query). QIDO-RS has no patient level, so a patient query looks
+//! for studies and lists each patient they belong to once.
+
+use crate::pacs::{StoreSummary, trimmed};
+use crate::query::{Match, Query, QueryLevel, Retrieved};
+use crate::uids::is_valid_uid;
+use crate::{DCMRedactErrors, gz};
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+use serde_json::Value;
+use std::fmt;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use ureq::{Agent, SendBody};
+
+/// Environment variable the bearer token is read from.
+pub const TOKEN_VAR: &str = "DICOMWEB_TOKEN";
+/// Accept header of WADO-RS retrievals: whole instances, in any transfer syntax.
+const ACCEPT_INSTANCES: &str = "multipart/related; type=\"application/dicom\"; transfer-syntax=*";
+/// Bytes read from a WADO-RS response at a time.
+const CHUNK: usize = 64 * 1024;
+
+/// Attributes asked for on top of those QIDO-RS returns by default.
+const STUDY_FIELDS: [&str; 4] = ["00081030", "00080061", "00201208", "00100030"];
+const SERIES_FIELDS: [&str; 2] = ["0008103E", "00201209"];
+
+/// How the app talks to a PACS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    /// C-STORE, C-FIND, C-MOVE and C-GET.
+    #[default]
+    Dimse,
+    /// STOW-RS, QIDO-RS and WADO-RS.
+    DicomWeb,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 2] = [Protocol::Dimse, Protocol::DicomWeb];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Protocol::Dimse => "DIMSE",
+            Protocol::DicomWeb => "DICOMweb",
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    /// Parse `dimse` or `dicomweb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "dimse" => Ok(Protocol::Dimse),
+            "dicomweb" => Ok(Protocol::DicomWeb),
+            _ => Err(format!(
+                "unknown protocol '{s}' (expected dimse or dicomweb)"
+            )),
+        }
+    }
+}
+
+/// A DICOMweb service: the base URL the `/studies` resources are under, and the bearer
+/// token to send, if any.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Server {
+    pub url: String,
+    pub token: Option<String>,
+}
+
+impl Server {
+    /// The service at `url`, which must be http or https. An empty token means none.
+    pub fn new(url: &str, token: &str) -> Result<Self, String> {
+        let url = url.trim().trim_end_matches('/');
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!(
+                "invalid DICOMweb URL '{url}' (expected http:// or https://)"
+            ));
+        }
+        let token = token.trim();
+        Ok(Server {
+            url: url.to_string(),
+            token: (!token.is_empty()).then(|| token.to_string()),
+        })
+    }
+
+    fn agent(&self) -> Agent {
+        Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into()
+    }
+
+    fn authorization(&self) -> Option<String> {
+        self.token.as_ref().map(|t| format!("Bearer {t}"))
+    }
+}
+
+// The token stays out of logs and error messages
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("url", &self.url)
+            .field("token", &self.token.as_ref().map(|_| "…"))
+            .finish()
+    }
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.url)
+    }
+}
+
+/// Values of the DICOM JSON attribute `tag` in `attrs`, joined with backslashes as
+/// they would be in a data set. Person names give their alphabetic form.
+fn value(attrs: &Value, tag: &str) -> String {
+    let Some(values) = attrs[tag]["Value"].as_array() else {
+        return String::new();
+    };
+    let values: Vec<String> = values
+        .iter()
+        .map(|v| match v {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            Value::Object(pn) => pn["Alphabetic"].as_str().unwrap_or_default().to_string(),
+            _ => String::new(),
+        })
+        .collect();
+    values.join("\\")
+}
+
+fn status_error(context: String, status: u16, body: &str) -> DCMRedactErrors {
+    let body = body.trim();
+    let detail = if body.is_empty() || body.len() > 300 {
+        format!("HTTP status {status}")
+    } else {
+        format!("HTTP status {status}: {body}")
+    };
+    DCMRedactErrors::network(context, detail)
+}
+
+/// QIDO-RS search of `server` for `query`.
+pub fn find(server: &Server, query: &Query) -> Result<Vec<Match>, DCMRedactErrors> {
+    let failed = || format!("Query on {server} failed");
+    let (url, fields): (String, &[&str]) = match query.level {
+        QueryLevel::Series => (
+            format!("{}/studies/{}/series", server.url, query.study_uid.trim()),
+            &SERIES_FIELDS,
+        ),
+        _ => (format!("{}/studies", server.url), &STUDY_FIELDS),
+    };
+    if query.level == QueryLevel::Series && query.study_uid.trim().is_empty() {
+        return Err(DCMRedactErrors::ValueError(
+            "A series query needs the Study Instance UID".to_string(),
+        ));
+    }
+    let name = query.patient_name.trim();
+    let name = if name.is_empty() || name.contains(['*', '?']) {
+        name.to_string()
+    } else {
+        format!("{name}*")
+    };
+    let keys: Vec<(&str, &str)> = match query.level {
+        QueryLevel::Series => vec![("Modality", query.modality.as_str())],
+        QueryLevel::Patient => vec![
+            ("PatientID", query.patient_id.as_str()),
+            ("PatientName", name.as_str()),
+        ],
+        QueryLevel::Study => vec![
+            ("PatientID", query.patient_id.as_str()),
+            ("PatientName", name.as_str()),
+            ("StudyDate", query.study_date.as_str()),
+            ("AccessionNumber", query.accession.as_str()),
+            ("ModalitiesInStudy", query.modality.as_str()),
+        ],
+    };
+
+    let mut request = server
+        .agent()
+        .get(&url)
+        .header("Accept", "application/dicom+json");
+    if let Some(auth) = server.authorization() {
+        request = request.header("Authorization", auth);
+    }
+    for (key, value) in keys {
+        if !value.trim().is_empty() {
+            request = request.query(key, value.trim());
+        }
+    }
+    for field in fields {
+        request = request.query("includefield", *field);
+    }
+    let mut response = request
+        .call()
+        .map_err(|e| DCMRedactErrors::network(failed(), e))?;
+    let status = response.status().as_u16();
+    let body = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| DCMRedactErrors::network(failed(), e))?;
+    if status == 204 {
+        return Ok(Vec::new());
+    }
+    if status != 200 {
+        return Err(status_error(failed(), status, &body));
+    }
+    let results: Vec<Value> = serde_json::from_str(&body)
+        .map_err(|e| DCMRedactErrors::network(format!("Invalid reply from {server}"), e))?;
+
+    let mut matches: Vec<Match> = Vec::new();
+    for attrs in &results {
+        let level = match query.level {
+            QueryLevel::Series => QueryLevel::Series,
+            _ => QueryLevel::Study,
+        };
+        let found = Match {
+            level,
+            patient_id: value(attrs, "00100020"),
+            patient_name: value(attrs, "00100010"),
+            birth_date: value(attrs, "00100030"),
+            study_uid: match level {
+                QueryLevel::Series => query.study_uid.trim().to_string(),
+                _ => value(attrs, "0020000D"),
+            },
+            study_date: value(attrs, "00080020"),
+            study_description: value(attrs, "00081030"),
+            accession: value(attrs, "00080050"),
+            modality: match level {
+                QueryLevel::Series => value(attrs, "00080060"),
+                _ => value(attrs, "00080061"),
+            },
+            series_uid: value(attrs, "0020000E"),
+            series_number: value(attrs, "00200011"),
+            series_description: value(attrs, "0008103E"),
+            related: match level {
+                QueryLevel::Series => value(attrs, "00201209"),
+                _ => value(attrs, "00201208"),
+            },
+        };
+        if query.level != QueryLevel::Patient {
+            matches.push(found);
+            continue;
+        }
+        // One match per patient, counting their studies
+        match matches
+            .iter_mut()
+            .find(|m| m.patient_id == found.patient_id && m.patient_name == found.patient_name)
+        {
+            Some(patient) => {
+                let studies = patient.related.parse::<usize>().unwrap_or(0) + 1;
+                patient.related = studies.to_string();
+            }
+            None => matches.push(Match {
+                level: QueryLevel::Patient,
+                patient_id: found.patient_id,
+                patient_name: found.patient_name,
+                birth_date: found.birth_date,
+                related: "1".to_string(),
+                ..Match::default()
+            }),
+        }
+    }
+    Ok(matches)
+}
+
+/// The `boundary` parameter of a multipart Content-Type.
+fn boundary(content_type: &str) -> Option<String> {
+    content_type.split(';').find_map(|param| {
+        let (name, value) = param.trim().split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The parts of a multipart body, read as they arrive so a whole study is never held
+/// in memory.
+struct Parts<R> {
+    reader: R,
+    buf: Vec<u8>,
+    /// CRLF, two dashes and the boundary.
+    delimiter: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> Parts<R> {
+    fn new(reader: R, boundary: &str) -> Self {
+        Parts {
+            reader,
+            // So the first boundary, at the very start, matches the delimiter too
+            buf: b"\r\n".to_vec(),
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            done: false,
+        }
+    }
+
+    /// Read more of the body; false at its end.
+    fn fill(&mut self) -> std::io::Result<bool> {
+        let mut chunk = vec![0; CHUNK];
+        let n = self.reader.read(&mut chunk)?;
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(n > 0)
+    }
+
+    fn truncated() -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "truncated multipart body",
+        )
+    }
+
+    /// Skip to the start of the first part's headers.
+    fn start(&mut self) -> std::io::Result<()> {
+        loop {
+            if let Some(pos) = find_bytes(&self.buf, &self.delimiter) {
+                self.buf.drain(..pos + self.delimiter.len());
+                return Ok(());
+            }
+            let keep = self.buf.len().saturating_sub(self.delimiter.len());
+            self.buf.drain(..keep);
+            if !self.fill()? {
+                return Err(Self::truncated());
+            }
+        }
+    }
+
+    /// Copy the body of the next part to `out`; false when there are no more parts.
+    fn next(&mut self, out: &mut impl Write) -> std::io::Result<bool> {
+        if self.done {
+            return Ok(false);
+        }
+        while self.buf.len() < 2 {
+            if !self.fill()? {
+                return Err(Self::truncated());
+            }
+        }
+        if self.buf.starts_with(b"--") {
+            self.done = true;
+            return Ok(false);
+        }
+        // Headers end at the first empty line
+        loop {
+            if let Some(pos) = find_bytes(&self.buf, b"\r\n\r\n") {
+                self.buf.drain(..pos + 4);
+                break;
+            }
+            if !self.fill()? {
+                return Err(Self::truncated());
+            }
+        }
+        loop {
+            if let Some(pos) = find_bytes(&self.buf, &self.delimiter) {
+                out.write_all(&self.buf[..pos])?;
+                self.buf.drain(..pos + self.delimiter.len());
+                return Ok(true);
+            }
+            // Keep what could be the start of the delimiter
+            let keep = self.delimiter.len() - 1;
+            if self.buf.len() > keep {
+                let n = self.buf.len() - keep;
+                out.write_all(&self.buf[..n])?;
+                self.buf.drain(..n);
+            }
+            if !self.fill()? {
+                return Err(Self::truncated());
+            }
+        }
+    }
+}
+
+/// Rename the file retrieved to `path` after its SOP Instance UID, if that is a
+/// well-formed UID and no file has that name yet; otherwise it keeps its generated
+/// name. `None` if it isn't a DICOM file.
+fn name_by_instance(path: &Path, dir: &Path) -> Option<PathBuf> {
+    let obj = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()?;
+    let instance = trimmed(obj.meta().media_storage_sop_instance_uid());
+    if !is_valid_uid(&instance) {
+        return Some(path.to_path_buf());
+    }
+    let named = dir.join(format!("{instance}.dcm"));
+    if named.exists() || std::fs::rename(path, &named).is_err() {
+        return Some(path.to_path_buf());
+    }
+    Some(named)
+}
+
+/// WADO-RS retrieval of `matches` (studies and series; others are skipped) from
+/// `server` into `dir`, as `<SOP Instance UID>.dcm`. Parts that are not DICOM files
+/// count as failed.
+pub fn retrieve(
+    server: &Server,
+    matches: &[Match],
+    dir: &Path,
+) -> Result<Retrieved, DCMRedactErrors> {
+    let mut retrieved = Retrieved::default();
+    let agent = server.agent();
+    for m in matches.iter().filter(|m| m.is_retrievable()) {
+        let mut url = format!("{}/studies/{}", server.url, m.study_uid);
+        if m.level == QueryLevel::Series {
+            url.push_str(&format!("/series/{}", m.series_uid));
+        }
+        let failed = || format!("Retrieval from {server} failed");
+        let mut request = agent.get(&url).header("Accept", ACCEPT_INSTANCES);
+        if let Some(auth) = server.authorization() {
+            request = request.header("Authorization", auth);
+        }
+        let mut response = request
+            .call()
+            .map_err(|e| DCMRedactErrors::network(failed(), e))?;
+        let status = response.status().as_u16();
+        if status != 200 {
+            let body = response.body_mut().read_to_string().unwrap_or_default();
+            return Err(status_error(failed(), status, &body));
+        }
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let boundary = boundary(&content_type).ok_or_else(|| {
+            DCMRedactErrors::network(failed(), format!("not a multipart reply ({content_type})"))
+        })?;
+        let mut parts = Parts::new(response.body_mut().as_reader(), &boundary);
+        parts
+            .start()
+            .map_err(|e| DCMRedactErrors::network(failed(), e))?;
+        loop {
+            let part = dir.join(format!("part-{}.dcm", uuid::Uuid::new_v4()));
+            let mut file = File::create(&part).map_err(|e| {
+                DCMRedactErrors::io(format!("Failed to create {}", part.display()), e)
+            })?;
+            let more = parts.next(&mut file);
+            drop(file);
+            match more {
+                Ok(true) => match name_by_instance(&part, dir) {
+                    Some(named) => retrieved.files.push(named),
+                    None => {
+                        let _ = std::fs::remove_file(&part);
+                        retrieved.failed += 1;
+                    }
+                },
+                Ok(false) => {
+                    let _ = std::fs::remove_file(&part);
+                    break;
+                }
+                Err(e) => {
+                    let _ = std::fs::remove_file(&part);
+                    return Err(DCMRedactErrors::network(failed(), e));
+                }
+            }
+        }
+    }
+    Ok(retrieved)
+}
+
+/// The reason the STOW-RS response `body` gives for the instances it failed, if any.
+fn failure_reason(body: &str) -> Option<String> {
+    let reply: Value = serde_json::from_str(body).ok()?;
+    let failed = reply["00081198"]["Value"].as_array()?;
+    let reason = failed.first().map(|item| value(item, "00081197"))?;
+    Some(format!("failure reason {reason}"))
+}
+
+/// The warning the STOW-RS response `body` gives for the instance it took, if any.
+fn warning_reason(body: &str) -> Option<String> {
+    let reply: Value = serde_json::from_str(body).ok()?;
+    let reason = reply["00081199"]["Value"]
+        .as_array()
+        .and_then(|stored| stored.first())
+        .map(|item| value(item, "00081196"))
+        .filter(|r| !r.is_empty())
+        .or_else(|| Some(value(&reply, "00081196")).filter(|r| !r.is_empty()))?;
+    Some(format!("warning reason {reason}"))
+}
+
+/// STOW-RS `files` to `server`, one request each, so every file is reported on its
+/// own. `on_sent` reports each file as it is sent or fails; a file failing never stops
+/// the rest.
+pub fn store(
+    server: &Server,
+    files: &[PathBuf],
+    mut on_sent: impl FnMut(&Path, Result<(), DCMRedactErrors>),
+) -> Result<StoreSummary, DCMRedactErrors> {
+    let agent = server.agent();
+    let url = format!("{}/studies", server.url);
+    let mut summary = StoreSummary::default();
+    for path in files {
+        let failed = || format!("Failed to send {} to {server}", path.display());
+        // The warning of a file taken with HTTP 202
+        let send = || -> Result<Option<String>, DCMRedactErrors> {
+            // STOW-RS takes the DICOM file itself, so gzipped files are sent decompressed
+            let file = gz::reader(path).map_err(|e| {
+                DCMRedactErrors::io(format!("Failed to open {}", path.display()), e)
+            })?;
+            let boundary = format!("dcm-redact-{}", uuid::Uuid::new_v4().simple());
+            let head = format!("--{boundary}\r\nContent-Type: application/dicom\r\n\r\n");
+            let tail = format!("\r\n--{boundary}--\r\n");
+            let mut body = Cursor::new(head).chain(file).chain(Cursor::new(tail));
+            let mut request = agent
+                .post(&url)
+                .header(
+                    "Content-Type",
+                    format!("multipart/related; type=\"application/dicom\"; boundary={boundary}"),
+                )
+                .header("Accept", "application/dicom+json");
+            if let Some(auth) = server.authorization() {
+                request = request.header("Authorization", auth);
+            }
+            let mut response = request
+                .send(SendBody::from_reader(&mut body))
+                .map_err(|e| DCMRedactErrors::network(failed(), e))?;
+            let status = response.status().as_u16();
+            let reply = response.body_mut().read_to_string().unwrap_or_default();
+            match (status, failure_reason(&reply)) {
+                (200, _) => Ok(None),
+                // Accepted with warnings, unless the one instance is listed as failed
+                (202, None) => Ok(Some(
+                    warning_reason(&reply).unwrap_or_else(|| "HTTP status 202".to_string()),
+                )),
+                (202 | 409, reason) => Err(DCMRedactErrors::network(
+                    failed(),
+                    reason.unwrap_or_else(|| format!("HTTP status {status}")),
+                )),
+                _ => Err(status_error(failed(), status, &reply)),
+            }
+        };
+        let result = send().map(|warning| {
+            if let Some(warning) = warning {
+                summary
+                    .warnings
+                    .push(format!("{}: {warning}", path.display()));
+            }
+        });
+        summary.record(&result);
+        on_sent(path, result);
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out its bytes a few at a time, as a network stream may.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(self.1).min(buf.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    const BODY: &[u8] = b"preamble\r\n--b0undary\r\nContent-Type: application/dicom\r\n\r\n\
+        first\r\n--b0undary\r\nContent-Type: application/dicom\r\n\
+        Content-Length: 12\r\n\r\nsecond\r\n-- b\r\n--b0undary--\r\nepilogue";
+
+    fn parts(reader: impl Read, boundary: &str) -> std::io::Result<Vec<Vec<u8>>> {
+        let mut parts = Parts::new(reader, boundary);
+        parts.start()?;
+        let mut found = Vec::new();
+        loop {
+            let mut part = Vec::new();
+            if !parts.next(&mut part)? {
+                return Ok(found);
+            }
+            found.push(part);
+        }
+    }
+
+    #[test]
+    fn boundary_is_read_from_the_content_type() {
+        let content_type = "multipart/related; type=\"application/dicom\"; Boundary=\"abc:1\"";
+        assert_eq!(boundary(content_type).as_deref(), Some("abc:1"));
+        assert_eq!(
+            boundary("multipart/related; boundary=abc").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(boundary("application/dicom+json"), None);
+    }
+
+    #[test]
+    fn parts_are_split_at_the_boundary() {
+        let expected = vec![b"first".to_vec(), b"second\r\n-- b".to_vec()];
+        assert_eq!(parts(BODY, "b0undary").unwrap(), expected);
+        for step in [1, 3, 7] {
+            assert_eq!(parts(Trickle(BODY, step), "b0undary").unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn a_body_starting_with_the_boundary_has_no_preamble() {
+        let body = b"--b\r\n\r\nonly\r\n--b--";
+        assert_eq!(parts(&body[..], "b").unwrap(), vec![b"only".to_vec()]);
+    }
+
+    #[test]
+    fn truncated_bodies_are_errors() {
+        let cut = &BODY[..BODY.len() - 30];
+        let error = parts(Trickle(cut, 5), "b0undary").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(parts(&b"no boundary here"[..], "b0undary").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Encapsulated documents: PDF reports and HL7 CDA documents wrapped in DICOM
+//! (EncapsulatedDocument) instead of pixel data.
+//!
+//! The document itself can hold anything the report does, names included, and isn't
+//! rendered here, so it can't be redacted in place. Instead it is kept, swapped for a
+//! placeholder saying it was removed ([`placeholder`]), or replaced by a copy
+//! redacted elsewhere ([`Redactor::replace_document`](crate::Redactor::replace_document)).
+//! The metadata around it is de-identified like any other file.
+
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use std::fmt;
+use std::str::FromStr;
+
+/// Text of the placeholder documents.
+const PLACEHOLDER_TEXT: &str = "This document was removed during de-identification.";
+
+/// What happens to encapsulated documents on save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Documents {
+    /// Keep the document as it is; only the metadata is de-identified.
+    Keep,
+    /// Swap the document for a placeholder of the same type.
+    #[default]
+    Replace,
+    /// Leave the instance out of the output.
+    Remove,
+}
+
+impl Documents {
+    pub const ALL: [Documents; 3] = [Documents::Keep, Documents::Replace, Documents::Remove];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Documents::Keep => "Keep",
+            Documents::Replace => "Replace",
+            Documents::Remove => "Remove",
+        }
+    }
+}
+
+impl fmt::Display for Documents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for Documents {
+    type Err = String;
+
+    /// Parse `keep`, `replace` or `remove`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" => Ok(Documents::Keep),
+            "replace" => Ok(Documents::Replace),
+            "remove" => Ok(Documents::Remove),
+            _ => Err(format!(
+                "unknown document handling '{s}' (expected keep, replace or remove)"
+            )),
+        }
+    }
+}
+
+/// Whether `obj` wraps an encapsulated document.
+pub fn is_encapsulated(obj: &InMemDicomObject) -> bool {
+    obj.element(tags::ENCAPSULATED_DOCUMENT).is_ok()
+}
+
+/// MIMETypeOfEncapsulatedDocument, lowercase (`application/pdf`, `text/xml`, ...).
+pub fn mime_type(obj: &InMemDicomObject) -> String {
+    obj.element(tags::MIME_TYPE_OF_ENCAPSULATED_DOCUMENT)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// `PDF`, `CDA`, or the MIME type of other documents, for display.
+pub fn kind(obj: &InMemDicomObject) -> String {
+    match mime_type(obj).as_str() {
+        "application/pdf" => "PDF".to_string(),
+        "text/xml" => "CDA".to_string(),
+        "" => "Unknown".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// File extension for the document in `obj`, without the dot.
+pub fn extension(obj: &InMemDicomObject) -> &'static str {
+    match mime_type(obj).as_str() {
+        "application/pdf" => "pdf",
+        "text/xml" => "xml",
+        _ => "bin",
+    }
+}
+
+/// The document bytes, without the padding added to make their length even.
+pub fn payload(obj: &InMemDicomObject) -> Option<Vec<u8>> {
+    let mut bytes = obj
+        .element(tags::ENCAPSULATED_DOCUMENT)
+        .ok()?
+        .to_bytes()
+        .ok()?
+        .into_owned();
+    let length = obj
+        .element(tags::ENCAPSULATED_DOCUMENT_LENGTH)
+        .ok()
+        .and_then(|e| e.to_int::<u32>().ok());
+    match length {
+        Some(len) => bytes.truncate(len as usize),
+        None if bytes.last() == Some(&0) => {
+            bytes.pop();
+        }
+        None => {}
+    }
+    Some(bytes)
+}
+
+/// Replace the document in `obj` with `bytes`, padded to even length, updating
+/// EncapsulatedDocumentLength where present.
+pub fn set_payload(obj: &mut InMemDicomObject, bytes: &[u8]) {
+    if obj.element(tags::ENCAPSULATED_DOCUMENT_LENGTH).is_ok() {
+        obj.put(DataElement::new(
+            tags::ENCAPSULATED_DOCUMENT_LENGTH,
+            VR::UL,
+            PrimitiveValue::from(bytes.len() as u32),
+        ));
+    }
+    let mut padded = bytes.to_vec();
+    if padded.len() % 2 == 1 {
+        padded.push(0);
+    }
+    obj.put(DataElement::new(
+        tags::ENCAPSULATED_DOCUMENT,
+        VR::OB,
+        PrimitiveValue::U8(padded.into()),
+    ));
+}
+
+/// A one-page PDF showing `text` (plain ASCII, no parentheses or backslashes).
+fn placeholder_pdf(text: &str) -> Vec<u8> {
+    let content = format!("BT /F1 16 Tf 72 720 Td ({text}) Tj ET");
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+         /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        format!(
+            "<< /Length {} >>\nstream\n{content}\nendstream",
+            content.len()
+        ),
+    ];
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, body) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{body}\nendobj\n", i + 1).as_bytes());
+    }
+    let xref = pdf.len();
+    let size = objects.len() + 1;
+    pdf.extend_from_slice(format!("xref\n0 {size}\n0000000000 65535 f \n").as_bytes());
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!("trailer\n<< /Size {size} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n").as_bytes(),
+    );
+    pdf
+}
+
+/// A document of the same type as the one in `obj` saying it was removed: a PDF, an
+/// empty CDA document, or plain text for other types.
+pub fn placeholder(obj: &InMemDicomObject) -> Vec<u8> {
+    match mime_type(obj).as_str() {
+        "application/pdf" => placeholder_pdf(PLACEHOLDER_TEXT),
+        "text/xml" => format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!-- {PLACEHOLDER_TEXT} -->\n\
+             <ClinicalDocument xmlns=\"urn:hl7-org:v3\"/>\n"
+        )
+        .into_bytes(),
+        _ => PLACEHOLDER_TEXT.as_bytes().to_vec(),
+    }
+}
@@ -0,0 +1,93 @@
+//! Export of a redacted frame as an ordinary image, for presentations and datasets
+//! that don't take DICOM: PNG, JPEG or TIFF, picked by the file extension.
+//!
+//! Grayscale frames are either windowed (the VOI baked in as 8-bit display values,
+//! as they look on screen) or written as their stored values: 16-bit in PNG and TIFF,
+//! the top 8 of `BitsStored` bits in JPEG.
+
+use crate::voi::{Rescale, Voi, display_lut};
+use crate::{DCMRedactErrors, Frame, Gray16Image};
+use image::codecs::jpeg::JpegEncoder;
+use image::{GrayImage, ImageFormat};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+const JPEG_QUALITY: u8 = 95;
+
+/// How grayscale frames are turned into image samples.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrayExport {
+    /// Stored values, unchanged where the format has 16-bit samples.
+    Stored { bits_stored: u16 },
+    /// Display values through `rescale` and `voi`, inverted for MONOCHROME1.
+    Windowed {
+        rescale: Rescale,
+        voi: Voi,
+        invert: bool,
+    },
+}
+
+/// The format for `path`'s extension.
+pub fn export_format(path: &Path) -> Result<ImageFormat, DCMRedactErrors> {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "png" => Ok(ImageFormat::Png),
+        "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+        "tif" | "tiff" => Ok(ImageFormat::Tiff),
+        _ => Err(DCMRedactErrors::ValueError(format!(
+            "Can't export {}: expected a .png, .jpg or .tif file",
+            path.display()
+        ))),
+    }
+}
+
+fn windowed(img: &Gray16Image, rescale: Rescale, voi: &Voi, invert: bool) -> GrayImage {
+    let lut = display_lut(rescale, voi);
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let v = lut[img.get_pixel(x, y)[0] as usize];
+        image::Luma([if invert { 255 - v } else { v }])
+    })
+}
+
+fn top_bits(img: &Gray16Image, bits_stored: u16) -> GrayImage {
+    let shift = bits_stored.clamp(8, 16) - 8;
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        image::Luma([(img.get_pixel(x, y)[0] >> shift).min(255) as u8])
+    })
+}
+
+/// Write `frame` to `path` as PNG, JPEG or TIFF (by extension), grayscale as `gray`
+/// says. Color frames are written as 8-bit RGB.
+pub fn export_frame(frame: &Frame, path: &Path, gray: &GrayExport) -> Result<(), DCMRedactErrors> {
+    let format = export_format(path)?;
+    let failed = |e: image::ImageError| {
+        DCMRedactErrors::encode(format!("Failed to export {}", path.display()), e)
+    };
+    let img: image::DynamicImage = match (frame, gray) {
+        (Frame::Rgb(img), _) => img.clone().into(),
+        (
+            Frame::Gray(img),
+            GrayExport::Windowed {
+                rescale,
+                voi,
+                invert,
+            },
+        ) => windowed(img, *rescale, voi, *invert).into(),
+        (Frame::Gray(img), GrayExport::Stored { bits_stored }) => match format {
+            ImageFormat::Jpeg => top_bits(img, *bits_stored).into(),
+            _ => img.clone().into(),
+        },
+    };
+    if format != ImageFormat::Jpeg {
+        return img.save_with_format(path, format).map_err(failed);
+    }
+    let file = File::create(path)
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to create {}", path.display()), e))?;
+    JpegEncoder::new_with_quality(BufWriter::new(file), JPEG_QUALITY)
+        .encode_image(&img)
+        .map_err(failed)
+}
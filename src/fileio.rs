@@ -0,0 +1,75 @@
+//! Where [`Redactor::open_in`](crate::Redactor::open_in) reads files and
+//! [`Redactor::save_in`](crate::Redactor::save_in) writes them: the file system on
+//! desktop, or memory in the browser build, where files come from the page's file
+//! picker and leave as downloads, never through a server.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub trait FileIo {
+    /// The whole contents of `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Replace the contents of `path` with `bytes`, creating it if needed.
+    fn write(&mut self, path: &Path, bytes: Vec<u8>) -> io::Result<()>;
+}
+
+/// The file system. Missing parent folders are created on write.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativeFs;
+
+impl FileIo for NativeFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn write(&mut self, path: &Path, bytes: Vec<u8>) -> io::Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, bytes)
+    }
+}
+
+/// Files held in memory by path.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFs {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, e.g. one picked on the page.
+    pub fn insert(&mut self, path: impl Into<PathBuf>, bytes: Vec<u8>) {
+        self.files.insert(path.into(), bytes);
+    }
+
+    /// Remove a file and return its contents, e.g. to offer it as a download.
+    pub fn take(&mut self, path: &Path) -> Option<Vec<u8>> {
+        self.files.remove(path)
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+}
+
+impl FileIo for MemoryFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.files.get(path).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not loaded", path.display()),
+            )
+        })
+    }
+
+    fn write(&mut self, path: &Path, bytes: Vec<u8>) -> io::Result<()> {
+        self.files.insert(path.to_path_buf(), bytes);
+        Ok(())
+    }
+}
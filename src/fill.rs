@@ -0,0 +1,347 @@
+//! What redacted pixels are replaced with.
+//!
+//! Black is the default and the only style that guarantees nothing of the original
+//! survives; the others avoid sharp edges for downstream ML pipelines. Blur and mosaic
+//! work on the shape's bounding box, so they only see pixels near the region.
+
+use crate::shape::Shape;
+use image::{ImageBuffer, Pixel, imageops};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactFill {
+    /// Zero; [`crate::Redactor`] uses the highest stored value for MONOCHROME1, where
+    /// that is what displays as black.
+    #[default]
+    Black,
+    /// Every channel set to this value (a stored value for grayscale, 0-255 for RGB).
+    Constant(u16),
+    /// Gaussian blur with this sigma, in pixels.
+    Blur(f32),
+    /// Average over square blocks of this size, in pixels.
+    Mosaic(u32),
+    /// Uniform noise between the darkest and brightest covered values.
+    Noise,
+    /// The mean of the covered pixels.
+    Mean,
+}
+
+impl RedactFill {
+    /// One of each style, with default parameters, for pickers.
+    pub const ALL: [RedactFill; 6] = [
+        RedactFill::Black,
+        RedactFill::Constant(0),
+        RedactFill::Blur(8.0),
+        RedactFill::Mosaic(16),
+        RedactFill::Noise,
+        RedactFill::Mean,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RedactFill::Black => "Black",
+            RedactFill::Constant(_) => "Constant",
+            RedactFill::Blur(_) => "Blur",
+            RedactFill::Mosaic(_) => "Mosaic",
+            RedactFill::Noise => "Noise",
+            RedactFill::Mean => "Mean",
+        }
+    }
+
+    /// Whether `self` and `other` are the same style, ignoring parameters.
+    pub fn same_style(self, other: RedactFill) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+}
+
+impl fmt::Display for RedactFill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedactFill::Black => write!(f, "black"),
+            RedactFill::Constant(v) => write!(f, "constant:{v}"),
+            RedactFill::Blur(sigma) => write!(f, "blur:{sigma}"),
+            RedactFill::Mosaic(block) => write!(f, "mosaic:{block}"),
+            RedactFill::Noise => write!(f, "noise"),
+            RedactFill::Mean => write!(f, "mean"),
+        }
+    }
+}
+
+impl FromStr for RedactFill {
+    type Err = String;
+
+    /// Parse `black`, `constant:V`, `blur[:SIGMA]`, `mosaic[:BLOCK]`, `noise` or `mean`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (style, param) = match s.split_once(':') {
+            Some((style, param)) => (style, Some(param.trim())),
+            None => (s, None),
+        };
+        let bad = || format!("invalid parameter in fill '{s}'");
+        match (style.trim().to_ascii_lowercase().as_str(), param) {
+            ("black", None) => Ok(RedactFill::Black),
+            ("constant", Some(v)) => v.parse().map(RedactFill::Constant).map_err(|_| bad()),
+            ("blur", None) => Ok(RedactFill::Blur(8.0)),
+            ("blur", Some(v)) => match v.parse::<f32>() {
+                Ok(sigma) if sigma > 0.0 => Ok(RedactFill::Blur(sigma)),
+                _ => Err(bad()),
+            },
+            ("mosaic", None) => Ok(RedactFill::Mosaic(16)),
+            ("mosaic", Some(v)) => match v.parse::<u32>() {
+                Ok(block) if block > 0 => Ok(RedactFill::Mosaic(block)),
+                _ => Err(bad()),
+            },
+            ("noise", None) => Ok(RedactFill::Noise),
+            ("mean", None) => Ok(RedactFill::Mean),
+            _ => Err(format!(
+                "unknown fill '{s}' (expected black, constant:V, blur[:SIGMA], mosaic[:BLOCK], noise or mean)"
+            )),
+        }
+    }
+}
+
+/// Channel types the fills can average and interpolate.
+pub trait Channel: Copy + 'static {
+    fn to_f64(self) -> f64;
+    /// Rounded and clamped into range.
+    fn from_f64(v: f64) -> Self;
+}
+
+impl Channel for u8 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(v: f64) -> Self {
+        v.round() as u8
+    }
+}
+
+impl Channel for u16 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(v: f64) -> Self {
+        v.round() as u16
+    }
+}
+
+fn uniform<P: Pixel>(v: P::Subpixel) -> P {
+    *P::from_slice(&[v; 4][..P::CHANNEL_COUNT as usize])
+}
+
+fn from_channels<P: Pixel>(values: &[f64]) -> P
+where
+    P::Subpixel: Channel,
+{
+    let mut channels = [P::Subpixel::from_f64(0.0); 4];
+    for (c, v) in channels.iter_mut().zip(values) {
+        *c = P::Subpixel::from_f64(*v);
+    }
+    *P::from_slice(&channels[..P::CHANNEL_COUNT as usize])
+}
+
+/// Replace every pixel covered by `shape` according to `fill` (in-place).
+pub fn fill_shape<P>(img: &mut ImageBuffer<P, Vec<P::Subpixel>>, shape: &Shape, fill: RedactFill)
+where
+    P: Pixel + 'static,
+    P::Subpixel: Channel,
+{
+    let (w, h) = img.dimensions();
+    let channels = P::CHANNEL_COUNT as usize;
+    let Some(b) = shape.bounds() else {
+        return;
+    };
+    let (bx1, by1) = (b.x1.min(w), b.y1.min(h));
+    if b.x0 >= bx1 || b.y0 >= by1 {
+        return;
+    }
+
+    match fill {
+        RedactFill::Black | RedactFill::Constant(_) => {
+            let value = match fill {
+                RedactFill::Constant(v) => P::Subpixel::from_f64(v as f64),
+                _ => P::Subpixel::from_f64(0.0),
+            };
+            let px = uniform::<P>(value);
+            shape.for_each_span(w, h, |y, x0, x1| {
+                for x in x0..x1 {
+                    img.put_pixel(x, y, px);
+                }
+            });
+        }
+        RedactFill::Mean => {
+            let mut sums = [0.0f64; 4];
+            let mut n = 0usize;
+            shape.for_each_span(w, h, |y, x0, x1| {
+                for x in x0..x1 {
+                    for (s, c) in sums.iter_mut().zip(img.get_pixel(x, y).channels()) {
+                        *s += c.to_f64();
+                    }
+                    n += 1;
+                }
+            });
+            let px = from_channels::<P>(&sums.map(|s| s / n.max(1) as f64)[..channels]);
+            shape.for_each_span(w, h, |y, x0, x1| {
+                for x in x0..x1 {
+                    img.put_pixel(x, y, px);
+                }
+            });
+        }
+        RedactFill::Noise => {
+            let mut lo = [f64::MAX; 4];
+            let mut hi = [f64::MIN; 4];
+            shape.for_each_span(w, h, |y, x0, x1| {
+                for x in x0..x1 {
+                    for (i, c) in img.get_pixel(x, y).channels().iter().enumerate() {
+                        lo[i] = lo[i].min(c.to_f64());
+                        hi[i] = hi[i].max(c.to_f64());
+                    }
+                }
+            });
+            let mut rng = rand::thread_rng();
+            shape.for_each_span(w, h, |y, x0, x1| {
+                for x in x0..x1 {
+                    let values: Vec<f64> = (0..channels)
+                        .map(|i| rng.gen_range(lo[i]..=hi[i]))
+                        .collect();
+                    img.put_pixel(x, y, from_channels::<P>(&values));
+                }
+            });
+        }
+        RedactFill::Blur(_) | RedactFill::Mosaic(_) => {
+            let patch = imageops::crop_imm(img, b.x0, b.y0, bx1 - b.x0, by1 - b.y0).to_image();
+            let patch = match fill {
+                RedactFill::Blur(sigma) => imageops::blur(&patch, sigma),
+                RedactFill::Mosaic(block) => mosaic(&patch, block.max(1)),
+                _ => unreachable!(),
+            };
+            shape.for_each_span(w, h, |y, x0, x1| {
+                for x in x0..x1 {
+                    img.put_pixel(x, y, *patch.get_pixel(x - b.x0, y - b.y0));
+                }
+            });
+        }
+    }
+}
+
+/// Every `block` x `block` tile replaced by its mean.
+fn mosaic<P>(img: &ImageBuffer<P, Vec<P::Subpixel>>, block: u32) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel,
+    P::Subpixel: Channel,
+{
+    let (w, h) = img.dimensions();
+    let channels = P::CHANNEL_COUNT as usize;
+    let mut out = img.clone();
+    for ty in (0..h).step_by(block as usize) {
+        for tx in (0..w).step_by(block as usize) {
+            let (tx1, ty1) = ((tx + block).min(w), (ty + block).min(h));
+            let mut sums = [0.0f64; 4];
+            for y in ty..ty1 {
+                for x in tx..tx1 {
+                    for (s, c) in sums.iter_mut().zip(img.get_pixel(x, y).channels()) {
+                        *s += c.to_f64();
+                    }
+                }
+            }
+            let n = ((tx1 - tx) * (ty1 - ty)) as f64;
+            let px = from_channels::<P>(&sums.map(|s| s / n)[..channels]);
+            for y in ty..ty1 {
+                for x in tx..tx1 {
+                    out.put_pixel(x, y, px);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RedactRect;
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    fn rect(x0: u32, y0: u32, x1: u32, y1: u32) -> Shape {
+        Shape::Rect(RedactRect { x0, y0, x1, y1 })
+    }
+
+    /// A 4x4 image with values 0, 10, ..., 150 row by row.
+    fn ramp() -> GrayImage {
+        GrayImage::from_fn(4, 4, |x, y| Luma([((y * 4 + x) * 10) as u8]))
+    }
+
+    #[test]
+    fn fills_parse_and_print_the_same() {
+        for fill in [
+            RedactFill::Black,
+            RedactFill::Constant(4095),
+            RedactFill::Blur(2.5),
+            RedactFill::Mosaic(8),
+            RedactFill::Noise,
+            RedactFill::Mean,
+        ] {
+            assert_eq!(fill.to_string().parse::<RedactFill>(), Ok(fill));
+        }
+        assert_eq!("Blur".parse(), Ok(RedactFill::Blur(8.0)));
+        assert_eq!("mosaic".parse(), Ok(RedactFill::Mosaic(16)));
+        for bad in ["constant", "blur:0", "mosaic:-1", "black:1", "paint"] {
+            assert!(bad.parse::<RedactFill>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn styles_compare_without_parameters() {
+        assert!(RedactFill::Blur(1.0).same_style(RedactFill::Blur(9.0)));
+        assert!(!RedactFill::Blur(1.0).same_style(RedactFill::Mosaic(1)));
+    }
+
+    #[test]
+    fn constant_fills_only_the_shape() {
+        let mut img = ramp();
+        fill_shape(&mut img, &rect(1, 1, 3, 2), RedactFill::Constant(200));
+        assert_eq!(img.get_pixel(1, 1)[0], 200);
+        assert_eq!(img.get_pixel(2, 1)[0], 200);
+        assert_eq!(img.get_pixel(3, 1)[0], 70);
+        assert_eq!(img.get_pixel(1, 2)[0], 90);
+    }
+
+    #[test]
+    fn mean_fills_with_the_covered_average() {
+        let mut img = RgbImage::from_pixel(4, 4, Rgb([0, 0, 0]));
+        img.put_pixel(0, 0, Rgb([100, 200, 40]));
+        fill_shape(&mut img, &rect(0, 0, 2, 1), RedactFill::Mean);
+        assert_eq!(*img.get_pixel(1, 0), Rgb([50, 100, 20]));
+        assert_eq!(*img.get_pixel(2, 0), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn noise_stays_within_the_covered_range() {
+        let mut img = ramp();
+        fill_shape(&mut img, &rect(0, 1, 4, 2), RedactFill::Noise);
+        assert!((0..4).all(|x| (40..=70).contains(&img.get_pixel(x, 1)[0])));
+        assert_eq!(img.get_pixel(0, 0)[0], 0);
+    }
+
+    #[test]
+    fn mosaic_averages_each_block() {
+        let mut img = ramp();
+        fill_shape(&mut img, &rect(0, 0, 4, 4), RedactFill::Mosaic(2));
+        // The top-left block holds 0, 10, 40 and 50
+        assert!((0..2).all(|x| (0..2).all(|y| img.get_pixel(x, y)[0] == 25)));
+        assert_eq!(img.get_pixel(3, 3)[0], 125);
+    }
+
+    #[test]
+    fn shapes_past_the_edge_are_clipped() {
+        let mut img = ramp();
+        fill_shape(&mut img, &rect(5, 5, 10, 10), RedactFill::Black);
+        assert_eq!(img, ramp());
+        fill_shape(&mut img, &rect(2, 3, 10, 10), RedactFill::Constant(1));
+        assert_eq!(img.get_pixel(3, 3)[0], 1);
+        assert_eq!(img.get_pixel(1, 3)[0], 130);
+    }
+}
@@ -0,0 +1,189 @@
+//! Folder and DICOMDIR scanning for browsing a whole study.
+
+use crate::DCMRedactErrors;
+use crate::batch::collect_dicom_files;
+use crate::gz;
+use dicom::dictionary_std::tags;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+use std::path::{Path, PathBuf};
+
+/// One DICOM file found in a folder, with the attributes used to sort it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub path: PathBuf,
+    pub series_number: Option<i32>,
+    pub instance_number: Option<i32>,
+    pub series_description: String,
+    /// SeriesInstanceUID; empty if missing.
+    pub series_uid: String,
+}
+
+impl Instance {
+    /// Short label such as "S2 I14".
+    pub fn label(&self) -> String {
+        let num = |n: Option<i32>| n.map_or_else(|| "?".to_string(), |n| n.to_string());
+        format!(
+            "S{} I{}",
+            num(self.series_number),
+            num(self.instance_number)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// SeriesNumber, then InstanceNumber (files without them go last).
+    #[default]
+    SeriesInstance,
+    /// InstanceNumber only, ignoring series.
+    Instance,
+    /// File path.
+    Path,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 3] = [
+        SortOrder::SeriesInstance,
+        SortOrder::Instance,
+        SortOrder::Path,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortOrder::SeriesInstance => "Series / Instance",
+            SortOrder::Instance => "Instance",
+            SortOrder::Path => "File name",
+        }
+    }
+}
+
+pub fn sort_instances(instances: &mut [Instance], order: SortOrder) {
+    // `None` sorts after every number
+    let key = |n: Option<i32>| n.unwrap_or(i32::MAX);
+    match order {
+        SortOrder::SeriesInstance => instances.sort_by(|a, b| {
+            (key(a.series_number), key(a.instance_number), &a.path).cmp(&(
+                key(b.series_number),
+                key(b.instance_number),
+                &b.path,
+            ))
+        }),
+        SortOrder::Instance => instances.sort_by(|a, b| {
+            (key(a.instance_number), &a.path).cmp(&(key(b.instance_number), &b.path))
+        }),
+        SortOrder::Path => instances.sort_by(|a, b| a.path.cmp(&b.path)),
+    }
+}
+
+fn int_attr(obj: &InMemDicomObject, tag: dicom::core::Tag) -> Option<i32> {
+    obj.element(tag).ok().and_then(|e| e.to_int().ok())
+}
+
+/// Read the sort attributes of `path` without loading its pixel data.
+fn read_instance(path: &Path) -> Result<Instance, DCMRedactErrors> {
+    let obj = gz::open_file(OpenFileOptions::new().read_until(tags::PIXEL_DATA), path)
+        .map_err(|e| DCMRedactErrors::decode(format!("Failed to read {}", path.display()), e))?;
+    Ok(Instance {
+        path: path.to_path_buf(),
+        series_number: int_attr(&obj, tags::SERIES_NUMBER),
+        instance_number: int_attr(&obj, tags::INSTANCE_NUMBER),
+        series_description: obj
+            .element(tags::SERIES_DESCRIPTION)
+            .ok()
+            .and_then(|e| e.to_str().ok())
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default(),
+        series_uid: obj
+            .element(tags::SERIES_INSTANCE_UID)
+            .ok()
+            .and_then(|e| e.to_str().ok())
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default(),
+    })
+}
+
+/// Every file referenced by a DICOMDIR, resolved relative to the DICOMDIR's folder.
+pub fn dicomdir_files(dicomdir: &Path) -> Result<Vec<PathBuf>, DCMRedactErrors> {
+    let obj = OpenFileOptions::new().open_file(dicomdir).map_err(|e| {
+        DCMRedactErrors::decode(format!("Failed to read {}", dicomdir.display()), e)
+    })?;
+    let root = dicomdir.parent().unwrap_or(Path::new("."));
+    let records = obj
+        .element(tags::DIRECTORY_RECORD_SEQUENCE)
+        .ok()
+        .and_then(|e| e.items())
+        .unwrap_or(&[]);
+    Ok(records
+        .iter()
+        .filter_map(|record| {
+            let id = record.element(tags::REFERENCED_FILE_ID).ok()?;
+            // ReferencedFileID is a multi-valued CS, one value per path component
+            let parts = id.to_multi_str().ok()?;
+            Some(
+                parts
+                    .iter()
+                    .fold(root.to_path_buf(), |p, c| p.join(c.trim())),
+            )
+        })
+        .collect())
+}
+
+/// Scan `dir` for DICOM instances: through its DICOMDIR if it has one, otherwise
+/// every DICOM file below it (by content or extension). Files that can't be read are
+/// skipped.
+pub fn scan_folder(dir: &Path, order: SortOrder) -> Result<Vec<Instance>, DCMRedactErrors> {
+    let dicomdir = dir.join("DICOMDIR");
+    let files = if dicomdir.is_file() {
+        dicomdir_files(&dicomdir)?
+    } else {
+        collect_dicom_files(dir)?
+    };
+    let mut instances: Vec<Instance> = files.iter().filter_map(|p| read_instance(p).ok()).collect();
+    sort_instances(&mut instances, order);
+    Ok(instances)
+}
+
+/// Instances for files and folders picked explicitly (e.g. on the command line):
+/// folders are scanned with [`scan_folder`], files read directly. Also returns the
+/// files that aren't readable DICOM.
+pub fn scan_paths(paths: &[PathBuf], order: SortOrder) -> (Vec<Instance>, Vec<PathBuf>) {
+    let mut instances: Vec<Instance> = Vec::new();
+    let mut skipped = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            match scan_folder(path, order) {
+                Ok(found) => instances.extend(found),
+                Err(_) => skipped.push(path.clone()),
+            }
+        } else {
+            match read_instance(path) {
+                Ok(instance) => instances.push(instance),
+                Err(_) => skipped.push(path.clone()),
+            }
+        }
+    }
+    instances.sort_by(|a, b| a.path.cmp(&b.path));
+    instances.dedup_by(|a, b| a.path == b.path);
+    sort_instances(&mut instances, order);
+    (instances, skipped)
+}
+
+/// Deepest folder containing every path (the folder itself for a lone folder).
+pub fn common_dir(paths: &[PathBuf]) -> Option<PathBuf> {
+    let mut dirs = paths.iter().map(|p| {
+        if p.is_dir() {
+            p.as_path()
+        } else {
+            p.parent().unwrap_or(Path::new(""))
+        }
+    });
+    let mut common = dirs.next()?.to_path_buf();
+    for dir in dirs {
+        while !dir.starts_with(&common) {
+            if !common.pop() {
+                return None;
+            }
+        }
+    }
+    Some(common)
+}
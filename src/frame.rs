@@ -0,0 +1,155 @@
+//! A single decoded frame, either grayscale (stored values) or 8-bit RGB.
+
+use crate::fill::{RedactFill, fill_shape};
+use crate::shape::Shape;
+use crate::{Gray16Image, RedactRect};
+use image::{ImageBuffer, Pixel, Primitive, imageops};
+use std::path::Path;
+
+pub type Rgb8Image = ImageBuffer<image::Rgb<u8>, Vec<u8>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    /// Monochrome stored pixel values, widened to 16 bits for 8-bit sources and
+    /// offset to start at 0 for signed ones.
+    Gray(Gray16Image),
+    /// Color pixels, already converted to RGB (from YBR / palette if needed).
+    Rgb(Rgb8Image),
+}
+
+fn black<P: Pixel>() -> P {
+    let zeros = [P::Subpixel::DEFAULT_MIN_VALUE; 4];
+    *P::from_slice(&zeros[..P::CHANNEL_COUNT as usize])
+}
+
+/// Turn pixels in the given (x0..x1, y0..y1) rectangle to black (in-place).
+pub fn blacken_rect<P: Pixel>(
+    img: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+) {
+    let (w, h) = img.dimensions();
+    let x0 = x0.min(w.saturating_sub(1));
+    let y0 = y0.min(h.saturating_sub(1));
+    let x1 = x1.min(w);
+    let y1 = y1.min(h);
+    let black = black::<P>();
+    for y in y0..y1 {
+        for x in x0..x1 {
+            img.put_pixel(x, y, black);
+        }
+    }
+}
+
+/// Turn every pixel covered by `shape` black (in-place).
+pub fn blacken_shape<P: Pixel>(img: &mut ImageBuffer<P, Vec<P::Subpixel>>, shape: &Shape) {
+    let (w, h) = img.dimensions();
+    let black = black::<P>();
+    shape.for_each_span(w, h, |y, x0, x1| {
+        for x in x0..x1 {
+            img.put_pixel(x, y, black);
+        }
+    });
+}
+
+impl From<Gray16Image> for Frame {
+    fn from(img: Gray16Image) -> Self {
+        Frame::Gray(img)
+    }
+}
+
+impl From<Rgb8Image> for Frame {
+    fn from(img: Rgb8Image) -> Self {
+        Frame::Rgb(img)
+    }
+}
+
+impl Frame {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Frame::Gray(img) => img.dimensions(),
+            Frame::Rgb(img) => img.dimensions(),
+        }
+    }
+
+    pub fn is_color(&self) -> bool {
+        matches!(self, Frame::Rgb(_))
+    }
+
+    pub fn as_gray(&self) -> Option<&Gray16Image> {
+        match self {
+            Frame::Gray(img) => Some(img),
+            Frame::Rgb(_) => None,
+        }
+    }
+
+    pub fn blacken_rect(&mut self, x0: u32, y0: u32, x1: u32, y1: u32) {
+        match self {
+            Frame::Gray(img) => blacken_rect(img, x0, y0, x1, y1),
+            Frame::Rgb(img) => blacken_rect(img, x0, y0, x1, y1),
+        }
+    }
+
+    pub fn blacken_shape(&mut self, shape: &Shape) {
+        match self {
+            Frame::Gray(img) => blacken_shape(img, shape),
+            Frame::Rgb(img) => blacken_shape(img, shape),
+        }
+    }
+
+    /// Replace every pixel covered by `shape` according to `fill`.
+    pub fn fill_shape(&mut self, shape: &Shape, fill: RedactFill) {
+        match self {
+            Frame::Gray(img) => fill_shape(img, shape, fill),
+            Frame::Rgb(img) => fill_shape(img, shape, fill),
+        }
+    }
+
+    /// Zero the `mask` bits of every grayscale value; color frames are left alone.
+    pub fn clear_bits(&mut self, mask: u16) {
+        if let Frame::Gray(img) = self {
+            for p in img.pixels_mut() {
+                p[0] &= !mask;
+            }
+        }
+    }
+
+    /// Save through the `image` crate (format picked from the extension).
+    pub fn save(&self, path: &Path) -> image::ImageResult<()> {
+        match self {
+            Frame::Gray(img) => img.save(path),
+            Frame::Rgb(img) => img.save(path),
+        }
+    }
+
+    /// Encode through the `image` crate, in the format of `path`'s extension.
+    pub fn encode(&self, path: &Path) -> image::ImageResult<Vec<u8>> {
+        let format = image::ImageFormat::from_path(path)?;
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        match self {
+            Frame::Gray(img) => img.write_to(&mut bytes, format)?,
+            Frame::Rgb(img) => img.write_to(&mut bytes, format)?,
+        }
+        Ok(bytes.into_inner())
+    }
+
+    /// Copy of the pixels inside `r` (which must lie within the frame).
+    pub fn crop(&self, r: RedactRect) -> Frame {
+        let (w, h) = (r.x1 - r.x0, r.y1 - r.y0);
+        match self {
+            Frame::Gray(img) => Frame::Gray(imageops::crop_imm(img, r.x0, r.y0, w, h).to_image()),
+            Frame::Rgb(img) => Frame::Rgb(imageops::crop_imm(img, r.x0, r.y0, w, h).to_image()),
+        }
+    }
+
+    /// Paste a patch from [`Frame::crop`] back at (x, y). Mismatched kinds are ignored.
+    pub fn paste(&mut self, patch: &Frame, x: u32, y: u32) {
+        match (self, patch) {
+            (Frame::Gray(img), Frame::Gray(p)) => imageops::replace(img, p, x as i64, y as i64),
+            (Frame::Rgb(img), Frame::Rgb(p)) => imageops::replace(img, p, x as i64, y as i64),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Gzip-compressed DICOM files (`.dcm.gz`), as some archives store instances. They are
+//! decompressed while opened and compressed again when saved under a `.gz` name.
+
+use crate::BoxError;
+use dicom::object::{FileDicomObject, InMemDicomObject, OpenFileOptions, ReadPreamble};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Whether `path` names a gzipped file, by its extension.
+pub fn is_gz(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+}
+
+/// `path` without its `.gz` extension (`scan.dcm.gz` → `scan.dcm`), to tell what is
+/// inside; other paths as they are.
+pub fn inner(path: &Path) -> PathBuf {
+    if is_gz(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// A reader of the contents of `path`, decompressed if it is gzipped.
+pub fn reader(path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(if is_gz(path) {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    })
+}
+
+/// The contents of `path`, decompressed if it is gzipped.
+pub fn read(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// `options.open_file(path)`, decompressing `path` on the fly if it is gzipped. The
+/// 128-byte preamble is optional there.
+pub fn open_file(
+    options: OpenFileOptions,
+    path: &Path,
+) -> Result<FileDicomObject<InMemDicomObject>, BoxError> {
+    if !is_gz(path) {
+        return Ok(options.open_file(path)?);
+    }
+    let mut source = reader(path)?;
+    let mut head = Vec::with_capacity(132);
+    (&mut source).take(132).read_to_end(&mut head)?;
+    if head.get(128..) == Some(b"DICM".as_slice()) {
+        head.drain(..128);
+    }
+    Ok(options
+        .read_preamble(ReadPreamble::Never)
+        .from_reader(Cursor::new(head).chain(source))?)
+}
+
+/// Write `obj` as a DICOM file to `path`, gzipped if `path` ends in `.gz`.
+pub fn write_file(obj: &FileDicomObject<InMemDicomObject>, path: &Path) -> Result<(), BoxError> {
+    if !is_gz(path) {
+        return Ok(obj.write_to_file(path)?);
+    }
+    let mut out = GzEncoder::new(BufWriter::new(File::create(path)?), Compression::default());
+    obj.write_all(&mut out)?;
+    out.finish()?.flush()?;
+    Ok(())
+}
@@ -0,0 +1,308 @@
+//! Undo/redo for pixel edits.
+//!
+//! Each edit stores the before/after pixels of the rectangle it touched on every
+//! frame it touched, so undo and redo are just patch pastes regardless of what the
+//! edit actually did. Edits made with [`History::record_region`] also remember the
+//! region, so the regions currently burned in can be listed.
+
+use crate::audit::AppliedRegion;
+use crate::{Frame, RedactFill, RedactRect, Region};
+
+#[derive(Debug, Clone)]
+struct Patch {
+    frame: usize,
+    x: u32,
+    y: u32,
+    before: Frame,
+    after: Frame,
+}
+
+/// One user action; may span several frames.
+#[derive(Debug, Clone, Default)]
+struct Edit {
+    patches: Vec<Patch>,
+    regions: Vec<AppliedRegion>,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+fn clamp_rect(img: &Frame, r: RedactRect) -> Option<RedactRect> {
+    let (w, h) = img.dimensions();
+    let x1 = r.x1.min(w);
+    let y1 = r.y1.min(h);
+    (r.x0 < x1 && r.y0 < y1).then_some(RedactRect {
+        x0: r.x0,
+        y0: r.y0,
+        x1,
+        y1,
+    })
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `op` on each of the `targets` frames, recording the pixels inside `area` so
+    /// the whole thing can be undone as one step. `op` must not modify anything
+    /// outside `area`. Clears the redo stack.
+    pub fn record(
+        &mut self,
+        frames: &mut [Frame],
+        targets: impl IntoIterator<Item = usize>,
+        area: RedactRect,
+        mut op: impl FnMut(&mut Frame),
+    ) {
+        let mut edit = Edit::default();
+        for frame in targets {
+            let Some(img) = frames.get_mut(frame) else {
+                continue;
+            };
+            let Some(r) = clamp_rect(img, area) else {
+                continue;
+            };
+            let before = img.crop(r);
+            op(img);
+            let after = img.crop(r);
+            edit.patches.push(Patch {
+                frame,
+                x: r.x0,
+                y: r.y0,
+                before,
+                after,
+            });
+        }
+        if !edit.patches.is_empty() {
+            self.undo.push(edit);
+            self.redo.clear();
+        }
+    }
+
+    /// Fill `region` with `pixels` as one undoable step, remembering it as filled with
+    /// `fill` (they differ where black is stored as a constant, see
+    /// [`crate::Redactor::pixel_fill`]). Returns false if the region covered no pixels,
+    /// in which case nothing was recorded.
+    pub fn record_region(
+        &mut self,
+        frames: &mut [Frame],
+        region: &Region,
+        fill: RedactFill,
+        pixels: RedactFill,
+    ) -> bool {
+        let Some(area) = region.shape.bounds() else {
+            return false;
+        };
+        let depth = self.undo.len();
+        let targets = region.target_frames(frames.len());
+        self.record(frames, targets, area, |frame| {
+            frame.fill_shape(&region.shape, pixels)
+        });
+        if self.undo.len() == depth {
+            return false;
+        }
+        let Some(edit) = self.undo.last_mut() else {
+            return false;
+        };
+        edit.regions.push(AppliedRegion {
+            region: region.clone(),
+            fill,
+        });
+        true
+    }
+
+    /// Number of [`History::applied_regions`].
+    pub fn applied_count(&self) -> usize {
+        self.undo.iter().map(|e| e.regions.len()).sum()
+    }
+
+    /// The regions of every edit that hasn't been undone, oldest first.
+    pub fn applied_regions(&self) -> Vec<AppliedRegion> {
+        self.undo
+            .iter()
+            .flat_map(|e| e.regions.iter().cloned())
+            .collect()
+    }
+
+    /// Frame `index` as it was before every edit that hasn't been undone, given the
+    /// frame as it is now.
+    pub fn original(&self, index: usize, current: &Frame) -> Frame {
+        let mut frame = current.clone();
+        let patches = self.undo.iter().rev().flat_map(|e| e.patches.iter().rev());
+        for p in patches.filter(|p| p.frame == index) {
+            frame.paste(&p.before, p.x, p.y);
+        }
+        frame
+    }
+
+    /// Revert the most recent edit. Returns false if there was nothing to undo.
+    pub fn undo(&mut self, frames: &mut [Frame]) -> bool {
+        let Some(edit) = self.undo.pop() else {
+            return false;
+        };
+        for p in edit.patches.iter().rev() {
+            frames[p.frame].paste(&p.before, p.x, p.y);
+        }
+        self.redo.push(edit);
+        true
+    }
+
+    /// Re-apply the most recently undone edit. Returns false if there was nothing to redo.
+    pub fn redo(&mut self, frames: &mut [Frame]) -> bool {
+        let Some(edit) = self.redo.pop() else {
+            return false;
+        };
+        for p in &edit.patches {
+            frames[p.frame].paste(&p.after, p.x, p.y);
+        }
+        self.undo.push(edit);
+        true
+    }
+
+    /// Merge the last `n` recorded edits into a single undo step.
+    pub fn merge_last(&mut self, n: usize) {
+        let n = n.min(self.undo.len());
+        if n < 2 {
+            return;
+        }
+        let mut merged = Edit::default();
+        for edit in self.undo.split_off(self.undo.len() - n) {
+            merged.patches.extend(edit.patches);
+            merged.regions.extend(edit.regions);
+        }
+        self.undo.push(merged);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gray16Image, Shape};
+    use image::{ImageBuffer, Luma};
+
+    fn frames(count: usize) -> Vec<Frame> {
+        let img: Gray16Image = ImageBuffer::from_pixel(4, 3, Luma([100]));
+        vec![Frame::Gray(img); count]
+    }
+
+    fn pixel(frame: &Frame, x: u32, y: u32) -> u16 {
+        frame.as_gray().unwrap().get_pixel(x, y)[0]
+    }
+
+    fn rect(x0: u32, y0: u32, x1: u32, y1: u32) -> RedactRect {
+        RedactRect { x0, y0, x1, y1 }
+    }
+
+    #[test]
+    fn empty_stacks_have_nothing_to_undo_or_redo() {
+        let mut history = History::new();
+        let mut frames = frames(1);
+        assert!(!history.can_undo() && !history.can_redo());
+        assert!(!history.undo(&mut frames));
+        assert!(!history.redo(&mut frames));
+    }
+
+    #[test]
+    fn undo_and_redo_swap_the_patch() {
+        let mut history = History::new();
+        let mut frames = frames(1);
+        let region = Region::all_frames(Shape::Rect(rect(1, 1, 3, 2)));
+        assert!(history.record_region(&mut frames, &region, RedactFill::Black, RedactFill::Black));
+        assert_eq!(pixel(&frames[0], 1, 1), 0);
+        assert_eq!(history.applied_count(), 1);
+        assert_eq!(pixel(&history.original(0, &frames[0]), 1, 1), 100);
+
+        assert!(history.undo(&mut frames));
+        assert_eq!(pixel(&frames[0], 1, 1), 100);
+        assert_eq!(history.applied_count(), 0);
+        assert!(!history.undo(&mut frames));
+
+        assert!(history.redo(&mut frames));
+        assert_eq!(pixel(&frames[0], 2, 1), 0);
+        assert_eq!(pixel(&frames[0], 3, 1), 100);
+        assert!(!history.redo(&mut frames));
+    }
+
+    #[test]
+    fn a_new_edit_clears_the_redo_stack() {
+        let mut history = History::new();
+        let mut frames = frames(1);
+        history.record(&mut frames, [0], rect(0, 0, 1, 1), |f| {
+            f.blacken_rect(0, 0, 1, 1)
+        });
+        history.undo(&mut frames);
+        assert!(history.can_redo());
+        history.record(&mut frames, [0], rect(1, 0, 2, 1), |f| {
+            f.blacken_rect(1, 0, 2, 1)
+        });
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn edits_are_clipped_to_the_frames_they_reach() {
+        let mut history = History::new();
+        let mut frames = frames(2);
+        // Outside the image, and on frames that don't exist: nothing to record
+        let outside = Region::all_frames(Shape::Rect(rect(10, 10, 20, 20)));
+        assert!(!history.record_region(
+            &mut frames,
+            &outside,
+            RedactFill::Black,
+            RedactFill::Black
+        ));
+        let missing = Region {
+            frame: Some(5),
+            ..Region::all_frames(Shape::Rect(rect(0, 0, 2, 2)))
+        };
+        assert!(!history.record_region(
+            &mut frames,
+            &missing,
+            RedactFill::Black,
+            RedactFill::Black
+        ));
+        assert!(!history.can_undo());
+
+        // Reaching past the edge: only the pixels inside are patched
+        let edge = Region {
+            frame: Some(1),
+            ..Region::all_frames(Shape::Rect(rect(2, 0, 10, 10)))
+        };
+        assert!(history.record_region(&mut frames, &edge, RedactFill::Black, RedactFill::Black));
+        assert_eq!(pixel(&frames[0], 3, 2), 100);
+        assert_eq!(pixel(&frames[1], 3, 2), 0);
+        history.undo(&mut frames);
+        assert_eq!(pixel(&frames[1], 3, 2), 100);
+    }
+
+    #[test]
+    fn merged_edits_undo_together() {
+        let mut history = History::new();
+        let mut frames = frames(1);
+        for x in 0..3 {
+            let region = Region::all_frames(Shape::Rect(rect(x, 0, x + 1, 1)));
+            history.record_region(&mut frames, &region, RedactFill::Black, RedactFill::Black);
+        }
+        history.merge_last(2);
+        history.merge_last(10);
+        assert_eq!(history.applied_count(), 3);
+        assert!(history.undo(&mut frames));
+        assert!(!history.can_undo());
+        assert!((0..3).all(|x| pixel(&frames[0], x, 0) == 100));
+    }
+}
@@ -0,0 +1,220 @@
+//! Translations of the GUI's text. English and German are built in; more languages,
+//! or changes to these, go in `locales/<code>.toml` in the config directory:
+//!
+//! ```toml
+//! language = "Español"
+//!
+//! [toolbar]
+//! open_image = "Abrir imagen…"
+//! save = "Guardar"
+//! ```
+//!
+//! Keys are those of the built-in `locales/en.toml`, by section. `{name}` marks a
+//! value filled in by the program. Text missing from a translation is shown in
+//! English.
+
+use dcm_redact::{DCMRedactErrors, config_dir};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, RwLock};
+
+const BUILT_IN: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.toml")),
+    ("de", include_str!("../locales/de.toml")),
+];
+
+/// One language's text by key.
+#[derive(Debug, Clone, Default)]
+struct Catalog {
+    name: String,
+    strings: HashMap<String, String>,
+}
+
+static ENGLISH: LazyLock<Catalog> =
+    LazyLock::new(|| parse(BUILT_IN[0].1).expect("locales/en.toml is valid"));
+
+static CURRENT: LazyLock<RwLock<Catalog>> = LazyLock::new(|| RwLock::new(Catalog::default()));
+
+/// The `locales` folder in the config directory.
+pub fn default_locales_dir() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("locales"))
+}
+
+/// Flatten `table` into `strings`, section names joined to keys with dots.
+fn flatten(prefix: &str, table: toml::Table, strings: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            toml::Value::String(text) => {
+                strings.insert(key, text);
+            }
+            toml::Value::Table(table) => flatten(&key, table, strings),
+            _ => {}
+        }
+    }
+}
+
+fn parse(text: &str) -> Result<Catalog, toml::de::Error> {
+    let mut table: toml::Table = text.parse()?;
+    let name = match table.remove("language") {
+        Some(toml::Value::String(name)) => name,
+        _ => String::new(),
+    };
+    let mut strings = HashMap::new();
+    flatten("", table, &mut strings);
+    Ok(Catalog { name, strings })
+}
+
+fn load_file(path: &Path) -> Result<Catalog, DCMRedactErrors> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        DCMRedactErrors::io(format!("Failed to read translation {}", path.display()), e)
+    })?;
+    parse(&text)
+        .map_err(|e| DCMRedactErrors::decode(format!("Invalid translation {}", path.display()), e))
+}
+
+/// The languages to choose from: code and name, built-in ones first, then those in
+/// `dir`.
+pub fn languages(dir: Option<&Path>) -> Vec<(String, String)> {
+    let mut found: BTreeMap<String, String> = BUILT_IN
+        .iter()
+        .map(|&(code, text)| {
+            let name = parse(text).map(|c| c.name).unwrap_or_default();
+            (code.to_string(), name)
+        })
+        .collect();
+    let entries = dir.and_then(|d| fs::read_dir(d).ok()).into_iter().flatten();
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.extension().is_some_and(|e| e == "toml")
+            && let Some(code) = path.file_stem().and_then(|s| s.to_str())
+            && !found.contains_key(code)
+        {
+            let name = load_file(&path).map(|c| c.name).unwrap_or_default();
+            found.insert(code.to_string(), name);
+        }
+    }
+    let (built_in, added): (Vec<_>, Vec<_>) = found
+        .into_iter()
+        .map(|(code, name)| {
+            let name = if name.is_empty() { code.clone() } else { name };
+            (code, name)
+        })
+        .partition(|(code, _)| BUILT_IN.iter().any(|&(c, _)| c == code));
+    built_in.into_iter().chain(added).collect()
+}
+
+/// The language of the desktop, from `LC_ALL`, `LC_MESSAGES` or `LANG`: `de` for
+/// `de_DE.UTF-8`.
+pub fn system_language() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|v| !v.is_empty())
+        .and_then(|v| {
+            let code: String = v.chars().take_while(char::is_ascii_alphabetic).collect();
+            (!code.is_empty() && code != "C").then(|| code.to_ascii_lowercase())
+        })
+        .unwrap_or_else(|| "en".to_string())
+}
+
+/// Show the GUI in `code`: the built-in translation, with the strings of
+/// `locales/<code>.toml` in `dir` on top. An unknown language is English.
+pub fn set_language(dir: Option<&Path>, code: &str) -> Result<(), DCMRedactErrors> {
+    let mut catalog = BUILT_IN
+        .iter()
+        .find(|&&(c, _)| c == code)
+        .and_then(|&(_, text)| parse(text).ok())
+        .unwrap_or_default();
+    let file = dir.map(|d| d.join(format!("{code}.toml")));
+    let result = match file {
+        Some(path) if path.exists() => load_file(&path).map(|user| {
+            if !user.name.is_empty() {
+                catalog.name = user.name;
+            }
+            catalog.strings.extend(user.strings);
+        }),
+        _ => Ok(()),
+    };
+    *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = catalog;
+    result
+}
+
+/// The text of `key` in the chosen language, else in English, else the key itself.
+/// Use [`tr!`] rather than calling this directly.
+pub fn text(key: &str) -> String {
+    let current = CURRENT.read().unwrap_or_else(|e| e.into_inner());
+    current
+        .strings
+        .get(key)
+        .or_else(|| ENGLISH.strings.get(key))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// [`text`] with each `{name}` replaced by its value.
+pub fn text_with(key: &str, values: &[(&str, String)]) -> String {
+    values.iter().fold(text(key), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// Translated text: `tr!("toolbar.save")`, or with values filled in,
+/// `tr!("toolbar.apply", count = regions.len())`.
+macro_rules! tr {
+    ($key:literal) => {
+        $crate::i18n::text($key)
+    };
+    ($key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::text_with($key, &[$((stringify!($name), ($value).to_string())),+])
+    };
+}
+
+pub(crate) use tr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(text: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = text
+            .split('{')
+            .skip(1)
+            .filter_map(|s| s.split_once('}').map(|(name, _)| name))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn built_in_translations_cover_every_english_key() {
+        for &(code, text) in &BUILT_IN[1..] {
+            let catalog = parse(text).unwrap();
+            assert!(!catalog.name.is_empty(), "{code}");
+            for (key, english) in &ENGLISH.strings {
+                let translated = catalog
+                    .strings
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{code} lacks {key}"));
+                assert_eq!(
+                    placeholders(translated),
+                    placeholders(english),
+                    "{code} {key}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn values_fill_their_placeholders() {
+        assert_eq!(
+            text_with("toolbar.apply", &[("count", "3".to_string())]),
+            "Apply (3)"
+        );
+        assert_eq!(text("no.such.key"), "no.such.key");
+    }
+}
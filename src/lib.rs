@@ -0,0 +1,1957 @@
+//! Core redaction pipeline for dcm-redact.
+//!
+//! Nothing in here depends on the GUI, so the same load → redact → save flow can be
+//! driven from other Rust code (services, scripts, batch jobs) via [`Redactor`].
+
+use dicom::core::smallvec::SmallVec;
+use dicom::encoding::TransferSyntaxIndex;
+use dicom::pixeldata::{PixelDecoder, Transcode};
+use dicom::transfer_syntax::{TransferSyntaxRegistry, entries};
+use dicom::{
+    self,
+    core::{DataElement, PrimitiveValue, VR},
+    dictionary_std::tags,
+    object::{FileDicomObject, FileMetaTableBuilder, InMemDicomObject},
+};
+use image::{ImageBuffer, Luma};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub mod anonymize;
+pub mod archive;
+pub mod audit;
+pub mod batch;
+pub mod borders;
+pub mod charset;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cloud;
+pub mod crop;
+pub mod deface;
+pub mod dicomdir;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dicomweb;
+pub mod encapsulated;
+pub mod export;
+pub mod fileio;
+pub mod fill;
+pub mod folder;
+pub mod frame;
+pub mod gz;
+pub mod history;
+pub mod metadata;
+pub mod multipage;
+pub mod naming;
+pub mod ocr;
+pub mod orientation;
+pub mod overlays;
+pub mod pacs;
+pub mod phi;
+pub mod plugin;
+pub mod presentation;
+pub mod presets;
+pub mod profile;
+pub mod project;
+pub mod pseudonym;
+pub mod query;
+pub mod report;
+pub mod rt;
+pub mod sc;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod seg;
+pub mod shape;
+pub mod sidecar;
+pub mod sr;
+pub mod stamp;
+pub mod suggest;
+pub mod template;
+pub mod uids;
+pub mod ultrasound;
+pub mod video;
+pub mod voi;
+pub mod volume;
+pub mod wsi;
+
+use anonymize::Anonymizer;
+use audit::{AppliedRegion, AuditEntry, AuditLog};
+pub use fill::RedactFill;
+pub use frame::{Frame, Rgb8Image, blacken_rect, blacken_shape};
+pub use profile::Profile;
+use pseudonym::PseudonymTable;
+use report::DryRunReport;
+pub use shape::{Region, Shape, ShapeKind};
+use sidecar::{RegionSidecar, sidecar_path};
+use uids::{UidMappingLog, UidRemapper};
+
+pub type Gray16Image = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+/// Any error type, as the source of a decode or encode failure.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DCMRedactErrors {
+    /// A file or directory couldn't be read or written.
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A file was read but couldn't be parsed or decoded (DICOM, pixel data, images,
+    /// TOML/JSON, encrypted tables).
+    #[error("{context}: {source}")]
+    Decode {
+        context: String,
+        #[source]
+        source: BoxError,
+    },
+    /// Output couldn't be encoded or written (DICOM, pixel data, images).
+    #[error("{context}: {source}")]
+    Encode {
+        context: String,
+        #[source]
+        source: BoxError,
+    },
+    /// A remote DICOM node couldn't be reached, or refused or failed a request.
+    #[error("{context}: {source}")]
+    Network {
+        context: String,
+        #[source]
+        source: BoxError,
+    },
+    /// Input that was read fine but is unsupported, inconsistent or invalid.
+    #[error("{0}")]
+    ValueError(String),
+}
+
+impl DCMRedactErrors {
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        DCMRedactErrors::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub fn decode(context: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        DCMRedactErrors::Decode {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+
+    pub fn encode(context: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        DCMRedactErrors::Encode {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+
+    pub fn network(context: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        DCMRedactErrors::Network {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+}
+
+/// Transfer syntaxes offered for output besides "original" and "uncompressed".
+/// Whether one can actually be written depends on the encoders built into dicom-rs.
+pub const COMMON_TRANSFER_SYNTAXES: &[&str] = &[
+    "1.2.840.10008.1.2.1",    // Explicit VR Little Endian
+    "1.2.840.10008.1.2",      // Implicit VR Little Endian
+    "1.2.840.10008.1.2.5",    // RLE Lossless
+    "1.2.840.10008.1.2.4.70", // JPEG Lossless, First-Order Prediction
+    "1.2.840.10008.1.2.4.80", // JPEG-LS Lossless
+    "1.2.840.10008.1.2.4.90", // JPEG 2000 Lossless Only
+    "1.2.840.10008.1.2.4.50", // JPEG Baseline (lossy)
+];
+
+/// Transfer syntax DICOM output is written with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum OutputTransferSyntax {
+    /// Keep native sources as they were; write compressed sources as Explicit VR
+    /// Little Endian.
+    #[default]
+    Uncompressed,
+    /// Re-encode the redacted pixel data into the source transfer syntax.
+    Original,
+    /// Re-encode into the transfer syntax with this UID.
+    Uid(String),
+}
+
+impl OutputTransferSyntax {
+    pub fn label(&self) -> String {
+        match self {
+            OutputTransferSyntax::Uncompressed => "Uncompressed".to_string(),
+            OutputTransferSyntax::Original => "Original".to_string(),
+            OutputTransferSyntax::Uid(uid) => TransferSyntaxRegistry
+                .get(uid)
+                .map_or_else(|| uid.clone(), |ts| ts.name().to_string()),
+        }
+    }
+}
+
+impl FromStr for OutputTransferSyntax {
+    type Err = DCMRedactErrors;
+
+    /// "original", "uncompressed", or a transfer syntax UID.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "original" => Ok(OutputTransferSyntax::Original),
+            "uncompressed" => Ok(OutputTransferSyntax::Uncompressed),
+            uid if TransferSyntaxRegistry.get(uid).is_some() => {
+                Ok(OutputTransferSyntax::Uid(uid.to_string()))
+            }
+            other => Err(DCMRedactErrors::ValueError(format!(
+                "Unknown transfer syntax {other:?}, expected \"original\", \"uncompressed\" or a transfer syntax UID"
+            ))),
+        }
+    }
+}
+
+/// Color photometric interpretations we decode to RGB.
+const COLOR_PHOTOMETRICS: &[&str] = &[
+    "RGB",
+    "YBR_FULL",
+    "YBR_FULL_422",
+    "YBR_PARTIAL_422",
+    "YBR_PARTIAL_420", // video transfer syntaxes only, see `video`
+    "YBR_ICT",
+    "YBR_RCT",
+    "PALETTE COLOR",
+];
+
+/// Pixel module attributes of a loaded DICOM object, as they were in the source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelInfo {
+    pub photometric_interpretation: String,
+    pub samples_per_pixel: u16,
+    pub bits_allocated: u16,
+    pub bits_stored: u16,
+    /// PixelRepresentation 1: stored values are two's complement.
+    pub signed: bool,
+    pub transfer_syntax: String,
+}
+
+impl PixelInfo {
+    pub fn is_color(&self) -> bool {
+        COLOR_PHOTOMETRICS.contains(&self.photometric_interpretation.as_str())
+    }
+
+    /// What was added to signed stored values to hold them in [`Frame::Gray`].
+    pub fn signed_offset(&self) -> u16 {
+        signed_offset(self.signed && !self.is_color(), self.bits_stored)
+    }
+}
+
+/// Signed stored values are kept in frames offset by 2^(BitsStored-1), so that
+/// 0..2^BitsStored runs from the lowest value to the highest like unsigned data and
+/// fills, means and ranges work unchanged; this is that offset (0 for unsigned data).
+fn signed_offset(signed: bool, bits_stored: u16) -> u16 {
+    if signed {
+        1 << (bits_stored.clamp(1, 16) - 1)
+    } else {
+        0
+    }
+}
+
+/// A two's complement `bits_stored`-bit sample (in the low bits of `raw`) as its
+/// offset frame value.
+fn from_signed(raw: u16, bits_stored: u16) -> u16 {
+    let unused = 16 - bits_stored.clamp(1, 16);
+    let value = ((raw << unused) as i16) >> unused;
+    (i32::from(value) + i32::from(signed_offset(true, bits_stored))) as u16
+}
+
+/// An offset frame value back as a two's complement sample, sign-extended to 16 bits.
+fn to_signed(value: u16, bits_stored: u16) -> u16 {
+    (i32::from(value) - i32::from(signed_offset(true, bits_stored))) as i16 as u16
+}
+
+/// PixelRepresentation and BitsStored of a grayscale object.
+fn signed_pixels(obj: &InMemDicomObject, bits_allocated: u16) -> (bool, u16) {
+    let int = |tag| obj.element(tag).ok().and_then(|e| e.to_int::<u16>().ok());
+    (
+        int(tags::PIXEL_REPRESENTATION) == Some(1),
+        int(tags::BITS_STORED).unwrap_or(bits_allocated),
+    )
+}
+
+/// Write `frames` (all the same size and kind) as the object's pixel data and save it.
+/// See [`encode_pixel_data`].
+pub fn write_dynamic_image_to_dicom(
+    file_obj: &mut FileDicomObject<InMemDicomObject>,
+    frames: &[Frame],
+    save_path: &Path,
+    transfer_syntax: &OutputTransferSyntax,
+) -> Result<(), DCMRedactErrors> {
+    encode_pixel_data(file_obj, frames, transfer_syntax)?;
+    write_dicom_file(file_obj, save_path)
+}
+
+/// Write `frames` (all the same size and kind) as the object's pixel data.
+///
+/// Grayscale frames hold stored values, so only PixelData and NumberOfFrames are
+/// replaced (8-bit sources are written back as 8-bit OB, signed values as two's
+/// complement again); the rest of the pixel module (BitsAllocated/Stored, HighBit,
+/// PixelRepresentation, PhotometricInterpretation, RescaleSlope/Intercept, ...) is
+/// written exactly as loaded. Color frames were converted to RGB on load, so the pixel
+/// module is rewritten to describe interleaved 8-bit RGB and any palette is dropped.
+/// Encapsulated sources are switched to Explicit VR Little Endian since the new pixel
+/// data is native, unless `transfer_syntax` asks for it to be re-encoded.
+pub fn encode_pixel_data(
+    file_obj: &mut FileDicomObject<InMemDicomObject>,
+    frames: &[Frame],
+    transfer_syntax: &OutputTransferSyntax,
+) -> Result<(), DCMRedactErrors> {
+    let source_uid = file_obj
+        .meta()
+        .transfer_syntax()
+        .trim_end_matches('\0')
+        .to_string();
+    let target_uid = match transfer_syntax {
+        OutputTransferSyntax::Uncompressed => TransferSyntaxRegistry
+            .get(&source_uid)
+            .filter(|ts| !ts.is_encapsulated_pixel_data())
+            .map_or(entries::EXPLICIT_VR_LITTLE_ENDIAN.uid(), |ts| ts.uid()),
+        OutputTransferSyntax::Original => source_uid.as_str(),
+        OutputTransferSyntax::Uid(uid) => uid.as_str(),
+    };
+    let target = TransferSyntaxRegistry.get(target_uid).ok_or_else(|| {
+        DCMRedactErrors::ValueError(format!("Unsupported transfer syntax {target_uid}"))
+    })?;
+
+    // dicom-rs has no video encoders; those go through ffmpeg
+    if let Some(codec) = video::codec(target_uid) {
+        video::put_video_pixel_data(file_obj, frames, codec)?;
+        file_obj.meta_mut().set_transfer_syntax(target);
+        return Ok(());
+    }
+
+    let (vr, pixel_data) = if frames.first().is_some_and(Frame::is_color) {
+        set_rgb_pixel_module(file_obj);
+        let samples = frames.iter().flat_map(|f| match f {
+            Frame::Rgb(img) => img.as_raw().as_slice(),
+            Frame::Gray(_) => &[],
+        });
+        (VR::OB, PrimitiveValue::U8(samples.copied().collect()))
+    } else {
+        let bits_allocated: u16 = file_obj
+            .element(tags::BITS_ALLOCATED)
+            .ok()
+            .and_then(|e| e.to_int().ok())
+            .unwrap_or(16);
+        let (signed, bits_stored) = signed_pixels(file_obj, bits_allocated);
+        set_gray_pixel_module(file_obj, bits_allocated, bits_stored);
+        let samples = frames
+            .iter()
+            .filter_map(Frame::as_gray)
+            .flat_map(|f| f.iter().copied())
+            .map(|v| if signed { to_signed(v, bits_stored) } else { v });
+        if bits_allocated == 8 {
+            let narrow = |v: u16| if signed { v as u8 } else { v.min(255) as u8 };
+            (VR::OB, PrimitiveValue::U8(samples.map(narrow).collect()))
+        } else {
+            (VR::OW, PrimitiveValue::U16(SmallVec::from_iter(samples)))
+        }
+    };
+
+    // NumberOfFrames is only mandatory for multi-frame IODs; keep it if it was there
+    if frames.len() > 1 || file_obj.element(tags::NUMBER_OF_FRAMES).is_ok() {
+        file_obj.put(DataElement::new(
+            tags::NUMBER_OF_FRAMES,
+            VR::IS,
+            PrimitiveValue::from(frames.len().to_string()),
+        ));
+    }
+    file_obj.put(DataElement::new(tags::PIXEL_DATA, vr, pixel_data));
+
+    // The pixel data put above is native: declare it as such, then encode if needed.
+    // Both paths keep the file meta group (transfer syntax, group length) in sync.
+    if target.is_encapsulated_pixel_data() {
+        file_obj
+            .meta_mut()
+            .set_transfer_syntax(&entries::EXPLICIT_VR_LITTLE_ENDIAN);
+        file_obj.transcode(target).map_err(|e| {
+            DCMRedactErrors::encode(
+                format!("Failed to encode pixel data as {}", target.name()),
+                e,
+            )
+        })?;
+    } else {
+        file_obj.meta_mut().set_transfer_syntax(target);
+    }
+    Ok(())
+}
+
+/// `file_obj` encoded as a DICOM file, preamble included.
+fn dicom_file_bytes(
+    file_obj: &FileDicomObject<InMemDicomObject>,
+) -> Result<Vec<u8>, DCMRedactErrors> {
+    let encoded = charset::encode_text(file_obj);
+    let file_obj = encoded.as_ref().unwrap_or(file_obj);
+    let mut bytes = Vec::new();
+    file_obj
+        .write_all(&mut bytes)
+        .map_err(|e| DCMRedactErrors::encode("Failed to encode DICOM file", e))?;
+    Ok(bytes)
+}
+
+fn write_dicom_file(
+    file_obj: &FileDicomObject<InMemDicomObject>,
+    save_path: &Path,
+) -> Result<(), DCMRedactErrors> {
+    let encoded = charset::encode_text(file_obj);
+    let file_obj = encoded.as_ref().unwrap_or(file_obj);
+    gz::write_file(file_obj, save_path).map_err(|e| {
+        DCMRedactErrors::encode(
+            format!("Failed to write DICOM file {}", save_path.display()),
+            e,
+        )
+    })
+}
+
+/// Make the grayscale pixel module describe the native samples written: packed 12-bit
+/// allocation becomes 16, and BitsStored/HighBit keep the original precision (values
+/// are never rescaled). Where fewer bits are stored than allocated, that is recorded
+/// in DerivationDescription.
+fn set_gray_pixel_module(
+    file_obj: &mut FileDicomObject<InMemDicomObject>,
+    bits_allocated: u16,
+    bits_stored: u16,
+) {
+    let allocated = if bits_allocated == 8 { 8 } else { 16 };
+    let stored = bits_stored.clamp(1, allocated);
+    for (tag, value) in [
+        (tags::BITS_ALLOCATED, allocated),
+        (tags::BITS_STORED, stored),
+        (tags::HIGH_BIT, stored - 1),
+    ] {
+        file_obj.put(DataElement::new(tag, VR::US, PrimitiveValue::from(value)));
+    }
+    if stored < allocated {
+        anonymize::append_derivation_description(
+            file_obj,
+            &format!(
+                "Pixel data rewritten with original BitsStored {stored} and HighBit {}, not rescaled",
+                stored - 1
+            ),
+        );
+    }
+}
+
+/// Describe interleaved 8-bit RGB pixel data, dropping palette color lookup tables.
+pub(crate) fn set_rgb_pixel_module(file_obj: &mut FileDicomObject<InMemDicomObject>) {
+    for (tag, value) in [
+        (tags::SAMPLES_PER_PIXEL, 3u16),
+        (tags::PLANAR_CONFIGURATION, 0),
+        (tags::BITS_ALLOCATED, 8),
+        (tags::BITS_STORED, 8),
+        (tags::HIGH_BIT, 7),
+        (tags::PIXEL_REPRESENTATION, 0),
+    ] {
+        file_obj.put(DataElement::new(tag, VR::US, PrimitiveValue::from(value)));
+    }
+    file_obj.put(DataElement::new(
+        tags::PHOTOMETRIC_INTERPRETATION,
+        VR::CS,
+        PrimitiveValue::from("RGB"),
+    ));
+    for tag in [
+        tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+        tags::GREEN_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+        tags::BLUE_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+        tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+        tags::GREEN_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+        tags::BLUE_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+        dicom::core::Tag(0x0028, 0x1199), // PaletteColorLookupTableUID
+        dicom::core::Tag(0x0028, 0x1221), // SegmentedRedPaletteColorLookupTableData
+        dicom::core::Tag(0x0028, 0x1222), // SegmentedGreenPaletteColorLookupTableData
+        dicom::core::Tag(0x0028, 0x1223), // SegmentedBluePaletteColorLookupTableData
+    ] {
+        file_obj.remove_element(tag);
+    }
+}
+
+/// A rectangle in full-res pixel coordinates; `x1`/`y1` are exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactRect {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl FromStr for RedactRect {
+    type Err = String;
+
+    /// Parse `x0,y0,x1,y1`, e.g. `0,0,512,64`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|p| p.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid rect {s:?}: {e}"))?;
+        let [x0, y0, x1, y1] = parts[..] else {
+            return Err(format!("invalid rect {s:?}: expected x0,y0,x1,y1"));
+        };
+        if x1 <= x0 || y1 <= y0 {
+            return Err(format!(
+                "invalid rect {s:?}: x1/y1 must be greater than x0/y0"
+            ));
+        }
+        Ok(Self { x0, y0, x1, y1 })
+    }
+}
+
+/// A parsed DICOM file plus its decoded pixel data, as returned by [`load_dcm`].
+pub type LoadedDicom = (FileDicomObject<InMemDicomObject>, PixelInfo, Vec<Frame>);
+
+/// Open a DICOM file, validate its pixel module and decode every frame to full res.
+///
+/// Monochrome frames hold the *stored* pixel values: no modality or VOI LUT is
+/// applied, so they can be written back without changing their meaning. Color frames
+/// (RGB, YBR_*, PALETTE COLOR) are converted to 8-bit RGB.
+///
+/// Returns the parsed object (kept around so it can be written back out), its pixel
+/// module attributes, and the decoded frames.
+pub fn load_dcm(path: &Path) -> Result<LoadedDicom, DCMRedactErrors> {
+    let dcm = open_dicom_object(path)?;
+    let info = pixel_info(&dcm)?;
+    let frames = decode_frames(&dcm, &info)?;
+    Ok((dcm, info, frames))
+}
+
+/// Validate the pixel module of `dcm` and return its attributes.
+fn pixel_info(dcm: &FileDicomObject<InMemDicomObject>) -> Result<PixelInfo, DCMRedactErrors> {
+    // Check Bits Allocated
+    let bits_allocated: u16 = dcm
+        .element(tags::BITS_ALLOCATED)
+        .map_err(|_| DCMRedactErrors::ValueError("Missing BITS_ALLOCATED tag".to_string()))?
+        .to_int()
+        .map_err(|_| DCMRedactErrors::ValueError("Invalid BITS_ALLOCATED value".to_string()))?;
+
+    // Photometric Interpretation
+    let photometric_interpretation = dcm
+        .element(tags::PHOTOMETRIC_INTERPRETATION)
+        .map_err(|_| {
+            DCMRedactErrors::ValueError("Missing PHOTOMETRIC_INTERPRETATION tag".to_string())
+        })?
+        .to_str()
+        .map_err(|_| {
+            DCMRedactErrors::ValueError(
+                "Invalid PHOTOMETRIC_INTERPRETATION value (not UTF-8)".to_string(),
+            )
+        })?
+        .into_owned();
+
+    let is_color = COLOR_PHOTOMETRICS.contains(&photometric_interpretation.as_str());
+    if !is_color
+        && photometric_interpretation != "MONOCHROME1"
+        && photometric_interpretation != "MONOCHROME2"
+    {
+        return Err(DCMRedactErrors::ValueError(format!(
+            "Mismatched PHOTOMETRIC_INTERPRETATION, expected MONOCHROME1, MONOCHROME2, RGB, YBR_* or PALETTE COLOR got {photometric_interpretation}"
+        )));
+    }
+
+    if is_color {
+        // Palette indices may be 16-bit; true color samples are always 8-bit here
+        if bits_allocated != 8 && photometric_interpretation != "PALETTE COLOR" {
+            return Err(DCMRedactErrors::ValueError(format!(
+                "Mismatched BITS_ALLOCATED, expected 8 for color images got {bits_allocated}"
+            )));
+        }
+    } else if ![8, 12, 16].contains(&bits_allocated) {
+        return Err(DCMRedactErrors::ValueError(format!(
+            "Mismatched BITS_ALLOCATED, expected 8, 12 or 16 got {bits_allocated}"
+        )));
+    }
+
+    let samples_per_pixel: u16 = dcm
+        .element(tags::SAMPLES_PER_PIXEL)
+        .ok()
+        .and_then(|e| e.to_int().ok())
+        .unwrap_or(if is_color { 3 } else { 1 });
+
+    let (signed, bits_stored) = signed_pixels(dcm, bits_allocated);
+    Ok(PixelInfo {
+        photometric_interpretation,
+        samples_per_pixel,
+        bits_allocated,
+        bits_stored,
+        signed,
+        transfer_syntax: dcm
+            .meta()
+            .transfer_syntax()
+            .trim_end_matches('\0')
+            .to_string(),
+    })
+}
+
+/// Decode every frame of `dcm`, whose pixel module is `info`, to full res.
+fn decode_frames(
+    dcm: &FileDicomObject<InMemDicomObject>,
+    info: &PixelInfo,
+) -> Result<Vec<Frame>, DCMRedactErrors> {
+    if video::codec(&info.transfer_syntax).is_some() {
+        return video::decode_frames(dcm);
+    }
+    let (is_color, bits_allocated) = (info.is_color(), info.bits_allocated);
+    let (signed, bits_stored) = (info.signed, info.bits_stored);
+
+    // Decode pixel data -> raw stored values (gray) or RGB (color), one per frame
+    let decoded = dcm
+        .decode_pixel_data()
+        .map_err(|e| DCMRedactErrors::decode("Failed to decode pixel data", e))?;
+    let (columns, rows) = (decoded.columns(), decoded.rows());
+    (0..decoded.number_of_frames().max(1))
+        .map(|i| {
+            if is_color {
+                // dicom-pixeldata handles YBR -> RGB and palette lookup
+                return decoded
+                    .to_dynamic_image(i)
+                    .map(|img| Frame::Rgb(img.to_rgb8()))
+                    .map_err(|e| {
+                        DCMRedactErrors::decode(format!("Failed to convert frame {i} to RGB"), e)
+                    });
+            }
+            let bytes = decoded
+                .frame_data(i)
+                .map_err(|e| DCMRedactErrors::decode(format!("Failed to read frame {i}"), e))?;
+            // 8-bit samples are widened, and narrowed again on write
+            let mut values: Vec<u16> = if bits_allocated == 8 {
+                bytes.iter().map(|&b| u16::from(b)).collect()
+            } else {
+                bytes
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect()
+            };
+            if signed {
+                for v in &mut values {
+                    *v = from_signed(*v, bits_stored);
+                }
+            }
+            Gray16Image::from_raw(columns, rows, values)
+                .map(Frame::Gray)
+                .ok_or_else(|| {
+                    DCMRedactErrors::ValueError(format!(
+                        "Frame {i} does not match {columns}x{rows} pixels"
+                    ))
+                })
+        })
+        .collect()
+}
+
+/// Open a DICOM file: a regular Part 10 file, one missing the 128-byte preamble
+/// (starting at `DICM`), or a bare dataset with no file meta group at all, as written
+/// by some older exports. Bare datasets are read as implicit then explicit VR little
+/// endian, and given a file meta group built from their SOP Class/Instance UIDs. Any
+/// of these can be gzipped (`.dcm.gz`, see [`gz`]).
+///
+/// Text in character sets dicom-rs doesn't decode is decoded here
+/// ([`charset::decode_text`]).
+pub fn open_dicom_object(
+    path: &Path,
+) -> Result<FileDicomObject<InMemDicomObject>, DCMRedactErrors> {
+    let mut obj = read_dicom_object(path)?;
+    charset::decode_text(&mut obj);
+    Ok(obj)
+}
+
+fn read_dicom_object(path: &Path) -> Result<FileDicomObject<InMemDicomObject>, DCMRedactErrors> {
+    let err = match gz::open_file(dicom::object::OpenFileOptions::new(), path) {
+        Ok(obj) => return Ok(obj),
+        Err(e) => e,
+    };
+    // gz::open_file already takes gzipped files with or without the preamble
+    if !gz::is_gz(path)
+        && let Ok(obj) = dicom::object::OpenFileOptions::new()
+            .read_preamble(dicom::object::ReadPreamble::Never)
+            .open_file(path)
+    {
+        return Ok(obj);
+    }
+    let bytes = gz::read(path)
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to read {}", path.display()), e))?;
+    read_bare_dataset(&bytes).ok_or_else(|| {
+        DCMRedactErrors::decode(format!("Failed to open DICOM file {}", path.display()), err)
+    })
+}
+
+/// [`read_dicom_object`] for a file already in memory; `path` only names it in errors.
+fn read_dicom_bytes(
+    bytes: &[u8],
+    path: &Path,
+) -> Result<FileDicomObject<InMemDicomObject>, DCMRedactErrors> {
+    let part10 = if bytes.get(128..132) == Some(b"DICM".as_slice()) {
+        Some(&bytes[128..])
+    } else if bytes.starts_with(b"DICM") {
+        Some(bytes)
+    } else {
+        None
+    };
+    let err: BoxError = match part10 {
+        Some(data) => match dicom::object::OpenFileOptions::new()
+            .read_preamble(dicom::object::ReadPreamble::Never)
+            .from_reader(data)
+        {
+            Ok(obj) => return Ok(obj),
+            Err(e) => e.into(),
+        },
+        None => "no DICM prefix".into(),
+    };
+    read_bare_dataset(bytes).ok_or_else(|| {
+        DCMRedactErrors::decode(format!("Failed to open DICOM file {}", path.display()), err)
+    })
+}
+
+/// A bare dataset with no file meta group, read as implicit then explicit VR little
+/// endian and given a file meta group.
+fn read_bare_dataset(bytes: &[u8]) -> Option<FileDicomObject<InMemDicomObject>> {
+    for uid in [
+        dicom::dictionary_std::uids::IMPLICIT_VR_LITTLE_ENDIAN,
+        dicom::dictionary_std::uids::EXPLICIT_VR_LITTLE_ENDIAN,
+    ] {
+        let Some(ts) = TransferSyntaxRegistry.get(uid) else {
+            continue;
+        };
+        // Random bytes can parse as a few bogus elements; insist on an image instance
+        let Ok(obj) = InMemDicomObject::read_dataset_with_ts(bytes, ts) else {
+            continue;
+        };
+        if obj.element(tags::SOP_CLASS_UID).is_err() || obj.element(tags::PIXEL_DATA).is_err() {
+            continue;
+        }
+        if let Ok(file) = obj.with_meta(FileMetaTableBuilder::new().transfer_syntax(uid)) {
+            return Some(file);
+        }
+    }
+    None
+}
+
+/// A decoded regular image as a frame: RGB if it has color, 16-bit gray otherwise.
+fn dynamic_frame(img: &image::DynamicImage) -> Frame {
+    if img.color().has_color() {
+        Frame::Rgb(img.to_rgb8())
+    } else {
+        Frame::Gray(img.to_luma16())
+    }
+}
+
+/// Per-user configuration directory (`~/.config/dcm-redact` on Linux), or `None` if
+/// the platform has none.
+pub fn config_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "dcm-redact").map(|d| d.config_dir().to_path_buf())
+}
+
+/// Whether `path` has a DICOM file extension (`.dcm`, `.dicom` or `.ima`), gzipped
+/// or not.
+pub fn is_dicom_path(path: &Path) -> bool {
+    matches!(
+        gz::inner(path).extension().and_then(|e| e.to_str()),
+        Some(ext) if ["dcm", "dicom", "ima"].iter().any(|d| ext.eq_ignore_ascii_case(d))
+    )
+}
+
+/// Whether `path` starts with a DICOM preamble: 128 bytes, then `DICM` (once
+/// decompressed, if it is gzipped).
+pub fn has_dicm_magic(path: &Path) -> bool {
+    let mut head = [0u8; 132];
+    gz::reader(path)
+        .and_then(|mut f| std::io::Read::read_exact(&mut f, &mut head))
+        .is_ok_and(|()| &head[128..] == b"DICM")
+}
+
+/// Whether `path` should be handled by the DICOM pipeline: by its content, whatever
+/// the extension, or by a DICOM extension.
+pub fn is_dicom_file(path: &Path) -> bool {
+    has_dicm_magic(path) || is_dicom_path(path)
+}
+
+/// A loaded image plus everything needed to write it back out after redaction.
+///
+/// ```no_run
+/// use dcm_redact::Redactor;
+/// use std::path::Path;
+///
+/// let mut r = Redactor::open(Path::new("in.dcm"))?;
+/// r.blacken_rect(0, 0, 512, 64); // every frame
+/// r.save(Path::new("out.dcm"))?;
+/// # Ok::<(), dcm_redact::DCMRedactErrors>(())
+/// ```
+pub struct Redactor {
+    // Full-res source frames (mutable for edits); never empty, all the same size
+    frames: Vec<Frame>,
+    dcm: Option<FileDicomObject<InMemDicomObject>>,
+    pixel_info: Option<PixelInfo>,
+    // Run the PS3.15 Basic Profile over the metadata when saving DICOM
+    deidentify: bool,
+    anonymizer: Anonymizer,
+    transfer_syntax: OutputTransferSyntax,
+    // Give the output a new SOP Instance UID even when metadata is kept
+    regenerate_uids: bool,
+    uid_log: Option<UidMappingLog>,
+    // How `apply_region` fills redacted pixels
+    fill: RedactFill,
+    // Remove overlay planes, curve data and icon images on save
+    strip_overlays: bool,
+    pseudonyms: Option<PseudonymTable>,
+    // Pixels were edited, so BurnedInAnnotation can be set to NO on save
+    pixels_redacted: bool,
+    // The face and ears were wiped by `deface::deface`
+    defaced: bool,
+    // What was burned in, and where from, for the audit log
+    applied_regions: Vec<AppliedRegion>,
+    source: Option<PathBuf>,
+    audit_log: Option<AuditLog>,
+    // Write a `.regions.json` next to every save
+    regions_sidecar: bool,
+    // Write the redaction mask as a `.seg.dcm` next to every DICOM save
+    write_seg: bool,
+    // Save DICOM as a new Secondary Capture instance instead of the original IOD
+    secondary_capture: bool,
+    // TIFF source with at most 8 bits per sample; TIFF output keeps that depth
+    eight_bit_tiff: bool,
+    // Whole slide image: `frames` holds the view at `view_origin` of its first plane
+    tiled: Option<wsi::TiledImage>,
+    view_origin: (u32, u32),
+    // SR, encapsulated, presentation state or RT document, with no pixels to redact
+    // (RT Dose grids are written back as they are); `frames` holds a 1×1 placeholder
+    document: bool,
+    // New EncapsulatedDocument payload written on save
+    replacement: Option<Vec<u8>>,
+    // Site detectors and tag transformers; the transformers run on save
+    plugins: plugin::Plugins,
+    // Turn and mirror the output as this displays the frames
+    orientation: orientation::Orientation,
+    // Text burned into a corner of every saved frame
+    stamp: Option<stamp::Stamp>,
+    // Part of the image the output is cut down to
+    crop: Option<RedactRect>,
+}
+
+impl Redactor {
+    /// Open a DICOM or regular image file. DICOM is recognized by its `DICM` magic
+    /// (or extension), so extensionless and `.ima` files work too.
+    pub fn open(path: &Path) -> Result<Self, DCMRedactErrors> {
+        if is_dicom_file(path) {
+            Self::open_dicom(path)
+        } else {
+            // Not recognizably DICOM; if it isn't an image either, try parsing it as
+            // DICOM anyway before giving up
+            Self::open_image(path).or_else(|e| Self::open_dicom(path).map_err(|_| e))
+        }
+    }
+
+    /// Open a DICOM file. Whole slide images are opened at the top-left view of the
+    /// slide ([`wsi`]); SR ([`sr`]), encapsulated ([`encapsulated`]), presentation state
+    /// ([`presentation`]) and RT documents ([`rt`]) have no pixels to redact and are only
+    /// de-identified; everything else is decoded in full.
+    pub fn open_dicom(path: &Path) -> Result<Self, DCMRedactErrors> {
+        Self::from_dicom(open_dicom_object(path)?, path)
+    }
+
+    /// Open `path` read through `io` rather than straight from the file system, e.g.
+    /// a file held in memory in the browser ([`fileio::MemoryFs`]). Like
+    /// [`Redactor::open`], except that only the first page of a TIFF is read and
+    /// gzipped files are not recognized.
+    pub fn open_in(io: &dyn fileio::FileIo, path: &Path) -> Result<Self, DCMRedactErrors> {
+        let bytes = io
+            .read(path)
+            .map_err(|e| DCMRedactErrors::io(format!("Failed to read {}", path.display()), e))?;
+        let dicom = |bytes: &[u8]| {
+            let mut dcm = read_dicom_bytes(bytes, path)?;
+            charset::decode_text(&mut dcm);
+            Self::from_dicom(dcm, path)
+        };
+        let magic = bytes.get(128..132) == Some(b"DICM".as_slice());
+        if magic || is_dicom_path(path) {
+            return dicom(&bytes);
+        }
+        let image = image::load_from_memory(&bytes)
+            .map_err(|e| {
+                DCMRedactErrors::decode(format!("Failed to open image {}", path.display()), e)
+            })
+            .map(|img| Self {
+                source: Some(path.to_path_buf()),
+                ..Self::from_image(dynamic_frame(&img))
+            });
+        image.or_else(|e| dicom(&bytes).map_err(|_| e))
+    }
+
+    fn from_dicom(
+        dcm: FileDicomObject<InMemDicomObject>,
+        path: &Path,
+    ) -> Result<Self, DCMRedactErrors> {
+        let no_pixels = dcm.element(tags::PIXEL_DATA).is_err()
+            && (sr::is_structured_report(&dcm)
+                || encapsulated::is_encapsulated(&dcm)
+                || presentation::is_presentation_state(&dcm));
+        if no_pixels || rt::is_rt_object(&dcm) {
+            return Ok(Self {
+                source: Some(path.to_path_buf()),
+                dcm: Some(dcm),
+                document: true,
+                ..Self::from_image(Gray16Image::new(1, 1))
+            });
+        }
+        let pixel_info = pixel_info(&dcm)?;
+        let (frames, tiled) = if wsi::is_tiled(&dcm) {
+            let mut tiled = wsi::TiledImage::new(&dcm, &pixel_info)?;
+            let view = tiled.view_at(0, 0);
+            (vec![tiled.render(&dcm, view)?], Some(tiled))
+        } else {
+            (decode_frames(&dcm, &pixel_info)?, None)
+        };
+        Ok(Self {
+            source: Some(path.to_path_buf()),
+            frames,
+            dcm: Some(dcm),
+            pixel_info: Some(pixel_info),
+            deidentify: true,
+            anonymizer: Anonymizer::new(),
+            transfer_syntax: OutputTransferSyntax::default(),
+            regenerate_uids: true,
+            uid_log: None,
+            fill: RedactFill::default(),
+            strip_overlays: true,
+            pseudonyms: None,
+            pixels_redacted: false,
+            defaced: false,
+            applied_regions: Vec::new(),
+            audit_log: None,
+            regions_sidecar: false,
+            write_seg: false,
+            secondary_capture: false,
+            eight_bit_tiff: false,
+            tiled,
+            view_origin: (0, 0),
+            document: false,
+            replacement: None,
+            plugins: plugin::Plugins::default(),
+            orientation: orientation::Orientation::default(),
+            stamp: None,
+            crop: None,
+        })
+    }
+
+    /// Open a regular image; every page of a TIFF becomes a frame.
+    pub fn open_image(path: &Path) -> Result<Self, DCMRedactErrors> {
+        if multipage::is_tiff_path(path) {
+            let pages = multipage::read_tiff(path)?;
+            return Ok(Self {
+                source: Some(path.to_path_buf()),
+                eight_bit_tiff: pages.eight_bit,
+                ..Self::from_frames(pages.frames)
+            });
+        }
+        let dyn_img = image::open(path).map_err(|e| {
+            DCMRedactErrors::decode(format!("Failed to open image {}", path.display()), e)
+        })?;
+        Ok(Self {
+            source: Some(path.to_path_buf()),
+            ..Self::from_image(dynamic_frame(&dyn_img))
+        })
+    }
+
+    /// Wrap an already-decoded image (no DICOM object attached).
+    pub fn from_image(image: impl Into<Frame>) -> Self {
+        Self::from_frames(vec![image.into()])
+    }
+
+    // `frames` must be non-empty and all the same size
+    fn from_frames(frames: Vec<Frame>) -> Self {
+        Self {
+            frames,
+            dcm: None,
+            pixel_info: None,
+            deidentify: true,
+            anonymizer: Anonymizer::new(),
+            transfer_syntax: OutputTransferSyntax::default(),
+            regenerate_uids: true,
+            uid_log: None,
+            fill: RedactFill::default(),
+            strip_overlays: true,
+            pseudonyms: None,
+            pixels_redacted: false,
+            defaced: false,
+            applied_regions: Vec::new(),
+            source: None,
+            audit_log: None,
+            regions_sidecar: false,
+            write_seg: false,
+            secondary_capture: false,
+            eight_bit_tiff: false,
+            tiled: None,
+            view_origin: (0, 0),
+            document: false,
+            replacement: None,
+            plugins: plugin::Plugins::default(),
+            orientation: orientation::Orientation::default(),
+            stamp: None,
+            crop: None,
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Full-res pixels of frame `i`. Panics if `i >= frame_count()`.
+    pub fn frame(&self, i: usize) -> &Frame {
+        &self.frames[i]
+    }
+
+    /// Direct access to the full-res frames, e.g. for [`history::History::record`].
+    /// Handing them out counts as redacting the pixels.
+    pub fn frames_mut(&mut self) -> &mut [Frame] {
+        self.pixels_redacted = true;
+        &mut self.frames
+    }
+
+    /// Record that [`deface::deface`] wiped the face and ears from the frames.
+    pub(crate) fn mark_defaced(&mut self) {
+        self.pixels_redacted = true;
+        self.defaced = true;
+    }
+
+    /// (width, height) shared by every frame.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames[0].dimensions()
+    }
+
+    pub fn dicom(&self) -> Option<&FileDicomObject<InMemDicomObject>> {
+        self.dcm.as_ref()
+    }
+
+    /// The source DICOM object, for editing metadata before saving.
+    pub fn dicom_mut(&mut self) -> Option<&mut FileDicomObject<InMemDicomObject>> {
+        self.dcm.as_mut()
+    }
+
+    pub fn is_dcm(&self) -> bool {
+        self.dcm.is_some()
+    }
+
+    /// Whether this is an SR ([`sr`]), encapsulated ([`encapsulated`]), presentation
+    /// state ([`presentation`]) or RT document ([`rt`]): no pixels to redact, only
+    /// metadata and content to de-identify. Its single frame is a 1×1 placeholder.
+    pub fn is_document(&self) -> bool {
+        self.document
+    }
+
+    /// The encapsulated document as it will be saved: its replacement if there is one,
+    /// else the original. `None` for everything but encapsulated documents.
+    pub fn document_payload(&self) -> Option<Vec<u8>> {
+        match &self.replacement {
+            Some(bytes) => Some(bytes.clone()),
+            None => self.dcm.as_ref().and_then(|dcm| encapsulated::payload(dcm)),
+        }
+    }
+
+    /// Save `bytes` (e.g. a PDF redacted elsewhere) as the encapsulated document in
+    /// place of the original. Ignored for everything but encapsulated documents.
+    pub fn replace_document(&mut self, bytes: Vec<u8>) {
+        if self
+            .dcm
+            .as_ref()
+            .is_some_and(|dcm| encapsulated::is_encapsulated(dcm))
+        {
+            self.replacement = Some(bytes);
+        }
+    }
+
+    /// Save a placeholder saying the document was removed in place of the encapsulated
+    /// document ([`encapsulated::placeholder`]).
+    pub fn strip_document(&mut self) {
+        if let Some(dcm) = &self.dcm {
+            let placeholder = encapsulated::placeholder(dcm);
+            self.replace_document(placeholder);
+        }
+    }
+
+    /// Whether the encapsulated document will be saved replaced.
+    pub fn document_replaced(&self) -> bool {
+        self.replacement.is_some()
+    }
+
+    /// The tile layout of a whole slide image, `None` for everything else.
+    pub fn tiled(&self) -> Option<&wsi::TiledImage> {
+        self.tiled.as_ref()
+    }
+
+    /// `LABEL` or `OVERVIEW` for those associated images of a slide ([`wsi::associated_image`]).
+    pub fn associated_image(&self) -> Option<&'static str> {
+        self.dcm.as_ref().and_then(|dcm| wsi::associated_image(dcm))
+    }
+
+    /// Top-left corner of the shown view in the whole slide ((0, 0) for other images).
+    pub fn view_origin(&self) -> (u32, u32) {
+        self.view_origin
+    }
+
+    /// Show the part of a whole slide image at (x, y), after writing the edits to the
+    /// current view back into its tiles. Regions recorded on the old view are cleared;
+    /// they stay in the audit record. Does nothing for other images.
+    pub fn set_view(&mut self, x: u32, y: u32) -> Result<(), DCMRedactErrors> {
+        self.store_view()?;
+        let (Some(tiled), Some(dcm)) = (self.tiled.as_mut(), self.dcm.as_ref()) else {
+            return Ok(());
+        };
+        let view = tiled.view_at(x, y);
+        self.frames = vec![tiled.render(dcm, view)?];
+        self.view_origin = (view.x0, view.y0);
+        self.applied_regions.clear();
+        Ok(())
+    }
+
+    /// Write the view of a whole slide image back into its tiles, and fill the
+    /// regions recorded on it on the slide's other planes.
+    fn store_view(&mut self) -> Result<(), DCMRedactErrors> {
+        let (x, y) = self.view_origin;
+        let regions: Vec<(AppliedRegion, RedactFill)> = self
+            .applied_regions
+            .iter()
+            .map(|r| {
+                let mut on_slide = r.clone();
+                on_slide.region.shape = r.region.shape.translated(x.into(), y.into());
+                (on_slide, self.pixel_fill(r.fill))
+            })
+            .collect();
+        let (Some(tiled), Some(dcm)) = (self.tiled.as_mut(), self.dcm.as_ref()) else {
+            return Ok(());
+        };
+        let (w, h) = self.frames[0].dimensions();
+        let view = RedactRect {
+            x0: x,
+            y0: y,
+            x1: x + w,
+            y1: y + h,
+        };
+        tiled.store(dcm, view, &self.frames[0])?;
+        for (region, pixels) in &regions {
+            tiled.burn(dcm, region, *pixels)?;
+        }
+        Ok(())
+    }
+
+    /// Regions for the sidecar, audit log and SEG, in the output's coordinates: for
+    /// whole slide images, every region filled on the slide, in slide coordinates;
+    /// for cropped or turned output, as cropped and turned. Regions outside the crop
+    /// are left out.
+    fn recorded_regions(&self) -> std::borrow::Cow<'_, [AppliedRegion]> {
+        if let Some(tiled) = &self.tiled {
+            return std::borrow::Cow::Borrowed(tiled.burned());
+        }
+        if self.crop.is_none() && self.orientation.is_identity() {
+            return std::borrow::Cow::Borrowed(&self.applied_regions);
+        }
+        let (width, height) = self.cropped_size();
+        self.applied_regions
+            .iter()
+            .filter_map(|r| {
+                let shape = match self.crop {
+                    Some(crop) => crop::crop_shape(&r.region.shape, crop)?,
+                    None => r.region.shape.clone(),
+                };
+                let mut moved = r.clone();
+                moved.region.shape = self.orientation.shape(&shape, width, height);
+                Some(moved)
+            })
+            .collect()
+    }
+
+    /// Width and height of the image once cropped, before it is turned.
+    fn cropped_size(&self) -> (u32, u32) {
+        match self.crop {
+            Some(r) => (r.x1 - r.x0, r.y1 - r.y0),
+            None => self.dimensions(),
+        }
+    }
+
+    /// Width and height of the output image.
+    fn output_size(&self) -> (u32, u32) {
+        match &self.tiled {
+            Some(tiled) => tiled.size(),
+            None => {
+                let (width, height) = self.cropped_size();
+                self.orientation.size(width, height)
+            }
+        }
+    }
+
+    /// The part of the image the output is cut down to, if any.
+    pub fn crop(&self) -> Option<RedactRect> {
+        self.crop
+    }
+
+    /// Save only the pixels inside `crop` (limited to the image), with Rows, Columns
+    /// and ImagePositionPatient updated to match ([`crop::crop_header`]); regions in
+    /// the sidecar, audit log and SEG are moved along. The crop comes before any
+    /// turning, so `crop` is in the loaded image's coordinates. The loaded frames stay
+    /// as they are, and whole slide images and documents are never cropped.
+    pub fn set_crop(&mut self, crop: Option<RedactRect>) {
+        let (width, height) = self.dimensions();
+        self.crop = crop.and_then(|r| crop::clip(r, width, height));
+    }
+
+    /// How the output is turned and mirrored (not at all by default).
+    pub fn orientation(&self) -> orientation::Orientation {
+        self.orientation
+    }
+
+    /// Save the frames turned and mirrored by `orientation`, with the image plane
+    /// attributes updated to match ([`orientation::Orientation::reorient_header`]).
+    /// Regions in the sidecar, audit log and SEG are turned along. The loaded frames
+    /// stay as they are, and whole slide images and documents are never turned.
+    pub fn set_orientation(&mut self, orientation: orientation::Orientation) {
+        self.orientation = orientation;
+    }
+
+    pub fn stamp(&self) -> Option<&stamp::Stamp> {
+        self.stamp.as_ref()
+    }
+
+    /// Burn `stamp` into a corner of every saved frame, after turning them. The loaded
+    /// frames stay as they are.
+    pub fn set_stamp(&mut self, stamp: Option<stamp::Stamp>) {
+        self.stamp = stamp;
+    }
+
+    pub fn pixel_info(&self) -> Option<&PixelInfo> {
+        self.pixel_info.as_ref()
+    }
+
+    pub fn photometric_interpretation(&self) -> Option<&str> {
+        self.pixel_info
+            .as_ref()
+            .map(|p| p.photometric_interpretation.as_str())
+    }
+
+    /// Modality LUT (RescaleSlope/RescaleIntercept) from frame values, for display;
+    /// identity for non-DICOM images. Takes out the offset of signed data.
+    pub fn rescale(&self) -> voi::Rescale {
+        let Some(dcm) = &self.dcm else {
+            return voi::Rescale::default();
+        };
+        let mut rescale = voi::rescale(dcm);
+        let offset = self.pixel_info.as_ref().map_or(0, PixelInfo::signed_offset);
+        rescale.intercept -= f64::from(offset) * rescale.slope;
+        rescale
+    }
+
+    /// Windows and VOI LUTs stored in the dataset, for display.
+    pub fn voi_presets(&self) -> Vec<voi::VoiPreset> {
+        self.dcm
+            .as_ref()
+            .map(|d| voi::presets(d))
+            .unwrap_or_default()
+    }
+
+    /// Significant bits per stored sample (16 for non-DICOM images, which are
+    /// widened to the full u16 range on load).
+    pub fn bits_stored(&self) -> u16 {
+        self.pixel_info.as_ref().map_or(16, |p| p.bits_stored)
+    }
+
+    /// Size of a pixel in mm, between rows then between columns: PixelSpacing (from
+    /// the functional groups of enhanced multi-frame images too), or else
+    /// ImagerPixelSpacing for projection images that only give that.
+    pub fn pixel_spacing(&self) -> Option<[f64; 2]> {
+        let dcm = self.dcm.as_ref()?;
+        let spacing = volume::functional_group(dcm, 0, tags::PIXEL_MEASURES_SEQUENCE)
+            .and_then(|g| volume::floats(g, tags::PIXEL_SPACING))
+            .or_else(|| volume::floats(dcm, tags::PIXEL_SPACING))
+            .or_else(|| volume::floats(dcm, tags::IMAGER_PIXEL_SPACING))?;
+        match spacing[..] {
+            [dy, dx] if dy > 0.0 && dx > 0.0 => Some([dy, dx]),
+            _ => None,
+        }
+    }
+
+    /// Height over width of a pixel: from the pixel spacing, or else
+    /// PixelAspectRatio; 1 for square pixels and when neither is given.
+    pub fn pixel_aspect(&self) -> f64 {
+        if let Some([dy, dx]) = self.pixel_spacing() {
+            return dy / dx;
+        }
+        match self
+            .dcm
+            .as_ref()
+            .and_then(|d| volume::floats(d, tags::PIXEL_ASPECT_RATIO))
+            .as_deref()
+        {
+            Some(&[v, h]) if v > 0.0 && h > 0.0 => v / h,
+            _ => 1.0,
+        }
+    }
+
+    /// Whether metadata is de-identified on save (default: on).
+    pub fn deidentify(&self) -> bool {
+        self.deidentify
+    }
+
+    pub fn set_deidentify(&mut self, on: bool) {
+        self.deidentify = on;
+    }
+
+    /// Whether overlay planes (60xx), curve data (50xx) and icon image sequences are
+    /// removed on save (default: on). Overlays embedded in unused pixel bits are
+    /// cleared too.
+    pub fn strip_overlays(&self) -> bool {
+        self.strip_overlays
+    }
+
+    pub fn set_strip_overlays(&mut self, on: bool) {
+        self.strip_overlays = on;
+    }
+
+    /// Transfer syntax used when saving DICOM (default: uncompressed).
+    pub fn transfer_syntax(&self) -> &OutputTransferSyntax {
+        &self.transfer_syntax
+    }
+
+    pub fn set_transfer_syntax(&mut self, ts: OutputTransferSyntax) {
+        self.transfer_syntax = ts;
+    }
+
+    /// Whether DICOM output gets a fresh SOP Instance UID even with de-identification
+    /// off (default: on). De-identification always replaces it.
+    pub fn regenerate_uids(&self) -> bool {
+        self.regenerate_uids
+    }
+
+    pub fn set_regenerate_uids(&mut self, on: bool) {
+        self.regenerate_uids = on;
+    }
+
+    /// Append the old → new UIDs of every DICOM save to this CSV file.
+    pub fn set_uid_mapping_file(&mut self, path: Option<PathBuf>) {
+        self.uid_log = path.map(UidMappingLog::new);
+    }
+
+    /// Replace UIDs through `uids`, shared with other redactors so every file of a
+    /// study or series is remapped consistently.
+    pub fn set_uid_remapper(&mut self, uids: UidRemapper) {
+        self.anonymizer.set_remapper(uids);
+    }
+
+    /// Whether private (odd-group) attributes are removed on save (default: on), with
+    /// or without de-identification.
+    pub fn remove_private(&self) -> bool {
+        self.anonymizer.remove_private
+    }
+
+    pub fn set_remove_private(&mut self, on: bool) {
+        self.anonymizer.remove_private = on;
+    }
+
+    /// Append an entry to `log` for every save.
+    pub fn set_audit_log(&mut self, log: Option<AuditLog>) {
+        self.audit_log = log;
+    }
+
+    /// The file this was opened from.
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    pub fn plugins(&self) -> &plugin::Plugins {
+        &self.plugins
+    }
+
+    /// Detectors for [`Redactor::detect_regions`], and transformers run over the
+    /// header on every DICOM save.
+    pub fn set_plugins(&mut self, plugins: plugin::Plugins) {
+        self.plugins = plugins;
+    }
+
+    pub fn regions_sidecar(&self) -> bool {
+        self.regions_sidecar
+    }
+
+    /// Write the applied regions to a [`sidecar`] file next to every save.
+    pub fn set_regions_sidecar(&mut self, on: bool) {
+        self.regions_sidecar = on;
+    }
+
+    pub fn write_seg(&self) -> bool {
+        self.write_seg
+    }
+
+    /// Also save the redaction mask as a DICOM Segmentation ([`seg`]) next to every
+    /// DICOM output that had regions applied.
+    pub fn set_write_seg(&mut self, on: bool) {
+        self.write_seg = on;
+    }
+
+    pub fn secondary_capture(&self) -> bool {
+        self.secondary_capture
+    }
+
+    /// Save DICOM sources as a new Secondary Capture instance ([`sc`]) rather than a
+    /// modified copy of the original IOD.
+    pub fn set_secondary_capture(&mut self, on: bool) {
+        self.secondary_capture = on;
+    }
+
+    /// Regions burned in since the file was opened, oldest first.
+    pub fn applied_regions(&self) -> &[AppliedRegion] {
+        &self.applied_regions
+    }
+
+    /// Replace the record of burned-in regions, for callers that edit
+    /// [`Redactor::frames_mut`] directly (e.g. through [`history::History`]).
+    pub fn set_applied_regions(&mut self, regions: Vec<AppliedRegion>) {
+        self.applied_regions = regions;
+    }
+
+    /// Replace PatientID/PatientName with pseudonyms from `table` on save (which also
+    /// writes the table back), whether or not metadata is otherwise de-identified.
+    pub fn set_pseudonym_table(&mut self, table: Option<PseudonymTable>) {
+        self.pseudonyms = table;
+    }
+
+    /// CID 7050 de-identification methods describing what [`Redactor::save`] does.
+    fn deidentification_methods(&self) -> Vec<(&'static str, &'static str)> {
+        use anonymize::methods::*;
+        use profile::{DatePolicy, UidPolicy};
+        let profile = self.anonymizer.profile();
+        let mut methods = vec![BASIC_PROFILE];
+        if self.pixels_redacted {
+            methods.push(CLEAN_PIXEL_DATA);
+        }
+        if self.strip_overlays {
+            methods.push(CLEAN_GRAPHICS);
+        }
+        match profile.dates {
+            DatePolicy::Keep => methods.push(RETAIN_FULL_DATES),
+            DatePolicy::Shift(_) => methods.push(RETAIN_MODIFIED_DATES),
+            DatePolicy::Profile | DatePolicy::Remove => {}
+        }
+        if profile.uids == UidPolicy::Keep {
+            methods.push(RETAIN_UIDS);
+        }
+        if !self.anonymizer.remove_private || !self.anonymizer.keep_private_creators.is_empty() {
+            methods.push(RETAIN_SAFE_PRIVATE);
+        }
+        methods
+    }
+
+    /// Anonymization profile used when de-identifying (default: the Basic Profile).
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.anonymizer.set_profile(profile);
+    }
+
+    /// Private creators (e.g. `SIEMENS CSA HEADER`) whose blocks are kept even when
+    /// private attributes are removed.
+    pub fn set_private_whitelist(&mut self, creators: Vec<String>) {
+        self.anonymizer.keep_private_creators = creators;
+    }
+
+    /// The anonymizer used on save; exposes the old → new UID map.
+    pub fn anonymizer(&self) -> &Anonymizer {
+        &self.anonymizer
+    }
+
+    /// Stored value that displays as black: MONOCHROME1 shows its highest value as
+    /// black, everything else zero.
+    fn black(&self) -> RedactFill {
+        if self.photometric_interpretation() == Some("MONOCHROME1") {
+            RedactFill::Constant(self.max_value())
+        } else {
+            RedactFill::Black
+        }
+    }
+
+    /// Stored value that displays as white, the opposite of [`Redactor::black`].
+    fn white(&self) -> RedactFill {
+        if self.photometric_interpretation() == Some("MONOCHROME1") {
+            RedactFill::Black
+        } else {
+            RedactFill::Constant(self.max_value())
+        }
+    }
+
+    /// `fill` as written into the frames: black is the highest value for MONOCHROME1,
+    /// and constants are clamped to [`Redactor::max_value`].
+    pub fn pixel_fill(&self, fill: RedactFill) -> RedactFill {
+        match fill {
+            RedactFill::Black => self.black(),
+            RedactFill::Constant(v) => RedactFill::Constant(v.min(self.max_value())),
+            fill => fill,
+        }
+    }
+
+    /// Highest value a frame sample can hold: 255 for color, else that of `BitsStored`
+    /// bits. Constant fills are clamped to it, so they can't spill into unused bits.
+    pub fn max_value(&self) -> u16 {
+        if self.frames[0].is_color() {
+            255
+        } else {
+            ((1u32 << self.bits_stored().clamp(1, 16)) - 1) as u16
+        }
+    }
+
+    /// Blacken the (x0..x1, y0..y1) rectangle in full-res pixel coordinates, on every frame.
+    pub fn blacken_rect(&mut self, x0: u32, y0: u32, x1: u32, y1: u32) {
+        self.blacken_shape(&Shape::Rect(RedactRect { x0, y0, x1, y1 }));
+    }
+
+    /// Blacken every frame entirely, as for the label image of a slide.
+    pub fn blank(&mut self) {
+        let (width, height) = self.dimensions();
+        self.blacken_rect(0, 0, width, height);
+    }
+
+    /// Like [`Redactor::blacken_rect`] but only on frame `i`.
+    pub fn blacken_rect_in_frame(&mut self, i: usize, x0: u32, y0: u32, x1: u32, y1: u32) {
+        self.blacken_shape_in_frame(i, &Shape::Rect(RedactRect { x0, y0, x1, y1 }));
+    }
+
+    /// Blacken every pixel covered by `shape`, on every frame.
+    pub fn blacken_shape(&mut self, shape: &Shape) {
+        self.note_region(Region::all_frames(shape.clone()), RedactFill::Black);
+        let black = self.black();
+        for frame in &mut self.frames {
+            frame.fill_shape(shape, black);
+        }
+    }
+
+    /// Like [`Redactor::blacken_shape`] but only on frame `i`.
+    pub fn blacken_shape_in_frame(&mut self, i: usize, shape: &Shape) {
+        let region = Region {
+            shape: shape.clone(),
+            frame: Some(i),
+            last_frame: None,
+        };
+        self.note_region(region, RedactFill::Black);
+        let black = self.black();
+        self.frames[i].fill_shape(shape, black);
+    }
+
+    /// Fill `region` on the frames it applies to, using [`Redactor::fill`].
+    pub fn apply_region(&mut self, region: &Region) {
+        self.note_region(region.clone(), self.fill);
+        let fill = self.pixel_fill(self.fill);
+        for i in region.target_frames(self.frames.len()) {
+            self.frames[i].fill_shape(&region.shape, fill);
+        }
+    }
+
+    fn note_region(&mut self, region: Region, fill: RedactFill) {
+        self.pixels_redacted = true;
+        self.applied_regions.push(AppliedRegion { region, fill });
+    }
+
+    /// How [`Redactor::apply_region`] fills redacted pixels (black by default).
+    pub fn fill(&self) -> RedactFill {
+        self.fill
+    }
+
+    pub fn set_fill(&mut self, fill: RedactFill) {
+        self.fill = fill;
+    }
+
+    /// Save frame `i` as a PNG, JPEG or TIFF image ([`export`]): grayscale through
+    /// `voi` as 8-bit display values, or as stored values without one.
+    pub fn export_frame(
+        &self,
+        i: usize,
+        path: &Path,
+        voi: Option<voi::Voi>,
+    ) -> Result<(), DCMRedactErrors> {
+        let gray = match voi {
+            Some(voi) => export::GrayExport::Windowed {
+                rescale: self.rescale(),
+                voi,
+                invert: self.photometric_interpretation() == Some("MONOCHROME1"),
+            },
+            None => export::GrayExport::Stored {
+                bits_stored: self.bits_stored(),
+            },
+        };
+        export::export_frame(&self.frames[i], path, &gray)
+    }
+
+    /// Run OCR on frame `i` and return candidate rectangles around burned-in text.
+    pub fn detect_text(
+        &self,
+        i: usize,
+        opts: &ocr::OcrOptions,
+    ) -> Result<Vec<RedactRect>, DCMRedactErrors> {
+        ocr::detect_text(
+            &self.frames[i],
+            self.bits_stored(),
+            self.photometric_interpretation(),
+            opts,
+        )
+    }
+
+    /// Candidate rectangles around bright burned-in text on frame `i`, found without
+    /// OCR ([`suggest`]).
+    pub fn suggest_regions(&self, i: usize, opts: &suggest::SuggestOptions) -> Vec<RedactRect> {
+        suggest::suggest_regions(
+            &self.frames[i],
+            self.bits_stored(),
+            self.photometric_interpretation(),
+            opts,
+        )
+    }
+
+    /// Rectangles the registered [`plugin::RegionDetector`]s find on frame `i`.
+    pub fn detect_regions(&self, i: usize) -> Result<Vec<RedactRect>, DCMRedactErrors> {
+        self.plugins.detect(self, i)
+    }
+
+    /// Rectangles covering everything outside the ultrasound regions of a US image
+    /// ([`ultrasound::mask`]); empty if it has none.
+    pub fn ultrasound_mask(&self) -> Vec<RedactRect> {
+        let Some(dcm) = &self.dcm else {
+            return Vec::new();
+        };
+        let (width, height) = self.dimensions();
+        ultrasound::mask(dcm, width, height)
+    }
+
+    /// The metadata [`Redactor::save`] would write with the current settings, before
+    /// the pixel data is replaced; `None` for non-DICOM sources, and when a plugin
+    /// transformer fails (saving reports why). Patients the pseudonym table doesn't
+    /// know yet get [`PseudonymTable::placeholder`], so previews don't add to it.
+    pub fn output_metadata(&mut self) -> Option<FileDicomObject<InMemDicomObject>> {
+        self.planned_metadata(&[], false).ok().flatten()
+    }
+
+    /// [`Redactor::output_metadata`] for naming the file about to be saved: a patient
+    /// new to the pseudonym table gets their pseudonym now, as saving would.
+    pub fn naming_metadata(&mut self) -> Option<FileDicomObject<InMemDicomObject>> {
+        self.planned_metadata(&[], true).ok().flatten()
+    }
+
+    /// Like [`Redactor::output_metadata`], as if `pending` regions were filled too.
+    /// New pseudonyms are only generated if `assign_pseudonyms`.
+    fn planned_metadata(
+        &mut self,
+        pending: &[AppliedRegion],
+        assign_pseudonyms: bool,
+    ) -> Result<Option<FileDicomObject<InMemDicomObject>>, DCMRedactErrors> {
+        let pixels_redacted = self.pixels_redacted || !pending.is_empty();
+        // Regions are only known once recorded (e.g. not for raw frame edits)
+        let mut regions = self.applied_regions.iter().chain(pending).peekable();
+        let rectangles_only =
+            regions.peek().is_some() && regions.all(|r| matches!(r.region.shape, Shape::Rect(_)));
+        let Some(dcm) = self.dcm.as_ref() else {
+            return Ok(None);
+        };
+        let mut out = dcm.clone();
+        if self.strip_overlays {
+            overlays::strip_overlays(&mut out);
+        }
+        if self.deidentify {
+            self.anonymizer.anonymize(&mut out);
+            if rt::is_rt_object(dcm) {
+                rt::scrub_labels(dcm, &mut out);
+            }
+            if presentation::is_presentation_state(dcm) {
+                presentation::scrub_annotations(dcm, &mut out);
+            }
+        } else if self.anonymizer.remove_private {
+            anonymize::strip_private(&mut out, &self.anonymizer.keep_private_creators);
+        }
+        if !self.deidentify
+            && self.regenerate_uids
+            && let Some(uid) = out
+                .element(tags::SOP_INSTANCE_UID)
+                .ok()
+                .and_then(|e| e.to_str().ok())
+        {
+            let new_uid = self.anonymizer.map_uid(&uid);
+            uids::set_sop_instance_uid(&mut out, &new_uid);
+        }
+        if !self.deidentify && self.regenerate_uids {
+            // References to other instances follow their new UIDs
+            self.anonymizer.remap_references(&mut out);
+        }
+        match &self.pseudonyms {
+            Some(table) if assign_pseudonyms => table.apply(dcm, &mut out),
+            Some(table) => table.preview(dcm, &mut out),
+            None => {}
+        }
+        if let Some(bytes) = &self.replacement {
+            encapsulated::set_payload(&mut out, bytes);
+        }
+        self.plugins.transform(dcm, &mut out)?;
+        if pixels_redacted {
+            out.put(DataElement::new(
+                tags::BURNED_IN_ANNOTATION,
+                VR::CS,
+                PrimitiveValue::from("NO"),
+            ));
+            let description = if rectangles_only {
+                "Rectangular regions replaced to remove burned-in PHI"
+            } else {
+                "Regions replaced to remove burned-in PHI"
+            };
+            anonymize::record_derivation(&mut out, description);
+            if self.defaced {
+                anonymize::record_derivation(&mut out, "Face and ears removed by defacing");
+            }
+        }
+        if self.deidentify {
+            anonymize::record_deidentification(&mut out, &self.deidentification_methods());
+        }
+        // A whole slide's tiles are written back as they are, never as a capture, and
+        // documents have no image to capture
+        if self.tiled.is_none() && !self.document {
+            if let Some(crop) = self.crop {
+                crop::crop_header(&mut out, crop);
+                let description = format!(
+                    "Cropped to the {}x{} pixels from column {}, row {}",
+                    crop.x1 - crop.x0,
+                    crop.y1 - crop.y0,
+                    crop.x0,
+                    crop.y0
+                );
+                anonymize::mark_derived(&mut out, &description);
+            }
+            if !self.orientation.is_identity() {
+                let (width, height) = self.cropped_size();
+                self.orientation.reorient_header(&mut out, width, height);
+            }
+        }
+        if self.secondary_capture && self.tiled.is_none() && !self.document {
+            sc::to_secondary_capture(&mut out, self.frames.len(), self.frames[0].is_color());
+        }
+        Ok(Some(out))
+    }
+
+    /// What [`Redactor::save`] to `path` would do with the current settings, once
+    /// `pending` regions are filled too, without touching any file or pixel. Fails
+    /// only if a plugin transformer does.
+    pub fn dry_run(
+        &mut self,
+        path: &Path,
+        pending: &[AppliedRegion],
+    ) -> Result<DryRunReport, DCMRedactErrors> {
+        let mut regions = self.applied_regions.clone();
+        regions.extend_from_slice(pending);
+        let planned = self.planned_metadata(pending, false)?;
+        Ok(DryRunReport::new(
+            self.source.as_deref(),
+            path,
+            regions,
+            self.dcm.as_deref(),
+            planned.as_deref(),
+        ))
+    }
+
+    /// Write the redacted image. DICOM sources are written back as DICOM, everything
+    /// else through the `image` crate (format picked from the extension), except TIFF,
+    /// which gets a page per frame.
+    ///
+    /// DICOM output is built from a copy, so the loaded object keeps its original
+    /// metadata and repeated saves produce the same replacement UIDs.
+    pub fn save(&mut self, path: &Path) -> Result<(), DCMRedactErrors> {
+        if self.tiled.is_some() {
+            return self.save_tiled(path);
+        }
+        if self.document {
+            return self.save_document(path);
+        }
+        let Some(mut out) = self.planned_metadata(&[], true)? else {
+            if multipage::is_tiff_path(path) {
+                multipage::write_tiff(path, &self.output_frames(), self.eight_bit_tiff)?;
+            } else if self.frame_count() > 1 {
+                return Err(DCMRedactErrors::ValueError(format!(
+                    "Can't save {} frames to {}: only TIFF holds more than one page",
+                    self.frame_count(),
+                    path.display()
+                )));
+            } else {
+                self.output_frames()[0].save(path).map_err(|e| {
+                    DCMRedactErrors::encode(format!("Failed to save image {}", path.display()), e)
+                })?;
+            }
+            self.write_sidecar(path)?;
+            return self.record_audit(path, None);
+        };
+
+        uids::stamp_file_meta(&mut out);
+        write_dynamic_image_to_dicom(&mut out, &self.output_frames(), path, &self.transfer_syntax)?;
+
+        if let Some(table) = &self.pseudonyms {
+            table.save()?;
+        }
+
+        if let (Some(log), Some(dcm)) = (&self.uid_log, &self.dcm) {
+            log.record(path, dcm, &out)?;
+        }
+        if self.write_seg && !self.applied_regions.is_empty() {
+            let (width, height) = self.output_size();
+            seg::write_segmentation(
+                &seg::seg_path(path),
+                &out,
+                width,
+                height,
+                self.frame_count(),
+                &self.recorded_regions(),
+            )?;
+        }
+        self.write_sidecar(path)?;
+        self.record_audit(path, Some(&out))
+    }
+
+    /// The frames as written: with overlays stripped, overlay bits embedded in the
+    /// pixel data are cleared; then they are cropped and turned as
+    /// [`Redactor::set_crop`] and [`Redactor::set_orientation`] ask, and stamped
+    /// ([`Redactor::set_stamp`]).
+    fn output_frames(&self) -> std::borrow::Cow<'_, [Frame]> {
+        let embedded = match &self.dcm {
+            Some(dcm) if self.strip_overlays => overlays::embedded_overlay_bits(dcm),
+            _ => 0,
+        };
+        if embedded == 0
+            && self.crop.is_none()
+            && self.orientation.is_identity()
+            && self.stamp.is_none()
+        {
+            return std::borrow::Cow::Borrowed(&self.frames);
+        }
+        let mut frames = self.frames.clone();
+        for frame in &mut frames {
+            if embedded != 0 {
+                frame.clear_bits(embedded);
+            }
+            if let Some(crop) = self.crop {
+                *frame = frame.crop(crop);
+            }
+            if !self.orientation.is_identity() {
+                *frame = self.orientation.frame(frame);
+            }
+            let (width, height) = frame.dimensions();
+            if let Some((backing, letters)) =
+                self.stamp.as_ref().and_then(|s| s.layout(width, height))
+            {
+                frame.fill_shape(&Shape::Rect(backing), self.black());
+                for letter in letters {
+                    frame.fill_shape(&Shape::Rect(letter), self.white());
+                }
+            }
+        }
+        std::borrow::Cow::Owned(frames)
+    }
+
+    /// Save to `path` through `io` rather than straight to the file system, e.g. into
+    /// memory to offer as a download in the browser. Only the redacted file itself is
+    /// written: the SEG, regions sidecar, audit log, UID log and pseudonym table need
+    /// [`Redactor::save`], as do whole slide images and documents.
+    pub fn save_in(
+        &mut self,
+        io: &mut dyn fileio::FileIo,
+        path: &Path,
+    ) -> Result<(), DCMRedactErrors> {
+        if self.tiled.is_some() || self.document {
+            return Err(DCMRedactErrors::ValueError(
+                "Whole slide images and documents can only be saved to a file".to_string(),
+            ));
+        }
+        let bytes = match self.planned_metadata(&[], true)? {
+            None if self.frame_count() > 1 => {
+                return Err(DCMRedactErrors::ValueError(format!(
+                    "Can't save {} frames to {}: only DICOM holds more than one here",
+                    self.frame_count(),
+                    path.display()
+                )));
+            }
+            None => self.output_frames()[0].encode(path).map_err(|e| {
+                DCMRedactErrors::encode(format!("Failed to encode image {}", path.display()), e)
+            })?,
+            Some(mut out) => {
+                uids::stamp_file_meta(&mut out);
+                encode_pixel_data(&mut out, &self.output_frames(), &self.transfer_syntax)?;
+                dicom_file_bytes(&out)?
+            }
+        };
+        io.write(path, bytes)
+            .map_err(|e| DCMRedactErrors::io(format!("Failed to write {}", path.display()), e))
+    }
+
+    /// [`Redactor::save`] for whole slide images: only redacted tiles are re-encoded,
+    /// in the source transfer syntax, and the rest are copied as they are.
+    fn save_tiled(&mut self, path: &Path) -> Result<(), DCMRedactErrors> {
+        self.store_view()?;
+        // Recorded regions now include those filled on earlier views
+        let burned = self.recorded_regions().to_vec();
+        let Some(mut out) = self.planned_metadata(&burned, true)? else {
+            return Ok(());
+        };
+        if let Some(tiled) = &self.tiled {
+            tiled.write_tiles(&mut out)?;
+        }
+        uids::stamp_file_meta(&mut out);
+        write_dicom_file(&out, path)?;
+
+        if let Some(table) = &self.pseudonyms {
+            table.save()?;
+        }
+        if let (Some(log), Some(dcm)) = (&self.uid_log, &self.dcm) {
+            log.record(path, dcm, &out)?;
+        }
+        self.write_sidecar(path)?;
+        self.record_audit(path, Some(&out))
+    }
+
+    /// [`Redactor::save`] for documents ([`Redactor::is_document`]): only the
+    /// de-identified metadata (and replaced document) is written.
+    fn save_document(&mut self, path: &Path) -> Result<(), DCMRedactErrors> {
+        let Some(mut out) = self.planned_metadata(&[], true)? else {
+            return Ok(());
+        };
+        uids::stamp_file_meta(&mut out);
+        write_dicom_file(&out, path)?;
+
+        if let Some(table) = &self.pseudonyms {
+            table.save()?;
+        }
+        if let (Some(log), Some(dcm)) = (&self.uid_log, &self.dcm) {
+            log.record(path, dcm, &out)?;
+        }
+        self.record_audit(path, Some(&out))
+    }
+
+    fn write_sidecar(&self, path: &Path) -> Result<(), DCMRedactErrors> {
+        if !self.regions_sidecar {
+            return Ok(());
+        }
+        let (width, height) = self.output_size();
+        RegionSidecar {
+            source: self.source.clone(),
+            width,
+            height,
+            frames: self.frame_count(),
+            regions: self.recorded_regions().to_vec(),
+        }
+        .save(&sidecar_path(path))
+    }
+
+    fn record_audit(
+        &self,
+        path: &Path,
+        written: Option<&InMemDicomObject>,
+    ) -> Result<(), DCMRedactErrors> {
+        let Some(log) = &self.audit_log else {
+            return Ok(());
+        };
+        log.record(&AuditEntry::new(
+            log.operator(),
+            self.source.as_deref(),
+            path,
+            &self.recorded_regions(),
+            self.dcm.as_deref(),
+            written,
+        ))
+    }
+}
@@ -1,25 +1,710 @@
-use anyhow::{Context, anyhow};
-use dicom::core::smallvec::SmallVec;
-use dicom::pixeldata::PixelDecoder;
-use dicom::{
-    self,
-    core::{DataElement, PrimitiveValue, VR},
-    dictionary_std::tags,
-    object::{FileDicomObject, InMemDicomObject},
+mod console;
+mod i18n;
+mod settings;
+mod shortcuts;
+
+use clap::Parser;
+use console::Console;
+use dcm_redact::audit::{AppliedRegion, AuditLog, default_operator};
+use dcm_redact::batch::{BatchControl, BatchEvent, BatchJob, run_batch, run_batch_files, save_to};
+use dcm_redact::borders::Borders;
+use dcm_redact::deface::{self, Deface, DefaceOptions};
+use dcm_redact::dicomdir::write_dicomdir;
+use dcm_redact::dicomweb::{self, Protocol, Server};
+use dcm_redact::encapsulated::{self, Documents};
+use dcm_redact::folder::{
+    Instance, SortOrder, common_dir, scan_folder, scan_paths, sort_instances,
+};
+use dcm_redact::history::History;
+use dcm_redact::metadata::{DiffRow, diff_rows, element_rows, format_tag, set_element_text};
+use dcm_redact::naming::NamePattern;
+use dcm_redact::ocr::OcrOptions;
+use dcm_redact::orientation::Orientation;
+use dcm_redact::pacs::{self, Remote};
+use dcm_redact::phi::{PhiFinding, default_rules, default_rules_path, load_rules};
+use dcm_redact::plugin::{Plugins, default_plugins_path, load_plugins};
+use dcm_redact::presentation;
+use dcm_redact::presets::{Preset, default_presets_path, load_presets, matching};
+use dcm_redact::profile::{default_profiles_path, find_profile, load_profile_rules, load_profiles};
+use dcm_redact::project::{self, Project, ProjectFile};
+use dcm_redact::pseudonym::{PseudonymTable, load_or_create_key};
+use dcm_redact::query::{self, Match, Query, QueryLevel, Retrieve};
+use dcm_redact::report::{DryRunReport, TagAction, describe_region};
+use dcm_redact::rt;
+use dcm_redact::seg;
+use dcm_redact::shape::{keep_only, outside};
+use dcm_redact::sidecar::load_regions;
+use dcm_redact::sr;
+use dcm_redact::stamp::{Corner, Stamp};
+use dcm_redact::suggest::SuggestOptions;
+use dcm_redact::template::{Template, list_templates, template_dir, template_file_name};
+use dcm_redact::uids::UidRemapper;
+use dcm_redact::voi::{Histogram, Rescale, Voi, VoiPreset, Window, display_lut};
+use dcm_redact::volume::{self, MprPlane, Stack};
+use dcm_redact::wsi::{self, SlideLabels};
+use dcm_redact::{
+    COMMON_TRANSFER_SYNTAXES, DCMRedactErrors, Frame, Gray16Image, OutputTransferSyntax, Profile,
+    RedactFill, RedactRect, Redactor, Region, Rgb8Image, Shape, ShapeKind,
 };
+use dcm_redact::{archive, cloud};
 use eframe::{
     egui,
-    egui::{ColorImage, Pos2, Rect, Sense, Stroke, Vec2},
+    egui::{ColorImage, Key, KeyboardShortcut, Pos2, Rect, Sense, Stroke, Vec2},
 };
+use i18n::tr;
 use image::imageops::FilterType;
-use image::{ImageBuffer, Luma};
+use settings::{Settings, Theme, default_settings_path};
+use shortcuts::{Action, Shortcuts, default_shortcuts_path, format_binding};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
+
+#[derive(Parser)]
+#[command(version, about = "Redact rectangular regions in DICOM and image files")]
+struct Cli {
+    /// Files or folders to open in the GUI; several are queued in the thumbnail strip
+    #[arg(value_name = "PATH", conflicts_with = "batch")]
+    paths: Vec<PathBuf>,
+
+    /// Run headless over a directory instead of opening the GUI
+    #[arg(long, requires = "input")]
+    batch: bool,
+
+    /// Directory to read DICOM files from (searched recursively), a ZIP archive of them, or an
+    /// s3://, gs:// or az:// prefix
+    #[arg(long, value_name = "DIR")]
+    input: Option<PathBuf>,
+
+    /// Directory to write redacted files to (input layout is mirrored), a .zip file to write
+    /// them to, or an s3://, gs:// or az:// prefix
+    #[arg(long, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    /// Send every output to this PACS by C-STORE; without --output nothing is kept locally
+    #[arg(long, value_name = "AE@HOST:PORT", conflicts_with = "dry_run")]
+    send: Option<Remote>,
+    /// AE title to call the PACS as
+    #[arg(long, value_name = "AE", default_value = pacs::DEFAULT_CALLING_AE)]
+    calling_ae: String,
+    /// Post every output to this DICOMweb service by STOW-RS; without --output nothing is kept locally
+    #[arg(long, value_name = "URL", conflicts_with_all = ["dry_run", "send"])]
+    stow: Option<String>,
+    /// Bearer token for --stow
+    #[arg(long, value_name = "TOKEN", env = dicomweb::TOKEN_VAR, hide_env_values = true)]
+    token: Option<String>,
+
+    /// Region to blacken, in full-res pixels with exclusive end; may be repeated
+    #[arg(long, value_name = "X0,Y0,X1,Y1")]
+    rect: Vec<RedactRect>,
+    /// Save only this part of every image, in full-res pixels with exclusive end
+    #[arg(long, value_name = "X0,Y0,X1,Y1")]
+    crop: Option<RedactRect>,
+
+    /// Blacken this many rows at the top of every image
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    top_rows: u32,
+    /// Blacken this many rows at the bottom of every image
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    bottom_rows: u32,
+    /// Blacken a border of this percent of the width and height on all four sides
+    #[arg(long, value_name = "PERCENT", default_value_t = 0.0)]
+    border_percent: f32,
+
+    /// Region template (JSON or TOML) whose regions are redacted too; may be repeated
+    #[arg(long, value_name = "FILE")]
+    template: Vec<PathBuf>,
+    /// Region file (.regions.json sidecar, JSON region list or CSV) to redact too; may be repeated
+    #[arg(long, value_name = "FILE")]
+    regions: Vec<PathBuf>,
+    /// Fill everything outside the --rect, --template and --regions regions instead of inside them
+    #[arg(long)]
+    keep_inside: bool,
+    /// Also black out everything outside the ultrasound regions (SequenceOfUltrasoundRegions)
+    #[arg(long)]
+    ultrasound_mask: bool,
+    /// Also redact the regions of every vendor preset matching a file
+    #[arg(long)]
+    vendor_presets: bool,
+    /// Presets file added to the built-in presets (default: presets.toml in the config directory)
+    #[arg(long, value_name = "FILE", requires = "vendor_presets")]
+    presets: Option<PathBuf>,
+    /// Plugins file of site detectors, whose rectangles are redacted too, and tag
+    /// transformers (default: plugins.toml in the config directory, if it exists)
+    #[arg(long, value_name = "FILE")]
+    plugins: Option<PathBuf>,
+    /// Rhai script run for every file first, to add regions or skip the file (needs the
+    /// `scripting` feature)
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+    /// Wipe the face and ears from head CT and MR series: off, remove or blur
+    #[arg(long, value_name = "METHOD", default_value = "off")]
+    deface: Deface,
+
+    /// How to fill redacted pixels: black, constant:V, blur[:SIGMA], mosaic[:BLOCK], noise, mean
+    #[arg(long, value_name = "FILL", default_value = "black")]
+    fill: RedactFill,
+
+    /// Write metadata unchanged instead of applying the PS3.15 Basic Profile
+    #[arg(long)]
+    keep_metadata: bool,
+
+    /// Output transfer syntax: "uncompressed", "original", or a transfer syntax UID
+    #[arg(long, value_name = "TS", default_value = "uncompressed")]
+    transfer_syntax: OutputTransferSyntax,
+
+    /// Anonymization profile (by name) to de-identify with instead of the Basic Profile
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Profiles file to look --profile up in, whose rules pick a template and profile per
+    /// file (default: profiles.toml in the config directory)
+    #[arg(long, value_name = "FILE")]
+    profiles: Option<PathBuf>,
+
+    /// Keep overlay planes, curve data and icon images instead of removing them
+    #[arg(long)]
+    keep_overlays: bool,
+
+    /// Keep private (odd-group) attributes instead of removing them
+    #[arg(long)]
+    keep_private: bool,
+
+    /// Private creator whose attributes are kept even when private tags are removed; may be repeated
+    #[arg(long, value_name = "CREATOR")]
+    keep_private_creator: Vec<String>,
+
+    /// Keep the source SOP Instance UID when metadata is kept (not DICOM conformant)
+    #[arg(long)]
+    keep_uids: bool,
+
+    /// CSV file to append old -> new UID mappings to
+    #[arg(long, value_name = "FILE")]
+    uid_map: Option<PathBuf>,
+
+    /// Persistent old -> new UID map, reused (and updated) across runs
+    #[arg(long, value_name = "FILE")]
+    uid_store: Option<PathBuf>,
+
+    /// Replace PatientID/PatientName with pseudonyms kept in this table across runs
+    #[arg(long, value_name = "FILE")]
+    pseudonyms: Option<PathBuf>,
+
+    /// Key file for encrypting the pseudonym table (created if missing); required
+    /// unless --pseudonym-plaintext is given
+    #[arg(long, value_name = "FILE", requires = "pseudonyms")]
+    pseudonym_key: Option<PathBuf>,
+
+    /// Keep the pseudonym table as plain CSV, unencrypted
+    #[arg(long, requires = "pseudonyms", conflicts_with = "pseudonym_key")]
+    pseudonym_plaintext: bool,
+
+    /// Name outputs like "{patient_pseudo}/{study_uid}/{series}_{instance}.dcm" instead of mirroring the input layout
+    #[arg(long, value_name = "PATTERN")]
+    name_pattern: Option<NamePattern>,
+
+    /// Files to process in parallel (default: one per CPU core)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+    /// JSON lines file to append an audit record of every processed file to
+    #[arg(long, value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+    /// Operator name for the audit log (default: the login name)
+    #[arg(long, value_name = "NAME", requires = "audit_log")]
+    operator: Option<String>,
+    /// Write the applied regions to a .regions.json file next to every output
+    #[arg(long)]
+    regions_sidecar: bool,
+    /// Also write the redaction mask as a DICOM Segmentation (.seg.dcm) next to every output
+    #[arg(long)]
+    seg: bool,
+    /// Save every output as a new Secondary Capture instance instead of a modified original
+    #[arg(long)]
+    secondary_capture: bool,
+    /// Burn TEXT (DE-IDENTIFIED if none is given) into a corner of every output
+    #[arg(long, value_name = "TEXT", num_args = 0..=1, default_missing_value = "DE-IDENTIFIED")]
+    stamp: Option<String>,
+    /// Corner of the stamp: top-left, top-right, bottom-left or bottom-right
+    #[arg(
+        long,
+        value_name = "CORNER",
+        default_value = "bottom-right",
+        requires = "stamp"
+    )]
+    stamp_corner: Corner,
+    /// Height of the stamp's letters, in percent of the image height
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = 3.0,
+        requires = "stamp"
+    )]
+    stamp_size: f32,
+    /// What to do with slide label and overview images: keep, blank or remove
+    #[arg(long, value_name = "ACTION", default_value = "keep")]
+    slide_labels: SlideLabels,
+    /// What to do with encapsulated PDF and CDA documents: keep, replace (with a placeholder) or remove
+    #[arg(long, value_name = "ACTION", default_value = "replace")]
+    documents: Documents,
+    /// Print the tags each file would have removed, replaced or added and the regions
+    /// that would be filled, without writing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Open a pseudonym table, encrypted with the key in `key_file` if given.
+fn open_pseudonyms(
+    path: &std::path::Path,
+    key_file: Option<&std::path::Path>,
+) -> Result<PseudonymTable, dcm_redact::DCMRedactErrors> {
+    let key = key_file.map(load_or_create_key).transpose()?;
+    PseudonymTable::open(path, key)
+}
+
+/// Headless `--batch` mode; returns the process exit code.
+fn run_batch_cli(cli: Cli) -> i32 {
+    let Some(input_dir) = cli.input else {
+        return 2;
+    };
+    let stow = match &cli.stow {
+        None => None,
+        Some(url) => match Server::new(url, cli.token.as_deref().unwrap_or_default()) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        },
+    };
+    if cli.output.is_none() && cli.send.is_none() && stow.is_none() {
+        eprintln!("--batch needs --output, --send or --stow (--output can go with either)");
+        return 2;
+    }
+    if cli.pseudonyms.is_some() && cli.pseudonym_key.is_none() && !cli.pseudonym_plaintext {
+        eprintln!(
+            "--pseudonyms needs --pseudonym-key to encrypt the table, or --pseudonym-plaintext to keep it as plain CSV"
+        );
+        return 2;
+    }
+    let mut regions: Vec<Region> = cli
+        .rect
+        .into_iter()
+        .map(|r| Region::all_frames(Shape::Rect(r)))
+        .collect();
+    for path in &cli.template {
+        match Template::load(path) {
+            Ok(t) => regions.extend(t.regions),
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        }
+    }
+    for path in &cli.regions {
+        match load_regions(path) {
+            Ok(r) => regions.extend(r),
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        }
+    }
+    let borders = Borders {
+        top: cli.top_rows,
+        bottom: cli.bottom_rows,
+        percent: cli.border_percent,
+    };
+    let plugins = match cli.plugins.clone().or_else(default_plugins_path) {
+        None => Plugins::default(),
+        Some(path) => match load_plugins(&path) {
+            Ok(plugins) => plugins,
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        },
+    };
+    let rules = match cli.profiles.clone().or_else(default_profiles_path) {
+        None => Vec::new(),
+        Some(path) => match load_profile_rules(&path) {
+            Ok(rules) => rules,
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        },
+    };
+    #[cfg(feature = "scripting")]
+    let script = match &cli.script {
+        None => None,
+        Some(path) => match dcm_redact::script::FileScript::load(path) {
+            Ok(script) => Some(std::sync::Arc::new(script)),
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        },
+    };
+    #[cfg(not(feature = "scripting"))]
+    if cli.script.is_some() {
+        eprintln!("--script needs dcm-redact built with the `scripting` feature");
+        return 2;
+    }
+    if regions.is_empty()
+        && borders.is_empty()
+        && !cli.ultrasound_mask
+        && !cli.vendor_presets
+        && !plugins.has_detectors()
+        && cli.script.is_none()
+        && rules.iter().all(|r| r.regions.is_empty())
+        && cli.deface == Deface::Off
+        && cli.slide_labels == SlideLabels::Keep
+    {
+        eprintln!(
+            "--batch needs at least one --rect, --template, --regions, --top-rows, \
+             --bottom-rows, --border-percent, --ultrasound-mask, --vendor-presets, \
+             --deface, --slide-labels or --script, or a plugin detector or profile rule \
+             with a template"
+        );
+        return 2;
+    }
+    if cli.keep_inside && regions.is_empty() {
+        eprintln!("--keep-inside needs regions to keep: --rect, --template or --regions");
+        return 2;
+    }
+    let presets = if cli.vendor_presets {
+        let Some(path) = cli.presets.clone().or_else(default_presets_path) else {
+            eprintln!("No configuration directory; pass --presets FILE");
+            return 2;
+        };
+        match load_presets(&path) {
+            Ok(presets) => presets,
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    let profile = match &cli.profile {
+        None => Profile::default(),
+        Some(name) => {
+            let Some(path) = cli.profiles.clone().or_else(default_profiles_path) else {
+                eprintln!("No configuration directory; pass --profiles FILE");
+                return 2;
+            };
+            match find_profile(&path, name) {
+                Ok(profile) => profile,
+                Err(e) => {
+                    eprintln!("{e}");
+                    return 2;
+                }
+            }
+        }
+    };
+    let pseudonyms = match &cli.pseudonyms {
+        None => None,
+        Some(path) => match open_pseudonyms(path, cli.pseudonym_key.as_deref()) {
+            Ok(table) => Some(table),
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        },
+    };
+    // Output only sent to a PACS, uploaded to object storage or zipped is written to a
+    // temporary folder, removed afterwards
+    let upload_to = cli
+        .output
+        .as_deref()
+        .and_then(cloud::as_url)
+        .map(str::to_string);
+    let zip_to = cli
+        .output
+        .clone()
+        .filter(|path| upload_to.is_none() && archive::is_zip(path));
+    let (output_dir, spooled) = match cli.output {
+        Some(dir) if upload_to.is_none() && zip_to.is_none() => (dir, false),
+        _ => match pacs::spool_dir() {
+            Ok(dir) => (dir, true),
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        },
+    };
+    // Object storage input is downloaded, and a ZIP extracted, to a temporary folder first
+    let url = cloud::as_url(&input_dir);
+    let downloaded = match url {
+        None if !archive::is_zip(&input_dir) || !input_dir.is_file() => None,
+        _ => {
+            let dir = match pacs::spool_dir() {
+                Ok(dir) => dir,
+                Err(e) => {
+                    eprintln!("{e}");
+                    return 2;
+                }
+            };
+            let fetched = match url {
+                Some(url) => cloud::download(url, &dir),
+                None => archive::extract(&input_dir, &dir),
+            };
+            match fetched {
+                Ok(files) => {
+                    eprintln!(
+                        "{} {} files from {}",
+                        if url.is_some() {
+                            "Downloaded"
+                        } else {
+                            "Extracted"
+                        },
+                        files.len(),
+                        input_dir.display()
+                    );
+                    Some(dir)
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    let _ = std::fs::remove_dir_all(&dir);
+                    if spooled {
+                        let _ = std::fs::remove_dir_all(&output_dir);
+                    }
+                    return 2;
+                }
+            }
+        }
+    };
+    let input_dir = downloaded.clone().unwrap_or(input_dir);
+    let job = BatchJob {
+        input_dir,
+        output_dir,
+        regions,
+        borders,
+        keep_inside: cli.keep_inside,
+        crop: cli.crop,
+        ultrasound_mask: cli.ultrasound_mask,
+        presets,
+        plugins,
+        #[cfg(feature = "scripting")]
+        script,
+        deface: cli.deface,
+        fill: cli.fill,
+        deidentify: !cli.keep_metadata,
+        profile,
+        rules,
+        strip_overlays: !cli.keep_overlays,
+        remove_private: !cli.keep_private,
+        private_whitelist: cli.keep_private_creator,
+        transfer_syntax: cli.transfer_syntax,
+        regenerate_uids: !cli.keep_uids,
+        uid_map: cli.uid_map,
+        uid_store: cli.uid_store,
+        pseudonyms,
+        name_pattern: cli.name_pattern,
+        threads: cli.threads,
+        audit_log: cli
+            .audit_log
+            .map(|path| AuditLog::new(path, cli.operator.unwrap_or_else(default_operator))),
+        regions_sidecar: cli.regions_sidecar,
+        write_seg: cli.seg,
+        secondary_capture: cli.secondary_capture,
+        stamp: cli.stamp.map(|text| Stamp {
+            text,
+            corner: cli.stamp_corner,
+            size: cli.stamp_size,
+        }),
+        slide_labels: cli.slide_labels,
+        documents: cli.documents,
+        dry_run: cli.dry_run,
+    };
+
+    let mut written = Vec::new();
+    let result = run_batch(&job, |event| {
+        let BatchEvent::Finished(outcome) = event else {
+            return;
+        };
+        let progress = format!("[{}/{}]", outcome.done, outcome.total);
+        match &outcome.result {
+            Ok(()) if outcome.removed => {
+                eprintln!("{progress} removed {}", outcome.input.display())
+            }
+            Ok(()) => match &outcome.skipped {
+                Some(reason) => {
+                    eprintln!("{progress} skipped {}: {reason}", outcome.input.display())
+                }
+                None => eprintln!("{progress} ok      {}", outcome.input.display()),
+            },
+            Err(e) => eprintln!("{progress} FAILED  {}: {e}", outcome.input.display()),
+        }
+        if let Some(report) = &outcome.report {
+            println!("{report}");
+        }
+        if outcome.result.is_ok()
+            && !outcome.removed
+            && outcome.skipped.is_none()
+            && outcome.report.is_none()
+        {
+            written.push(outcome.output.clone());
+            let seg = seg::seg_path(&outcome.output);
+            if job.write_seg && seg.is_file() {
+                written.push(seg);
+            }
+        }
+    });
+
+    let code = match result {
+        Ok(summary) => {
+            eprintln!("{} succeeded, {} failed", summary.succeeded, summary.failed);
+            if summary.removed > 0 {
+                eprintln!(
+                    "{} slide label or overview images or documents left out",
+                    summary.removed
+                );
+            }
+            if summary.skipped > 0 {
+                eprintln!("{} skipped by the script", summary.skipped);
+            }
+            if job.dry_run {
+                eprintln!("Dry run: no files were written");
+            }
+            if summary.failed > 0 { 1 } else { 0 }
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    };
+    let report = |path: &std::path::Path, result| match result {
+        Ok(()) => eprintln!("sent    {}", path.display()),
+        Err(e) => eprintln!("FAILED  {}: {e}", path.display()),
+    };
+    let sent = match (&cli.send, &stow) {
+        _ if written.is_empty() => None,
+        (Some(remote), _) => {
+            eprintln!("Sending {} files to {remote}", written.len());
+            Some(pacs::store(remote, &cli.calling_ae, &written, report))
+        }
+        (None, Some(server)) => {
+            eprintln!("Posting {} files to {server}", written.len());
+            Some(dicomweb::store(server, &written, report))
+        }
+        (None, None) => None,
+    };
+    let code = match sent {
+        Some(Ok(sent)) => {
+            for warning in &sent.warnings {
+                eprintln!("WARNING {warning}");
+            }
+            eprintln!("{} sent, {} not sent", sent.sent, sent.failed);
+            if sent.failed > 0 { 1 } else { code }
+        }
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            1
+        }
+        None => code,
+    };
+    // Files from a CD/DVD export get a DICOMDIR of their own
+    let code = if job.input_dir.join("DICOMDIR").is_file() && !job.dry_run {
+        match write_dicomdir(&job.output_dir) {
+            Ok(0) => code,
+            Ok(n) => {
+                eprintln!("Wrote a DICOMDIR listing {n} files");
+                written.push(job.output_dir.join("DICOMDIR"));
+                code
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                1
+            }
+        }
+    } else {
+        code
+    };
+    let code = match &zip_to {
+        Some(path) if !written.is_empty() => {
+            match archive::create(path, &job.output_dir, &written) {
+                Ok(()) => {
+                    eprintln!("Wrote {} files to {}", written.len(), path.display());
+                    code
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    1
+                }
+            }
+        }
+        _ => code,
+    };
+    let code = match &upload_to {
+        Some(url) if !written.is_empty() => {
+            eprintln!("Uploading {} files to {url}", written.len());
+            let uploaded = cloud::upload(
+                &written,
+                &job.output_dir,
+                url,
+                |path, result| match result {
+                    Ok(()) => eprintln!("uploaded {}", path.display()),
+                    Err(e) => eprintln!("FAILED   {}: {e}", path.display()),
+                },
+            );
+            match uploaded {
+                Ok(uploaded) => {
+                    eprintln!(
+                        "{} uploaded, {} not uploaded",
+                        uploaded.sent, uploaded.failed
+                    );
+                    if uploaded.failed > 0 { 1 } else { code }
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    1
+                }
+            }
+        }
+        _ => code,
+    };
+    if spooled {
+        let _ = std::fs::remove_dir_all(&job.output_dir);
+    }
+    if let Some(dir) = downloaded {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+    code
+}
 
-type Gray16Image = ImageBuffer<Luma<u16>, Vec<u16>>;
+/// System fonts with Japanese, Korean and Chinese glyphs, which egui's built-in fonts
+/// lack. Every one found is added as a fallback, so names in those scripts display.
+const CJK_FONTS: [&str; 8] = [
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/System/Library/Fonts/AppleSDGothicNeo.ttc",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+    "C:\\Windows\\Fonts\\malgun.ttf",
+    "C:\\Windows\\Fonts\\msyh.ttc",
+];
 
-#[derive(Debug)]
-enum DCMRedactErrors {
-    ValueError(String),
+fn install_fallback_fonts(ctx: &egui::Context) {
+    let mut fonts = egui::FontDefinitions::default();
+    for path in CJK_FONTS {
+        let Ok(bytes) = std::fs::read(path) else {
+            continue;
+        };
+        fonts
+            .font_data
+            .insert(path.to_string(), egui::FontData::from_owned(bytes));
+        for family in [egui::FontFamily::Proportional, egui::FontFamily::Monospace] {
+            fonts
+                .families
+                .entry(family)
+                .or_default()
+                .push(path.to_string());
+        }
+    }
+    ctx.set_fonts(fonts);
 }
 
 fn max_display_dim_from_env() -> u32 {
@@ -30,61 +715,6 @@ fn max_display_dim_from_env() -> u32 {
         .unwrap_or(8192)
 }
 
-fn write_dynamic_image_to_dicom(
-    file_obj: &mut dicom::object::FileDicomObject<InMemDicomObject>,
-    img: &Gray16Image,
-    save_path: &PathBuf,
-) {
-    let raw_u16 = SmallVec::from_vec(img.to_vec());
-
-    file_obj.put(DataElement::new(
-        tags::BITS_ALLOCATED,
-        VR::US,
-        PrimitiveValue::from(16u16),
-    ));
-    file_obj.put(DataElement::new(
-        tags::BITS_STORED,
-        VR::US,
-        PrimitiveValue::from(16u16),
-    ));
-    file_obj.put(DataElement::new(
-        tags::HIGH_BIT,
-        VR::US,
-        PrimitiveValue::from(15u16),
-    ));
-    file_obj.put(DataElement::new(
-        tags::PIXEL_REPRESENTATION,
-        VR::US,
-        PrimitiveValue::from(0u16),
-    )); // unsigned
-    file_obj.put(DataElement::new(
-        tags::PHOTOMETRIC_INTERPRETATION,
-        VR::CS,
-        PrimitiveValue::from("MONOCHROME2"),
-    ));
-    file_obj.put(DataElement::new(
-        tags::PIXEL_DATA,
-        VR::OW,
-        PrimitiveValue::U16(raw_u16),
-    ));
-
-    let _ = file_obj.write_to_file(save_path);
-}
-
-/// Turn pixels in the given (x0..x1, y0..y1) rectangle to black (in-place).
-fn blacken_rect(img: &mut Gray16Image, x0: u32, y0: u32, x1: u32, y1: u32) {
-    let (w, h) = img.dimensions();
-    let x0 = x0.min(w.saturating_sub(1));
-    let y0 = y0.min(h.saturating_sub(1));
-    let x1 = x1.min(w);
-    let y1 = y1.min(h);
-    for y in y0..y1 {
-        for x in x0..x1 {
-            img.put_pixel(x, y, Luma([0u16]));
-        }
-    }
-}
-
 /// Compute a display size (w,h) that fits within max_dim while preserving aspect ratio.
 /// If already within bounds, returns original.
 fn fit_within_max_dim(w: u32, h: u32, max_dim: u32) -> (u32, u32) {
@@ -100,22 +730,29 @@ fn fit_within_max_dim(w: u32, h: u32, max_dim: u32) -> (u32, u32) {
 
 /// Convert a full-res Gray16 image to a *downscaled* ColorImage for display (<= max_dim).
 /// We keep aspect ratio and apply MONOCHROME1 inversion if needed.
-/// For display, we map u16 -> u8 via high byte (val >> 8) (simple but fast).
+/// For display, we map stored values -> u8 through `lut` if given, otherwise via
+/// their top 8 significant bits (val >> (bits_stored - 8)) (simple but fast).
 fn gray16_to_display_color_image(
     full: &Gray16Image,
     display_w: u32,
     display_h: u32,
     photometric: Option<&str>,
+    bits_stored: u16,
+    lut: Option<&[u8]>,
 ) -> ColorImage {
     // Resize full-res gray -> display gray (keeps black boxes crisp w/ Nearest)
     let resized: Gray16Image =
         image::imageops::resize(full, display_w, display_h, FilterType::Nearest);
 
     let invert = matches!(photometric, Some("MONOCHROME1"));
+    let shift = bits_stored.saturating_sub(8);
 
     let mut pixels = Vec::with_capacity((display_w * display_h) as usize);
     for p in resized.pixels() {
-        let mut v = (p[0] >> 8) as u8;
+        let mut v = match lut {
+            Some(lut) => lut[p[0] as usize],
+            None => (p[0] >> shift).min(255) as u8,
+        };
         if invert {
             v = 255u8.saturating_sub(v);
         }
@@ -128,371 +765,6669 @@ fn gray16_to_display_color_image(
     }
 }
 
-struct App {
-    // Full-res source image (mutable for edits)
-    gray_img: Option<Gray16Image>,
-
-    // Display-only (downscaled) data
-    color_img: Option<ColorImage>,
-    tex: Option<egui::TextureHandle>,
-    display_dims: Option<(u32, u32)>, // width,height of displayed texture (<= 8192)
+/// Convert a full-res RGB frame to a downscaled ColorImage for display.
+fn rgb8_to_display_color_image(full: &Rgb8Image, display_w: u32, display_h: u32) -> ColorImage {
+    let resized: Rgb8Image =
+        image::imageops::resize(full, display_w, display_h, FilterType::Nearest);
+    ColorImage::from_rgb([display_w as usize, display_h as usize], resized.as_raw())
+}
 
-    // For drag-to-select
-    drag_start_px: Option<[u32; 2]>,
-    drag_start_screen: Option<Pos2>,
-    drag_current_screen: Option<Pos2>,
+fn frame_to_display_color_image(
+    frame: &Frame,
+    display_w: u32,
+    display_h: u32,
+    photometric: Option<&str>,
+    bits_stored: u16,
+    lut: Option<&[u8]>,
+) -> ColorImage {
+    match frame {
+        Frame::Gray(img) => {
+            gray16_to_display_color_image(img, display_w, display_h, photometric, bits_stored, lut)
+        }
+        Frame::Rgb(img) => rgb8_to_display_color_image(img, display_w, display_h),
+    }
+}
 
-    // Bookkeeping
-    opened_path: Option<PathBuf>,
-    fit_scale: f32, // UI zoom (1.0 = native display texture)
-    is_dcm: bool,
-    dcm: Option<FileDicomObject<InMemDicomObject>>,
-    last_error: Option<String>,
-    photometric_interpretation: Option<String>,
-    max_display_dim: u32,
+/// 64-point outline of the ellipse inscribed in `rect`.
+fn ellipse_points(rect: Rect) -> Vec<Pos2> {
+    (0..64)
+        .map(|i| {
+            let t = i as f32 / 64.0 * std::f32::consts::TAU;
+            rect.center() + Vec2::new(t.cos() * rect.width(), t.sin() * rect.height()) / 2.0
+        })
+        .collect()
 }
 
-impl App {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self {
-            gray_img: None,
-            color_img: None,
-            tex: None,
-            display_dims: None,
-            drag_start_px: None,
-            drag_start_screen: None,
-            drag_current_screen: None,
-            opened_path: None,
-            fit_scale: 1.0,
-            is_dcm: false,
-            dcm: None,
-            last_error: None,
-            photometric_interpretation: None,
-            max_display_dim: max_display_dim_from_env(),
-        }
+/// Side of the loupe, in screen points.
+const LOUPE_SIZE: f32 = 180.0;
+
+/// Paint `texture` (shown at `img_rect`) magnified `zoom` times around `pointer`, in a
+/// square beside it that stays inside `canvas`.
+fn loupe(
+    painter: &egui::Painter,
+    canvas: Rect,
+    img_rect: Rect,
+    texture: egui::TextureId,
+    pointer: Pos2,
+    zoom: f32,
+) {
+    let offset = 24.0;
+    let mut min = pointer + Vec2::splat(offset);
+    if min.x + LOUPE_SIZE > canvas.right() {
+        min.x = pointer.x - offset - LOUPE_SIZE;
     }
+    if min.y + LOUPE_SIZE > canvas.bottom() {
+        min.y = pointer.y - offset - LOUPE_SIZE;
+    }
+    let rect = Rect::from_min_size(min, Vec2::splat(LOUPE_SIZE));
+    // The patch of the image under the pointer, as texture coordinates; parts past
+    // the image edges show the background
+    let half = Vec2::splat(LOUPE_SIZE / zoom / 2.0);
+    let to_uv = |p: Pos2| {
+        Pos2::new(
+            (p.x - img_rect.left()) / img_rect.width(),
+            (p.y - img_rect.top()) / img_rect.height(),
+        )
+    };
+    let patch = Rect::from_min_max(pointer - half, pointer + half);
+    let visible = patch.intersect(img_rect);
+    let scale = LOUPE_SIZE / patch.width();
+    let shown = Rect::from_min_max(
+        rect.min + (visible.min - patch.min) * scale,
+        rect.min + (visible.max - patch.min) * scale,
+    );
+    painter.rect_filled(rect, 0.0, egui::Color32::BLACK);
+    painter.image(
+        texture,
+        shown,
+        Rect::from_min_max(to_uv(visible.min), to_uv(visible.max)),
+        egui::Color32::WHITE,
+    );
+    // Crosshair on the pixel under the pointer
+    let stroke = Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(255, 255, 0, 160));
+    painter.hline(rect.x_range(), rect.center().y, stroke);
+    painter.vline(rect.center().x, rect.y_range(), stroke);
+    painter.rect_stroke(rect, 0.0, Stroke::new(1.5, egui::Color32::GRAY));
+}
 
-    fn load_dcm(&mut self, path: &PathBuf) -> Result<Gray16Image, DCMRedactErrors> {
-        // Open DICOM file
-        let file = dicom::object::open_file(path)
-            .map_err(|e| DCMRedactErrors::ValueError(format!("Failed to open DICOM file: {e}")))?;
-        self.dcm = Some(file);
-        self.is_dcm = true;
-
-        if let Some(dcm) = self.dcm.as_ref() {
-            // Check Bits Allocated
-            let bits_allocated: u16 = dcm
-                .element(tags::BITS_ALLOCATED)
-                .map_err(|_| DCMRedactErrors::ValueError("Missing BITS_ALLOCATED tag".to_string()))?
-                .to_int()
-                .map_err(|_| {
-                    DCMRedactErrors::ValueError("Invalid BITS_ALLOCATED value".to_string())
-                })?;
-
-            if bits_allocated != 16u16 && bits_allocated != 12u16 {
-                return Err(DCMRedactErrors::ValueError(format!(
-                    "Mismatched BITS_ALLOCATED, expected 12 or 16 got {bits_allocated}"
-                )));
-            }
-
-            // Photometric Interpretation
-            self.photometric_interpretation = Some(
-                dcm.element(tags::PHOTOMETRIC_INTERPRETATION)
-                    .map_err(|_| {
-                        DCMRedactErrors::ValueError(
-                            "Missing PHOTOMETRIC_INTERPRETATION tag".to_string(),
-                        )
-                    })?
-                    .to_str()
-                    .map_err(|_| {
-                        DCMRedactErrors::ValueError(
-                            "Invalid PHOTOMETRIC_INTERPRETATION value (not UTF-8)".to_string(),
-                        )
-                    })?
-                    .into_owned(),
-            );
+/// Longest side of a thumbnail in the folder strip, in pixels.
+const THUMBNAIL_DIM: u32 = 96;
 
-            if let Some(v) = self.photometric_interpretation.as_ref() {
-                if v != "MONOCHROME1" && v != "MONOCHROME2" {
-                    return Err(DCMRedactErrors::ValueError(format!(
-                        "Mismatched PHOTOMETRIC_INTERPRETATION, expected MONOCHROME1 or MONOCHROME2 got {v}"
-                    )));
-                }
-            }
-        }
+/// Screen-space radius of the resize handles on the selected region.
+const HANDLE_RADIUS: f32 = 5.0;
+
+/// Window title, after the name of the open file.
+const APP_TITLE: &str = "Box Blackout (drag to blacken)";
 
-        // Decode pixel data -> DynamicImage -> full-res Gray16
-        let dyn_img = self
-            .dcm
-            .as_mut()
-            .ok_or_else(|| DCMRedactErrors::ValueError("Missing DICOM object".to_string()))?
-            .decode_pixel_data()
-            .map_err(|e| DCMRedactErrors::ValueError(format!("Failed to decode pixel data: {e}")))?
-            .to_dynamic_image(0)
-            .map_err(|e| {
-                DCMRedactErrors::ValueError(format!("Failed to convert to DynamicImage: {e}"))
-            })?;
+/// How often unsaved work is snapshotted to the recovery file.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
 
-        Ok(dyn_img.to_luma16())
+/// Interface sizes offered in the settings.
+const UI_SCALES: [f32; 7] = [0.75, 1.0, 1.25, 1.5, 2.0, 2.5, 3.0];
+
+fn remove_recovery_file(path: &std::path::Path) {
+    if let Err(e) = std::fs::remove_file(path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        eprintln!("Failed to remove {}: {e}", path.display());
     }
+}
 
-    fn load_image(&mut self, ctx: &egui::Context, path: PathBuf) -> anyhow::Result<()> {
-        // Load full-res gray image (for editing/saving)
-        let full_gray: Gray16Image = match path.extension().and_then(|e| e.to_str()) {
-            Some(ext) if ext.eq_ignore_ascii_case("dcm") => {
-                let g = self
-                    .load_dcm(&path)
-                    .map_err(|e| anyhow!("Invalid DICOM: {:?}", e))?;
-                g
-            }
-            _ => {
-                self.is_dcm = false;
-                self.dcm = None;
-                self.photometric_interpretation = None;
+/// A decoded image and its first display frame, built off the UI thread.
+struct LoadedImage {
+    path: PathBuf,
+    redactor: Redactor,
+    voi_presets: Vec<VoiPreset>,
+    rescale: Rescale,
+    display_dims: (u32, u32),
+    color_img: ColorImage,
+}
 
-                let dyn_img = image::open(&path)
-                    .with_context(|| format!("Failed to open image: {}", path.display()))?;
-                dyn_img.to_luma16()
-            }
-        };
+/// An image being decoded on a worker thread.
+struct Loading {
+    path: PathBuf,
+    rx: mpsc::Receiver<anyhow::Result<LoadedImage>>,
+    /// Tab to return to if this load opened a new tab and fails.
+    new_tab_from: Option<usize>,
+}
 
-        // Determine display size <= 8192 while keeping aspect ratio
-        let (full_w, full_h) = full_gray.dimensions();
-        let (disp_w, disp_h) = fit_within_max_dim(full_w, full_h, self.max_display_dim);
+/// Open and decode `path` and render its first frame for display. Files from a
+/// folder/DICOMDIR (`dicom`) are DICOM even without a .dcm extension.
+fn decode_image(path: PathBuf, dicom: bool, max_display_dim: u32) -> anyhow::Result<LoadedImage> {
+    let redactor = if dicom {
+        Redactor::open_dicom(&path)?
+    } else {
+        Redactor::open(&path)?
+    };
 
-        // Build display ColorImage from full-res gray
-        let color_img = gray16_to_display_color_image(
-            &full_gray,
-            disp_w,
-            disp_h,
-            self.photometric_interpretation.as_deref(),
-        );
+    // Determine display size <= 8192 while keeping aspect ratio
+    let (full_w, full_h) = redactor.dimensions();
+    let display_dims = fit_within_max_dim(full_w, full_h, max_display_dim);
 
-        // Update state
-        self.gray_img = Some(full_gray);
-        self.display_dims = Some((disp_w, disp_h));
-        self.color_img = Some(color_img.clone());
-        self.tex = Some(ctx.load_texture("image", color_img, egui::TextureOptions::LINEAR));
-        self.opened_path = Some(path);
-        self.fit_scale = 1.0;
+    // The file's first stored window or VOI LUT, if it has one
+    let voi_presets = redactor.voi_presets();
+    let rescale = redactor.rescale();
+    let lut = voi_presets
+        .first()
+        .map(|preset| display_lut(rescale, &preset.voi));
 
-        Ok(())
-    }
+    // Build display ColorImage from the full-res first frame
+    let color_img = frame_to_display_color_image(
+        redactor.frame(0),
+        display_dims.0,
+        display_dims.1,
+        redactor.photometric_interpretation(),
+        redactor.bits_stored(),
+        lut.as_deref(),
+    );
+    Ok(LoadedImage {
+        path,
+        redactor,
+        voi_presets,
+        rescale,
+        display_dims,
+        color_img,
+    })
+}
 
-    fn refresh_texture(&mut self, ctx: &egui::Context) {
-        if let (Some(ci), Some(tex)) = (self.color_img.as_ref(), self.tex.as_mut()) {
-            tex.set(ci.clone(), egui::TextureOptions::LINEAR);
-        } else if let Some(ci) = self.color_img.clone() {
-            self.tex = Some(ctx.load_texture("image", ci, egui::TextureOptions::LINEAR));
+/// Where a file of a multi-file save stands.
+enum QueueStatus {
+    Queued,
+    Running,
+    Done(PathBuf), // written to this path
+    Removed,       // slide label, overview image or document, left out
+    Failed(String),
+    Cancelled,
+}
+
+/// Progress message from the thread running a multi-file save.
+enum QueueEvent {
+    Started(PathBuf),
+    Finished(PathBuf, Result<Option<PathBuf>, String>), // None: left out
+}
+
+/// A multi-file save running in the background, shown in the "Batch queue" window.
+struct BatchQueue {
+    job: BatchJob, // kept to retry failed files with the same settings
+    files: Vec<(PathBuf, QueueStatus)>,
+    control: Arc<BatchControl>,
+    rx: mpsc::Receiver<QueueEvent>, // disconnected once the run is over
+    running: bool,
+    open: bool,
+    zip_to: Option<PathBuf>, // outputs are zipped here from the temporary job.output_dir
+}
+
+impl Drop for BatchQueue {
+    fn drop(&mut self) {
+        if self.zip_to.is_some() {
+            let _ = std::fs::remove_dir_all(&self.job.output_dir);
         }
     }
+}
 
-    fn rebuild_display_from_full(&mut self, ctx: &egui::Context) {
-        let (full, (disp_w, disp_h)) = match (self.gray_img.as_ref(), self.display_dims) {
-            (Some(f), Some(d)) => (f, d),
-            _ => return,
-        };
-
-        let ci = gray16_to_display_color_image(
-            full,
-            disp_w,
-            disp_h,
-            self.photometric_interpretation.as_deref(),
-        );
+/// A ZIP opened as a folder: the archive, and the temporary folder it was extracted
+/// to, removed once another folder is opened or the app closes.
+struct ZipSource {
+    archive: PathBuf,
+    dir: PathBuf,
+}
 
-        self.color_img = Some(ci);
-        self.refresh_texture(ctx);
+impl Drop for ZipSource {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
     }
+}
 
-    fn apply_blacken(&mut self, rect_px: [u32; 4], ctx: &egui::Context) {
-        if let Some(img) = self.gray_img.as_mut() {
-            blacken_rect(img, rect_px[0], rect_px[1], rect_px[2], rect_px[3]);
-            // Rebuild the *downscaled display* from the full-res edited data
-            self.rebuild_display_from_full(ctx);
-        }
+impl BatchQueue {
+    fn count(&self, f: impl Fn(&QueueStatus) -> bool) -> usize {
+        self.files.iter().filter(|(_, s)| f(s)).count()
     }
 
-    /// Map a screen point to FULL-RES image pixel coordinates, clamped,
-    /// given the on-screen rect of the displayed image.
-    ///
-    /// This works even though we display a downscaled texture because we use UVs
-    /// (relative position within the drawn rectangle) and apply them to full-res dims.
-    fn screen_to_pixel(&self, img_rect: Rect, p: Pos2) -> Option<[u32; 2]> {
-        let (w, h) = match self.gray_img.as_ref() {
-            Some(i) => i.dimensions(),
-            None => return None,
-        };
-        if !img_rect.contains(p) {
-            return None;
+    fn summary(&self) -> String {
+        let saved = self.count(|s| matches!(s, QueueStatus::Done(_)));
+        let failed = self.count(|s| matches!(s, QueueStatus::Failed(_)));
+        let cancelled = self.count(|s| matches!(s, QueueStatus::Cancelled));
+        let removed = self.count(|s| matches!(s, QueueStatus::Removed));
+        let mut summary = format!("{saved} files saved, {failed} failed");
+        if removed > 0 {
+            summary += &format!(", {removed} slide labels or documents left out");
         }
-
-        let uv = (
-            (p.x - img_rect.left()) / img_rect.width(),
-            (p.y - img_rect.top()) / img_rect.height(),
-        );
-        let x = (uv.0 * w as f32).floor().clamp(0.0, (w - 1) as f32) as u32;
-        let y = (uv.1 * h as f32).floor().clamp(0.0, (h - 1) as f32) as u32;
-        Some([x, y])
+        if cancelled > 0 {
+            summary += &format!(", {cancelled} cancelled");
+        }
+        summary
     }
 }
 
-impl eframe::App for App {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::TopBottomPanel::top("top").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                if ui.button("Open Image…").clicked() {
-                    if let Some(path) = rfd::FileDialog::new()
-                        .add_filter("Images", &["png", "jpg", "jpeg", "tiff", "tif", "dcm"])
-                        .pick_file()
-                    {
-                        if let Err(e) = self.load_image(ctx, path) {
-                            self.last_error = Some(e.to_string());
-                        }
-                    }
-                }
+/// Redact `files` with `job` on a worker thread, reporting progress to the returned
+/// receiver.
+fn spawn_batch(
+    ctx: &egui::Context,
+    job: BatchJob,
+    files: Vec<PathBuf>,
+    uids: UidRemapper,
+    control: Arc<BatchControl>,
+) -> mpsc::Receiver<QueueEvent> {
+    let (tx, rx) = mpsc::channel();
+    let ctx = ctx.clone();
+    std::thread::spawn(move || {
+        run_batch_files(&job, &files, &uids, &control, |event| {
+            let event = match event {
+                BatchEvent::Started(input) => QueueEvent::Started(input.to_path_buf()),
+                BatchEvent::Finished(outcome) => QueueEvent::Finished(
+                    outcome.input.clone(),
+                    match &outcome.result {
+                        Ok(()) => Ok((!outcome.removed).then(|| outcome.output.clone())),
+                        Err(e) => Err(e.to_string()),
+                    },
+                ),
+            };
+            // The receiver is gone if the queue window was closed
+            let _ = tx.send(event);
+            ctx.request_repaint();
+        });
+        ctx.request_repaint();
+    });
+    rx
+}
 
-                if self.last_error.is_some() {
-                    let mut dismiss = false;
+/// An in-progress move or resize of a staged region.
+struct RegionDrag {
+    index: usize,
+    original: Shape,
+    from: RedactRect,         // bounds of `original`
+    start_px: [u32; 2],       // where the drag began
+    anchor: Option<[u32; 2]>, // resize: the corner that stays put; None = move
+}
 
-                    egui::Window::new("Error")
-                        .collapsible(false)
-                        .resizable(false)
-                        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
-                        .show(ctx, |ui| {
-                            if let Some(err) = &self.last_error {
-                                ui.label(err);
-                            }
-                            if ui.button("OK").clicked() {
-                                dismiss = true;
-                            }
-                        });
+struct App {
+    // Full-res source image + DICOM object (mutable for edits)
+    redactor: Option<Redactor>,
+    history: History,
+    current_frame: usize,
+    redact_all_frames: bool, // multi-frame: apply boxes to every frame, not just the shown one
 
-                    if dismiss {
-                        self.last_error = None;
-                    }
-                }
+    // Display-only (downscaled) data
+    color_img: Option<ColorImage>,
+    tex: Option<egui::TextureHandle>,
+    window: Option<Window>, // manual grayscale window/level, in modality units
+    voi_presets: Vec<VoiPreset>, // windows and VOI LUTs stored in the file
+    voi_preset: Option<usize>, // used when there's no manual window; None = top 8 bits
+    rescale: Rescale,
+    display_dims: Option<(u32, u32)>, // width,height of displayed texture (<= 8192)
+    orientation: Orientation,         // how the image is turned on screen
+    crop: Option<RedactRect>,         // part of the image saved
+    save_rotated: bool,               // ...and in the output
+    show_histogram: bool,
+    histogram: Option<Histogram>, // of the shown frame; None until needed
+    percentiles: (f64, f64),      // low/high percentile for windowing from the histogram
+    log_histogram: bool,
 
-                if ui.button("Save As…").clicked() {
-                    if let (Some(img), Some(path)) =
-                        (self.gray_img.as_ref(), self.opened_path.clone())
+    // For drag-to-select
+    shape_kind: ShapeKind,
+    shape_points: Vec<[u32; 2]>, // polygon/lasso vertices drawn so far (full-res px)
+    drag_start_px: Option<[u32; 2]>,
+    drag_start_screen: Option<Pos2>,
+    drag_current_screen: Option<Pos2>,
+
+    // Staged regions: drawn but not yet burned into the pixels
+    regions: Vec<Region>,
+    selected_region: Option<usize>,
+    fill: RedactFill,  // what Apply replaces region pixels with
+    keep_inside: bool, // Apply fills everything outside the regions instead
+    region_drag: Option<RegionDrag>,
+
+    // Bookkeeping
+    opened_path: Option<PathBuf>,
+    fit_scale: f32,          // UI zoom (1.0 = native display texture)
+    pan: Vec2,               // image center offset from the canvas center, in screen points
+    compare: bool,           // the original next to the redacted image
+    probe: Option<[u32; 2]>, // full-res pixel under the pointer, for the status bar
+    loupe: bool,             // magnified patch next to the pointer
+    loupe_zoom: f32,         // relative to the view's zoom
+    grid: bool,
+    grid_step: f64,                            // mm, or pixels without a pixel spacing
+    measuring: bool,                           // left drag measures instead of drawing
+    measure: Option<[[f64; 2]; 2]>,            // line measured, in full-res image coordinates
+    original_tex: Option<egui::TextureHandle>, // shown frame before any edit; None until needed
+    console: Console,
+    max_display_dim: u32,
+    deidentify: bool, // apply PS3.15 Basic Profile to DICOM metadata on save
+    profile: Profile,
+    profiles: Vec<Profile>, // from profiles.toml in the config directory
+    presets: Vec<Preset>,   // built in, and from presets.toml in the config directory
+    plugins: Plugins,       // from plugins.toml in the config directory
+    borders: Borders,       // the "Borders" quick action
+    strip_overlays: bool,   // drop overlays, curves and icon images on save
+    remove_private: bool,
+    private_whitelist: String, // comma-separated private creators to keep
+    transfer_syntax: OutputTransferSyntax,
+    regenerate_uids: bool, // new SOP Instance UID even when metadata is kept
+    uid_map_path: Option<PathBuf>, // CSV log of old -> new UIDs
+    audit_log_path: Option<PathBuf>, // JSON lines record of every save
+    operator: String,      // name in the audit log; empty = login name
+    regions_sidecar: bool, // write `.regions.json` next to every save
+    write_seg: bool,       // write the redaction mask as `.seg.dcm` next to every save
+    secondary_capture: bool, // save as a new Secondary Capture instance
+    stamp_on: bool,
+    stamp: Stamp,              // burned into saved frames while stamp_on
+    slide_labels: SlideLabels, // label/overview images in multi-file saves
+    documents: Documents,      // encapsulated PDF/CDA in multi-file saves
+    deface: Deface,            // head CT/MR series in multi-file saves
+    export_windowed: bool,     // "Export as image" bakes in the window
+    uid_remapper: UidRemapper, // shared by every file opened this session
+    pseudonyms: Option<PseudonymTable>,
+
+    // Folder / DICOMDIR browsing
+    instances: Vec<Instance>,
+    sort_order: SortOrder,
+    thumbnails: HashMap<PathBuf, Option<egui::TextureHandle>>, // None = couldn't load
+    folder_dir: Option<PathBuf>,
+    zip_source: Option<ZipSource>, // folder_dir was extracted from this
+    selected_instances: HashSet<PathBuf>, // Ctrl+click in the strip; empty = all
+    notice: Option<String>,        // latest outcome to show, e.g. of the last multi-file run
+
+    // `.dcmredact` project
+    project_path: Option<PathBuf>, // last opened or saved
+    project_pending: HashMap<PathBuf, ProjectFile>, // restored when the file is opened
+    recovery: Option<Project>,     // left by a crashed session, not restored or discarded yet
+    autosaved: Option<Project>,    // last written to the recovery file
+    last_autosave: Instant,
+
+    // Metadata panel
+    show_metadata: bool,
+    metadata_filter: String,
+    metadata_edit: Option<(dicom::core::Tag, String)>, // element being edited, and its text
+
+    // PHI scan of the header, by the rules in phi_rules.toml
+    phi_findings: Option<Vec<PhiFinding>>, // None = scan window closed
+    phi_scan_output: bool,                 // scan the header as it will be saved
+
+    // What saving would do, from "Preview anonymization"
+    preview: Option<DryRunReport>,
+
+    // Template manager
+    show_templates: bool,
+    templates: Vec<(PathBuf, Template)>,
+    template_name: String,
+
+    // De-identification review before saving
+    review_metadata: bool,
+    save_review: Option<(PathBuf, Vec<DiffRow>)>, // destination and original vs. output
+    review_changes_only: bool,
+
+    window_title: String, // last sent, so the title is only set when it changes
+
+    // Preferences kept between launches (see `settings.rs`)
+    language: String,                 // empty = the desktop's
+    languages: Vec<(String, String)>, // code and name, for the settings
+    theme: Theme,
+    ui_scale: f32, // egui zoom factor, on top of the system's scale
+    zoom_speed: f32,
+    zoom_to_pointer: bool,
+    window_size: Option<Vec2>,
+    show_settings: bool,
+
+    // Keyboard shortcuts and their settings window
+    shortcuts: Shortcuts,
+    show_shortcuts: bool,
+    capturing_shortcut: Option<Action>, // waiting for the keys to bind to this action
+
+    // Ctrl+C / Ctrl+V of the staged regions, with the size of the image they came from
+    region_clipboard: Option<(Vec<Region>, (u32, u32))>,
+
+    loading: Option<Loading>, // image being decoded for the active tab
+    batch_queue: Option<BatchQueue>, // the last "Apply regions to files" run
+    series: Option<SeriesView>, // the "Series volume" window
+
+    // Other open images; the entry at `active_tab` is an empty placeholder
+    tabs: Vec<Tab>,
+    active_tab: usize,
+
+    // Unsaved-changes protection
+    dirty: bool, // pixels or header edited since the file was opened or last saved
+    pending_action: Option<PendingAction>, // waiting for the operator to discard edits
+    close_confirmed: bool,
+
+    // Save destination, kept for the session
+    output_dir: Option<PathBuf>, // None = `redacted/` next to the source
+    name_pattern: String,        // output naming pattern; empty = source file name
+    overwrite_original: bool,    // don't ask before replacing the source file
+    confirm_overwrite: Option<PathBuf>,
+
+    // "Send to PACS"
+    send_node: PacsNode,
+    calling_ae: String, // for both windows
+    show_send: bool,
+    sending: Option<mpsc::Receiver<Result<String, String>>>, // notice or error once done
+
+    // "Query PACS"
+    show_query: bool,
+    query_node: PacsNode,
+    query: Query,
+    query_matches: Vec<(bool, Match)>, // ticked for retrieval, and the match
+    retrieve: Retrieve,
+    store_port: u16,                                // C-MOVE destination port
+    query_task: Option<mpsc::Receiver<QueryEvent>>, // search or retrieval running
+
+    // "Object storage"
+    show_cloud: bool,
+    download_url: String,
+    upload_url: String,
+    cloud_task: Option<mpsc::Receiver<CloudEvent>>, // download or upload running
+}
+
+/// An open image and its redaction state. The active tab lives in the `App` fields;
+/// the others are parked here while in the background.
+#[derive(Default)]
+struct Tab {
+    redactor: Option<Redactor>,
+    history: History,
+    current_frame: usize,
+    color_img: Option<ColorImage>,
+    tex: Option<egui::TextureHandle>,
+    window: Option<Window>,
+    voi_presets: Vec<VoiPreset>,
+    voi_preset: Option<usize>,
+    rescale: Rescale,
+    display_dims: Option<(u32, u32)>,
+    orientation: Orientation,
+    crop: Option<RedactRect>,
+    regions: Vec<Region>,
+    selected_region: Option<usize>,
+    opened_path: Option<PathBuf>,
+    fit_scale: f32,
+    pan: Vec2,
+    dirty: bool,
+}
+
+impl Tab {
+    fn has_unsaved_changes(&self) -> bool {
+        self.dirty || !self.regions.is_empty()
+    }
+}
+
+/// Longest side of each plane in the "Series volume" window, in points.
+const SERIES_VIEW_DIM: f32 = 320.0;
+
+/// A series stacked into a volume for the "Series volume" window. It works on its own
+/// copy of the slices, so the open file is left as it is.
+struct SeriesView {
+    paths: Vec<PathBuf>,
+    slices: Vec<Redactor>,
+    stack: Stack,
+    positions: [usize; 3], // shown position of each plane, in `MprPlane::ALL` order
+    textures: [Option<egui::TextureHandle>; 3],
+    // Rectangles drawn on the planes, with positions through the volume as frames
+    regions: Vec<(MprPlane, Region)>,
+    depth: usize, // positions a new rectangle covers; 0 = the whole volume
+    drag: Option<(MprPlane, [u32; 2])>, // plane and pixel a rectangle is dragged from
+    dirty: bool,  // slices edited since they were loaded or saved
+}
+
+/// One plane of the "Series volume" window, with its position slider; returns
+/// whether a shown position changed.
+fn series_plane(ui: &mut egui::Ui, series: &mut SeriesView, plane: MprPlane) -> bool {
+    let i = plane as usize;
+    let count = series.stack.positions(plane);
+    let mut moved = ui
+        .add(egui::Slider::new(&mut series.positions[i], 0..=count - 1).text(plane.label()))
+        .changed();
+    let Some(tex) = &series.textures[i] else {
+        return moved;
+    };
+    let (w, h) = series.stack.view_size(plane);
+    let scale = SERIES_VIEW_DIM / w.max(h) as f32;
+    let (rect, response) = ui.allocate_exact_size(
+        Vec2::new(w as f32, h as f32) * scale,
+        Sense::click_and_drag(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.image(
+        tex.id(),
+        rect,
+        Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+        egui::Color32::WHITE,
+    );
+    let to_px = |p: Pos2| {
+        let v = (p - rect.min) / scale;
+        [
+            v.x.clamp(0.0, w as f32) as u32,
+            v.y.clamp(0.0, h as f32) as u32,
+        ]
+    };
+    let to_screen = |x: u32, y: u32| rect.min + Vec2::new(x as f32, y as f32) * scale;
+
+    // Where the other two planes cross this one
+    let [axial, coronal, sagittal] = series.positions;
+    let (across, down) = match plane {
+        MprPlane::Axial => (sagittal as u32, coronal as u32),
+        MprPlane::Coronal => (sagittal as u32, series.stack.row_of_slice(plane, axial)),
+        MprPlane::Sagittal => (coronal as u32, series.stack.row_of_slice(plane, axial)),
+    };
+    let cross = Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(0, 200, 255, 120));
+    painter.line_segment([to_screen(across, 0), to_screen(across, h)], cross);
+    painter.line_segment([to_screen(0, down), to_screen(w, down)], cross);
+
+    let red = Stroke::new(1.5, egui::Color32::RED);
+    for (p, region) in &series.regions {
+        if *p == plane
+            && region.target_frames(count).contains(&series.positions[i])
+            && let Some(b) = region.shape.bounds()
+        {
+            painter.rect_stroke(
+                Rect::from_min_max(to_screen(b.x0, b.y0), to_screen(b.x1, b.y1)),
+                0.0,
+                red,
+            );
+        }
+    }
+
+    if response.drag_started()
+        && let Some(pos) = response.interact_pointer_pos()
+    {
+        series.drag = Some((plane, to_px(pos)));
+    }
+    if let Some((from_plane, from)) = series.drag
+        && from_plane == plane
+        && let Some(pos) = response.interact_pointer_pos()
+    {
+        let to = to_px(pos);
+        let r = RedactRect {
+            x0: from[0].min(to[0]),
+            y0: from[1].min(to[1]),
+            x1: from[0].max(to[0]),
+            y1: from[1].max(to[1]),
+        };
+        painter.rect_stroke(
+            Rect::from_min_max(to_screen(r.x0, r.y0), to_screen(r.x1, r.y1)),
+            0.0,
+            Stroke::new(1.5, egui::Color32::YELLOW),
+        );
+        if response.drag_stopped() {
+            series.drag = None;
+            if r.x0 < r.x1 && r.y0 < r.y1 {
+                let region = match series.depth {
+                    0 => Region::all_frames(Shape::Rect(r)),
+                    depth => {
+                        let first = series.positions[i].saturating_sub(depth / 2);
+                        Region {
+                            shape: Shape::Rect(r),
+                            frame: Some(first),
+                            last_frame: Some((first + depth - 1).min(count - 1)),
+                        }
+                    }
+                };
+                series.regions.push((plane, region));
+            }
+        }
+    }
+    if response.clicked()
+        && let Some(pos) = response.interact_pointer_pos()
+    {
+        // Move the other planes through the clicked point
+        let [a, b] = to_px(pos);
+        let last = |p: MprPlane| series.stack.positions(p) - 1;
+        let (a, b) = (a as usize, b as usize);
+        match plane {
+            MprPlane::Axial => {
+                series.positions[MprPlane::Sagittal as usize] = a.min(last(MprPlane::Sagittal));
+                series.positions[MprPlane::Coronal as usize] = b.min(last(MprPlane::Coronal));
+            }
+            MprPlane::Coronal | MprPlane::Sagittal => {
+                let other = if plane == MprPlane::Coronal {
+                    MprPlane::Sagittal
+                } else {
+                    MprPlane::Coronal
+                };
+                series.positions[other as usize] = a.min(last(other));
+                series.positions[MprPlane::Axial as usize] =
+                    series.stack.slice_at_row(plane, b as u32);
+            }
+        }
+        moved = true;
+    }
+    moved
+}
+
+/// A PACS the "Send to PACS" or "Query PACS" window talks to, as entered.
+struct PacsNode {
+    protocol: Protocol,
+    remote: String, // AE@host:port, for DIMSE; empty = not set up
+    url: String,    // base URL, for DICOMweb; empty = not set up
+    token: String,  // bearer token, for DICOMweb; never saved
+}
+
+impl Default for PacsNode {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::default(),
+            remote: String::new(),
+            url: String::new(),
+            token: std::env::var(dicomweb::TOKEN_VAR).unwrap_or_default(),
+        }
+    }
+}
+
+/// A [`PacsNode`] checked and ready to connect to.
+enum Node {
+    Dimse(Remote),
+    DicomWeb(Server),
+}
+
+impl PacsNode {
+    /// The protocol and address rows of a window's grid. `ae` is the example AE title.
+    fn fields(&mut self, ui: &mut egui::Ui, calling_ae: &mut String, ae: &str, id: &str) {
+        ui.label(tr!("node.protocol"));
+        egui::ComboBox::from_id_source(id)
+            .selected_text(self.protocol.label())
+            .show_ui(ui, |ui| {
+                for protocol in Protocol::ALL {
+                    ui.selectable_value(&mut self.protocol, protocol, protocol.label());
+                }
+            });
+        ui.end_row();
+        let (first, second) = match self.protocol {
+            Protocol::Dimse => (
+                (tr!("node.pacs"), &mut self.remote),
+                (tr!("node.calling_ae"), calling_ae),
+            ),
+            Protocol::DicomWeb => (
+                (tr!("node.url"), &mut self.url),
+                (tr!("node.token"), &mut self.token),
+            ),
+        };
+        let hint = match self.protocol {
+            Protocol::Dimse => format!("{ae}@pacs.example.org:104"),
+            Protocol::DicomWeb => "https://pacs.example.org/dicom-web".to_string(),
+        };
+        ui.label(first.0);
+        ui.add(
+            egui::TextEdit::singleline(first.1)
+                .hint_text(hint)
+                .desired_width(240.0),
+        );
+        ui.end_row();
+        ui.label(second.0);
+        let second_field = egui::TextEdit::singleline(second.1).desired_width(240.0);
+        ui.add(match self.protocol {
+            Protocol::Dimse => second_field.hint_text(pacs::DEFAULT_CALLING_AE),
+            Protocol::DicomWeb => second_field.password(true).hint_text(dicomweb::TOKEN_VAR),
+        });
+        ui.end_row();
+    }
+
+    fn node(&self) -> Result<Node, String> {
+        match self.protocol {
+            Protocol::Dimse => self.remote.parse().map(Node::Dimse),
+            Protocol::DicomWeb => Server::new(&self.url, &self.token).map(Node::DicomWeb),
+        }
+    }
+}
+
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Dimse(remote) => remote.fmt(f),
+            Node::DicomWeb(server) => server.fmt(f),
+        }
+    }
+}
+
+/// What an "Object storage" download or upload sends back when it is done.
+enum CloudEvent {
+    Downloaded(Result<Vec<PathBuf>, String>),
+    Uploaded(Result<String, String>), // notice or error
+}
+
+/// What a "Query PACS" search or retrieval sends back when it is done.
+enum QueryEvent {
+    Found(Result<Vec<Match>, String>),
+    Retrieved(Result<query::Retrieved, String>),
+}
+
+/// The level and matching keys of the "Query PACS" window, as rows of its grid.
+fn query_keys(ui: &mut egui::Ui, query: &mut Query) {
+    ui.label(tr!("query.level"));
+    egui::ComboBox::from_id_source("query_level")
+        .selected_text(query.level.label())
+        .show_ui(ui, |ui| {
+            for level in QueryLevel::ALL {
+                ui.selectable_value(&mut query.level, level, level.label());
+            }
+        });
+    ui.end_row();
+    let field = |ui: &mut egui::Ui, label: String, value: &mut String, hint: &str| {
+        ui.label(label);
+        ui.add(
+            egui::TextEdit::singleline(value)
+                .hint_text(hint)
+                .desired_width(240.0),
+        );
+        ui.end_row();
+    };
+    if query.level == QueryLevel::Series {
+        field(ui, tr!("query.study_uid"), &mut query.study_uid, "");
+        field(ui, tr!("query.modality"), &mut query.modality, "CT");
+        return;
+    }
+    field(ui, tr!("query.patient_id"), &mut query.patient_id, "");
+    field(
+        ui,
+        tr!("query.patient_name"),
+        &mut query.patient_name,
+        "DOE^JANE",
+    );
+    if query.level == QueryLevel::Study {
+        field(
+            ui,
+            tr!("query.study_date"),
+            &mut query.study_date,
+            "20240101-20240131",
+        );
+        field(ui, tr!("query.accession"), &mut query.accession, "");
+        field(ui, tr!("query.modality"), &mut query.modality, "CT");
+    }
+}
+
+/// Something that replaces the open image, held back while it has unsaved edits.
+enum PendingAction {
+    Reset,
+    Load(PathBuf),
+    OpenPaths(Vec<PathBuf>),
+    OpenFolder(PathBuf),
+    CloseTab(usize),
+    Close,
+}
+
+impl PendingAction {
+    /// What the action does to unsaved edits.
+    fn describe(&self) -> String {
+        match self {
+            PendingAction::Reset => tr!("unsaved.reset"),
+            PendingAction::CloseTab(_) => tr!("unsaved.close_tab"),
+            PendingAction::Load(_) | PendingAction::OpenPaths(_) | PendingAction::OpenFolder(_) => {
+                tr!("unsaved.open")
+            }
+            PendingAction::Close => tr!("unsaved.close"),
+        }
+    }
+}
+
+impl App {
+    fn new(
+        cc: &eframe::CreationContext<'_>,
+        paths: Vec<PathBuf>,
+        settings: Result<Settings, DCMRedactErrors>,
+    ) -> Self {
+        let mut app = Self {
+            redactor: None,
+            history: History::new(),
+            current_frame: 0,
+            redact_all_frames: false,
+            color_img: None,
+            tex: None,
+            window: None,
+            voi_presets: Vec::new(),
+            voi_preset: None,
+            rescale: Rescale::default(),
+            display_dims: None,
+            orientation: Orientation::default(),
+            crop: None,
+            save_rotated: false,
+            show_histogram: false,
+            histogram: None,
+            percentiles: (1.0, 99.0),
+            log_histogram: true,
+            shape_kind: ShapeKind::default(),
+            shape_points: Vec::new(),
+            drag_start_px: None,
+            drag_start_screen: None,
+            drag_current_screen: None,
+            regions: Vec::new(),
+            selected_region: None,
+            region_drag: None,
+            fill: RedactFill::default(),
+            keep_inside: false,
+            opened_path: None,
+            fit_scale: 1.0,
+            pan: Vec2::ZERO,
+            compare: false,
+            probe: None,
+            loupe: false,
+            loupe_zoom: 4.0,
+            grid: false,
+            grid_step: 10.0,
+            measuring: false,
+            measure: None,
+            original_tex: None,
+            console: Console::default(),
+            max_display_dim: max_display_dim_from_env(),
+            deidentify: true,
+            strip_overlays: true,
+            remove_private: true,
+            private_whitelist: String::new(),
+            transfer_syntax: OutputTransferSyntax::default(),
+            regenerate_uids: true,
+            uid_map_path: None,
+            audit_log_path: None,
+            operator: String::new(),
+            regions_sidecar: false,
+            write_seg: false,
+            secondary_capture: false,
+            stamp_on: false,
+            stamp: Stamp::default(),
+            slide_labels: SlideLabels::default(),
+            documents: Documents::default(),
+            deface: Deface::default(),
+            export_windowed: true,
+            uid_remapper: UidRemapper::new(),
+            pseudonyms: None,
+            instances: Vec::new(),
+            sort_order: SortOrder::default(),
+            thumbnails: HashMap::new(),
+            folder_dir: None,
+            zip_source: None,
+            selected_instances: HashSet::new(),
+            notice: None,
+            project_path: None,
+            project_pending: HashMap::new(),
+            recovery: None,
+            autosaved: None,
+            last_autosave: Instant::now(),
+            show_metadata: false,
+            phi_findings: None,
+            phi_scan_output: false,
+            preview: None,
+            metadata_filter: String::new(),
+            metadata_edit: None,
+            show_templates: false,
+            templates: Vec::new(),
+            template_name: String::new(),
+            review_metadata: true,
+            save_review: None,
+            review_changes_only: true,
+            dirty: false,
+            shortcuts: Shortcuts::default(),
+            window_title: APP_TITLE.to_string(),
+            language: String::new(),
+            languages: i18n::languages(i18n::default_locales_dir().as_deref()),
+            theme: Theme::default(),
+            ui_scale: 1.0,
+            zoom_speed: 1.0,
+            zoom_to_pointer: true,
+            window_size: None,
+            show_settings: false,
+            show_shortcuts: false,
+            capturing_shortcut: None,
+            region_clipboard: None,
+            loading: None,
+            batch_queue: None,
+            series: None,
+            tabs: vec![Tab::default()],
+            active_tab: 0,
+            pending_action: None,
+            close_confirmed: false,
+            output_dir: None,
+            name_pattern: String::new(),
+            overwrite_original: false,
+            confirm_overwrite: None,
+            send_node: PacsNode::default(),
+            calling_ae: pacs::DEFAULT_CALLING_AE.to_string(),
+            show_send: false,
+            sending: None,
+            show_query: false,
+            query_node: PacsNode::default(),
+            query: Query::default(),
+            query_matches: Vec::new(),
+            retrieve: Retrieve::default(),
+            store_port: query::DEFAULT_STORE_PORT,
+            query_task: None,
+            show_cloud: false,
+            download_url: String::new(),
+            upload_url: String::new(),
+            cloud_task: None,
+            profile: Profile::default(),
+            profiles: Vec::new(),
+            presets: Vec::new(),
+            plugins: Plugins::default(),
+            borders: Borders::default(),
+        };
+        install_fallback_fonts(&cc.egui_ctx);
+        app.reload_profiles();
+        app.reload_presets();
+        app.reload_plugins();
+        match settings {
+            Ok(settings) => app.apply_settings(settings),
+            Err(e) => app.console.push(e),
+        }
+        cc.egui_ctx.set_zoom_factor(app.ui_scale);
+        if let Some(path) = default_shortcuts_path() {
+            match Shortcuts::load(&path) {
+                Ok(shortcuts) => app.shortcuts = shortcuts,
+                Err(e) => app.console.push(e),
+            }
+        }
+        if let Some(path) = project::default_recovery_path()
+            && path.exists()
+        {
+            match Project::load(&path) {
+                Ok(saved) => app.recovery = Some(saved),
+                Err(e) => app.console.push(e),
+            }
+        }
+        if !paths.is_empty() {
+            app.open_paths(&cc.egui_ctx, paths);
+        }
+        app
+    }
+
+    fn apply_settings(&mut self, settings: Settings) {
+        self.window_size = settings.window_size.map(Vec2::from);
+        self.set_language(settings.language);
+        self.ui_scale = settings.ui_scale.clamp(0.5, 3.0);
+        self.zoom_speed = settings.zoom_speed.clamp(0.1, 10.0);
+        self.zoom_to_pointer = settings.zoom_to_pointer;
+        self.output_dir = settings.output_dir;
+        self.name_pattern = settings.name_pattern;
+        self.audit_log_path = settings.audit_log;
+        self.operator = settings.operator;
+        self.regions_sidecar = settings.regions_sidecar;
+        self.write_seg = settings.write_seg;
+        self.secondary_capture = settings.secondary_capture;
+        self.stamp_on = settings.stamp;
+        self.stamp.text = settings.stamp_text;
+        self.stamp.size = settings.stamp_size.clamp(1.0, 20.0);
+        match settings.stamp_corner.parse() {
+            Ok(corner) => self.stamp.corner = corner,
+            Err(e) => self.console.push(format!("Settings: {e}")),
+        }
+        match settings.fill.parse() {
+            Ok(fill) => self.fill = fill,
+            Err(e) => self.console.push(format!("Settings: {e}")),
+        }
+        match settings.theme.parse() {
+            Ok(theme) => self.theme = theme,
+            Err(e) => self.console.push(format!("Settings: {e}")),
+        }
+        match settings.slide_labels.parse() {
+            Ok(action) => self.slide_labels = action,
+            Err(e) => self.console.push(format!("Settings: {e}")),
+        }
+        match settings.deface.parse() {
+            Ok(how) => self.deface = how,
+            Err(e) => self.console.push(format!("Settings: {e}")),
+        }
+        match settings.documents.parse() {
+            Ok(action) => self.documents = action,
+            Err(e) => self.console.push(format!("Settings: {e}")),
+        }
+        self.send_node.remote = settings.pacs;
+        self.send_node.url = settings.stow_url;
+        self.calling_ae = settings.calling_ae;
+        self.query_node.remote = settings.query_pacs;
+        self.query_node.url = settings.query_url;
+        for (protocol, node) in [
+            (&settings.send_protocol, &mut self.send_node),
+            (&settings.query_protocol, &mut self.query_node),
+        ] {
+            match protocol.parse() {
+                Ok(protocol) => node.protocol = protocol,
+                Err(e) => self.console.push(format!("Settings: {e}")),
+            }
+        }
+        match settings.retrieve.parse() {
+            Ok(how) => self.retrieve = how,
+            Err(e) => self.console.push(format!("Settings: {e}")),
+        }
+        self.store_port = settings.store_port;
+        self.download_url = settings.download_url;
+        self.upload_url = settings.upload_url;
+        if let Some(name) = settings.profile {
+            match self.profiles.iter().find(|p| p.name == name) {
+                Some(profile) => self.profile = profile.clone(),
+                None => {
+                    self.notice = Some(tr!("notice.profile_gone", name = name));
+                }
+            }
+        }
+    }
+
+    fn settings(&self) -> Settings {
+        let default_profile = Profile::default();
+        Settings {
+            window_size: self.window_size.map(|s| [s.x, s.y]),
+            language: self.language.clone(),
+            theme: self.theme.to_string(),
+            ui_scale: self.ui_scale,
+            zoom_speed: self.zoom_speed,
+            zoom_to_pointer: self.zoom_to_pointer,
+            output_dir: self.output_dir.clone(),
+            name_pattern: self.name_pattern.clone(),
+            fill: self.fill.to_string(),
+            profile: (self.profile != default_profile).then(|| self.profile.name.clone()),
+            audit_log: self.audit_log_path.clone(),
+            operator: self.operator.clone(),
+            regions_sidecar: self.regions_sidecar,
+            write_seg: self.write_seg,
+            secondary_capture: self.secondary_capture,
+            stamp: self.stamp_on,
+            stamp_text: self.stamp.text.clone(),
+            stamp_corner: self.stamp.corner.to_string(),
+            stamp_size: self.stamp.size,
+            slide_labels: self.slide_labels.to_string(),
+            deface: self.deface.to_string(),
+            documents: self.documents.to_string(),
+            send_protocol: self.send_node.protocol.to_string(),
+            pacs: self.send_node.remote.clone(),
+            stow_url: self.send_node.url.clone(),
+            calling_ae: self.calling_ae.clone(),
+            query_protocol: self.query_node.protocol.to_string(),
+            query_pacs: self.query_node.remote.clone(),
+            query_url: self.query_node.url.clone(),
+            retrieve: self.retrieve.to_string(),
+            store_port: self.store_port,
+            download_url: self.download_url.clone(),
+            upload_url: self.upload_url.clone(),
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if let Some(path) = default_settings_path()
+            && let Err(e) = self.settings().save(&path)
+        {
+            eprintln!("{e}");
+        }
+    }
+
+    /// Switch the visuals when the theme, or the desktop's under System, changed, and
+    /// pick up interface size changes made with the keyboard.
+    fn apply_theme(&mut self, ctx: &egui::Context, app_frame: &eframe::Frame) {
+        let dark = match self.theme {
+            Theme::System => app_frame.info().system_theme != Some(eframe::Theme::Light),
+            Theme::Dark => true,
+            Theme::Light => false,
+        };
+        if ctx.style().visuals.dark_mode != dark {
+            ctx.set_visuals(if dark {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            });
+        }
+        self.ui_scale = ctx.zoom_factor();
+    }
+
+    fn settings_window(&mut self, ctx: &egui::Context) {
+        let mut language = None;
+        egui::Window::new(tr!("settings.title"))
+            .id(egui::Id::new("settings"))
+            .open(&mut self.show_settings)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("appearance").num_columns(2).show(ui, |ui| {
+                    ui.label(tr!("settings.language"));
+                    let name = |code: &str| {
+                        self.languages
+                            .iter()
+                            .find(|(c, _)| c == code)
+                            .map_or_else(|| code.to_string(), |(_, name)| name.clone())
+                    };
+                    let system = tr!(
+                        "settings.language_system",
+                        language = name(&i18n::system_language())
+                    );
+                    let selected = if self.language.is_empty() {
+                        system.clone()
+                    } else {
+                        name(&self.language)
+                    };
+                    egui::ComboBox::from_id_source("language")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            let choices = std::iter::once((String::new(), system))
+                                .chain(self.languages.iter().cloned());
+                            for (code, name) in choices {
+                                if ui.selectable_label(self.language == code, name).clicked() {
+                                    language = Some(code);
+                                }
+                            }
+                        });
+                    ui.end_row();
+                    ui.label(tr!("settings.theme"));
+                    let theme_label = |theme: Theme| match theme {
+                        Theme::System => tr!("settings.theme_system"),
+                        Theme::Dark => tr!("settings.theme_dark"),
+                        Theme::Light => tr!("settings.theme_light"),
+                    };
+                    egui::ComboBox::from_id_source("theme")
+                        .selected_text(theme_label(self.theme))
+                        .show_ui(ui, |ui| {
+                            for theme in Theme::ALL {
+                                ui.selectable_value(&mut self.theme, theme, theme_label(theme));
+                            }
+                        });
+                    ui.end_row();
+                    ui.label(tr!("settings.interface_size"));
+                    egui::ComboBox::from_id_source("ui_scale")
+                        .selected_text(format!("{:.0}%", self.ui_scale * 100.0))
+                        .show_ui(ui, |ui| {
+                            for scale in UI_SCALES {
+                                let label = format!("{:.0}%", scale * 100.0);
+                                if ui.selectable_label(self.ui_scale == scale, label).clicked() {
+                                    self.ui_scale = scale;
+                                    ctx.set_zoom_factor(scale);
+                                }
+                            }
+                        })
+                        .response
+                        .on_hover_text(tr!("settings.interface_size_hover"));
+                    ui.end_row();
+                });
+                ui.add(
+                    egui::Slider::new(&mut self.zoom_speed, 0.25..=4.0)
+                        .logarithmic(true)
+                        .text(tr!("settings.wheel_zoom")),
+                );
+                ui.checkbox(&mut self.zoom_to_pointer, tr!("settings.zoom_to_pointer"))
+                    .on_hover_text(tr!("settings.zoom_to_pointer_hover"));
+                if ui.button(tr!("settings.shortcuts")).clicked() {
+                    self.show_shortcuts = true;
+                }
+                ui.horizontal(|ui| {
+                    ui.label(tr!("settings.operator"));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.operator)
+                            .hint_text(default_operator())
+                            .desired_width(140.0),
+                    );
+                });
+                ui.separator();
+                ui.label(tr!("settings.remembered"));
+            });
+        if let Some(code) = language {
+            self.set_language(code);
+        }
+    }
+
+    /// Show the GUI in the language `code`, or the desktop's if empty.
+    fn set_language(&mut self, code: String) {
+        let effective = if code.is_empty() {
+            i18n::system_language()
+        } else {
+            code.clone()
+        };
+        if let Err(e) = i18n::set_language(i18n::default_locales_dir().as_deref(), &effective) {
+            self.console.push(e);
+        }
+        self.language = code;
+    }
+
+    fn reload_profiles(&mut self) {
+        let Some(path) = default_profiles_path() else {
+            return;
+        };
+        match load_profiles(&path) {
+            Ok(profiles) => self.profiles = profiles,
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    fn reload_presets(&mut self) {
+        let loaded = match default_presets_path() {
+            Some(path) => load_presets(&path),
+            None => Ok(dcm_redact::presets::builtin_presets()),
+        };
+        match loaded {
+            Ok(presets) => self.presets = presets,
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    fn reload_plugins(&mut self) {
+        let Some(path) = default_plugins_path() else {
+            return;
+        };
+        match load_plugins(&path) {
+            Ok(plugins) => self.plugins = plugins,
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    /// Start loading `path` into the active tab on a worker thread; the tab keeps its
+    /// current image until decoding finishes. A newer load replaces a pending one.
+    fn load_image(&mut self, ctx: &egui::Context, path: PathBuf) {
+        let dicom = self.instances.iter().any(|i| i.path == path);
+        let max_display_dim = self.max_display_dim;
+        let (tx, rx) = mpsc::channel();
+        let worker_path = path.clone();
+        let worker_ctx = ctx.clone();
+        std::thread::spawn(move || {
+            // The receiver is gone if another load replaced this one
+            let _ = tx.send(decode_image(worker_path, dicom, max_display_dim));
+            worker_ctx.request_repaint();
+        });
+        let new_tab_from = self.loading.take().and_then(|l| l.new_tab_from);
+        self.loading = Some(Loading {
+            path,
+            rx,
+            new_tab_from,
+        });
+    }
+
+    /// Take the result of the pending load, if it has finished.
+    fn poll_loading(&mut self, ctx: &egui::Context) {
+        let Some(loading) = &self.loading else {
+            return;
+        };
+        let result = match loading.rx.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(anyhow::anyhow!(
+                "Loading {} stopped unexpectedly",
+                loading.path.display()
+            )),
+        };
+        let new_tab_from = self.loading.take().and_then(|l| l.new_tab_from);
+        match result {
+            Ok(loaded) => self.finish_load(ctx, loaded),
+            Err(e) => {
+                self.console.push(e);
+                if let Some(previous) = new_tab_from {
+                    self.close_tab(self.active_tab);
+                    self.switch_tab(previous);
+                }
+            }
+        }
+    }
+
+    fn finish_load(&mut self, ctx: &egui::Context, loaded: LoadedImage) {
+        let LoadedImage {
+            path,
+            mut redactor,
+            voi_presets,
+            rescale,
+            display_dims,
+            color_img,
+        } = loaded;
+        redactor.set_uid_remapper(self.uid_remapper.clone());
+
+        // Update state
+        self.window = None;
+        self.voi_preset = (!voi_presets.is_empty()).then_some(0);
+        self.voi_presets = voi_presets;
+        self.rescale = rescale;
+        self.redactor = Some(redactor);
+        self.history.clear();
+        self.current_frame = 0;
+        self.shape_points.clear();
+        self.measure = None;
+        self.regions.clear();
+        self.selected_region = None;
+        self.region_drag = None;
+        self.metadata_edit = None;
+        self.phi_findings = None;
+        self.preview = None;
+        self.dirty = false;
+        self.display_dims = Some(display_dims);
+        self.orientation = Orientation::default();
+        self.crop = None;
+        self.color_img = Some(color_img.clone());
+        self.tex = Some(ctx.load_texture("image", color_img, egui::TextureOptions::LINEAR));
+        self.original_tex = None;
+        self.histogram = None;
+        self.fit_scale = 1.0;
+        self.pan = Vec2::ZERO;
+        if let Some(saved) = self.project_pending.remove(&path) {
+            self.restore_project_file(ctx, saved);
+        }
+        self.opened_path = Some(path);
+    }
+
+    /// Burn a project's applied regions back into the file just opened, as one undo
+    /// step, and stage its staged ones.
+    fn restore_project_file(&mut self, ctx: &egui::Context, saved: ProjectFile) {
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        let mut applied = 0;
+        for done in &saved.applied {
+            let pixels = redactor.pixel_fill(done.fill);
+            if self
+                .history
+                .record_region(redactor.frames_mut(), &done.region, done.fill, pixels)
+            {
+                applied += 1;
+            }
+        }
+        self.history.merge_last(applied);
+        self.current_frame = saved.frame.min(redactor.frame_count() - 1);
+        self.regions = saved.staged;
+        self.dirty = applied > 0;
+        self.rebuild_display_from_full(ctx);
+    }
+
+    /// Folder the open files' paths are stored relative to in a project.
+    fn project_base(&self) -> Option<&std::path::Path> {
+        self.folder_dir.as_deref()
+    }
+
+    /// The work in every tab, and in files of the last project not opened since.
+    fn project(&self) -> Project {
+        let base = self.project_base();
+        let mut files = Vec::new();
+        let mut add = |path: &Option<PathBuf>, frame, staged: &[Region], applied| {
+            if let Some(path) = path {
+                files.push(ProjectFile {
+                    path: Project::relative(base, path),
+                    frame,
+                    staged: staged.to_vec(),
+                    applied,
+                });
+            }
+        };
+        add(
+            &self.opened_path,
+            self.current_frame,
+            &self.regions,
+            self.history.applied_regions(),
+        );
+        for (i, tab) in self.tabs.iter().enumerate() {
+            if i != self.active_tab {
+                add(
+                    &tab.opened_path,
+                    tab.current_frame,
+                    &tab.regions,
+                    tab.history.applied_regions(),
+                );
+            }
+        }
+        let active = self
+            .opened_path
+            .as_ref()
+            .map(|p| Project::relative(base, p));
+        files.retain(|f| !f.is_empty());
+        let mut pending: Vec<&ProjectFile> = self.project_pending.values().collect();
+        pending.sort_by(|a, b| a.path.cmp(&b.path));
+        files.extend(pending.into_iter().map(|f| ProjectFile {
+            path: Project::relative(base, &f.path),
+            ..f.clone()
+        }));
+        Project {
+            folder: match &self.zip_source {
+                Some(zip) => Some(zip.archive.clone()),
+                None => self.folder_dir.clone(),
+            },
+            profile: (self.profile != Profile::default()).then(|| self.profile.name.clone()),
+            deidentify: self.deidentify,
+            fill: self.fill,
+            active,
+            files,
+        }
+    }
+
+    fn save_project_dialog(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter(tr!("dialog.projects"), &[project::EXTENSION])
+            .set_file_name(format!("study.{}", project::EXTENSION));
+        if let Some(path) = &self.project_path {
+            if let Some(name) = path.file_name() {
+                dialog = dialog.set_file_name(name.to_string_lossy());
+            }
+            if let Some(dir) = path.parent() {
+                dialog = dialog.set_directory(dir);
+            }
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        match self.project().save(&path) {
+            Ok(()) => {
+                self.notice = Some(tr!("notice.project_saved", path = path.display()));
+                self.project_path = Some(path);
+            }
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    fn open_project(&mut self, ctx: &egui::Context, path: PathBuf) -> anyhow::Result<()> {
+        self.restore_project(ctx, Project::load(&path)?)?;
+        self.project_path = Some(path);
+        Ok(())
+    }
+
+    /// Open a project's folder, settings and active file; the work on each file is
+    /// restored when it is opened.
+    fn restore_project(&mut self, ctx: &egui::Context, saved: Project) -> anyhow::Result<()> {
+        match &saved.folder {
+            Some(folder) if archive::is_zip(folder) => self.open_zip(ctx, folder.clone())?,
+            Some(folder) => self.open_folder(ctx, folder.clone())?,
+            None => self.project_pending.clear(),
+        }
+        let base = saved
+            .folder
+            .as_ref()
+            .and(self.project_base())
+            .map(std::path::Path::to_path_buf);
+        self.project_pending = saved
+            .files
+            .into_iter()
+            .map(|f| (Project::resolve(base.as_deref(), &f.path), f))
+            .collect();
+        self.deidentify = saved.deidentify;
+        self.fill = saved.fill;
+        self.profile = match &saved.profile {
+            None => Profile::default(),
+            Some(name) => match self.profiles.iter().find(|p| p.name == *name) {
+                Some(profile) => profile.clone(),
+                None => {
+                    self.console
+                        .push(tr!("notice.project_profile_gone", name = name));
+                    Profile::default()
+                }
+            },
+        };
+        if let Some(active) = &saved.active {
+            self.load_image(ctx, Project::resolve(base.as_deref(), active));
+        }
+        Ok(())
+    }
+
+    /// Snapshot the unsaved work to the recovery file every [`AUTOSAVE_INTERVAL`];
+    /// once everything is saved, the file is removed.
+    fn autosave(&mut self, ctx: &egui::Context) {
+        // Until the operator decides, the file still holds the crashed session's work
+        if self.recovery.is_some() {
+            return;
+        }
+        // Wake up for it even when the window is idle
+        let elapsed = self.last_autosave.elapsed();
+        if elapsed < AUTOSAVE_INTERVAL {
+            ctx.request_repaint_after(AUTOSAVE_INTERVAL - elapsed);
+            return;
+        }
+        self.last_autosave = Instant::now();
+        ctx.request_repaint_after(AUTOSAVE_INTERVAL);
+        let Some(path) = project::default_recovery_path() else {
+            return;
+        };
+        if self.unsaved_tab_count() == 0 {
+            if self.autosaved.take().is_some() {
+                remove_recovery_file(&path);
+            }
+            return;
+        }
+        let snapshot = self.project();
+        if self.autosaved.as_ref() == Some(&snapshot) {
+            return;
+        }
+        if let Some(dir) = path.parent()
+            && let Err(e) = std::fs::create_dir_all(dir)
+        {
+            eprintln!("Failed to create {}: {e}", dir.display());
+            return;
+        }
+        match snapshot.save(&path) {
+            Ok(()) => self.autosaved = Some(snapshot),
+            Err(e) => eprintln!("Autosave failed: {e}"),
+        }
+    }
+
+    fn recovery_window(&mut self, ctx: &egui::Context) {
+        let Some(saved) = &self.recovery else {
+            return;
+        };
+        let mut restore = false;
+        let mut discard = false;
+        egui::Window::new(tr!("recovery.title"))
+            .id(egui::Id::new("recovery"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(tr!("recovery.message"));
+                if let Some(folder) = &saved.folder {
+                    ui.label(tr!("recovery.folder", folder = folder.display()));
+                }
+                let regions: usize = saved
+                    .files
+                    .iter()
+                    .map(|f| f.staged.len() + f.applied.len())
+                    .sum();
+                ui.label(tr!(
+                    "recovery.regions",
+                    regions = regions,
+                    files = saved.files.len()
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button(tr!("recovery.restore")).clicked() {
+                        restore = true;
+                    }
+                    if ui.button(tr!("recovery.discard")).clicked() {
+                        discard = true;
+                    }
+                });
+            });
+        if restore && let Some(saved) = self.recovery.take() {
+            if let Err(e) = self.restore_project(ctx, saved) {
+                self.console
+                    .push(format!("Failed to restore the unsaved work: {e}"));
+            }
+        } else if discard {
+            self.recovery = None;
+            if let Some(path) = project::default_recovery_path() {
+                remove_recovery_file(&path);
+            }
+        }
+    }
+
+    /// Scan a folder (or its DICOMDIR) and show the first instance.
+    fn open_folder(&mut self, ctx: &egui::Context, dir: PathBuf) -> anyhow::Result<()> {
+        let instances = scan_folder(&dir, self.sort_order)?;
+        let Some(first) = instances.first().map(|i| i.path.clone()) else {
+            anyhow::bail!("No DICOM files found in {}", dir.display());
+        };
+        self.instances = instances;
+        self.thumbnails.clear();
+        self.selected_instances.clear();
+        self.folder_dir = Some(dir);
+        self.zip_source = None;
+        self.project_pending.clear();
+        self.load_image(ctx, first);
+        Ok(())
+    }
+
+    /// Extract a ZIP to a temporary folder and browse it like a folder.
+    fn open_zip(&mut self, ctx: &egui::Context, archive: PathBuf) -> anyhow::Result<()> {
+        let dir = pacs::spool_dir()?;
+        let opened = archive::extract(&archive, &dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| self.open_folder(ctx, dir.clone()));
+        match opened {
+            Ok(()) => {
+                self.zip_source = Some(ZipSource { archive, dir });
+                Ok(())
+            }
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&dir);
+                Err(e)
+            }
+        }
+    }
+
+    /// Open files and folders from the command line or a multi-file pick. A single
+    /// file is opened as-is; anything more is queued in the thumbnail strip like a
+    /// folder (DICOM only).
+    fn open_paths(&mut self, ctx: &egui::Context, paths: Vec<PathBuf>) {
+        let result = match paths.as_slice() {
+            [path] if path.is_dir() => self.open_folder(ctx, path.clone()),
+            [path] if archive::is_zip(path) => self.open_zip(ctx, path.clone()),
+            [path] if project::is_project_path(path) => self.open_project(ctx, path.clone()),
+            [path] => {
+                self.load_image(ctx, path.clone());
+                Ok(())
+            }
+            _ => {
+                let (instances, skipped) = scan_paths(&paths, self.sort_order);
+                if !skipped.is_empty() {
+                    let names: Vec<String> =
+                        skipped.iter().map(|p| p.display().to_string()).collect();
+                    self.notice = Some(tr!(
+                        "notice.skipped_paths",
+                        count = skipped.len(),
+                        paths = names.join(", ")
+                    ));
+                }
+                match instances.first().map(|i| i.path.clone()) {
+                    None => Err(anyhow::anyhow!("No DICOM files among the given paths")),
+                    Some(first) => {
+                        self.instances = instances;
+                        self.thumbnails.clear();
+                        self.selected_instances.clear();
+                        self.folder_dir = common_dir(&paths);
+                        self.zip_source = None;
+                        self.project_pending.clear();
+                        self.load_image(ctx, first);
+                        Ok(())
+                    }
+                }
+            }
+        };
+        if let Err(e) = result {
+            self.console.push(e);
+        }
+    }
+
+    /// Render the next missing thumbnail; one per repaint keeps the UI responsive.
+    fn load_next_thumbnail(&mut self, ctx: &egui::Context) {
+        let Some(path) = self
+            .instances
+            .iter()
+            .map(|i| &i.path)
+            .find(|p| !self.thumbnails.contains_key(*p))
+            .cloned()
+        else {
+            return;
+        };
+        let tex = Redactor::open_dicom(&path).ok().map(|r| {
+            let (w, h) = r.dimensions();
+            let (tw, th) = fit_within_max_dim(w, h, THUMBNAIL_DIM);
+            let ci = frame_to_display_color_image(
+                r.frame(0),
+                tw,
+                th,
+                r.photometric_interpretation(),
+                r.bits_stored(),
+                None,
+            );
+            ctx.load_texture(path.display().to_string(), ci, egui::TextureOptions::LINEAR)
+        });
+        self.thumbnails.insert(path, tex);
+        ctx.request_repaint();
+    }
+
+    /// The settings of multi-file saves from `input_dir` to `output_dir`, with the
+    /// staged regions.
+    fn files_job(
+        &self,
+        input_dir: PathBuf,
+        output_dir: PathBuf,
+        name_pattern: Option<NamePattern>,
+    ) -> BatchJob {
+        BatchJob {
+            input_dir,
+            output_dir,
+            regions: self.regions.clone(),
+            borders: Borders::default(),
+            keep_inside: self.keep_inside,
+            crop: self.crop,
+            ultrasound_mask: false,
+            presets: Vec::new(),
+            plugins: self.plugins.without_detectors(),
+            #[cfg(feature = "scripting")]
+            script: None,
+            deface: self.deface,
+            fill: self.fill,
+            deidentify: self.deidentify,
+            profile: self.profile.clone(),
+            rules: Vec::new(),
+            strip_overlays: self.strip_overlays,
+            remove_private: self.remove_private,
+            private_whitelist: self.private_creators(),
+            transfer_syntax: self.transfer_syntax.clone(),
+            regenerate_uids: self.regenerate_uids,
+            uid_map: self.uid_map_path.clone(),
+            audit_log: self.audit_log(),
+            regions_sidecar: self.regions_sidecar,
+            write_seg: self.write_seg,
+            secondary_capture: self.secondary_capture,
+            stamp: self.stamp(),
+            slide_labels: self.slide_labels,
+            documents: self.documents,
+            uid_store: None,
+            pseudonyms: self.pseudonyms.clone(),
+            name_pattern,
+            threads: 0,
+            dry_run: false,
+        }
+    }
+
+    /// Burn the staged regions into every selected instance (all of them if none are
+    /// selected) and save the results under a chosen output folder, mirroring the
+    /// folder layout, along with the presentation states that reference them. The open
+    /// file keeps its in-memory edits. Files opened from a ZIP are saved to a new ZIP
+    /// with the same layout instead.
+    fn apply_regions_to_files(&mut self, ctx: &egui::Context) {
+        let Some(input_dir) = self.folder_dir.clone() else {
+            return;
+        };
+        let name_pattern = match self.name_pattern() {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                self.console.push(e);
+                return;
+            }
+        };
+        let (output_dir, zip_to) = match &self.zip_source {
+            Some(source) => {
+                let stem = source
+                    .archive
+                    .file_stem()
+                    .map_or_else(|| "study".into(), |s| s.to_string_lossy());
+                let Some(zip_to) = rfd::FileDialog::new()
+                    .set_title(tr!("dialog.save_zip"))
+                    .add_filter(tr!("dialog.zip_archives"), &["zip"])
+                    .set_file_name(format!("{stem}-redacted.zip"))
+                    .save_file()
+                else {
+                    return;
+                };
+                match pacs::spool_dir() {
+                    Ok(dir) => (dir, Some(zip_to)),
+                    Err(e) => {
+                        self.console.push(e);
+                        return;
+                    }
+                }
+            }
+            None => {
+                let Some(output_dir) = rfd::FileDialog::new()
+                    .set_title(tr!("toolbar.output_title"))
+                    .pick_folder()
+                else {
+                    return;
+                };
+                (output_dir, None)
+            }
+        };
+        let mut targets: Vec<PathBuf> = self
+            .instances
+            .iter()
+            .map(|i| i.path.clone())
+            .filter(|p| self.selected_instances.is_empty() || self.selected_instances.contains(p))
+            .collect();
+        // Presentation states referencing the selected images go with them, so their
+        // references are remapped along
+        if !self.selected_instances.is_empty() {
+            let all: Vec<PathBuf> = self.instances.iter().map(|i| i.path.clone()).collect();
+            targets.extend(presentation::accompanying(&all, &targets));
+        }
+        let job = self.files_job(input_dir, output_dir, name_pattern);
+
+        // The open file is saved from memory, with its edits; the rest in the background.
+        // Defacing needs the whole series, so then the open file is redone from disk too
+        let current = self
+            .opened_path
+            .clone()
+            .filter(|p| targets.contains(p) && self.deface == Deface::Off);
+        let mut files = Vec::new();
+        if let Some(path) = current.clone() {
+            self.apply_regions(ctx);
+            let label = self
+                .redactor
+                .as_ref()
+                .is_some_and(|r| r.associated_image().is_some());
+            if label && self.slide_labels == SlideLabels::Blank {
+                self.blank_image(ctx);
+            }
+            // A document replaced by hand is saved with its replacement
+            let document = self.redactor.as_ref().is_some_and(|r| {
+                !r.document_replaced()
+                    && r.dicom().is_some_and(|d| encapsulated::is_encapsulated(d))
+            });
+            if (label && self.slide_labels == SlideLabels::Remove)
+                || (document && self.documents == Documents::Remove)
+            {
+                files.push((path, QueueStatus::Removed));
+            } else if let Some(redactor) = self.redactor.as_mut() {
+                job.configure(redactor, &self.uid_remapper);
+                if document {
+                    job.handle_document(redactor);
+                }
+                redactor.set_applied_regions(self.history.applied_regions());
+                let output = job.output_path(&path, redactor.naming_metadata().as_deref());
+                let status = match save_to(redactor, &output) {
+                    Ok(()) => QueueStatus::Done(output),
+                    Err(e) => QueueStatus::Failed(e.to_string()),
+                };
+                files.push((path, status));
+            }
+        }
+        let others: Vec<PathBuf> = targets
+            .into_iter()
+            .filter(|p| Some(p) != current.as_ref())
+            .collect();
+        files.extend(others.iter().map(|p| (p.clone(), QueueStatus::Queued)));
+
+        let control = Arc::new(BatchControl::default());
+        let rx = spawn_batch(
+            ctx,
+            job.clone(),
+            others,
+            self.uid_remapper.clone(),
+            control.clone(),
+        );
+        self.notice = None;
+        self.batch_queue = Some(BatchQueue {
+            job,
+            files,
+            control,
+            rx,
+            running: true,
+            open: true,
+            zip_to,
+        });
+    }
+
+    /// Run the failed files of the finished queue again.
+    fn retry_failed(&mut self, ctx: &egui::Context) {
+        let Some(queue) = self.batch_queue.as_mut() else {
+            return;
+        };
+        let mut retry = Vec::new();
+        for (path, status) in &mut queue.files {
+            if matches!(status, QueueStatus::Failed(_)) {
+                *status = QueueStatus::Queued;
+                retry.push(path.clone());
+            }
+        }
+        queue.control = Arc::new(BatchControl::default());
+        queue.rx = spawn_batch(
+            ctx,
+            queue.job.clone(),
+            retry,
+            self.uid_remapper.clone(),
+            queue.control.clone(),
+        );
+        queue.running = true;
+        self.notice = None;
+    }
+
+    /// Take the progress of the background batch run.
+    fn poll_batch_queue(&mut self) {
+        let Some(queue) = self.batch_queue.as_mut() else {
+            return;
+        };
+        loop {
+            match queue.rx.try_recv() {
+                Ok(event) => {
+                    let (path, status) = match event {
+                        QueueEvent::Started(path) => (path, QueueStatus::Running),
+                        QueueEvent::Finished(path, Ok(Some(output))) => {
+                            (path, QueueStatus::Done(output))
+                        }
+                        QueueEvent::Finished(path, Ok(None)) => (path, QueueStatus::Removed),
+                        QueueEvent::Finished(path, Err(e)) => (path, QueueStatus::Failed(e)),
+                    };
+                    if let Some(entry) = queue.files.iter_mut().find(|(p, _)| *p == path) {
+                        entry.1 = status;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+        }
+        if queue.running {
+            queue.running = false;
+            for (_, status) in &mut queue.files {
+                if matches!(status, QueueStatus::Queued | QueueStatus::Running) {
+                    *status = QueueStatus::Cancelled;
+                }
+            }
+            let mut notice = queue.summary();
+            // Files from a CD/DVD export get a DICOMDIR of their own
+            let dicomdir = queue.job.output_dir.join("DICOMDIR");
+            if queue.job.input_dir.join("DICOMDIR").is_file() {
+                match write_dicomdir(&queue.job.output_dir) {
+                    Ok(0) => {}
+                    Ok(n) => notice += &tr!("notice.dicomdir_lists", count = n),
+                    Err(e) => self.console.push(e),
+                }
+            }
+            if let Some(zip_to) = &queue.zip_to {
+                let mut written = Vec::new();
+                for (_, status) in &queue.files {
+                    if let QueueStatus::Done(output) = status {
+                        written.push(output.clone());
+                        let seg = seg::seg_path(output);
+                        if seg.is_file() {
+                            written.push(seg);
+                        }
+                    }
+                }
+                if dicomdir.is_file() {
+                    written.push(dicomdir);
+                }
+                match archive::create(zip_to, &queue.job.output_dir, &written) {
+                    Ok(()) => notice += &tr!("notice.zipped_to", path = zip_to.display()),
+                    Err(e) => self.console.push(e),
+                }
+            }
+            self.notice = Some(notice);
+        }
+    }
+
+    /// Per-file progress of the multi-file save, with pause, cancel and retry.
+    fn batch_queue_window(&mut self, ctx: &egui::Context) {
+        let Some(queue) = self.batch_queue.as_mut() else {
+            return;
+        };
+        let mut open = queue.open;
+        let mut retry = false;
+        let mut dismiss = false;
+        egui::Window::new(tr!("queue.title"))
+            .id(egui::Id::new("batch_queue"))
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                let total = queue.files.len();
+                let finished = queue.count(|s| {
+                    matches!(
+                        s,
+                        QueueStatus::Done(_)
+                            | QueueStatus::Removed
+                            | QueueStatus::Failed(_)
+                            | QueueStatus::Cancelled
+                    )
+                });
+                let failed = queue.count(|s| matches!(s, QueueStatus::Failed(_)));
+                ui.label(tr!(
+                    "queue.counts",
+                    queued = queue.count(|s| matches!(s, QueueStatus::Queued)),
+                    running = queue.count(|s| matches!(s, QueueStatus::Running)),
+                    done = queue.count(|s| matches!(s, QueueStatus::Done(_))),
+                    failed = failed,
+                ));
+                ui.add(
+                    egui::ProgressBar::new(finished as f32 / total.max(1) as f32)
+                        .text(format!("{finished}/{total}")),
+                );
+                ui.horizontal(|ui| {
+                    if queue.running {
+                        let paused = queue.control.is_paused();
+                        if ui
+                            .button(if paused {
+                                tr!("queue.resume")
+                            } else {
+                                tr!("queue.pause")
+                            })
+                            .on_hover_text(tr!("queue.pause_hover"))
+                            .clicked()
+                        {
+                            queue.control.set_paused(!paused);
+                        }
+                        if ui.button(tr!("common.cancel")).clicked() {
+                            queue.control.cancel();
+                        }
+                        if paused {
+                            ui.label(tr!("queue.paused"));
+                        } else {
+                            ui.spinner();
+                        }
+                    } else {
+                        ui.label(queue.summary());
+                        if ui
+                            .add_enabled(failed > 0, egui::Button::new(tr!("queue.retry")))
+                            .clicked()
+                        {
+                            retry = true;
+                        }
+                        if ui.button(tr!("queue.clear")).clicked() {
+                            dismiss = true;
+                        }
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("batch_queue_files")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (path, status) in &queue.files {
+                                    let name =
+                                        path.file_name().unwrap_or_default().to_string_lossy();
+                                    ui.label(name).on_hover_text(path.display().to_string());
+                                    match status {
+                                        QueueStatus::Queued => ui.weak(tr!("queue.queued")),
+                                        QueueStatus::Running => ui.label(tr!("queue.running")),
+                                        QueueStatus::Done(output) => ui
+                                            .label(tr!("queue.done"))
+                                            .on_hover_text(output.display().to_string()),
+                                        QueueStatus::Removed => ui
+                                            .weak(tr!("queue.left_out"))
+                                            .on_hover_text(tr!("queue.left_out_hover")),
+                                        QueueStatus::Failed(e) => ui
+                                            .colored_label(egui::Color32::RED, tr!("queue.failed"))
+                                            .on_hover_text(e),
+                                        QueueStatus::Cancelled => ui.weak(tr!("queue.cancelled")),
+                                    };
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        queue.open = open;
+        if retry {
+            self.retry_failed(ctx);
+        } else if dismiss {
+            self.batch_queue = None;
+        }
+    }
+
+    /// The private creator whitelist as entered, split on commas.
+    fn private_creators(&self) -> Vec<String> {
+        self.private_whitelist
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Copy the save settings from the UI into the redactor.
+    fn configure_redactor(&mut self) {
+        let private_creators = self.private_creators();
+        let audit_log = self.audit_log();
+        let stamp = self.stamp();
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        redactor.set_applied_regions(self.history.applied_regions());
+        redactor.set_deidentify(self.deidentify);
+        redactor.set_profile(self.profile.clone());
+        redactor.set_pseudonym_table(self.pseudonyms.clone());
+        redactor.set_strip_overlays(self.strip_overlays);
+        redactor.set_remove_private(self.remove_private);
+        redactor.set_private_whitelist(private_creators);
+        redactor.set_transfer_syntax(self.transfer_syntax.clone());
+        redactor.set_regenerate_uids(self.regenerate_uids);
+        redactor.set_uid_mapping_file(self.uid_map_path.clone());
+        redactor.set_audit_log(audit_log);
+        redactor.set_regions_sidecar(self.regions_sidecar);
+        redactor.set_write_seg(self.write_seg);
+        redactor.set_secondary_capture(self.secondary_capture);
+        redactor.set_stamp(stamp);
+        redactor.set_crop(self.crop);
+        redactor.set_plugins(self.plugins.clone());
+        redactor.set_orientation(if self.save_rotated {
+            self.orientation
+        } else {
+            Orientation::default()
+        });
+    }
+
+    fn stamp(&self) -> Option<Stamp> {
+        self.stamp_on.then(|| self.stamp.clone())
+    }
+
+    fn audit_log(&self) -> Option<AuditLog> {
+        let operator = match self.operator.trim() {
+            "" => default_operator(),
+            name => name.to_string(),
+        };
+        Some(AuditLog::new(self.audit_log_path.clone()?, operator))
+    }
+
+    fn save_redactor(&mut self, path: &std::path::Path) {
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        redactor.set_applied_regions(self.history.applied_regions());
+        match redactor.save(path) {
+            Ok(()) => self.dirty = false,
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    /// Where "Save" writes the open file: under the chosen output folder, or `redacted/`
+    /// next to the source, named by the naming pattern (or the source file name).
+    /// Unless `saving`, a patient new to the pseudonym table is named by its
+    /// placeholder rather than given a pseudonym.
+    fn save_path(&mut self, saving: bool) -> Option<PathBuf> {
+        let dir = self.save_dir()?;
+        let source = self.opened_path.clone()?;
+        let pattern = match self.name_pattern() {
+            Ok(Some(pattern)) => pattern,
+            Ok(None) => return Some(dir.join(source.file_name()?)),
+            Err(e) => {
+                self.console.push(e);
+                return None;
+            }
+        };
+        // Names come from the header as it will be written
+        self.configure_redactor();
+        let obj = self.redactor.as_mut().and_then(|r| {
+            if saving {
+                r.naming_metadata()
+            } else {
+                r.output_metadata()
+            }
+        });
+        Some(dir.join(pattern.expand(&source, obj.as_deref())))
+    }
+
+    fn save_dir(&self) -> Option<PathBuf> {
+        let opened = self.opened_path.as_ref()?;
+        match (&self.output_dir, &self.zip_source) {
+            (Some(dir), _) => Some(dir.clone()),
+            // Not into the temporary folder a ZIP was extracted to, but next to the ZIP
+            (None, Some(source)) if opened.starts_with(&source.dir) => {
+                Some(source.archive.parent()?.join("redacted"))
+            }
+            (None, _) => Some(opened.parent()?.join("redacted")),
+        }
+    }
+
+    fn name_pattern(&self) -> Result<Option<NamePattern>, String> {
+        let text = self.name_pattern.trim();
+        if text.is_empty() {
+            return Ok(None);
+        }
+        text.parse().map(Some)
+    }
+
+    fn is_opened_file(&self, path: &std::path::Path) -> bool {
+        let Some(source) = &self.opened_path else {
+            return false;
+        };
+        match (std::fs::canonicalize(source), std::fs::canonicalize(path)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Save to `out`, asking first if that would replace the source file.
+    fn request_save(&mut self, ctx: &egui::Context, out: PathBuf) {
+        if self.is_opened_file(&out) && !self.overwrite_original {
+            self.confirm_overwrite = Some(out);
+        } else {
+            self.start_save(ctx, out);
+        }
+    }
+
+    /// Burn in staged regions and write `out`, through the review window if enabled.
+    fn start_save(&mut self, ctx: &egui::Context, out: PathBuf) {
+        if let Some(dir) = out.parent()
+            && let Err(e) = std::fs::create_dir_all(dir)
+        {
+            self.console
+                .push(format!("Failed to create {}: {e}", dir.display()));
+            return;
+        }
+        self.apply_regions(ctx);
+        self.configure_redactor();
+        let review = match self.redactor.as_mut() {
+            Some(redactor) if self.deidentify && self.review_metadata => redactor
+                .output_metadata()
+                .zip(redactor.dicom())
+                .map(|(after, before)| diff_rows(before, &after)),
+            _ => None,
+        };
+        match review {
+            Some(rows) => self.save_review = Some((out, rows)),
+            None => self.save_redactor(&out),
+        }
+    }
+
+    fn confirm_overwrite_window(&mut self, ctx: &egui::Context) {
+        let Some(path) = &self.confirm_overwrite else {
+            return;
+        };
+        let mut overwrite = false;
+        let mut cancel = false;
+        egui::Window::new(tr!("overwrite.title"))
+            .id(egui::Id::new("confirm_overwrite"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(tr!("overwrite.message", path = path.display()));
+                ui.checkbox(&mut self.overwrite_original, tr!("overwrite.dont_ask"));
+                ui.horizontal(|ui| {
+                    if ui.button(tr!("overwrite.overwrite")).clicked() {
+                        overwrite = true;
+                    }
+                    if ui.button(tr!("common.cancel")).clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+        if overwrite && let Some(path) = self.confirm_overwrite.take() {
+            self.start_save(ctx, path);
+        } else if cancel {
+            self.confirm_overwrite = None;
+            self.overwrite_original = false;
+        }
+    }
+
+    /// Edits that would be lost by reloading: burned-in regions, header changes and
+    /// regions still staged.
+    fn has_unsaved_changes(&self) -> bool {
+        self.dirty || !self.regions.is_empty()
+    }
+
+    fn open_image_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(paths) = rfd::FileDialog::new()
+            .add_filter(
+                tr!("dialog.images"),
+                &[
+                    "png", "jpg", "jpeg", "tiff", "tif", "dcm", "dicom", "ima", "gz",
+                ],
+            )
+            .add_filter(tr!("dialog.zip_archives"), &["zip"])
+            .add_filter(tr!("dialog.projects"), &[project::EXTENSION])
+            .add_filter(tr!("dialog.all_files"), &["*"])
+            .pick_files()
+        {
+            self.guard(ctx, PendingAction::OpenPaths(paths));
+        }
+    }
+
+    fn open_folder_dialog(&mut self, ctx: &egui::Context) {
+        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+            self.guard(ctx, PendingAction::OpenFolder(dir));
+        }
+    }
+
+    fn save(&mut self, ctx: &egui::Context) {
+        if self.redactor.is_some()
+            && let Some(out) = self.save_path(true)
+        {
+            self.request_save(ctx, out);
+        }
+    }
+
+    fn save_as(&mut self, ctx: &egui::Context) {
+        let Some(suggested) = self.save_path(true) else {
+            return;
+        };
+        let file_name = suggested
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let mut dialog = rfd::FileDialog::new().set_file_name(file_name);
+        if let Some(dir) = suggested.parent()
+            && dir.is_dir()
+        {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(out) = dialog.save_file() {
+            self.request_save(ctx, out);
+        }
+    }
+
+    fn send_window(&mut self, ctx: &egui::Context) {
+        let mut send = false;
+        egui::Window::new(tr!("send.title"))
+            .id(egui::Id::new("send_to_pacs"))
+            .open(&mut self.show_send)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("send_to_pacs")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        self.send_node
+                            .fields(ui, &mut self.calling_ae, "RESEARCH", "stow");
+                    });
+                ui.label(tr!("send.explanation"));
+                ui.horizontal(|ui| {
+                    let idle = self.sending.is_none();
+                    if ui
+                        .add_enabled(
+                            idle && self.redactor.is_some(),
+                            egui::Button::new(tr!("send.send")),
+                        )
+                        .clicked()
+                    {
+                        send = true;
+                    }
+                    if !idle {
+                        ui.spinner();
+                        ui.label(tr!("send.sending"));
+                    }
+                });
+            });
+        if send {
+            self.send_to_pacs(ctx);
+        }
+    }
+
+    /// Burn in staged regions and save the open file as "Save" would, to a new
+    /// temporary folder. Returns the folder and the files written: the file and its SEG.
+    fn spool_open_file(&mut self, ctx: &egui::Context) -> Option<(PathBuf, Vec<PathBuf>)> {
+        let spool = match pacs::spool_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                self.console.push(e);
+                return None;
+            }
+        };
+        let name = self
+            .opened_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map_or_else(|| PathBuf::from("redacted.dcm"), PathBuf::from);
+        let path = spool.join(name);
+        self.apply_regions(ctx);
+        self.configure_redactor();
+        self.save_redactor(&path);
+        if !path.is_file() {
+            let _ = std::fs::remove_dir_all(&spool);
+            return None;
+        }
+        let mut files = vec![path.clone()];
+        if seg::seg_path(&path).is_file() {
+            files.push(seg::seg_path(&path));
+        }
+        Some((spool, files))
+    }
+
+    fn cloud_window(&mut self, ctx: &egui::Context) {
+        let mut download = false;
+        let mut upload = false;
+        let idle = self.cloud_task.is_none();
+        egui::Window::new(tr!("storage.title"))
+            .id(egui::Id::new("object_storage"))
+            .open(&mut self.show_cloud)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("object_storage")
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        ui.label(tr!("storage.open"));
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.download_url)
+                                .hint_text("s3://bucket/study/")
+                                .desired_width(280.0),
+                        );
+                        if ui
+                            .add_enabled(idle, egui::Button::new(tr!("storage.download")))
+                            .on_hover_text(tr!("storage.download_hover"))
+                            .clicked()
+                        {
+                            download = true;
+                        }
+                        ui.end_row();
+                        ui.label(tr!("storage.upload_to"));
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.upload_url)
+                                .hint_text("s3://bucket/redacted/")
+                                .desired_width(280.0),
+                        );
+                        if ui
+                            .add_enabled(
+                                idle && self.redactor.is_some(),
+                                egui::Button::new(tr!("storage.upload")),
+                            )
+                            .on_hover_text(tr!("storage.upload_hover"))
+                            .clicked()
+                        {
+                            upload = true;
+                        }
+                        ui.end_row();
+                    });
+                ui.label(tr!("storage.explanation"));
+                if !idle {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(tr!("storage.transferring"));
+                    });
+                }
+            });
+        if download {
+            self.download_from_cloud(ctx);
+        }
+        if upload {
+            self.upload_to_cloud(ctx);
+        }
+    }
+
+    /// Ask for a folder and download `download_url` into it in the background.
+    fn download_from_cloud(&mut self, ctx: &egui::Context) {
+        let url = self.download_url.trim().to_string();
+        if !cloud::is_url(&url) {
+            self.console
+                .push(format!("Not an object storage URL: {url}"));
+            return;
+        }
+        let Some(dir) = rfd::FileDialog::new()
+            .set_title(tr!("storage.download_into"))
+            .pick_folder()
+        else {
+            return;
+        };
+        let (tx, rx) = mpsc::channel();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let result = cloud::download(&url, &dir).map_err(|e| e.to_string());
+            let _ = tx.send(CloudEvent::Downloaded(result));
+            ctx.request_repaint();
+        });
+        self.cloud_task = Some(rx);
+    }
+
+    /// Save the open file to a temporary folder and upload it under `upload_url` in the
+    /// background.
+    fn upload_to_cloud(&mut self, ctx: &egui::Context) {
+        let url = self.upload_url.trim().to_string();
+        if !cloud::is_url(&url) {
+            self.console
+                .push(format!("Not an object storage URL: {url}"));
+            return;
+        }
+        let Some((spool, files)) = self.spool_open_file(ctx) else {
+            return;
+        };
+        let (tx, rx) = mpsc::channel();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let mut errors = Vec::new();
+            let result = cloud::upload(&files, &spool, &url, |_, result| {
+                if let Err(e) = result {
+                    errors.push(e.to_string());
+                }
+            });
+            let _ = std::fs::remove_dir_all(&spool);
+            let message = match result {
+                Ok(summary) if summary.failed == 0 => Ok(match summary.sent {
+                    1 => tr!("notice.uploaded_one", url = url),
+                    n => tr!("notice.uploaded", count = n, url = url),
+                }),
+                Ok(_) => Err(errors.join("\n")),
+                Err(e) => Err(e.to_string()),
+            };
+            let _ = tx.send(CloudEvent::Uploaded(message));
+            ctx.request_repaint();
+        });
+        self.cloud_task = Some(rx);
+    }
+
+    fn poll_cloud(&mut self, ctx: &egui::Context) {
+        let Some(rx) = &self.cloud_task else {
+            return;
+        };
+        let event = match rx.try_recv() {
+            Ok(event) => event,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                CloudEvent::Uploaded(Err(tr!("notice.transfer_stopped")))
+            }
+        };
+        self.cloud_task = None;
+        match event {
+            CloudEvent::Downloaded(Ok(files)) if files.is_empty() => {
+                self.console.push(tr!("notice.nothing_at_url"));
+            }
+            CloudEvent::Downloaded(Ok(files)) => {
+                self.notice = Some(match files.len() {
+                    1 => tr!("notice.downloaded_one"),
+                    n => tr!("notice.downloaded", count = n),
+                });
+                self.guard(ctx, PendingAction::OpenPaths(files));
+            }
+            CloudEvent::Uploaded(Ok(notice)) => self.notice = Some(notice),
+            CloudEvent::Downloaded(Err(e)) | CloudEvent::Uploaded(Err(e)) => self.console.push(e),
+        }
+    }
+
+    /// Burn in staged regions, save the open file to a temporary folder and send it to
+    /// the PACS in the background.
+    fn send_to_pacs(&mut self, ctx: &egui::Context) {
+        let node = match self.send_node.node() {
+            Ok(node) => node,
+            Err(e) => {
+                self.console.push(e);
+                return;
+            }
+        };
+        let calling_ae = self.calling_ae_title();
+        let Some((spool, files)) = self.spool_open_file(ctx) else {
+            return;
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let mut errors = Vec::new();
+            let on_sent = |_: &std::path::Path, result: Result<(), DCMRedactErrors>| {
+                if let Err(e) = result {
+                    errors.push(e.to_string());
+                }
+            };
+            let result = match &node {
+                Node::Dimse(remote) => pacs::store(remote, &calling_ae, &files, on_sent),
+                Node::DicomWeb(server) => dicomweb::store(server, &files, on_sent),
+            };
+            let _ = std::fs::remove_dir_all(&spool);
+            let message = match result {
+                Ok(summary) if summary.failed == 0 => {
+                    let sent = match summary.sent {
+                        1 => tr!("notice.sent_one", node = node),
+                        n => tr!("notice.sent", count = n, node = node),
+                    };
+                    Ok(summary.warnings.iter().fold(sent, |text, w| {
+                        text + "\n" + &tr!("notice.warning", warning = w)
+                    }))
+                }
+                Ok(_) => Err(errors.join("\n")),
+                Err(e) => Err(e.to_string()),
+            };
+            let _ = tx.send(message);
+            ctx.request_repaint();
+        });
+        self.sending = Some(rx);
+    }
+
+    fn poll_send(&mut self) {
+        let Some(rx) = &self.sending else {
+            return;
+        };
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(tr!("notice.send_stopped")),
+        };
+        self.sending = None;
+        match result {
+            Ok(notice) => self.notice = Some(notice),
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    /// The calling AE title, or the default one when none is set.
+    fn calling_ae_title(&self) -> String {
+        match self.calling_ae.trim() {
+            "" => pacs::DEFAULT_CALLING_AE.to_string(),
+            ae => ae.to_string(),
+        }
+    }
+
+    fn query_window(&mut self, ctx: &egui::Context) {
+        let mut search = false;
+        let mut retrieve = false;
+        let mut drill_down = None;
+        let idle = self.query_task.is_none();
+        egui::Window::new(tr!("query.title"))
+            .id(egui::Id::new("query_pacs"))
+            .open(&mut self.show_query)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                egui::Grid::new("query_pacs").num_columns(2).show(ui, |ui| {
+                    self.query_node
+                        .fields(ui, &mut self.calling_ae, "CLINICAL", "query");
+                    query_keys(ui, &mut self.query);
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(idle, egui::Button::new(tr!("query.search")))
+                        .clicked()
+                    {
+                        search = true;
+                    }
+                    if !idle {
+                        ui.spinner();
+                    }
+                });
+                ui.separator();
+
+                egui::ScrollArea::vertical()
+                    .max_height(320.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("query_matches")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (ticked, m) in &mut self.query_matches {
+                                    let checkbox = egui::Checkbox::without_text(ticked);
+                                    ui.add_enabled(m.is_retrievable(), checkbox);
+                                    for column in m.columns() {
+                                        ui.label(column);
+                                    }
+                                    let next = match m.level {
+                                        QueryLevel::Patient => tr!("query.studies"),
+                                        QueryLevel::Study => tr!("query.series"),
+                                        QueryLevel::Series => String::new(),
+                                    };
+                                    if let Some(query) = m.children()
+                                        && ui.add_enabled(idle, egui::Button::new(next)).clicked()
+                                    {
+                                        drill_down = Some(query);
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                ui.separator();
+
+                let dimse = self.query_node.protocol == Protocol::Dimse;
+                ui.horizontal(|ui| {
+                    ui.label(tr!("query.retrieve_with"));
+                    if !dimse {
+                        ui.label("WADO-RS");
+                    } else {
+                        egui::ComboBox::from_id_source("query_retrieve")
+                            .selected_text(self.retrieve.label())
+                            .show_ui(ui, |ui| {
+                                for how in Retrieve::ALL {
+                                    ui.selectable_value(&mut self.retrieve, how, how.label());
+                                }
+                            });
+                    }
+                    if dimse && self.retrieve == Retrieve::Move {
+                        ui.label(tr!("query.on_port"));
+                        ui.add(egui::DragValue::new(&mut self.store_port).range(1..=65535));
+                    }
+                    let any = self
+                        .query_matches
+                        .iter()
+                        .any(|(ticked, m)| *ticked && m.is_retrievable());
+                    if ui
+                        .add_enabled(idle && any, egui::Button::new(tr!("query.retrieve")))
+                        .on_hover_text(tr!("query.retrieve_hover"))
+                        .clicked()
+                    {
+                        retrieve = true;
+                    }
+                });
+                if dimse && self.retrieve == Retrieve::Move {
+                    ui.label(tr!("query.move_note"));
+                }
+            });
+        if let Some(query) = drill_down {
+            self.query = query;
+            search = true;
+        }
+        if search {
+            self.search_pacs(ctx);
+        }
+        if retrieve {
+            self.retrieve_from_pacs(ctx);
+        }
+    }
+
+    /// Run the query in the background.
+    fn search_pacs(&mut self, ctx: &egui::Context) {
+        let node = match self.query_node.node() {
+            Ok(node) => node,
+            Err(e) => {
+                self.console.push(e);
+                return;
+            }
+        };
+        let calling_ae = self.calling_ae_title();
+        let query = self.query.clone();
+        let (tx, rx) = mpsc::channel();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let result = match &node {
+                Node::Dimse(remote) => query::find(remote, &calling_ae, &query),
+                Node::DicomWeb(server) => dicomweb::find(server, &query),
+            };
+            let result = result.map_err(|e| e.to_string());
+            let _ = tx.send(QueryEvent::Found(result));
+            ctx.request_repaint();
+        });
+        self.query_task = Some(rx);
+    }
+
+    /// Ask for a folder and retrieve the ticked matches into it in the background.
+    fn retrieve_from_pacs(&mut self, ctx: &egui::Context) {
+        let node = match self.query_node.node() {
+            Ok(node) => node,
+            Err(e) => {
+                self.console.push(e);
+                return;
+            }
+        };
+        let matches: Vec<Match> = self
+            .query_matches
+            .iter()
+            .filter(|(ticked, _)| *ticked)
+            .map(|(_, m)| m.clone())
+            .collect();
+        let Some(dir) = rfd::FileDialog::new()
+            .set_title(tr!("query.retrieve_into"))
+            .pick_folder()
+        else {
+            return;
+        };
+        let calling_ae = self.calling_ae_title();
+        let (how, port) = (self.retrieve, self.store_port);
+        let (tx, rx) = mpsc::channel();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let result = match &node {
+                Node::Dimse(remote) => {
+                    query::retrieve(remote, &calling_ae, how, port, &matches, &dir)
+                }
+                Node::DicomWeb(server) => dicomweb::retrieve(server, &matches, &dir),
+            };
+            let result = result.map_err(|e| e.to_string());
+            let _ = tx.send(QueryEvent::Retrieved(result));
+            ctx.request_repaint();
+        });
+        self.query_task = Some(rx);
+    }
+
+    fn poll_query(&mut self, ctx: &egui::Context) {
+        let Some(rx) = &self.query_task else {
+            return;
+        };
+        let event = match rx.try_recv() {
+            Ok(event) => event,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                QueryEvent::Found(Err(tr!("notice.query_stopped")))
+            }
+        };
+        self.query_task = None;
+        match event {
+            QueryEvent::Found(Ok(matches)) => {
+                if matches.is_empty() {
+                    self.notice = Some(tr!("notice.no_matches"));
+                }
+                self.query_matches = matches.into_iter().map(|m| (false, m)).collect();
+            }
+            QueryEvent::Retrieved(Ok(retrieved)) => {
+                let failed = match retrieved.failed {
+                    0 => String::new(),
+                    n => tr!("notice.pacs_failed", count = n),
+                };
+                if retrieved.files.is_empty() {
+                    self.console
+                        .push(tr!("notice.none_retrieved", failed = failed));
+                    return;
+                }
+                self.notice = Some(match retrieved.files.len() {
+                    1 => tr!("notice.retrieved_one", failed = failed),
+                    n => tr!("notice.retrieved", count = n, failed = failed),
+                });
+                self.guard(ctx, PendingAction::OpenPaths(retrieved.files));
+            }
+            QueryEvent::Found(Err(e)) | QueryEvent::Retrieved(Err(e)) => self.console.push(e),
+        }
+    }
+
+    /// Burn in staged regions and write the shown frame as a PNG, JPEG or TIFF image.
+    fn export_image(&mut self, ctx: &egui::Context) {
+        let stem = self
+            .opened_path
+            .as_ref()
+            .and_then(|p| p.file_stem())
+            .map_or_else(|| "redacted".into(), |s| s.to_string_lossy().into_owned());
+        let Some(path) = rfd::FileDialog::new()
+            .set_title(tr!("dialog.export_frame"))
+            .add_filter("PNG", &["png"])
+            .add_filter("JPEG", &["jpg", "jpeg"])
+            .add_filter("TIFF", &["tif", "tiff"])
+            .set_file_name(format!("{stem}.png"))
+            .save_file()
+        else {
+            return;
+        };
+        self.apply_regions(ctx);
+        // What's on screen: without a window or VOI, the whole stored range
+        let voi = self.export_windowed.then(|| {
+            self.current_voi()
+                .unwrap_or(Voi::Window(self.full_window()))
+        });
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        match redactor.export_frame(self.current_frame, &path, voi) {
+            Ok(()) => self.notice = Some(tr!("notice.exported", path = path.display())),
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    fn show_frame(&mut self, ctx: &egui::Context, frame: usize) {
+        let frame_count = self.redactor.as_ref().map_or(1, |r| r.frame_count());
+        let frame = frame.min(frame_count.saturating_sub(1));
+        if frame != self.current_frame {
+            self.current_frame = frame;
+            self.rebuild_display_from_full(ctx);
+        }
+    }
+
+    fn set_shape_kind(&mut self, kind: ShapeKind) {
+        if self.shape_kind != kind {
+            self.shape_kind = kind;
+            self.shape_points.clear();
+        }
+    }
+
+    fn run_shortcut(&mut self, ctx: &egui::Context, action: Action) {
+        match action {
+            Action::Open => self.open_image_dialog(ctx),
+            Action::OpenFolder => self.open_folder_dialog(ctx),
+            Action::Save => self.save(ctx),
+            Action::SaveAs => self.save_as(ctx),
+            Action::Reset if self.opened_path.is_some() => self.guard(ctx, PendingAction::Reset),
+            Action::Reset => {}
+            Action::Undo => self.undo(ctx),
+            Action::Redo => self.redo(ctx),
+            Action::Apply => self.apply_regions(ctx),
+            // Keyboard zoom keeps the canvas center in place
+            Action::ZoomIn => self.zoom_at(1.25, None, Pos2::ZERO),
+            Action::ZoomOut => self.zoom_at(0.8, None, Pos2::ZERO),
+            Action::ZoomReset => {
+                self.fit_scale = 1.0;
+                self.pan = Vec2::ZERO;
+            }
+            Action::NextFrame => self.show_frame(ctx, self.current_frame + 1),
+            Action::PrevFrame => self.show_frame(ctx, self.current_frame.saturating_sub(1)),
+            Action::FirstFrame => self.show_frame(ctx, 0),
+            Action::LastFrame => self.show_frame(ctx, usize::MAX),
+            Action::Rectangle => self.set_shape_kind(ShapeKind::Rectangle),
+            Action::Ellipse => self.set_shape_kind(ShapeKind::Ellipse),
+            Action::Polygon => self.set_shape_kind(ShapeKind::Polygon),
+            Action::Lasso => self.set_shape_kind(ShapeKind::Lasso),
+            Action::PeekOriginal => {} // held, not pressed; see the image view
+        }
+    }
+
+    /// Every action with its binding; click one and press the new keys to remap it.
+    fn shortcuts_window(&mut self, ctx: &egui::Context) {
+        if let Some(action) = self.capturing_shortcut {
+            let pressed = ctx.input(|i| {
+                i.events.iter().find_map(|e| match e {
+                    egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers,
+                        ..
+                    } => Some((*key, *modifiers)),
+                    _ => None,
+                })
+            });
+            match pressed {
+                Some((Key::Escape, _)) => self.capturing_shortcut = None,
+                Some((key, modifiers)) => {
+                    self.shortcuts
+                        .set(action, Some(KeyboardShortcut::new(modifiers, key)));
+                    self.capturing_shortcut = None;
+                    self.save_shortcuts();
+                }
+                None => {}
+            }
+        }
+
+        let mut open = self.show_shortcuts;
+        let mut changed = false;
+        egui::Window::new(tr!("shortcuts.title"))
+            .id(egui::Id::new("keyboard_shortcuts"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(tr!("shortcuts.explanation"));
+                egui::Grid::new("shortcuts").striped(true).show(ui, |ui| {
+                    for action in Action::ALL {
+                        ui.label(action.label());
+                        let text = if self.capturing_shortcut == Some(action) {
+                            tr!("shortcuts.press_keys")
+                        } else {
+                            self.shortcuts
+                                .get(action)
+                                .map_or_else(|| "—".to_string(), |s| format_binding(&s))
+                        };
+                        if ui.button(text).clicked() {
+                            self.capturing_shortcut = Some(action);
+                        }
+                        if ui
+                            .small_button("✖")
+                            .on_hover_text(tr!("shortcuts.unbind"))
+                            .clicked()
+                        {
+                            self.shortcuts.set(action, None);
+                            changed = true;
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+                if ui.button(tr!("shortcuts.restore_defaults")).clicked() {
+                    self.shortcuts = Shortcuts::default();
+                    changed = true;
+                }
+            });
+        self.show_shortcuts = open;
+        if !open {
+            self.capturing_shortcut = None;
+        }
+        if changed {
+            self.save_shortcuts();
+        }
+    }
+
+    fn save_shortcuts(&mut self) {
+        if let Some(path) = default_shortcuts_path()
+            && let Err(e) = self.shortcuts.save(&path)
+        {
+            self.console.push(e);
+        }
+    }
+
+    /// Tabs (including the active one) with edits that haven't been saved.
+    fn unsaved_tab_count(&self) -> usize {
+        let parked = self
+            .tabs
+            .iter()
+            .enumerate()
+            .filter(|(i, t)| *i != self.active_tab && t.has_unsaved_changes())
+            .count();
+        parked + usize::from(self.has_unsaved_changes())
+    }
+
+    /// Move the active image's state out of the `App` fields.
+    fn park_tab(&mut self) -> Tab {
+        self.shape_points.clear();
+        self.measure = None;
+        self.drag_start_px = None;
+        self.drag_start_screen = None;
+        self.drag_current_screen = None;
+        self.region_drag = None;
+        self.metadata_edit = None;
+        Tab {
+            redactor: self.redactor.take(),
+            history: std::mem::take(&mut self.history),
+            current_frame: self.current_frame,
+            color_img: self.color_img.take(),
+            tex: self.tex.take(),
+            window: self.window.take(),
+            voi_presets: std::mem::take(&mut self.voi_presets),
+            voi_preset: self.voi_preset.take(),
+            rescale: self.rescale,
+            display_dims: self.display_dims.take(),
+            orientation: std::mem::take(&mut self.orientation),
+            crop: self.crop.take(),
+            regions: std::mem::take(&mut self.regions),
+            selected_region: self.selected_region.take(),
+            opened_path: self.opened_path.take(),
+            fit_scale: self.fit_scale,
+            pan: self.pan,
+            dirty: self.dirty,
+        }
+    }
+
+    fn unpark_tab(&mut self, tab: Tab) {
+        self.redactor = tab.redactor;
+        self.history = tab.history;
+        self.current_frame = tab.current_frame;
+        self.color_img = tab.color_img;
+        self.tex = tab.tex;
+        self.original_tex = None;
+        self.histogram = None;
+        self.window = tab.window;
+        self.voi_presets = tab.voi_presets;
+        self.voi_preset = tab.voi_preset;
+        self.rescale = tab.rescale;
+        self.display_dims = tab.display_dims;
+        self.orientation = tab.orientation;
+        self.crop = tab.crop;
+        self.regions = tab.regions;
+        self.selected_region = tab.selected_region;
+        self.opened_path = tab.opened_path;
+        self.fit_scale = tab.fit_scale;
+        self.pan = tab.pan;
+        self.dirty = tab.dirty;
+    }
+
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return;
+        }
+        self.tabs[self.active_tab] = self.park_tab();
+        let tab = std::mem::take(&mut self.tabs[index]);
+        self.unpark_tab(tab);
+        self.active_tab = index;
+        self.phi_findings = None;
+        self.preview = None;
+    }
+
+    /// Open `path` in a new tab, or in the active one if nothing is loaded there.
+    fn open_in_new_tab(&mut self, ctx: &egui::Context, path: PathBuf) {
+        if self.loading.is_some() {
+            return;
+        }
+        if self.redactor.is_none() {
+            return self.load_image(ctx, path);
+        }
+        let previous = self.active_tab;
+        self.tabs.push(Tab::default());
+        self.switch_tab(self.tabs.len() - 1);
+        self.load_image(ctx, path);
+        if let Some(loading) = &mut self.loading {
+            loading.new_tab_from = Some(previous);
+        }
+    }
+
+    /// Drop a tab and its edits; closing the last one leaves an empty view.
+    fn close_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() {
+            return;
+        }
+        if index != self.active_tab {
+            self.tabs.remove(index);
+            if index < self.active_tab {
+                self.active_tab -= 1;
+            }
+            return;
+        }
+        self.park_tab();
+        self.tabs.remove(index);
+        if self.tabs.is_empty() {
+            self.tabs.push(Tab::default());
+        }
+        self.active_tab = index.min(self.tabs.len() - 1);
+        let tab = std::mem::take(&mut self.tabs[self.active_tab]);
+        self.unpark_tab(tab);
+    }
+
+    fn tab_bar(&mut self, ctx: &egui::Context) {
+        let mut switch = None;
+        let mut close = None;
+        egui::TopBottomPanel::top("tabs").show(ctx, |ui| {
+            // A finished load goes into the active tab, so stay on it until then
+            ui.add_enabled_ui(self.loading.is_none(), |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for i in 0..self.tabs.len() {
+                        let (path, unsaved) = if i == self.active_tab {
+                            (self.opened_path.as_ref(), self.has_unsaved_changes())
+                        } else {
+                            let tab = &self.tabs[i];
+                            (tab.opened_path.as_ref(), tab.has_unsaved_changes())
+                        };
+                        let name = path.and_then(|p| p.file_name()).map_or_else(
+                            || tr!("common.empty"),
+                            |n| n.to_string_lossy().into_owned(),
+                        );
+                        let label = if unsaved { format!("● {name}") } else { name };
+                        let tab = ui.selectable_label(i == self.active_tab, label);
+                        if tab.clicked() {
+                            switch = Some(i);
+                        }
+                        let tab = match path {
+                            Some(p) => tab.on_hover_text(p.display().to_string()),
+                            None => tab,
+                        };
+                        if tab.middle_clicked() || ui.small_button("×").clicked() {
+                            close = Some(i);
+                        }
+                        ui.separator();
+                    }
+                });
+            });
+        });
+        if let Some(i) = switch {
+            self.switch_tab(i);
+        }
+        if let Some(i) = close {
+            // Ask about the tab's own edits with it in front
+            self.switch_tab(i);
+            self.guard(ctx, PendingAction::CloseTab(i));
+        }
+    }
+
+    /// Run `action` now, or ask first if it would throw away unsaved edits.
+    fn guard(&mut self, ctx: &egui::Context, action: PendingAction) {
+        let unsaved = match action {
+            PendingAction::Close => self.unsaved_tab_count() > 0,
+            _ => self.has_unsaved_changes(),
+        };
+        if unsaved {
+            self.pending_action = Some(action);
+        } else {
+            self.run_action(ctx, action);
+        }
+    }
+
+    fn run_action(&mut self, ctx: &egui::Context, action: PendingAction) {
+        let result = match action {
+            PendingAction::Reset => {
+                if let Some(p) = self.opened_path.clone() {
+                    self.load_image(ctx, p);
+                }
+                Ok(())
+            }
+            PendingAction::Load(p) => {
+                self.load_image(ctx, p);
+                Ok(())
+            }
+            PendingAction::OpenPaths(paths) => {
+                self.open_paths(ctx, paths);
+                Ok(())
+            }
+            PendingAction::OpenFolder(dir) => self.open_folder(ctx, dir),
+            PendingAction::CloseTab(index) => {
+                self.close_tab(index);
+                Ok(())
+            }
+            PendingAction::Close => {
+                self.close_confirmed = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.console.push(e);
+        }
+    }
+
+    fn unsaved_changes_window(&mut self, ctx: &egui::Context) {
+        let Some(action) = &self.pending_action else {
+            return;
+        };
+        let mut discard = false;
+        let mut cancel = false;
+        egui::Window::new(tr!("unsaved.title"))
+            .id(egui::Id::new("unsaved_changes"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                let unsaved = self.unsaved_tab_count();
+                if matches!(action, PendingAction::Close) && unsaved > 1 {
+                    ui.label(tr!("unsaved.tabs", count = unsaved));
+                } else {
+                    let name = self
+                        .opened_path
+                        .as_deref()
+                        .and_then(|p| p.file_name())
+                        .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+                    ui.label(tr!("unsaved.file", name = name));
+                }
+                ui.label(action.describe());
+                ui.horizontal(|ui| {
+                    if ui.button(tr!("unsaved.discard")).clicked() {
+                        discard = true;
+                    }
+                    if ui.button(tr!("common.cancel")).clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+        if discard && let Some(action) = self.pending_action.take() {
+            self.run_action(ctx, action);
+        } else if cancel {
+            self.pending_action = None;
+        }
+    }
+
+    /// Check the header's text elements against the PHI rules and list the matches.
+    fn scan_phi(&mut self) {
+        let rules = match default_rules_path() {
+            Some(path) => match load_rules(&path) {
+                Ok(rules) => rules,
+                Err(e) => {
+                    self.console.push(e);
+                    return;
+                }
+            },
+            None => default_rules(),
+        };
+        if self.phi_scan_output {
+            self.configure_redactor();
+        }
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        let obj = if self.phi_scan_output {
+            redactor.output_metadata()
+        } else {
+            redactor.dicom().cloned()
+        };
+        let Some(obj) = obj else {
+            self.console.push(tr!("phi.not_dicom"));
+            return;
+        };
+        self.phi_findings = Some(dcm_redact::phi::scan(&obj, &rules));
+    }
+
+    /// Review list of the last PHI scan.
+    fn phi_window(&mut self, ctx: &egui::Context) {
+        let Some(findings) = &self.phi_findings else {
+            return;
+        };
+        let mut open = true;
+        let mut rescan = false;
+        let mut show = None;
+        egui::Window::new(tr!("phi.title"))
+            .id(egui::Id::new("phi_scan"))
+            .open(&mut open)
+            .resizable(true)
+            .default_size([640.0, 400.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if findings.is_empty() {
+                        ui.label(tr!("phi.none"));
+                    } else {
+                        ui.label(tr!("phi.found", count = findings.len()));
+                    }
+                    rescan |= ui
+                        .checkbox(&mut self.phi_scan_output, tr!("phi.after"))
+                        .on_hover_text(tr!("phi.after_hover"))
+                        .changed();
+                    rescan |= ui.button(tr!("phi.rescan")).clicked();
+                });
+                ui.separator();
+                egui::ScrollArea::both().show(ui, |ui| {
+                    egui::Grid::new("phi_findings")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong(tr!("common.tag"));
+                            ui.strong(tr!("common.name"));
+                            ui.strong(tr!("phi.value"));
+                            ui.strong(tr!("phi.rule"));
+                            ui.end_row();
+                            for finding in findings {
+                                let row = &finding.element;
+                                let indent = "  ".repeat(row.depth);
+                                if ui
+                                    .link(format!("{indent}{}", format_tag(row.tag)))
+                                    .on_hover_text(tr!("phi.show"))
+                                    .clicked()
+                                {
+                                    show = Some(row.tag);
+                                }
+                                ui.label(&row.name);
+                                ui.label(row.value.chars().take(60).collect::<String>())
+                                    .on_hover_text(tr!("phi.matched", text = finding.matched));
+                                ui.label(&finding.rule);
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+        if let Some(tag) = show {
+            self.show_metadata = true;
+            self.metadata_filter = format_tag(tag);
+        }
+        if !open {
+            self.phi_findings = None;
+        } else if rescan {
+            self.scan_phi();
+        }
+    }
+
+    /// Work out what saving would do, staged regions included, without saving.
+    fn preview_anonymization(&mut self) {
+        let Some(path) = self.save_path(false) else {
+            return;
+        };
+        self.configure_redactor();
+        let pending: Vec<AppliedRegion> = self
+            .regions
+            .iter()
+            .map(|region| AppliedRegion {
+                region: region.clone(),
+                fill: self.fill,
+            })
+            .collect();
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        match redactor.dry_run(&path, &pending) {
+            Ok(report) => self.preview = Some(report),
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    fn preview_window(&mut self, ctx: &egui::Context) {
+        let Some(report) = &self.preview else {
+            return;
+        };
+        let mut open = true;
+        let mut refresh = false;
+        egui::Window::new(tr!("preview.title"))
+            .id(egui::Id::new("anonymization_preview"))
+            .open(&mut open)
+            .resizable(true)
+            .default_size([720.0, 480.0])
+            .show(ctx, |ui| {
+                ui.label(tr!("preview.saving_to", path = report.output.display()));
+                ui.label(tr!(
+                    "preview.counts",
+                    regions = report.regions.len(),
+                    removed = report.count(TagAction::Removed),
+                    replaced = report.count(TagAction::Replaced),
+                    added = report.count(TagAction::Added),
+                ));
+                ui.separator();
+                egui::ScrollArea::both().max_height(400.0).show(ui, |ui| {
+                    if !report.regions.is_empty() {
+                        ui.strong(tr!("preview.regions"));
+                        for applied in &report.regions {
+                            ui.label(format!(
+                                "{} ({})",
+                                describe_region(&applied.region),
+                                applied.fill
+                            ));
+                        }
+                        ui.separator();
+                    }
+                    egui::Grid::new("anonymization_preview")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong(tr!("preview.action"));
+                            ui.strong(tr!("common.tag"));
+                            ui.strong(tr!("common.name"));
+                            ui.strong(tr!("common.original"));
+                            ui.strong(tr!("preview.saved"));
+                            ui.end_row();
+                            for planned in &report.tags {
+                                let change = &planned.change;
+                                ui.label(match planned.action {
+                                    TagAction::Removed => tr!("preview.remove"),
+                                    TagAction::Replaced => tr!("preview.replace"),
+                                    TagAction::Added => tr!("preview.add"),
+                                });
+                                ui.monospace(&change.tag);
+                                ui.label(&change.name);
+                                for value in [&change.before, &change.after] {
+                                    let text = match value {
+                                        Some(v) => egui::RichText::new(
+                                            v.chars().take(60).collect::<String>(),
+                                        ),
+                                        None => egui::RichText::new(tr!("common.absent"))
+                                            .italics()
+                                            .weak(),
+                                    };
+                                    ui.label(text).on_hover_text(value.as_deref().unwrap_or(""));
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+                ui.separator();
+                refresh = ui
+                    .button(tr!("preview.refresh"))
+                    .on_hover_text(tr!("preview.refresh_hover"))
+                    .clicked();
+            });
+        if !open {
+            self.preview = None;
+        } else if refresh {
+            self.preview_anonymization();
+        }
+    }
+
+    /// Original vs. de-identified values of the pending save; nothing is written until
+    /// the operator confirms.
+    fn save_review_window(&mut self, ctx: &egui::Context) {
+        let Some((path, rows)) = &self.save_review else {
+            return;
+        };
+        let mut open = true;
+        let mut confirm = false;
+        let mut cancel = false;
+        egui::Window::new(tr!("review.title"))
+            .id(egui::Id::new("save_review"))
+            .open(&mut open)
+            .resizable(true)
+            .default_size([720.0, 480.0])
+            .show(ctx, |ui| {
+                let changed = rows.iter().filter(|r| r.changed()).count();
+                ui.horizontal(|ui| {
+                    ui.label(tr!("review.changed", count = changed));
+                    ui.checkbox(&mut self.review_changes_only, tr!("review.changes_only"));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.metadata_filter)
+                            .hint_text(tr!("common.search_hint")),
+                    );
+                });
+                ui.separator();
+                let query = self.metadata_filter.trim().to_lowercase();
+                egui::ScrollArea::both().max_height(360.0).show(ui, |ui| {
+                    egui::Grid::new("save_review").striped(true).show(ui, |ui| {
+                        ui.strong(tr!("common.tag"));
+                        ui.strong(tr!("common.name"));
+                        ui.strong(tr!("common.original"));
+                        ui.strong(tr!("review.deidentified"));
+                        ui.end_row();
+                        for row in rows.iter().filter(|r| {
+                            (r.changed() || !self.review_changes_only) && r.matches(&query)
+                        }) {
+                            let indent = "  ".repeat(row.depth);
+                            ui.monospace(format!("{indent}{}", format_tag(row.tag)));
+                            ui.label(&row.name);
+                            for value in [&row.before, &row.after] {
+                                let text = match value {
+                                    Some(v) => {
+                                        egui::RichText::new(v.chars().take(60).collect::<String>())
+                                    }
+                                    None => {
+                                        egui::RichText::new(tr!("common.absent")).italics().weak()
+                                    }
+                                };
+                                let text = if row.changed() {
+                                    text.color(ui.visuals().warn_fg_color)
+                                } else {
+                                    text
+                                };
+                                ui.label(text).on_hover_text(value.as_deref().unwrap_or(""));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(tr!("review.save_to", path = path.display()))
+                        .clicked()
+                    {
+                        confirm = true;
+                    }
+                    if ui.button(tr!("common.cancel")).clicked() {
+                        cancel = true;
+                    }
+                    ui.checkbox(&mut self.review_metadata, tr!("review.every_save"));
+                });
+            });
+
+        if confirm && let Some((path, _)) = self.save_review.take() {
+            self.save_redactor(&path);
+        } else if cancel || !open {
+            self.save_review = None;
+        }
+    }
+
+    /// Side panel listing every data element, with search, inline editing and delete.
+    fn metadata_panel(&mut self, ctx: &egui::Context) {
+        let mut commit = None;
+        let mut cancel = false;
+        let mut delete = None;
+        egui::SidePanel::right("metadata")
+            .resizable(true)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading(tr!("toolbar.metadata"));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.metadata_filter)
+                            .hint_text(tr!("common.search_hint")),
+                    );
+                });
+                let Some(obj) = self.redactor.as_ref().and_then(|r| r.dicom()) else {
+                    ui.label(tr!("metadata.no_dicom"));
+                    return;
+                };
+                let query = self.metadata_filter.trim().to_lowercase();
+                let rows: Vec<_> = element_rows(obj)
+                    .into_iter()
+                    .filter(|r| r.matches(&query))
+                    .collect();
+                egui::ScrollArea::both().show(ui, |ui| {
+                    egui::Grid::new("elements").striped(true).show(ui, |ui| {
+                        for row in &rows {
+                            let indent = "  ".repeat(row.depth);
+                            ui.monospace(format!("{indent}{}", format_tag(row.tag)));
+                            ui.label(&row.name);
+                            ui.monospace(format!("{:?}", row.vr));
+                            match &mut self.metadata_edit {
+                                Some((tag, text)) if *tag == row.tag && row.depth == 0 => {
+                                    let edit = ui.text_edit_singleline(text);
+                                    if edit.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter))
+                                    {
+                                        commit = Some((*tag, text.clone()));
+                                    }
+                                    ui.horizontal(|ui| {
+                                        if ui.small_button("✔").clicked() {
+                                            commit = Some((*tag, text.clone()));
+                                        }
+                                        if ui.small_button("✖").clicked() {
+                                            cancel = true;
+                                        }
+                                    });
+                                }
+                                _ => {
+                                    let shown: String = row.value.chars().take(80).collect();
+                                    ui.label(shown).on_hover_text(&row.value);
+                                    ui.horizontal(|ui| {
+                                        if row.editable
+                                            && ui
+                                                .small_button("✏")
+                                                .on_hover_text(tr!("metadata.edit"))
+                                                .clicked()
+                                        {
+                                            self.metadata_edit = Some((row.tag, row.value.clone()));
+                                        }
+                                        if row.depth == 0
+                                            && ui
+                                                .small_button("🗑")
+                                                .on_hover_text(tr!("metadata.delete"))
+                                                .clicked()
+                                        {
+                                            delete = Some(row.tag);
+                                        }
+                                    });
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+
+        if cancel {
+            self.metadata_edit = None;
+        }
+        let Some(obj) = self.redactor.as_mut().and_then(|r| r.dicom_mut()) else {
+            return;
+        };
+        if let Some((tag, text)) = commit {
+            match set_element_text(obj, tag, &text) {
+                Ok(()) => {
+                    self.metadata_edit = None;
+                    self.dirty = true;
+                }
+                Err(e) => self.console.push(e),
+            }
+        }
+        if let Some(tag) = delete {
+            obj.remove_element(tag);
+            self.dirty = true;
+        }
+    }
+
+    fn refresh_templates(&mut self) {
+        self.templates = template_dir().map_or_else(Vec::new, |d| list_templates(&d));
+    }
+
+    /// Stage a template's regions on top of any already staged.
+    fn stage_template(&mut self, template: &Template) {
+        if let Some(redactor) = &self.redactor {
+            let (w, h) = redactor.dimensions();
+            self.notice = template.size_mismatch(w, h).then(|| {
+                let [tw, th] = template.image_size.unwrap_or_default();
+                tr!(
+                    "notice.template_size",
+                    name = template.name,
+                    drawn = format!("{tw}×{th}"),
+                    size = format!("{w}×{h}")
+                )
+            });
+        }
+        self.regions.extend(template.regions.iter().cloned());
+        self.selected_region = None;
+    }
+
+    /// Copy the staged regions, also to the system clipboard as JSON.
+    fn copy_regions(&mut self, ctx: &egui::Context) {
+        let Some(redactor) = &self.redactor else {
+            return;
+        };
+        if self.regions.is_empty() {
+            return;
+        }
+        if let Ok(json) = serde_json::to_string(&self.regions) {
+            ctx.output_mut(|o| o.copied_text = json);
+        }
+        self.region_clipboard = Some((self.regions.clone(), redactor.dimensions()));
+        self.notice = Some(tr!("notice.copied", count = self.regions.len()));
+    }
+
+    /// Stage the copied regions at the same pixel coordinates. Regions drawn on a
+    /// single frame land on the shown frame, frame ranges start there.
+    fn paste_regions(&mut self) {
+        let (Some(redactor), Some((regions, (cw, ch)))) = (&self.redactor, &self.region_clipboard)
+        else {
+            return;
+        };
+        let (w, h) = redactor.dimensions();
+        let current = self.current_frame;
+        self.regions.extend(regions.iter().map(|r| {
+            Region {
+                shape: r.shape.clone(),
+                frame: r.frame.map(|_| current),
+                last_frame: r
+                    .frame
+                    .zip(r.last_frame)
+                    .map(|(first, last)| current + last.saturating_sub(first)),
+            }
+        }));
+        self.selected_region = None;
+        self.notice = Some(if (w, h) == (*cw, *ch) {
+            tr!("notice.pasted", count = regions.len())
+        } else {
+            tr!(
+                "notice.pasted_other_size",
+                count = regions.len(),
+                copied = format!("{cw}×{ch}"),
+                size = format!("{w}×{h}")
+            )
+        });
+    }
+
+    /// Save the staged regions as a new template in the template directory.
+    fn save_template(&mut self) {
+        let Some(dir) = template_dir() else {
+            self.console.push(tr!("templates.no_config_dir"));
+            return;
+        };
+        let name = self.template_name.trim().to_string();
+        let size = self.redactor.as_ref().map(|r| {
+            let (w, h) = r.dimensions();
+            [w, h]
+        });
+        let template = Template::new(name.clone(), self.regions.clone(), size);
+        match template.save(&dir.join(template_file_name(&name))) {
+            Ok(()) => self.template_name.clear(),
+            Err(e) => self.console.push(e),
+        }
+        self.refresh_templates();
+    }
+
+    /// Window listing saved templates, with save/import/export/delete.
+    fn template_manager(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_templates;
+        let mut stage = None;
+        let mut delete = None;
+        egui::Window::new(tr!("templates.title"))
+            .id(egui::Id::new("templates"))
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.template_name)
+                        .on_hover_text(tr!("templates.name_hover"));
+                    if ui
+                        .add_enabled(
+                            !self.regions.is_empty() && !self.template_name.trim().is_empty(),
+                            egui::Button::new(tr!("templates.save", count = self.regions.len())),
+                        )
+                        .clicked()
+                    {
+                        self.save_template();
+                    }
+                    if ui.button(tr!("templates.import")).clicked()
+                        && let Some(path) = rfd::FileDialog::new()
+                            .add_filter(tr!("templates.title"), &["json", "toml"])
+                            .pick_file()
+                    {
+                        // Without a template directory the import is just staged
+                        let imported = Template::load(&path).and_then(|t| match template_dir() {
+                            Some(dir) => t.save(&dir.join(template_file_name(&t.name))),
+                            None => {
+                                self.stage_template(&t);
+                                Ok(())
+                            }
+                        });
+                        if let Err(e) = imported {
+                            self.console.push(e);
+                        }
+                        self.refresh_templates();
+                    }
+                });
+                ui.separator();
+
+                if self.templates.is_empty() {
+                    ui.label(tr!("templates.none"));
+                }
+                egui::Grid::new("templates").striped(true).show(ui, |ui| {
+                    for (i, (path, template)) in self.templates.iter().enumerate() {
+                        ui.label(&template.name);
+                        ui.label(tr!("templates.regions", count = template.regions.len()));
+                        ui.label(
+                            template
+                                .image_size
+                                .map_or_else(String::new, |[w, h]| format!("{w}×{h}")),
+                        );
+                        if ui
+                            .add_enabled(
+                                self.redactor.is_some(),
+                                egui::Button::new(tr!("templates.stage")),
+                            )
+                            .on_hover_text(tr!("templates.stage_hover"))
+                            .clicked()
+                        {
+                            stage = Some(i);
+                        }
+                        if ui.button(tr!("templates.export")).clicked()
+                            && let Some(out) = rfd::FileDialog::new()
+                                .add_filter("JSON", &["json"])
+                                .add_filter("TOML", &["toml"])
+                                .set_file_name(template_file_name(&template.name))
+                                .save_file()
+                            && let Err(e) = template.save(&out)
+                        {
+                            self.console.push(e);
+                        }
+                        if ui.button(tr!("templates.delete")).clicked() {
+                            delete = Some(path.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        self.show_templates = open;
+
+        if let Some(i) = stage {
+            let template = self.templates[i].1.clone();
+            self.stage_template(&template);
+        }
+        if let Some(path) = delete {
+            if let Err(e) = std::fs::remove_file(&path) {
+                self.console
+                    .push(format!("Failed to delete {}: {e}", path.display()));
+            }
+            self.refresh_templates();
+        }
+    }
+
+    /// Position and value of the pixel under the pointer: the stored value, and for
+    /// rescaled data the modality value (Hounsfield units for CT).
+    fn probe_text(&self) -> Option<String> {
+        let [x, y] = self.probe?;
+        let redactor = self.redactor.as_ref()?;
+        let (w, h) = redactor.dimensions();
+        if x >= w || y >= h || redactor.is_document() {
+            return None;
+        }
+        let value = match redactor.frame(self.current_frame) {
+            Frame::Gray(img) => {
+                let v = img.get_pixel(x, y)[0];
+                let offset = redactor.pixel_info().map_or(0, |p| p.signed_offset());
+                let stored = i32::from(v) - i32::from(offset);
+                let modality = self.rescale.apply(f64::from(v));
+                if modality == f64::from(stored) {
+                    tr!("status.stored", stored = stored)
+                } else {
+                    let ct = redactor
+                        .dicom()
+                        .and_then(|d| d.element(dicom::dictionary_std::tags::MODALITY).ok())
+                        .and_then(|e| e.to_str().ok())
+                        .is_some_and(|m| m.trim() == "CT");
+                    let unit = if ct { " HU" } else { "" };
+                    tr!(
+                        "status.stored_value",
+                        stored = stored,
+                        value = format!("{modality:.1}{unit}")
+                    )
+                }
+            }
+            Frame::Rgb(img) => {
+                let [r, g, b] = img.get_pixel(x, y).0;
+                format!("RGB {r}, {g}, {b}")
+            }
+        };
+        Some(format!("x {x}  y {y}   {value}"))
+    }
+
+    /// Bit depth of the open image: BitsStored and the photometric interpretation for
+    /// DICOM, the decoded format for other images.
+    fn bit_depth_text(redactor: &Redactor) -> String {
+        match redactor.pixel_info() {
+            Some(p) => {
+                let signed = if p.signed {
+                    tr!("status.signed")
+                } else {
+                    String::new()
+                };
+                tr!(
+                    "status.bits",
+                    bits = p.bits_stored,
+                    photometric = p.photometric_interpretation.trim(),
+                    signed = signed
+                )
+            }
+            None => match redactor.frame(0) {
+                Frame::Gray(_) => tr!("status.gray"),
+                Frame::Rgb(_) => tr!("status.rgb"),
+            },
+        }
+    }
+
+    /// The open file, what it is, the frame shown and the state of its regions on the
+    /// left; the pixel under the pointer on the right.
+    fn status_bar(&mut self, ctx: &egui::Context) {
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if let Some(path) = &self.opened_path {
+                    let name = path.file_name().map_or_else(
+                        || path.display().to_string(),
+                        |n| n.to_string_lossy().into(),
+                    );
+                    ui.label(name).on_hover_text(path.display().to_string());
+                    ui.separator();
+                }
+                let modality = redactor
+                    .dicom()
+                    .and_then(|d| d.element(dicom::dictionary_std::tags::MODALITY).ok())
+                    .and_then(|e| e.to_str().ok())
+                    .map(|m| m.trim().to_string())
+                    .filter(|m| !m.is_empty());
+                if let Some(modality) = modality {
+                    ui.label(modality);
+                    ui.separator();
+                }
+                let (w, h) = redactor.dimensions();
+                ui.label(format!("{w} × {h}"));
+                ui.label(Self::bit_depth_text(redactor));
+                ui.separator();
+                let frames = redactor.frame_count();
+                if frames > 1 {
+                    ui.label(tr!(
+                        "status.frame",
+                        frame = self.current_frame + 1,
+                        frames = frames
+                    ));
+                    ui.separator();
+                }
+                ui.label(tr!(
+                    "status.regions",
+                    staged = self.regions.len(),
+                    applied = self.history.applied_count()
+                ));
+                ui.separator();
+                if self.has_unsaved_changes() {
+                    ui.colored_label(ui.visuals().warn_fg_color, tr!("status.unsaved"))
+                        .on_hover_text(tr!("status.unsaved_hover"));
+                } else {
+                    ui.weak(tr!("status.saved"));
+                }
+                ui.with_layout(
+                    egui::Layout::right_to_left(egui::Align::Center),
+                    |ui| match self.probe_text() {
+                        Some(text) => ui.monospace(text),
+                        None => ui.weak(tr!("status.probe_hint")),
+                    },
+                );
+            });
+        });
+    }
+
+    /// Name the open file in the window title, marked while it has unsaved changes.
+    fn update_title(&mut self, ctx: &egui::Context) {
+        let title = match &self.opened_path {
+            Some(path) => {
+                let name = path
+                    .file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy();
+                let unsaved = if self.has_unsaved_changes() {
+                    " •"
+                } else {
+                    ""
+                };
+                format!("{name}{unsaved} — {APP_TITLE}")
+            }
+            None => APP_TITLE.to_string(),
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    /// Bottom strip of thumbnails for the open folder; click one to switch to it.
+    fn thumbnail_strip(&mut self, ctx: &egui::Context) {
+        if self.instances.is_empty() {
+            return;
+        }
+        let mut clicked = None;
+        let mut apply_to_files = false;
+        let mut open_series = false;
+        egui::TopBottomPanel::bottom("thumbnails").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(tr!("thumbnails.instances", count = self.instances.len()));
+                let prev_order = self.sort_order;
+                egui::ComboBox::from_label(tr!("thumbnails.sort"))
+                    .selected_text(self.sort_order.label())
+                    .show_ui(ui, |ui| {
+                        for order in SortOrder::ALL {
+                            ui.selectable_value(&mut self.sort_order, order, order.label());
+                        }
+                    });
+                if self.sort_order != prev_order {
+                    sort_instances(&mut self.instances, self.sort_order);
+                }
+
+                let count = if self.selected_instances.is_empty() {
+                    self.instances.len()
+                } else {
+                    self.selected_instances.len()
+                };
+                let batch_running = self.batch_queue.as_ref().is_some_and(|q| q.running);
+                if ui
+                    .add_enabled(
+                        !self.regions.is_empty() && !batch_running,
+                        egui::Button::new(tr!("thumbnails.apply_to_files", count = count)),
+                    )
+                    .on_hover_text(tr!("thumbnails.apply_to_files_hover"))
+                    .clicked()
+                {
+                    apply_to_files = true;
+                }
+                let series_len = self.open_series_paths().len();
+                if ui
+                    .add_enabled(
+                        series_len > 1 && self.series.is_none(),
+                        egui::Button::new(tr!("thumbnails.series_volume", count = series_len)),
+                    )
+                    .on_hover_text(tr!("thumbnails.series_volume_hover"))
+                    .clicked()
+                {
+                    open_series = true;
+                }
+                if !self.selected_instances.is_empty()
+                    && ui.button(tr!("thumbnails.clear_selection")).clicked()
+                {
+                    self.selected_instances.clear();
+                }
+                if let Some(queue) = self.batch_queue.as_mut()
+                    && !queue.open
+                    && ui.button(tr!("thumbnails.batch_queue")).clicked()
+                {
+                    queue.open = true;
+                }
+            });
+            egui::ScrollArea::horizontal().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for inst in &self.instances {
+                        let selected = self.opened_path.as_ref() == Some(&inst.path);
+                        ui.vertical(|ui| {
+                            let resp = match self.thumbnails.get(&inst.path) {
+                                Some(Some(tex)) => ui.add(
+                                    egui::ImageButton::new(egui::Image::from_texture((
+                                        tex.id(),
+                                        tex.size_vec2(),
+                                    )))
+                                    .selected(selected),
+                                ),
+                                _ => ui.add_sized(
+                                    [THUMBNAIL_DIM as f32, THUMBNAIL_DIM as f32],
+                                    egui::SelectableLabel::new(selected, "…"),
+                                ),
+                            };
+                            let marked = self.selected_instances.contains(&inst.path);
+                            ui.label(if marked {
+                                format!("✔ {}", inst.label())
+                            } else {
+                                inst.label()
+                            });
+                            if resp
+                                .on_hover_text(format!(
+                                    "{}\n{}",
+                                    inst.path.display(),
+                                    inst.series_description
+                                ))
+                                .clicked()
+                            {
+                                clicked = Some(inst.path.clone());
+                            }
+                        });
+                    }
+                });
+            });
+        });
+        if let Some(path) = clicked {
+            let modifiers = ctx.input(|i| i.modifiers);
+            if modifiers.command {
+                // Ctrl+click toggles the selection instead of opening
+                if !self.selected_instances.remove(&path) {
+                    self.selected_instances.insert(path);
+                }
+            } else if modifiers.shift {
+                self.open_in_new_tab(ctx, path);
+            } else if self.opened_path.as_ref() != Some(&path) {
+                self.guard(ctx, PendingAction::Load(path));
+            }
+        }
+        if apply_to_files {
+            self.apply_regions_to_files(ctx);
+        }
+        if open_series {
+            self.open_series(ctx);
+        }
+    }
+
+    /// The instances in the series of the open file.
+    fn open_series_paths(&self) -> Vec<PathBuf> {
+        let uid = self
+            .opened_path
+            .as_ref()
+            .and_then(|p| self.instances.iter().find(|i| &i.path == p))
+            .map(|i| i.series_uid.as_str())
+            .unwrap_or_default();
+        if uid.is_empty() {
+            return Vec::new();
+        }
+        self.instances
+            .iter()
+            .filter(|i| i.series_uid == uid)
+            .map(|i| i.path.clone())
+            .collect()
+    }
+
+    /// Load every slice of the open file's series and stack them for the "Series
+    /// volume" window.
+    fn open_series(&mut self, ctx: &egui::Context) {
+        let paths = self.open_series_paths();
+        let slices = match paths
+            .iter()
+            .map(|p| Redactor::open_dicom(p))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(slices) => slices,
+            Err(e) => {
+                self.console.push(e);
+                return;
+            }
+        };
+        let stack = match volume::stack(&slices) {
+            Ok(stack) => stack,
+            Err(e) => {
+                self.console.push(e);
+                return;
+            }
+        };
+        self.series = Some(SeriesView {
+            positions: MprPlane::ALL.map(|p| stack.positions(p) / 2),
+            paths,
+            slices,
+            stack,
+            textures: [None, None, None],
+            regions: Vec::new(),
+            depth: 0,
+            drag: None,
+            dirty: false,
+        });
+        self.refresh_series(ctx);
+    }
+
+    /// Rebuild the planes of the "Series volume" window from its slices, windowed like
+    /// the open image.
+    fn refresh_series(&mut self, ctx: &egui::Context) {
+        let lut = self.display_lut();
+        let Some(series) = self.series.as_mut() else {
+            return;
+        };
+        let first = &series.slices[0];
+        for plane in MprPlane::ALL {
+            let img =
+                series
+                    .stack
+                    .reformat(&series.slices, plane, series.positions[plane as usize]);
+            let (w, h) = img.dimensions();
+            let (dw, dh) = fit_within_max_dim(w, h, 2 * SERIES_VIEW_DIM as u32);
+            let ci = frame_to_display_color_image(
+                &Frame::Gray(img),
+                dw,
+                dh,
+                first.photometric_interpretation(),
+                first.bits_stored(),
+                lut.as_deref(),
+            );
+            series.textures[plane as usize] = Some(ctx.load_texture(
+                format!("series-{}", plane.label()),
+                ci,
+                egui::TextureOptions::LINEAR,
+            ));
+        }
+    }
+
+    /// Fill the rectangles staged in the "Series volume" window into its slices.
+    fn apply_series_regions(&mut self, ctx: &egui::Context) {
+        let Some(series) = self.series.as_mut() else {
+            return;
+        };
+        for (plane, region) in series.regions.drain(..) {
+            for (r, on_slice) in series.stack.slice_regions(plane, &region) {
+                let slice = &mut series.slices[r];
+                slice.set_fill(self.fill);
+                slice.apply_region(&on_slice);
+                series.dirty = true;
+            }
+        }
+        self.refresh_series(ctx);
+    }
+
+    /// Deface the volume of the "Series volume" window, removing unless the Deface
+    /// option says to blur.
+    fn deface_series(&mut self, ctx: &egui::Context) {
+        let Some(series) = self.series.as_mut() else {
+            return;
+        };
+        let how = match self.deface {
+            Deface::Off => Deface::Remove,
+            how => how,
+        };
+        match deface::deface(&mut series.slices, how, &DefaceOptions::default()) {
+            Ok(0) => self.notice = Some(tr!("notice.no_face")),
+            Ok(changed) => {
+                series.dirty = true;
+                self.notice = Some(tr!("notice.defaced", count = changed));
+            }
+            Err(e) => self.console.push(e),
+        }
+        self.refresh_series(ctx);
+    }
+
+    /// Apply the staged rectangles and save every slice of the "Series volume" window
+    /// under a chosen folder, with the settings of multi-file saves.
+    fn save_series(&mut self, ctx: &egui::Context) {
+        let name_pattern = match self.name_pattern() {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                self.console.push(e);
+                return;
+            }
+        };
+        let Some(output_dir) = rfd::FileDialog::new()
+            .set_title(tr!("dialog.save_series"))
+            .pick_folder()
+        else {
+            return;
+        };
+        self.apply_series_regions(ctx);
+        let Some(input_dir) = self
+            .folder_dir
+            .clone()
+            .or_else(|| common_dir(&self.series.as_ref()?.paths))
+        else {
+            return;
+        };
+        let job = self.files_job(input_dir, output_dir, name_pattern);
+        let Some(series) = self.series.as_mut() else {
+            return;
+        };
+        let mut saved = 0;
+        let mut failed = Vec::new();
+        for (slice, path) in series.slices.iter_mut().zip(&series.paths) {
+            job.configure(slice, &self.uid_remapper);
+            let output = job.output_path(path, slice.naming_metadata().as_deref());
+            match save_to(slice, &output) {
+                Ok(()) => saved += 1,
+                Err(e) => failed.push(e.to_string()),
+            }
+        }
+        series.dirty = !failed.is_empty();
+        self.notice = Some(tr!(
+            "notice.series_saved",
+            saved = saved,
+            count = series.paths.len()
+        ));
+        if let Some(first) = failed.first() {
+            self.console.push(tr!(
+                "notice.slices_failed",
+                count = failed.len(),
+                error = first
+            ));
+        }
+    }
+
+    /// The "Series volume" window: axial, coronal and sagittal planes through the
+    /// stacked series. Clicking a plane moves the others through that point; dragging
+    /// stages a rectangle, through the whole volume or `depth` positions of it.
+    fn series_window(&mut self, ctx: &egui::Context) {
+        let Some(series) = self.series.as_mut() else {
+            return;
+        };
+        let mut open = true;
+        let mut moved = false;
+        let (mut apply, mut deface, mut save) = (false, false, false);
+        egui::Window::new(tr!("series.title"))
+            .id(egui::Id::new("series_volume"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let [nx, ny, nz] = series.stack.dims;
+                let [sx, sy, sz] = series.stack.spacing;
+                ui.label(tr!(
+                    "series.size",
+                    dims = format!("{nx}×{ny}×{nz}"),
+                    spacing = format!("{sx:.2}×{sy:.2}×{sz:.2}"),
+                    edited = if series.dirty {
+                        tr!("series.edited")
+                    } else {
+                        String::new()
+                    },
+                ));
+                ui.horizontal(|ui| {
+                    for plane in MprPlane::ALL {
+                        ui.vertical(|ui| {
+                            moved |= series_plane(ui, series, plane);
+                        });
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let most = MprPlane::ALL
+                        .map(|p| series.stack.positions(p))
+                        .into_iter()
+                        .max()
+                        .unwrap_or(1);
+                    ui.add(
+                        egui::DragValue::new(&mut series.depth)
+                            .range(0..=most)
+                            .prefix(tr!("series.depth")),
+                    )
+                    .on_hover_text(tr!("series.depth_hover"));
+                    ui.label(tr!("series.staged", count = series.regions.len()));
+                    if ui
+                        .add_enabled(
+                            !series.regions.is_empty(),
+                            egui::Button::new(tr!("series.clear")),
+                        )
+                        .clicked()
+                    {
+                        series.regions.clear();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    apply = ui
+                        .add_enabled(
+                            !series.regions.is_empty(),
+                            egui::Button::new(tr!("series.apply")),
+                        )
+                        .on_hover_text(tr!("series.apply_hover"))
+                        .clicked();
+                    deface = ui
+                        .button(tr!("series.deface"))
+                        .on_hover_text(tr!("series.deface_hover"))
+                        .clicked();
+                    save = ui
+                        .button(tr!("series.save"))
+                        .on_hover_text(tr!("series.save_hover"))
+                        .clicked();
+                });
+            });
+        if !open {
+            if self.series.take().is_some_and(|s| s.dirty) {
+                self.notice = Some(tr!("notice.series_closed"));
+            }
+            return;
+        }
+        if moved {
+            self.refresh_series(ctx);
+        }
+        if apply {
+            self.apply_series_regions(ctx);
+        }
+        if deface {
+            self.deface_series(ctx);
+        }
+        if save {
+            self.save_series(ctx);
+        }
+    }
+
+    /// The central panel for documents: the content tree of an SR, the ROIs and beams
+    /// of an RT object, the text annotations of a presentation state, or what an
+    /// encapsulated document is, with ways to get it out and replace it.
+    fn document_view(&mut self, ui: &mut egui::Ui) {
+        let Some(dcm) = self.redactor.as_ref().and_then(|r| r.dicom()) else {
+            return;
+        };
+        if rt::is_rt_object(dcm) {
+            ui.label(tr!("document.rt"));
+            ui.separator();
+            egui::ScrollArea::both().show(ui, |ui| {
+                for line in rt::summary_lines(dcm) {
+                    ui.monospace(line);
+                }
+            });
+            return;
+        }
+        if presentation::is_presentation_state(dcm) {
+            ui.label(tr!("document.presentation"));
+            ui.separator();
+            egui::ScrollArea::both().show(ui, |ui| {
+                for text in presentation::annotation_texts(dcm) {
+                    ui.monospace(text);
+                }
+            });
+            return;
+        }
+        if !encapsulated::is_encapsulated(dcm) {
+            ui.label(tr!("document.sr"));
+            ui.separator();
+            egui::ScrollArea::both().show(ui, |ui| {
+                for line in sr::content_lines(dcm) {
+                    ui.monospace(line);
+                }
+            });
+            return;
+        }
+
+        let kind = encapsulated::kind(dcm);
+        let extension = encapsulated::extension(dcm);
+        let (mut export, mut replace, mut strip) = (false, false, false);
+        if let Some(redactor) = self.redactor.as_ref() {
+            let size = redactor.document_payload().map_or(0, |p| p.len());
+            ui.heading(tr!("document.encapsulated", kind = kind));
+            ui.label(tr!("document.size", size = size));
+            if redactor.document_replaced() {
+                ui.label(tr!("document.replaced"));
+            }
+            ui.label(tr!("document.explanation"));
+            ui.horizontal(|ui| {
+                export = ui.button(tr!("document.export")).clicked();
+                replace = ui.button(tr!("document.replace")).clicked();
+                strip = ui
+                    .button(tr!("document.placeholder"))
+                    .on_hover_text(tr!("document.placeholder_hover"))
+                    .clicked();
+            });
+        }
+        if export
+            && let Some(bytes) = self.redactor.as_ref().and_then(|r| r.document_payload())
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter(&kind, &[extension])
+                .save_file()
+            && let Err(e) = std::fs::write(&path, bytes)
+        {
+            self.console
+                .push(format!("Failed to write {}: {e}", path.display()));
+        }
+        if replace
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter(&kind, &[extension])
+                .pick_file()
+        {
+            match std::fs::read(&path) {
+                Ok(bytes) => {
+                    if let Some(redactor) = self.redactor.as_mut() {
+                        redactor.replace_document(bytes);
+                    }
+                    self.dirty = true;
+                }
+                Err(e) => self
+                    .console
+                    .push(format!("Failed to read {}: {e}", path.display())),
+            }
+        }
+        if strip && let Some(redactor) = self.redactor.as_mut() {
+            redactor.strip_document();
+            self.dirty = true;
+        }
+    }
+
+    fn refresh_texture(&mut self, ctx: &egui::Context) {
+        self.original_tex = None;
+        self.histogram = None;
+        if let (Some(ci), Some(tex)) = (self.color_img.as_ref(), self.tex.as_mut()) {
+            tex.set(ci.clone(), egui::TextureOptions::LINEAR);
+        } else if let Some(ci) = self.color_img.clone() {
+            self.tex = Some(ctx.load_texture("image", ci, egui::TextureOptions::LINEAR));
+        }
+    }
+
+    fn rebuild_display_from_full(&mut self, ctx: &egui::Context) {
+        let Some(ci) = self
+            .redactor
+            .as_ref()
+            .and_then(|r| self.display_image(r.frame(self.current_frame)))
+        else {
+            return;
+        };
+        self.color_img = Some(ci);
+        self.refresh_texture(ctx);
+    }
+
+    /// `frame` of the open image as displayed: downscaled, windowed and turned.
+    fn display_image(&self, frame: &Frame) -> Option<ColorImage> {
+        let redactor = self.redactor.as_ref()?;
+        let (disp_w, disp_h) = self.display_dims?;
+        let (disp_w, disp_h) = self.orientation.size(disp_w, disp_h);
+        let turned;
+        let frame = if self.orientation.is_identity() {
+            frame
+        } else {
+            turned = self.orientation.frame(frame);
+            &turned
+        };
+        Some(frame_to_display_color_image(
+            frame,
+            disp_w,
+            disp_h,
+            redactor.photometric_interpretation(),
+            redactor.bits_stored(),
+            self.display_lut().as_deref(),
+        ))
+    }
+
+    /// The shown frame as it was before the edits made since it was opened, for
+    /// display next to the edited one.
+    fn original_image(&self) -> Option<ColorImage> {
+        let redactor = self.redactor.as_ref()?;
+        let frame = self
+            .history
+            .original(self.current_frame, redactor.frame(self.current_frame));
+        self.display_image(&frame)
+    }
+
+    /// Turn or mirror the image on screen; shapes are still drawn on the stored
+    /// pixels.
+    fn set_orientation(&mut self, ctx: &egui::Context, orientation: Orientation) {
+        self.orientation = orientation;
+        self.rebuild_display_from_full(ctx);
+    }
+
+    /// Scroll wheel and pinch zoom over `response`, which covers a canvas centered
+    /// on `canvas_center`.
+    fn scroll_zoom(&mut self, ctx: &egui::Context, response: &egui::Response, canvas_center: Pos2) {
+        if !response.hovered() {
+            return;
+        }
+        let anchor = if self.zoom_to_pointer {
+            response.hover_pos()
+        } else {
+            None
+        };
+        let pinch_factor = ctx.input(|i| i.zoom_delta());
+        if pinch_factor != 1.0 {
+            self.zoom_at(pinch_factor, anchor, canvas_center);
+            ctx.request_repaint();
+        }
+        let dy = ctx.input(|i| i.smooth_scroll_delta.y);
+        if dy != 0.0 {
+            self.zoom_at((dy / 240.0 * self.zoom_speed).exp(), anchor, canvas_center);
+            ctx.request_repaint();
+        }
+    }
+
+    /// Stored → display values for grayscale frames: the manual window, else the
+    /// selected stored VOI, after the rescale. `None` maps the top 8 stored bits.
+    fn display_lut(&self) -> Option<Vec<u8>> {
+        Some(display_lut(self.rescale, &self.current_voi()?))
+    }
+
+    /// The manual window, else the selected stored VOI.
+    fn current_voi(&self) -> Option<Voi> {
+        match self.window {
+            Some(window) => Some(Voi::Window(window)),
+            None => Some(
+                self.voi_preset
+                    .and_then(|i| self.voi_presets.get(i))?
+                    .voi
+                    .clone(),
+            ),
+        }
+    }
+
+    /// Every possible stored value, rescaled.
+    fn full_window(&self) -> Window {
+        let bits = self.redactor.as_ref().map_or(16, |r| r.bits_stored());
+        Window::full_range(bits, self.rescale)
+    }
+
+    /// The window in effect, as a starting point for adjusting it.
+    fn current_window(&self) -> Window {
+        if let Some(window) = self.window {
+            return window;
+        }
+        match self.voi_preset.and_then(|i| self.voi_presets.get(i)) {
+            Some(VoiPreset {
+                voi: Voi::Window(window),
+                ..
+            }) => *window,
+            _ => self.full_window(),
+        }
+    }
+
+    /// Window covering the darkest to brightest value of the shown frame.
+    fn auto_window(&mut self, ctx: &egui::Context) {
+        let Some(img) = self
+            .redactor
+            .as_ref()
+            .and_then(|r| r.frame(self.current_frame).as_gray())
+        else {
+            return;
+        };
+        let (min, max) = img
+            .pixels()
+            .fold((u16::MAX, 0), |(lo, hi), p| (lo.min(p[0]), hi.max(p[0])));
+        if min <= max {
+            self.window = Some(Window::from_min_max(
+                self.rescale.apply(min as f64),
+                self.rescale.apply(max as f64),
+            ));
+            self.rebuild_display_from_full(ctx);
+        }
+    }
+
+    /// Window spanning the chosen percentiles of the shown frame's values.
+    fn percentile_window(&mut self, ctx: &egui::Context) {
+        let Some(histogram) = &self.histogram else {
+            return;
+        };
+        let (low, high) = self.percentiles;
+        if let Some(window) = Window::from_percentiles(histogram, low, high, self.rescale) {
+            self.window = Some(window);
+            self.rebuild_display_from_full(ctx);
+        }
+    }
+
+    /// Histogram of the shown frame with the window over it, and windowing by
+    /// percentiles.
+    fn histogram_window(&mut self, ctx: &egui::Context) {
+        if self.histogram.is_none() {
+            self.histogram = self
+                .redactor
+                .as_ref()
+                .and_then(|r| r.frame(self.current_frame).as_gray())
+                .map(|img| Histogram::new(img.pixels().map(|p| p[0])));
+        }
+        let mut open = self.show_histogram;
+        let mut apply = false;
+        egui::Window::new(tr!("histogram.title"))
+            .id(egui::Id::new("histogram"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let Some((histogram, (lo, hi))) =
+                    self.histogram.as_ref().and_then(|h| Some((h, h.range()?)))
+                else {
+                    ui.label(tr!("histogram.gray_only"));
+                    return;
+                };
+                let (rect, _) = ui.allocate_exact_size(Vec2::new(360.0, 140.0), Sense::hover());
+                let painter = ui.painter_at(rect);
+                painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+                let bins = histogram.bins(lo, hi, rect.width() as usize);
+                let scale = |c: u64| {
+                    if self.log_histogram {
+                        (c as f64).ln_1p()
+                    } else {
+                        c as f64
+                    }
+                };
+                let top = bins.iter().copied().map(scale).fold(0.0, f64::max).max(1.0);
+                let bar = rect.width() / bins.len() as f32;
+                for (i, &c) in bins.iter().enumerate() {
+                    let height = (scale(c) / top) as f32 * rect.height();
+                    let x = rect.left() + i as f32 * bar;
+                    painter.rect_filled(
+                        Rect::from_min_max(
+                            Pos2::new(x, rect.bottom() - height),
+                            Pos2::new(x + bar, rect.bottom()),
+                        ),
+                        0.0,
+                        ui.visuals().text_color(),
+                    );
+                }
+                // The window in effect, from modality units back to frame values
+                let (lo_value, hi_value) =
+                    (self.rescale.apply(lo as f64), self.rescale.apply(hi as f64));
+                let to_x = |value: f64| {
+                    let t = if hi_value == lo_value {
+                        0.5
+                    } else {
+                        (value - lo_value) / (hi_value - lo_value)
+                    };
+                    rect.left() + t.clamp(0.0, 1.0) as f32 * rect.width()
+                };
+                if let Some(Voi::Window(window)) = self.current_voi() {
+                    let stroke = Stroke::new(1.5, egui::Color32::YELLOW);
+                    for edge in [
+                        window.center - window.width / 2.0,
+                        window.center + window.width / 2.0,
+                    ] {
+                        painter.vline(to_x(edge), rect.y_range(), stroke);
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.label(format!("{lo_value:.0}"));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(format!("{hi_value:.0}"));
+                    });
+                });
+                ui.checkbox(&mut self.log_histogram, tr!("histogram.log_scale"));
+                ui.horizontal(|ui| {
+                    let (low, high) = &mut self.percentiles;
+                    ui.add(
+                        egui::DragValue::new(low)
+                            .speed(0.1)
+                            .range(0.0..=100.0)
+                            .suffix(" %"),
+                    );
+                    ui.label(tr!("histogram.to"));
+                    ui.add(
+                        egui::DragValue::new(high)
+                            .speed(0.1)
+                            .range(0.0..=100.0)
+                            .suffix(" %"),
+                    );
+                    apply = ui
+                        .button(tr!("histogram.window"))
+                        .on_hover_text(tr!("histogram.window_hover"))
+                        .clicked();
+                });
+            });
+        self.show_histogram = open;
+        if apply {
+            self.percentile_window(ctx);
+        }
+    }
+
+    /// Stage a freshly drawn shape as an editable region (selected).
+    fn stage_shape(&mut self, shape: Shape) {
+        if shape.bounds().is_none() {
+            return;
+        }
+        let frame = (!self.redact_all_frames).then_some(self.current_frame);
+        self.regions.push(Region {
+            shape,
+            frame,
+            last_frame: None,
+        });
+        self.selected_region = Some(self.regions.len() - 1);
+    }
+
+    /// Vendor presets matching the open file; picking one stages its regions.
+    fn preset_menu(&mut self, ui: &mut egui::Ui) {
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        let (width, height) = redactor.dimensions();
+        let matches: Vec<&Preset> = redactor
+            .dicom()
+            .map(|dcm| matching(&self.presets, dcm))
+            .unwrap_or_default();
+        let mut staged = None;
+        let mut reload = false;
+        egui::ComboBox::from_id_source("vendor_presets")
+            .selected_text(tr!("presets.button", count = matches.len()))
+            .show_ui(ui, |ui| {
+                if matches.is_empty() {
+                    ui.weak(tr!("presets.none"));
+                }
+                for preset in &matches {
+                    if ui.selectable_label(false, &preset.name).clicked() {
+                        staged = Some(preset.regions(width, height));
+                    }
+                }
+                ui.separator();
+                if ui.button(tr!("presets.reload")).clicked() {
+                    reload = true;
+                }
+            })
+            .response
+            .on_hover_text(tr!("presets.hover"));
+        if let Some(regions) = staged
+            && !regions.is_empty()
+        {
+            self.regions.extend(regions);
+            self.selected_region = Some(self.regions.len() - 1);
+        }
+        if reload {
+            self.reload_presets();
+        }
+    }
+
+    /// Row counts and a border percentage, staged as bands on every frame.
+    fn borders_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button(tr!("borders.title"), |ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.borders.top)
+                    .range(0..=u32::MAX)
+                    .prefix(tr!("borders.top")),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.borders.bottom)
+                    .range(0..=u32::MAX)
+                    .prefix(tr!("borders.bottom")),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.borders.percent)
+                    .range(0.0..=50.0)
+                    .speed(0.5)
+                    .prefix(tr!("borders.border"))
+                    .suffix(" %"),
+            );
+            let Some((width, height)) = self.redactor.as_ref().map(Redactor::dimensions) else {
+                return;
+            };
+            if ui
+                .add_enabled(
+                    !self.borders.is_empty(),
+                    egui::Button::new(tr!("borders.stage")),
+                )
+                .clicked()
+            {
+                self.regions.extend(self.borders.regions(width, height));
+                self.selected_region = Some(self.regions.len() - 1);
+                ui.close_menu();
+            }
+        });
+    }
+
+    /// Stage a region around every cluster of bright text the heuristic detector finds.
+    fn suggest_regions(&mut self) {
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        let rects = redactor.suggest_regions(self.current_frame, &SuggestOptions::default());
+        if rects.is_empty() {
+            self.notice = Some(tr!("notice.no_bright_text"));
+            return;
+        }
+        for r in rects {
+            self.stage_shape(Shape::Rect(r));
+        }
+    }
+
+    /// Stage a region around everything the plugin detectors find in the shown frame.
+    fn run_detectors(&mut self) {
+        self.configure_redactor();
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        match redactor.detect_regions(self.current_frame) {
+            Ok(rects) if rects.is_empty() => {
+                self.notice = Some(tr!("notice.detectors_nothing"));
+            }
+            Ok(rects) => {
+                for r in rects {
+                    self.stage_shape(Shape::Rect(r));
+                }
+            }
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    /// Stage a region on every frame over each part outside the ultrasound regions.
+    fn mask_ultrasound(&mut self) {
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        let mask = redactor.ultrasound_mask();
+        if mask.is_empty() {
+            return;
+        }
+        self.regions
+            .extend(mask.into_iter().map(|r| Region::all_frames(Shape::Rect(r))));
+        self.selected_region = Some(self.regions.len() - 1);
+    }
+
+    /// Stage the regions of a file picked by the operator.
+    fn import_regions(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter(tr!("dialog.regions"), &["json", "csv"])
+            .pick_file()
+        else {
+            return;
+        };
+        match load_regions(&path) {
+            Ok(regions) => {
+                self.notice = Some(tr!(
+                    "notice.staged_from",
+                    count = regions.len(),
+                    path = path.display()
+                ));
+                self.regions.extend(regions);
+                self.selected_region = None;
+            }
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    /// Burn every staged region into the pixels as a single undo step.
+    fn apply_regions(&mut self, ctx: &egui::Context) {
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        if self.keep_inside {
+            let (width, height) = redactor.dimensions();
+            self.regions = keep_only(&self.regions, redactor.frame_count(), width, height);
+        }
+        let mut applied = 0;
+        let pixels = redactor.pixel_fill(self.fill);
+        for region in self.regions.drain(..) {
+            if self
+                .history
+                .record_region(redactor.frames_mut(), &region, self.fill, pixels)
+            {
+                applied += 1;
+            }
+        }
+        self.history.merge_last(applied);
+        self.selected_region = None;
+        self.region_drag = None;
+        if applied > 0 {
+            self.dirty = true;
+            // Rebuild the *downscaled display* from the full-res edited data
+            self.rebuild_display_from_full(ctx);
+        }
+    }
+
+    /// Black out every frame of the open image as one undo step, as for a slide label.
+    fn blank_image(&mut self, ctx: &egui::Context) {
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        let (x1, y1) = redactor.dimensions();
+        let region = Region::all_frames(Shape::Rect(RedactRect {
+            x0: 0,
+            y0: 0,
+            x1,
+            y1,
+        }));
+        let black = redactor.pixel_fill(RedactFill::Black);
+        if self
+            .history
+            .record_region(redactor.frames_mut(), &region, RedactFill::Black, black)
+        {
+            self.dirty = true;
+            self.rebuild_display_from_full(ctx);
+        }
+    }
+
+    /// Show another part of a whole slide image. Staged regions are applied first,
+    /// and the edits to the old view are written into its tiles, so they can no
+    /// longer be undone.
+    fn move_view(&mut self, ctx: &egui::Context, x: u32, y: u32) {
+        self.apply_regions(ctx);
+        self.configure_redactor();
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        if let Err(e) = redactor.set_view(x, y) {
+            self.console.push(e);
+            return;
+        }
+        self.history.clear();
+        self.rebuild_display_from_full(ctx);
+    }
+
+    /// OCR the shown frame and stage a region around every line of text found.
+    fn auto_detect_text(&mut self) {
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        match redactor.detect_text(self.current_frame, &OcrOptions::default()) {
+            Ok(rects) if rects.is_empty() => {
+                self.notice = Some(tr!("notice.no_text"));
+            }
+            Ok(rects) => {
+                for r in rects {
+                    self.stage_shape(Shape::Rect(r));
+                }
+            }
+            Err(e) => self.console.push(e),
+        }
+    }
+
+    fn delete_selected_region(&mut self) {
+        if let Some(i) = self.selected_region.take()
+            && i < self.regions.len()
+        {
+            self.regions.remove(i);
+        }
+    }
+
+    /// Give the selected region the bounding box `to`, clamped to the image.
+    fn set_selected_bounds(&mut self, to: RedactRect) {
+        let (Some(i), Some((w, h))) = (
+            self.selected_region,
+            self.redactor.as_ref().map(|r| r.dimensions()),
+        ) else {
+            return;
+        };
+        let Some(region) = self.regions.get_mut(i) else {
+            return;
+        };
+        let Some(from) = region.shape.bounds() else {
+            return;
+        };
+        let x0 = to.x0.min(w - 1);
+        let y0 = to.y0.min(h - 1);
+        let to = RedactRect {
+            x0,
+            y0,
+            x1: to.x1.clamp(x0 + 1, w),
+            y1: to.y1.clamp(y0 + 1, h),
+        };
+        region.shape = region.shape.transformed(from, to);
+    }
+
+    /// Arrow keys: move the selected region by `dx`/`dy` pixels, or with `resize` move
+    /// only its right/bottom edge.
+    fn nudge_selected(&mut self, dx: i64, dy: i64, resize: bool) {
+        let (Some(b), Some((w, h))) = (
+            self.selected_region
+                .and_then(|i| self.regions.get(i))
+                .and_then(|r| r.shape.bounds()),
+            self.redactor.as_ref().map(|r| r.dimensions()),
+        ) else {
+            return;
+        };
+        let to = if resize {
+            RedactRect {
+                x1: (b.x1 as i64 + dx).max(b.x0 as i64 + 1) as u32,
+                y1: (b.y1 as i64 + dy).max(b.y0 as i64 + 1) as u32,
+                ..b
+            }
+        } else {
+            // Keep the size: stop at the image border
+            let dx = dx.clamp(-(b.x0 as i64), w as i64 - b.x1 as i64);
+            let dy = dy.clamp(-(b.y0 as i64), h as i64 - b.y1 as i64);
+            RedactRect {
+                x0: (b.x0 as i64 + dx) as u32,
+                y0: (b.y0 as i64 + dy) as u32,
+                x1: (b.x1 as i64 + dx) as u32,
+                y1: (b.y1 as i64 + dy) as u32,
+            }
+        };
+        self.set_selected_bounds(to);
+    }
+
+    /// `width` x `height` full-res pixels in mm, when the file gives a pixel spacing.
+    fn physical_size(&self, width: u32, height: u32) -> Option<String> {
+        let [dy, dx] = self.redactor.as_ref()?.pixel_spacing()?;
+        Some(format!(
+            "{:.1} × {:.1} mm",
+            width as f64 * dx,
+            height as f64 * dy
+        ))
+    }
+
+    /// Height over width of a pixel on screen. Limited, so a broken header can't
+    /// flatten the view.
+    fn display_aspect(&self) -> f32 {
+        let aspect = self
+            .redactor
+            .as_ref()
+            .map_or(1.0, |r| r.pixel_aspect())
+            .clamp(0.1, 10.0) as f32;
+        if self.orientation.turns % 2 == 1 {
+            1.0 / aspect
+        } else {
+            aspect
+        }
+    }
+
+    /// Exact position and size of the selected region, in full-res pixels.
+    fn region_inspector(&mut self, ctx: &egui::Context) {
+        let (Some(i), Some((w, h))) = (
+            self.selected_region,
+            self.redactor.as_ref().map(|r| r.dimensions()),
+        ) else {
+            return;
+        };
+        let Some(region) = self.regions.get(i) else {
+            return;
+        };
+        let Some(b) = region.shape.bounds() else {
+            return;
+        };
+        let kind = match region.shape {
+            Shape::Rect(_) => tr!("region.rectangle"),
+            Shape::Ellipse(_) => tr!("region.ellipse"),
+            Shape::Polygon(_) => tr!("region.polygon"),
+        };
+        let (mut frame, mut last_frame) = (region.frame, region.last_frame);
+        let (mut x, mut y) = (b.x0, b.y0);
+        let (mut width, mut height) = (b.x1 - b.x0, b.y1 - b.y0);
+        let physical = self.physical_size(width, height);
+        let mut delete = false;
+        let mut crop = false;
+        let croppable = self.redactor.as_ref().is_some_and(|r| r.tiled().is_none());
+        let frame_count = self.redactor.as_ref().map_or(1, |r| r.frame_count());
+        let current_frame = self.current_frame;
+        egui::Window::new(tr!("region.title"))
+            .id(egui::Id::new("selected_region"))
+            .resizable(false)
+            .default_pos(ctx.screen_rect().right_top() + Vec2::new(-220.0, 80.0))
+            .show(ctx, |ui| {
+                egui::Grid::new("region_inspector").show(ui, |ui| {
+                    ui.label(tr!("region.shape"));
+                    ui.label(kind);
+                    ui.end_row();
+                    ui.label("X");
+                    ui.add(egui::DragValue::new(&mut x).range(0..=w - 1));
+                    ui.end_row();
+                    ui.label("Y");
+                    ui.add(egui::DragValue::new(&mut y).range(0..=h - 1));
+                    ui.end_row();
+                    ui.label(tr!("region.width"));
+                    ui.add(egui::DragValue::new(&mut width).range(1..=w));
+                    ui.end_row();
+                    ui.label(tr!("region.height"));
+                    ui.add(egui::DragValue::new(&mut height).range(1..=h));
+                    ui.end_row();
+                    if let Some(physical) = &physical {
+                        ui.label("");
+                        ui.weak(physical);
+                        ui.end_row();
+                    }
+                    if frame_count > 1 {
+                        ui.label(tr!("region.frames"));
+                        let selected = match (frame, last_frame) {
+                            (None, _) => tr!("region.all_frames"),
+                            (Some(f), None) => tr!("region.frame", frame = f + 1),
+                            (Some(f), Some(l)) => {
+                                tr!("region.frame_range", first = f + 1, last = l + 1)
+                            }
+                        };
+                        egui::ComboBox::from_id_source("region_frames")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                let single = frame.is_some() && last_frame.is_none();
+                                if ui
+                                    .selectable_label(single, tr!("region.current_frame"))
+                                    .clicked()
+                                {
+                                    (frame, last_frame) = (Some(current_frame), None);
+                                }
+                                if ui
+                                    .selectable_label(last_frame.is_some(), tr!("region.range"))
+                                    .clicked()
+                                    && last_frame.is_none()
+                                {
+                                    let first = frame.unwrap_or(current_frame);
+                                    (frame, last_frame) = (Some(first), Some(frame_count - 1));
+                                }
+                                if ui
+                                    .selectable_label(frame.is_none(), tr!("region.all_frames"))
+                                    .clicked()
+                                {
+                                    (frame, last_frame) = (None, None);
+                                }
+                            });
+                        ui.end_row();
+                        if let (Some(first), Some(last)) = (&mut frame, &mut last_frame) {
+                            // Shown 1-based, like the frame slider's "Frame N"
+                            let (mut n, mut m) = (*first + 1, *last + 1);
+                            ui.label("");
+                            ui.horizontal(|ui| {
+                                ui.add(egui::DragValue::new(&mut n).range(1..=frame_count));
+                                ui.label(tr!("region.to"));
+                                ui.add(egui::DragValue::new(&mut m).range(1..=frame_count));
+                            });
+                            ui.end_row();
+                            *first = n - 1;
+                            *last = (m - 1).max(*first);
+                        }
+                    }
+                });
+                ui.label(tr!("region.keys"));
+                ui.horizontal(|ui| {
+                    if ui.button(tr!("region.delete")).clicked() {
+                        delete = true;
+                    }
+                    if ui
+                        .add_enabled(croppable, egui::Button::new(tr!("region.crop")))
+                        .on_hover_text(tr!("region.crop_hover"))
+                        .clicked()
                     {
-                        let file_name = path.file_name().unwrap().to_owned().into_string().unwrap();
+                        crop = true;
+                    }
+                });
+            });
+        if delete || crop {
+            self.delete_selected_region();
+            if crop {
+                self.crop = Some(b);
+            }
+            return;
+        }
+        let to = RedactRect {
+            x0: x,
+            y0: y,
+            x1: x.saturating_add(width),
+            y1: y.saturating_add(height),
+        };
+        if to != b {
+            self.set_selected_bounds(to);
+        }
+        if let Some(region) = self.regions.get_mut(i) {
+            region.frame = frame;
+            region.last_frame = last_frame;
+        }
+    }
+
+    /// Topmost staged region on the current frame covering `px`.
+    fn region_at(&self, px: [u32; 2]) -> Option<usize> {
+        self.regions
+            .iter()
+            .rposition(|r| r.applies_to(self.current_frame) && r.shape.contains(px[0], px[1]))
+    }
+
+    /// Corners (inclusive pixel coords) of the selected region's bounding box.
+    fn selected_corners(&self) -> Option<[[u32; 2]; 4]> {
+        let b = self.regions.get(self.selected_region?)?.shape.bounds()?;
+        Some([
+            [b.x0, b.y0],
+            [b.x1 - 1, b.y0],
+            [b.x1 - 1, b.y1 - 1],
+            [b.x0, b.y1 - 1],
+        ])
+    }
+
+    /// Start moving/resizing a staged region if the drag began on one (or on a handle of
+    /// the selected one); otherwise the drag draws a new shape.
+    fn begin_region_drag(&mut self, img_rect: Rect, pos: Pos2) {
+        let Some(px) = self.screen_to_pixel(img_rect, pos) else {
+            return;
+        };
+        let handle = self.selected_corners().and_then(|corners| {
+            let hit = corners
+                .iter()
+                .position(|&c| self.pixel_to_screen(img_rect, c).distance(pos) <= HANDLE_RADIUS)?;
+            Some((self.selected_region?, corners[(hit + 2) % 4]))
+        });
+        let (index, anchor) = match handle {
+            Some((index, opposite)) => (index, Some(opposite)),
+            None => match self.region_at(px) {
+                Some(index) => (index, None),
+                None => return,
+            },
+        };
+        let original = self.regions[index].shape.clone();
+        let Some(from) = original.bounds() else {
+            return;
+        };
+        self.selected_region = Some(index);
+        self.region_drag = Some(RegionDrag {
+            index,
+            original,
+            from,
+            start_px: px,
+            anchor,
+        });
+    }
+
+    fn update_region_drag(&mut self, img_rect: Rect, pos: Pos2) {
+        let (Some(drag), Some(px), Some((w, h))) = (
+            self.region_drag.as_ref(),
+            self.screen_to_pixel(img_rect, pos),
+            self.redactor.as_ref().map(|r| r.dimensions()),
+        ) else {
+            return;
+        };
+        let f = drag.from;
+        let to = match drag.anchor {
+            // Move: shift by the pointer delta, kept inside the image
+            None => {
+                let dx = (px[0] as i64 - drag.start_px[0] as i64)
+                    .clamp(-(f.x0 as i64), w as i64 - f.x1 as i64);
+                let dy = (px[1] as i64 - drag.start_px[1] as i64)
+                    .clamp(-(f.y0 as i64), h as i64 - f.y1 as i64);
+                RedactRect {
+                    x0: (f.x0 as i64 + dx) as u32,
+                    y0: (f.y0 as i64 + dy) as u32,
+                    x1: (f.x1 as i64 + dx) as u32,
+                    y1: (f.y1 as i64 + dy) as u32,
+                }
+            }
+            // Resize: the box spanned by the fixed corner and the pointer
+            Some(a) => RedactRect {
+                x0: a[0].min(px[0]),
+                y0: a[1].min(px[1]),
+                x1: a[0].max(px[0]) + 1,
+                y1: a[1].max(px[1]) + 1,
+            },
+        };
+        let shape = drag.original.transformed(f, to);
+        if let Some(region) = self.regions.get_mut(drag.index) {
+            region.shape = shape;
+        }
+    }
+
+    /// Screen-space outline of a staged shape.
+    fn shape_outline(&self, img_rect: Rect, shape: &Shape) -> Vec<Pos2> {
+        let to_screen = |x: u32, y: u32| self.image_to_screen(img_rect, [x as f64, y as f64]);
+        match shape {
+            Shape::Rect(r) => vec![
+                to_screen(r.x0, r.y0),
+                to_screen(r.x1, r.y0),
+                to_screen(r.x1, r.y1),
+                to_screen(r.x0, r.y1),
+            ],
+            Shape::Ellipse(r) => ellipse_points(Rect::from_two_pos(
+                to_screen(r.x0, r.y0),
+                to_screen(r.x1, r.y1),
+            )),
+            Shape::Polygon(points) => points
+                .iter()
+                .map(|&p| self.pixel_to_screen(img_rect, p))
+                .collect(),
+        }
+    }
+
+    fn undo(&mut self, ctx: &egui::Context) {
+        if let Some(redactor) = self.redactor.as_mut()
+            && self.history.undo(redactor.frames_mut())
+        {
+            self.dirty = true;
+            self.rebuild_display_from_full(ctx);
+        }
+    }
+
+    fn redo(&mut self, ctx: &egui::Context) {
+        if let Some(redactor) = self.redactor.as_mut()
+            && self.history.redo(redactor.frames_mut())
+        {
+            self.dirty = true;
+            self.rebuild_display_from_full(ctx);
+        }
+    }
+
+    /// Map a screen point to FULL-RES image pixel coordinates, clamped,
+    /// given the on-screen rect of the displayed image.
+    ///
+    /// This works even though we display a downscaled texture because we use UVs
+    /// (relative position within the drawn rectangle) and apply them to full-res dims.
+    fn screen_to_pixel(&self, img_rect: Rect, p: Pos2) -> Option<[u32; 2]> {
+        let (w, h) = match self.redactor.as_ref() {
+            Some(r) => r.dimensions(),
+            None => return None,
+        };
+        if !img_rect.contains(p) {
+            return None;
+        }
+        let [x, y] = self.screen_to_image(img_rect, p);
+        let x = x.floor().clamp(0.0, (w - 1) as f64) as u32;
+        let y = y.floor().clamp(0.0, (h - 1) as f64) as u32;
+        Some([x, y])
+    }
+
+    /// Point of the full-res image (pixel edges at whole numbers) under screen point
+    /// `p`, not clamped to the image. Inverse of [`App::image_to_screen`].
+    fn screen_to_image(&self, img_rect: Rect, p: Pos2) -> [f64; 2] {
+        let (w, h) = self.redactor.as_ref().map_or((1, 1), |r| r.dimensions());
+        // Through the displayed (turned) image back to the stored one
+        let (dw, dh) = self.orientation.size(w, h);
+        let shown = [
+            ((p.x - img_rect.left()) / img_rect.width()) as f64 * dw as f64,
+            ((p.y - img_rect.top()) / img_rect.height()) as f64 * dh as f64,
+        ];
+        self.orientation.to_stored(shown, w as f64, h as f64)
+    }
 
-                        if let Some(out) =
-                            rfd::FileDialog::new().set_file_name(file_name).save_file()
+    /// Inverse of [`App::screen_to_pixel`]: screen position of a full-res pixel's center.
+    fn pixel_to_screen(&self, img_rect: Rect, px: [u32; 2]) -> Pos2 {
+        self.image_to_screen(img_rect, [px[0] as f64 + 0.5, px[1] as f64 + 0.5])
+    }
+
+    /// Screen position of a point of the full-res image (pixel edges at whole
+    /// numbers).
+    fn image_to_screen(&self, img_rect: Rect, point: [f64; 2]) -> Pos2 {
+        let (w, h) = self.redactor.as_ref().map_or((1, 1), |r| r.dimensions());
+        let (dw, dh) = self.orientation.size(w, h);
+        let [x, y] = self.orientation.to_display(point, w as f64, h as f64);
+        Pos2::new(
+            img_rect.left() + (x / dw as f64) as f32 * img_rect.width(),
+            img_rect.top() + (y / dh as f64) as f32 * img_rect.height(),
+        )
+    }
+
+    /// Scale the view by `factor`, keeping the image point under `anchor` (the pointer)
+    /// where it is; without an anchor the canvas center stays put.
+    fn zoom_at(&mut self, factor: f32, anchor: Option<Pos2>, canvas_center: Pos2) {
+        let old = self.fit_scale;
+        self.fit_scale = (old * factor).clamp(0.05, 20.0);
+        let ratio = self.fit_scale / old;
+        let anchor = anchor.unwrap_or(canvas_center);
+        let center = canvas_center + self.pan;
+        let new_center = anchor + (center - anchor) * ratio;
+        self.pan = new_center - canvas_center;
+    }
+
+    /// Measuring: drag a line, kept on screen until the next one.
+    fn handle_measure(&mut self, response: &egui::Response, img_rect: Rect) {
+        let Some(pos) = response.interact_pointer_pos() else {
+            return;
+        };
+        let point = self.screen_to_image(img_rect, pos);
+        if response.drag_started() {
+            self.measure = Some([point, point]);
+        } else if response.dragged()
+            && let Some([_, end]) = &mut self.measure
+        {
+            *end = point;
+        } else if response.clicked() {
+            self.measure = None;
+        }
+    }
+
+    /// Length of the measured line: in mm with a pixel spacing, always in pixels.
+    fn measure_text(&self, [a, b]: [[f64; 2]; 2]) -> String {
+        let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+        let pixels = dx.hypot(dy);
+        match self.redactor.as_ref().and_then(|r| r.pixel_spacing()) {
+            Some([row, col]) => format!("{:.1} mm ({pixels:.0} px)", (dx * col).hypot(dy * row)),
+            None => format!("{pixels:.0} px"),
+        }
+    }
+
+    /// Darken everything outside `kept`: what Apply fills in "Keep inside" mode, or
+    /// what the crop leaves out.
+    fn shade_outside(&self, painter: &egui::Painter, img_rect: Rect, kept: &[Shape]) {
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        if kept.is_empty() {
+            return;
+        }
+        let (width, height) = redactor.dimensions();
+        for r in outside(kept, width, height) {
+            let corners = Rect::from_two_pos(
+                self.image_to_screen(img_rect, [r.x0 as f64, r.y0 as f64]),
+                self.image_to_screen(img_rect, [r.x1 as f64, r.y1 as f64]),
+            );
+            painter.rect_filled(corners, 0.0, egui::Color32::from_black_alpha(150));
+        }
+    }
+
+    /// Lines every `grid_step` mm (pixels without a pixel spacing) over the image,
+    /// left out when they would be too dense to see the image through.
+    fn paint_grid(&self, painter: &egui::Painter, img_rect: Rect) {
+        let Some(redactor) = self.redactor.as_ref() else {
+            return;
+        };
+        let (w, h) = redactor.dimensions();
+        let [row, col] = redactor.pixel_spacing().unwrap_or([1.0, 1.0]);
+        let stroke = Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(0, 255, 255, 90));
+        let (w, h) = (w as f64, h as f64);
+        for (step, extent, across, vertical) in [
+            (self.grid_step / col, w, h, true),
+            (self.grid_step / row, h, w, false),
+        ] {
+            let gap = self.image_to_screen(img_rect, [0.0, 0.0]).distance(
+                self.image_to_screen(img_rect, if vertical { [step, 0.0] } else { [0.0, step] }),
+            );
+            if gap < 4.0 {
+                continue;
+            }
+            let mut at = step;
+            while at < extent {
+                let (a, b) = if vertical {
+                    ([at, 0.0], [at, across])
+                } else {
+                    ([0.0, at], [across, at])
+                };
+                painter.line_segment(
+                    [
+                        self.image_to_screen(img_rect, a),
+                        self.image_to_screen(img_rect, b),
+                    ],
+                    stroke,
+                );
+                at += step;
+            }
+        }
+    }
+
+    /// Rectangle/ellipse tools: drag out a bounding box, stage it on release.
+    fn handle_box_drag(&mut self, response: &egui::Response, img_rect: Rect) {
+        if response.drag_started()
+            && let Some(px) =
+                self.screen_to_pixel(img_rect, response.interact_pointer_pos().unwrap())
+        {
+            self.drag_start_px = Some(px);
+            self.drag_start_screen = response.interact_pointer_pos();
+            self.drag_current_screen = self.drag_start_screen;
+        }
+        if response.dragged() {
+            self.drag_current_screen = response.interact_pointer_pos();
+        }
+        if response.drag_stopped() {
+            if let (Some(start_px), Some(curr_screen)) =
+                (self.drag_start_px.take(), self.drag_current_screen.take())
+                && let Some(end_px) = self.screen_to_pixel(img_rect, curr_screen)
+            {
+                let rect = RedactRect {
+                    x0: start_px[0].min(end_px[0]),
+                    y0: start_px[1].min(end_px[1]),
+                    x1: start_px[0].max(end_px[0]) + 1, // exclusive
+                    y1: start_px[1].max(end_px[1]) + 1,
+                };
+                let shape = match self.shape_kind {
+                    ShapeKind::Ellipse => Shape::Ellipse(rect),
+                    _ => Shape::Rect(rect),
+                };
+                self.stage_shape(shape);
+            }
+            self.drag_start_screen = None;
+        }
+    }
+
+    /// Close the in-progress polygon/lasso and stage it (needs at least 3 vertices).
+    fn finish_polygon(&mut self) {
+        let points = std::mem::take(&mut self.shape_points);
+        if points.len() >= 3 {
+            self.stage_shape(Shape::Polygon(points));
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, app_frame: &mut eframe::Frame) {
+        self.apply_theme(ctx, app_frame);
+        self.poll_loading(ctx);
+        self.poll_batch_queue();
+        self.poll_send();
+        self.poll_query(ctx);
+        self.poll_cloud(ctx);
+        if ctx.input(|i| i.viewport().close_requested())
+            && !self.close_confirmed
+            && self.unsaved_tab_count() > 0
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.pending_action = Some(PendingAction::Close);
+        } else if ctx.input(|i| i.viewport().close_requested()) {
+            self.save_settings();
+            // Closed on purpose: nothing to recover, even if changes were discarded
+            if self.recovery.is_none()
+                && let Some(path) = project::default_recovery_path()
+            {
+                remove_recovery_file(&path);
+            }
+        }
+        if let Some(rect) = ctx.input(|i| i.viewport().inner_rect) {
+            self.window_size = Some(rect.size());
+        }
+
+        // While a new binding is being recorded, keys go to the shortcuts window
+        if self.capturing_shortcut.is_none() {
+            let typing = ctx.wants_keyboard_input();
+            for action in self.shortcuts.pressed(ctx, typing) {
+                self.run_shortcut(ctx, action);
+            }
+        }
+
+        // Keys typed into a text field (metadata, template name...) are not shortcuts
+        if !ctx.wants_keyboard_input() {
+            if ctx.input(|i| i.key_pressed(Key::Enter)) {
+                self.finish_polygon();
+            } else if ctx.input(|i| i.key_pressed(Key::Escape)) {
+                self.shape_points.clear();
+                self.selected_region = None;
+            }
+            if ctx.input(|i| i.key_pressed(Key::Delete) || i.key_pressed(Key::Backspace)) {
+                self.delete_selected_region();
+            }
+            if self.selected_region.is_some() {
+                let (dx, dy, modifiers) = ctx.input(|i| {
+                    let axis =
+                        |neg, pos| i64::from(i.key_pressed(pos)) - i64::from(i.key_pressed(neg));
+                    (
+                        axis(Key::ArrowLeft, Key::ArrowRight),
+                        axis(Key::ArrowUp, Key::ArrowDown),
+                        i.modifiers,
+                    )
+                });
+                if dx != 0 || dy != 0 {
+                    let step = if modifiers.shift { 10 } else { 1 };
+                    self.nudge_selected(dx * step, dy * step, modifiers.command);
+                }
+            }
+            // Ctrl+C/V usually arrive as copy/paste events rather than key presses
+            let (copy, paste) = ctx.input(|i| {
+                let command = |key| i.modifiers.command && i.key_pressed(key);
+                (
+                    command(Key::C) || i.events.iter().any(|e| matches!(e, egui::Event::Copy)),
+                    command(Key::V) || i.events.iter().any(|e| matches!(e, egui::Event::Paste(_))),
+                )
+            });
+            if copy {
+                self.copy_regions(ctx);
+            }
+            if paste {
+                self.paste_regions();
+            }
+        }
+
+        egui::TopBottomPanel::top("top").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button(tr!("toolbar.open_image"))
+                    .on_hover_text(self.shortcuts.hint(Action::Open))
+                    .clicked()
+                {
+                    self.open_image_dialog(ctx);
+                }
+
+                if ui
+                    .add_enabled(
+                        self.loading.is_none(),
+                        egui::Button::new(tr!("toolbar.open_in_tab")),
+                    )
+                    .on_hover_text(tr!("toolbar.open_in_tab_hover"))
+                    .clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter(
+                            tr!("dialog.images"),
+                            &[
+                                "png", "jpg", "jpeg", "tiff", "tif", "dcm", "dicom", "ima", "gz",
+                            ],
+                        )
+                        .add_filter(tr!("dialog.all_files"), &["*"])
+                        .pick_file()
+                {
+                    self.open_in_new_tab(ctx, path);
+                }
+
+                if ui
+                    .button(tr!("toolbar.open_folder"))
+                    .on_hover_text(self.shortcuts.hint(Action::OpenFolder))
+                    .clicked()
+                {
+                    self.open_folder_dialog(ctx);
+                }
+                ui.menu_button(tr!("toolbar.project"), |ui| {
+                    if ui
+                        .button(tr!("toolbar.open_project"))
+                        .on_hover_text(tr!("toolbar.open_project_hover"))
+                        .clicked()
+                    {
+                        ui.close_menu();
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter(tr!("dialog.projects"), &[project::EXTENSION])
+                            .pick_file()
                         {
-                            if self.is_dcm {
-                                if let Some(dcm) = self.dcm.as_mut() {
-                                    write_dynamic_image_to_dicom(dcm, img, &out);
+                            self.guard(ctx, PendingAction::OpenPaths(vec![path]));
+                        }
+                    }
+                    if ui
+                        .add_enabled(
+                            self.opened_path.is_some(),
+                            egui::Button::new(tr!("toolbar.save_project")),
+                        )
+                        .on_hover_text(tr!("toolbar.save_project_hover"))
+                        .clicked()
+                    {
+                        ui.close_menu();
+                        self.save_project_dialog();
+                    }
+                });
+                if ui
+                    .button(tr!("toolbar.query_pacs"))
+                    .on_hover_text(tr!("toolbar.query_pacs_hover"))
+                    .clicked()
+                {
+                    self.show_query = true;
+                }
+                if ui
+                    .button(tr!("toolbar.object_storage"))
+                    .on_hover_text(tr!("toolbar.object_storage_hover"))
+                    .clicked()
+                {
+                    self.show_cloud = true;
+                }
+
+                self.console.button(ui);
+
+                let save_dir = self.save_dir();
+                let save_hover = save_dir.as_ref().map_or_else(String::new, |d| {
+                    tr!(
+                        "toolbar.save_hover",
+                        dir = d.display(),
+                        shortcut = self.shortcuts.hint(Action::Save)
+                    )
+                });
+                if ui
+                    .add_enabled(
+                        self.redactor.is_some() && save_dir.is_some(),
+                        egui::Button::new(tr!("toolbar.save")),
+                    )
+                    .on_hover_text(save_hover)
+                    .clicked()
+                {
+                    self.save(ctx);
+                }
+
+                if ui
+                    .button(tr!("toolbar.save_as"))
+                    .on_hover_text(self.shortcuts.hint(Action::SaveAs))
+                    .clicked()
+                {
+                    self.save_as(ctx);
+                }
+
+                if ui
+                    .add_enabled(
+                        self.redactor.is_some(),
+                        egui::Button::new(tr!("toolbar.export_image")),
+                    )
+                    .on_hover_text(tr!("toolbar.export_image_hover"))
+                    .clicked()
+                {
+                    self.export_image(ctx);
+                }
+                if ui
+                    .add_enabled(
+                        self.redactor.is_some(),
+                        egui::Button::new(tr!("toolbar.send")),
+                    )
+                    .on_hover_text(tr!("toolbar.send_hover"))
+                    .clicked()
+                {
+                    self.show_send = true;
+                }
+                ui.checkbox(&mut self.export_windowed, tr!("toolbar.with_window"))
+                    .on_hover_text(tr!("toolbar.with_window_hover"));
+
+                let output_label = match &self.output_dir {
+                    Some(dir) => tr!("toolbar.output", dir = dir.display()),
+                    None => tr!("toolbar.output", dir = "redacted/"),
+                };
+                let output_button = ui
+                    .button(output_label)
+                    .on_hover_text(tr!("toolbar.output_hover"));
+                if output_button.clicked()
+                    && let Some(dir) = rfd::FileDialog::new()
+                        .set_title(tr!("toolbar.output_title"))
+                        .pick_folder()
+                {
+                    self.output_dir = Some(dir);
+                }
+                if output_button.secondary_clicked() {
+                    self.output_dir = None;
+                }
+
+                let pattern_valid = self.name_pattern().is_ok();
+                let pattern_edit = egui::TextEdit::singleline(&mut self.name_pattern)
+                    .hint_text("{name}")
+                    .desired_width(180.0);
+                let pattern_edit = if pattern_valid {
+                    pattern_edit
+                } else {
+                    pattern_edit.text_color(ui.visuals().error_fg_color)
+                };
+                ui.add(pattern_edit)
+                    .on_hover_text(tr!("toolbar.name_pattern_hover"));
+
+                if ui
+                    .add_enabled(
+                        self.opened_path.is_some(),
+                        egui::Button::new(tr!("toolbar.reset")),
+                    )
+                    .on_hover_text(tr!(
+                        "toolbar.reset_hover",
+                        shortcut = self.shortcuts.hint(Action::Reset)
+                    ))
+                    .clicked()
+                {
+                    self.guard(ctx, PendingAction::Reset);
+                }
+
+                if ui
+                    .add_enabled(
+                        self.redactor.is_some(),
+                        egui::Button::new(tr!("toolbar.auto_detect")),
+                    )
+                    .on_hover_text(tr!("toolbar.auto_detect_hover"))
+                    .clicked()
+                {
+                    self.auto_detect_text();
+                }
+                if ui
+                    .add_enabled(
+                        self.redactor.is_some(),
+                        egui::Button::new(tr!("toolbar.suggest")),
+                    )
+                    .on_hover_text(tr!("toolbar.suggest_hover"))
+                    .clicked()
+                {
+                    self.suggest_regions();
+                }
+                if self.plugins.has_detectors()
+                    && ui
+                        .add_enabled(
+                            self.redactor.is_some(),
+                            egui::Button::new(tr!("toolbar.run_detectors")),
+                        )
+                        .on_hover_text(tr!(
+                            "toolbar.run_detectors_hover",
+                            detectors = self.plugins.detector_names().join(", ")
+                        ))
+                        .clicked()
+                {
+                    self.run_detectors();
+                }
+                let has_us_regions = self
+                    .redactor
+                    .as_ref()
+                    .is_some_and(|r| !r.ultrasound_mask().is_empty());
+                if self.redactor.as_ref().is_some_and(Redactor::is_dcm) {
+                    self.preset_menu(ui);
+                }
+                ui.add_enabled_ui(self.redactor.is_some(), |ui| self.borders_menu(ui))
+                    .response
+                    .on_hover_text(tr!("toolbar.borders_hover"));
+                if has_us_regions
+                    && ui
+                        .button(tr!("toolbar.mask_ultrasound"))
+                        .on_hover_text(tr!("toolbar.mask_ultrasound_hover"))
+                        .clicked()
+                {
+                    self.mask_ultrasound();
+                }
+                if ui
+                    .add_enabled(
+                        self.redactor.is_some(),
+                        egui::Button::new(tr!("toolbar.import_regions")),
+                    )
+                    .on_hover_text(tr!("toolbar.import_regions_hover"))
+                    .clicked()
+                {
+                    self.import_regions();
+                }
+
+                if ui
+                    .add_enabled(
+                        !self.regions.is_empty(),
+                        egui::Button::new(tr!("toolbar.apply", count = self.regions.len())),
+                    )
+                    .on_hover_text(tr!(
+                        "toolbar.apply_hover",
+                        shortcut = self.shortcuts.hint(Action::Apply)
+                    ))
+                    .clicked()
+                {
+                    self.apply_regions(ctx);
+                }
+                ui.checkbox(&mut self.keep_inside, tr!("toolbar.keep_inside"))
+                    .on_hover_text(tr!("toolbar.keep_inside_hover"));
+                if let Some(crop) = self.crop
+                    && ui
+                        .button(tr!("toolbar.clear_crop"))
+                        .on_hover_text(tr!(
+                            "toolbar.clear_crop_hover",
+                            width = crop.x1 - crop.x0,
+                            height = crop.y1 - crop.y0
+                        ))
+                        .clicked()
+                {
+                    self.crop = None;
+                }
+
+                ui.toggle_value(&mut self.show_metadata, tr!("toolbar.metadata"))
+                    .on_hover_text(tr!("toolbar.metadata_hover"));
+                if ui
+                    .add_enabled(
+                        self.redactor.as_ref().is_some_and(|r| r.is_dcm()),
+                        egui::Button::new(tr!("toolbar.scan_phi")),
+                    )
+                    .on_hover_text(tr!("toolbar.scan_phi_hover"))
+                    .clicked()
+                {
+                    self.scan_phi();
+                }
+                if ui
+                    .add_enabled(
+                        self.redactor.is_some(),
+                        egui::Button::new(tr!("toolbar.preview")),
+                    )
+                    .on_hover_text(tr!("toolbar.preview_hover"))
+                    .clicked()
+                {
+                    self.preview_anonymization();
+                }
+
+                if ui
+                    .button(tr!("toolbar.templates"))
+                    .on_hover_text(tr!("toolbar.templates_hover"))
+                    .clicked()
+                {
+                    self.show_templates = !self.show_templates;
+                    if self.show_templates {
+                        self.refresh_templates();
+                    }
+                }
+
+                if ui
+                    .button(tr!("toolbar.settings"))
+                    .on_hover_text(tr!("toolbar.settings_hover"))
+                    .clicked()
+                {
+                    self.show_settings = !self.show_settings;
+                }
+
+                if ui
+                    .add_enabled(
+                        self.history.can_undo(),
+                        egui::Button::new(tr!("toolbar.undo")),
+                    )
+                    .on_hover_text(self.shortcuts.hint(Action::Undo))
+                    .clicked()
+                {
+                    self.undo(ctx);
+                }
+                if ui
+                    .add_enabled(
+                        self.history.can_redo(),
+                        egui::Button::new(tr!("toolbar.redo")),
+                    )
+                    .on_hover_text(self.shortcuts.hint(Action::Redo))
+                    .clicked()
+                {
+                    self.redo(ctx);
+                }
+
+                ui.checkbox(&mut self.deidentify, tr!("toolbar.deidentify"))
+                    .on_hover_text(tr!("toolbar.deidentify_hover"));
+
+                ui.add_enabled_ui(self.deidentify, |ui| {
+                    egui::ComboBox::new("profile", tr!("toolbar.profile"))
+                        .selected_text(&self.profile.name)
+                        .show_ui(ui, |ui| {
+                            for profile in std::iter::once(Profile::default())
+                                .chain(self.profiles.iter().cloned())
+                            {
+                                let name = profile.name.clone();
+                                let hover = profile.description.clone();
+                                let selected = self.profile.name == name;
+                                if ui
+                                    .selectable_label(selected, name)
+                                    .on_hover_text(hover)
+                                    .clicked()
+                                {
+                                    self.profile = profile;
                                 }
-                            } else {
-                                let _ = img.save(out);
                             }
+                            ui.separator();
+                            if ui.button(tr!("toolbar.reload_profiles")).clicked() {
+                                self.reload_profiles();
+                            }
+                        })
+                        .response
+                        .on_hover_text(tr!("toolbar.profile_hover"));
+                });
+
+                ui.checkbox(&mut self.remove_private, tr!("toolbar.remove_private"))
+                    .on_hover_text(tr!("toolbar.remove_private_hover"));
+                if self.remove_private {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.private_whitelist)
+                            .hint_text(tr!("toolbar.keep_creators"))
+                            .desired_width(120.0),
+                    )
+                    .on_hover_text(tr!("toolbar.keep_creators_hover"));
+                }
+
+                ui.checkbox(&mut self.strip_overlays, tr!("toolbar.remove_overlays"))
+                    .on_hover_text(tr!("toolbar.remove_overlays_hover"));
+
+                ui.add_enabled(
+                    !self.deidentify,
+                    egui::Checkbox::new(&mut self.regenerate_uids, tr!("toolbar.new_uid")),
+                )
+                .on_hover_text(tr!("toolbar.new_uid_hover"));
+
+                let uid_map_label = match &self.uid_map_path {
+                    Some(p) => tr!("toolbar.uid_map_set", path = p.display()),
+                    None => tr!("toolbar.uid_map"),
+                };
+                let uid_map_button = ui
+                    .button(uid_map_label)
+                    .on_hover_text(tr!("toolbar.uid_map_hover"));
+                if uid_map_button.clicked()
+                    && let Some(p) = rfd::FileDialog::new()
+                        .add_filter("CSV", &["csv"])
+                        .set_file_name("uid_map.csv")
+                        .save_file()
+                {
+                    self.uid_map_path = Some(p);
+                }
+                if uid_map_button.secondary_clicked() {
+                    self.uid_map_path = None;
+                }
+
+                let audit_label = match &self.audit_log_path {
+                    Some(p) => tr!("toolbar.audit_log_set", path = p.display()),
+                    None => tr!("toolbar.audit_log"),
+                };
+                let audit_button = ui
+                    .button(audit_label)
+                    .on_hover_text(tr!("toolbar.audit_log_hover"));
+                if audit_button.clicked()
+                    && let Some(p) = rfd::FileDialog::new()
+                        .add_filter(tr!("dialog.json_lines"), &["jsonl"])
+                        .set_file_name("audit.jsonl")
+                        .save_file()
+                {
+                    self.audit_log_path = Some(p);
+                }
+                if audit_button.secondary_clicked() {
+                    self.audit_log_path = None;
+                }
+                ui.checkbox(&mut self.regions_sidecar, tr!("toolbar.sidecar"))
+                    .on_hover_text(tr!("toolbar.sidecar_hover"));
+                ui.checkbox(&mut self.write_seg, tr!("toolbar.write_seg"))
+                    .on_hover_text(tr!("toolbar.write_seg_hover"));
+                ui.checkbox(
+                    &mut self.secondary_capture,
+                    tr!("toolbar.secondary_capture"),
+                )
+                .on_hover_text(tr!("toolbar.secondary_capture_hover"));
+                ui.checkbox(&mut self.stamp_on, tr!("toolbar.stamp"))
+                    .on_hover_text(tr!("toolbar.stamp_hover"));
+                if self.stamp_on {
+                    ui.add(egui::TextEdit::singleline(&mut self.stamp.text).desired_width(110.0));
+                    egui::ComboBox::from_id_source("stamp_corner")
+                        .selected_text(self.stamp.corner.label())
+                        .show_ui(ui, |ui| {
+                            for corner in Corner::ALL {
+                                ui.selectable_value(&mut self.stamp.corner, corner, corner.label());
+                            }
+                        });
+                    ui.add(
+                        egui::DragValue::new(&mut self.stamp.size)
+                            .speed(0.1)
+                            .range(1.0..=20.0)
+                            .suffix(" %"),
+                    )
+                    .on_hover_text(tr!("toolbar.stamp_size_hover"));
+                }
+                egui::ComboBox::from_label(tr!("toolbar.slide_labels"))
+                    .selected_text(self.slide_labels.label())
+                    .show_ui(ui, |ui| {
+                        for action in SlideLabels::ALL {
+                            ui.selectable_value(&mut self.slide_labels, action, action.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text(tr!("toolbar.slide_labels_hover"));
+                egui::ComboBox::from_label(tr!("toolbar.documents"))
+                    .selected_text(self.documents.label())
+                    .show_ui(ui, |ui| {
+                        for action in Documents::ALL {
+                            ui.selectable_value(&mut self.documents, action, action.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text(tr!("toolbar.documents_hover"));
+                egui::ComboBox::from_label(tr!("toolbar.deface"))
+                    .selected_text(self.deface.label())
+                    .show_ui(ui, |ui| {
+                        for how in Deface::ALL {
+                            ui.selectable_value(&mut self.deface, how, how.label());
                         }
+                    })
+                    .response
+                    .on_hover_text(tr!("toolbar.deface_hover"));
+
+                let pseudonym_label = match &self.pseudonyms {
+                    Some(t) => tr!(
+                        "toolbar.pseudonyms_set",
+                        path = t.path().display(),
+                        count = t.len()
+                    ),
+                    None => tr!("toolbar.pseudonyms"),
+                };
+                let pseudonym_button = ui
+                    .button(pseudonym_label)
+                    .on_hover_text(tr!("toolbar.pseudonyms_hover"));
+                if pseudonym_button.clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .set_title(tr!("dialog.pseudonym_table"))
+                        .set_file_name("pseudonyms.csv")
+                        .save_file()
+                {
+                    // The table holds the original identifiers: without a key, it isn't used
+                    match rfd::FileDialog::new()
+                        .set_title(tr!("dialog.pseudonym_key"))
+                        .set_file_name("pseudonyms.key")
+                        .save_file()
+                    {
+                        Some(key_file) => match open_pseudonyms(&path, Some(&key_file)) {
+                            Ok(table) => self.pseudonyms = Some(table),
+                            Err(e) => self.console.push(e),
+                        },
+                        None => self.console.push(tr!("notice.pseudonyms_need_key")),
+                    }
+                }
+                if pseudonym_button.secondary_clicked() {
+                    self.pseudonyms = None;
+                }
+
+                egui::ComboBox::from_label(tr!("toolbar.transfer_syntax"))
+                    .selected_text(self.transfer_syntax.label())
+                    .show_ui(ui, |ui| {
+                        let options = [
+                            OutputTransferSyntax::Uncompressed,
+                            OutputTransferSyntax::Original,
+                        ]
+                        .into_iter()
+                        .chain(
+                            COMMON_TRANSFER_SYNTAXES
+                                .iter()
+                                .map(|uid| OutputTransferSyntax::Uid(uid.to_string())),
+                        );
+                        for ts in options {
+                            let label = ts.label();
+                            ui.selectable_value(&mut self.transfer_syntax, ts, label);
+                        }
+                    })
+                    .response
+                    .on_hover_text(tr!("toolbar.transfer_syntax_hover"));
+
+                ui.add(egui::Slider::new(&mut self.fit_scale, 0.1..=5.0).text(tr!("view.zoom")));
+                if self
+                    .redactor
+                    .as_ref()
+                    .is_some_and(|r| !r.is_document() && r.tiled().is_none())
+                {
+                    let o = self.orientation;
+                    for (label, hover, turned) in [
+                        ("↺".to_string(), tr!("view.rotate_ccw"), o.rotated_ccw()),
+                        ("↻".to_string(), tr!("view.rotate_cw"), o.rotated_cw()),
+                        (tr!("view.flip_h"), tr!("view.flip_h_hover"), o.flipped_h()),
+                        (tr!("view.flip_v"), tr!("view.flip_v_hover"), o.flipped_v()),
+                    ] {
+                        if ui.button(label).on_hover_text(hover).clicked() {
+                            self.set_orientation(ctx, turned);
+                        }
+                    }
+                    ui.add_enabled(
+                        !o.is_identity(),
+                        egui::Checkbox::new(&mut self.save_rotated, tr!("view.save_as_shown")),
+                    )
+                    .on_hover_text(tr!("view.save_as_shown_hover"));
+                }
+                ui.checkbox(&mut self.loupe, tr!("view.loupe"))
+                    .on_hover_text(tr!("view.loupe_hover"));
+                if self.loupe {
+                    ui.add(
+                        egui::DragValue::new(&mut self.loupe_zoom)
+                            .speed(0.05)
+                            .range(2.0..=8.0)
+                            .suffix("×"),
+                    )
+                    .on_hover_text(tr!("view.loupe_zoom_hover"));
+                }
+                ui.checkbox(&mut self.grid, tr!("view.grid"))
+                    .on_hover_text(tr!("view.grid_hover"));
+                if self.grid {
+                    let unit = if self
+                        .redactor
+                        .as_ref()
+                        .and_then(|r| r.pixel_spacing())
+                        .is_some()
+                    {
+                        " mm"
+                    } else {
+                        " px"
+                    };
+                    ui.add(
+                        egui::DragValue::new(&mut self.grid_step)
+                            .speed(0.5)
+                            .range(1.0..=1000.0)
+                            .suffix(unit),
+                    )
+                    .on_hover_text(tr!("view.grid_step_hover"));
+                }
+                if ui
+                    .toggle_value(&mut self.measuring, tr!("view.measure"))
+                    .on_hover_text(tr!("view.measure_hover"))
+                    .changed()
+                    && !self.measuring
+                {
+                    self.measure = None;
+                }
+                ui.checkbox(&mut self.compare, tr!("view.compare"))
+                    .on_hover_text(tr!(
+                        "view.compare_hover",
+                        keys = self
+                            .shortcuts
+                            .get(Action::PeekOriginal)
+                            .map_or_else(|| tr!("view.peek_shortcut"), |s| format_binding(&s))
+                    ));
+
+                let gray = self
+                    .redactor
+                    .as_ref()
+                    .is_some_and(|r| !r.frame(self.current_frame).is_color());
+                if gray {
+                    let mut window = self.current_window();
+                    let speed = window.width / 200.0;
+                    let changed = ui
+                        .add(
+                            egui::DragValue::new(&mut window.center)
+                                .speed(speed)
+                                .prefix("L "),
+                        )
+                        .on_hover_text(tr!("view.level_hover"))
+                        .changed()
+                        | ui.add(
+                            egui::DragValue::new(&mut window.width)
+                                .speed(speed)
+                                .range(1.0..=f64::MAX)
+                                .prefix("W "),
+                        )
+                        .on_hover_text(tr!("view.width_hover"))
+                        .changed();
+                    if changed {
+                        self.window = Some(window);
+                        self.rebuild_display_from_full(ctx);
+                    }
+                    if ui
+                        .button(tr!("view.auto_window"))
+                        .on_hover_text(tr!("view.auto_window_hover"))
+                        .clicked()
+                    {
+                        self.auto_window(ctx);
+                    }
+                    ui.toggle_value(&mut self.show_histogram, tr!("histogram.title"))
+                        .on_hover_text(tr!("view.histogram_hover"));
+                    if !self.voi_presets.is_empty() {
+                        let is_default = self.window.is_none() && self.voi_preset.is_none();
+                        let selected = match (self.window, self.voi_preset) {
+                            (Some(_), _) => tr!("view.voi_custom"),
+                            (None, Some(i)) => self.voi_presets[i].label.clone(),
+                            (None, None) => tr!("view.voi_default"),
+                        };
+                        let mut choice = None;
+                        egui::ComboBox::from_id_source("voi")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                if ui
+                                    .selectable_label(is_default, tr!("view.voi_default"))
+                                    .clicked()
+                                {
+                                    choice = Some(None);
+                                }
+                                for (i, preset) in self.voi_presets.iter().enumerate() {
+                                    let current =
+                                        self.window.is_none() && self.voi_preset == Some(i);
+                                    if ui.selectable_label(current, &preset.label).clicked() {
+                                        choice = Some(Some(i));
+                                    }
+                                }
+                            })
+                            .response
+                            .on_hover_text(tr!("view.voi_hover"));
+                        if let Some(preset) = choice {
+                            self.window = None;
+                            self.voi_preset = preset;
+                            self.rebuild_display_from_full(ctx);
+                        }
+                    }
+                    let default_preset = (!self.voi_presets.is_empty()).then_some(0);
+                    if ui
+                        .add_enabled(
+                            self.window.is_some() || self.voi_preset != default_preset,
+                            egui::Button::new(tr!("view.reset_window")),
+                        )
+                        .clicked()
+                    {
+                        self.window = None;
+                        self.voi_preset = default_preset;
+                        self.rebuild_display_from_full(ctx);
+                    }
+                }
+
+                let frame_count = self.redactor.as_ref().map_or(1, |r| r.frame_count());
+                if frame_count > 1 {
+                    // Multi-page TIFFs have pages rather than frames
+                    let pages = self.redactor.as_ref().is_some_and(|r| !r.is_dcm());
+                    let prev_frame = self.current_frame;
+                    ui.add(
+                        egui::Slider::new(&mut self.current_frame, 0..=frame_count - 1).text(
+                            if pages {
+                                tr!("view.page")
+                            } else {
+                                tr!("view.frame")
+                            },
+                        ),
+                    );
+                    if self.current_frame != prev_frame {
+                        self.rebuild_display_from_full(ctx);
+                    }
+                    let (this, all) = if pages {
+                        (tr!("view.this_page"), tr!("view.all_pages"))
+                    } else {
+                        (tr!("view.this_frame"), tr!("region.all_frames"))
+                    };
+                    ui.radio_value(&mut self.redact_all_frames, false, this);
+                    ui.radio_value(&mut self.redact_all_frames, true, all);
+                }
+
+                let associated = self.redactor.as_ref().and_then(Redactor::associated_image);
+                if let Some(flavor) = associated {
+                    ui.label(tr!("slide.associated", flavor = flavor))
+                        .on_hover_text(tr!("slide.associated_hover"));
+                    if ui
+                        .button(tr!("slide.blank"))
+                        .on_hover_text(tr!("slide.blank_hover"))
+                        .clicked()
+                    {
+                        self.blank_image(ctx);
+                    }
+                }
+
+                let slide = self
+                    .redactor
+                    .as_ref()
+                    .and_then(|r| Some((r.tiled()?.size(), r.view_origin())));
+                if let Some(((width, height), (x, y))) = slide {
+                    ui.label(tr!("slide.size", size = format!("{width}×{height}")))
+                        .on_hover_text(tr!("slide.size_hover"));
+                    let (mut to_x, mut to_y) = (x, y);
+                    ui.add(
+                        egui::DragValue::new(&mut to_x)
+                            .range(0..=width)
+                            .prefix("x "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut to_y)
+                            .range(0..=height)
+                            .prefix("y "),
+                    );
+                    let step = wsi::VIEW_SIZE;
+                    if ui.button("◀").on_hover_text(tr!("slide.left")).clicked() {
+                        to_x = x.saturating_sub(step);
+                    }
+                    if ui.button("▶").on_hover_text(tr!("slide.right")).clicked() {
+                        to_x = x.saturating_add(step);
+                    }
+                    if ui.button("▲").on_hover_text(tr!("slide.up")).clicked() {
+                        to_y = y.saturating_sub(step);
+                    }
+                    if ui.button("▼").on_hover_text(tr!("slide.down")).clicked() {
+                        to_y = y.saturating_add(step);
+                    }
+                    if (to_x, to_y) != (x, y) {
+                        self.move_view(ctx, to_x, to_y);
                     }
                 }
 
-                if ui.button("Reset").clicked() {
-                    if let Some(p) = self.opened_path.as_ref().cloned() {
-                        if let Err(e) = self.load_image(ctx, p) {
-                            self.last_error = Some(e.to_string());
+                egui::ComboBox::from_label(tr!("view.fill"))
+                    .selected_text(self.fill.label())
+                    .show_ui(ui, |ui| {
+                        for fill in RedactFill::ALL {
+                            let selected = self.fill.same_style(fill);
+                            if ui.selectable_label(selected, fill.label()).clicked() && !selected {
+                                self.fill = fill;
+                            }
                         }
+                    })
+                    .response
+                    .on_hover_text(tr!("view.fill_hover"));
+                let max_value = self.redactor.as_ref().map_or(u16::MAX, Redactor::max_value);
+                match &mut self.fill {
+                    RedactFill::Constant(v) => {
+                        ui.add(
+                            egui::DragValue::new(v)
+                                .range(0..=max_value)
+                                .prefix(tr!("view.fill_value")),
+                        );
                     }
+                    RedactFill::Blur(sigma) => {
+                        ui.add(
+                            egui::DragValue::new(sigma)
+                                .range(0.5..=100.0)
+                                .speed(0.5)
+                                .prefix("σ "),
+                        );
+                    }
+                    RedactFill::Mosaic(block) => {
+                        ui.add(
+                            egui::DragValue::new(block)
+                                .range(2..=256)
+                                .prefix(tr!("view.fill_block")),
+                        );
+                    }
+                    _ => {}
+                }
+
+                let prev_kind = self.shape_kind;
+                egui::ComboBox::from_label(tr!("region.shape"))
+                    .selected_text(self.shape_kind.label())
+                    .show_ui(ui, |ui| {
+                        for kind in ShapeKind::ALL {
+                            ui.selectable_value(&mut self.shape_kind, kind, kind.label());
+                        }
+                    });
+                if self.shape_kind != prev_kind {
+                    self.shape_points.clear();
                 }
 
-                ui.add(egui::Slider::new(&mut self.fit_scale, 0.1..=5.0).text("Zoom"));
-                ui.label("Drag to draw a box; release to blacken.");
+                ui.label(match self.shape_kind {
+                    ShapeKind::Rectangle | ShapeKind::Ellipse => tr!("view.draw_hint"),
+                    ShapeKind::Polygon => tr!("view.polygon_hint"),
+                    ShapeKind::Lasso => tr!("view.lasso_hint"),
+                });
+
+                if let Some(loading) = &self.loading {
+                    ui.spinner();
+                    let name = loading.path.file_name().unwrap_or_default();
+                    ui.label(tr!("view.loading", name = name.to_string_lossy()));
+                }
+
+                if let Some(notice) = &self.notice {
+                    ui.label(notice);
+                }
 
                 // Optional: show full and display dims to confirm behavior
-                if let (Some(full), Some((dw, dh))) = (self.gray_img.as_ref(), self.display_dims) {
-                    let (fw, fh) = full.dimensions();
-                    ui.label(format!("Full: {fw}×{fh}  Display: {dw}×{dh}"));
+                if let (Some(redactor), Some((dw, dh))) =
+                    (self.redactor.as_ref(), self.display_dims)
+                {
+                    let (fw, fh) = redactor.dimensions();
+                    ui.label(tr!(
+                        "view.dimensions",
+                        full = format!("{fw}×{fh}"),
+                        display = format!("{dw}×{dh}")
+                    ));
                 }
             });
         });
 
+        if self.redactor.is_some() || self.tabs.len() > 1 {
+            self.tab_bar(ctx);
+        }
+        self.status_bar(ctx);
+        self.update_title(ctx);
+        self.console.show(ctx);
+        self.load_next_thumbnail(ctx);
+        self.thumbnail_strip(ctx);
+        self.series_window(ctx);
+        if self.show_templates {
+            self.template_manager(ctx);
+        }
+        if self.show_metadata {
+            self.metadata_panel(ctx);
+        }
+        self.save_review_window(ctx);
+        self.phi_window(ctx);
+        self.preview_window(ctx);
+        self.region_inspector(ctx);
+        self.batch_queue_window(ctx);
+        if self.show_send {
+            self.send_window(ctx);
+        }
+        if self.show_query {
+            self.query_window(ctx);
+        }
+        if self.show_cloud {
+            self.cloud_window(ctx);
+        }
+        if self.show_settings {
+            self.settings_window(ctx);
+        }
+        if self.show_histogram {
+            self.histogram_window(ctx);
+        }
+        if self.show_shortcuts {
+            self.shortcuts_window(ctx);
+        }
+        self.unsaved_changes_window(ctx);
+        self.recovery_window(ctx);
+        self.autosave(ctx);
+        self.confirm_overwrite_window(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
+                // Documents have no image to show or redact
+                if self.redactor.as_ref().is_some_and(|r| r.is_document()) {
+                    self.document_view(ui);
+                    return;
+                }
+                let peek =
+                    self.shortcuts
+                        .held(ctx, Action::PeekOriginal, ctx.wants_keyboard_input());
+                if (self.compare || peek) && self.original_tex.is_none() {
+                    self.original_tex = self
+                        .original_image()
+                        .map(|ci| ctx.load_texture("original", ci, egui::TextureOptions::LINEAR));
+                }
                 if let (Some(tex), Some(ci)) = (self.tex.as_ref(), self.color_img.as_ref()) {
-                    let img_size = Vec2::new(ci.size[0] as f32, ci.size[1] as f32) * self.fit_scale;
-
-                    let response = ui.add(
-                        egui::Image::from_texture((tex.id(), img_size))
-                            .sense(Sense::click_and_drag()),
-                    );
-                    let img_rect = response.rect;
-
-                    // --- Zoom handling (scroll wheel / pinch) ---
-                    let zoom_speed: f32 = 1.0;
+                    // Non-square pixels are stretched to their physical proportions
+                    let img_size =
+                        Vec2::new(ci.size[0] as f32, ci.size[1] as f32 * self.display_aspect())
+                            * self.fit_scale;
+                    let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                    let label = |painter: &egui::Painter, canvas: Rect, text: &str| {
+                        painter.text(
+                            canvas.left_top() + Vec2::splat(6.0),
+                            egui::Align2::LEFT_TOP,
+                            text,
+                            egui::FontId::proportional(14.0),
+                            egui::Color32::YELLOW,
+                        );
+                    };
 
-                    let pinch_factor = ctx.input(|i| i.zoom_delta());
-                    if response.hovered() && pinch_factor != 1.0 {
-                        self.fit_scale = (self.fit_scale * pinch_factor).clamp(0.05, 20.0);
-                        ctx.request_repaint();
+                    // The image is painted into a canvas filling the panel, so it can be
+                    // panned past the panel edges. Comparing, the original gets the left
+                    // half, with the same zoom and pan.
+                    let available = ui.available_rect_before_wrap();
+                    let (original_canvas, canvas) = if self.compare {
+                        let mid = available.center().x;
+                        (
+                            Some(available.with_max_x(mid - 2.0)),
+                            available.with_min_x(mid + 2.0),
+                        )
+                    } else {
+                        (None, available)
+                    };
+                    let response = ui.allocate_rect(canvas, Sense::click_and_drag());
+                    let img_rect = Rect::from_center_size(canvas.center() + self.pan, img_size);
+                    let painter = ui.painter_at(canvas);
+                    let shown = match &self.original_tex {
+                        Some(original) if peek && !self.compare => original.id(),
+                        _ => tex.id(),
+                    };
+                    painter.image(shown, img_rect, uv, egui::Color32::WHITE);
+                    if self.grid {
+                        self.paint_grid(&painter, img_rect);
                     }
-
-                    let wheel_zoom = ctx.input(|i| {
-                        if response.hovered() {
-                            let dy = i.smooth_scroll_delta.y;
-                            if dy != 0.0 {
-                                let factor = (dy / 240.0 * zoom_speed).exp();
-                                Some(factor)
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
+                    if self.keep_inside {
+                        let kept: Vec<Shape> = self
+                            .regions
+                            .iter()
+                            .filter(|r| r.applies_to(self.current_frame))
+                            .map(|r| r.shape.clone())
+                            .collect();
+                        self.shade_outside(&painter, img_rect, &kept);
+                    }
+                    if let Some(crop) = self.crop {
+                        let crop = Shape::Rect(crop);
+                        self.shade_outside(&painter, img_rect, std::slice::from_ref(&crop));
+                        painter.add(egui::Shape::closed_line(
+                            self.shape_outline(img_rect, &crop),
+                            Stroke::new(1.5, egui::Color32::LIGHT_BLUE),
+                        ));
+                    }
+                    if let Some(original_canvas) = original_canvas {
+                        let original_response = ui.allocate_rect(original_canvas, Sense::drag());
+                        let original_painter = ui.painter_at(original_canvas);
+                        if let Some(original) = &self.original_tex {
+                            original_painter.image(
+                                original.id(),
+                                Rect::from_center_size(
+                                    original_canvas.center() + self.pan,
+                                    img_size,
+                                ),
+                                uv,
+                                egui::Color32::WHITE,
+                            );
                         }
-                    });
+                        label(&original_painter, original_canvas, &tr!("common.original"));
+                        label(&painter, canvas, &tr!("view.redacted"));
+                        if original_response.dragged() {
+                            self.pan += original_response.drag_delta();
+                        }
+                        self.scroll_zoom(ctx, &original_response, original_canvas.center());
+                    } else if peek {
+                        label(&painter, canvas, &tr!("common.original"));
+                    }
 
-                    if let Some(factor) = wheel_zoom {
-                        self.fit_scale = (self.fit_scale * factor).clamp(0.05, 20.0);
-                        ctx.request_repaint();
+                    self.scroll_zoom(ctx, &response, canvas.center());
+                    self.probe = response
+                        .hover_pos()
+                        .and_then(|p| self.screen_to_pixel(img_rect, p));
+
+                    // Middle-drag or Space+drag pans instead of drawing
+                    let typing = ctx.wants_keyboard_input();
+                    let pan_mode = ctx
+                        .input(|i| i.pointer.middle_down() || (i.key_down(Key::Space) && !typing));
+                    // Right-drag adjusts window/level: horizontal = width, vertical = level
+                    let window_mode = ctx.input(|i| i.pointer.secondary_down());
+                    if pan_mode && response.hovered() {
+                        ctx.set_cursor_icon(if response.dragged() {
+                            egui::CursorIcon::Grabbing
+                        } else {
+                            egui::CursorIcon::Grab
+                        });
                     }
-                    // --- end zoom handling ---
 
                     // Handle mouse interactions over the image
-                    if response.hovered() || response.dragged() || response.clicked() {
-                        if response.drag_started() {
-                            if let Some(px) = self
-                                .screen_to_pixel(img_rect, response.interact_pointer_pos().unwrap())
-                            {
-                                self.drag_start_px = Some(px);
-                                self.drag_start_screen = response.interact_pointer_pos();
-                                self.drag_current_screen = self.drag_start_screen;
-                            }
-                        }
+                    if pan_mode {
                         if response.dragged() {
-                            self.drag_current_screen = response.interact_pointer_pos();
+                            self.pan += response.drag_delta();
                         }
-                        if response.drag_stopped() {
-                            if let (Some(start_px), Some(curr_screen)) =
-                                (self.drag_start_px.take(), self.drag_current_screen.take())
-                            {
-                                if let Some(end_px) = self.screen_to_pixel(img_rect, curr_screen) {
-                                    let x0 = start_px[0].min(end_px[0]);
-                                    let y0 = start_px[1].min(end_px[1]);
-                                    let x1 = start_px[0].max(end_px[0]) + 1; // exclusive
-                                    let y1 = start_px[1].max(end_px[1]) + 1;
-                                    self.apply_blacken([x0, y0, x1, y1], ctx);
+                    } else if window_mode {
+                        let delta = response.drag_delta();
+                        if response.dragged()
+                            && delta != Vec2::ZERO
+                            && self
+                                .redactor
+                                .as_ref()
+                                .is_some_and(|r| !r.frame(self.current_frame).is_color())
+                        {
+                            let window = self.current_window();
+                            let step = self.full_window().width / 1024.0;
+                            self.window = Some(Window::new(
+                                window.center + delta.y as f64 * step,
+                                window.width + delta.x as f64 * step,
+                            ));
+                            self.rebuild_display_from_full(ctx);
+                        }
+                    } else if self.measuring {
+                        self.handle_measure(&response, img_rect);
+                    } else if response.hovered() || response.dragged() || response.clicked() {
+                        let pointer = response.interact_pointer_pos();
+                        if response.drag_started()
+                            && self.shape_points.is_empty()
+                            && let Some(pos) = pointer
+                        {
+                            self.begin_region_drag(img_rect, pos);
+                        }
+
+                        if self.region_drag.is_some() {
+                            if let Some(pos) = pointer {
+                                self.update_region_drag(img_rect, pos);
+                            }
+                            if response.drag_stopped() {
+                                self.region_drag = None;
+                            }
+                        } else if response.clicked()
+                            && self.shape_points.is_empty()
+                            && let Some(index) = pointer
+                                .and_then(|p| self.screen_to_pixel(img_rect, p))
+                                .and_then(|px| self.region_at(px))
+                        {
+                            // Clicking a staged region selects it instead of drawing
+                            self.selected_region = Some(index);
+                        } else {
+                            if response.clicked() {
+                                self.selected_region = None;
+                            }
+                            match self.shape_kind {
+                                ShapeKind::Rectangle | ShapeKind::Ellipse => {
+                                    self.handle_box_drag(&response, img_rect)
+                                }
+                                ShapeKind::Polygon => {
+                                    if response.clicked()
+                                        && let Some(px) =
+                                            pointer.and_then(|p| self.screen_to_pixel(img_rect, p))
+                                        && self.shape_points.last() != Some(&px)
+                                    {
+                                        self.shape_points.push(px);
+                                    }
+                                    if response.double_clicked() {
+                                        self.finish_polygon();
+                                    }
+                                }
+                                ShapeKind::Lasso => {
+                                    if response.drag_started() {
+                                        self.shape_points.clear();
+                                    }
+                                    if (response.drag_started() || response.dragged())
+                                        && let Some(px) =
+                                            pointer.and_then(|p| self.screen_to_pixel(img_rect, p))
+                                        && self.shape_points.last() != Some(&px)
+                                    {
+                                        self.shape_points.push(px);
+                                    }
+                                    if response.drag_stopped() {
+                                        self.finish_polygon();
+                                    }
                                 }
                             }
-                            self.drag_start_screen = None;
                         }
                     }
 
-                    // Draw temporary selection rectangle overlay
+                    // Draw staged regions; the selected one gets corner handles
+                    for (i, region) in self.regions.iter().enumerate() {
+                        if !region.applies_to(self.current_frame) {
+                            continue;
+                        }
+                        let color = if self.selected_region == Some(i) {
+                            egui::Color32::from_rgb(255, 140, 0)
+                        } else {
+                            egui::Color32::YELLOW
+                        };
+                        let outline = self.shape_outline(img_rect, &region.shape);
+                        painter.add(egui::Shape::closed_line(outline, Stroke::new(2.0, color)));
+                    }
+                    if let Some(corners) = self.selected_corners() {
+                        for c in corners {
+                            painter.circle_filled(
+                                self.pixel_to_screen(img_rect, c),
+                                HANDLE_RADIUS,
+                                egui::Color32::from_rgb(255, 140, 0),
+                            );
+                        }
+                    }
+
+                    // Draw temporary selection overlay
+                    let stroke = Stroke::new(2.0, egui::Color32::YELLOW);
                     if let (Some(p0), Some(p1)) = (self.drag_start_screen, self.drag_current_screen)
                     {
                         let rect = Rect::from_two_pos(p0, p1);
-                        ui.painter().rect_stroke(
-                            rect,
-                            0.0,
-                            Stroke::new(2.0, egui::Color32::YELLOW),
+                        if self.shape_kind == ShapeKind::Ellipse {
+                            painter.add(egui::Shape::closed_line(ellipse_points(rect), stroke));
+                        } else {
+                            painter.rect_stroke(rect, 0.0, stroke);
+                        }
+                        if let (Some(a), Some(b)) =
+                            (self.drag_start_px, self.screen_to_pixel(img_rect, p1))
+                        {
+                            let (width, height) =
+                                (a[0].abs_diff(b[0]) + 1, a[1].abs_diff(b[1]) + 1);
+                            let mut text = format!("{width} × {height} px");
+                            if let Some(physical) = self.physical_size(width, height) {
+                                text += &format!("  {physical}");
+                            }
+                            painter.text(
+                                rect.right_bottom() + Vec2::new(6.0, 6.0),
+                                egui::Align2::LEFT_TOP,
+                                text,
+                                egui::FontId::proportional(13.0),
+                                egui::Color32::YELLOW,
+                            );
+                        }
+                    }
+                    if !self.shape_points.is_empty() {
+                        let mut points: Vec<Pos2> = self
+                            .shape_points
+                            .iter()
+                            .map(|&px| self.pixel_to_screen(img_rect, px))
+                            .collect();
+                        // Rubber band from the last vertex to the cursor
+                        if self.shape_kind == ShapeKind::Polygon
+                            && let Some(hover) = response.hover_pos()
+                        {
+                            points.push(hover);
+                        }
+                        painter.add(egui::Shape::line(points, stroke));
+                    }
+
+                    if let Some(line) = self.measure {
+                        let [a, b] = line.map(|p| self.image_to_screen(img_rect, p));
+                        let stroke = Stroke::new(2.0, egui::Color32::from_rgb(0, 255, 255));
+                        painter.line_segment([a, b], stroke);
+                        for end in [a, b] {
+                            painter.circle_filled(end, 3.0, stroke.color);
+                        }
+                        painter.text(
+                            b + Vec2::new(8.0, 8.0),
+                            egui::Align2::LEFT_TOP,
+                            self.measure_text(line),
+                            egui::FontId::proportional(14.0),
+                            stroke.color,
                         );
                     }
+
+                    if self.loupe
+                        && let Some(hover) = response.hover_pos()
+                        && img_rect.contains(hover)
+                    {
+                        loupe(&painter, canvas, img_rect, shown, hover, self.loupe_zoom);
+                    }
+                } else if self.loading.is_some() {
+                    ui.spinner();
                 } else {
-                    ui.label("Click “Open Image…” to begin.");
+                    ui.label(tr!("view.start", button = tr!("toolbar.open_image")));
                 }
             });
         });
@@ -500,14 +7435,31 @@ impl eframe::App for App {
 }
 
 fn main() -> eframe::Result<()> {
+    let cli = Cli::parse();
+    if cli.batch {
+        std::process::exit(run_batch_cli(cli));
+    }
+
+    let settings = default_settings_path()
+        .map(|path| Settings::load(&path))
+        .transpose()
+        .map(Option::unwrap_or_default);
+    let mut viewport = egui::ViewportBuilder::default();
+    if let Ok(Settings {
+        window_size: Some(size),
+        ..
+    }) = &settings
+    {
+        viewport = viewport.with_inner_size(*size);
+    }
     let native_options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default(),
+        viewport,
         ..Default::default()
     };
 
     eframe::run_native(
-        "Box Blackout (drag to blacken)",
+        APP_TITLE,
         native_options,
-        Box::new(|cc| Ok(Box::new(App::new(cc)))),
+        Box::new(|cc| Ok(Box::new(App::new(cc, cli.paths, settings)))),
     )
 }
@@ -0,0 +1,232 @@
+//! Flattened, display-ready view of a DICOM header, and text editing of its values.
+
+use crate::DCMRedactErrors;
+use dicom::core::dictionary::{DataDictionary, DataDictionaryEntry};
+use dicom::core::header::Header;
+use dicom::core::value::Value;
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::{StandardDataDictionary, tags};
+use dicom::object::InMemDicomObject;
+use std::cmp::Ordering;
+
+/// One data element as shown in the metadata panel. Sequence items follow their
+/// sequence with `depth` one higher.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementRow {
+    pub depth: usize,
+    pub tag: Tag,
+    /// Dictionary keyword, or "Private" / "Unknown".
+    pub name: String,
+    pub vr: VR,
+    pub value: String,
+    /// Top-level text or numeric value that [`set_element_text`] can replace.
+    pub editable: bool,
+}
+
+impl ElementRow {
+    /// Whether `query` (lowercase) appears in the tag, name or value.
+    pub fn matches(&self, query: &str) -> bool {
+        query.is_empty()
+            || format_tag(self.tag).to_lowercase().contains(query)
+            || self.name.to_lowercase().contains(query)
+            || self.value.to_lowercase().contains(query)
+    }
+}
+
+/// `(gggg,eeee)`.
+pub fn format_tag(tag: Tag) -> String {
+    format!("({:04X},{:04X})", tag.group(), tag.element())
+}
+
+pub fn tag_name(tag: Tag) -> String {
+    if tag.group() % 2 == 1 {
+        return "Private".to_string();
+    }
+    StandardDataDictionary
+        .by_tag(tag)
+        .map_or_else(|| "Unknown".to_string(), |e| e.alias().to_string())
+}
+
+fn is_binary(vr: VR) -> bool {
+    matches!(
+        vr,
+        VR::OB | VR::OW | VR::OF | VR::OD | VR::OL | VR::OV | VR::UN
+    )
+}
+
+fn is_numeric(vr: VR) -> bool {
+    matches!(vr, VR::US | VR::SS | VR::UL | VR::SL | VR::FL | VR::FD)
+}
+
+/// Every element of `obj`, recursing into sequence items.
+pub fn element_rows(obj: &InMemDicomObject) -> Vec<ElementRow> {
+    let mut rows = Vec::new();
+    collect_rows(obj, &mut Vec::new(), &mut rows);
+    rows.into_iter().map(|(_, row)| row).collect()
+}
+
+/// Rows keyed by their position in the dataset: the tag of each enclosing sequence
+/// and the item index, then the element's own tag. Keys sort in dataset order.
+fn collect_rows(
+    obj: &InMemDicomObject,
+    path: &mut Vec<u32>,
+    rows: &mut Vec<(Vec<u32>, ElementRow)>,
+) {
+    let depth = path.len() / 2;
+    for elem in obj.iter() {
+        let (tag, vr) = (elem.tag(), elem.vr());
+        let value = match elem.value() {
+            Value::Sequence(seq) => format!("({} items)", seq.items().len()),
+            Value::PixelSequence(seq) => format!("<{} fragments>", seq.fragments().len()),
+            Value::Primitive(_) if tag == tags::PIXEL_DATA => "<pixel data>".to_string(),
+            Value::Primitive(p) if is_binary(vr) => format!("<{} bytes>", p.to_bytes().len()),
+            Value::Primitive(p) => p.to_str().trim_end_matches(['\0', ' ']).to_string(),
+        };
+        let editable = depth == 0
+            && matches!(elem.value(), Value::Primitive(_))
+            && tag != tags::PIXEL_DATA
+            && !is_binary(vr)
+            && vr != VR::AT;
+        path.push(u32::from(tag.group()) << 16 | u32::from(tag.element()));
+        rows.push((
+            path.clone(),
+            ElementRow {
+                depth,
+                tag,
+                name: tag_name(tag),
+                vr,
+                value,
+                editable,
+            },
+        ));
+        if let Value::Sequence(seq) = elem.value() {
+            for (i, item) in seq.items().iter().enumerate() {
+                path.push(i as u32);
+                collect_rows(item, path, rows);
+                path.pop();
+            }
+        }
+        path.pop();
+    }
+}
+
+/// One element in a before/after comparison; `None` where the element is absent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffRow {
+    pub depth: usize,
+    pub tag: Tag,
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl DiffRow {
+    pub fn changed(&self) -> bool {
+        self.before != self.after
+    }
+
+    /// Whether `query` (lowercase) appears in the tag, name or either value.
+    pub fn matches(&self, query: &str) -> bool {
+        query.is_empty()
+            || format_tag(self.tag).to_lowercase().contains(query)
+            || self.name.to_lowercase().contains(query)
+            || [&self.before, &self.after]
+                .into_iter()
+                .flatten()
+                .any(|v| v.to_lowercase().contains(query))
+    }
+}
+
+/// Every element of `before` and `after` side by side, in dataset order, matched by
+/// tag and sequence item position.
+pub fn diff_rows(before: &InMemDicomObject, after: &InMemDicomObject) -> Vec<DiffRow> {
+    let (mut old, mut new) = (Vec::new(), Vec::new());
+    collect_rows(before, &mut Vec::new(), &mut old);
+    collect_rows(after, &mut Vec::new(), &mut new);
+    let mut old = old.into_iter().peekable();
+    let mut new = new.into_iter().peekable();
+    let mut rows = Vec::new();
+    loop {
+        let order = match (old.peek(), new.peek()) {
+            (Some((a, _)), Some((b, _))) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => break,
+        };
+        let (row, before, after) = match order {
+            Ordering::Less => {
+                let (_, a) = old.next().unwrap();
+                let value = a.value.clone();
+                (a, Some(value), None)
+            }
+            Ordering::Greater => {
+                let (_, b) = new.next().unwrap();
+                let value = b.value.clone();
+                (b, None, Some(value))
+            }
+            Ordering::Equal => {
+                let (_, a) = old.next().unwrap();
+                let (_, b) = new.next().unwrap();
+                let value = a.value.clone();
+                (a, Some(value), Some(b.value))
+            }
+        };
+        rows.push(DiffRow {
+            depth: row.depth,
+            tag: row.tag,
+            name: row.name,
+            before,
+            after,
+        });
+    }
+    rows
+}
+
+/// Replace the value of `tag` with `text` (multiple values separated by `\`), parsed
+/// according to the element's VR.
+pub fn set_element_text(
+    obj: &mut InMemDicomObject,
+    tag: Tag,
+    text: &str,
+) -> Result<(), DCMRedactErrors> {
+    let vr = obj
+        .element(tag)
+        .map_err(|_| {
+            DCMRedactErrors::ValueError(format!("{} is not in the dataset", format_tag(tag)))
+        })?
+        .vr();
+    let parts: Vec<&str> = text.split('\\').map(str::trim).collect();
+    let bad =
+        |part: &str| DCMRedactErrors::ValueError(format!("'{part}' is not a valid {vr:?} value"));
+    macro_rules! numbers {
+        ($variant:ident, $t:ty) => {
+            PrimitiveValue::$variant(
+                parts
+                    .iter()
+                    .map(|p| p.parse::<$t>().map_err(|_| bad(p)))
+                    .collect::<Result<Vec<$t>, _>>()?
+                    .into(),
+            )
+        };
+    }
+    let value = if text.is_empty() {
+        PrimitiveValue::Empty
+    } else if is_numeric(vr) {
+        match vr {
+            VR::US => numbers!(U16, u16),
+            VR::SS => numbers!(I16, i16),
+            VR::UL => numbers!(U32, u32),
+            VR::SL => numbers!(I32, i32),
+            VR::FL => numbers!(F32, f32),
+            _ => numbers!(F64, f64),
+        }
+    } else if is_binary(vr) || vr == VR::SQ || vr == VR::AT {
+        return Err(DCMRedactErrors::ValueError(format!(
+            "{vr:?} values can't be edited as text"
+        )));
+    } else {
+        PrimitiveValue::Strs(parts.iter().map(|p| p.to_string()).collect())
+    };
+    obj.put(DataElement::new(tag, vr, value));
+    Ok(())
+}
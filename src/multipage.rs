@@ -0,0 +1,157 @@
+//! Multi-page TIFF, as scanned documents often come: every page is loaded as a frame
+//! and written back as a page of its own.
+//!
+//! The `image` crate only reads the first page of a TIFF, so pages are decoded with
+//! the `tiff` crate directly. Pages must all be the same size.
+
+use crate::{DCMRedactErrors, Frame};
+use image::{DynamicImage, GenericImageView, ImageBuffer};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::{Compression, TiffEncoder, colortype};
+
+/// Pages read from a TIFF file.
+pub struct TiffPages {
+    pub frames: Vec<Frame>,
+    /// No page had more than 8 bits per sample.
+    pub eight_bit: bool,
+}
+
+/// Whether `path` has a `.tif`/`.tiff` extension.
+pub fn is_tiff_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff"))
+}
+
+/// Expand 1-bit rows (MSB first, padded to whole bytes) to 0/255 samples.
+fn expand_bilevel(packed: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row_bytes = width.div_ceil(8) as usize;
+    let mut out = Vec::with_capacity(width as usize * height as usize);
+    for row in packed.chunks(row_bytes).take(height as usize) {
+        out.extend((0..width as usize).map(|x| {
+            if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                255
+            } else {
+                0
+            }
+        }));
+    }
+    out
+}
+
+fn read_page(decoder: &mut Decoder<BufReader<File>>) -> Result<DynamicImage, String> {
+    let (w, h) = decoder.dimensions().map_err(|e| e.to_string())?;
+    let color = decoder.colortype().map_err(|e| e.to_string())?;
+    let data = decoder.read_image().map_err(|e| e.to_string())?;
+    use tiff::ColorType as C;
+    let img = match (color, data) {
+        (C::Gray(1), DecodingResult::U8(v)) => {
+            ImageBuffer::from_raw(w, h, expand_bilevel(&v, w, h)).map(DynamicImage::ImageLuma8)
+        }
+        (C::Gray(8), DecodingResult::U8(v)) => {
+            ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageLuma8)
+        }
+        (C::Gray(16), DecodingResult::U16(v)) => {
+            ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageLuma16)
+        }
+        (C::GrayA(8), DecodingResult::U8(v)) => {
+            ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageLumaA8)
+        }
+        (C::RGB(8), DecodingResult::U8(v)) => {
+            ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgb8)
+        }
+        (C::RGB(16), DecodingResult::U16(v)) => {
+            ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgb16)
+        }
+        (C::RGBA(8), DecodingResult::U8(v)) => {
+            ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgba8)
+        }
+        (C::RGBA(16), DecodingResult::U16(v)) => {
+            ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgba16)
+        }
+        (color, _) => return Err(format!("unsupported color type {color:?}")),
+    };
+    // A short buffer means planar samples, of which only the first plane was read
+    img.ok_or_else(|| format!("unsupported sample layout for {color:?}"))
+}
+
+/// Read every page of the TIFF at `path`. Color pages make all frames RGB; grayscale
+/// is widened to 16 bits as for other images.
+pub fn read_tiff(path: &Path) -> Result<TiffPages, DCMRedactErrors> {
+    let failed = |page: usize, e: String| {
+        DCMRedactErrors::decode(
+            format!("Failed to read page {} of {}", page + 1, path.display()),
+            e,
+        )
+    };
+    let file = File::open(path)
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to open {}", path.display()), e))?;
+    let mut decoder = Decoder::new(BufReader::new(file)).map_err(|e| failed(0, e.to_string()))?;
+
+    let mut pages = Vec::new();
+    let mut eight_bit = true;
+    loop {
+        let depth = decoder.colortype().map_or(8, |c| c.bit_depth());
+        eight_bit &= depth <= 8;
+        pages.push(read_page(&mut decoder).map_err(|e| failed(pages.len(), e))?);
+        if !decoder.more_images() {
+            break;
+        }
+        decoder
+            .next_image()
+            .map_err(|e| failed(pages.len(), e.to_string()))?;
+    }
+
+    let size = pages[0].dimensions();
+    if let Some(i) = pages.iter().position(|p| p.dimensions() != size) {
+        let (w, h) = pages[i].dimensions();
+        return Err(DCMRedactErrors::ValueError(format!(
+            "Page {} of {} is {w}x{h}, but page 1 is {}x{}; pages of different sizes aren't supported",
+            i + 1,
+            path.display(),
+            size.0,
+            size.1
+        )));
+    }
+    let color = pages.iter().any(|p| p.color().has_color());
+    let frames = pages
+        .into_iter()
+        .map(|p| {
+            if color {
+                Frame::Rgb(p.to_rgb8())
+            } else {
+                Frame::Gray(p.to_luma16())
+            }
+        })
+        .collect();
+    Ok(TiffPages { frames, eight_bit })
+}
+
+/// Write `frames` as the pages of an LZW-compressed TIFF at `path`. Grayscale is
+/// written with 8 bits per sample if `eight_bit`, else 16.
+pub fn write_tiff(path: &Path, frames: &[Frame], eight_bit: bool) -> Result<(), DCMRedactErrors> {
+    let failed = |e: tiff::TiffError| {
+        DCMRedactErrors::encode(format!("Failed to write {}", path.display()), e)
+    };
+    let file = File::create(path)
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to create {}", path.display()), e))?;
+    let mut encoder = TiffEncoder::new(BufWriter::new(file))
+        .map_err(failed)?
+        .with_compression(Compression::Lzw);
+    for frame in frames {
+        let (w, h) = frame.dimensions();
+        match frame {
+            Frame::Gray(img) if eight_bit => {
+                let bytes: Vec<u8> = img.as_raw().iter().map(|&v| (v >> 8) as u8).collect();
+                encoder.write_image::<colortype::Gray8>(w, h, &bytes)
+            }
+            Frame::Gray(img) => encoder.write_image::<colortype::Gray16>(w, h, img.as_raw()),
+            Frame::Rgb(img) => encoder.write_image::<colortype::RGB8>(w, h, img.as_raw()),
+        }
+        .map_err(failed)?;
+    }
+    Ok(())
+}
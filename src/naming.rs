@@ -0,0 +1,260 @@
+//! Output file naming patterns such as `{patient_pseudo}/{study_uid}/{series}_{instance}.dcm`
+//! or `{stem}_redacted.dcm`.
+//!
+//! Placeholders are filled from the source file name (`{name}`, `{stem}`, `{ext}`) or
+//! from the header being written, so names never contain identifiers the
+//! de-identification removed. Besides the shortcuts below, any attribute can be named
+//! by keyword (`{PatientAge}`) or tag (`{0020,0011}`). `{field:N}` zero-pads numbers to
+//! `N` digits. `/` in the pattern separates directories.
+
+use dicom::core::Tag;
+use dicom::core::dictionary::DataDictionary;
+use dicom::dictionary_std::{StandardDataDictionary, tags};
+use dicom::object::InMemDicomObject;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// Shortcut placeholders and the attributes they stand for.
+const SHORTCUTS: &[(&str, Tag)] = &[
+    ("patient_id", tags::PATIENT_ID),
+    // PatientID of the output, i.e. the pseudonym when a pseudonym table is used
+    ("patient_pseudo", tags::PATIENT_ID),
+    ("study_uid", tags::STUDY_INSTANCE_UID),
+    ("series_uid", tags::SERIES_INSTANCE_UID),
+    ("sop_uid", tags::SOP_INSTANCE_UID),
+    ("series", tags::SERIES_NUMBER),
+    ("instance", tags::INSTANCE_NUMBER),
+    ("modality", tags::MODALITY),
+    ("study_date", tags::STUDY_DATE),
+    ("accession", tags::ACCESSION_NUMBER),
+];
+
+/// Used for attributes that are missing or empty, and for every attribute of
+/// non-DICOM sources.
+const MISSING: &str = "unknown";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    Stem,
+    Ext,
+    Tag(Tag),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field { field: Field, width: usize },
+}
+
+/// A parsed naming pattern; the result is always a relative path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamePattern {
+    pattern: String,
+    parts: Vec<Part>,
+}
+
+impl fmt::Display for NamePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+fn parse_field(spec: &str) -> Result<Part, String> {
+    let (key, width) = match spec.split_once(':') {
+        Some((key, width)) => (
+            key.trim(),
+            width
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid width in '{{{spec}}}'"))?,
+        ),
+        None => (spec.trim(), 0),
+    };
+    let field = match key {
+        "name" => Field::Name,
+        "stem" => Field::Stem,
+        "ext" => Field::Ext,
+        _ => match SHORTCUTS.iter().find(|(k, _)| *k == key) {
+            Some(&(_, tag)) => Field::Tag(tag),
+            None => Field::Tag(
+                StandardDataDictionary
+                    .parse_tag(key)
+                    .ok_or_else(|| format!("unknown placeholder '{{{key}}}'"))?,
+            ),
+        },
+    };
+    Ok(Part::Field { field, width })
+}
+
+impl FromStr for NamePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let Some(len) = rest[start..].find('}') else {
+                return Err(format!("unclosed '{{' in name pattern '{s}'"));
+            };
+            parts.push(parse_field(&rest[start + 1..start + len])?);
+            rest = &rest[start + len + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if parts.is_empty() {
+            return Err("empty name pattern".to_string());
+        }
+        let literal: String = parts
+            .iter()
+            .map(|p| match p {
+                Part::Text(t) => t.as_str(),
+                Part::Field { .. } => "x",
+            })
+            .collect();
+        let path = Path::new(&literal);
+        if path.has_root()
+            || path
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!(
+                "name pattern '{s}' must be a relative path without '..'"
+            ));
+        }
+        Ok(Self {
+            pattern: s.to_string(),
+            parts,
+        })
+    }
+}
+
+/// Make an attribute value safe as (part of) a single path component.
+fn sanitize(value: &str) -> String {
+    let cleaned: String = value
+        .trim_end_matches(['\0', ' '])
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match cleaned.as_str() {
+        "" | "." | ".." => MISSING.to_string(),
+        _ => cleaned,
+    }
+}
+
+impl NamePattern {
+    /// The output path for `source`, relative to the output directory. `obj` is the
+    /// header that will be written (`None` for non-DICOM sources).
+    pub fn expand(&self, source: &Path, obj: Option<&InMemDicomObject>) -> PathBuf {
+        let from_path = |s: Option<&std::ffi::OsStr>| {
+            s.map_or_else(String::new, |s| s.to_string_lossy().into_owned())
+        };
+        let mut out = String::new();
+        for part in &self.parts {
+            let (field, width) = match part {
+                Part::Text(text) => {
+                    out += text;
+                    continue;
+                }
+                Part::Field { field, width } => (*field, *width),
+            };
+            let value = match field {
+                Field::Name => from_path(source.file_name()),
+                Field::Stem => from_path(source.file_stem()),
+                Field::Ext => from_path(source.extension()),
+                Field::Tag(tag) => obj
+                    .and_then(|o| o.element(tag).ok())
+                    .and_then(|e| e.to_str().ok())
+                    .map_or_else(String::new, |s| s.into_owned()),
+            };
+            let value = sanitize(&value);
+            if width > 0 && value.chars().all(|c| c.is_ascii_digit()) {
+                out += &format!("{value:0>width$}");
+            } else {
+                out += &value;
+            }
+        }
+        PathBuf::from(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+
+    fn pattern(s: &str) -> NamePattern {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn patterns_must_stay_below_the_output_directory() {
+        for bad in [
+            "/abs/{stem}.dcm",
+            "../{stem}.dcm",
+            "a/../../{stem}",
+            "{stem",
+            "",
+        ] {
+            assert!(bad.parse::<NamePattern>().is_err(), "{bad}");
+        }
+        assert!("{stem}:{bogus}".parse::<NamePattern>().is_err());
+        assert!("{series:x}".parse::<NamePattern>().is_err());
+        assert_eq!(pattern("./{stem}.dcm").to_string(), "./{stem}.dcm");
+    }
+
+    #[test]
+    fn file_name_fields_come_from_the_source() {
+        let source = Path::new("/in/study/IM0001.dcm");
+        assert_eq!(
+            pattern("{stem}_redacted.{ext}").expand(source, None),
+            PathBuf::from("IM0001_redacted.dcm")
+        );
+        assert_eq!(
+            pattern("out/{name}").expand(source, None),
+            PathBuf::from("out/IM0001.dcm")
+        );
+    }
+
+    #[test]
+    fn missing_attributes_are_unknown() {
+        assert_eq!(
+            pattern("{patient_pseudo}/{series:4}.dcm").expand(Path::new("a.png"), None),
+            PathBuf::from("unknown/unknown.dcm")
+        );
+    }
+
+    #[test]
+    fn values_cannot_escape_their_path_component() {
+        assert_eq!(sanitize("../etc/passwd"), ".._etc_passwd");
+        assert_eq!(sanitize(".."), MISSING);
+        assert_eq!(sanitize(" \0"), MISSING);
+        assert_eq!(sanitize("a\\b:c*?\"<>|\td"), "a_b_c_______d");
+        assert_eq!(sanitize("CT "), "CT");
+    }
+
+    #[test]
+    fn attributes_fill_their_fields() {
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::PATIENT_ID, VR::LO, PrimitiveValue::from("PSEUDO/01")),
+            DataElement::new(tags::SERIES_NUMBER, VR::IS, PrimitiveValue::from("3")),
+            DataElement::new(tags::INSTANCE_NUMBER, VR::IS, PrimitiveValue::from("12 ")),
+            DataElement::new(tags::MODALITY, VR::CS, PrimitiveValue::from("CT")),
+        ]);
+        assert_eq!(
+            pattern("{patient_pseudo}/{modality}_{series:3}_{instance:4}.dcm")
+                .expand(Path::new("x.dcm"), Some(&obj)),
+            PathBuf::from("PSEUDO_01/CT_003_0012.dcm")
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Burned-in text detection via the `tesseract` command line tool.
+//!
+//! Each frame is rendered to an 8-bit PNG in the temp directory and fed to
+//! `tesseract ... tsv`; recognized words are grouped per text line and returned as
+//! candidate redaction rectangles.
+
+use crate::{DCMRedactErrors, Frame, RedactRect};
+use image::GrayImage;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Debug, Clone)]
+pub struct OcrOptions {
+    /// The tesseract executable; defaults to `$DCM_REDACT_TESSERACT` or `tesseract`.
+    pub tesseract: PathBuf,
+    /// Words below this confidence (0-100) are ignored.
+    pub min_confidence: f32,
+    /// Pixels added on every side of each detected line.
+    pub padding: u32,
+}
+
+impl Default for OcrOptions {
+    fn default() -> Self {
+        Self {
+            tesseract: std::env::var_os("DCM_REDACT_TESSERACT")
+                .map_or_else(|| PathBuf::from("tesseract"), PathBuf::from),
+            min_confidence: 50.0,
+            padding: 2,
+        }
+    }
+}
+
+/// Render a frame to 8-bit gray for OCR: the top 8 significant bits of stored values,
+/// inverted for MONOCHROME1 so text is light-on-dark like on screen.
+pub(crate) fn frame_to_luma8(frame: &Frame, bits_stored: u16, invert: bool) -> GrayImage {
+    match frame {
+        Frame::Gray(img) => {
+            let shift = bits_stored.saturating_sub(8);
+            GrayImage::from_fn(img.width(), img.height(), |x, y| {
+                let v = (img.get_pixel(x, y)[0] >> shift).min(255) as u8;
+                image::Luma([if invert { 255 - v } else { v }])
+            })
+        }
+        Frame::Rgb(img) => image::DynamicImage::ImageRgb8(img.clone()).to_luma8(),
+    }
+}
+
+/// Parse `tesseract ... tsv` output into one rectangle per text line.
+fn parse_tsv(tsv: &str, opts: &OcrOptions, width: u32, height: u32) -> Vec<RedactRect> {
+    // (block, paragraph, line) -> union of the word boxes on that line
+    let mut lines: BTreeMap<(u32, u32, u32), RedactRect> = BTreeMap::new();
+    for row in tsv.lines().skip(1) {
+        let cols: Vec<&str> = row.split('\t').collect();
+        if cols.len() < 12 || cols[0] != "5" || cols[11].trim().is_empty() {
+            continue;
+        }
+        let num = |i: usize| cols[i].parse::<u32>().ok();
+        let conf = cols[10].parse::<f32>().unwrap_or(-1.0);
+        if conf < opts.min_confidence {
+            continue;
+        }
+        let (Some(block), Some(par), Some(line)) = (num(2), num(3), num(4)) else {
+            continue;
+        };
+        let (Some(left), Some(top), Some(w), Some(h)) = (num(6), num(7), num(8), num(9)) else {
+            continue;
+        };
+        let word = RedactRect {
+            x0: left.saturating_sub(opts.padding),
+            y0: top.saturating_sub(opts.padding),
+            x1: (left + w + opts.padding).min(width),
+            y1: (top + h + opts.padding).min(height),
+        };
+        lines
+            .entry((block, par, line))
+            .and_modify(|r| {
+                r.x0 = r.x0.min(word.x0);
+                r.y0 = r.y0.min(word.y0);
+                r.x1 = r.x1.max(word.x1);
+                r.y1 = r.y1.max(word.y1);
+            })
+            .or_insert(word);
+    }
+    lines
+        .into_values()
+        .filter(|r| r.x1 > r.x0 && r.y1 > r.y0)
+        .collect()
+}
+
+/// Run OCR over `frame` and return candidate rectangles around burned-in text.
+pub fn detect_text(
+    frame: &Frame,
+    bits_stored: u16,
+    photometric: Option<&str>,
+    opts: &OcrOptions,
+) -> Result<Vec<RedactRect>, DCMRedactErrors> {
+    let img = frame_to_luma8(frame, bits_stored, photometric == Some("MONOCHROME1"));
+    let png = std::env::temp_dir().join(format!("dcm-redact-ocr-{}.png", uuid::Uuid::new_v4()));
+    img.save(&png)
+        .map_err(|e| DCMRedactErrors::encode("Failed to write OCR input", e))?;
+
+    // --psm 11: sparse text, find as much text as possible in no particular order
+    let output = Command::new(&opts.tesseract)
+        .arg(&png)
+        .args(["stdout", "--psm", "11", "tsv"])
+        .output();
+    let _ = std::fs::remove_file(&png);
+
+    let output = output.map_err(|e| {
+        DCMRedactErrors::io(
+            format!(
+                "Failed to run {} (is tesseract installed?)",
+                opts.tesseract.display()
+            ),
+            e,
+        )
+    })?;
+    if !output.status.success() {
+        return Err(DCMRedactErrors::ValueError(format!(
+            "tesseract failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(parse_tsv(
+        &String::from_utf8_lossy(&output.stdout),
+        opts,
+        img.width(),
+        img.height(),
+    ))
+}
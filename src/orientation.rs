@@ -0,0 +1,402 @@
+//! Display orientation: quarter turns and mirroring, for images that arrive rotated
+//! (portable X-rays, mostly).
+//!
+//! An [`Orientation`] mirrors the stored image first, then turns it clockwise. The GUI
+//! only displays through it, mapping drawn shapes back to stored pixels;
+//! [`crate::Redactor::set_orientation`] bakes it into the output's pixels and header.
+
+use crate::{Frame, RedactRect, Shape};
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use image::{ImageBuffer, Pixel, imageops};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Orientation {
+    /// Clockwise quarter turns, 0-3.
+    pub turns: u8,
+    /// Mirrored left to right, before turning.
+    pub flip_h: bool,
+    /// Mirrored top to bottom, before turning.
+    pub flip_v: bool,
+}
+
+fn reoriented<P: Pixel + 'static>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    o: Orientation,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut img = img.clone();
+    if o.flip_h {
+        imageops::flip_horizontal_in_place(&mut img);
+    }
+    if o.flip_v {
+        imageops::flip_vertical_in_place(&mut img);
+    }
+    match o.turns % 4 {
+        1 => imageops::rotate90(&img),
+        2 => imageops::rotate180(&img),
+        3 => imageops::rotate270(&img),
+        _ => img,
+    }
+}
+
+/// `value` as a DS: at most 16 characters.
+pub(crate) fn ds(value: f64) -> String {
+    let text = format!("{value:.6}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        _ => text.chars().take(16).collect(),
+    }
+}
+
+/// The direction letters of a PatientOrientation value, reversed.
+fn opposite(direction: &str) -> String {
+    direction
+        .chars()
+        .map(|c| match c {
+            'A' => 'P',
+            'P' => 'A',
+            'L' => 'R',
+            'R' => 'L',
+            'H' => 'F',
+            'F' => 'H',
+            c => c,
+        })
+        .collect()
+}
+
+impl Orientation {
+    pub fn is_identity(self) -> bool {
+        self == Self::default()
+    }
+
+    pub fn rotated_cw(self) -> Self {
+        Self {
+            turns: (self.turns + 1) % 4,
+            ..self
+        }
+    }
+
+    pub fn rotated_ccw(self) -> Self {
+        Self {
+            turns: (self.turns + 3) % 4,
+            ..self
+        }
+    }
+
+    /// Mirrored left to right as displayed, i.e. top to bottom as stored when turned
+    /// a quarter.
+    pub fn flipped_h(self) -> Self {
+        if self.turns % 2 == 1 {
+            Self {
+                flip_v: !self.flip_v,
+                ..self
+            }
+        } else {
+            Self {
+                flip_h: !self.flip_h,
+                ..self
+            }
+        }
+    }
+
+    /// Mirrored top to bottom as displayed.
+    pub fn flipped_v(self) -> Self {
+        if self.turns % 2 == 1 {
+            Self {
+                flip_h: !self.flip_h,
+                ..self
+            }
+        } else {
+            Self {
+                flip_v: !self.flip_v,
+                ..self
+            }
+        }
+    }
+
+    /// Size of a `width` x `height` image as displayed.
+    pub fn size(self, width: u32, height: u32) -> (u32, u32) {
+        if self.turns % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// A point of a `width` x `height` stored image (edges at 0 and `width`), as
+    /// displayed.
+    pub fn to_display(self, [mut x, mut y]: [f64; 2], width: f64, height: f64) -> [f64; 2] {
+        if self.flip_h {
+            x = width - x;
+        }
+        if self.flip_v {
+            y = height - y;
+        }
+        let mut size = (width, height);
+        for _ in 0..self.turns % 4 {
+            (x, y) = (size.1 - y, x);
+            size = (size.1, size.0);
+        }
+        [x, y]
+    }
+
+    /// Inverse of [`Orientation::to_display`]; `width` and `height` are still those
+    /// of the stored image.
+    pub fn to_stored(self, [mut x, mut y]: [f64; 2], width: f64, height: f64) -> [f64; 2] {
+        let mut size = if self.turns % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        for _ in 0..self.turns % 4 {
+            (x, y) = (y, size.0 - x);
+            size = (size.1, size.0);
+        }
+        if self.flip_h {
+            x = width - x;
+        }
+        if self.flip_v {
+            y = height - y;
+        }
+        [x, y]
+    }
+
+    /// Stored pixel of a `width` x `height` image shown at displayed pixel `px`.
+    pub fn stored_pixel(self, [x, y]: [u32; 2], width: u32, height: u32) -> [u32; 2] {
+        let [sx, sy] = self.to_stored(
+            [x as f64 + 0.5, y as f64 + 0.5],
+            width as f64,
+            height as f64,
+        );
+        [
+            (sx.floor() as u32).min(width.saturating_sub(1)),
+            (sy.floor() as u32).min(height.saturating_sub(1)),
+        ]
+    }
+
+    /// Stored displacement of one displayed pixel to the right (`axis` 0) or down (1):
+    /// a unit step along one stored axis.
+    fn stored_step(self, axis: usize) -> [i32; 2] {
+        let mut to = [0.0, 0.0];
+        to[axis] = 1.0;
+        let a = self.to_stored([0.0, 0.0], 2.0, 2.0);
+        let b = self.to_stored(to, 2.0, 2.0);
+        [(b[0] - a[0]).round() as i32, (b[1] - a[1]).round() as i32]
+    }
+
+    /// The frame as displayed.
+    pub fn frame(self, frame: &Frame) -> Frame {
+        match frame {
+            Frame::Gray(img) => Frame::Gray(reoriented(img, self)),
+            Frame::Rgb(img) => Frame::Rgb(reoriented(img, self)),
+        }
+    }
+
+    /// `shape`, drawn on a `width` x `height` stored image, as displayed.
+    pub fn shape(self, shape: &Shape, width: u32, height: u32) -> Shape {
+        let (w, h) = (width as f64, height as f64);
+        let rect = |r: &RedactRect| {
+            let a = self.to_display([r.x0 as f64, r.y0 as f64], w, h);
+            let b = self.to_display([r.x1 as f64, r.y1 as f64], w, h);
+            RedactRect {
+                x0: a[0].min(b[0]).round() as u32,
+                y0: a[1].min(b[1]).round() as u32,
+                x1: a[0].max(b[0]).round() as u32,
+                y1: a[1].max(b[1]).round() as u32,
+            }
+        };
+        match shape {
+            Shape::Rect(r) => Shape::Rect(rect(r)),
+            Shape::Ellipse(r) => Shape::Ellipse(rect(r)),
+            Shape::Polygon(points) => Shape::Polygon(
+                points
+                    .iter()
+                    .map(|&[x, y]| {
+                        let [dx, dy] = self.to_display([x as f64 + 0.5, y as f64 + 0.5], w, h);
+                        [dx.floor().max(0.0) as u32, dy.floor().max(0.0) as u32]
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Update the image plane attributes of `obj`, a `width` x `height` image, for its
+    /// pixels turned as displayed: Rows and Columns, the pixel spacings,
+    /// PatientOrientation, and ImageOrientationPatient with ImagePositionPatient.
+    pub fn reorient_header(self, obj: &mut InMemDicomObject, width: u32, height: u32) {
+        let (columns, rows) = self.size(width, height);
+        for (tag, value) in [(tags::ROWS, rows), (tags::COLUMNS, columns)] {
+            obj.put(DataElement::new(
+                tag,
+                VR::US,
+                PrimitiveValue::from(value as u16),
+            ));
+        }
+        let steps = [self.stored_step(0), self.stored_step(1)];
+        // Which stored axis each displayed one runs along, 0 for x
+        let axis = |step: [i32; 2]| usize::from(step[0] == 0);
+        let sign = |step: [i32; 2]| (step[0] + step[1]) as f64;
+        let floats = |obj: &InMemDicomObject, tag: Tag| {
+            obj.element(tag)
+                .ok()
+                .and_then(|e| e.to_multi_float64().ok())
+        };
+        let put_floats = |obj: &mut InMemDicomObject, tag: Tag, values: &[f64]| {
+            obj.put(DataElement::new(
+                tag,
+                VR::DS,
+                PrimitiveValue::Strs(values.iter().map(|&v| ds(v)).collect()),
+            ));
+        };
+
+        // Spacings are given between rows (along y) first, then between columns
+        let spacing = floats(obj, tags::PIXEL_SPACING);
+        for tag in [
+            tags::PIXEL_SPACING,
+            tags::IMAGER_PIXEL_SPACING,
+            tags::NOMINAL_SCANNED_PIXEL_SPACING,
+        ] {
+            if let Some(&[dy, dx]) = floats(obj, tag).as_deref() {
+                let along = |step| if axis(step) == 0 { dx } else { dy };
+                put_floats(obj, tag, &[along(steps[1]), along(steps[0])]);
+            }
+        }
+
+        let directions: Option<Vec<String>> = obj
+            .element(tags::PATIENT_ORIENTATION)
+            .ok()
+            .and_then(|e| e.to_multi_str().ok())
+            .map(|v| v.iter().map(|s| s.trim().to_string()).collect());
+        if let Some([row, col]) = directions.as_deref() {
+            let direction = |step: [i32; 2]| {
+                let d = if axis(step) == 0 { row } else { col };
+                if sign(step) < 0.0 {
+                    opposite(d)
+                } else {
+                    d.clone()
+                }
+            };
+            obj.put(DataElement::new(
+                tags::PATIENT_ORIENTATION,
+                VR::CS,
+                PrimitiveValue::Strs([direction(steps[0]), direction(steps[1])].into()),
+            ));
+        }
+
+        if let Some(&[rx, ry, rz, cx, cy, cz]) =
+            floats(obj, tags::IMAGE_ORIENTATION_PATIENT).as_deref()
+        {
+            let cosines = [[rx, ry, rz], [cx, cy, cz]];
+            let direction = |step: [i32; 2]| cosines[axis(step)].map(|c| c * sign(step));
+            let (row, col) = (direction(steps[0]), direction(steps[1]));
+            // The first displayed pixel is another corner of the stored image
+            if let (Some(&[px, py, pz]), Some(&[dy, dx])) = (
+                floats(obj, tags::IMAGE_POSITION_PATIENT).as_deref(),
+                spacing.as_deref(),
+            ) {
+                let [i, j] = self.stored_pixel([0, 0], width, height);
+                let offset =
+                    |k: usize| cosines[0][k] * i as f64 * dx + cosines[1][k] * j as f64 * dy;
+                put_floats(
+                    obj,
+                    tags::IMAGE_POSITION_PATIENT,
+                    &[px + offset(0), py + offset(1), pz + offset(2)],
+                );
+            }
+            put_floats(
+                obj,
+                tags::IMAGE_ORIENTATION_PATIENT,
+                &[row[0], row[1], row[2], col[0], col[1], col[2]],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Gray16Image;
+
+    /// Every combination of turns and mirroring.
+    fn all() -> impl Iterator<Item = Orientation> {
+        (0..16).map(|i| Orientation {
+            turns: i % 4,
+            flip_h: i & 4 != 0,
+            flip_v: i & 8 != 0,
+        })
+    }
+
+    #[test]
+    fn to_stored_undoes_to_display() {
+        let (w, h) = (7.0, 4.0);
+        for o in all() {
+            for p in [[0.0, 0.0], [7.0, 4.0], [2.5, 1.0], [6.0, 0.5]] {
+                let shown = o.to_display(p, w, h);
+                let (dw, dh) = o.size(7, 4);
+                assert!(shown[0] >= 0.0 && shown[0] <= dw as f64, "{o:?} {p:?}");
+                assert!(shown[1] >= 0.0 && shown[1] <= dh as f64, "{o:?} {p:?}");
+                assert_eq!(o.to_stored(shown, w, h), p, "{o:?} {p:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn displayed_pixels_come_from_their_stored_pixel() {
+        let (w, h) = (5, 3);
+        let stored: Gray16Image = ImageBuffer::from_fn(w, h, |x, y| [(y * w + x) as u16].into());
+        for o in all() {
+            let Frame::Gray(shown) = o.frame(&Frame::Gray(stored.clone())) else {
+                unreachable!();
+            };
+            assert_eq!(shown.dimensions(), o.size(w, h), "{o:?}");
+            for (x, y, px) in shown.enumerate_pixels() {
+                let [sx, sy] = o.stored_pixel([x, y], w, h);
+                assert_eq!(px, stored.get_pixel(sx, sy), "{o:?} at {x},{y}");
+            }
+        }
+    }
+
+    #[test]
+    fn turns_and_flips_compose() {
+        let o = Orientation::default();
+        assert!(o.rotated_cw().rotated_ccw().is_identity());
+        assert!((0..4).fold(o, |o, _| o.rotated_cw()).is_identity());
+        assert!(o.flipped_h().flipped_h().is_identity());
+        // Mirroring a turned image as displayed mirrors the other stored axis
+        assert!(o.rotated_cw().flipped_h().flip_v);
+        assert_eq!(o.rotated_cw().size(5, 3), (3, 5));
+    }
+
+    #[test]
+    fn shapes_cover_the_same_pixels_when_displayed() {
+        let o = Orientation::default().rotated_cw();
+        let r = RedactRect {
+            x0: 0,
+            y0: 0,
+            x1: 2,
+            y1: 1,
+        };
+        // The top-left of a 5x3 image ends up at the top right once turned
+        assert_eq!(
+            o.shape(&Shape::Rect(r), 5, 3),
+            Shape::Rect(RedactRect {
+                x0: 2,
+                y0: 0,
+                x1: 3,
+                y1: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn decimal_strings_fit_sixteen_characters() {
+        assert_eq!(ds(0.5), "0.5");
+        assert_eq!(ds(2.0), "2");
+        assert_eq!(ds(-0.0000001), "0");
+        assert!(ds(-123456789.123456).len() <= 16);
+        assert_eq!(opposite("AFL"), "PHR");
+    }
+}
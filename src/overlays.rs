@@ -0,0 +1,53 @@
+//! Overlay planes (60xx), curve data (50xx) and icon images: secondary image data that
+//! can carry burned-in PHI of its own and isn't touched by redacting the main pixels.
+
+use dicom::core::Tag;
+use dicom::core::header::Header;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+/// Overlay (60xx) and curve (50xx) repeating groups; `xx` is even.
+pub fn is_overlay_or_curve(tag: Tag) -> bool {
+    let g = tag.group();
+    g.is_multiple_of(2) && (g & 0xFF00 == 0x6000 || g & 0xFF00 == 0x5000)
+}
+
+/// Pixel data bits holding (retired) embedded overlays: overlay groups without their own
+/// OverlayData (60xx,3000) store the plane in bit OverlayBitPosition (60xx,0102) of
+/// each pixel value.
+pub fn embedded_overlay_bits(obj: &InMemDicomObject) -> u16 {
+    let mut mask = 0u16;
+    for group in (0x6000..=0x601E).step_by(2) {
+        if obj.element(Tag(group, 0x3000)).is_ok() {
+            continue;
+        }
+        if let Some(pos) = obj
+            .element(Tag(group, 0x0102))
+            .ok()
+            .and_then(|e| e.to_int::<u16>().ok())
+            && pos < 16
+        {
+            mask |= 1 << pos;
+        }
+    }
+    mask
+}
+
+/// Remove overlay and curve groups and every IconImageSequence, recursing into
+/// sequence items.
+pub fn strip_overlays(obj: &mut InMemDicomObject) {
+    let tags: Vec<Tag> = obj.iter().map(|e| e.tag()).collect();
+    for tag in tags {
+        if is_overlay_or_curve(tag) || tag == tags::ICON_IMAGE_SEQUENCE {
+            obj.remove_element(tag);
+            continue;
+        }
+        obj.update_value(tag, |value| {
+            if let Some(items) = value.items_mut() {
+                for item in items.iter_mut() {
+                    strip_overlays(item);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,430 @@
+//! Sending to a PACS: a C-STORE SCU, so de-identified output can go straight into a
+//! research archive instead of through a folder.
+//!
+//! One association carries a whole list of files. A presentation context is proposed
+//! for every SOP class among them, with the transfer syntaxes the files are in plus
+//! explicit and implicit VR little endian, and each file is sent in whichever the
+//! PACS accepted: as it is, or re-encoded between uncompressed transfer syntaxes.
+//! Compressed files only go in their own transfer syntax.
+
+use crate::{DCMRedactErrors, gz};
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom::dictionary_std::{tags, uids};
+use dicom::encoding::TransferSyntaxIndex;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+use dicom::transfer_syntax::{TransferSyntaxRegistry, entries};
+use dicom::ul::association::client::ClientAssociationOptions;
+use dicom::ul::pdu::{PDataValue, PDataValueType, Pdu, PresentationContextResultReason};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// AE title dcm-redact calls as unless told otherwise.
+pub const DEFAULT_CALLING_AE: &str = "DCM-REDACT";
+/// Port assumed when a remote node doesn't give one.
+const DICOM_PORT: u16 = 104;
+/// Uncompressed transfer syntaxes proposed for every SOP class.
+pub(crate) const NATIVE: [&str; 2] = [
+    uids::EXPLICIT_VR_LITTLE_ENDIAN,
+    uids::IMPLICIT_VR_LITTLE_ENDIAN,
+];
+/// CommandField of a C-STORE-RQ.
+const C_STORE_RQ: u16 = 0x0001;
+/// CommandDataSetType when a data set follows the command.
+pub(crate) const DATA_SET_PRESENT: u16 = 0x0000;
+
+/// A remote DICOM node, written `AE@host:port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub ae_title: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl FromStr for Remote {
+    type Err = String;
+
+    /// Parse `AE@host:port`; the port defaults to 104.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("invalid remote node '{s}' (expected AE@host:port)");
+        let (ae_title, address) = s.trim().split_once('@').ok_or_else(bad)?;
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) if !host.ends_with(':') => (host, port.parse().map_err(|_| bad())?),
+            _ => (address, DICOM_PORT),
+        };
+        let ae_title = ae_title.trim();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if ae_title.is_empty() || ae_title.len() > 16 || host.is_empty() {
+            return Err(bad());
+        }
+        Ok(Remote {
+            ae_title: ae_title.to_string(),
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}:{}", self.ae_title, self.host, self.port)
+    }
+}
+
+/// How many files a [`store`] sent, and how many it couldn't.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreSummary {
+    pub sent: usize,
+    pub failed: usize,
+    /// Files the receiver took with a warning, and the warning.
+    pub warnings: Vec<String>,
+}
+
+impl StoreSummary {
+    pub(crate) fn record(&mut self, result: &Result<(), DCMRedactErrors>) {
+        if result.is_ok() {
+            self.sent += 1;
+        } else {
+            self.failed += 1;
+        }
+    }
+}
+
+pub(crate) fn trimmed(uid: &str) -> String {
+    uid.trim_end_matches(['\0', ' ']).to_string()
+}
+
+/// Whether the transfer syntax `uid` has native (uncompressed) pixel data, so a data
+/// set in it can be re-encoded in another one.
+fn is_native(uid: &str) -> bool {
+    TransferSyntaxRegistry
+        .get(uid)
+        .is_some_and(|ts| !ts.is_encapsulated_pixel_data())
+}
+
+/// The command set of a C-STORE-RQ for one instance.
+fn store_request(sop_class: &str, sop_instance: &str, message_id: u16) -> InMemDicomObject {
+    InMemDicomObject::command_from_element_iter([
+        DataElement::new(
+            tags::AFFECTED_SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(sop_class),
+        ),
+        DataElement::new(
+            tags::COMMAND_FIELD,
+            VR::US,
+            PrimitiveValue::from(C_STORE_RQ),
+        ),
+        DataElement::new(tags::MESSAGE_ID, VR::US, PrimitiveValue::from(message_id)),
+        DataElement::new(tags::PRIORITY, VR::US, PrimitiveValue::from(0u16)),
+        DataElement::new(
+            tags::COMMAND_DATA_SET_TYPE,
+            VR::US,
+            PrimitiveValue::from(DATA_SET_PRESENT),
+        ),
+        DataElement::new(
+            tags::AFFECTED_SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from(sop_instance),
+        ),
+    ])
+}
+
+/// `command` encoded for a P-DATA command fragment (always implicit VR little endian).
+pub(crate) fn encode_command(command: &InMemDicomObject) -> Result<Vec<u8>, DCMRedactErrors> {
+    let mut data = Vec::new();
+    command
+        .write_dataset_with_ts(&mut data, &entries::IMPLICIT_VR_LITTLE_ENDIAN.erased())
+        .map_err(|e| DCMRedactErrors::encode("Failed to encode DIMSE command", e))?;
+    Ok(data)
+}
+
+/// The command set carried by `pdu`, a response from the remote node.
+fn read_command(pdu: &Pdu, remote: &Remote) -> Result<InMemDicomObject, DCMRedactErrors> {
+    let unexpected = |what: &str| {
+        DCMRedactErrors::network(format!("Unexpected reply from {remote}"), what.to_string())
+    };
+    match pdu {
+        Pdu::PData { data } => {
+            let value = data
+                .iter()
+                .find(|v| v.value_type == PDataValueType::Command)
+                .ok_or_else(|| unexpected("no command in the response"))?;
+            InMemDicomObject::read_dataset_with_ts(
+                value.data.as_slice(),
+                &entries::IMPLICIT_VR_LITTLE_ENDIAN.erased(),
+            )
+            .map_err(|e| DCMRedactErrors::decode(format!("Invalid response from {remote}"), e))
+        }
+        Pdu::AbortRQ { .. } => Err(unexpected("the association was aborted")),
+        _ => Err(unexpected("not a DIMSE response")),
+    }
+}
+
+/// The Status of a response command set, and its ErrorComment if any.
+pub(crate) fn status(command: &InMemDicomObject) -> (u16, Option<String>) {
+    let status = command
+        .element(tags::STATUS)
+        .ok()
+        .and_then(|e| e.to_int::<u16>().ok())
+        .unwrap_or(0xFFFF);
+    let comment = command
+        .element(tags::ERROR_COMMENT)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    (status, comment)
+}
+
+/// Most presentation contexts one association can propose: their IDs are the odd
+/// numbers 1 to 255.
+const MAX_CONTEXTS: usize = 128;
+
+/// What the C-STORE response `status` (with its ErrorComment) says about the file:
+/// stored, with the warning if it was only stored with one (coerced, or with elements
+/// discarded), or refused, with why.
+fn store_outcome(status: u16, comment: Option<String>) -> Result<Option<String>, String> {
+    let comment = comment.map_or_else(String::new, |c| format!(" ({c})"));
+    match status {
+        0x0000 => Ok(None),
+        0xB000..=0xBFFF => Ok(Some(format!("warning status {status:#06X}{comment}"))),
+        _ => Err(format!("refused with status {status:#06X}{comment}")),
+    }
+}
+
+/// Send `files` to `remote` over one association, calling as `calling_ae`, or one per
+/// [`MAX_CONTEXTS`] SOP classes if there are more. `on_sent` reports each file as it
+/// is sent or fails; a file failing never stops the rest. Fails as a whole only when
+/// the first association can't be made.
+pub fn store(
+    remote: &Remote,
+    calling_ae: &str,
+    files: &[PathBuf],
+    mut on_sent: impl FnMut(&Path, Result<(), DCMRedactErrors>),
+) -> Result<StoreSummary, DCMRedactErrors> {
+    let mut summary = StoreSummary::default();
+    // SOP class and transfer syntax of every file, and the contexts to propose
+    let mut instances = Vec::with_capacity(files.len());
+    let mut contexts: Vec<(String, Vec<String>)> = Vec::new();
+    for path in files {
+        let header = gz::open_file(OpenFileOptions::new().read_until(tags::PIXEL_DATA), path);
+        let obj = match header {
+            Ok(obj) => obj,
+            Err(e) => {
+                let result = Err(DCMRedactErrors::decode(
+                    format!("Failed to read {}", path.display()),
+                    e,
+                ));
+                summary.record(&result);
+                on_sent(path, result);
+                continue;
+            }
+        };
+        let class = trimmed(obj.meta().media_storage_sop_class_uid());
+        let ts = trimmed(obj.meta().transfer_syntax());
+        match contexts.iter_mut().find(|(c, _)| *c == class) {
+            Some((_, syntaxes)) if !syntaxes.contains(&ts) => syntaxes.insert(0, ts.clone()),
+            Some(_) => {}
+            None => {
+                let mut syntaxes = vec![ts.clone()];
+                syntaxes.extend(NATIVE.iter().map(|s| s.to_string()).filter(|s| *s != ts));
+                contexts.push((class.clone(), syntaxes));
+            }
+        }
+        instances.push((path, class, ts));
+    }
+    if instances.is_empty() {
+        return Ok(summary);
+    }
+
+    // More SOP classes than one association can propose are sent over several, in turn
+    let mut message_id: u16 = 0;
+    for (n, chunk) in contexts.chunks(MAX_CONTEXTS).enumerate() {
+        let batch: Vec<&(&PathBuf, String, String)> = instances
+            .iter()
+            .filter(|(_, class, _)| chunk.iter().any(|(c, _)| c == class))
+            .collect();
+        let mut options = ClientAssociationOptions::new()
+            .calling_ae_title(calling_ae)
+            .called_ae_title(remote.ae_title.as_str());
+        for (class, syntaxes) in chunk {
+            options = options.with_presentation_context(class.clone(), syntaxes.clone());
+        }
+        let mut scu = match options.establish((remote.host.as_str(), remote.port)) {
+            Ok(scu) => scu,
+            Err(e) if n == 0 => {
+                return Err(DCMRedactErrors::network(
+                    format!("Failed to associate with {remote}"),
+                    e,
+                ));
+            }
+            Err(e) => {
+                let why = format!("no association: {e}");
+                for (path, _, _) in batch {
+                    let result = Err(DCMRedactErrors::network(
+                        format!("Failed to send {} to {remote}", path.display()),
+                        why.clone(),
+                    ));
+                    summary.record(&result);
+                    on_sent(path, result);
+                }
+                continue;
+            }
+        };
+
+        for (path, class, ts) in batch {
+            let (path, class, ts) = (*path, class.as_str(), ts.as_str());
+            message_id = message_id.wrapping_add(1).max(1);
+            let failed = |why: String| {
+                DCMRedactErrors::network(
+                    format!("Failed to send {} to {remote}", path.display()),
+                    why,
+                )
+            };
+            let mut send = || -> Result<Option<String>, DCMRedactErrors> {
+                // Contexts are numbered 1, 3, 5, ... in the order they were proposed
+                let index = chunk.iter().position(|(c, _)| c == class).unwrap_or(0);
+                let id = (2 * index + 1) as u8;
+                let accepted = scu
+                    .presentation_contexts()
+                    .iter()
+                    .find(|pc| {
+                        pc.id == id && pc.reason == PresentationContextResultReason::Acceptance
+                    })
+                    .ok_or_else(|| failed(format!("SOP class {class} was not accepted")))?;
+                let accepted_ts = trimmed(&accepted.transfer_syntax);
+                if accepted_ts != ts && !(is_native(ts) && is_native(&accepted_ts)) {
+                    return Err(failed(format!(
+                        "it takes {accepted_ts} for this SOP class, and the file is compressed as {ts}"
+                    )));
+                }
+                let write_ts = TransferSyntaxRegistry
+                    .get(&accepted_ts)
+                    .ok_or_else(|| failed(format!("unknown transfer syntax {accepted_ts}")))?;
+                let obj = gz::open_file(OpenFileOptions::new(), path).map_err(|e| {
+                    DCMRedactErrors::decode(format!("Failed to read {}", path.display()), e)
+                })?;
+                let mut data = Vec::new();
+                obj.write_dataset_with_ts(&mut data, write_ts)
+                    .map_err(|e| {
+                        DCMRedactErrors::encode(format!("Failed to encode {}", path.display()), e)
+                    })?;
+                let instance = trimmed(obj.meta().media_storage_sop_instance_uid());
+                let command = store_request(class, &instance, message_id);
+
+                scu.send(&Pdu::PData {
+                    data: vec![PDataValue {
+                        presentation_context_id: id,
+                        value_type: PDataValueType::Command,
+                        is_last: true,
+                        data: encode_command(&command)?,
+                    }],
+                })
+                .map_err(|e| failed(e.to_string()))?;
+                scu.send_pdata(id)
+                    .write_all(&data)
+                    .map_err(|e| failed(e.to_string()))?;
+                let response = scu.receive().map_err(|e| failed(e.to_string()))?;
+                let (code, comment) = status(&read_command(&response, remote)?);
+                store_outcome(code, comment).map_err(failed)
+            };
+            let result = send().map(|warning| {
+                if let Some(warning) = warning {
+                    summary
+                        .warnings
+                        .push(format!("{}: {warning}", path.display()));
+                }
+            });
+            summary.record(&result);
+            on_sent(path, result);
+        }
+        // The files are stored whether or not the release goes through
+        let _ = scu.release();
+    }
+    Ok(summary)
+}
+
+/// A new, empty folder under the system temporary directory to save output to before
+/// sending it. The caller removes it afterwards.
+pub fn spool_dir() -> Result<PathBuf, DCMRedactErrors> {
+    let dir = std::env::temp_dir().join(format!("dcm-redact-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to create {}", dir.display()), e))?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remotes_parse_with_a_default_port() {
+        let remote: Remote = "PACS@10.0.0.5:11112".parse().unwrap();
+        assert_eq!((remote.ae_title.as_str(), remote.port), ("PACS", 11112));
+        assert_eq!(
+            "PACS@pacs.local".parse::<Remote>().unwrap().port,
+            DICOM_PORT
+        );
+        assert_eq!("PACS@[::1]:4242".parse::<Remote>().unwrap().host, "::1");
+        assert_eq!(remote.to_string(), "PACS@10.0.0.5:11112");
+        for bad in [
+            "pacs.local:104",
+            "@pacs.local",
+            "PACS@",
+            "A_TITLE_TOO_LONG_@h",
+            "PACS@h:port",
+        ] {
+            assert!(bad.parse::<Remote>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn success_stores_without_a_warning() {
+        assert_eq!(store_outcome(0x0000, None), Ok(None));
+    }
+
+    #[test]
+    fn warning_statuses_store_and_are_reported() {
+        assert_eq!(
+            store_outcome(0xB000, Some("coerced".to_string())),
+            Ok(Some("warning status 0xB000 (coerced)".to_string()))
+        );
+        assert_eq!(
+            store_outcome(0xB007, None),
+            Ok(Some("warning status 0xB007".to_string()))
+        );
+    }
+
+    #[test]
+    fn other_statuses_are_failures() {
+        assert_eq!(
+            store_outcome(0x0001, None),
+            Err("refused with status 0x0001".to_string())
+        );
+        assert_eq!(
+            store_outcome(0xA700, Some("out of resources".to_string())),
+            Err("refused with status 0xA700 (out of resources)".to_string())
+        );
+        assert!(store_outcome(0xC000, None).is_err());
+        assert!(store_outcome(0xFFFF, None).is_err());
+    }
+
+    #[test]
+    fn summaries_count_each_result() {
+        let mut summary = StoreSummary::default();
+        summary.record(&Ok(()));
+        summary.record(&Err(DCMRedactErrors::ValueError("no".to_string())));
+        summary.record(&Ok(()));
+        assert_eq!((summary.sent, summary.failed), (2, 1));
+    }
+
+    #[test]
+    fn only_uncompressed_syntaxes_are_re_encoded() {
+        assert!(is_native(uids::EXPLICIT_VR_LITTLE_ENDIAN));
+        assert!(is_native(uids::IMPLICIT_VR_LITTLE_ENDIAN));
+        // JPEG baseline
+        assert!(!is_native("1.2.840.10008.1.2.4.50"));
+    }
+}
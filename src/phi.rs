@@ -0,0 +1,184 @@
+//! Scan of a DICOM header for text that looks like PHI, so it can be reviewed before
+//! anonymization. Every string-valued element, including those inside sequences, is
+//! checked against a list of regex rules, kept in `phi_rules.toml` in the config
+//! directory:
+//!
+//! ```toml
+//! [[rule]]
+//! name = "MRN"
+//! pattern = '\bMRN[#: ]*\d{5,}'
+//!
+//! [[rule]]
+//! name = "Person name"
+//! pattern = '\S'
+//! vrs = ["PN"]        # only check elements with these VRs
+//! ```
+//!
+//! Without the file, [`default_rules`] are used. Patterns use the `regex` crate's
+//! syntax; `(?i)` makes one case-insensitive.
+
+use crate::metadata::{ElementRow, element_rows};
+use crate::{DCMRedactErrors, config_dir};
+use dicom::core::VR;
+use dicom::object::InMemDicomObject;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhiRule {
+    pub name: String,
+    pub pattern: String,
+    /// VRs (e.g. `"PN"`) the rule applies to; empty = every text VR.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vrs: Vec<String>,
+    #[serde(skip)]
+    regex: Option<Regex>,
+}
+
+impl PhiRule {
+    pub fn new(name: &str, pattern: &str, vrs: &[&str]) -> Result<Self, DCMRedactErrors> {
+        let mut rule = Self {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            vrs: vrs.iter().map(|v| v.to_string()).collect(),
+            regex: None,
+        };
+        rule.compile()?;
+        Ok(rule)
+    }
+
+    fn compile(&mut self) -> Result<(), DCMRedactErrors> {
+        let regex = Regex::new(&self.pattern).map_err(|e| {
+            DCMRedactErrors::decode(format!("Invalid pattern for PHI rule '{}'", self.name), e)
+        })?;
+        self.regex = Some(regex);
+        Ok(())
+    }
+
+    fn applies_to(&self, vr: VR) -> bool {
+        self.vrs.is_empty()
+            || self
+                .vrs
+                .iter()
+                .any(|v| v.eq_ignore_ascii_case(vr.to_string()))
+    }
+
+    /// The first part of `value` the rule matches.
+    fn find<'a>(&self, value: &'a str) -> Option<&'a str> {
+        self.regex.as_ref()?.find(value).map(|m| m.as_str())
+    }
+}
+
+/// Built-in rules: person names, MRNs, phone numbers, e-mail addresses, SSNs and dates.
+pub fn default_rules() -> Vec<PhiRule> {
+    [
+        ("Person name", r"\S", &["PN"][..]),
+        ("Name in text", r"\b[A-Z][A-Za-z'-]+\^[A-Z][A-Za-z'-]+", &[]),
+        (
+            "MRN",
+            r"(?i)\b(MRN|med(ical)?[ .]*rec(ord)?[ .]*(no|num(ber)?)?|patient[ .]*id)[#:. ]*\d{4,}",
+            &[],
+        ),
+        (
+            "Phone number",
+            r"(\+\d{1,3}[ .-]?)?\(?\b\d{3}\)?[ .-]\d{3}[ .-]\d{4}\b",
+            &[],
+        ),
+        ("E-mail address", r"\b[\w.+-]+@[\w-]+\.[\w.-]+\b", &[]),
+        ("SSN", r"\b\d{3}-\d{2}-\d{4}\b", &[]),
+        (
+            "Date",
+            r"\b(19|20)\d{2}(0[1-9]|1[0-2])(0[1-9]|[12]\d|3[01])\b|\b\d{4}[-/.]\d{1,2}[-/.]\d{1,2}\b|\b\d{1,2}[-/.]\d{1,2}[-/.](\d{4}|\d{2})\b",
+            &[],
+        ),
+    ]
+    .into_iter()
+    .map(|(name, pattern, vrs)| PhiRule::new(name, pattern, vrs).expect("valid built-in rule"))
+    .collect()
+}
+
+#[derive(Deserialize, Serialize)]
+struct RuleFile {
+    #[serde(default)]
+    rule: Vec<PhiRule>,
+}
+
+/// `phi_rules.toml` in the config directory.
+pub fn default_rules_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("phi_rules.toml"))
+}
+
+/// Rules from `path`. A missing file gives [`default_rules`].
+pub fn load_rules(path: &Path) -> Result<Vec<PhiRule>, DCMRedactErrors> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(default_rules()),
+        Err(e) => {
+            return Err(DCMRedactErrors::io(
+                format!("Failed to read PHI rules {}", path.display()),
+                e,
+            ));
+        }
+    };
+    let mut file: RuleFile = toml::from_str(&text)
+        .map_err(|e| DCMRedactErrors::decode(format!("Invalid PHI rules {}", path.display()), e))?;
+    for rule in &mut file.rule {
+        rule.compile()?;
+    }
+    Ok(file.rule)
+}
+
+/// An element whose value matched a rule.
+#[derive(Debug, Clone)]
+pub struct PhiFinding {
+    pub element: ElementRow,
+    pub rule: String,
+    /// The part of the value that matched.
+    pub matched: String,
+}
+
+/// Elements that can hold free text; UIDs are left out, their digits match too much.
+fn is_text(vr: VR) -> bool {
+    matches!(
+        vr,
+        VR::AE
+            | VR::AS
+            | VR::CS
+            | VR::DA
+            | VR::DS
+            | VR::DT
+            | VR::IS
+            | VR::LO
+            | VR::LT
+            | VR::PN
+            | VR::SH
+            | VR::ST
+            | VR::TM
+            | VR::UC
+            | VR::UR
+            | VR::UT
+    )
+}
+
+/// Every text element of `obj` (in dataset order, sequence items included) matching a
+/// rule; an element appears once per rule it matches.
+pub fn scan(obj: &InMemDicomObject, rules: &[PhiRule]) -> Vec<PhiFinding> {
+    let mut findings = Vec::new();
+    for row in element_rows(obj) {
+        if !is_text(row.vr) || row.value.is_empty() {
+            continue;
+        }
+        for rule in rules.iter().filter(|r| r.applies_to(row.vr)) {
+            if let Some(matched) = rule.find(&row.value) {
+                findings.push(PhiFinding {
+                    element: row.clone(),
+                    rule: rule.name.clone(),
+                    matched: matched.to_string(),
+                });
+            }
+        }
+    }
+    findings
+}
@@ -0,0 +1,373 @@
+//! Extension points for site-specific detection and de-identification steps.
+//!
+//! A [`RegionDetector`] proposes rectangles to redact on a frame, and a
+//! [`TagTransformer`] edits the output header once the anonymization profile has run.
+//! Both are registered in [`Plugins`], which batch jobs run on every file and the GUI
+//! offers next to its own detectors. Rust code registers its own implementations;
+//! without recompiling, `plugins.toml` in the config directory turns on built-in
+//! detectors by name and hooks up external programs:
+//!
+//! ```toml
+//! detectors = ["ultrasound"]          # built-in: text, ocr, ultrasound
+//!
+//! [[command_detector]]
+//! name = "site-burnin"
+//! command = "/opt/site/find-burnin"   # run as: command [args] frame.png
+//! args = ["--strict"]
+//!
+//! [[command_transformer]]
+//! name = "site-codes"
+//! command = "/opt/site/fix-tags"      # run as: command [args] header.dcm
+//! ```
+//!
+//! A command detector gets the frame as an 8-bit PNG (with `DCM_REDACT_SOURCE` and
+//! `DCM_REDACT_FRAME` set to the source file and frame index) and prints a JSON list of
+//! `[x0, y0, x1, y1]` rectangles. A command transformer gets the output header, without
+//! pixel data, as a DICOM file to edit in place. Either one exiting unsuccessfully fails
+//! the file.
+
+use crate::{DCMRedactErrors, RedactRect, Redactor, config_dir};
+use dicom::dictionary_std::tags;
+use dicom::object::{FileDicomObject, InMemDicomObject};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+/// Finds regions with PHI burned in.
+pub trait RegionDetector: Send + Sync {
+    /// Shown in the GUI and in error messages.
+    fn name(&self) -> &str;
+
+    /// Rectangles to redact on frame `frame` of `redactor`, in full-res pixels.
+    fn detect(&self, redactor: &Redactor, frame: usize)
+    -> Result<Vec<RedactRect>, DCMRedactErrors>;
+}
+
+/// Edits the header written on save, after de-identification.
+pub trait TagTransformer: Send + Sync {
+    /// Shown in error messages.
+    fn name(&self) -> &str;
+
+    /// Change `out`, the header about to be written. `source` is the header as loaded,
+    /// for values the profile has already removed from `out`.
+    fn transform(
+        &self,
+        source: &InMemDicomObject,
+        out: &mut FileDicomObject<InMemDicomObject>,
+    ) -> Result<(), DCMRedactErrors>;
+}
+
+/// Registered detectors and transformers, run in registration order. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct Plugins {
+    detectors: Vec<Arc<dyn RegionDetector>>,
+    transformers: Vec<Arc<dyn TagTransformer>>,
+}
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plugins")
+            .field(
+                "detectors",
+                &self.detectors.iter().map(|d| d.name()).collect::<Vec<_>>(),
+            )
+            .field(
+                "transformers",
+                &self
+                    .transformers
+                    .iter()
+                    .map(|t| t.name())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Plugins {
+    pub fn register_detector(&mut self, detector: impl RegionDetector + 'static) {
+        self.detectors.push(Arc::new(detector));
+    }
+
+    pub fn register_transformer(&mut self, transformer: impl TagTransformer + 'static) {
+        self.transformers.push(Arc::new(transformer));
+    }
+
+    pub fn has_detectors(&self) -> bool {
+        !self.detectors.is_empty()
+    }
+
+    pub fn has_transformers(&self) -> bool {
+        !self.transformers.is_empty()
+    }
+
+    /// These plugins with the detectors left out, for runs where nobody reviews what
+    /// they find.
+    pub fn without_detectors(&self) -> Self {
+        Self {
+            detectors: Vec::new(),
+            transformers: self.transformers.clone(),
+        }
+    }
+
+    /// Names of the registered detectors.
+    pub fn detector_names(&self) -> Vec<&str> {
+        self.detectors.iter().map(|d| d.name()).collect()
+    }
+
+    /// Every detector's rectangles on frame `frame`, clamped to the image; empty ones
+    /// are dropped.
+    pub fn detect(
+        &self,
+        redactor: &Redactor,
+        frame: usize,
+    ) -> Result<Vec<RedactRect>, DCMRedactErrors> {
+        let (width, height) = redactor.dimensions();
+        let mut rects = Vec::new();
+        for detector in &self.detectors {
+            let found = detector.detect(redactor, frame).map_err(|e| {
+                DCMRedactErrors::ValueError(format!("Detector {}: {e}", detector.name()))
+            })?;
+            rects.extend(found.into_iter().filter_map(|r| {
+                let r = RedactRect {
+                    x0: r.x0.min(width),
+                    y0: r.y0.min(height),
+                    x1: r.x1.min(width),
+                    y1: r.y1.min(height),
+                };
+                (r.x1 > r.x0 && r.y1 > r.y0).then_some(r)
+            }));
+        }
+        Ok(rects)
+    }
+
+    /// Run every transformer over `out`.
+    pub fn transform(
+        &self,
+        source: &InMemDicomObject,
+        out: &mut FileDicomObject<InMemDicomObject>,
+    ) -> Result<(), DCMRedactErrors> {
+        for transformer in &self.transformers {
+            transformer.transform(source, out).map_err(|e| {
+                DCMRedactErrors::ValueError(format!("Transformer {}: {e}", transformer.name()))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// The built-in detector called `name`: `text` ([`crate::suggest`]), `ocr`
+/// ([`crate::ocr`]) or `ultrasound` (everything outside the ultrasound regions).
+#[cfg(feature = "builtin-detectors")]
+pub fn builtin_detector(name: &str) -> Option<Arc<dyn RegionDetector>> {
+    match name {
+        "text" => Some(Arc::new(builtin::Text)),
+        "ocr" => Some(Arc::new(builtin::Ocr)),
+        "ultrasound" => Some(Arc::new(builtin::Ultrasound)),
+        _ => None,
+    }
+}
+
+/// Built without the `builtin-detectors` feature: there are none.
+#[cfg(not(feature = "builtin-detectors"))]
+pub fn builtin_detector(_name: &str) -> Option<Arc<dyn RegionDetector>> {
+    None
+}
+
+#[cfg(feature = "builtin-detectors")]
+mod builtin {
+    use super::RegionDetector;
+    use crate::ocr::OcrOptions;
+    use crate::suggest::SuggestOptions;
+    use crate::{DCMRedactErrors, RedactRect, Redactor};
+
+    pub struct Text;
+    pub struct Ocr;
+    pub struct Ultrasound;
+
+    impl RegionDetector for Text {
+        fn name(&self) -> &str {
+            "text"
+        }
+
+        fn detect(&self, r: &Redactor, frame: usize) -> Result<Vec<RedactRect>, DCMRedactErrors> {
+            Ok(r.suggest_regions(frame, &SuggestOptions::default()))
+        }
+    }
+
+    impl RegionDetector for Ocr {
+        fn name(&self) -> &str {
+            "ocr"
+        }
+
+        fn detect(&self, r: &Redactor, frame: usize) -> Result<Vec<RedactRect>, DCMRedactErrors> {
+            r.detect_text(frame, &OcrOptions::default())
+        }
+    }
+
+    impl RegionDetector for Ultrasound {
+        fn name(&self) -> &str {
+            "ultrasound"
+        }
+
+        fn detect(&self, r: &Redactor, _frame: usize) -> Result<Vec<RedactRect>, DCMRedactErrors> {
+            Ok(r.ultrasound_mask())
+        }
+    }
+}
+
+/// An external program run as a [`RegionDetector`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandDetector {
+    pub name: String,
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl RegionDetector for CommandDetector {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(
+        &self,
+        redactor: &Redactor,
+        frame: usize,
+    ) -> Result<Vec<RedactRect>, DCMRedactErrors> {
+        let img = crate::ocr::frame_to_luma8(
+            redactor.frame(frame),
+            redactor.bits_stored(),
+            redactor.photometric_interpretation() == Some("MONOCHROME1"),
+        );
+        let png =
+            std::env::temp_dir().join(format!("dcm-redact-plugin-{}.png", uuid::Uuid::new_v4()));
+        img.save(&png)
+            .map_err(|e| DCMRedactErrors::encode("Failed to write detector input", e))?;
+        let mut command = Command::new(&self.command);
+        command
+            .args(&self.args)
+            .arg(&png)
+            .env("DCM_REDACT_FRAME", frame.to_string());
+        if let Some(source) = redactor.source() {
+            command.env("DCM_REDACT_SOURCE", source);
+        }
+        let output = command.output();
+        let _ = fs::remove_file(&png);
+        let stdout = run_output(&self.command, output)?;
+        let rects: Vec<[u32; 4]> = serde_json::from_slice(&stdout).map_err(|e| {
+            DCMRedactErrors::decode(
+                format!("Unexpected output from {}", self.command.display()),
+                e,
+            )
+        })?;
+        Ok(rects
+            .into_iter()
+            .map(|[x0, y0, x1, y1]| RedactRect { x0, y0, x1, y1 })
+            .collect())
+    }
+}
+
+/// An external program run as a [`TagTransformer`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandTransformer {
+    pub name: String,
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl TagTransformer for CommandTransformer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn transform(
+        &self,
+        _source: &InMemDicomObject,
+        out: &mut FileDicomObject<InMemDicomObject>,
+    ) -> Result<(), DCMRedactErrors> {
+        // The program only sees the header; the (unredacted) pixels are put back after
+        let pixels = out.take_element(tags::PIXEL_DATA).ok();
+        let path =
+            std::env::temp_dir().join(format!("dcm-redact-plugin-{}.dcm", uuid::Uuid::new_v4()));
+        let edited = crate::write_dicom_file(out, &path).and_then(|()| {
+            let output = Command::new(&self.command)
+                .args(&self.args)
+                .arg(&path)
+                .output();
+            run_output(&self.command, output)?;
+            crate::read_dicom_object(&path)
+        });
+        let _ = fs::remove_file(&path);
+        *out = edited?;
+        if let Some(pixels) = pixels {
+            out.put(pixels);
+        }
+        Ok(())
+    }
+}
+
+/// The stdout of a finished plugin command, or why it failed.
+fn run_output(
+    command: &Path,
+    output: std::io::Result<std::process::Output>,
+) -> Result<Vec<u8>, DCMRedactErrors> {
+    let output = output
+        .map_err(|e| DCMRedactErrors::io(format!("Failed to run {}", command.display()), e))?;
+    if !output.status.success() {
+        return Err(DCMRedactErrors::ValueError(format!(
+            "{} failed: {}",
+            command.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+#[derive(Deserialize)]
+struct PluginFile {
+    #[serde(default)]
+    detectors: Vec<String>,
+    #[serde(default)]
+    command_detector: Vec<CommandDetector>,
+    #[serde(default)]
+    command_transformer: Vec<CommandTransformer>,
+}
+
+/// `plugins.toml` in the config directory.
+pub fn default_plugins_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("plugins.toml"))
+}
+
+/// The detectors and transformers listed in `path`. A missing file gives none.
+pub fn load_plugins(path: &Path) -> Result<Plugins, DCMRedactErrors> {
+    let mut plugins = Plugins::default();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(plugins),
+        Err(e) => {
+            return Err(DCMRedactErrors::io(
+                format!("Failed to read plugins {}", path.display()),
+                e,
+            ));
+        }
+    };
+    let file: PluginFile = toml::from_str(&text)
+        .map_err(|e| DCMRedactErrors::decode(format!("Invalid plugins {}", path.display()), e))?;
+    for name in &file.detectors {
+        let detector = builtin_detector(name).ok_or_else(|| {
+            DCMRedactErrors::ValueError(format!("Unknown detector '{name}' in {}", path.display()))
+        })?;
+        plugins.detectors.push(detector);
+    }
+    for detector in file.command_detector {
+        plugins.register_detector(detector);
+    }
+    for transformer in file.command_transformer {
+        plugins.register_transformer(transformer);
+    }
+    Ok(plugins)
+}
@@ -0,0 +1,138 @@
+//! Presentation states (GSPS and the other softcopy presentation states): the
+//! windowing, shutters and annotations a reader saved for a set of images, stored as
+//! separate instances next to them.
+//!
+//! They have no pixels, but their text annotations are typed by readers and can name
+//! the patient, and they reference the images they apply to by SOP Instance UID. They
+//! are opened as documents; de-identification remaps those references through the
+//! same UID map as the images, and [`scrub_annotations`] replaces the patient's names
+//! and IDs in the annotation text. Saving images from a folder also picks up the
+//! presentation states that reference them ([`accompanying`]), so they stay together.
+
+use dicom::core::header::Header;
+use dicom::core::{DataElement, PrimitiveValue, Tag};
+use dicom::dictionary_std::tags;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::anonymize::{patient_identifiers, replace_identifiers};
+use crate::gz;
+
+/// SOP Class UIDs of the softcopy presentation states (grayscale, color, pseudo-color,
+/// blending, ...) start with this.
+const PR_CLASS_PREFIX: &str = "1.2.840.10008.5.1.4.1.1.11.";
+
+/// Free text in a presentation state that identifiers are scrubbed from.
+const TEXT: [Tag; 2] = [tags::UNFORMATTED_TEXT_VALUE, tags::CONTENT_DESCRIPTION];
+
+fn text(obj: &InMemDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+        .unwrap_or_default()
+}
+
+fn items(obj: &InMemDicomObject, tag: Tag) -> &[InMemDicomObject] {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.items())
+        .unwrap_or_default()
+}
+
+/// Whether `obj` is a presentation state, by its SOP Class or Modality.
+pub fn is_presentation_state(obj: &InMemDicomObject) -> bool {
+    text(obj, tags::SOP_CLASS_UID).starts_with(PR_CLASS_PREFIX)
+        || text(obj, tags::MODALITY).trim().eq_ignore_ascii_case("PR")
+}
+
+/// Every ReferencedSOPInstanceUID in `obj`, at any depth.
+fn referenced_instances(obj: &InMemDicomObject, uids: &mut HashSet<String>) {
+    for elem in obj.iter() {
+        if elem.tag() == tags::REFERENCED_SOP_INSTANCE_UID {
+            if let Ok(uid) = elem.to_str() {
+                uids.insert(uid.trim_end_matches(['\0', ' ']).to_string());
+            }
+        } else if let Some(items) = elem.items() {
+            for item in items {
+                referenced_instances(item, uids);
+            }
+        }
+    }
+}
+
+/// The presentation states among `candidates` that reference any of `images`, by
+/// SOP Instance UID. Files whose header can't be read are skipped.
+pub fn accompanying(candidates: &[PathBuf], images: &[PathBuf]) -> Vec<PathBuf> {
+    let header = |path: &PathBuf| {
+        gz::open_file(OpenFileOptions::new().read_until(tags::PIXEL_DATA), path).ok()
+    };
+    let wanted: HashSet<String> = images
+        .iter()
+        .filter_map(header)
+        .map(|obj| text(&obj, tags::SOP_INSTANCE_UID))
+        .filter(|uid| !uid.is_empty())
+        .collect();
+    candidates
+        .iter()
+        .filter(|path| !images.contains(path))
+        .filter(|path| {
+            header(path).is_some_and(|obj| {
+                if !is_presentation_state(&obj) {
+                    return false;
+                }
+                let mut uids = HashSet::new();
+                referenced_instances(&obj, &mut uids);
+                !uids.is_disjoint(&wanted)
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+fn scrub_level(obj: &mut InMemDicomObject, ids: &[String]) {
+    let tags: Vec<Tag> = obj.iter().map(|e| e.tag()).collect();
+    for tag in tags {
+        if TEXT.contains(&tag) {
+            let Ok(elem) = obj.element(tag) else {
+                continue;
+            };
+            let vr = elem.vr();
+            if let Some(new) = elem
+                .to_str()
+                .ok()
+                .and_then(|v| replace_identifiers(&v, ids))
+            {
+                obj.put(DataElement::new(tag, vr, PrimitiveValue::from(new)));
+            }
+            continue;
+        }
+        obj.update_value(tag, |value| {
+            if let Some(items) = value.items_mut() {
+                for item in items.iter_mut() {
+                    scrub_level(item, ids);
+                }
+            }
+        });
+    }
+}
+
+/// Replace the patient's names and IDs, as found in `source`, wherever they appear in
+/// the text annotations and content description of `out`.
+pub fn scrub_annotations(source: &InMemDicomObject, out: &mut InMemDicomObject) {
+    let ids = patient_identifiers(source);
+    if !ids.is_empty() {
+        scrub_level(out, &ids);
+    }
+}
+
+/// The text annotations of `obj`, for display.
+pub fn annotation_texts(obj: &InMemDicomObject) -> Vec<String> {
+    items(obj, tags::GRAPHIC_ANNOTATION_SEQUENCE)
+        .iter()
+        .flat_map(|annotation| items(annotation, tags::TEXT_OBJECT_SEQUENCE))
+        .map(|t| text(t, tags::UNFORMATTED_TEXT_VALUE))
+        .filter(|t| !t.is_empty())
+        .collect()
+}
@@ -0,0 +1,176 @@
+//! Vendor presets: where particular scanners burn PHI banners into their images, keyed
+//! on Manufacturer, ManufacturerModelName and Modality. A matching preset stages its
+//! regions for review instead of drawing them by hand.
+//!
+//! A few presets are built in ([`builtin_presets`]); more are read from
+//! `presets.toml` in the config directory, and replace built-in ones of the same name:
+//!
+//! ```toml
+//! [[preset]]
+//! name = "Philips EPIQ top banner"
+//! manufacturer = "Philips"      # case-insensitive, part of the value
+//! model = "EPIQ"                # likewise, ManufacturerModelName
+//! modality = "US"               # exact (case-insensitive)
+//! rects = [[0, 0, 0, 60]]       # x0, y0, x1, y1
+//! ```
+//!
+//! Rectangle edges count back from the right or bottom edge of the image where
+//! negative, and for `x1`/`y1` also where 0, so `[0, 0, 0, 60]` is the top 60 rows
+//! and `[0, -40, 0, 0]` the bottom 40, whatever the image size.
+
+use crate::{DCMRedactErrors, RedactRect, Region, Shape, config_dir};
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modality: Option<String>,
+    pub rects: Vec<[i64; 4]>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PresetFile {
+    #[serde(default)]
+    preset: Vec<Preset>,
+}
+
+fn text(obj: &InMemDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+impl Preset {
+    fn new(
+        name: &str,
+        manufacturer: &str,
+        model: &str,
+        modality: &str,
+        rects: &[[i64; 4]],
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            manufacturer: Some(manufacturer.to_string()),
+            model: Some(model.to_string()),
+            modality: Some(modality.to_string()),
+            rects: rects.to_vec(),
+        }
+    }
+
+    /// Whether every attribute the preset names matches `obj`.
+    pub fn matches(&self, obj: &InMemDicomObject) -> bool {
+        let contains = |wanted: &Option<String>, tag| {
+            wanted.as_ref().is_none_or(|w| {
+                text(obj, tag)
+                    .to_lowercase()
+                    .contains(&w.trim().to_lowercase())
+            })
+        };
+        contains(&self.manufacturer, tags::MANUFACTURER)
+            && contains(&self.model, tags::MANUFACTURER_MODEL_NAME)
+            && self
+                .modality
+                .as_ref()
+                .is_none_or(|m| text(obj, tags::MODALITY).eq_ignore_ascii_case(m.trim()))
+    }
+
+    /// The preset's rectangles on a `width`×`height` image, as regions on every
+    /// frame. Rectangles that end up empty are left out.
+    pub fn regions(&self, width: u32, height: u32) -> Vec<Region> {
+        let edge = |v: i64, size: u32, end: bool| {
+            let size = i64::from(size);
+            let v = if v < 0 || (end && v == 0) {
+                size + v
+            } else {
+                v
+            };
+            v.clamp(0, size) as u32
+        };
+        self.rects
+            .iter()
+            .map(|&[x0, y0, x1, y1]| RedactRect {
+                x0: edge(x0, width, false),
+                y0: edge(y0, height, false),
+                x1: edge(x1, width, true),
+                y1: edge(y1, height, true),
+            })
+            .filter(|r| r.x0 < r.x1 && r.y0 < r.y1)
+            .map(|r| Region::all_frames(Shape::Rect(r)))
+            .collect()
+    }
+}
+
+/// Presets shipped with dcm-redact: the top banner (patient name, ID and date) of
+/// common ultrasound systems. They are starting points; review what they stage.
+pub fn builtin_presets() -> Vec<Preset> {
+    vec![
+        Preset::new(
+            "Philips EPIQ top banner",
+            "Philips",
+            "EPIQ",
+            "US",
+            &[[0, 0, 0, 60]],
+        ),
+        Preset::new(
+            "Philips Affiniti top banner",
+            "Philips",
+            "Affiniti",
+            "US",
+            &[[0, 0, 0, 60]],
+        ),
+        Preset::new("GE LOGIQ top banner", "GE", "LOGIQ", "US", &[[0, 0, 0, 56]]),
+        Preset::new("GE Vivid top banner", "GE", "Vivid", "US", &[[0, 0, 0, 56]]),
+        Preset::new(
+            "Siemens ACUSON top banner",
+            "Siemens",
+            "ACUSON",
+            "US",
+            &[[0, 0, 0, 64]],
+        ),
+    ]
+}
+
+/// `presets.toml` in the config directory.
+pub fn default_presets_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("presets.toml"))
+}
+
+/// The built-in presets and those in `path`, which replace built-in ones of the same
+/// name (case-insensitive). A missing file gives the built-in presets.
+pub fn load_presets(path: &Path) -> Result<Vec<Preset>, DCMRedactErrors> {
+    let mut presets = builtin_presets();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(presets),
+        Err(e) => {
+            return Err(DCMRedactErrors::io(
+                format!("Failed to read presets {}", path.display()),
+                e,
+            ));
+        }
+    };
+    let file: PresetFile = toml::from_str(&text)
+        .map_err(|e| DCMRedactErrors::decode(format!("Invalid presets {}", path.display()), e))?;
+    for preset in file.preset {
+        presets.retain(|p| !p.name.eq_ignore_ascii_case(&preset.name));
+        presets.push(preset);
+    }
+    Ok(presets)
+}
+
+/// The presets in `presets` that match `obj`.
+pub fn matching<'a>(presets: &'a [Preset], obj: &InMemDicomObject) -> Vec<&'a Preset> {
+    presets.iter().filter(|p| p.matches(obj)).collect()
+}
@@ -0,0 +1,285 @@
+//! Site-specific anonymization profiles, read from `profiles.toml` in the config
+//! directory (`~/.config/dcm-redact/` on Linux):
+//!
+//! ```toml
+//! [[profile]]
+//! name = "Research, shifted dates"
+//! keep = ["PatientSex", "PatientAge"]
+//! hash = ["PatientID"]
+//! remove = ["0008,1030"]
+//! dates = { shift = -30 }
+//! uids = "hash"
+//! hash_salt = "site secret"
+//! ```
+//!
+//! A profile starts from the PS3.15 Basic Profile (unless `basic_profile = false`) and
+//! overrides it per attribute: `keep` > `remove` > `blank` > `hash`, then the date and
+//! UID policies. `structured_reports = false` removes the content of SR documents, as
+//! the Basic Profile does, instead of scrubbing it ([`crate::sr`]).
+//!
+//! The same file holds rules that pick a region template and profile per file in
+//! batch runs ([`ProfileRule`]):
+//!
+//! ```toml
+//! [[rule]]
+//! name = "Vivid echo"
+//! modality = "US"                     # exact (case-insensitive)
+//! manufacturer = "GE"                 # case-insensitive, part of the value
+//! series_description = "echo"         # likewise
+//! template = "templates/vivid.json"   # relative to this file
+//! profile = "Research, shifted dates"
+//! ```
+
+use crate::template::Template;
+use crate::{DCMRedactErrors, Region};
+use dicom::core::Tag;
+use dicom::core::dictionary::DataDictionary;
+use dicom::dictionary_std::{StandardDataDictionary, tags};
+use dicom::object::InMemDicomObject;
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What happens to DA/DT (and TM) attributes not listed explicitly. Policies only
+/// apply where the base profile empties, replaces or keeps the value: attributes it
+/// removes stay removed, and PatientBirthDate always follows the base profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatePolicy {
+    /// Whatever the base profile does (usually empty or dummy values).
+    #[default]
+    Profile,
+    /// Keep dates and times.
+    Keep,
+    /// Remove dates and times; required (Type 2) ones are emptied, and those the base
+    /// profile replaces with dummy values keep them.
+    Remove,
+    /// Move every date by this many days, keeping intervals; times are kept.
+    Shift(i64),
+}
+
+/// What happens to UIDs the base profile replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UidPolicy {
+    /// Random new UIDs, consistent within a session or batch run.
+    #[default]
+    Remap,
+    /// Keep the source UIDs.
+    Keep,
+    /// UIDs derived from a salted hash of the source UID, consistent across runs.
+    Hash,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Start from the Basic Profile; otherwise everything not listed is kept.
+    #[serde(default = "default_true")]
+    pub basic_profile: bool,
+    #[serde(default, deserialize_with = "tag_list")]
+    pub keep: Vec<Tag>,
+    #[serde(default, deserialize_with = "tag_list")]
+    pub remove: Vec<Tag>,
+    /// Replaced with a zero-length value.
+    #[serde(default, deserialize_with = "tag_list")]
+    pub blank: Vec<Tag>,
+    /// Replaced with a salted hash of the value, so equal values stay equal.
+    #[serde(default, deserialize_with = "tag_list")]
+    pub hash: Vec<Tag>,
+    #[serde(default)]
+    pub dates: DatePolicy,
+    #[serde(default)]
+    pub uids: UidPolicy,
+    /// Keep the content tree of SR documents, scrubbing the names, text and dates in
+    /// it, rather than removing it.
+    #[serde(default = "default_true")]
+    pub structured_reports: bool,
+    /// Secret mixed into every hash; without it hashed IDs can be brute-forced.
+    #[serde(default)]
+    pub hash_salt: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Attributes given by keyword (`PatientName`) or tag (`0010,0010`, `(0010,0010)`).
+fn tag_list<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<Tag>, D::Error> {
+    let names = Vec::<String>::deserialize(de)?;
+    names
+        .iter()
+        .map(|name| {
+            StandardDataDictionary
+                .parse_tag(name.trim())
+                .ok_or_else(|| serde::de::Error::custom(format!("unknown attribute '{name}'")))
+        })
+        .collect()
+}
+
+impl Default for Profile {
+    /// The plain PS3.15 Basic Profile.
+    fn default() -> Self {
+        Self {
+            name: "Basic Profile".to_string(),
+            description: String::new(),
+            basic_profile: true,
+            keep: Vec::new(),
+            remove: Vec::new(),
+            blank: Vec::new(),
+            hash: Vec::new(),
+            dates: DatePolicy::default(),
+            uids: UidPolicy::default(),
+            structured_reports: true,
+            hash_salt: String::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ProfileFile {
+    #[serde(default)]
+    profile: Vec<Profile>,
+    #[serde(default)]
+    rule: Vec<RuleSpec>,
+}
+
+/// A `[[rule]]` as written, before its template and profile are looked up.
+#[derive(Deserialize)]
+struct RuleSpec {
+    name: String,
+    modality: Option<String>,
+    manufacturer: Option<String>,
+    series_description: Option<String>,
+    template: Option<PathBuf>,
+    profile: Option<String>,
+}
+
+/// Conditions on a file's Modality, Manufacturer and SeriesDescription, and the
+/// regions and profile used for files that meet them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileRule {
+    pub name: String,
+    /// Exact (case-insensitive); `None` matches anything, as for the others.
+    pub modality: Option<String>,
+    /// Case-insensitive, part of the value.
+    pub manufacturer: Option<String>,
+    /// Case-insensitive, part of the value.
+    pub series_description: Option<String>,
+    /// The regions of the rule's template, redacted on top of the run's own.
+    pub regions: Vec<Region>,
+    /// Used instead of the run's profile.
+    pub profile: Option<Profile>,
+}
+
+impl ProfileRule {
+    /// Whether `obj` meets every condition of the rule.
+    pub fn matches(&self, obj: &InMemDicomObject) -> bool {
+        let text = |tag| {
+            obj.element(tag)
+                .ok()
+                .and_then(|e| e.to_str().ok())
+                .map(|s| s.trim().to_lowercase())
+                .unwrap_or_default()
+        };
+        let contains = |wanted: &Option<String>, tag| {
+            wanted
+                .as_ref()
+                .is_none_or(|w| text(tag).contains(&w.trim().to_lowercase()))
+        };
+        self.modality
+            .as_ref()
+            .is_none_or(|m| text(tags::MODALITY) == m.trim().to_lowercase())
+            && contains(&self.manufacturer, tags::MANUFACTURER)
+            && contains(&self.series_description, tags::SERIES_DESCRIPTION)
+    }
+}
+
+/// The first rule in `rules` that `obj` matches.
+pub fn matching_rule<'a>(
+    rules: &'a [ProfileRule],
+    obj: &InMemDicomObject,
+) -> Option<&'a ProfileRule> {
+    rules.iter().find(|r| r.matches(obj))
+}
+
+/// `profiles.toml` in the config directory.
+pub fn default_profiles_path() -> Option<PathBuf> {
+    crate::config_dir().map(|d| d.join("profiles.toml"))
+}
+
+fn read_profile_file(path: &Path) -> Result<Option<ProfileFile>, DCMRedactErrors> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(DCMRedactErrors::io(
+                format!("Failed to read profiles {}", path.display()),
+                e,
+            ));
+        }
+    };
+    toml::from_str(&text)
+        .map(Some)
+        .map_err(|e| DCMRedactErrors::decode(format!("Invalid profiles {}", path.display()), e))
+}
+
+/// Every profile in `path`. A missing file gives an empty list.
+pub fn load_profiles(path: &Path) -> Result<Vec<Profile>, DCMRedactErrors> {
+    Ok(read_profile_file(path)?.map_or_else(Vec::new, |f| f.profile))
+}
+
+/// Every rule in `path`, in order, with its template loaded (relative paths are
+/// relative to `path`) and its profile found among those in `path`. A missing file
+/// gives an empty list.
+pub fn load_profile_rules(path: &Path) -> Result<Vec<ProfileRule>, DCMRedactErrors> {
+    let Some(file) = read_profile_file(path)? else {
+        return Ok(Vec::new());
+    };
+    let base = path.parent().unwrap_or(Path::new(""));
+    file.rule
+        .into_iter()
+        .map(|spec| {
+            let regions = match &spec.template {
+                Some(template) => Template::load(&base.join(template))?.regions,
+                None => Vec::new(),
+            };
+            let profile = match &spec.profile {
+                Some(name) => Some(
+                    file.profile
+                        .iter()
+                        .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+                        .cloned()
+                        .ok_or_else(|| {
+                            DCMRedactErrors::ValueError(format!(
+                                "Rule '{}' names no profile '{name}' in {}",
+                                spec.name,
+                                path.display()
+                            ))
+                        })?,
+                ),
+                None => None,
+            };
+            Ok(ProfileRule {
+                name: spec.name,
+                modality: spec.modality,
+                manufacturer: spec.manufacturer,
+                series_description: spec.series_description,
+                regions,
+                profile,
+            })
+        })
+        .collect()
+}
+
+/// The profile called `name` in `path` (case-insensitive).
+pub fn find_profile(path: &Path, name: &str) -> Result<Profile, DCMRedactErrors> {
+    load_profiles(path)?
+        .into_iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| {
+            DCMRedactErrors::ValueError(format!("No profile named '{name}' in {}", path.display()))
+        })
+}
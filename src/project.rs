@@ -0,0 +1,123 @@
+//! `.dcmredact` project files: work in progress on a folder, saved so a large study
+//! can be finished over several sittings.
+//!
+//! A project records the folder (or ZIP archive) being worked through, the
+//! de-identification choices, and for every file with work on it the regions staged
+//! and those already burned in. Nothing else is written: restoring opens the source
+//! files again and burns the applied regions back in. File paths inside the folder are
+//! stored relative to it, so a project still opens after the folder is moved along
+//! with it, and a ZIP's files are found again in its next extraction.
+//!
+//! ```json
+//! {
+//!   "folder": "/data/study-42",
+//!   "profile": "Research, shifted dates",
+//!   "deidentify": true,
+//!   "fill": "black",
+//!   "active": "series1/IM0003",
+//!   "files": [
+//!     {
+//!       "path": "series1/IM0003",
+//!       "frame": 12,
+//!       "staged": [{ "shape": { "rect": { "x0": 0, "y0": 0, "x1": 640, "y1": 48 } } }],
+//!       "applied": [{ "shape": { "rect": { "x0": 0, "y0": 440, "x1": 640, "y1": 480 } }, "fill": "black" }]
+//!     }
+//!   ]
+//! }
+//! ```
+
+use crate::audit::AppliedRegion;
+use crate::{DCMRedactErrors, RedactFill, Region};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extension of project files.
+pub const EXTENSION: &str = "dcmredact";
+
+/// `recovery.dcmredact` in the config directory: the unsaved work of the running
+/// session, autosaved so it survives a crash.
+pub fn default_recovery_path() -> Option<PathBuf> {
+    crate::config_dir().map(|d| d.join(format!("recovery.{EXTENSION}")))
+}
+
+pub fn is_project_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(EXTENSION))
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    /// Folder or ZIP archive being worked through; `None` for files opened on their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<PathBuf>,
+    /// Anonymization profile by name; `None` is the Basic Profile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default = "default_true")]
+    pub deidentify: bool,
+    #[serde(default)]
+    pub fill: RedactFill,
+    /// The file shown when the project was saved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<PathBuf>,
+    #[serde(default)]
+    pub files: Vec<ProjectFile>,
+}
+
+/// The work on one file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectFile {
+    pub path: PathBuf,
+    /// Frame shown.
+    #[serde(default)]
+    pub frame: usize,
+    /// Regions drawn but not applied yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub staged: Vec<Region>,
+    /// Regions burned into the pixels, in the order they were applied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applied: Vec<AppliedRegion>,
+}
+
+impl ProjectFile {
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty() && self.applied.is_empty()
+    }
+}
+
+impl Project {
+    pub fn load(path: &Path) -> Result<Self, DCMRedactErrors> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to read project {}", path.display()), e)
+        })?;
+        serde_json::from_str(&text)
+            .map_err(|e| DCMRedactErrors::decode(format!("Invalid project {}", path.display()), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DCMRedactErrors> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| DCMRedactErrors::encode("Failed to encode project", e))?;
+        fs::write(path, text + "\n").map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to write project {}", path.display()), e)
+        })
+    }
+
+    /// `path` as stored: relative to `dir` (the open folder) when inside it.
+    pub fn relative(dir: Option<&Path>, path: &Path) -> PathBuf {
+        dir.and_then(|d| path.strip_prefix(d).ok())
+            .map_or_else(|| path.to_path_buf(), Path::to_path_buf)
+    }
+
+    /// A stored path back as a path on disk, with the folder open at `dir`.
+    pub fn resolve(dir: Option<&Path>, path: &Path) -> PathBuf {
+        match dir {
+            Some(d) if path.is_relative() => d.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+}
@@ -0,0 +1,460 @@
+//! Persistent patient pseudonyms: the same PatientID always gets the same generated
+//! pseudonym, across files, sessions and batch runs.
+//!
+//! The lookup table is a CSV file (`patient_id,patient_name,pseudonym`). With a key it
+//! is stored encrypted with AES-256-GCM instead: `DCMRPSE1`, a 12-byte nonce, then the
+//! encrypted CSV. Keys are 32 random bytes kept hex-encoded in a separate key file.
+
+use crate::DCMRedactErrors;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use rand::Rng;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const MAGIC: &[u8; 8] = b"DCMRPSE1";
+
+fn err(msg: String) -> DCMRedactErrors {
+    DCMRedactErrors::ValueError(msg)
+}
+
+fn not_utf8(path: &Path, e: std::string::FromUtf8Error) -> DCMRedactErrors {
+    DCMRedactErrors::decode(format!("{} is not valid UTF-8", path.display()), e)
+}
+
+/// Open `path` for writing, readable and writable by its owner only on Unix. With
+/// `new`, fails if it already exists; otherwise truncates it.
+fn open_private(path: &Path, new: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if new {
+        options.create_new(true);
+    } else {
+        options.create(true).truncate(true);
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+/// Read a key file, or create one with a fresh random key if it doesn't exist.
+pub fn load_or_create_key(path: &Path) -> Result<[u8; 32], DCMRedactErrors> {
+    let write_err =
+        |e| DCMRedactErrors::io(format!("Failed to write key file {}", path.display()), e);
+    match open_private(path, true) {
+        Ok(mut file) => {
+            let key: [u8; 32] = rand::thread_rng().r#gen();
+            let hex: String = key.iter().map(|b| format!("{b:02x}")).collect();
+            file.write_all((hex + "\n").as_bytes()).map_err(write_err)?;
+            return Ok(key);
+        }
+        // Created meanwhile by another run: read that one
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(write_err(e)),
+    }
+    let text = fs::read_to_string(path).map_err(|e| {
+        DCMRedactErrors::io(format!("Failed to read key file {}", path.display()), e)
+    })?;
+    let text = text.trim();
+    let bad = || err(format!("{}: expected 64 hex digits", path.display()));
+    if text.len() != 64 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(bad());
+    }
+    let mut key = [0u8; 32];
+    for (i, b) in key.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&text[2 * i..2 * i + 2], 16).map_err(|_| bad())?;
+    }
+    Ok(key)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    patient_id: String,
+    patient_name: String,
+    pseudonym: String,
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break.
+fn csv_quote(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Split CSV text written with [`csv_quote`] into records, each with the line it
+/// starts on. Line breaks inside quoted fields belong to the field; blank lines are
+/// skipped.
+fn csv_records(text: &str) -> Vec<(usize, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut fields = vec![String::new()];
+    let (mut line, mut start) = (1, 1);
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                let record = std::mem::replace(&mut fields, vec![String::new()]);
+                if record.len() > 1 || !record[0].trim().is_empty() {
+                    records.push((start, record));
+                }
+                line += 1;
+                start = line;
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                fields.last_mut().unwrap().push(c);
+            }
+        }
+    }
+    if fields.len() > 1 || !fields[0].trim().is_empty() {
+        records.push((start, fields));
+    }
+    records
+}
+
+/// The entry of a patient, by ID, or by name when the ID is empty.
+fn find<'a>(entries: &'a [Entry], id: &str, name: &str) -> Option<&'a Entry> {
+    entries.iter().find(|e| {
+        if id.is_empty() {
+            e.patient_id.is_empty() && e.patient_name == name
+        } else {
+            e.patient_id == id
+        }
+    })
+}
+
+/// PatientID and PatientName of `source`, without padding.
+fn patient(source: &InMemDicomObject) -> (String, String) {
+    let text = |tag| {
+        source
+            .element(tag)
+            .ok()
+            .and_then(|e| e.to_str().ok())
+            .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+            .unwrap_or_default()
+    };
+    (text(tags::PATIENT_ID), text(tags::PATIENT_NAME))
+}
+
+fn put_pseudonym(out: &mut InMemDicomObject, pseudonym: &str) {
+    out.put(DataElement::new(
+        tags::PATIENT_ID,
+        VR::LO,
+        PrimitiveValue::from(pseudonym),
+    ));
+    out.put(DataElement::new(
+        tags::PATIENT_NAME,
+        VR::PN,
+        PrimitiveValue::from(pseudonym),
+    ));
+}
+
+/// Patient → pseudonym table shared by every file saved in a session. Clones are
+/// handles to the same table.
+#[derive(Clone)]
+pub struct PseudonymTable {
+    path: PathBuf,
+    key: Option<[u8; 32]>,
+    /// Pseudonyms are this prefix plus 8 random hex digits.
+    prefix: String,
+    entries: Arc<Mutex<Vec<Entry>>>,
+}
+
+impl fmt::Debug for PseudonymTable {
+    // Keep the key out of logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PseudonymTable")
+            .field("path", &self.path)
+            .field("encrypted", &self.key.is_some())
+            .field("entries", &self.len())
+            .finish()
+    }
+}
+
+impl PseudonymTable {
+    /// Open the table at `path`, encrypted with `key` if given. A missing file gives
+    /// an empty table; a plain table opened with a key is encrypted on the next save.
+    pub fn open(path: &Path, key: Option<[u8; 32]>) -> Result<Self, DCMRedactErrors> {
+        let entries = match fs::read(path) {
+            Ok(bytes) => Self::parse(path, &bytes, key.as_ref())?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(DCMRedactErrors::io(
+                    format!("Failed to read pseudonym table {}", path.display()),
+                    e,
+                ));
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            key,
+            prefix: "ANON".to_string(),
+            entries: Arc::new(Mutex::new(entries)),
+        })
+    }
+
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn parse(
+        path: &Path,
+        bytes: &[u8],
+        key: Option<&[u8; 32]>,
+    ) -> Result<Vec<Entry>, DCMRedactErrors> {
+        let text = if let Some(body) = bytes.strip_prefix(MAGIC) {
+            let Some(key) = key else {
+                return Err(err(format!(
+                    "{} is encrypted; a key file is needed to open it",
+                    path.display()
+                )));
+            };
+            if body.len() < 12 {
+                return Err(err(format!("{} is truncated", path.display())));
+            }
+            let cipher = Aes256Gcm::new_from_slice(key)
+                .map_err(|_| err("Invalid pseudonym key".to_string()))?;
+            let (nonce, ciphertext) = body.split_at(12);
+            let plain = cipher
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|_| err(format!("Wrong key for {}", path.display())))?;
+            String::from_utf8(plain).map_err(|e| not_utf8(path, e))?
+        } else {
+            String::from_utf8(bytes.to_vec()).map_err(|e| not_utf8(path, e))?
+        };
+        let mut entries = Vec::new();
+        for (line, fields) in csv_records(&text).into_iter().skip(1) {
+            let [patient_id, patient_name, pseudonym] =
+                <[String; 3]>::try_from(fields).map_err(|_| {
+                    err(format!(
+                        "{}:{line}: expected patient_id,patient_name,pseudonym",
+                        path.display()
+                    ))
+                })?;
+            entries.push(Entry {
+                patient_id,
+                patient_name,
+                pseudonym,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// The pseudonym for a patient, generating (and remembering) one on first use.
+    /// Patients are matched by ID, or by name when the ID is empty.
+    pub fn pseudonym(&self, patient_id: &str, patient_name: &str) -> String {
+        let (id, name) = (patient_id.trim(), patient_name.trim());
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(e) = find(&entries, id, name) {
+            return e.pseudonym.clone();
+        }
+        let mut rng = rand::thread_rng();
+        let pseudonym = loop {
+            let candidate = format!("{}{:08X}", self.prefix, rng.r#gen::<u32>());
+            if !entries.iter().any(|e| e.pseudonym == candidate) {
+                break candidate;
+            }
+        };
+        entries.push(Entry {
+            patient_id: id.to_string(),
+            patient_name: name.to_string(),
+            pseudonym: pseudonym.clone(),
+        });
+        pseudonym
+    }
+
+    /// The pseudonym a patient already has, like [`PseudonymTable::pseudonym`] but
+    /// without generating one.
+    pub fn lookup(&self, patient_id: &str, patient_name: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        find(&entries, patient_id.trim(), patient_name.trim()).map(|e| e.pseudonym.clone())
+    }
+
+    /// Stands in for the pseudonym of a patient not in the table yet, in previews.
+    pub fn placeholder(&self) -> String {
+        format!("{}XXXXXXXX", self.prefix)
+    }
+
+    /// Replace PatientID and PatientName in `out` with the pseudonym of the patient
+    /// in `source`, generating one if needed.
+    pub fn apply(&self, source: &InMemDicomObject, out: &mut InMemDicomObject) {
+        let (id, name) = patient(source);
+        put_pseudonym(out, &self.pseudonym(&id, &name));
+    }
+
+    /// [`PseudonymTable::apply`] for previews: a patient not in the table gets
+    /// [`PseudonymTable::placeholder`] and the table is left as it is.
+    pub fn preview(&self, source: &InMemDicomObject, out: &mut InMemDicomObject) {
+        let (id, name) = patient(source);
+        let pseudonym = self
+            .lookup(&id, &name)
+            .unwrap_or_else(|| self.placeholder());
+        put_pseudonym(out, &pseudonym);
+    }
+
+    /// Write the table back to its file, encrypted if it has a key. It is written to
+    /// a temporary file next to it first, then renamed over it, so a crash or full
+    /// disk never leaves a truncated table behind.
+    pub fn save(&self) -> Result<(), DCMRedactErrors> {
+        // Held until written, so saves from parallel batch threads don't interleave
+        let entries = self.entries.lock().unwrap();
+        let mut text = String::from("patient_id,patient_name,pseudonym\n");
+        for e in entries.iter() {
+            text += &format!(
+                "{},{},{}\n",
+                csv_quote(&e.patient_id),
+                csv_quote(&e.patient_name),
+                csv_quote(&e.pseudonym)
+            );
+        }
+        let bytes = match &self.key {
+            None => text.into_bytes(),
+            Some(key) => {
+                let cipher = Aes256Gcm::new_from_slice(key)
+                    .map_err(|_| err("Invalid pseudonym key".to_string()))?;
+                let nonce: [u8; 12] = rand::thread_rng().r#gen();
+                let ciphertext = cipher
+                    .encrypt(Nonce::from_slice(&nonce), text.as_bytes())
+                    .map_err(|_| err("Failed to encrypt the pseudonym table".to_string()))?;
+                [MAGIC.as_slice(), &nonce, &ciphertext].concat()
+            }
+        };
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let written = open_private(&temp, false)
+            .and_then(|mut file| {
+                file.write_all(&bytes)?;
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&temp, &self.path));
+        written.map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            DCMRedactErrors::io(
+                format!("Failed to write pseudonym table {}", self.path.display()),
+                e,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dcm-redact-{}-{name}", crate::uids::new_uid()))
+    }
+
+    #[test]
+    fn csv_records_survive_quoting() {
+        let fields = ["MRN,1", "Doe^\"JJ\"\r\nJr", "ANON0000002A"];
+        let line: Vec<String> = fields.iter().map(|f| csv_quote(f)).collect();
+        let text = format!("a,b,c\r\n\n{}\nd,e,f", line.join(","));
+        let records = csv_records(&text);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1], (3, fields.map(String::from).to_vec()));
+        assert_eq!(records[2].0, 5);
+    }
+
+    #[test]
+    fn patients_keep_their_pseudonym() {
+        let table = PseudonymTable::open(&temp_path("missing.csv"), None)
+            .unwrap()
+            .with_prefix("SUBJ");
+        assert_eq!(table.lookup("MRN1", "Doe^Jane"), None);
+        let pseudonym = table.pseudonym("MRN1", "Doe^Jane");
+        assert!(pseudonym.starts_with("SUBJ") && pseudonym.len() == 12);
+        assert_eq!(table.pseudonym(" MRN1 ", "Other^Name"), pseudonym);
+        assert_ne!(table.pseudonym("MRN2", "Doe^Jane"), pseudonym);
+        // Without an ID, patients are told apart by name
+        assert_ne!(
+            table.pseudonym("", "Roe^Rick"),
+            table.pseudonym("", "Poe^Pat")
+        );
+        assert_eq!(
+            table.lookup("", "Roe^Rick"),
+            Some(table.pseudonym("", "Roe^Rick"))
+        );
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.placeholder(), "SUBJXXXXXXXX");
+    }
+
+    #[test]
+    fn tables_survive_a_save_and_open() {
+        let path = temp_path("table.csv");
+        let table = PseudonymTable::open(&path, None).unwrap();
+        let pseudonym = table.pseudonym("MRN,1", "Doe^Jane");
+        let multiline = table.pseudonym("", "Doe^Jane\nJr");
+        table.save().unwrap();
+        let reopened = PseudonymTable::open(&path, None).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.lookup("MRN,1", ""), Some(pseudonym));
+        assert_eq!(reopened.lookup("", "Doe^Jane\nJr"), Some(multiline));
+        assert_eq!(reopened.len(), 2);
+        assert!(!path.with_extension("csv.tmp").exists());
+    }
+
+    #[test]
+    fn encrypted_tables_need_their_key() {
+        let path = temp_path("table.bin");
+        let key = [7; 32];
+        let table = PseudonymTable::open(&path, Some(key)).unwrap();
+        let pseudonym = table.pseudonym("MRN1", "");
+        table.save().unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+        assert!(PseudonymTable::open(&path, None).is_err());
+        assert!(PseudonymTable::open(&path, Some([8; 32])).is_err());
+        let reopened = PseudonymTable::open(&path, Some(key));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.unwrap().lookup("MRN1", ""), Some(pseudonym));
+    }
+
+    #[test]
+    fn key_files_are_created_once_and_kept_private() {
+        let path = temp_path("key.txt");
+        let key = load_or_create_key(&path).unwrap();
+        assert_eq!(load_or_create_key(&path).unwrap(), key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::write(&path, "not a key\n").unwrap();
+        assert!(load_or_create_key(&path).is_err());
+        fs::write(&path, "é".repeat(32)).unwrap();
+        assert!(load_or_create_key(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,990 @@
+//! Pulling source images from a PACS: a C-FIND SCU to look for patients, studies and
+//! series, and C-MOVE or C-GET to retrieve the ones picked into a folder, where they
+//! are opened for redaction like any other.
+//!
+//! Patients are looked up in the Patient Root model, studies and series in the Study
+//! Root model, and retrievals are always by study or series. C-MOVE has the PACS open
+//! an association back to a storage SCP run here for the length of the retrieval, so
+//! the PACS must know the calling AE title with this machine's address and the port
+//! given. C-GET brings the instances back on the association it was asked on, which
+//! needs no setup on the PACS, but only for the storage SOP classes proposed with it
+//! ([`STORAGE_CLASSES`]).
+
+use crate::DCMRedactErrors;
+use crate::pacs::{DATA_SET_PRESENT, NATIVE, Remote, encode_command, status, trimmed};
+use crate::uids::{is_valid_uid, new_uid};
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::encoding::TransferSyntaxIndex;
+use dicom::object::mem::InMemElement;
+use dicom::object::{FileMetaTableBuilder, InMemDicomObject};
+use dicom::transfer_syntax::{TransferSyntaxRegistry, entries};
+use dicom::ul::association::client::ClientAssociationOptions;
+use dicom::ul::association::server::ServerAssociationOptions;
+use dicom::ul::pdu::{
+    PDataValue, PDataValueType, Pdu, PresentationContextResult, PresentationContextResultReason,
+};
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{IpAddr, TcpListener, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Patient Root Query/Retrieve Information Model - FIND.
+const PATIENT_ROOT_FIND: &str = "1.2.840.10008.5.1.4.1.2.1.1";
+/// Study Root Query/Retrieve Information Model - FIND.
+const STUDY_ROOT_FIND: &str = "1.2.840.10008.5.1.4.1.2.2.1";
+/// Study Root Query/Retrieve Information Model - MOVE.
+const STUDY_ROOT_MOVE: &str = "1.2.840.10008.5.1.4.1.2.2.2";
+/// Study Root Query/Retrieve Information Model - GET.
+const STUDY_ROOT_GET: &str = "1.2.840.10008.5.1.4.1.2.2.3";
+
+/// Storage SOP classes proposed with a C-GET, the PACS can only send back these.
+pub const STORAGE_CLASSES: [&str; 24] = [
+    "1.2.840.10008.5.1.4.1.1.1",      // CR
+    "1.2.840.10008.5.1.4.1.1.1.1",    // DX, for presentation
+    "1.2.840.10008.5.1.4.1.1.1.1.1",  // DX, for processing
+    "1.2.840.10008.5.1.4.1.1.1.2",    // MG, for presentation
+    "1.2.840.10008.5.1.4.1.1.2",      // CT
+    "1.2.840.10008.5.1.4.1.1.2.1",    // Enhanced CT
+    "1.2.840.10008.5.1.4.1.1.3.1",    // US multi-frame
+    "1.2.840.10008.5.1.4.1.1.4",      // MR
+    "1.2.840.10008.5.1.4.1.1.4.1",    // Enhanced MR
+    "1.2.840.10008.5.1.4.1.1.6.1",    // US
+    "1.2.840.10008.5.1.4.1.1.7",      // Secondary Capture
+    "1.2.840.10008.5.1.4.1.1.11.1",   // Grayscale Softcopy Presentation State
+    "1.2.840.10008.5.1.4.1.1.12.1",   // XA
+    "1.2.840.10008.5.1.4.1.1.12.2",   // RF
+    "1.2.840.10008.5.1.4.1.1.20",     // NM
+    "1.2.840.10008.5.1.4.1.1.77.1.4", // VL Photographic
+    "1.2.840.10008.5.1.4.1.1.88.11",  // Basic Text SR
+    "1.2.840.10008.5.1.4.1.1.88.22",  // Enhanced SR
+    "1.2.840.10008.5.1.4.1.1.88.33",  // Comprehensive SR
+    "1.2.840.10008.5.1.4.1.1.104.1",  // Encapsulated PDF
+    "1.2.840.10008.5.1.4.1.1.128",    // PET
+    "1.2.840.10008.5.1.4.1.1.481.1",  // RT Image
+    "1.2.840.10008.5.1.4.1.1.481.2",  // RT Dose
+    "1.2.840.10008.5.1.4.1.1.481.3",  // RT Structure Set
+];
+
+/// Transfer syntaxes accepted for instances coming back from a C-GET: the
+/// uncompressed ones, then the common lossless and lossy compressions.
+const RECEIVED_SYNTAXES: [&str; 8] = [
+    NATIVE[0],
+    NATIVE[1],
+    "1.2.840.10008.1.2.4.70", // JPEG Lossless, first-order prediction
+    "1.2.840.10008.1.2.4.80", // JPEG-LS Lossless
+    "1.2.840.10008.1.2.4.90", // JPEG 2000 Lossless
+    "1.2.840.10008.1.2.5",    // RLE Lossless
+    "1.2.840.10008.1.2.4.50", // JPEG Baseline
+    "1.2.840.10008.1.2.4.91", // JPEG 2000
+];
+
+/// CommandField values.
+const C_STORE_RQ: u16 = 0x0001;
+const C_STORE_RSP: u16 = 0x8001;
+const C_GET_RQ: u16 = 0x0010;
+const C_FIND_RQ: u16 = 0x0020;
+const C_MOVE_RQ: u16 = 0x0021;
+/// CommandDataSetType when no data set follows the command.
+const NO_DATA_SET: u16 = 0x0101;
+/// C-STORE statuses sent back for sub-operations.
+const OUT_OF_RESOURCES: u16 = 0xA700;
+const CANNOT_UNDERSTAND: u16 = 0xC000;
+
+/// Port C-MOVE retrievals are received on unless told otherwise.
+pub const DEFAULT_STORE_PORT: u16 = 11112;
+/// How often the C-MOVE storage SCP checks whether the retrieval is over.
+const POLL: Duration = Duration::from_millis(50);
+
+/// What a [`find`] looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryLevel {
+    Patient,
+    #[default]
+    Study,
+    Series,
+}
+
+impl QueryLevel {
+    pub const ALL: [QueryLevel; 3] = [QueryLevel::Patient, QueryLevel::Study, QueryLevel::Series];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            QueryLevel::Patient => "Patient",
+            QueryLevel::Study => "Study",
+            QueryLevel::Series => "Series",
+        }
+    }
+
+    /// The QueryRetrieveLevel value.
+    fn keyword(self) -> &'static str {
+        match self {
+            QueryLevel::Patient => "PATIENT",
+            QueryLevel::Study => "STUDY",
+            QueryLevel::Series => "SERIES",
+        }
+    }
+}
+
+impl fmt::Display for QueryLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label().to_ascii_lowercase())
+    }
+}
+
+/// How matches are retrieved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Retrieve {
+    /// The PACS sends them to a storage SCP run here for the retrieval.
+    #[default]
+    Move,
+    /// The PACS sends them back on the association they were asked for on.
+    Get,
+}
+
+impl Retrieve {
+    pub const ALL: [Retrieve; 2] = [Retrieve::Move, Retrieve::Get];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Retrieve::Move => "C-MOVE",
+            Retrieve::Get => "C-GET",
+        }
+    }
+}
+
+impl fmt::Display for Retrieve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Retrieve::Move => "move",
+            Retrieve::Get => "get",
+        })
+    }
+}
+
+impl FromStr for Retrieve {
+    type Err = String;
+
+    /// Parse `move` or `get`, with or without the `c-`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().trim_start_matches("c-") {
+            "move" => Ok(Retrieve::Move),
+            "get" => Ok(Retrieve::Get),
+            _ => Err(format!("unknown retrieval '{s}' (expected move or get)")),
+        }
+    }
+}
+
+/// The matching keys of a [`find`]. Empty keys match anything. The patient name
+/// matches as a prefix unless it has wildcards (`*`, `?`) of its own; the study date
+/// can be a range (`20240101-20240131`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub level: QueryLevel,
+    pub patient_id: String,
+    pub patient_name: String,
+    pub study_date: String,
+    pub accession: String,
+    pub modality: String,
+    /// The study whose series are listed, at series level.
+    pub study_uid: String,
+}
+
+impl Query {
+    /// The identifier sent with the C-FIND-RQ: the matching keys, and empty return
+    /// keys for the rest of what a [`Match`] shows.
+    fn identifier(&self) -> InMemDicomObject {
+        let name = self.patient_name.trim();
+        let name = if name.is_empty() || name.contains(['*', '?']) {
+            name.to_string()
+        } else {
+            format!("{name}*")
+        };
+        let mut keys = vec![key(
+            tags::QUERY_RETRIEVE_LEVEL,
+            VR::CS,
+            self.level.keyword(),
+        )];
+        match self.level {
+            QueryLevel::Patient => keys.extend([
+                key(tags::PATIENT_NAME, VR::PN, &name),
+                key(tags::PATIENT_ID, VR::LO, &self.patient_id),
+                key(tags::PATIENT_BIRTH_DATE, VR::DA, ""),
+                key(tags::NUMBER_OF_PATIENT_RELATED_STUDIES, VR::IS, ""),
+            ]),
+            QueryLevel::Study => keys.extend([
+                key(tags::STUDY_DATE, VR::DA, &self.study_date),
+                key(tags::ACCESSION_NUMBER, VR::SH, &self.accession),
+                key(tags::MODALITIES_IN_STUDY, VR::CS, &self.modality),
+                key(tags::STUDY_DESCRIPTION, VR::LO, ""),
+                key(tags::PATIENT_NAME, VR::PN, &name),
+                key(tags::PATIENT_ID, VR::LO, &self.patient_id),
+                key(tags::STUDY_INSTANCE_UID, VR::UI, &self.study_uid),
+                key(tags::NUMBER_OF_STUDY_RELATED_INSTANCES, VR::IS, ""),
+            ]),
+            QueryLevel::Series => keys.extend([
+                key(tags::MODALITY, VR::CS, &self.modality),
+                key(tags::SERIES_DESCRIPTION, VR::LO, ""),
+                key(tags::STUDY_INSTANCE_UID, VR::UI, &self.study_uid),
+                key(tags::SERIES_INSTANCE_UID, VR::UI, ""),
+                key(tags::SERIES_NUMBER, VR::IS, ""),
+                key(tags::NUMBER_OF_SERIES_RELATED_INSTANCES, VR::IS, ""),
+            ]),
+        }
+        InMemDicomObject::from_element_iter(keys)
+    }
+}
+
+/// One patient, study or series a [`find`] found. Only the attributes of its level
+/// (and the patient's, for studies) are filled in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Match {
+    pub level: QueryLevel,
+    pub patient_id: String,
+    pub patient_name: String,
+    pub birth_date: String,
+    pub study_uid: String,
+    pub study_date: String,
+    pub study_description: String,
+    pub accession: String,
+    /// ModalitiesInStudy for a study, Modality for a series.
+    pub modality: String,
+    pub series_uid: String,
+    pub series_number: String,
+    pub series_description: String,
+    /// Studies of a patient, or instances of a study or series, as the PACS counts them.
+    pub related: String,
+}
+
+impl Match {
+    fn from_identifier(level: QueryLevel, obj: &InMemDicomObject) -> Self {
+        let modality = match level {
+            QueryLevel::Series => tags::MODALITY,
+            _ => tags::MODALITIES_IN_STUDY,
+        };
+        let related = match level {
+            QueryLevel::Patient => tags::NUMBER_OF_PATIENT_RELATED_STUDIES,
+            QueryLevel::Study => tags::NUMBER_OF_STUDY_RELATED_INSTANCES,
+            QueryLevel::Series => tags::NUMBER_OF_SERIES_RELATED_INSTANCES,
+        };
+        Match {
+            level,
+            patient_id: text(obj, tags::PATIENT_ID),
+            patient_name: text(obj, tags::PATIENT_NAME),
+            birth_date: text(obj, tags::PATIENT_BIRTH_DATE),
+            study_uid: text(obj, tags::STUDY_INSTANCE_UID),
+            study_date: text(obj, tags::STUDY_DATE),
+            study_description: text(obj, tags::STUDY_DESCRIPTION),
+            accession: text(obj, tags::ACCESSION_NUMBER),
+            modality: text(obj, modality),
+            series_uid: text(obj, tags::SERIES_INSTANCE_UID),
+            series_number: text(obj, tags::SERIES_NUMBER),
+            series_description: text(obj, tags::SERIES_DESCRIPTION),
+            related: text(obj, related),
+        }
+    }
+
+    /// Whether it is a study or a series, which are what can be retrieved.
+    pub fn is_retrievable(&self) -> bool {
+        match self.level {
+            QueryLevel::Patient => false,
+            QueryLevel::Study => !self.study_uid.is_empty(),
+            QueryLevel::Series => !self.study_uid.is_empty() && !self.series_uid.is_empty(),
+        }
+    }
+
+    /// What it shows in a list of matches, column by column.
+    pub fn columns(&self) -> Vec<String> {
+        let mut columns = match self.level {
+            QueryLevel::Patient => vec![&self.patient_id, &self.patient_name, &self.birth_date],
+            QueryLevel::Study => vec![
+                &self.study_date,
+                &self.patient_id,
+                &self.patient_name,
+                &self.accession,
+                &self.modality,
+                &self.study_description,
+            ],
+            QueryLevel::Series => vec![
+                &self.series_number,
+                &self.modality,
+                &self.series_description,
+            ],
+        }
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .collect::<Vec<_>>();
+        columns.push(match (self.level, self.related.trim()) {
+            (_, "") => String::new(),
+            (QueryLevel::Patient, n) => format!("{n} studies"),
+            (_, n) => format!("{n} instances"),
+        });
+        columns
+    }
+
+    /// The query one level down: a patient's studies or a study's series.
+    pub fn children(&self) -> Option<Query> {
+        match self.level {
+            QueryLevel::Patient => Some(Query {
+                level: QueryLevel::Study,
+                patient_id: self.patient_id.clone(),
+                ..Query::default()
+            }),
+            QueryLevel::Study => Some(Query {
+                level: QueryLevel::Series,
+                study_uid: self.study_uid.clone(),
+                ..Query::default()
+            }),
+            QueryLevel::Series => None,
+        }
+    }
+
+    /// The identifier of a C-MOVE-RQ or C-GET-RQ for it.
+    fn identifier(&self) -> InMemDicomObject {
+        let mut keys = vec![
+            key(tags::QUERY_RETRIEVE_LEVEL, VR::CS, self.level.keyword()),
+            key(tags::STUDY_INSTANCE_UID, VR::UI, &self.study_uid),
+        ];
+        if self.level == QueryLevel::Series {
+            keys.push(key(tags::SERIES_INSTANCE_UID, VR::UI, &self.series_uid));
+        }
+        InMemDicomObject::from_element_iter(keys)
+    }
+}
+
+/// The files a [`retrieve`] received, and how many instances the PACS couldn't send.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Retrieved {
+    pub files: Vec<PathBuf>,
+    pub failed: usize,
+}
+
+fn key(tag: Tag, vr: VR, value: &str) -> InMemElement {
+    DataElement::new(tag, vr, PrimitiveValue::from(value.trim()))
+}
+
+fn text(obj: &InMemDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+        .unwrap_or_default()
+}
+
+fn number(obj: &InMemDicomObject, tag: Tag) -> Option<u16> {
+    obj.element(tag).ok().and_then(|e| e.to_int::<u16>().ok())
+}
+
+/// The command set of a C-FIND, C-MOVE or C-GET request.
+fn request(
+    field: u16,
+    model: &str,
+    message_id: u16,
+    destination: Option<&str>,
+) -> InMemDicomObject {
+    let mut elements = vec![
+        DataElement::new(
+            tags::AFFECTED_SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(model),
+        ),
+        DataElement::new(tags::COMMAND_FIELD, VR::US, PrimitiveValue::from(field)),
+        DataElement::new(tags::MESSAGE_ID, VR::US, PrimitiveValue::from(message_id)),
+        DataElement::new(tags::PRIORITY, VR::US, PrimitiveValue::from(0u16)),
+        DataElement::new(
+            tags::COMMAND_DATA_SET_TYPE,
+            VR::US,
+            PrimitiveValue::from(DATA_SET_PRESENT),
+        ),
+    ];
+    if let Some(ae) = destination {
+        elements.push(DataElement::new(
+            tags::MOVE_DESTINATION,
+            VR::AE,
+            PrimitiveValue::from(ae),
+        ));
+    }
+    InMemDicomObject::command_from_element_iter(elements)
+}
+
+/// The C-STORE-RSP to a C-STORE-RQ.
+fn store_response(request: &InMemDicomObject, code: u16) -> InMemDicomObject {
+    InMemDicomObject::command_from_element_iter([
+        DataElement::new(
+            tags::AFFECTED_SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(text(request, tags::AFFECTED_SOP_CLASS_UID)),
+        ),
+        DataElement::new(
+            tags::COMMAND_FIELD,
+            VR::US,
+            PrimitiveValue::from(C_STORE_RSP),
+        ),
+        DataElement::new(
+            tags::MESSAGE_ID_BEING_RESPONDED_TO,
+            VR::US,
+            PrimitiveValue::from(number(request, tags::MESSAGE_ID).unwrap_or(0)),
+        ),
+        DataElement::new(
+            tags::COMMAND_DATA_SET_TYPE,
+            VR::US,
+            PrimitiveValue::from(NO_DATA_SET),
+        ),
+        DataElement::new(tags::STATUS, VR::US, PrimitiveValue::from(code)),
+        DataElement::new(
+            tags::AFFECTED_SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from(text(request, tags::AFFECTED_SOP_INSTANCE_UID)),
+        ),
+    ])
+}
+
+/// A P-DATA PDU carrying `command` on presentation context `id`.
+fn command_pdu(id: u8, command: &InMemDicomObject) -> Result<Pdu, DCMRedactErrors> {
+    Ok(Pdu::PData {
+        data: vec![PDataValue {
+            presentation_context_id: id,
+            value_type: PDataValueType::Command,
+            is_last: true,
+            data: encode_command(command)?,
+        }],
+    })
+}
+
+/// The request and its identifier, encoded in the transfer syntax accepted for `id`.
+fn request_pdu(
+    id: u8,
+    contexts: &[PresentationContextResult],
+    command: &InMemDicomObject,
+    identifier: &InMemDicomObject,
+) -> Result<Pdu, DCMRedactErrors> {
+    let ts = contexts
+        .iter()
+        .find(|pc| pc.id == id)
+        .and_then(|pc| TransferSyntaxRegistry.get(&trimmed(&pc.transfer_syntax)))
+        .ok_or_else(|| {
+            DCMRedactErrors::network("No transfer syntax for the query", "unknown context")
+        })?;
+    let mut data = Vec::new();
+    identifier
+        .write_dataset_with_ts(&mut data, ts)
+        .map_err(|e| DCMRedactErrors::encode("Failed to encode the query", e))?;
+    Ok(Pdu::PData {
+        data: vec![
+            PDataValue {
+                presentation_context_id: id,
+                value_type: PDataValueType::Command,
+                is_last: true,
+                data: encode_command(command)?,
+            },
+            PDataValue {
+                presentation_context_id: id,
+                value_type: PDataValueType::Data,
+                is_last: true,
+                data,
+            },
+        ],
+    })
+}
+
+/// Whether the context numbered `id` was accepted.
+fn accepted(contexts: &[PresentationContextResult], id: u8) -> bool {
+    contexts
+        .iter()
+        .any(|pc| pc.id == id && pc.reason == PresentationContextResultReason::Acceptance)
+}
+
+/// A DIMSE message part, put back together from its P-DATA fragments.
+enum Part {
+    Command(InMemDicomObject),
+    /// A data set, still encoded, and the presentation context it came on.
+    Data(u8, Vec<u8>),
+}
+
+/// Fragments of the command and data set being received.
+#[derive(Default)]
+struct Fragments {
+    command: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Fragments {
+    /// Add the fragments in `pdu`, returning the parts they complete.
+    fn push(&mut self, pdu: Pdu, peer: &str) -> Result<Vec<Part>, DCMRedactErrors> {
+        let values = match pdu {
+            Pdu::PData { data } => data,
+            Pdu::AbortRQ { .. } => {
+                return Err(DCMRedactErrors::network(
+                    format!("{peer} aborted the association"),
+                    "A-ABORT",
+                ));
+            }
+            _ => {
+                return Err(DCMRedactErrors::network(
+                    format!("Unexpected reply from {peer}"),
+                    "not a DIMSE message",
+                ));
+            }
+        };
+        let mut parts = Vec::new();
+        for value in values {
+            match value.value_type {
+                PDataValueType::Command => {
+                    self.command.extend(value.data);
+                    if value.is_last {
+                        let bytes = std::mem::take(&mut self.command);
+                        let command = InMemDicomObject::read_dataset_with_ts(
+                            bytes.as_slice(),
+                            &entries::IMPLICIT_VR_LITTLE_ENDIAN.erased(),
+                        )
+                        .map_err(|e| {
+                            DCMRedactErrors::decode(format!("Invalid command from {peer}"), e)
+                        })?;
+                        parts.push(Part::Command(command));
+                    }
+                }
+                PDataValueType::Data => {
+                    self.data.extend(value.data);
+                    if value.is_last {
+                        parts.push(Part::Data(
+                            value.presentation_context_id,
+                            std::mem::take(&mut self.data),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(parts)
+    }
+}
+
+/// Whether `code` says more responses follow.
+fn is_pending(code: u16) -> bool {
+    code == 0xFF00 || code == 0xFF01
+}
+
+/// Whether a final `code` is success or a warning.
+fn is_done(code: u16) -> bool {
+    code == 0x0000 || (0xB000..=0xBFFF).contains(&code)
+}
+
+fn refused(what: String, command: &InMemDicomObject) -> DCMRedactErrors {
+    let (code, comment) = status(command);
+    DCMRedactErrors::network(
+        what,
+        format!(
+            "refused with status {code:#06X}{}",
+            comment.map_or_else(String::new, |c| format!(" ({c})"))
+        ),
+    )
+}
+
+/// Write the instance of a C-STORE sub-operation, received on context `id`, into
+/// `dir` as `<SOP Instance UID>.dcm`. Returns the status to answer with, and the file.
+/// An instance whose UID isn't a well-formed UID is refused, since it names the file.
+fn store_instance(
+    dir: &Path,
+    request: &InMemDicomObject,
+    id: u8,
+    data: &[u8],
+    contexts: &[PresentationContextResult],
+) -> (u16, Option<PathBuf>) {
+    let Some(ts) = contexts.iter().find(|pc| pc.id == id) else {
+        return (CANNOT_UNDERSTAND, None);
+    };
+    let ts_uid = trimmed(&ts.transfer_syntax);
+    let Some(ts) = TransferSyntaxRegistry.get(&ts_uid) else {
+        return (CANNOT_UNDERSTAND, None);
+    };
+    let Ok(obj) = InMemDicomObject::read_dataset_with_ts(data, ts) else {
+        return (CANNOT_UNDERSTAND, None);
+    };
+    let class = text(request, tags::AFFECTED_SOP_CLASS_UID);
+    let mut instance = text(request, tags::AFFECTED_SOP_INSTANCE_UID);
+    if instance.is_empty() {
+        instance = new_uid();
+    } else if !is_valid_uid(&instance) {
+        return (CANNOT_UNDERSTAND, None);
+    }
+    let file = obj.with_meta(
+        FileMetaTableBuilder::new()
+            .transfer_syntax(ts_uid)
+            .media_storage_sop_class_uid(class)
+            .media_storage_sop_instance_uid(instance.clone()),
+    );
+    let path = dir.join(format!("{instance}.dcm"));
+    match file.map(|file| file.write_to_file(&path)) {
+        Ok(Ok(())) => (0x0000, Some(path)),
+        _ => (OUT_OF_RESOURCES, None),
+    }
+}
+
+/// Look for `query` on `remote`, calling as `calling_ae`.
+pub fn find(
+    remote: &Remote,
+    calling_ae: &str,
+    query: &Query,
+) -> Result<Vec<Match>, DCMRedactErrors> {
+    let model = match query.level {
+        QueryLevel::Patient => PATIENT_ROOT_FIND,
+        _ => STUDY_ROOT_FIND,
+    };
+    let mut scu = ClientAssociationOptions::new()
+        .calling_ae_title(calling_ae)
+        .called_ae_title(remote.ae_title.as_str())
+        .with_presentation_context(model, NATIVE.to_vec())
+        .establish((remote.host.as_str(), remote.port))
+        .map_err(|e| DCMRedactErrors::network(format!("Failed to associate with {remote}"), e))?;
+    let failed = |why: String| DCMRedactErrors::network(format!("Query on {remote} failed"), why);
+    if !accepted(scu.presentation_contexts(), 1) {
+        let _ = scu.abort();
+        return Err(failed("it does not take queries in this model".to_string()));
+    }
+    let pdu = request_pdu(
+        1,
+        scu.presentation_contexts(),
+        &request(C_FIND_RQ, model, 1, None),
+        &query.identifier(),
+    )?;
+    scu.send(&pdu).map_err(|e| failed(e.to_string()))?;
+
+    let ts = scu
+        .presentation_contexts()
+        .iter()
+        .find(|pc| pc.id == 1)
+        .and_then(|pc| TransferSyntaxRegistry.get(&trimmed(&pc.transfer_syntax)))
+        .ok_or_else(|| failed("unknown transfer syntax".to_string()))?;
+    let peer = remote.to_string();
+    let mut fragments = Fragments::default();
+    let mut matches = Vec::new();
+    'responses: loop {
+        let pdu = scu.receive().map_err(|e| failed(e.to_string()))?;
+        for part in fragments.push(pdu, &peer)? {
+            match part {
+                Part::Command(command) => {
+                    let (code, _) = status(&command);
+                    if is_pending(code) {
+                        continue;
+                    }
+                    let _ = scu.release();
+                    if is_done(code) {
+                        break 'responses;
+                    }
+                    return Err(refused(format!("Query on {remote} failed"), &command));
+                }
+                Part::Data(_, data) => {
+                    let identifier = InMemDicomObject::read_dataset_with_ts(data.as_slice(), ts)
+                        .map_err(|e| {
+                            DCMRedactErrors::decode(format!("Invalid match from {remote}"), e)
+                        })?;
+                    matches.push(Match::from_identifier(query.level, &identifier));
+                }
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Run a storage SCP on `listener` for a C-MOVE, writing what it is sent into `dir`,
+/// until `done` is set. Returns the files written. Only associations from `peers`
+/// (the PACS) that call `ae_title` are taken.
+fn serve(
+    listener: TcpListener,
+    ae_title: String,
+    peers: Vec<IpAddr>,
+    dir: PathBuf,
+    done: Arc<AtomicBool>,
+) -> thread::JoinHandle<Vec<PathBuf>> {
+    thread::spawn(move || {
+        let mut options = ServerAssociationOptions::new()
+            .accept_called_ae_title()
+            .ae_title(ae_title)
+            .promiscuous(true);
+        for ts in TransferSyntaxRegistry.iter() {
+            if !ts.is_unsupported() {
+                options = options.with_transfer_syntax(ts.uid());
+            }
+        }
+        let mut files = Vec::new();
+        loop {
+            let stream = match listener.accept() {
+                Ok((stream, from)) if peers.contains(&from.ip()) => stream,
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    thread::sleep(POLL);
+                    continue;
+                }
+                Err(_) => break,
+            };
+            if stream.set_nonblocking(false).is_err() {
+                continue;
+            }
+            let Ok(mut scp) = options.establish(stream) else {
+                continue;
+            };
+            let contexts = scp.presentation_contexts().to_vec();
+            let mut fragments = Fragments::default();
+            let mut pending = None;
+            loop {
+                let parts = match scp.receive() {
+                    Ok(Pdu::ReleaseRQ) => {
+                        let _ = scp.send(&Pdu::ReleaseRP);
+                        break;
+                    }
+                    Ok(pdu) => match fragments.push(pdu, "the PACS") {
+                        Ok(parts) => parts,
+                        Err(_) => break,
+                    },
+                    Err(_) => break,
+                };
+                for part in parts {
+                    match part {
+                        Part::Command(command) => pending = Some(command),
+                        Part::Data(id, data) => {
+                            let Some(request) = pending.take() else {
+                                continue;
+                            };
+                            let (code, file) = store_instance(&dir, &request, id, &data, &contexts);
+                            files.extend(file);
+                            let Ok(pdu) = command_pdu(id, &store_response(&request, code)) else {
+                                continue;
+                            };
+                            let _ = scp.send(&pdu);
+                        }
+                    }
+                }
+            }
+        }
+        files
+    })
+}
+
+/// Retrieve `matches` (studies and series; others are skipped) from `remote` into
+/// `dir`, calling as `calling_ae`. With C-MOVE, `calling_ae` is also the move
+/// destination, and the instances are taken on `store_port`.
+pub fn retrieve(
+    remote: &Remote,
+    calling_ae: &str,
+    how: Retrieve,
+    store_port: u16,
+    matches: &[Match],
+    dir: &Path,
+) -> Result<Retrieved, DCMRedactErrors> {
+    let matches: Vec<&Match> = matches.iter().filter(|m| m.is_retrievable()).collect();
+    let mut retrieved = Retrieved::default();
+    if matches.is_empty() {
+        return Ok(retrieved);
+    }
+    let (model, field) = match how {
+        Retrieve::Move => (STUDY_ROOT_MOVE, C_MOVE_RQ),
+        Retrieve::Get => (STUDY_ROOT_GET, C_GET_RQ),
+    };
+    let mut options = ClientAssociationOptions::new()
+        .calling_ae_title(calling_ae)
+        .called_ae_title(remote.ae_title.as_str())
+        .with_presentation_context(model, NATIVE.to_vec());
+    if how == Retrieve::Get {
+        for class in STORAGE_CLASSES {
+            options = options.with_presentation_context(class, RECEIVED_SYNTAXES.to_vec());
+        }
+    }
+    // The storage SCP is up before the PACS is asked to connect to it
+    let server = match how {
+        Retrieve::Move => {
+            let peers: Vec<IpAddr> = (remote.host.as_str(), remote.port)
+                .to_socket_addrs()
+                .map_err(|e| {
+                    DCMRedactErrors::network(format!("Failed to resolve {}", remote.host), e)
+                })?
+                .map(|a| a.ip())
+                .collect();
+            let listener = TcpListener::bind(("0.0.0.0", store_port))
+                .and_then(|l| l.set_nonblocking(true).map(|()| l))
+                .map_err(|e| {
+                    DCMRedactErrors::network(format!("Failed to listen on port {store_port}"), e)
+                })?;
+            let done = Arc::new(AtomicBool::new(false));
+            let handle = serve(
+                listener,
+                calling_ae.to_string(),
+                peers,
+                dir.to_path_buf(),
+                done.clone(),
+            );
+            Some((done, handle))
+        }
+        Retrieve::Get => None,
+    };
+    let run = || -> Result<(), DCMRedactErrors> {
+        let mut scu = options
+            .establish((remote.host.as_str(), remote.port))
+            .map_err(|e| {
+                DCMRedactErrors::network(format!("Failed to associate with {remote}"), e)
+            })?;
+        let failed =
+            |why: String| DCMRedactErrors::network(format!("Retrieval from {remote} failed"), why);
+        if !accepted(scu.presentation_contexts(), 1) {
+            let _ = scu.abort();
+            return Err(failed(format!("it does not take {} requests", how.label())));
+        }
+        let contexts = scu.presentation_contexts().to_vec();
+        let destination = (how == Retrieve::Move).then_some(calling_ae);
+        let peer = remote.to_string();
+        for (i, m) in matches.iter().enumerate() {
+            let message_id = (i % usize::from(u16::MAX)) as u16 + 1;
+            let pdu = request_pdu(
+                1,
+                &contexts,
+                &request(field, model, message_id, destination),
+                &m.identifier(),
+            )?;
+            scu.send(&pdu).map_err(|e| failed(e.to_string()))?;
+            let mut fragments = Fragments::default();
+            let mut pending = None;
+            'responses: loop {
+                let pdu = scu.receive().map_err(|e| failed(e.to_string()))?;
+                for part in fragments.push(pdu, &peer)? {
+                    match part {
+                        // C-GET sub-operations come in on this association
+                        Part::Command(command)
+                            if number(&command, tags::COMMAND_FIELD) == Some(C_STORE_RQ) =>
+                        {
+                            pending = Some(command);
+                        }
+                        Part::Command(command) => {
+                            let (code, _) = status(&command);
+                            if is_pending(code) {
+                                continue;
+                            }
+                            retrieved.failed += usize::from(
+                                number(&command, tags::NUMBER_OF_FAILED_SUBOPERATIONS).unwrap_or(0),
+                            );
+                            if !is_done(code) {
+                                let _ = scu.abort();
+                                return Err(refused(
+                                    format!("Retrieval from {remote} failed"),
+                                    &command,
+                                ));
+                            }
+                            break 'responses;
+                        }
+                        Part::Data(id, data) => {
+                            // Data after a final response is its failed instance list
+                            let Some(request) = pending.take() else {
+                                continue;
+                            };
+                            let (code, file) = store_instance(dir, &request, id, &data, &contexts);
+                            retrieved.files.extend(file);
+                            scu.send(&command_pdu(id, &store_response(&request, code))?)
+                                .map_err(|e| failed(e.to_string()))?;
+                        }
+                    }
+                }
+            }
+        }
+        let _ = scu.release();
+        Ok(())
+    };
+    let result = run();
+    if let Some((done, handle)) = server {
+        done.store(true, Ordering::Relaxed);
+        retrieved.files.extend(handle.join().unwrap_or_default());
+    }
+    result.map(|()| retrieved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn study() -> Match {
+        Match {
+            level: QueryLevel::Study,
+            patient_id: "P1".to_string(),
+            patient_name: "DOE^JANE ".to_string(),
+            study_uid: "1.2.3".to_string(),
+            study_date: "20240102".to_string(),
+            modality: "CT".to_string(),
+            related: "12".to_string(),
+            ..Match::default()
+        }
+    }
+
+    #[test]
+    fn retrievals_print_and_parse_the_same() {
+        for retrieve in Retrieve::ALL {
+            assert_eq!(retrieve.to_string().parse::<Retrieve>(), Ok(retrieve));
+        }
+        assert_eq!("C-GET".parse::<Retrieve>(), Ok(Retrieve::Get));
+        assert!("store".parse::<Retrieve>().is_err());
+    }
+
+    #[test]
+    fn pending_warning_and_failure_statuses() {
+        assert!(is_pending(0xFF00) && is_pending(0xFF01));
+        assert!(!is_pending(0x0000));
+        assert!(is_done(0x0000) && is_done(0xB000));
+        assert!(!is_done(0xA700) && !is_done(0xC000) && !is_done(0xFE00));
+    }
+
+    #[test]
+    fn studies_and_series_are_retrievable_and_patients_list_their_studies() {
+        assert!(study().is_retrievable());
+        let series = Match {
+            level: QueryLevel::Series,
+            ..study()
+        };
+        assert!(!series.is_retrievable());
+        let patient = Match {
+            level: QueryLevel::Patient,
+            ..study()
+        };
+        assert!(!patient.is_retrievable());
+        assert_eq!(
+            patient.children(),
+            Some(Query {
+                level: QueryLevel::Study,
+                patient_id: "P1".to_string(),
+                ..Query::default()
+            })
+        );
+        assert_eq!(study().children().unwrap().study_uid, "1.2.3");
+        assert_eq!(series.children(), None);
+    }
+
+    #[test]
+    fn columns_follow_the_level() {
+        assert_eq!(
+            study().columns(),
+            ["20240102", "P1", "DOE^JANE", "", "CT", "", "12 instances"]
+        );
+        let patient = Match {
+            level: QueryLevel::Patient,
+            related: String::new(),
+            ..study()
+        };
+        assert_eq!(patient.columns(), ["P1", "DOE^JANE", "", ""]);
+    }
+
+    #[test]
+    fn patient_names_match_as_a_prefix_unless_wildcarded() {
+        let name = |name: &str| {
+            let query = Query {
+                patient_name: name.to_string(),
+                ..Query::default()
+            };
+            text(&query.identifier(), tags::PATIENT_NAME)
+        };
+        assert_eq!(name("DOE"), "DOE*");
+        assert_eq!(name("D?E^*"), "D?E^*");
+        assert_eq!(name(" "), "");
+    }
+}
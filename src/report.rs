@@ -0,0 +1,135 @@
+//! Dry-run reports: what saving a file would change, worked out without writing
+//! anything. Which attributes would be removed, replaced or added, and which regions
+//! would be filled.
+
+use crate::audit::{AppliedRegion, TagChange, tag_changes};
+use crate::shape::{Region, Shape};
+use dicom::object::InMemDicomObject;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagAction {
+    Removed,
+    Replaced,
+    Added,
+}
+
+impl TagAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            TagAction::Removed => "remove",
+            TagAction::Replaced => "replace",
+            TagAction::Added => "add",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedChange {
+    pub action: TagAction,
+    #[serde(flatten)]
+    pub change: TagChange,
+}
+
+/// What saving `input` to `output` would do.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunReport {
+    pub input: Option<PathBuf>,
+    pub output: PathBuf,
+    pub regions: Vec<AppliedRegion>,
+    pub tags: Vec<PlannedChange>,
+}
+
+impl DryRunReport {
+    /// The report for writing `planned` (the output header, `None` for non-DICOM)
+    /// from `source`, filling `regions`.
+    pub fn new(
+        input: Option<&Path>,
+        output: &Path,
+        regions: Vec<AppliedRegion>,
+        source: Option<&InMemDicomObject>,
+        planned: Option<&InMemDicomObject>,
+    ) -> Self {
+        let tags = match (source, planned) {
+            (Some(source), Some(planned)) => tag_changes(source, planned)
+                .into_iter()
+                .map(|change| PlannedChange {
+                    action: match (&change.before, &change.after) {
+                        (Some(_), None) => TagAction::Removed,
+                        (None, Some(_)) => TagAction::Added,
+                        _ => TagAction::Replaced,
+                    },
+                    change,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Self {
+            input: input.map(Path::to_path_buf),
+            output: output.to_path_buf(),
+            regions,
+            tags,
+        }
+    }
+
+    /// Number of attributes with the given action.
+    pub fn count(&self, action: TagAction) -> usize {
+        self.tags.iter().filter(|t| t.action == action).count()
+    }
+}
+
+/// `rect 0,0 to 512,64 on every frame` style description of a region. Frames are
+/// numbered from 1.
+pub fn describe_region(region: &Region) -> String {
+    let shape = match &region.shape {
+        Shape::Rect(r) => format!("rect {},{} to {},{}", r.x0, r.y0, r.x1, r.y1),
+        Shape::Ellipse(r) => format!("ellipse in {},{} to {},{}", r.x0, r.y0, r.x1, r.y1),
+        Shape::Polygon(points) => format!("polygon of {} points", points.len()),
+    };
+    match (region.frame, region.last_frame) {
+        (Some(i), None) => format!("{shape} on frame {}", i + 1),
+        (Some(i), Some(last)) => format!("{shape} on frames {}-{}", i + 1, last + 1),
+        (None, _) => format!("{shape} on every frame"),
+    }
+}
+
+impl fmt::Display for DryRunReport {
+    /// The plain text report printed by `--dry-run`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.input {
+            Some(input) => writeln!(f, "{} -> {}", input.display(), self.output.display())?,
+            None => writeln!(f, "-> {}", self.output.display())?,
+        }
+        if self.regions.is_empty() {
+            writeln!(f, "  no regions filled")?;
+        }
+        for applied in &self.regions {
+            writeln!(
+                f,
+                "  fill    {} ({})",
+                describe_region(&applied.region),
+                applied.fill
+            )?;
+        }
+        for planned in &self.tags {
+            let change = &planned.change;
+            write!(
+                f,
+                "  {:<7} {} {}",
+                planned.action.label(),
+                change.tag,
+                change.name
+            )?;
+            match (&change.before, &change.after) {
+                (Some(before), Some(after)) => writeln!(f, ": \"{before}\" -> \"{after}\"")?,
+                (Some(before), None) => writeln!(f, ": \"{before}\"")?,
+                (None, Some(after)) => writeln!(f, ": \"{after}\"")?,
+                (None, None) => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
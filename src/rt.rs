@@ -0,0 +1,108 @@
+//! Radiotherapy objects: RT Structure Sets, Plans, Doses and Treatment Records.
+//!
+//! They carry no burned-in text to redact (an RT Dose grid is numbers, not a picture),
+//! so they are opened as documents and only their metadata is de-identified, pixel
+//! data included as it is. The Basic Profile table covers their dates, descriptions,
+//! reviewers and UIDs; references to the planning images are remapped along with the
+//! images themselves, so a whole RT study stays linked.
+//!
+//! What the table keeps are the names the plan depends on: ROI names ("PTV", "Lt
+//! Parotid"), beam, block and setup names. Planners sometimes put the patient's name
+//! or ID in those, so [`scrub_labels`] replaces any of the patient's identifiers found
+//! in them ([`replace_identifiers`]) and leaves the rest of the name alone.
+
+use dicom::core::header::Header;
+use dicom::core::{DataElement, PrimitiveValue, Tag};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+use crate::anonymize::{patient_identifiers, replace_identifiers};
+
+/// Modalities opened as RT documents. RT Images are pictures and stay images.
+const RT_MODALITIES: [&str; 4] = ["RTSTRUCT", "RTPLAN", "RTDOSE", "RTRECORD"];
+
+/// Names kept by the Basic Profile that identifiers are scrubbed from.
+const LABELS: [Tag; 6] = [
+    tags::ROI_NAME,
+    tags::BEAM_NAME,
+    tags::TOLERANCE_TABLE_LABEL,
+    tags::BLOCK_NAME,
+    tags::APPLICATION_SETUP_NAME,
+    tags::DOSE_COMMENT,
+];
+
+fn text(obj: &InMemDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+        .unwrap_or_default()
+}
+
+fn items(obj: &InMemDicomObject, tag: Tag) -> &[InMemDicomObject] {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.items())
+        .unwrap_or_default()
+}
+
+/// Whether `obj` is an RT Structure Set, Plan, Dose or Treatment Record.
+pub fn is_rt_object(obj: &InMemDicomObject) -> bool {
+    let modality = text(obj, tags::MODALITY).trim().to_uppercase();
+    RT_MODALITIES.contains(&modality.as_str())
+}
+
+fn scrub_level(obj: &mut InMemDicomObject, ids: &[String]) {
+    let tags: Vec<Tag> = obj.iter().map(|e| e.tag()).collect();
+    for tag in tags {
+        if LABELS.contains(&tag) {
+            let Ok(elem) = obj.element(tag) else {
+                continue;
+            };
+            let vr = elem.vr();
+            if let Some(new) = elem
+                .to_str()
+                .ok()
+                .and_then(|v| replace_identifiers(&v, ids))
+            {
+                obj.put(DataElement::new(tag, vr, PrimitiveValue::from(new)));
+            }
+            continue;
+        }
+        obj.update_value(tag, |value| {
+            if let Some(items) = value.items_mut() {
+                for item in items.iter_mut() {
+                    scrub_level(item, ids);
+                }
+            }
+        });
+    }
+}
+
+/// Replace the patient's names and IDs, as found in `source`, wherever they appear in
+/// the ROI, beam, block, setup and tolerance table names of `out` (and in dose
+/// comments), at any depth.
+pub fn scrub_labels(source: &InMemDicomObject, out: &mut InMemDicomObject) {
+    let ids = patient_identifiers(source);
+    if !ids.is_empty() {
+        scrub_level(out, &ids);
+    }
+}
+
+/// What an RT object holds, for display: its ROIs or beams by number and name.
+pub fn summary_lines(obj: &InMemDicomObject) -> Vec<String> {
+    let mut lines = Vec::new();
+    for roi in items(obj, tags::STRUCTURE_SET_ROI_SEQUENCE) {
+        lines.push(format!(
+            "ROI {}: {}",
+            text(roi, tags::ROI_NUMBER).trim(),
+            text(roi, tags::ROI_NAME)
+        ));
+    }
+    for tag in [tags::BEAM_SEQUENCE, tags::ION_BEAM_SEQUENCE] {
+        for beam in items(obj, tag) {
+            lines.push(format!("Beam: {}", text(beam, tags::BEAM_NAME)));
+        }
+    }
+    lines
+}
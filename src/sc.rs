@@ -0,0 +1,328 @@
+//! Secondary Capture output, for sites that don't allow editing primary acquisitions:
+//! the redacted image is saved as a new Secondary Capture instance in a series of its
+//! own, referencing the image it was made from, instead of as a modified copy of the
+//! original IOD.
+//!
+//! Only the attributes of the Patient, Study, Series, Equipment, Image, Image Pixel
+//! and LUT modules are kept; modality-specific modules (including the functional
+//! groups of enhanced objects) are dropped.
+
+use crate::uids::hashed_uid;
+use crate::voi;
+use dicom::core::header::Header;
+use dicom::core::smallvec::SmallVec;
+use dicom::core::value::DataSetSequence;
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::mem::InMemElement;
+use dicom::object::{FileDicomObject, InMemDicomObject};
+
+/// Secondary Capture Image Storage.
+pub const SECONDARY_CAPTURE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.7";
+/// Multi-frame Grayscale Byte Secondary Capture Image Storage.
+pub const MULTIFRAME_GRAYSCALE_BYTE_SC_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.7.2";
+/// Multi-frame Grayscale Word Secondary Capture Image Storage.
+pub const MULTIFRAME_GRAYSCALE_WORD_SC_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.7.3";
+/// Multi-frame True Color Secondary Capture Image Storage.
+pub const MULTIFRAME_TRUE_COLOR_SC_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.7.4";
+
+/// Salt of the hashes the Secondary Capture UIDs are derived with.
+const UID_SALT: &str = "dcm-redact secondary capture";
+
+/// Attributes kept from the redacted header.
+const KEPT: &[Tag] = &[
+    tags::SPECIFIC_CHARACTER_SET,
+    // Patient
+    tags::PATIENT_NAME,
+    tags::PATIENT_ID,
+    tags::PATIENT_BIRTH_DATE,
+    tags::PATIENT_SEX,
+    tags::PATIENT_AGE,
+    tags::PATIENT_IDENTITY_REMOVED,
+    tags::DEIDENTIFICATION_METHOD,
+    tags::DEIDENTIFICATION_METHOD_CODE_SEQUENCE,
+    // General Study
+    tags::STUDY_INSTANCE_UID,
+    tags::STUDY_DATE,
+    tags::STUDY_TIME,
+    tags::STUDY_ID,
+    tags::STUDY_DESCRIPTION,
+    tags::ACCESSION_NUMBER,
+    tags::REFERRING_PHYSICIAN_NAME,
+    // General Series
+    tags::MODALITY,
+    tags::SERIES_DESCRIPTION,
+    tags::LATERALITY,
+    tags::BODY_PART_EXAMINED,
+    // General Equipment
+    tags::MANUFACTURER,
+    tags::INSTITUTION_NAME,
+    // General Image
+    tags::INSTANCE_NUMBER,
+    tags::PATIENT_ORIENTATION,
+    tags::CONTENT_DATE,
+    tags::CONTENT_TIME,
+    tags::ACQUISITION_DATE,
+    tags::ACQUISITION_TIME,
+    tags::DERIVATION_DESCRIPTION,
+    tags::DERIVATION_CODE_SEQUENCE,
+    tags::BURNED_IN_ANNOTATION,
+    tags::LOSSY_IMAGE_COMPRESSION,
+    tags::LOSSY_IMAGE_COMPRESSION_RATIO,
+    tags::LOSSY_IMAGE_COMPRESSION_METHOD,
+    // Image Pixel and Multi-frame
+    tags::SAMPLES_PER_PIXEL,
+    tags::PHOTOMETRIC_INTERPRETATION,
+    tags::ROWS,
+    tags::COLUMNS,
+    tags::BITS_ALLOCATED,
+    tags::BITS_STORED,
+    tags::HIGH_BIT,
+    tags::PIXEL_REPRESENTATION,
+    tags::PLANAR_CONFIGURATION,
+    tags::PIXEL_ASPECT_RATIO,
+    tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+    tags::GREEN_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+    tags::BLUE_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+    tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+    tags::GREEN_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+    tags::BLUE_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+    tags::NUMBER_OF_FRAMES,
+    tags::FRAME_TIME,
+    // Modality and VOI LUT
+    tags::RESCALE_INTERCEPT,
+    tags::RESCALE_SLOPE,
+    tags::RESCALE_TYPE,
+    tags::WINDOW_CENTER,
+    tags::WINDOW_WIDTH,
+    tags::WINDOW_CENTER_WIDTH_EXPLANATION,
+    tags::VOILUT_SEQUENCE,
+    tags::PIXEL_DATA,
+];
+
+fn text(tag: Tag, vr: VR, value: &str) -> InMemElement {
+    DataElement::new(tag, vr, PrimitiveValue::from(value))
+}
+
+fn uid_of(obj: &InMemDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+        .unwrap_or_default()
+}
+
+/// The Secondary Capture SOP Class for `frames` frames of the pixel data `obj`
+/// describes (color is written as RGB).
+pub fn sop_class(obj: &InMemDicomObject, frames: usize, color: bool) -> &'static str {
+    let bits_allocated = obj
+        .element(tags::BITS_ALLOCATED)
+        .ok()
+        .and_then(|e| e.to_int::<u16>().ok())
+        .unwrap_or(16);
+    match (frames, color) {
+        (0 | 1, _) => SECONDARY_CAPTURE_STORAGE,
+        (_, true) => MULTIFRAME_TRUE_COLOR_SC_STORAGE,
+        _ if bits_allocated == 8 => MULTIFRAME_GRAYSCALE_BYTE_SC_STORAGE,
+        _ => MULTIFRAME_GRAYSCALE_WORD_SC_STORAGE,
+    }
+}
+
+/// Turn the header about to be saved into that of a Secondary Capture of it.
+///
+/// The new SOP Instance and Series Instance UIDs are hashed from the header's own, so
+/// saving again gives the same UIDs and the captures of one series share a series.
+pub fn to_secondary_capture(
+    obj: &mut FileDicomObject<InMemDicomObject>,
+    frames: usize,
+    color: bool,
+) {
+    let source_class = uid_of(obj, tags::SOP_CLASS_UID);
+    let source_instance = uid_of(obj, tags::SOP_INSTANCE_UID);
+    let series = uid_of(obj, tags::SERIES_INSTANCE_UID);
+    // Enhanced objects keep their rescale in the shared functional groups
+    let rescale = voi::rescale(obj);
+    let sop_class = sop_class(obj, frames, color);
+
+    let dropped: Vec<Tag> = obj
+        .iter()
+        .map(|e| e.tag())
+        .filter(|tag| !KEPT.contains(tag))
+        .collect();
+    for tag in dropped {
+        obj.remove_element(tag);
+    }
+
+    if obj.element(tags::RESCALE_SLOPE).is_err() && rescale != voi::Rescale::default() {
+        obj.put(text(
+            tags::RESCALE_INTERCEPT,
+            VR::DS,
+            &rescale.intercept.to_string(),
+        ));
+        obj.put(text(
+            tags::RESCALE_SLOPE,
+            VR::DS,
+            &rescale.slope.to_string(),
+        ));
+    }
+    for element in [
+        text(tags::SOP_CLASS_UID, VR::UI, sop_class),
+        text(
+            tags::SOP_INSTANCE_UID,
+            VR::UI,
+            &hashed_uid(UID_SALT, &source_instance),
+        ),
+        text(
+            tags::SERIES_INSTANCE_UID,
+            VR::UI,
+            &hashed_uid(UID_SALT, &series),
+        ),
+        DataElement::new(
+            tags::IMAGE_TYPE,
+            VR::CS,
+            PrimitiveValue::Strs(["DERIVED".to_string(), "SECONDARY".to_string()].into()),
+        ),
+        // Workstation
+        text(tags::CONVERSION_TYPE, VR::CS, "WSD"),
+        text(
+            tags::SECONDARY_CAPTURE_DEVICE_MANUFACTURER,
+            VR::LO,
+            "dcm-redact",
+        ),
+        text(
+            tags::SECONDARY_CAPTURE_DEVICE_SOFTWARE_VERSIONS,
+            VR::LO,
+            env!("CARGO_PKG_VERSION"),
+        ),
+    ] {
+        obj.put(element);
+    }
+    if obj.element(tags::MODALITY).is_err() {
+        obj.put(text(tags::MODALITY, VR::CS, "OT"));
+    }
+    if frames > 1 {
+        // Multi-frame SC needs something to step through; cine keeps its frame time
+        let pointer = if obj.element(tags::FRAME_TIME).is_ok() {
+            tags::FRAME_TIME
+        } else {
+            let pages: Vec<String> = (1..=frames).map(|i| i.to_string()).collect();
+            obj.put(DataElement::new(
+                tags::PAGE_NUMBER_VECTOR,
+                VR::IS,
+                PrimitiveValue::Strs(pages.into()),
+            ));
+            tags::PAGE_NUMBER_VECTOR
+        };
+        obj.put(DataElement::new(
+            tags::FRAME_INCREMENT_POINTER,
+            VR::AT,
+            PrimitiveValue::Tags(SmallVec::from_slice(&[pointer])),
+        ));
+    }
+
+    obj.put(DataElement::new(
+        tags::SOURCE_IMAGE_SEQUENCE,
+        VR::SQ,
+        DataSetSequence::from(vec![InMemDicomObject::from_element_iter([
+            text(tags::REFERENCED_SOP_CLASS_UID, VR::UI, &source_class),
+            text(tags::REFERENCED_SOP_INSTANCE_UID, VR::UI, &source_instance),
+            DataElement::new(
+                tags::PURPOSE_OF_REFERENCE_CODE_SEQUENCE,
+                VR::SQ,
+                DataSetSequence::from(vec![InMemDicomObject::from_element_iter([
+                    text(tags::CODE_VALUE, VR::SH, "121322"),
+                    text(tags::CODING_SCHEME_DESIGNATOR, VR::SH, "DCM"),
+                    text(
+                        tags::CODE_MEANING,
+                        VR::LO,
+                        "Source image for image processing operation",
+                    ),
+                ])]),
+            ),
+        ])]),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::object::FileMetaTableBuilder;
+
+    fn header(bits_allocated: u16) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            text(tags::SOP_CLASS_UID, VR::UI, "1.2.840.10008.5.1.4.1.1.2"),
+            text(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+            text(tags::SERIES_INSTANCE_UID, VR::UI, "1.2.3"),
+            text(tags::PATIENT_ID, VR::LO, "ANON"),
+            text(tags::STATION_NAME, VR::SH, "CT01"),
+            DataElement::new(
+                tags::BITS_ALLOCATED,
+                VR::US,
+                PrimitiveValue::from(bits_allocated),
+            ),
+        ])
+    }
+
+    fn file() -> FileDicomObject<InMemDicomObject> {
+        header(16)
+            .with_meta(
+                FileMetaTableBuilder::new()
+                    .transfer_syntax("1.2.840.10008.1.2.1")
+                    .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+                    .media_storage_sop_instance_uid("1.2.3.4"),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn the_sop_class_follows_frames_color_and_depth() {
+        assert_eq!(sop_class(&header(16), 1, true), SECONDARY_CAPTURE_STORAGE);
+        assert_eq!(
+            sop_class(&header(8), 4, true),
+            MULTIFRAME_TRUE_COLOR_SC_STORAGE
+        );
+        assert_eq!(
+            sop_class(&header(8), 4, false),
+            MULTIFRAME_GRAYSCALE_BYTE_SC_STORAGE
+        );
+        assert_eq!(
+            sop_class(&header(16), 4, false),
+            MULTIFRAME_GRAYSCALE_WORD_SC_STORAGE
+        );
+    }
+
+    #[test]
+    fn captures_drop_other_modules_and_reference_the_source() {
+        let mut obj = file();
+        to_secondary_capture(&mut obj, 1, false);
+        assert!(obj.element(tags::STATION_NAME).is_err());
+        assert_eq!(uid_of(&obj, tags::PATIENT_ID), "ANON");
+        assert_eq!(uid_of(&obj, tags::SOP_CLASS_UID), SECONDARY_CAPTURE_STORAGE);
+        assert_eq!(uid_of(&obj, tags::MODALITY), "OT");
+
+        let instance = uid_of(&obj, tags::SOP_INSTANCE_UID);
+        assert_eq!(instance, hashed_uid(UID_SALT, "1.2.3.4"));
+        let source = obj.element(tags::SOURCE_IMAGE_SEQUENCE).unwrap();
+        let item = &source.items().unwrap()[0];
+        assert_eq!(uid_of(item, tags::REFERENCED_SOP_INSTANCE_UID), "1.2.3.4");
+
+        // Saving again gives the same instance
+        let mut again = file();
+        to_secondary_capture(&mut again, 1, false);
+        assert_eq!(uid_of(&again, tags::SOP_INSTANCE_UID), instance);
+    }
+
+    #[test]
+    fn multiframe_captures_step_through_pages() {
+        let mut obj = file();
+        to_secondary_capture(&mut obj, 3, false);
+        assert!(obj.element(tags::FRAME_INCREMENT_POINTER).is_ok());
+        assert_eq!(
+            obj.element(tags::PAGE_NUMBER_VECTOR)
+                .unwrap()
+                .to_multi_int::<u32>()
+                .unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+}
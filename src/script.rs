@@ -0,0 +1,146 @@
+//! Per-file [Rhai](https://rhai.rs) scripts for batch runs, behind the `scripting`
+//! feature. A script runs once for every file before it is redacted; it can read the
+//! header, add regions and leave the file out:
+//!
+//! ```rhai
+//! if tag("Modality") != "US" {
+//!     skip("not ultrasound");
+//! }
+//! if tag("StationAETitle") == "US_ROOM_3" {
+//!     redact(0, height - 40, width, height);   // this room burns in a footer too
+//! }
+//! ```
+//!
+//! Besides Rhai's own functions, scripts get:
+//! - `path`, `width`, `height` and `frames`: the file and its image size;
+//! - `tag(name)`: an attribute's value as text, `""` if it is missing; `name` is a
+//!   keyword (`PatientName`) or a tag (`0010,0010`);
+//! - `redact(x0, y0, x1, y1)` and `redact_frame(frame, x0, y0, x1, y1)`: a rectangle
+//!   to redact on every frame, or on one;
+//! - `skip(reason)`: leave the file out of the output.
+
+use crate::{DCMRedactErrors, RedactRect, Redactor, Region, Shape};
+use dicom::core::dictionary::DataDictionary;
+use dicom::core::header::Header;
+use dicom::dictionary_std::{StandardDataDictionary, tags};
+use dicom::object::InMemDicomObject;
+use rhai::{AST, Engine, EvalAltResult, Scope};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A runaway script fails its file instead of hanging the run.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// A compiled script, shared by every file of a run.
+#[derive(Debug)]
+pub struct FileScript {
+    path: PathBuf,
+    ast: AST,
+}
+
+/// What a script decided for one file.
+#[derive(Debug, Default)]
+pub struct ScriptOutcome {
+    /// Why the file is left out, if it is.
+    pub skip: Option<String>,
+    /// Regions to redact on top of the job's.
+    pub regions: Vec<Region>,
+}
+
+fn rect(x0: i64, y0: i64, x1: i64, y1: i64) -> Shape {
+    let edge = |v: i64| v.clamp(0, u32::MAX as i64) as u32;
+    Shape::Rect(RedactRect {
+        x0: edge(x0),
+        y0: edge(y0),
+        x1: edge(x1),
+        y1: edge(y1),
+    })
+}
+
+impl FileScript {
+    pub fn load(path: &Path) -> Result<Self, DCMRedactErrors> {
+        let ast = Engine::new()
+            .compile_file(path.to_path_buf())
+            .map_err(|e| {
+                DCMRedactErrors::decode(format!("Invalid script {}", path.display()), e.to_string())
+            })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            ast,
+        })
+    }
+
+    /// Run the script for `redactor`.
+    pub fn run(&self, redactor: &Redactor) -> Result<ScriptOutcome, DCMRedactErrors> {
+        // The script only sees the header
+        let header = Arc::new(
+            redactor
+                .dicom()
+                .map_or_else(InMemDicomObject::new_empty, |d| {
+                    InMemDicomObject::from_element_iter(
+                        d.iter().filter(|e| e.tag() != tags::PIXEL_DATA).cloned(),
+                    )
+                }),
+        );
+        let outcome = Arc::new(Mutex::new(ScriptOutcome::default()));
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.register_fn(
+            "tag",
+            move |name: &str| -> Result<String, Box<EvalAltResult>> {
+                let tag = StandardDataDictionary
+                    .parse_tag(name.trim())
+                    .ok_or_else(|| format!("unknown attribute '{name}'"))?;
+                Ok(header
+                    .element(tag)
+                    .ok()
+                    .and_then(|e| e.to_str().ok())
+                    .map(|v| v.trim().to_string())
+                    .unwrap_or_default())
+            },
+        );
+        let o = outcome.clone();
+        engine.register_fn("skip", move |reason: &str| {
+            o.lock().unwrap_or_else(|e| e.into_inner()).skip = Some(reason.to_string());
+        });
+        let o = outcome.clone();
+        engine.register_fn("redact", move |x0: i64, y0: i64, x1: i64, y1: i64| {
+            let region = Region::all_frames(rect(x0, y0, x1, y1));
+            o.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .regions
+                .push(region);
+        });
+        let o = outcome.clone();
+        engine.register_fn(
+            "redact_frame",
+            move |frame: i64, x0: i64, y0: i64, x1: i64, y1: i64| {
+                let region = Region {
+                    shape: rect(x0, y0, x1, y1),
+                    frame: Some(frame.max(0) as usize),
+                    last_frame: None,
+                };
+                o.lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .regions
+                    .push(region);
+            },
+        );
+
+        let (width, height) = redactor.dimensions();
+        let mut scope = Scope::new();
+        let source = redactor.source().map(|p| p.display().to_string());
+        scope.push_constant("path", source.unwrap_or_default());
+        scope.push_constant("width", width as i64);
+        scope.push_constant("height", height as i64);
+        scope.push_constant("frames", redactor.frame_count() as i64);
+        engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| {
+                DCMRedactErrors::ValueError(format!("Script {}: {e}", self.path.display()))
+            })?;
+        let mut outcome = outcome.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(std::mem::take(&mut *outcome))
+    }
+}
@@ -0,0 +1,384 @@
+//! The redaction mask as a binary DICOM Segmentation (SEG) object referencing the
+//! saved instance, for provenance: which pixels of that instance were redacted.
+//!
+//! The SEG has one segment ("Redacted") and one frame per frame of the instance, in
+//! the same order. Patient and study attributes are copied from the saved (so already
+//! de-identified) header.
+
+use crate::DCMRedactErrors;
+use crate::audit::{AppliedRegion, utc_timestamp};
+use crate::uids::{self, new_uid};
+use crate::{charset, gz};
+use dicom::core::smallvec::SmallVec;
+use dicom::core::value::DataSetSequence;
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::mem::InMemElement;
+use dicom::object::{FileDicomObject, FileMetaTableBuilder, InMemDicomObject};
+use dicom::transfer_syntax::entries;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Segmentation Storage.
+pub const SEGMENTATION_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.66.4";
+
+/// Attributes copied from the segmented instance: Patient, General Study and Frame of
+/// Reference modules, and the de-identification flags.
+const COPIED: &[Tag] = &[
+    tags::SPECIFIC_CHARACTER_SET,
+    tags::PATIENT_NAME,
+    tags::PATIENT_ID,
+    tags::PATIENT_BIRTH_DATE,
+    tags::PATIENT_SEX,
+    tags::PATIENT_IDENTITY_REMOVED,
+    tags::DEIDENTIFICATION_METHOD,
+    tags::DEIDENTIFICATION_METHOD_CODE_SEQUENCE,
+    tags::STUDY_INSTANCE_UID,
+    tags::STUDY_DATE,
+    tags::STUDY_TIME,
+    tags::STUDY_ID,
+    tags::ACCESSION_NUMBER,
+    tags::REFERRING_PHYSICIAN_NAME,
+    tags::FRAME_OF_REFERENCE_UID,
+    tags::POSITION_REFERENCE_INDICATOR,
+];
+
+/// `scan.dcm` → `scan.seg.dcm` (`scan.dcm.gz` → `scan.seg.dcm.gz`), in the same
+/// directory.
+pub fn seg_path(output: &Path) -> PathBuf {
+    let inner = gz::inner(output);
+    let stem = inner.file_stem().unwrap_or_default().to_string_lossy();
+    let gz = if gz::is_gz(output) { ".gz" } else { "" };
+    output.with_file_name(format!("{stem}.seg.dcm{gz}"))
+}
+
+fn text(tag: Tag, vr: VR, value: &str) -> InMemElement {
+    DataElement::new(tag, vr, PrimitiveValue::from(value))
+}
+
+fn sequence(tag: Tag, items: Vec<InMemDicomObject>) -> InMemElement {
+    DataElement::new(tag, VR::SQ, DataSetSequence::from(items))
+}
+
+fn code(tag: Tag, value: &str, scheme: &str, meaning: &str) -> InMemElement {
+    sequence(
+        tag,
+        vec![InMemDicomObject::from_element_iter([
+            text(tags::CODE_VALUE, VR::SH, value),
+            text(tags::CODING_SCHEME_DESIGNATOR, VR::SH, scheme),
+            text(tags::CODE_MEANING, VR::LO, meaning),
+        ])],
+    )
+}
+
+fn uid_of(obj: &InMemDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+        .unwrap_or_default()
+}
+
+/// Bit-packed binary frames (first pixel in the lowest bit), frames back to back.
+fn pack_masks(width: u32, height: u32, frames: usize, regions: &[AppliedRegion]) -> Vec<u8> {
+    let frame_len = width as usize * height as usize;
+    let mut bits = vec![0u8; (frame_len * frames).div_ceil(8)];
+    for applied in regions {
+        for frame in applied.region.target_frames(frames) {
+            let offset = frame * frame_len;
+            applied
+                .region
+                .shape
+                .for_each_span(width, height, |y, x0, x1| {
+                    let row = offset + y as usize * width as usize;
+                    for i in row + x0 as usize..row + x1 as usize {
+                        bits[i / 8] |= 1 << (i % 8);
+                    }
+                });
+        }
+    }
+    // Pixel Data has an even length
+    if bits.len() % 2 == 1 {
+        bits.push(0);
+    }
+    bits
+}
+
+/// Build a SEG of `regions` on the `frames` frames of `width` x `height` of the
+/// instance described by `written`.
+pub fn build_segmentation(
+    written: &InMemDicomObject,
+    width: u32,
+    height: u32,
+    frames: usize,
+    regions: &[AppliedRegion],
+) -> Result<FileDicomObject<InMemDicomObject>, DCMRedactErrors> {
+    let mut seg = InMemDicomObject::new_empty();
+    for tag in COPIED {
+        if let Ok(e) = written.element(*tag) {
+            seg.put(e.clone());
+        }
+    }
+    if seg.element(tags::FRAME_OF_REFERENCE_UID).is_err() {
+        seg.put(text(tags::FRAME_OF_REFERENCE_UID, VR::UI, &new_uid()));
+    }
+
+    let source_class = uid_of(written, tags::SOP_CLASS_UID);
+    let source_instance = uid_of(written, tags::SOP_INSTANCE_UID);
+    let sop_instance = new_uid();
+    // YYYY-MM-DDTHH:MM:SSZ
+    let now = utc_timestamp(SystemTime::now());
+    let (date, time) = (now[..10].replace('-', ""), now[11..19].replace(':', ""));
+    for element in [
+        text(tags::SOP_CLASS_UID, VR::UI, SEGMENTATION_STORAGE),
+        text(tags::SOP_INSTANCE_UID, VR::UI, &sop_instance),
+        text(tags::MODALITY, VR::CS, "SEG"),
+        text(tags::SERIES_INSTANCE_UID, VR::UI, &new_uid()),
+        text(tags::SERIES_NUMBER, VR::IS, "999"),
+        text(tags::SERIES_DESCRIPTION, VR::LO, "Redaction mask"),
+        text(tags::INSTANCE_NUMBER, VR::IS, "1"),
+        text(tags::CONTENT_DATE, VR::DA, &date),
+        text(tags::CONTENT_TIME, VR::TM, &time),
+        text(tags::CONTENT_LABEL, VR::CS, "REDACTION"),
+        text(
+            tags::CONTENT_DESCRIPTION,
+            VR::LO,
+            "Pixels redacted by dcm-redact",
+        ),
+        text(tags::CONTENT_CREATOR_NAME, VR::PN, ""),
+        text(tags::MANUFACTURER, VR::LO, "dcm-redact"),
+        text(tags::MANUFACTURER_MODEL_NAME, VR::LO, "dcm-redact"),
+        text(tags::DEVICE_SERIAL_NUMBER, VR::LO, "1"),
+        text(tags::SOFTWARE_VERSIONS, VR::LO, env!("CARGO_PKG_VERSION")),
+        DataElement::new(
+            tags::IMAGE_TYPE,
+            VR::CS,
+            PrimitiveValue::Strs(["DERIVED".to_string(), "PRIMARY".to_string()].into()),
+        ),
+        text(tags::SEGMENTATION_TYPE, VR::CS, "BINARY"),
+        text(tags::LOSSY_IMAGE_COMPRESSION, VR::CS, "00"),
+        text(tags::PHOTOMETRIC_INTERPRETATION, VR::CS, "MONOCHROME2"),
+        text(tags::NUMBER_OF_FRAMES, VR::IS, &frames.to_string()),
+    ] {
+        seg.put(element);
+    }
+    for (tag, value) in [
+        (tags::SAMPLES_PER_PIXEL, 1u16),
+        (tags::ROWS, height as u16),
+        (tags::COLUMNS, width as u16),
+        (tags::BITS_ALLOCATED, 1),
+        (tags::BITS_STORED, 1),
+        (tags::HIGH_BIT, 0),
+        (tags::PIXEL_REPRESENTATION, 0),
+    ] {
+        seg.put(DataElement::new(tag, VR::US, PrimitiveValue::from(value)));
+    }
+
+    seg.put(sequence(
+        tags::SEGMENT_SEQUENCE,
+        vec![InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SEGMENT_NUMBER, VR::US, PrimitiveValue::from(1u16)),
+            text(tags::SEGMENT_LABEL, VR::LO, "Redacted"),
+            text(tags::SEGMENT_ALGORITHM_TYPE, VR::CS, "MANUAL"),
+            // No standard concept for "redacted pixels"; local codes use a 99 scheme
+            code(
+                tags::SEGMENTED_PROPERTY_CATEGORY_CODE_SEQUENCE,
+                "260787004",
+                "SCT",
+                "Physical object",
+            ),
+            code(
+                tags::SEGMENTED_PROPERTY_TYPE_CODE_SEQUENCE,
+                "REDACTED",
+                "99DCMREDACT",
+                "Redacted region",
+            ),
+        ])],
+    ));
+
+    let dimension_uid = new_uid();
+    seg.put(sequence(
+        tags::DIMENSION_ORGANIZATION_SEQUENCE,
+        vec![InMemDicomObject::from_element_iter([text(
+            tags::DIMENSION_ORGANIZATION_UID,
+            VR::UI,
+            &dimension_uid,
+        )])],
+    ));
+    seg.put(sequence(
+        tags::DIMENSION_INDEX_SEQUENCE,
+        vec![InMemDicomObject::from_element_iter([
+            text(tags::DIMENSION_ORGANIZATION_UID, VR::UI, &dimension_uid),
+            DataElement::new(
+                tags::DIMENSION_INDEX_POINTER,
+                VR::AT,
+                PrimitiveValue::Tags(SmallVec::from_slice(&[tags::REFERENCED_SEGMENT_NUMBER])),
+            ),
+            DataElement::new(
+                tags::FUNCTIONAL_GROUP_POINTER,
+                VR::AT,
+                PrimitiveValue::Tags(SmallVec::from_slice(&[
+                    tags::SEGMENT_IDENTIFICATION_SEQUENCE,
+                ])),
+            ),
+        ])],
+    ));
+    seg.put(sequence(
+        tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE,
+        vec![InMemDicomObject::from_element_iter([sequence(
+            tags::SEGMENT_IDENTIFICATION_SEQUENCE,
+            vec![InMemDicomObject::from_element_iter([DataElement::new(
+                tags::REFERENCED_SEGMENT_NUMBER,
+                VR::US,
+                PrimitiveValue::from(1u16),
+            )])],
+        )])],
+    ));
+
+    let per_frame = (0..frames)
+        .map(|i| {
+            let mut source = InMemDicomObject::from_element_iter([
+                text(tags::REFERENCED_SOP_CLASS_UID, VR::UI, &source_class),
+                text(tags::REFERENCED_SOP_INSTANCE_UID, VR::UI, &source_instance),
+                code(
+                    tags::PURPOSE_OF_REFERENCE_CODE_SEQUENCE,
+                    "121322",
+                    "DCM",
+                    "Source image for image processing operation",
+                ),
+            ]);
+            if frames > 1 {
+                source.put(text(
+                    tags::REFERENCED_FRAME_NUMBER,
+                    VR::IS,
+                    &(i + 1).to_string(),
+                ));
+            }
+            InMemDicomObject::from_element_iter([
+                sequence(
+                    tags::DERIVATION_IMAGE_SEQUENCE,
+                    vec![InMemDicomObject::from_element_iter([
+                        code(
+                            tags::DERIVATION_CODE_SEQUENCE,
+                            "113076",
+                            "DCM",
+                            "Segmentation",
+                        ),
+                        sequence(tags::SOURCE_IMAGE_SEQUENCE, vec![source]),
+                    ])],
+                ),
+                sequence(
+                    tags::FRAME_CONTENT_SEQUENCE,
+                    vec![InMemDicomObject::from_element_iter([DataElement::new(
+                        tags::DIMENSION_INDEX_VALUES,
+                        VR::UL,
+                        PrimitiveValue::from(1u32),
+                    )])],
+                ),
+            ])
+        })
+        .collect();
+    seg.put(sequence(
+        tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE,
+        per_frame,
+    ));
+
+    seg.put(sequence(
+        tags::REFERENCED_SERIES_SEQUENCE,
+        vec![InMemDicomObject::from_element_iter([
+            text(
+                tags::SERIES_INSTANCE_UID,
+                VR::UI,
+                &uid_of(written, tags::SERIES_INSTANCE_UID),
+            ),
+            sequence(
+                tags::REFERENCED_INSTANCE_SEQUENCE,
+                vec![InMemDicomObject::from_element_iter([
+                    text(tags::REFERENCED_SOP_CLASS_UID, VR::UI, &source_class),
+                    text(tags::REFERENCED_SOP_INSTANCE_UID, VR::UI, &source_instance),
+                ])],
+            ),
+        ])],
+    ));
+
+    seg.put(DataElement::new(
+        tags::PIXEL_DATA,
+        VR::OB,
+        PrimitiveValue::U8(pack_masks(width, height, frames, regions).into()),
+    ));
+
+    let mut file = seg
+        .with_meta(
+            FileMetaTableBuilder::new()
+                .transfer_syntax(entries::EXPLICIT_VR_LITTLE_ENDIAN.uid())
+                .media_storage_sop_class_uid(SEGMENTATION_STORAGE)
+                .media_storage_sop_instance_uid(sop_instance),
+        )
+        .map_err(|e| DCMRedactErrors::encode("Failed to build the segmentation file meta", e))?;
+    uids::stamp_file_meta(&mut file);
+    Ok(file)
+}
+
+/// Build the SEG for `written` and save it to `path`.
+pub fn write_segmentation(
+    path: &Path,
+    written: &InMemDicomObject,
+    width: u32,
+    height: u32,
+    frames: usize,
+    regions: &[AppliedRegion],
+) -> Result<(), DCMRedactErrors> {
+    let seg = build_segmentation(written, width, height, frames, regions)?;
+    let seg = charset::encode_text(&seg).unwrap_or(seg);
+    gz::write_file(&seg, path).map_err(|e| {
+        DCMRedactErrors::encode(
+            format!("Failed to write segmentation {}", path.display()),
+            e,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::{Region, Shape};
+    use crate::{RedactFill, RedactRect};
+
+    fn applied(x0: u32, y0: u32, x1: u32, y1: u32, frame: Option<usize>) -> AppliedRegion {
+        AppliedRegion {
+            region: Region {
+                shape: Shape::Rect(RedactRect { x0, y0, x1, y1 }),
+                frame,
+                last_frame: None,
+            },
+            fill: RedactFill::Black,
+        }
+    }
+
+    #[test]
+    fn seg_files_sit_next_to_the_output() {
+        assert_eq!(
+            seg_path(Path::new("out/scan.dcm")),
+            PathBuf::from("out/scan.seg.dcm")
+        );
+        assert_eq!(
+            seg_path(Path::new("out/scan.dcm.gz")),
+            PathBuf::from("out/scan.seg.dcm.gz")
+        );
+    }
+
+    #[test]
+    fn masks_are_packed_lowest_bit_first_per_frame() {
+        // 4x2 frames are a byte each; the region covers (1..3, 1..2) of frame 1 only
+        let bits = pack_masks(4, 2, 2, &[applied(1, 1, 3, 2, Some(1))]);
+        assert_eq!(bits, vec![0b0000_0000, 0b0110_0000]);
+    }
+
+    #[test]
+    fn masks_of_every_frame_are_padded_to_an_even_length() {
+        // 3x1 frames: 9 bits over three frames, padded to two bytes
+        let bits = pack_masks(3, 1, 3, &[applied(0, 0, 1, 1, None)]);
+        assert_eq!(bits, vec![0b0100_1001, 0]);
+        assert!(pack_masks(3, 1, 3, &[]).iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,200 @@
+//! GUI preferences kept between launches in `settings.toml` in the config directory.
+//! Written when the window closes; missing keys keep their defaults.
+
+use dcm_redact::{DCMRedactErrors, config_dir};
+use dcm_redact::{pacs, query};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Inner size of the main window, in points.
+    pub window_size: Option<[f32; 2]>,
+    /// Language code of the GUI, such as `de`; empty = the desktop's.
+    pub language: String,
+    /// Color scheme: system, dark or light.
+    pub theme: String,
+    /// Size of the whole interface; 1 is the system's own scale.
+    pub ui_scale: f32,
+    /// Mouse wheel zoom sensitivity; 1 is the default.
+    pub zoom_speed: f32,
+    /// Zoom around the pointer rather than the center of the view.
+    pub zoom_to_pointer: bool,
+    /// Where "Save" writes; unset = `redacted/` next to the source.
+    pub output_dir: Option<PathBuf>,
+    pub name_pattern: String,
+    /// Fill style, as accepted by `--fill`.
+    pub fill: String,
+    /// Anonymization profile by name; unset = the Basic Profile.
+    pub profile: Option<String>,
+    /// JSON lines file every save is logged to; unset = no audit log.
+    pub audit_log: Option<PathBuf>,
+    /// Name recorded in the audit log; empty = the login name.
+    pub operator: String,
+    /// Write a `.regions.json` sidecar next to every save.
+    pub regions_sidecar: bool,
+    /// Write the redaction mask as a DICOM SEG next to every save.
+    pub write_seg: bool,
+    /// Save DICOM as a new Secondary Capture instance.
+    pub secondary_capture: bool,
+    /// Burn `stamp_text` into a corner of every save.
+    pub stamp: bool,
+    pub stamp_text: String,
+    /// top-left, top-right, bottom-left or bottom-right.
+    pub stamp_corner: String,
+    /// Letter height, in percent of the image height.
+    pub stamp_size: f32,
+    /// Slide label and overview images in multi-file saves: keep, blank or remove.
+    pub slide_labels: String,
+    /// Defacing of head CT and MR series in multi-file saves: off, remove or blur.
+    pub deface: String,
+    /// Encapsulated PDF and CDA documents in multi-file saves: keep, replace or remove.
+    pub documents: String,
+    /// How "Send to PACS" sends: dimse (C-STORE) or dicomweb (STOW-RS).
+    pub send_protocol: String,
+    /// PACS "Send to PACS" sends to, as `AE@host:port`; empty = none yet.
+    pub pacs: String,
+    /// DICOMweb service "Send to PACS" posts to; empty = none yet.
+    pub stow_url: String,
+    /// AE title "Send to PACS" and "Query PACS" call as, and C-MOVE retrieves to.
+    pub calling_ae: String,
+    /// How "Query PACS" searches and retrieves: dimse or dicomweb.
+    pub query_protocol: String,
+    /// PACS "Query PACS" searches, as `AE@host:port`; empty = none yet.
+    pub query_pacs: String,
+    /// DICOMweb service "Query PACS" searches; empty = none yet.
+    pub query_url: String,
+    /// How "Query PACS" retrieves: move or get.
+    pub retrieve: String,
+    /// Port C-MOVE retrievals are received on.
+    pub store_port: u16,
+    /// Object storage URL last downloaded from.
+    pub download_url: String,
+    /// Object storage prefix last uploaded to.
+    pub upload_url: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_size: None,
+            language: String::new(),
+            theme: "system".to_string(),
+            ui_scale: 1.0,
+            zoom_speed: 1.0,
+            zoom_to_pointer: true,
+            output_dir: None,
+            name_pattern: String::new(),
+            fill: "black".to_string(),
+            profile: None,
+            audit_log: None,
+            operator: String::new(),
+            regions_sidecar: false,
+            write_seg: false,
+            secondary_capture: false,
+            stamp: false,
+            stamp_text: "DE-IDENTIFIED".to_string(),
+            stamp_corner: "bottom-right".to_string(),
+            stamp_size: 3.0,
+            slide_labels: "keep".to_string(),
+            deface: "off".to_string(),
+            documents: "replace".to_string(),
+            send_protocol: "dimse".to_string(),
+            pacs: String::new(),
+            stow_url: String::new(),
+            calling_ae: pacs::DEFAULT_CALLING_AE.to_string(),
+            query_protocol: "dimse".to_string(),
+            query_pacs: String::new(),
+            query_url: String::new(),
+            retrieve: "move".to_string(),
+            store_port: query::DEFAULT_STORE_PORT,
+            download_url: String::new(),
+            upload_url: String::new(),
+        }
+    }
+}
+
+/// Color scheme of the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    /// Dark or light as the desktop is, dark where it doesn't say.
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Dark, Theme::Light];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Theme::System => "System",
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    /// Parse `system`, `dark` or `light`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "system" => Ok(Theme::System),
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            _ => Err(format!(
+                "unknown theme '{s}' (expected system, dark or light)"
+            )),
+        }
+    }
+}
+
+/// `settings.toml` in the config directory.
+pub fn default_settings_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("settings.toml"))
+}
+
+impl Settings {
+    /// Settings from `path`. A missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self, DCMRedactErrors> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(DCMRedactErrors::io(
+                    format!("Failed to read settings {}", path.display()),
+                    e,
+                ));
+            }
+        };
+        toml::from_str(&text)
+            .map_err(|e| DCMRedactErrors::decode(format!("Invalid settings {}", path.display()), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DCMRedactErrors> {
+        let text = toml::to_string_pretty(self).map_err(|e| {
+            DCMRedactErrors::encode(format!("Failed to encode settings {}", path.display()), e)
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                DCMRedactErrors::io(format!("Failed to create {}", dir.display()), e)
+            })?;
+        }
+        fs::write(path, text).map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to write settings {}", path.display()), e)
+        })
+    }
+}
@@ -0,0 +1,330 @@
+//! Redaction shapes and their rasterization into pixel spans.
+//!
+//! Coordinates are full-res pixel indices. A pixel is inside a shape when its center
+//! (x + 0.5, y + 0.5) is.
+
+use crate::RedactRect;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Which tool the user draws with. Lasso and polygon both produce [`Shape::Polygon`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShapeKind {
+    #[default]
+    Rectangle,
+    Ellipse,
+    Polygon,
+    Lasso,
+}
+
+impl ShapeKind {
+    pub const ALL: [ShapeKind; 4] = [
+        ShapeKind::Rectangle,
+        ShapeKind::Ellipse,
+        ShapeKind::Polygon,
+        ShapeKind::Lasso,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ShapeKind::Rectangle => "Rectangle",
+            ShapeKind::Ellipse => "Ellipse",
+            ShapeKind::Polygon => "Polygon",
+            ShapeKind::Lasso => "Lasso",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shape {
+    /// (x0..x1, y0..y1), end exclusive.
+    Rect(RedactRect),
+    /// Ellipse inscribed in the given bounding box.
+    Ellipse(RedactRect),
+    /// Closed polygon through the given pixels (even-odd fill).
+    Polygon(Vec<[u32; 2]>),
+}
+
+impl Shape {
+    /// Smallest rectangle containing every pixel of the shape, or `None` if it is empty.
+    pub fn bounds(&self) -> Option<RedactRect> {
+        match self {
+            Shape::Rect(r) | Shape::Ellipse(r) => (r.x1 > r.x0 && r.y1 > r.y0).then_some(*r),
+            Shape::Polygon(points) => {
+                if points.len() < 3 {
+                    return None;
+                }
+                let xs = points.iter().map(|p| p[0]);
+                let ys = points.iter().map(|p| p[1]);
+                Some(RedactRect {
+                    x0: xs.clone().min()?,
+                    y0: ys.clone().min()?,
+                    x1: xs.max()? + 1,
+                    y1: ys.max()? + 1,
+                })
+            }
+        }
+    }
+
+    /// Call `f(y, x0, x1)` for every horizontal run of covered pixels (x1 exclusive),
+    /// clipped to a `width` x `height` image.
+    pub fn for_each_span(&self, width: u32, height: u32, mut f: impl FnMut(u32, u32, u32)) {
+        let Some(b) = self.bounds() else {
+            return;
+        };
+        for y in b.y0..b.y1.min(height) {
+            self.row_spans(y, width, |x0, x1| f(y, x0, x1));
+        }
+    }
+
+    /// Whether the pixel (x, y) is covered by the shape.
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        let Some(b) = self.bounds() else {
+            return false;
+        };
+        let mut hit = false;
+        if (b.y0..b.y1).contains(&y) {
+            self.row_spans(y, b.x1, |x0, x1| hit |= (x0..x1).contains(&x));
+        }
+        hit
+    }
+
+    /// The same shape with its bounding box `from` mapped linearly onto `to`.
+    /// Used to move (same size) and resize staged regions.
+    pub fn transformed(&self, from: RedactRect, to: RedactRect) -> Shape {
+        let sx = (to.x1 - to.x0) as f64 / (from.x1 - from.x0).max(1) as f64;
+        let sy = (to.y1 - to.y0) as f64 / (from.y1 - from.y0).max(1) as f64;
+        let map = |x: u32, y: u32| {
+            [
+                (to.x0 as f64 + (x as f64 - from.x0 as f64) * sx)
+                    .round()
+                    .max(0.0) as u32,
+                (to.y0 as f64 + (y as f64 - from.y0 as f64) * sy)
+                    .round()
+                    .max(0.0) as u32,
+            ]
+        };
+        let map_rect = |r: &RedactRect| {
+            let [x0, y0] = map(r.x0, r.y0);
+            let [x1, y1] = map(r.x1, r.y1);
+            RedactRect { x0, y0, x1, y1 }
+        };
+        match self {
+            Shape::Rect(r) => Shape::Rect(map_rect(r)),
+            Shape::Ellipse(r) => Shape::Ellipse(map_rect(r)),
+            Shape::Polygon(points) => {
+                Shape::Polygon(points.iter().map(|p| map(p[0], p[1])).collect())
+            }
+        }
+    }
+
+    /// The same shape moved by (dx, dy); coordinates stop at 0.
+    pub fn translated(&self, dx: i64, dy: i64) -> Shape {
+        let map = |x: u32, y: u32| {
+            [
+                (x as i64 + dx).clamp(0, u32::MAX as i64) as u32,
+                (y as i64 + dy).clamp(0, u32::MAX as i64) as u32,
+            ]
+        };
+        let map_rect = |r: &RedactRect| {
+            let [x0, y0] = map(r.x0, r.y0);
+            let [x1, y1] = map(r.x1, r.y1);
+            RedactRect { x0, y0, x1, y1 }
+        };
+        match self {
+            Shape::Rect(r) => Shape::Rect(map_rect(r)),
+            Shape::Ellipse(r) => Shape::Ellipse(map_rect(r)),
+            Shape::Polygon(points) => {
+                Shape::Polygon(points.iter().map(|p| map(p[0], p[1])).collect())
+            }
+        }
+    }
+
+    /// Covered runs `f(x0, x1)` on row `y`, clipped to `0..width`.
+    fn row_spans(&self, y: u32, width: u32, mut f: impl FnMut(u32, u32)) {
+        let yc = y as f64 + 0.5;
+        let clip = |x: f64| x.clamp(0.0, width as f64) as u32;
+        match self {
+            Shape::Rect(r) => f(r.x0.min(width), r.x1.min(width)),
+            Shape::Ellipse(r) => {
+                let (cx, cy) = ((r.x0 + r.x1) as f64 / 2.0, (r.y0 + r.y1) as f64 / 2.0);
+                let (rx, ry) = ((r.x1 - r.x0) as f64 / 2.0, (r.y1 - r.y0) as f64 / 2.0);
+                let dy = (yc - cy) / ry;
+                if dy.abs() > 1.0 {
+                    return;
+                }
+                let half = rx * (1.0 - dy * dy).sqrt();
+                // Pixels whose centers lie within [cx - half, cx + half]
+                let x0 = clip((cx - half - 0.5).ceil());
+                let x1 = clip((cx + half - 0.5).floor() + 1.0);
+                if x0 < x1 {
+                    f(x0, x1);
+                }
+            }
+            Shape::Polygon(points) => {
+                let mut xs: Vec<f64> = Vec::new();
+                for (i, a) in points.iter().enumerate() {
+                    let b = points[(i + 1) % points.len()];
+                    let (ax, ay) = (a[0] as f64 + 0.5, a[1] as f64 + 0.5);
+                    let (bx, by) = (b[0] as f64 + 0.5, b[1] as f64 + 0.5);
+                    if (ay <= yc) != (by <= yc) {
+                        xs.push(ax + (yc - ay) * (bx - ax) / (by - ay));
+                    }
+                }
+                xs.sort_by(f64::total_cmp);
+                for pair in xs.chunks_exact(2) {
+                    let x0 = clip((pair[0] - 0.5).ceil());
+                    let x1 = clip((pair[1] - 0.5).floor() + 1.0);
+                    if x0 < x1 {
+                        f(x0, x1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A shape to redact, on a single frame, a range of frames or every frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub shape: Shape,
+    /// `None` applies the region to every frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame: Option<usize>,
+    /// With `frame`, the last frame (inclusive) of a range starting there; `None` is
+    /// `frame` alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_frame: Option<usize>,
+}
+
+impl Region {
+    /// A region on every frame.
+    pub fn all_frames(shape: Shape) -> Self {
+        Self {
+            shape,
+            frame: None,
+            last_frame: None,
+        }
+    }
+
+    pub fn applies_to(&self, frame: usize) -> bool {
+        match self.frame {
+            Some(first) => (first..=self.last_frame.unwrap_or(first)).contains(&frame),
+            None => true,
+        }
+    }
+
+    /// Indices of the frames this region applies to, out of `frame_count`.
+    pub fn target_frames(&self, frame_count: usize) -> Range<usize> {
+        match self.frame {
+            Some(f) => {
+                let last = self.last_frame.unwrap_or(f).max(f);
+                f.min(frame_count)..(last + 1).min(frame_count)
+            }
+            None => 0..frame_count,
+        }
+    }
+}
+
+impl From<RedactRect> for Shape {
+    fn from(r: RedactRect) -> Self {
+        Shape::Rect(r)
+    }
+}
+
+/// Rectangles covering every pixel of a `width`×`height` image outside all of `keep`.
+///
+/// Each row gives the gaps between the spans of the shapes crossing it, and runs of
+/// rows with the same gaps are merged, so a single rectangle gives at most four
+/// rectangles; curved edges give a staircase of them.
+pub fn outside(keep: &[Shape], width: u32, height: u32) -> Vec<RedactRect> {
+    let mut done = Vec::new();
+    // Rectangles of the previous row, still growing downwards
+    let mut open: Vec<RedactRect> = Vec::new();
+    for y in 0..height {
+        let mut spans = Vec::new();
+        for shape in keep {
+            if shape.bounds().is_some_and(|b| (b.y0..b.y1).contains(&y)) {
+                shape.row_spans(y, width, |x0, x1| spans.push((x0, x1)));
+            }
+        }
+        spans.sort_unstable();
+        let mut gaps = Vec::new();
+        let mut x = 0;
+        for (x0, x1) in spans {
+            if x0 > x {
+                gaps.push((x, x0));
+            }
+            x = x.max(x1);
+        }
+        if x < width {
+            gaps.push((x, width));
+        }
+
+        let mut next = Vec::new();
+        for (x0, x1) in gaps {
+            match open.iter().position(|r| r.x0 == x0 && r.x1 == x1) {
+                Some(i) => {
+                    let mut r = open.swap_remove(i);
+                    r.y1 = y + 1;
+                    next.push(r);
+                }
+                None => next.push(RedactRect {
+                    x0,
+                    y0: y,
+                    x1,
+                    y1: y + 1,
+                }),
+            }
+        }
+        done.append(&mut open);
+        open = next;
+    }
+    done.append(&mut open);
+    done
+}
+
+/// Regions covering everything outside `keep` on a `width`×`height` image of
+/// `frame_count` frames, so filling them keeps only the insides of `keep`. Frames no
+/// region of `keep` applies to are left alone; runs of frames keeping the same
+/// regions share their rectangles.
+pub fn keep_only(keep: &[Region], frame_count: usize, width: u32, height: u32) -> Vec<Region> {
+    let shapes =
+        |kept: &[&Region]| -> Vec<Shape> { kept.iter().map(|r| r.shape.clone()).collect() };
+    if keep.is_empty() {
+        return Vec::new();
+    }
+    if keep.iter().all(|r| r.frame.is_none()) {
+        let kept: Vec<&Region> = keep.iter().collect();
+        return outside(&shapes(&kept), width, height)
+            .into_iter()
+            .map(|r| Region::all_frames(Shape::Rect(r)))
+            .collect();
+    }
+
+    let kept_on =
+        |frame: usize| -> Vec<&Region> { keep.iter().filter(|r| r.applies_to(frame)).collect() };
+    let mut regions = Vec::new();
+    let mut first = 0;
+    while first < frame_count {
+        let kept = kept_on(first);
+        let mut last = first;
+        while last + 1 < frame_count && kept_on(last + 1) == kept {
+            last += 1;
+        }
+        if !kept.is_empty() {
+            regions.extend(
+                outside(&shapes(&kept), width, height)
+                    .into_iter()
+                    .map(|r| Region {
+                        shape: Shape::Rect(r),
+                        frame: Some(first),
+                        last_frame: (last > first).then_some(last),
+                    }),
+            );
+        }
+        first = last + 1;
+    }
+    regions
+}
@@ -0,0 +1,393 @@
+//! Remappable keyboard shortcuts of the GUI, stored in `shortcuts.toml` in the config
+//! directory:
+//!
+//! ```toml
+//! [bindings]
+//! save = "Ctrl+S"
+//! apply = "Ctrl+Enter"
+//! lasso = ""          # unbound
+//! ```
+//!
+//! `Ctrl` is Cmd on macOS. Actions missing from the file keep their default binding.
+
+use dcm_redact::{DCMRedactErrors, config_dir};
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Open,
+    OpenFolder,
+    Save,
+    SaveAs,
+    Reset,
+    Undo,
+    Redo,
+    Apply,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    NextFrame,
+    PrevFrame,
+    FirstFrame,
+    LastFrame,
+    Rectangle,
+    Ellipse,
+    Polygon,
+    Lasso,
+    PeekOriginal,
+}
+
+impl Action {
+    pub const ALL: [Action; 20] = [
+        Action::Open,
+        Action::OpenFolder,
+        Action::Save,
+        Action::SaveAs,
+        Action::Reset,
+        Action::Undo,
+        Action::Redo,
+        Action::Apply,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ZoomReset,
+        Action::NextFrame,
+        Action::PrevFrame,
+        Action::FirstFrame,
+        Action::LastFrame,
+        Action::Rectangle,
+        Action::Ellipse,
+        Action::Polygon,
+        Action::Lasso,
+        Action::PeekOriginal,
+    ];
+
+    /// The action's name in the GUI's language.
+    pub fn label(self) -> String {
+        crate::i18n::text(&format!("shortcuts.{}", self.key()))
+    }
+
+    /// Name in `shortcuts.toml`.
+    fn key(self) -> &'static str {
+        match self {
+            Action::Open => "open",
+            Action::OpenFolder => "open_folder",
+            Action::Save => "save",
+            Action::SaveAs => "save_as",
+            Action::Reset => "reset",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Apply => "apply",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::ZoomReset => "zoom_reset",
+            Action::NextFrame => "next_frame",
+            Action::PrevFrame => "prev_frame",
+            Action::FirstFrame => "first_frame",
+            Action::LastFrame => "last_frame",
+            Action::Rectangle => "rectangle",
+            Action::Ellipse => "ellipse",
+            Action::Polygon => "polygon",
+            Action::Lasso => "lasso",
+            Action::PeekOriginal => "peek_original",
+        }
+    }
+
+    fn default_binding(self) -> KeyboardShortcut {
+        let ctrl = |key| KeyboardShortcut::new(Modifiers::COMMAND, key);
+        let ctrl_shift = |key| KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, key);
+        let plain = |key| KeyboardShortcut::new(Modifiers::NONE, key);
+        match self {
+            Action::Open => ctrl(Key::O),
+            Action::OpenFolder => ctrl_shift(Key::O),
+            Action::Save => ctrl(Key::S),
+            Action::SaveAs => ctrl_shift(Key::S),
+            Action::Reset => ctrl(Key::R),
+            Action::Undo => ctrl(Key::Z),
+            Action::Redo => ctrl_shift(Key::Z),
+            Action::Apply => plain(Key::A),
+            Action::ZoomIn => plain(Key::Plus),
+            Action::ZoomOut => plain(Key::Minus),
+            Action::ZoomReset => plain(Key::Num0),
+            Action::NextFrame => plain(Key::PageDown),
+            Action::PrevFrame => plain(Key::PageUp),
+            Action::FirstFrame => plain(Key::Home),
+            Action::LastFrame => plain(Key::End),
+            Action::Rectangle => plain(Key::R),
+            Action::Ellipse => plain(Key::E),
+            Action::Polygon => plain(Key::P),
+            Action::Lasso => plain(Key::L),
+            Action::PeekOriginal => plain(Key::B),
+        }
+    }
+}
+
+/// `Ctrl+Shift+Z` style text for a binding.
+pub fn format_binding(shortcut: &KeyboardShortcut) -> String {
+    let m = shortcut.modifiers;
+    let mut parts = Vec::new();
+    if m.command || m.ctrl || m.mac_cmd {
+        parts.push("Ctrl");
+    }
+    if m.alt {
+        parts.push("Alt");
+    }
+    if m.shift {
+        parts.push("Shift");
+    }
+    parts.push(shortcut.logical_key.name());
+    parts.join("+")
+}
+
+fn parse_binding(text: &str) -> Option<KeyboardShortcut> {
+    let mut modifiers = Modifiers::NONE;
+    let mut key = None;
+    for part in text.split('+').map(str::trim) {
+        match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "cmd" | "command" => modifiers = modifiers | Modifiers::COMMAND,
+            "alt" | "option" => modifiers = modifiers | Modifiers::ALT,
+            "shift" => modifiers = modifiers | Modifiers::SHIFT,
+            _ => key = Some(Key::from_name(part)?),
+        }
+    }
+    Some(KeyboardShortcut::new(modifiers, key?))
+}
+
+/// Whether the binding needs a modifier other than Shift, and so can't be typed text.
+fn has_command(shortcut: &KeyboardShortcut) -> bool {
+    let m = shortcut.modifiers;
+    m.command || m.ctrl || m.mac_cmd || m.alt
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ShortcutFile {
+    #[serde(default)]
+    bindings: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Shortcuts {
+    bindings: HashMap<Action, Option<KeyboardShortcut>>,
+}
+
+impl Default for Shortcuts {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL
+                .iter()
+                .map(|&a| (a, Some(a.default_binding())))
+                .collect(),
+        }
+    }
+}
+
+/// `shortcuts.toml` in the config directory.
+pub fn default_shortcuts_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("shortcuts.toml"))
+}
+
+impl Shortcuts {
+    /// Bindings from `path` over the defaults. A missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self, DCMRedactErrors> {
+        let mut shortcuts = Self::default();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(shortcuts),
+            Err(e) => {
+                return Err(DCMRedactErrors::io(
+                    format!("Failed to read shortcuts {}", path.display()),
+                    e,
+                ));
+            }
+        };
+        let file: ShortcutFile = toml::from_str(&text).map_err(|e| {
+            DCMRedactErrors::decode(format!("Invalid shortcuts {}", path.display()), e)
+        })?;
+        for (name, binding) in &file.bindings {
+            let Some(action) = Action::ALL.into_iter().find(|a| a.key() == name) else {
+                return Err(DCMRedactErrors::ValueError(format!(
+                    "{}: unknown action '{name}'",
+                    path.display()
+                )));
+            };
+            let shortcut = if binding.trim().is_empty() {
+                None
+            } else {
+                Some(parse_binding(binding).ok_or_else(|| {
+                    DCMRedactErrors::ValueError(format!(
+                        "{}: invalid shortcut '{binding}' for {name}",
+                        path.display()
+                    ))
+                })?)
+            };
+            shortcuts.bindings.insert(action, shortcut);
+        }
+        Ok(shortcuts)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DCMRedactErrors> {
+        let file = ShortcutFile {
+            bindings: Action::ALL
+                .iter()
+                .map(|&a| {
+                    let binding = self.get(a).map_or_else(String::new, |s| format_binding(&s));
+                    (a.key().to_string(), binding)
+                })
+                .collect(),
+        };
+        let text = toml::to_string_pretty(&file).map_err(|e| {
+            DCMRedactErrors::encode(format!("Failed to encode shortcuts {}", path.display()), e)
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                DCMRedactErrors::io(format!("Failed to create {}", dir.display()), e)
+            })?;
+        }
+        fs::write(path, text).map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to write shortcuts {}", path.display()), e)
+        })
+    }
+
+    pub fn get(&self, action: Action) -> Option<KeyboardShortcut> {
+        self.bindings.get(&action).copied().flatten()
+    }
+
+    /// Bind `action` to `shortcut`, unbinding any other action that used it.
+    pub fn set(&mut self, action: Action, shortcut: Option<KeyboardShortcut>) {
+        if let Some(s) = shortcut {
+            for binding in self.bindings.values_mut() {
+                if *binding == Some(s) {
+                    *binding = None;
+                }
+            }
+        }
+        self.bindings.insert(action, shortcut);
+    }
+
+    /// Hover text suffix for a button, e.g. `" (Ctrl+S)"`.
+    pub fn hint(&self, action: Action) -> String {
+        self.get(action)
+            .map_or_else(String::new, |s| format!(" ({})", format_binding(&s)))
+    }
+
+    /// Consume this frame's key presses and return the actions they trigger. While
+    /// `typing`, only bindings with Ctrl or Alt count.
+    pub fn pressed(&self, ctx: &egui::Context, typing: bool) -> Vec<Action> {
+        // Ctrl+Shift+Z must be checked before Ctrl+Z, which also matches with Shift held
+        let mut bound: Vec<(Action, KeyboardShortcut)> = Action::ALL
+            .iter()
+            .filter_map(|&a| Some((a, self.get(a)?)))
+            .filter(|(_, s)| !typing || has_command(s))
+            .collect();
+        bound.sort_by_key(|(_, s)| {
+            let m = s.modifiers;
+            std::cmp::Reverse(u8::from(m.command) + u8::from(m.alt) + u8::from(m.shift))
+        });
+        bound
+            .into_iter()
+            .filter(|(_, s)| ctx.input_mut(|i| i.consume_shortcut(s)))
+            .map(|(a, _)| a)
+            .collect()
+    }
+
+    /// Whether the keys of `action` are held down this frame. While `typing`, only
+    /// bindings with Ctrl or Alt count.
+    pub fn held(&self, ctx: &egui::Context, action: Action, typing: bool) -> bool {
+        self.get(action)
+            .filter(|s| !typing || has_command(s))
+            .is_some_and(|s| {
+                ctx.input(|i| {
+                    i.key_down(s.logical_key) && i.modifiers.matches_logically(s.modifiers)
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "dcm-redact-shortcuts-{}.toml",
+            dcm_redact::uids::new_uid()
+        ))
+    }
+
+    #[test]
+    fn bindings_print_and_parse_the_same() {
+        for action in Action::ALL {
+            let binding = action.default_binding();
+            assert_eq!(parse_binding(&format_binding(&binding)), Some(binding));
+        }
+        assert_eq!(
+            parse_binding("cmd + shift + s"),
+            Some(KeyboardShortcut::new(
+                Modifiers::COMMAND | Modifiers::SHIFT,
+                Key::S
+            ))
+        );
+        assert_eq!(parse_binding("Ctrl+Shift"), None);
+        assert_eq!(parse_binding("Ctrl+Nope"), None);
+    }
+
+    #[test]
+    fn defaults_are_distinct_and_labelled() {
+        for (i, a) in Action::ALL.iter().enumerate() {
+            for b in &Action::ALL[i + 1..] {
+                assert_ne!(a.default_binding(), b.default_binding(), "{a:?} {b:?}");
+            }
+            assert_ne!(a.label(), format!("shortcuts.{}", a.key()), "{a:?}");
+        }
+    }
+
+    #[test]
+    fn binding_a_taken_shortcut_unbinds_the_other_action() {
+        let mut shortcuts = Shortcuts::default();
+        let save = shortcuts.get(Action::Save);
+        shortcuts.set(Action::Apply, save);
+        assert_eq!(shortcuts.get(Action::Apply), save);
+        assert_eq!(shortcuts.get(Action::Save), None);
+        assert_eq!(shortcuts.hint(Action::Save), "");
+        assert_eq!(shortcuts.hint(Action::Apply), " (Ctrl+S)");
+    }
+
+    #[test]
+    fn files_keep_defaults_for_missing_actions() {
+        let path = temp_path();
+        fs::write(&path, "[bindings]\nsave = \"Alt+W\"\nlasso = \"\"\n").unwrap();
+        let shortcuts = Shortcuts::load(&path).unwrap();
+        assert_eq!(
+            shortcuts.get(Action::Save),
+            Some(KeyboardShortcut::new(Modifiers::ALT, Key::W))
+        );
+        assert_eq!(shortcuts.get(Action::Lasso), None);
+        assert_eq!(
+            shortcuts.get(Action::Undo),
+            Some(Action::Undo.default_binding())
+        );
+
+        shortcuts.save(&path).unwrap();
+        let reloaded = Shortcuts::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(
+            Action::ALL
+                .iter()
+                .all(|&a| reloaded.get(a) == shortcuts.get(a))
+        );
+        assert!(Shortcuts::load(&path).is_ok());
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_are_errors() {
+        let path = temp_path();
+        fs::write(&path, "[bindings]\nsmile = \"Ctrl+U\"\n").unwrap();
+        assert!(Shortcuts::load(&path).is_err());
+        fs::write(&path, "[bindings]\nsave = \"Ctrl+Nope\"\n").unwrap();
+        assert!(Shortcuts::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
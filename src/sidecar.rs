@@ -0,0 +1,229 @@
+//! `.regions.json` sidecars: the regions burned into a saved file, in full-resolution
+//! pixels with their fill style, written next to it so QA tools can check coverage
+//! and the redaction can be reproduced.
+//!
+//! [`load_regions`] reads them back, along with region lists from other tools such as
+//! a text-detection pipeline: a JSON array (or an object with a `regions` array) of
+//! regions, `{x0,y0,x1,y1}` or `{x,y,width,height}` rectangles, or a CSV file with an
+//! `x0,y0,x1,y1` or `x,y,width,height` header. An optional `frame` field or column puts
+//! a region on a single frame.
+//!
+//! ```json
+//! {
+//!   "source": "in/a.dcm",
+//!   "width": 1024,
+//!   "height": 768,
+//!   "frames": 1,
+//!   "regions": [{ "shape": { "rect": { "x0": 0, "y0": 0, "x1": 1024, "y1": 64 } }, "fill": "black" }]
+//! }
+//! ```
+
+use crate::audit::AppliedRegion;
+use crate::gz;
+use crate::{DCMRedactErrors, RedactRect, Region, Shape};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionSidecar {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    pub width: u32,
+    pub height: u32,
+    pub frames: usize,
+    pub regions: Vec<AppliedRegion>,
+}
+
+/// `scan.dcm` (or `scan.dcm.gz`) → `scan.regions.json`, in the same directory.
+pub fn sidecar_path(output: &Path) -> PathBuf {
+    let stem = gz::inner(output);
+    let stem = stem.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{stem}.regions.json"))
+}
+
+impl RegionSidecar {
+    pub fn save(&self, path: &Path) -> Result<(), DCMRedactErrors> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| {
+            DCMRedactErrors::encode(format!("Failed to encode {}", path.display()), e)
+        })?;
+        fs::write(path, text + "\n")
+            .map_err(|e| DCMRedactErrors::io(format!("Failed to write {}", path.display()), e))
+    }
+}
+
+/// One region in an imported file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RegionEntry {
+    Region(Region),
+    Corners {
+        x0: u32,
+        y0: u32,
+        x1: u32,
+        y1: u32,
+        #[serde(default)]
+        frame: Option<usize>,
+        #[serde(default)]
+        last_frame: Option<usize>,
+    },
+    Sized {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        frame: Option<usize>,
+        #[serde(default)]
+        last_frame: Option<usize>,
+    },
+}
+
+impl From<RegionEntry> for Region {
+    fn from(entry: RegionEntry) -> Self {
+        match entry {
+            RegionEntry::Region(region) => region,
+            RegionEntry::Corners {
+                x0,
+                y0,
+                x1,
+                y1,
+                frame,
+                last_frame,
+            } => rect_region(x0, y0, x1, y1, frame, last_frame),
+            RegionEntry::Sized {
+                x,
+                y,
+                width,
+                height,
+                frame,
+                last_frame,
+            } => rect_region(
+                x,
+                y,
+                x.saturating_add(width),
+                y.saturating_add(height),
+                frame,
+                last_frame,
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RegionFile {
+    List(Vec<RegionEntry>),
+    Wrapped { regions: Vec<RegionEntry> },
+}
+
+fn rect_region(
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    frame: Option<usize>,
+    last_frame: Option<usize>,
+) -> Region {
+    Region {
+        shape: Shape::Rect(RedactRect { x0, y0, x1, y1 }),
+        frame,
+        last_frame: frame.and(last_frame),
+    }
+}
+
+/// Read the regions of a sidecar, template or region list, as JSON or (with a `.csv`
+/// extension) CSV. Empty regions are dropped.
+pub fn load_regions(path: &Path) -> Result<Vec<Region>, DCMRedactErrors> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        DCMRedactErrors::io(format!("Failed to read regions {}", path.display()), e)
+    })?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+    let regions = if is_csv {
+        parse_csv(path, &text)?
+    } else {
+        let file: RegionFile = serde_json::from_str(&text).map_err(|e| {
+            DCMRedactErrors::decode(format!("Invalid regions {}", path.display()), e)
+        })?;
+        let (RegionFile::List(entries) | RegionFile::Wrapped { regions: entries }) = file;
+        entries.into_iter().map(Region::from).collect()
+    };
+    Ok(regions
+        .into_iter()
+        .filter(|r| r.shape.bounds().is_some())
+        .collect())
+}
+
+/// Column indices of a region CSV.
+enum CsvLayout {
+    Corners([usize; 4]), // x0, y0, x1, y1
+    Sized([usize; 4]),   // x, y, width, height
+}
+
+fn parse_csv(path: &Path, text: &str) -> Result<Vec<Region>, DCMRedactErrors> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let header: Vec<String> = lines
+        .next()
+        .map(|(_, l)| {
+            l.split(',')
+                .map(|c| c.trim().to_ascii_lowercase())
+                .collect()
+        })
+        .unwrap_or_default();
+    let column = |name: &str| header.iter().position(|c| c == name);
+    let frame_col = column("frame");
+    let last_frame_col = column("last_frame");
+    let layout = match (column("x0"), column("y0"), column("x1"), column("y1")) {
+        (Some(x0), Some(y0), Some(x1), Some(y1)) => CsvLayout::Corners([x0, y0, x1, y1]),
+        _ => match (column("x"), column("y"), column("width"), column("height")) {
+            (Some(x), Some(y), Some(w), Some(h)) => CsvLayout::Sized([x, y, w, h]),
+            _ => {
+                return Err(DCMRedactErrors::ValueError(format!(
+                    "{}: expected a header with x0,y0,x1,y1 or x,y,width,height",
+                    path.display()
+                )));
+            }
+        },
+    };
+    let mut regions = Vec::new();
+    for (n, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let bad =
+            || DCMRedactErrors::ValueError(format!("{}:{}: invalid region", path.display(), n + 1));
+        let number = |i: usize| -> Result<u32, DCMRedactErrors> {
+            fields.get(i).and_then(|f| f.parse().ok()).ok_or_else(bad)
+        };
+        let optional = |col: Option<usize>| match col.and_then(|i| fields.get(i)) {
+            None | Some(&"") => Ok(None),
+            Some(f) => f.parse().map(Some).map_err(|_| bad()),
+        };
+        let (frame, last_frame) = (optional(frame_col)?, optional(last_frame_col)?);
+        regions.push(match layout {
+            CsvLayout::Corners([x0, y0, x1, y1]) => rect_region(
+                number(x0)?,
+                number(y0)?,
+                number(x1)?,
+                number(y1)?,
+                frame,
+                last_frame,
+            ),
+            CsvLayout::Sized([x, y, w, h]) => {
+                let (x, y) = (number(x)?, number(y)?);
+                rect_region(
+                    x,
+                    y,
+                    x.saturating_add(number(w)?),
+                    y.saturating_add(number(h)?),
+                    frame,
+                    last_frame,
+                )
+            }
+        });
+    }
+    Ok(regions)
+}
@@ -0,0 +1,97 @@
+//! Structured Report (SR) documents: reports, measurements and key image notes stored
+//! as a tree of content items (ContentSequence) instead of pixel data.
+//!
+//! Names, free text and dates sit in those content items, so the Basic Profile removes
+//! the whole tree, leaving an SR with nothing in it. With
+//! [`Profile::structured_reports`](crate::profile::Profile::structured_reports) on, the
+//! anonymizer keeps the tree and scrubs the value of every PNAME, TEXT, DATE, TIME and
+//! DATETIME item instead; UIDREF and image references are remapped like any other UID.
+//! Codes, measurements and the structure of the report are kept.
+
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+/// SOP Class UIDs of the SR family (Basic Text SR, Enhanced SR, Key Object Selection,
+/// measurement reports, ...) start with this.
+const SR_CLASS_PREFIX: &str = "1.2.840.10008.5.1.4.1.1.88.";
+
+fn text(obj: &InMemDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn items(obj: &InMemDicomObject, tag: Tag) -> &[InMemDicomObject] {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.items())
+        .unwrap_or_default()
+}
+
+/// Whether `obj` is an SR document, by its SOP Class or Modality.
+pub fn is_structured_report(obj: &InMemDicomObject) -> bool {
+    text(obj, tags::SOP_CLASS_UID).starts_with(SR_CLASS_PREFIX)
+        || text(obj, tags::MODALITY).eq_ignore_ascii_case("SR")
+}
+
+/// CodeMeaning of the first item of code sequence `tag` in `obj`.
+fn code_meaning(obj: &InMemDicomObject, tag: Tag) -> String {
+    items(obj, tag)
+        .first()
+        .map(|code| text(code, tags::CODE_MEANING))
+        .unwrap_or_default()
+}
+
+/// The value of a content item as shown: text, names, dates and UIDs as they are,
+/// codes by their meaning, numbers with their unit.
+fn item_value(item: &InMemDicomObject) -> String {
+    match text(item, tags::VALUE_TYPE).as_str() {
+        "TEXT" => text(item, tags::TEXT_VALUE),
+        "PNAME" => text(item, tags::PERSON_NAME),
+        "DATE" => text(item, tags::DATE),
+        "TIME" => text(item, tags::TIME),
+        "DATETIME" => text(item, tags::DATE_TIME),
+        "UIDREF" => text(item, tags::UID),
+        "CODE" => code_meaning(item, tags::CONCEPT_CODE_SEQUENCE),
+        "NUM" => items(item, tags::MEASURED_VALUE_SEQUENCE)
+            .first()
+            .map(|m| {
+                let unit = code_meaning(m, tags::MEASUREMENT_UNITS_CODE_SEQUENCE);
+                format!("{} {unit}", text(m, tags::NUMERIC_VALUE))
+                    .trim_end()
+                    .to_string()
+            })
+            .unwrap_or_default(),
+        "CONTAINER" => String::new(),
+        other => format!("[{other}]"),
+    }
+}
+
+fn push_lines(obj: &InMemDicomObject, depth: usize, lines: &mut Vec<String>) {
+    for item in items(obj, tags::CONTENT_SEQUENCE) {
+        let concept = code_meaning(item, tags::CONCEPT_NAME_CODE_SEQUENCE);
+        let value = item_value(item);
+        let line = match (concept.is_empty(), value.is_empty()) {
+            (false, false) => format!("{concept}: {value}"),
+            (false, true) => concept,
+            (true, _) => value,
+        };
+        lines.push(format!("{}{line}", "  ".repeat(depth)));
+        push_lines(item, depth + 1, lines);
+    }
+}
+
+/// The content tree of `obj` as lines of `Concept: value`, indented two spaces per
+/// level, headed by the document title.
+pub fn content_lines(obj: &InMemDicomObject) -> Vec<String> {
+    let mut lines = Vec::new();
+    let title = code_meaning(obj, tags::CONCEPT_NAME_CODE_SEQUENCE);
+    if !title.is_empty() {
+        lines.push(title);
+    }
+    push_lines(obj, usize::from(!lines.is_empty()), &mut lines);
+    lines
+}
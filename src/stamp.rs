@@ -0,0 +1,196 @@
+//! A text stamp such as "DE-IDENTIFIED" burned into a corner of every saved frame,
+//! which some IRBs require as a visual marker on de-identified copies.
+//!
+//! The text is set in a built-in 5×7 pixel font (letters, digits and common
+//! punctuation; lowercase is shown as capitals and anything else as `?`), scaled to a
+//! share of the image height, light on a dark box so it reads on any background.
+
+use crate::RedactRect;
+use std::fmt;
+use std::str::FromStr;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Which corner of the image the stamp goes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    pub const ALL: [Corner; 4] = [
+        Corner::TopLeft,
+        Corner::TopRight,
+        Corner::BottomLeft,
+        Corner::BottomRight,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Corner::TopLeft => "Top left",
+            Corner::TopRight => "Top right",
+            Corner::BottomLeft => "Bottom left",
+            Corner::BottomRight => "Bottom right",
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label().to_ascii_lowercase().replace(' ', "-"))
+    }
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    /// Parse `top-left`, `top-right`, `bottom-left` or `bottom-right`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Corner::ALL
+            .into_iter()
+            .find(|c| c.to_string() == s.trim().to_ascii_lowercase().replace([' ', '_'], "-"))
+            .ok_or_else(|| {
+                format!(
+                    "unknown corner '{s}' (expected top-left, top-right, bottom-left or bottom-right)"
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamp {
+    pub text: String,
+    pub corner: Corner,
+    /// Height of the letters, in percent of the image height.
+    pub size: f32,
+}
+
+impl Default for Stamp {
+    fn default() -> Self {
+        Self {
+            text: "DE-IDENTIFIED".to_string(),
+            corner: Corner::default(),
+            size: 3.0,
+        }
+    }
+}
+
+/// Rows of `c` in the 5×7 font, top first, the leftmost pixel in bit 4.
+#[rustfmt::skip]
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b01110, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'D' => [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        'G' => [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+        'H' => [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'I' => [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'J' => [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+        'K' => [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+        'L' => [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+        'M' => [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+        'N' => [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+        'O' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'P' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+        'Q' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+        'R' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'T' => [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+        'U' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'V' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        'W' => [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+        'X' => [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+        'Y' => [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100],
+        'Z' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        ' ' => [0; 7],
+        '-' => [0, 0, 0, 0b11111, 0, 0, 0],
+        '+' => [0, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0],
+        '_' => [0, 0, 0, 0, 0, 0, 0b11111],
+        '.' => [0, 0, 0, 0, 0, 0b01100, 0b01100],
+        ',' => [0, 0, 0, 0, 0b01100, 0b00100, 0b01000],
+        ':' => [0, 0b01100, 0b01100, 0, 0b01100, 0b01100, 0],
+        '/' => [0, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0],
+        '(' => [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010],
+        ')' => [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000],
+        '#' => [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010],
+        _ => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0, 0b00100],
+    }
+}
+
+impl Stamp {
+    /// Where the stamp goes on a `width` x `height` image: the backing box, then the
+    /// runs of letter pixels inside it. `None` if there is no text.
+    pub fn layout(&self, width: u32, height: u32) -> Option<(RedactRect, Vec<RedactRect>)> {
+        let text: Vec<char> = self.text.trim().chars().collect();
+        if text.is_empty() {
+            return None;
+        }
+        let scale = (height as f32 * self.size / 100.0 / GLYPH_HEIGHT as f32)
+            .round()
+            .max(1.0) as u32;
+        let advance = (GLYPH_WIDTH + 1) * scale;
+        let pad = 2 * scale;
+        let box_width = text.len() as u32 * advance - scale + 2 * pad;
+        let box_height = GLYPH_HEIGHT * scale + 2 * pad;
+        let left = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => pad,
+            Corner::TopRight | Corner::BottomRight => width.saturating_sub(pad + box_width),
+        };
+        let top = match self.corner {
+            Corner::TopLeft | Corner::TopRight => pad,
+            Corner::BottomLeft | Corner::BottomRight => height.saturating_sub(pad + box_height),
+        };
+        let backing = RedactRect {
+            x0: left,
+            y0: top,
+            x1: (left + box_width).min(width),
+            y1: (top + box_height).min(height),
+        };
+
+        // One rectangle per run of set pixels in a glyph row
+        let mut letters = Vec::new();
+        for (i, &c) in text.iter().enumerate() {
+            let x = left + pad + i as u32 * advance;
+            for (row, bits) in glyph(c).into_iter().enumerate() {
+                let y = top + pad + row as u32 * scale;
+                let mut col = 0;
+                while col < GLYPH_WIDTH {
+                    let set = |col: u32| bits >> (GLYPH_WIDTH - 1 - col) & 1 == 1;
+                    if !set(col) {
+                        col += 1;
+                        continue;
+                    }
+                    let start = col;
+                    while col < GLYPH_WIDTH && set(col) {
+                        col += 1;
+                    }
+                    letters.push(RedactRect {
+                        x0: x + start * scale,
+                        y0: y,
+                        x1: x + col * scale,
+                        y1: y + scale,
+                    });
+                }
+            }
+        }
+        Some((backing, letters))
+    }
+}
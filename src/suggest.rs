@@ -0,0 +1,167 @@
+//! Burned-in annotation detection without OCR: small clusters of bright pixels on a
+//! dark background, as ultrasound and angiography systems draw their text.
+//!
+//! Bright pixels are smeared sideways so the letters of a word (and the words of a
+//! line) join up, grouped into connected components, and every component shaped like
+//! a line of text becomes a candidate rectangle. Nothing is read, so it needs no
+//! external tool, but it also flags bright markers and scales; review what it finds.
+
+use crate::{Frame, RedactRect};
+
+#[derive(Debug, Clone)]
+pub struct SuggestOptions {
+    /// Pixels at least this bright (0-255, as displayed) count as text.
+    pub threshold: u8,
+    /// Horizontal gap (pixels) bridged between letters and words.
+    pub gap: u32,
+    /// Components taller than this are not text.
+    pub max_height: u32,
+    /// Components with fewer bright pixels than this are noise.
+    pub min_pixels: usize,
+    /// Pixels added on every side of each candidate.
+    pub padding: u32,
+}
+
+impl Default for SuggestOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 192,
+            gap: 6,
+            max_height: 48,
+            min_pixels: 12,
+            padding: 2,
+        }
+    }
+}
+
+/// Brightness as displayed: the top 8 significant bits of stored values (inverted for
+/// MONOCHROME1), or the brightest channel of color pixels, so colored text counts.
+fn brightness(frame: &Frame, bits_stored: u16, invert: bool) -> (u32, u32, Vec<u8>) {
+    match frame {
+        Frame::Gray(img) => {
+            let shift = bits_stored.saturating_sub(8);
+            let values = img
+                .as_raw()
+                .iter()
+                .map(|&v| {
+                    let v = (v >> shift).min(255) as u8;
+                    if invert { 255 - v } else { v }
+                })
+                .collect();
+            (img.width(), img.height(), values)
+        }
+        Frame::Rgb(img) => {
+            let values = img
+                .pixels()
+                .map(|p| p.0.into_iter().max().unwrap_or(0))
+                .collect();
+            (img.width(), img.height(), values)
+        }
+    }
+}
+
+/// `mask` grown by `dx` pixels sideways and one row up and down.
+fn smear(mask: &[bool], width: usize, height: usize, dx: usize) -> Vec<bool> {
+    let mut wide = vec![false; mask.len()];
+    for y in 0..height {
+        let row = &mask[y * width..(y + 1) * width];
+        // Distance since the last bright pixel, looking both ways
+        let mut since = usize::MAX;
+        for x in 0..width {
+            since = if row[x] { 0 } else { since.saturating_add(1) };
+            wide[y * width + x] = since <= dx;
+        }
+        since = usize::MAX;
+        for x in (0..width).rev() {
+            since = if row[x] { 0 } else { since.saturating_add(1) };
+            wide[y * width + x] |= since <= dx;
+        }
+    }
+    let mut out = wide.clone();
+    for y in 0..height {
+        for x in 0..width {
+            if wide[y * width + x] {
+                if y > 0 {
+                    out[(y - 1) * width + x] = true;
+                }
+                if y + 1 < height {
+                    out[(y + 1) * width + x] = true;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Candidate rectangles around bright text in `frame`.
+pub fn suggest_regions(
+    frame: &Frame,
+    bits_stored: u16,
+    photometric: Option<&str>,
+    opts: &SuggestOptions,
+) -> Vec<RedactRect> {
+    let (w, h, values) = brightness(frame, bits_stored, photometric == Some("MONOCHROME1"));
+    let (width, height) = (w as usize, h as usize);
+    let bright: Vec<bool> = values.iter().map(|&v| v >= opts.threshold).collect();
+    let joined = smear(&bright, width, height, opts.gap as usize);
+
+    let mut seen = vec![false; joined.len()];
+    let mut stack = Vec::new();
+    let mut found = Vec::new();
+    for start in 0..joined.len() {
+        if !joined[start] || seen[start] {
+            continue;
+        }
+        // Flood fill one component, tracking the bounds of its bright pixels
+        seen[start] = true;
+        stack.push(start);
+        let (mut x0, mut y0, mut x1, mut y1) = (width, height, 0, 0);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            if bright[i] {
+                (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1));
+            }
+            let neighbors = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - width),
+                (y + 1 < height).then(|| i + width),
+            ];
+            for n in neighbors.into_iter().flatten() {
+                if joined[n] && !seen[n] {
+                    seen[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+
+        let (bw, bh) = (x1 - x0, y1 - y0);
+        if bh > opts.max_height as usize || bw < 3 {
+            continue;
+        }
+        let mut lit = 0;
+        let mut dark = 0;
+        for y in y0..y1 {
+            for &v in &values[y * width + x0..y * width + x1] {
+                if v >= opts.threshold {
+                    lit += 1;
+                } else if v < opts.threshold / 2 {
+                    dark += 1;
+                }
+            }
+        }
+        let area = bw * bh;
+        // Text is thin strokes on a dark background: neither a solid bright blob nor a
+        // bright patch of image
+        if lit < opts.min_pixels || lit * 10 > area * 6 || dark * 10 < area * 3 {
+            continue;
+        }
+        found.push(RedactRect {
+            x0: (x0 as u32).saturating_sub(opts.padding),
+            y0: (y0 as u32).saturating_sub(opts.padding),
+            x1: (x1 as u32 + opts.padding).min(w),
+            y1: (y1 as u32 + opts.padding).min(h),
+        });
+    }
+    found
+}
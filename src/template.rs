@@ -0,0 +1,112 @@
+//! Named region templates (e.g. "GE Logiq E9 top banner") saved as JSON or TOML, so
+//! the same machine layout can be redacted week after week without redrawing it.
+//!
+//! The format is picked from the file extension: `.toml` is TOML, anything else JSON.
+
+use crate::{BoxError, DCMRedactErrors, Region};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    /// Width and height of the image the regions were drawn on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_size: Option<[u32; 2]>,
+    pub regions: Vec<Region>,
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("toml"))
+}
+
+impl Template {
+    pub fn new(
+        name: impl Into<String>,
+        regions: Vec<Region>,
+        image_size: Option<[u32; 2]>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            image_size,
+            regions,
+        }
+    }
+
+    /// Whether the template was drawn on an image of a different size.
+    pub fn size_mismatch(&self, width: u32, height: u32) -> bool {
+        self.image_size.is_some_and(|s| s != [width, height])
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DCMRedactErrors> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to read template {}", path.display()), e)
+        })?;
+        let invalid = |e: BoxError| {
+            DCMRedactErrors::decode(format!("Invalid template {}", path.display()), e)
+        };
+        if is_toml(path) {
+            toml::from_str(&text).map_err(|e| invalid(Box::new(e)))
+        } else {
+            serde_json::from_str(&text).map_err(|e| invalid(Box::new(e)))
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DCMRedactErrors> {
+        let text = if is_toml(path) {
+            toml::to_string_pretty(self).map_err(|e| -> BoxError { Box::new(e) })
+        } else {
+            serde_json::to_string_pretty(self).map_err(|e| -> BoxError { Box::new(e) })
+        }
+        .map_err(|e| DCMRedactErrors::encode("Failed to encode template", e))?;
+        let write_err =
+            |e| DCMRedactErrors::io(format!("Failed to write template {}", path.display()), e);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(write_err)?;
+        }
+        fs::write(path, text).map_err(write_err)
+    }
+}
+
+/// Where the template manager keeps its templates (`templates` in the config
+/// directory), or `None` if the platform has no config directory.
+pub fn template_dir() -> Option<PathBuf> {
+    crate::config_dir().map(|d| d.join("templates"))
+}
+
+/// File name for a template called `name`: unsafe characters become `_`.
+pub fn template_file_name(name: &str) -> String {
+    let stem: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.json", if stem.is_empty() { "template" } else { &stem })
+}
+
+/// Every readable `.json`/`.toml` template in `dir`, sorted by name. A missing
+/// directory gives an empty list.
+pub fn list_templates(dir: &Path) -> Vec<(PathBuf, Template)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut templates: Vec<(PathBuf, Template)> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("json") || e.eq_ignore_ascii_case("toml"))
+        })
+        .filter_map(|p| Template::load(&p).ok().map(|t| (p, t)))
+        .collect();
+    templates.sort_by_key(|t| t.1.name.to_lowercase());
+    templates
+}
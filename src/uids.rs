@@ -0,0 +1,344 @@
+//! UID bookkeeping: consistent old → new remapping across files, fresh SOP Instance
+//! UIDs, our implementation identity in the file meta group, and an optional CSV log
+//! of old → new UIDs.
+
+use crate::DCMRedactErrors;
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::{FileDicomObject, InMemDicomObject};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Identifies dcm-redact as the implementation that wrote a file (0002,0012).
+pub const IMPLEMENTATION_CLASS_UID: &str = "2.25.154682090676548382369833209923958805209";
+
+/// (0002,0013), at most 16 characters.
+pub const IMPLEMENTATION_VERSION_NAME: &str = concat!("DCMR_", env!("CARGO_PKG_VERSION"));
+
+// SH allows 16 characters: a longer version number must not build
+const _: () = assert!(IMPLEMENTATION_VERSION_NAME.len() <= 16);
+
+/// Top-level UIDs compared between source and output for the mapping log.
+const LOGGED_UIDS: &[(&str, dicom::core::Tag)] = &[
+    ("SOPInstanceUID", tags::SOP_INSTANCE_UID),
+    ("SeriesInstanceUID", tags::SERIES_INSTANCE_UID),
+    ("StudyInstanceUID", tags::STUDY_INSTANCE_UID),
+    ("FrameOfReferenceUID", tags::FRAME_OF_REFERENCE_UID),
+];
+
+/// Generate a fresh UID under the `2.25` (UUID-derived) root.
+pub fn new_uid() -> String {
+    format!("2.25.{}", uuid::Uuid::new_v4().as_u128())
+}
+
+/// Whether `uid` is well formed: 1 to 64 characters, digits and dots only. UIDs read
+/// from files or peers are checked with this before they name a file.
+pub fn is_valid_uid(uid: &str) -> bool {
+    (1..=64).contains(&uid.len()) && uid.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+}
+
+/// Deterministic replacement for `uid`: a `2.25` UID from the salted SHA-1 of `uid`,
+/// so separate runs with the same salt agree without sharing a map.
+pub fn hashed_uid(salt: &str, uid: &str) -> String {
+    let digest = salted_sha1(salt, uid);
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    format!("2.25.{}", u128::from_be_bytes(bytes))
+}
+
+pub(crate) fn salted_sha1(salt: &str, value: &str) -> [u8; 20] {
+    use sha1::{Digest, Sha1};
+    let mut hasher = Sha1::new();
+    hasher.update(salt.as_bytes());
+    hasher.update([0]);
+    hasher.update(value.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Old → new UID map shared by every file saved in a session, so all files of a study
+/// get the same new Study/Series/Frame of Reference UIDs and references between them
+/// stay intact. Clones are handles to the same map.
+///
+/// The map can be persisted with [`UidRemapper::save`] and reloaded with
+/// [`UidRemapper::load`] to keep remapping consistent across runs.
+#[derive(Debug, Clone, Default)]
+pub struct UidRemapper {
+    map: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl UidRemapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replacement for `uid`, generating (and remembering) one on first use.
+    pub fn map(&self, uid: &str) -> String {
+        self.map_with(uid, |_| new_uid())
+    }
+
+    /// Like [`UidRemapper::map`], but a first-time replacement comes from `make`.
+    pub fn map_with(&self, uid: &str, make: impl FnOnce(&str) -> String) -> String {
+        let uid = uid.trim_end_matches(['\0', ' ']);
+        self.map
+            .lock()
+            .unwrap()
+            .entry(uid.to_string())
+            .or_insert_with(|| make(uid))
+            .clone()
+    }
+
+    /// The replacement already assigned to `uid`, if any.
+    pub fn get(&self, uid: &str) -> Option<String> {
+        let uid = uid.trim_end_matches(['\0', ' ']);
+        self.map.lock().unwrap().get(uid).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy of every old → new pair assigned so far.
+    pub fn snapshot(&self) -> HashMap<String, String> {
+        self.map.lock().unwrap().clone()
+    }
+
+    /// Read a map written by [`UidRemapper::save`] (`old,new` per line). A missing
+    /// file gives an empty map, so the same path can be used for the first run.
+    pub fn load(path: &Path) -> Result<Self, DCMRedactErrors> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => {
+                return Err(DCMRedactErrors::io(
+                    format!("Failed to read UID map {}", path.display()),
+                    e,
+                ));
+            }
+        };
+        let mut map = HashMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((old, new)) = line.split_once(',') else {
+                return Err(DCMRedactErrors::ValueError(format!(
+                    "{}:{}: expected old_uid,new_uid",
+                    path.display(),
+                    n + 1
+                )));
+            };
+            map.insert(old.trim().to_string(), new.trim().to_string());
+        }
+        Ok(Self {
+            map: Arc::new(Mutex::new(map)),
+        })
+    }
+
+    /// Write the map as `old,new` lines, sorted for stable diffs.
+    pub fn save(&self, path: &Path) -> Result<(), DCMRedactErrors> {
+        let mut pairs: Vec<_> = self.snapshot().into_iter().collect();
+        pairs.sort();
+        let text: String = pairs
+            .iter()
+            .map(|(old, new)| format!("{old},{new}\n"))
+            .collect();
+        fs::write(path, text).map_err(|e| {
+            DCMRedactErrors::io(format!("Failed to write UID map {}", path.display()), e)
+        })
+    }
+}
+
+fn uid_of(obj: &InMemDicomObject, tag: dicom::core::Tag) -> Option<String> {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|s| s.trim_end_matches(['\0', ' ']).to_string())
+}
+
+/// Replace the SOP Instance UID with `new_uid`.
+pub fn set_sop_instance_uid(obj: &mut FileDicomObject<InMemDicomObject>, new_uid: &str) {
+    obj.put(DataElement::new(
+        tags::SOP_INSTANCE_UID,
+        VR::UI,
+        PrimitiveValue::from(new_uid),
+    ));
+}
+
+/// Bring the file meta group in line with the dataset about to be written: the media
+/// storage UIDs follow the SOP Class/Instance UIDs, and the implementation UID and
+/// version name identify dcm-redact as the writer.
+pub fn stamp_file_meta(obj: &mut FileDicomObject<InMemDicomObject>) {
+    let sop_instance = uid_of(obj, tags::SOP_INSTANCE_UID);
+    let sop_class = uid_of(obj, tags::SOP_CLASS_UID);
+    let meta = obj.meta_mut();
+    if let Some(uid) = sop_instance {
+        meta.media_storage_sop_instance_uid = uid;
+    }
+    if let Some(uid) = sop_class {
+        meta.media_storage_sop_class_uid = uid;
+    }
+    meta.implementation_class_uid = IMPLEMENTATION_CLASS_UID.to_string();
+    meta.implementation_version_name = Some(IMPLEMENTATION_VERSION_NAME.to_string());
+    meta.update_information_group_length();
+}
+
+/// Top-level UIDs that differ between `source` and `written`, as
+/// `(attribute, old, new)`.
+pub fn changed_uids(
+    source: &InMemDicomObject,
+    written: &InMemDicomObject,
+) -> Vec<(&'static str, String, String)> {
+    LOGGED_UIDS
+        .iter()
+        .filter_map(|&(name, tag)| {
+            let (old, new) = (uid_of(source, tag)?, uid_of(written, tag)?);
+            (old != new).then_some((name, old, new))
+        })
+        .collect()
+}
+
+/// Appends `output,attribute,old_uid,new_uid` rows to a CSV file, one per UID that
+/// changed between a source object and what was written.
+#[derive(Debug, Clone)]
+pub struct UidMappingLog {
+    path: PathBuf,
+}
+
+impl UidMappingLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Log every changed top-level UID of `source` → `written`, saved at `output`.
+    pub fn record(
+        &self,
+        output: &Path,
+        source: &InMemDicomObject,
+        written: &InMemDicomObject,
+    ) -> Result<(), DCMRedactErrors> {
+        let io_err = |e: std::io::Error| {
+            DCMRedactErrors::io(
+                format!("Failed to write UID mapping file {}", self.path.display()),
+                e,
+            )
+        };
+        let mut rows = String::new();
+        for (name, old, new) in changed_uids(source, written) {
+            rows += &format!("{},{name},{old},{new}\n", csv_field(output));
+        }
+        // Parallel batch runs log from several threads; keep each file's rows together
+        static APPEND: Mutex<()> = Mutex::new(());
+        let _guard = APPEND.lock().unwrap_or_else(|e| e.into_inner());
+        let is_new = !self.path.exists();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(io_err)?;
+        if is_new {
+            rows.insert_str(0, "output,attribute,old_uid,new_uid\n");
+        }
+        file.write_all(rows.as_bytes()).map_err(io_err)
+    }
+}
+
+/// Quote a path for CSV if it contains a separator or quote.
+fn csv_field(path: &Path) -> String {
+    let s = path.display().to_string();
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uids_are_digits_and_dots_up_to_64_characters() {
+        assert!(is_valid_uid("1.2.840.10008.5.1.4.1.1.2"));
+        assert!(is_valid_uid(&new_uid()));
+        assert!(is_valid_uid(&"1".repeat(64)));
+        assert!(!is_valid_uid(""));
+        assert!(!is_valid_uid(&"1".repeat(65)));
+        assert!(!is_valid_uid("1.2.3\0"));
+        assert!(!is_valid_uid("../1.2.3"));
+        assert!(!is_valid_uid("1.2/3"));
+    }
+
+    #[test]
+    fn hashed_uids_are_stable_and_salted() {
+        let uid = hashed_uid("salt", "1.2.3");
+        assert!(uid.starts_with("2.25."));
+        assert!(is_valid_uid(&uid));
+        assert_eq!(uid, hashed_uid("salt", "1.2.3"));
+        assert_ne!(uid, hashed_uid("pepper", "1.2.3"));
+        assert_ne!(uid, hashed_uid("salt", "1.2.4"));
+    }
+
+    #[test]
+    fn remapping_is_consistent_and_ignores_padding() {
+        let remapper = UidRemapper::new();
+        let new = remapper.map("1.2.3");
+        assert_ne!(new, "1.2.3");
+        assert_eq!(remapper.map("1.2.3\0"), new);
+        assert_eq!(remapper.get("1.2.3 "), Some(new.clone()));
+        assert_ne!(remapper.map("1.2.4"), new);
+        assert_eq!(remapper.len(), 2);
+    }
+
+    #[test]
+    fn clones_share_the_map() {
+        let remapper = UidRemapper::new();
+        let clone = remapper.clone();
+        let new = clone.map("1.2.3");
+        assert_eq!(remapper.get("1.2.3"), Some(new));
+        assert_eq!(
+            remapper.map_with("1.2.3", |_| "9.9".to_string()),
+            clone.map("1.2.3")
+        );
+    }
+
+    #[test]
+    fn maps_survive_a_save_and_load() {
+        let path = std::env::temp_dir().join(format!("dcm-redact-uids-{}.csv", new_uid()));
+        let remapper = UidRemapper::new();
+        let a = remapper.map("1.2.3");
+        let b = remapper.map("1.2.4");
+        remapper.save(&path).unwrap();
+        let loaded = UidRemapper::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.get("1.2.3"), Some(a));
+        assert_eq!(loaded.get("1.2.4"), Some(b));
+        assert!(UidRemapper::load(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field(Path::new("out/a.dcm")), "out/a.dcm");
+        assert_eq!(csv_field(Path::new("a,\"b\".dcm")), "\"a,\"\"b\"\".dcm\"");
+    }
+
+    #[test]
+    fn version_name_fits_its_vr() {
+        assert_eq!(
+            IMPLEMENTATION_VERSION_NAME,
+            format!("DCMR_{}", env!("CARGO_PKG_VERSION"))
+        );
+        assert!(is_valid_uid(IMPLEMENTATION_CLASS_UID));
+    }
+}
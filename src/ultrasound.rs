@@ -0,0 +1,64 @@
+//! Masking ultrasound images down to their echo regions.
+//!
+//! SequenceOfUltrasoundRegions says where on the image the acquired data (2D, M-mode,
+//! spectral Doppler, waveforms) lies. Vendor banners, patient demographics and
+//! measurement text sit around it, so blacking out everything outside those regions
+//! removes them in one go.
+
+use crate::RedactRect;
+use crate::shape::{Shape, outside};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+/// RegionSpatialFormat of a region holding no image data.
+const SPATIAL_FORMAT_NONE: u16 = 0;
+/// RegionSpatialFormat of a graphics region (annotations, not echo data).
+const SPATIAL_FORMAT_GRAPHICS: u16 = 5;
+
+/// The echo regions of `obj` that lie on a `width`×`height` image, as exclusive
+/// rectangles. Regions of spatial format none or graphics are left out.
+pub fn echo_regions(obj: &InMemDicomObject, width: u32, height: u32) -> Vec<RedactRect> {
+    let Some(items) = obj
+        .element(tags::SEQUENCE_OF_ULTRASOUND_REGIONS)
+        .ok()
+        .and_then(|e| e.items())
+    else {
+        return Vec::new();
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let int = |tag| item.element(tag).ok().and_then(|e| e.to_int::<u32>().ok());
+            let format = item
+                .element(tags::REGION_SPATIAL_FORMAT)
+                .ok()
+                .and_then(|e| e.to_int::<u16>().ok());
+            if matches!(format, Some(SPATIAL_FORMAT_NONE | SPATIAL_FORMAT_GRAPHICS)) {
+                return None;
+            }
+            // The max corner is inclusive
+            let r = RedactRect {
+                x0: int(tags::REGION_LOCATION_MIN_X0)?.min(width),
+                y0: int(tags::REGION_LOCATION_MIN_Y0)?.min(height),
+                x1: int(tags::REGION_LOCATION_MAX_X1)?
+                    .saturating_add(1)
+                    .min(width),
+                y1: int(tags::REGION_LOCATION_MAX_Y1)?
+                    .saturating_add(1)
+                    .min(height),
+            };
+            (r.x0 < r.x1 && r.y0 < r.y1).then_some(r)
+        })
+        .collect()
+}
+
+/// What to black out of a `width`×`height` ultrasound image `obj` to keep only its
+/// echo regions; empty if it has none.
+pub fn mask(obj: &InMemDicomObject, width: u32, height: u32) -> Vec<RedactRect> {
+    let keep = echo_regions(obj, width, height);
+    if keep.is_empty() {
+        return Vec::new();
+    }
+    let keep: Vec<Shape> = keep.into_iter().map(Shape::Rect).collect();
+    outside(&keep, width, height)
+}
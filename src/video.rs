@@ -0,0 +1,284 @@
+//! MPEG-2, MPEG-4 AVC/H.264 and HEVC/H.265 video transfer syntaxes, as used by some
+//! ultrasound and endoscopy objects, via the `ffmpeg` command line tool.
+//!
+//! The encapsulated bitstream is handed to ffmpeg as-is and comes back as raw RGB
+//! frames, which are redacted like any other. On save the frames are piped back
+//! through ffmpeg and encapsulated as a single elementary stream fragment.
+
+use crate::{DCMRedactErrors, Frame, Rgb8Image};
+use dicom::core::value::PixelFragmentSequence;
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::{FileDicomObject, InMemDicomObject};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Frame rate assumed when the header gives none.
+const DEFAULT_FRAME_RATE: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Mpeg2,
+    H264,
+    Hevc,
+    /// HEVC Main 10 profile.
+    Hevc10,
+}
+
+impl Codec {
+    /// ffmpeg encoder options, and the elementary stream format written.
+    fn encoder_args(self) -> &'static [&'static str] {
+        match self {
+            Codec::Mpeg2 => &["-c:v", "mpeg2video", "-q:v", "2", "-f", "mpeg2video"],
+            Codec::H264 => &[
+                "-c:v",
+                "libx264",
+                "-profile:v",
+                "high",
+                "-pix_fmt",
+                "yuv420p",
+                "-crf",
+                "18",
+                "-f",
+                "h264",
+            ],
+            Codec::Hevc => &[
+                "-c:v", "libx265", "-pix_fmt", "yuv420p", "-crf", "20", "-f", "hevc",
+            ],
+            Codec::Hevc10 => &[
+                "-c:v",
+                "libx265",
+                "-pix_fmt",
+                "yuv420p10le",
+                "-crf",
+                "20",
+                "-f",
+                "hevc",
+            ],
+        }
+    }
+}
+
+/// The video codec of transfer syntax `uid`, or `None` if it isn't a video one.
+pub fn codec(uid: &str) -> Option<Codec> {
+    match uid.trim_end_matches('\0') {
+        // MPEG2 Main Profile @ Main Level, @ High Level
+        "1.2.840.10008.1.2.4.100" | "1.2.840.10008.1.2.4.101" => Some(Codec::Mpeg2),
+        // MPEG-4 AVC/H.264 High Profile @ Level 4.1, BD-compatible, @ Level 4.2 2D/3D,
+        // Stereo High Profile
+        "1.2.840.10008.1.2.4.102"
+        | "1.2.840.10008.1.2.4.103"
+        | "1.2.840.10008.1.2.4.104"
+        | "1.2.840.10008.1.2.4.105"
+        | "1.2.840.10008.1.2.4.106" => Some(Codec::H264),
+        "1.2.840.10008.1.2.4.107" => Some(Codec::Hevc), // HEVC/H.265 Main Profile
+        "1.2.840.10008.1.2.4.108" => Some(Codec::Hevc10), // HEVC/H.265 Main 10 Profile
+        _ => None,
+    }
+}
+
+/// The ffmpeg executable: `$DCM_REDACT_FFMPEG` or `ffmpeg`.
+fn ffmpeg() -> PathBuf {
+    std::env::var_os("DCM_REDACT_FFMPEG").map_or_else(|| PathBuf::from("ffmpeg"), PathBuf::from)
+}
+
+fn run_failed(e: std::io::Error) -> DCMRedactErrors {
+    DCMRedactErrors::io(
+        format!(
+            "Failed to run {} (is ffmpeg installed?)",
+            ffmpeg().display()
+        ),
+        e,
+    )
+}
+
+fn ffmpeg_failed(stderr: &[u8]) -> DCMRedactErrors {
+    DCMRedactErrors::ValueError(format!(
+        "ffmpeg failed: {}",
+        String::from_utf8_lossy(stderr).trim()
+    ))
+}
+
+fn temp_path(ext: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dcm-redact-video-{}.{ext}", uuid::Uuid::new_v4()))
+}
+
+fn dimension(obj: &InMemDicomObject, tag: dicom::core::Tag) -> Result<u32, DCMRedactErrors> {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_int().ok())
+        .ok_or_else(|| DCMRedactErrors::ValueError(format!("Missing or invalid {tag} in video")))
+}
+
+/// Frames per second from FrameTime (ms), CineRate or RecommendedDisplayFrameRate.
+pub fn frame_rate(obj: &InMemDicomObject) -> f64 {
+    let float = |tag| {
+        obj.element(tag)
+            .ok()
+            .and_then(|e| e.to_float64().ok())
+            .filter(|v| v.is_finite() && *v > 0.0)
+    };
+    float(tags::FRAME_TIME)
+        .map(|ms| 1000.0 / ms)
+        .or_else(|| float(tags::CINE_RATE))
+        .or_else(|| float(tags::RECOMMENDED_DISPLAY_FRAME_RATE))
+        .unwrap_or(DEFAULT_FRAME_RATE)
+}
+
+/// Decode the encapsulated video stream of `obj` into RGB frames.
+pub fn decode_frames(obj: &InMemDicomObject) -> Result<Vec<Frame>, DCMRedactErrors> {
+    let (columns, rows) = (dimension(obj, tags::COLUMNS)?, dimension(obj, tags::ROWS)?);
+    let stream: Vec<u8> = obj
+        .element(tags::PIXEL_DATA)
+        .ok()
+        .and_then(|e| e.fragments())
+        .ok_or_else(|| {
+            DCMRedactErrors::ValueError("Video pixel data is not encapsulated".to_string())
+        })?
+        .concat();
+
+    // A file rather than a pipe, so ffmpeg can seek in containers that need it
+    let input = temp_path("bin");
+    std::fs::write(&input, &stream)
+        .map_err(|e| DCMRedactErrors::io("Failed to write ffmpeg input", e))?;
+    let output = Command::new(ffmpeg())
+        .args(["-v", "error", "-i"])
+        .arg(&input)
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+        .output();
+    let _ = std::fs::remove_file(&input);
+    let output = output.map_err(run_failed)?;
+    if !output.status.success() {
+        return Err(ffmpeg_failed(&output.stderr));
+    }
+
+    let frame_len = columns as usize * rows as usize * 3;
+    let frames: Vec<Frame> = output
+        .stdout
+        .chunks_exact(frame_len)
+        .filter_map(|bytes| Rgb8Image::from_raw(columns, rows, bytes.to_vec()))
+        .map(Frame::Rgb)
+        .collect();
+    if frames.is_empty() {
+        return Err(DCMRedactErrors::ValueError(format!(
+            "ffmpeg returned no {columns}x{rows} frames from the video"
+        )));
+    }
+    Ok(frames)
+}
+
+fn encode(frames: &[Frame], codec: Codec, fps: f64, path: &Path) -> Result<(), DCMRedactErrors> {
+    let (columns, rows) = frames[0].dimensions();
+    let mut child = Command::new(ffmpeg())
+        .args(["-v", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .args(["-s", &format!("{columns}x{rows}"), "-r", &fps.to_string()])
+        .args(["-i", "-"])
+        .args(codec.encoder_args())
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(run_failed)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        for frame in frames {
+            let Frame::Rgb(img) = frame else {
+                continue;
+            };
+            // A closed pipe means ffmpeg gave up; its stderr says why
+            if stdin.write_all(img.as_raw()).is_err() {
+                break;
+            }
+        }
+    }
+    let output = child.wait_with_output().map_err(run_failed)?;
+    if !output.status.success() {
+        return Err(ffmpeg_failed(&output.stderr));
+    }
+    Ok(())
+}
+
+/// Encode `frames` with `codec` and put them in `obj` as encapsulated pixel data,
+/// with the pixel module video requires (8-bit YBR_PARTIAL_420).
+pub fn put_video_pixel_data(
+    obj: &mut FileDicomObject<InMemDicomObject>,
+    frames: &[Frame],
+    codec: Codec,
+) -> Result<(), DCMRedactErrors> {
+    if !frames.first().is_some_and(Frame::is_color) {
+        return Err(DCMRedactErrors::ValueError(
+            "Only color images can be encoded as video".to_string(),
+        ));
+    }
+    let output = temp_path("video");
+    let encoded = encode(frames, codec, frame_rate(obj), &output).and_then(|()| {
+        std::fs::read(&output).map_err(|e| DCMRedactErrors::io("Failed to read ffmpeg output", e))
+    });
+    let _ = std::fs::remove_file(&output);
+    let stream = encoded?;
+
+    crate::set_rgb_pixel_module(obj);
+    obj.put(DataElement::new(
+        tags::PHOTOMETRIC_INTERPRETATION,
+        VR::CS,
+        PrimitiveValue::from("YBR_PARTIAL_420"),
+    ));
+    obj.put(DataElement::new(
+        tags::NUMBER_OF_FRAMES,
+        VR::IS,
+        PrimitiveValue::from(frames.len().to_string()),
+    ));
+    obj.put(DataElement::new(
+        tags::PIXEL_DATA,
+        VR::OB,
+        PixelFragmentSequence::new(Vec::<u32>::new(), vec![stream]),
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::object::mem::InMemElement;
+
+    fn rate(tag: dicom::core::Tag, vr: VR, value: &str) -> InMemElement {
+        DataElement::new(tag, vr, PrimitiveValue::from(value))
+    }
+
+    #[test]
+    fn video_transfer_syntaxes_map_to_their_codec() {
+        assert_eq!(codec("1.2.840.10008.1.2.4.100"), Some(Codec::Mpeg2));
+        assert_eq!(codec("1.2.840.10008.1.2.4.102\0"), Some(Codec::H264));
+        assert_eq!(codec("1.2.840.10008.1.2.4.106"), Some(Codec::H264));
+        assert_eq!(codec("1.2.840.10008.1.2.4.107"), Some(Codec::Hevc));
+        assert_eq!(codec("1.2.840.10008.1.2.4.108"), Some(Codec::Hevc10));
+        // JPEG baseline
+        assert_eq!(codec("1.2.840.10008.1.2.4.50"), None);
+    }
+
+    #[test]
+    fn main_10_keeps_ten_bits() {
+        assert!(Codec::Hevc10.encoder_args().contains(&"yuv420p10le"));
+        assert!(!Codec::Hevc.encoder_args().contains(&"yuv420p10le"));
+    }
+
+    #[test]
+    fn frame_time_comes_before_the_cine_rate() {
+        let obj = InMemDicomObject::from_element_iter([
+            rate(tags::FRAME_TIME, VR::DS, "40"),
+            rate(tags::CINE_RATE, VR::IS, "10"),
+        ]);
+        assert_eq!(frame_rate(&obj), 25.0);
+
+        let obj = InMemDicomObject::from_element_iter([
+            rate(tags::FRAME_TIME, VR::DS, "0"),
+            rate(tags::CINE_RATE, VR::IS, "10"),
+        ]);
+        assert_eq!(frame_rate(&obj), 10.0);
+        assert_eq!(
+            frame_rate(&InMemDicomObject::new_empty()),
+            DEFAULT_FRAME_RATE
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Grayscale display pipeline: stored values → Modality LUT (rescale slope/intercept)
+//! → VOI (window or VOI LUT) → 8-bit display values.
+//!
+//! Only the on-screen copy goes through this; redaction and saving always work on the
+//! stored pixel values.
+
+use dicom::core::VR;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+/// Modality LUT as RescaleSlope/RescaleIntercept: stored value → modality value
+/// (e.g. Hounsfield units).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rescale {
+    pub slope: f64,
+    pub intercept: f64,
+}
+
+impl Default for Rescale {
+    fn default() -> Self {
+        Self {
+            slope: 1.0,
+            intercept: 0.0,
+        }
+    }
+}
+
+impl Rescale {
+    pub fn apply(&self, stored: f64) -> f64 {
+        stored * self.slope + self.intercept
+    }
+}
+
+/// A linear VOI window, in modality units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub center: f64,
+    pub width: f64,
+}
+
+impl Window {
+    pub fn new(center: f64, width: f64) -> Self {
+        Self {
+            center,
+            width: width.max(1.0),
+        }
+    }
+
+    /// The whole range of a `bits_stored`-bit unsigned stored value, after `rescale`.
+    pub fn full_range(bits_stored: u16, rescale: Rescale) -> Self {
+        let max = ((1u64 << bits_stored.clamp(1, 16)) - 1) as f64;
+        Self::from_min_max(rescale.apply(0.0), rescale.apply(max))
+    }
+
+    /// The smallest window covering `a..=b` (in either order).
+    pub fn from_min_max(a: f64, b: f64) -> Self {
+        let (min, max) = (a.min(b), a.max(b));
+        Self::new((min + max + 1.0) / 2.0, max - min + 1.0)
+    }
+
+    /// The linear VOI function of PS3.3 C.11.2.1.2.1, scaled to 0-255.
+    pub fn apply(&self, value: f64) -> u8 {
+        let (c, w) = (self.center - 0.5, self.width - 1.0);
+        if w <= 0.0 {
+            return if value <= c { 0 } else { 255 };
+        }
+        (((value - c) / w + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+/// How often each frame value occurs in a grayscale frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u64>, // one per 16-bit frame value
+    total: u64,
+}
+
+impl Histogram {
+    pub fn new(values: impl IntoIterator<Item = u16>) -> Self {
+        let mut counts = vec![0u64; 1 << 16];
+        let mut total = 0;
+        for v in values {
+            counts[v as usize] += 1;
+            total += 1;
+        }
+        Self { counts, total }
+    }
+
+    /// Number of values counted.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The lowest and highest value present, or `None` if there are none.
+    pub fn range(&self) -> Option<(u16, u16)> {
+        let lo = self.counts.iter().position(|&c| c > 0)?;
+        let hi = self.counts.iter().rposition(|&c| c > 0)?;
+        Some((lo as u16, hi as u16))
+    }
+
+    /// The lowest value with at least `percent` (0-100) of the values at or below it.
+    pub fn percentile(&self, percent: f64) -> Option<u16> {
+        if self.total == 0 {
+            return None;
+        }
+        let target = ((percent.clamp(0.0, 100.0) / 100.0 * self.total as f64).ceil() as u64)
+            .clamp(1, self.total);
+        let mut seen = 0;
+        self.counts
+            .iter()
+            .position(|&c| {
+                seen += c;
+                seen >= target
+            })
+            .map(|v| v as u16)
+    }
+
+    /// The counts of `lo..=hi` gathered into `n` bins of equal width.
+    pub fn bins(&self, lo: u16, hi: u16, n: usize) -> Vec<u64> {
+        let n = n.max(1);
+        let mut bins = vec![0; n];
+        let span = (hi.max(lo) - lo) as usize + 1;
+        for (i, &c) in self.counts[lo as usize..=hi.max(lo) as usize]
+            .iter()
+            .enumerate()
+        {
+            bins[i * n / span] += c;
+        }
+        bins
+    }
+}
+
+impl Window {
+    /// The window spanning the `low` to `high` percentiles (0-100) of a frame's
+    /// values, after `rescale`: stretching only the bulk of the values, so a few very
+    /// bright or dark pixels don't flatten the contrast of the rest.
+    pub fn from_percentiles(
+        histogram: &Histogram,
+        low: f64,
+        high: f64,
+        rescale: Rescale,
+    ) -> Option<Self> {
+        let lo = histogram.percentile(low.min(high))?;
+        let hi = histogram.percentile(low.max(high))?;
+        Some(Self::from_min_max(
+            rescale.apply(lo as f64),
+            rescale.apply(hi as f64),
+        ))
+    }
+}
+
+/// A VOI LUT Sequence item: a table from modality values to display values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiLut {
+    /// Modality value mapped by `data[0]`; lower values use the first entry, higher
+    /// ones the last.
+    pub first_mapped: i32,
+    pub bits: u16,
+    pub data: Vec<u16>,
+}
+
+impl VoiLut {
+    pub fn apply(&self, value: f64) -> u8 {
+        let Some(last) = self.data.len().checked_sub(1) else {
+            return 0;
+        };
+        let i = (value.round() as i64 - self.first_mapped as i64).clamp(0, last as i64);
+        let max = ((1u32 << self.bits.clamp(1, 16)) - 1) as f64;
+        (self.data[i as usize] as f64 / max * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Voi {
+    Window(Window),
+    Lut(VoiLut),
+}
+
+impl Voi {
+    pub fn apply(&self, value: f64) -> u8 {
+        match self {
+            Voi::Window(w) => w.apply(value),
+            Voi::Lut(lut) => lut.apply(value),
+        }
+    }
+}
+
+/// A VOI stored in the dataset, with its explanation (or a generated name).
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiPreset {
+    pub label: String,
+    pub voi: Voi,
+}
+
+/// A 0-255 display value for every 16-bit stored value.
+pub fn display_lut(rescale: Rescale, voi: &Voi) -> Vec<u8> {
+    (0..=u16::MAX)
+        .map(|v| voi.apply(rescale.apply(v as f64)))
+        .collect()
+}
+
+/// Where the pixel value transformation and VOI attributes live: the dataset itself,
+/// then (for enhanced multi-frame objects) the shared functional groups.
+fn sources(obj: &InMemDicomObject) -> Vec<&InMemDicomObject> {
+    let mut sources = vec![obj];
+    if let Some(shared) = obj
+        .element(tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE)
+        .ok()
+        .and_then(|e| e.items())
+        .and_then(|items| items.first())
+    {
+        for seq in [
+            tags::PIXEL_VALUE_TRANSFORMATION_SEQUENCE,
+            tags::FRAME_VOILUT_SEQUENCE,
+        ] {
+            if let Some(item) = shared
+                .element(seq)
+                .ok()
+                .and_then(|e| e.items())
+                .and_then(|items| items.first())
+            {
+                sources.push(item);
+            }
+        }
+    }
+    sources
+}
+
+/// RescaleSlope/RescaleIntercept, or the identity if absent.
+pub fn rescale(obj: &InMemDicomObject) -> Rescale {
+    for source in sources(obj) {
+        let value = |tag| source.element(tag).ok().and_then(|e| e.to_float64().ok());
+        if let (Some(slope), Some(intercept)) =
+            (value(tags::RESCALE_SLOPE), value(tags::RESCALE_INTERCEPT))
+            && slope != 0.0
+        {
+            return Rescale { slope, intercept };
+        }
+    }
+    Rescale::default()
+}
+
+fn voi_lut(item: &InMemDicomObject) -> Option<VoiLut> {
+    let descriptor = item
+        .element(tags::LUT_DESCRIPTOR)
+        .ok()?
+        .to_multi_int::<i32>()
+        .ok()?;
+    let [entries, first_mapped, bits] = <[i32; 3]>::try_from(descriptor).ok()?;
+    let data_elem = item.element(tags::LUT_DATA).ok()?;
+    let data: Vec<u16> = if data_elem.vr() == VR::US {
+        data_elem.to_multi_int().ok()?
+    } else {
+        data_elem
+            .to_bytes()
+            .ok()?
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect()
+    };
+    // 0 entries means 65536; the first value is unsigned even for signed pixels
+    let entries = if entries == 0 {
+        65536
+    } else {
+        entries as u16 as usize
+    };
+    Some(VoiLut {
+        first_mapped,
+        bits: bits as u16,
+        data: data.into_iter().take(entries).collect(),
+    })
+}
+
+/// Every VOI stored in the dataset: each WindowCenter/WindowWidth pair, then each
+/// VOI LUT Sequence item, in dataset order.
+pub fn presets(obj: &InMemDicomObject) -> Vec<VoiPreset> {
+    let mut presets = Vec::new();
+    for source in sources(obj) {
+        let floats = |tag| {
+            source
+                .element(tag)
+                .ok()
+                .and_then(|e| e.to_multi_float64().ok())
+                .unwrap_or_default()
+        };
+        let explanations: Vec<String> = source
+            .element(tags::WINDOW_CENTER_WIDTH_EXPLANATION)
+            .ok()
+            .and_then(|e| e.to_multi_str().ok())
+            .map(|s| s.iter().map(|s| s.trim().to_string()).collect())
+            .unwrap_or_default();
+        let centers = floats(tags::WINDOW_CENTER);
+        let widths = floats(tags::WINDOW_WIDTH);
+        for (i, (&center, &width)) in centers.iter().zip(&widths).enumerate() {
+            let window = Window::new(center, width);
+            let label = match explanations.get(i) {
+                Some(e) if !e.is_empty() => format!("{e} ({center}/{width})"),
+                _ => format!("Window {center}/{width}"),
+            };
+            presets.push(VoiPreset {
+                label,
+                voi: Voi::Window(window),
+            });
+        }
+
+        let items = source
+            .element(tags::VOILUT_SEQUENCE)
+            .ok()
+            .and_then(|e| e.items());
+        for (i, item) in items.into_iter().flatten().enumerate() {
+            let Some(lut) = voi_lut(item) else {
+                continue;
+            };
+            let label = item
+                .element(tags::LUT_EXPLANATION)
+                .ok()
+                .and_then(|e| e.to_str().ok())
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| format!("VOI LUT {}", i + 1));
+            presets.push(VoiPreset {
+                label,
+                voi: Voi::Lut(lut),
+            });
+        }
+    }
+    presets
+}
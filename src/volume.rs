@@ -0,0 +1,460 @@
+//! Series as volumes: the frames of a series stacked by position, and multi-planar
+//! reformats (MPR) through them.
+//!
+//! The slices keep their own pixels; a [`Stack`] only records their order and the
+//! voxel grid they form, so reformats are read from, and regions drawn on them go back
+//! into, the slices themselves as ordinary [`Region`]s. That way fills, the audit log,
+//! sidecars and SEG work on them as on any other region.
+
+use crate::{DCMRedactErrors, Frame, Gray16Image, RedactRect, Redactor, Region, Shape};
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use std::collections::BTreeSet;
+
+pub type Vec3 = [f64; 3];
+
+pub fn dot(a: Vec3, b: Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Where a frame lies in the patient: ImagePositionPatient, the row and column
+/// directions of ImageOrientationPatient, and PixelSpacing (between rows, between
+/// columns).
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    position: Vec3,
+    row: Vec3,
+    col: Vec3,
+    spacing: [f64; 2],
+}
+
+pub(crate) fn floats(obj: &InMemDicomObject, tag: Tag) -> Option<Vec<f64>> {
+    obj.element(tag).ok()?.to_multi_float64().ok()
+}
+
+/// The `macro_tag` item of frame `i`'s functional groups, or of the shared ones.
+pub(crate) fn functional_group(
+    obj: &InMemDicomObject,
+    i: usize,
+    macro_tag: Tag,
+) -> Option<&InMemDicomObject> {
+    let group = |sequence: Tag, n: usize| {
+        obj.element(sequence)
+            .ok()?
+            .items()?
+            .get(n)?
+            .element(macro_tag)
+            .ok()?
+            .items()?
+            .first()
+    };
+    group(tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE, i)
+        .or_else(|| group(tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE, 0))
+}
+
+/// The plane of frame `i` of `obj`, from its functional groups (enhanced multi-frame
+/// images) or else the top-level attributes.
+fn plane(obj: &InMemDicomObject, i: usize) -> Option<Plane> {
+    let attr = |macro_tag, tag| {
+        functional_group(obj, i, macro_tag)
+            .and_then(|g| floats(g, tag))
+            .or_else(|| floats(obj, tag))
+    };
+    let position = attr(tags::PLANE_POSITION_SEQUENCE, tags::IMAGE_POSITION_PATIENT)?;
+    let orientation = attr(
+        tags::PLANE_ORIENTATION_SEQUENCE,
+        tags::IMAGE_ORIENTATION_PATIENT,
+    )?;
+    let spacing = attr(tags::PIXEL_MEASURES_SEQUENCE, tags::PIXEL_SPACING)?;
+    if position.len() != 3
+        || orientation.len() != 6
+        || spacing.len() != 2
+        || spacing.iter().any(|&s| s <= 0.0)
+    {
+        return None;
+    }
+    Some(Plane {
+        position: [position[0], position[1], position[2]],
+        row: [orientation[0], orientation[1], orientation[2]],
+        col: [orientation[3], orientation[4], orientation[5]],
+        spacing: [spacing[0], spacing[1]],
+    })
+}
+
+/// The frames of a series in order along their normal, as a voxel grid: x along the
+/// rows, y down the columns, z across the slices.
+#[derive(Debug, Clone)]
+pub struct Stack {
+    /// (redactor, frame) of every slice, from the lowest position along the normal.
+    pub slices: Vec<(usize, usize)>,
+    /// Voxels along x, y and z.
+    pub dims: [usize; 3],
+    /// Voxel size along x, y and z, in mm.
+    pub spacing: [f64; 3],
+    /// Directions of x, y and z in the patient (LPS).
+    pub dirs: [Vec3; 3],
+}
+
+/// Stack every frame of `slices` (one series) into a volume.
+///
+/// Fails unless the slices are grayscale DICOM of one size, orientation and pixel
+/// spacing, each with a position, at two or more distinct positions.
+pub fn stack(slices: &[Redactor]) -> Result<Stack, DCMRedactErrors> {
+    let invalid = |why: &str| DCMRedactErrors::ValueError(format!("Not a volume: {why}"));
+    let mut found = Vec::new();
+    let mut first: Option<Plane> = None;
+    for (r, redactor) in slices.iter().enumerate() {
+        let Some(dcm) = redactor.dicom() else {
+            return Err(invalid("not a DICOM series"));
+        };
+        if redactor.tiled().is_some() || redactor.frame(0).is_color() {
+            return Err(invalid("not a grayscale series"));
+        }
+        if redactor.dimensions() != slices[0].dimensions() {
+            return Err(invalid("the slices differ in size"));
+        }
+        for f in 0..redactor.frame_count() {
+            let plane = plane(dcm, f)
+                .ok_or_else(|| invalid("a slice has no position, orientation or spacing"))?;
+            let reference = *first.get_or_insert(plane);
+            let same = |a: f64, b: f64| (a - b).abs() < 1e-3 * a.abs().max(1.0);
+            if dot(plane.row, reference.row) < 0.999
+                || dot(plane.col, reference.col) < 0.999
+                || !same(plane.spacing[0], reference.spacing[0])
+                || !same(plane.spacing[1], reference.spacing[1])
+            {
+                return Err(invalid("the slices differ in orientation or spacing"));
+            }
+            let depth = dot(plane.position, cross(reference.row, reference.col));
+            found.push((r, f, depth));
+        }
+    }
+    let Some(plane) = first else {
+        return Err(invalid("no slices"));
+    };
+    if found.len() < 2 {
+        return Err(invalid("a single slice"));
+    }
+    found.sort_by(|a, b| a.2.total_cmp(&b.2));
+    let thickness = (found[found.len() - 1].2 - found[0].2) / (found.len() - 1) as f64;
+    if thickness <= 0.0 {
+        return Err(invalid("the slices all lie at the same position"));
+    }
+    let (width, height) = slices[0].dimensions();
+    Ok(Stack {
+        dims: [width as usize, height as usize, found.len()],
+        slices: found.into_iter().map(|(r, f, _)| (r, f)).collect(),
+        spacing: [plane.spacing[1], plane.spacing[0], thickness],
+        dirs: [plane.row, plane.col, cross(plane.row, plane.col)],
+    })
+}
+
+impl Stack {
+    pub fn index(&self, c: [usize; 3]) -> usize {
+        (c[2] * self.dims[1] + c[1]) * self.dims[0] + c[0]
+    }
+
+    pub fn coords(&self, i: usize) -> [usize; 3] {
+        let plane = self.dims[0] * self.dims[1];
+        [i % self.dims[0], i % plane / self.dims[0], i / plane]
+    }
+
+    /// Every voxel coordinate along `axis` through the line at (`p`, `q`) on the other
+    /// two axes, in order, starting from the high end if `from_high`.
+    pub fn line(&self, axis: usize, p: usize, q: usize, from_high: bool) -> Vec<[usize; 3]> {
+        let (a, b) = match axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        let mut coords: Vec<[usize; 3]> = (0..self.dims[axis])
+            .map(|t| {
+                let mut c = [0; 3];
+                (c[axis], c[a], c[b]) = (t, p, q);
+                c
+            })
+            .collect();
+        if from_high {
+            coords.reverse();
+        }
+        coords
+    }
+
+    /// The sizes of the two axes other than `axis`.
+    pub fn across(&self, axis: usize) -> (usize, usize) {
+        match axis {
+            0 => (self.dims[1], self.dims[2]),
+            1 => (self.dims[0], self.dims[2]),
+            _ => (self.dims[0], self.dims[1]),
+        }
+    }
+}
+
+/// A plane of a multi-planar reformat, named as for an axial series: the acquired
+/// slices, and the two planes across them. Reformats show the last slice on top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MprPlane {
+    /// The slices as acquired (z fixed).
+    #[default]
+    Axial,
+    /// Across the slices along the rows (y fixed).
+    Coronal,
+    /// Across the slices down the columns (x fixed).
+    Sagittal,
+}
+
+impl MprPlane {
+    pub const ALL: [MprPlane; 3] = [MprPlane::Axial, MprPlane::Coronal, MprPlane::Sagittal];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MprPlane::Axial => "Axial",
+            MprPlane::Coronal => "Coronal",
+            MprPlane::Sagittal => "Sagittal",
+        }
+    }
+
+    /// The volume axis the plane is fixed on.
+    fn axis(self) -> usize {
+        match self {
+            MprPlane::Axial => 2,
+            MprPlane::Coronal => 1,
+            MprPlane::Sagittal => 0,
+        }
+    }
+}
+
+impl Stack {
+    /// Positions of `plane` through the volume.
+    pub fn positions(&self, plane: MprPlane) -> usize {
+        self.dims[plane.axis()]
+    }
+
+    /// Rows given to each slice in reformats of `plane`, so their voxels look square
+    /// however thick the slices are; 1 for the acquired slices.
+    pub fn stretch(&self, plane: MprPlane) -> u32 {
+        let across = match plane {
+            MprPlane::Axial => return 1,
+            MprPlane::Coronal => self.spacing[0],
+            MprPlane::Sagittal => self.spacing[1],
+        };
+        (self.spacing[2] / across).round().clamp(1.0, 16.0) as u32
+    }
+
+    /// (width, height) of reformats of `plane`.
+    pub fn view_size(&self, plane: MprPlane) -> (u32, u32) {
+        let rows = || (self.dims[2] as u32) * self.stretch(plane);
+        match plane {
+            MprPlane::Axial => (self.dims[0] as u32, self.dims[1] as u32),
+            MprPlane::Coronal => (self.dims[0] as u32, rows()),
+            MprPlane::Sagittal => (self.dims[1] as u32, rows()),
+        }
+    }
+
+    /// The slice shown on row `row` of a reformat of `plane` (not the acquired one).
+    pub fn slice_at_row(&self, plane: MprPlane, row: u32) -> usize {
+        let z = (row / self.stretch(plane)) as usize;
+        self.dims[2] - 1 - z.min(self.dims[2] - 1)
+    }
+
+    /// The middle row showing slice `z` in reformats of `plane`.
+    pub fn row_of_slice(&self, plane: MprPlane, z: usize) -> u32 {
+        let stretch = self.stretch(plane);
+        (self.dims[2] - 1 - z.min(self.dims[2] - 1)) as u32 * stretch + stretch / 2
+    }
+
+    /// The stored values of `plane` at `position`, read from `slices`.
+    pub fn reformat(&self, slices: &[Redactor], plane: MprPlane, position: usize) -> Gray16Image {
+        let (width, height) = self.view_size(plane);
+        let gray = |z: usize| {
+            let (r, f) = self.slices[z];
+            match slices[r].frame(f) {
+                Frame::Gray(img) => Some(img),
+                Frame::Rgb(_) => None,
+            }
+        };
+        if plane == MprPlane::Axial {
+            return gray(position.min(self.dims[2] - 1))
+                .cloned()
+                .unwrap_or_else(|| Gray16Image::new(width, height));
+        }
+        let mut out = Gray16Image::new(width, height);
+        for row in 0..height {
+            let Some(img) = gray(self.slice_at_row(plane, row)) else {
+                continue;
+            };
+            for col in 0..width {
+                let (x, y) = match plane {
+                    MprPlane::Coronal => (col, position as u32),
+                    _ => (position as u32, col),
+                };
+                if x < img.width() && y < img.height() {
+                    out.put_pixel(col, row, *img.get_pixel(x, y));
+                }
+            }
+        }
+        out
+    }
+
+    /// `region`, drawn on reformats of `plane` with its frames as positions through
+    /// the volume, as regions on the slices it covers: (redactor, region on one of its
+    /// frames). Shapes on the acquired slices carry over as they are; on the other
+    /// planes every slice gets a rectangle per span, as deep as the positions covered.
+    pub fn slice_regions(&self, plane: MprPlane, region: &Region) -> Vec<(usize, Region)> {
+        let positions = region.target_frames(self.positions(plane));
+        let on_slice = |z: usize, shape: Shape| {
+            let (r, f) = self.slices[z];
+            (
+                r,
+                Region {
+                    shape,
+                    frame: Some(f),
+                    last_frame: None,
+                },
+            )
+        };
+        if plane == MprPlane::Axial {
+            return positions
+                .map(|z| on_slice(z, region.shape.clone()))
+                .collect();
+        }
+        if positions.is_empty() {
+            return Vec::new();
+        }
+        let (width, height) = self.view_size(plane);
+        // Rows stretched from the same slice give the same spans
+        let mut spans = BTreeSet::new();
+        region.shape.for_each_span(width, height, |row, x0, x1| {
+            spans.insert((self.slice_at_row(plane, row), x0, x1));
+        });
+        let (p0, p1) = (positions.start as u32, positions.end as u32);
+        spans
+            .into_iter()
+            .map(|(z, a0, a1)| {
+                let rect = match plane {
+                    MprPlane::Coronal => RedactRect {
+                        x0: a0,
+                        y0: p0,
+                        x1: a1,
+                        y1: p1,
+                    },
+                    _ => RedactRect {
+                        x0: p0,
+                        y0: a0,
+                        x1: p1,
+                        y1: a1,
+                    },
+                };
+                on_slice(z, Shape::Rect(rect))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Three 4x3 axial slices, 0.5 mm pixels 1 mm apart
+    fn volume() -> Stack {
+        Stack {
+            slices: vec![(0, 0), (1, 0), (2, 0)],
+            dims: [4, 3, 3],
+            spacing: [0.5, 0.5, 1.0],
+            dirs: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+
+    fn rect(x0: u32, y0: u32, x1: u32, y1: u32) -> Shape {
+        Shape::Rect(RedactRect { x0, y0, x1, y1 })
+    }
+
+    fn on(shape: Shape, frame: usize) -> Region {
+        Region {
+            shape,
+            frame: Some(frame),
+            last_frame: None,
+        }
+    }
+
+    #[test]
+    fn the_normal_is_the_cross_product_of_row_and_column() {
+        let (row, col) = ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+        assert_eq!(cross(row, col), [0.0, 0.0, 1.0]);
+        assert_eq!(dot(cross(row, col), row), 0.0);
+    }
+
+    #[test]
+    fn voxel_indices_and_coordinates_agree() {
+        let stack = volume();
+        for i in 0..4 * 3 * 3 {
+            assert_eq!(stack.index(stack.coords(i)), i);
+        }
+        assert_eq!(stack.coords(13), [1, 0, 1]);
+        assert_eq!(
+            stack.line(2, 1, 2, true),
+            vec![[1, 2, 2], [1, 2, 1], [1, 2, 0]]
+        );
+        assert_eq!(stack.across(1), (4, 3));
+    }
+
+    #[test]
+    fn reformats_stretch_thick_slices_and_show_the_last_on_top() {
+        let stack = volume();
+        assert_eq!(stack.stretch(MprPlane::Axial), 1);
+        assert_eq!(stack.stretch(MprPlane::Coronal), 2);
+        assert_eq!(stack.view_size(MprPlane::Coronal), (4, 6));
+        assert_eq!(stack.view_size(MprPlane::Sagittal), (3, 6));
+        assert_eq!(stack.positions(MprPlane::Sagittal), 4);
+        assert_eq!(stack.slice_at_row(MprPlane::Coronal, 0), 2);
+        assert_eq!(stack.slice_at_row(MprPlane::Coronal, 5), 0);
+        assert_eq!(stack.slice_at_row(MprPlane::Coronal, 99), 0);
+        for z in 0..3 {
+            let row = stack.row_of_slice(MprPlane::Coronal, z);
+            assert_eq!(stack.slice_at_row(MprPlane::Coronal, row), z);
+        }
+    }
+
+    #[test]
+    fn axial_regions_carry_over_to_their_slices() {
+        let stack = volume();
+        let region = Region {
+            shape: rect(0, 0, 2, 2),
+            frame: Some(1),
+            last_frame: Some(2),
+        };
+        assert_eq!(
+            stack.slice_regions(MprPlane::Axial, &region),
+            vec![(1, on(rect(0, 0, 2, 2), 0)), (2, on(rect(0, 0, 2, 2), 0))]
+        );
+    }
+
+    #[test]
+    fn reformat_regions_become_rectangles_on_the_slices() {
+        let stack = volume();
+        // The top two rows of the coronal reformat at y = 1 are the last slice
+        assert_eq!(
+            stack.slice_regions(MprPlane::Coronal, &on(rect(1, 0, 3, 2), 1)),
+            vec![(2, on(rect(1, 1, 3, 2), 0))]
+        );
+        // The bottom two rows of the sagittal reformat at x = 3 are the first slice
+        assert_eq!(
+            stack.slice_regions(MprPlane::Sagittal, &on(rect(0, 4, 2, 6), 3)),
+            vec![(0, on(rect(3, 0, 4, 2), 0))]
+        );
+        assert!(
+            stack
+                .slice_regions(MprPlane::Coronal, &on(rect(0, 0, 4, 6), 7))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,786 @@
+//! Whole slide images (VL Whole Slide Microscopy): tiled multi-frame objects whose
+//! total pixel matrix is far too big to decode at once.
+//!
+//! Tiles are decoded on demand, for the part of the slide that is shown or redacted,
+//! and only tiles whose pixels changed are re-encoded on save; every other tile keeps
+//! its original bytes. Label and overview images are separate instances with a single
+//! frame and open like any other image.
+//!
+//! The label image photographs the slide label, which often carries the patient's
+//! name; the overview usually shows it too. [`associated_image`] recognizes both, so
+//! they can be blanked or left out ([`SlideLabels`]).
+//!
+//! Edits are made on a view of the first focal plane, which is written back into its
+//! tiles when the view moves or the file is saved. Regions are also filled on every
+//! other focal plane / optical path.
+
+use crate::audit::AppliedRegion;
+use crate::{
+    DCMRedactErrors, Frame, Gray16Image, PixelInfo, RedactFill, RedactRect, Rgb8Image, from_signed,
+    to_signed,
+};
+use dicom::core::value::PixelFragmentSequence;
+use dicom::core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::encoding::TransferSyntaxIndex;
+use dicom::object::{FileDicomObject, FileMetaTableBuilder, InMemDicomObject};
+use dicom::pixeldata::{PixelDecoder, Transcode};
+use dicom::transfer_syntax::{TransferSyntax, TransferSyntaxRegistry, entries};
+use image::imageops;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// VL Whole Slide Microscopy Image Storage.
+pub const VL_WHOLE_SLIDE_MICROSCOPY_IMAGE_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.77.1.6";
+
+/// Side of the largest part of a slide shown at once.
+pub const VIEW_SIZE: u32 = 2048;
+
+/// Decoded tiles kept around; the least recently used unredacted ones go first.
+const CACHED_TILES: usize = 512;
+
+/// Largest area (in pixels) redacted in one go on the other focal planes: the tiles
+/// under a region are decoded into a single canvas so fills spanning tile edges come
+/// out the same as on the view.
+const MAX_CANVAS_PIXELS: u64 = 8192 * 8192;
+
+fn int(obj: &InMemDicomObject, tag: Tag) -> Option<u32> {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_int::<u32>().ok())
+        .filter(|&v| v > 0)
+}
+
+/// What happens to the label and overview images of a slide on save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SlideLabels {
+    #[default]
+    Keep,
+    /// Fill the whole image with black.
+    Blank,
+    /// Leave the instance out of the output.
+    Remove,
+}
+
+impl SlideLabels {
+    pub const ALL: [SlideLabels; 3] = [SlideLabels::Keep, SlideLabels::Blank, SlideLabels::Remove];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SlideLabels::Keep => "Keep",
+            SlideLabels::Blank => "Blank",
+            SlideLabels::Remove => "Remove",
+        }
+    }
+}
+
+impl fmt::Display for SlideLabels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for SlideLabels {
+    type Err = String;
+
+    /// Parse `keep`, `blank` or `remove`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" => Ok(SlideLabels::Keep),
+            "blank" => Ok(SlideLabels::Blank),
+            "remove" => Ok(SlideLabels::Remove),
+            _ => Err(format!(
+                "unknown slide label handling '{s}' (expected keep, blank or remove)"
+            )),
+        }
+    }
+}
+
+/// `LABEL` or `OVERVIEW` if `obj` is that associated image of a slide, going by the
+/// third value of ImageType, or of FrameType in the shared functional groups.
+pub fn associated_image(obj: &InMemDicomObject) -> Option<&'static str> {
+    let flavor = |e: &dicom::object::mem::InMemElement| {
+        let values = e.to_multi_str().ok()?;
+        match values.get(2)?.trim() {
+            "LABEL" => Some("LABEL"),
+            "OVERVIEW" => Some("OVERVIEW"),
+            _ => None,
+        }
+    };
+    if let Some(found) = obj.element(tags::IMAGE_TYPE).ok().and_then(flavor) {
+        return Some(found);
+    }
+    let frame_type = obj
+        .element(tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE)
+        .ok()?
+        .items()?
+        .first()?
+        .element(tags::WHOLE_SLIDE_MICROSCOPY_IMAGE_FRAME_TYPE_SEQUENCE)
+        .ok()?
+        .items()?
+        .first()?
+        .element(tags::FRAME_TYPE)
+        .ok()?;
+    flavor(frame_type)
+}
+
+/// Whether `obj` is a tiled image (more than one frame of a total pixel matrix).
+pub fn is_tiled(obj: &InMemDicomObject) -> bool {
+    int(obj, tags::NUMBER_OF_FRAMES).is_some_and(|n| n > 1)
+        && int(obj, tags::TOTAL_PIXEL_MATRIX_COLUMNS).is_some()
+        && int(obj, tags::TOTAL_PIXEL_MATRIX_ROWS).is_some()
+}
+
+/// Top-left corner of every frame of a TILED_SPARSE image, from the plane position
+/// of its per-frame functional group.
+fn sparse_origins(obj: &InMemDicomObject, frames: usize) -> Option<Vec<(u32, u32)>> {
+    let items = obj
+        .element(tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE)
+        .ok()?
+        .items()?;
+    if items.len() != frames {
+        return None;
+    }
+    items
+        .iter()
+        .map(|item| {
+            let position = item
+                .element(tags::PLANE_POSITION_SLIDE_SEQUENCE)
+                .ok()?
+                .items()?
+                .first()?;
+            let at = |tag| {
+                position
+                    .element(tag)
+                    .ok()
+                    .and_then(|e| e.to_int::<i64>().ok())
+            };
+            // 1-based; tiles hanging over the top or left edge start before 1
+            Some((
+                (at(tags::COLUMN_POSITION_IN_TOTAL_IMAGE_PIXEL_MATRIX)? - 1).max(0) as u32,
+                (at(tags::ROW_POSITION_IN_TOTAL_IMAGE_PIXEL_MATRIX)? - 1).max(0) as u32,
+            ))
+        })
+        .collect()
+}
+
+/// Paste `src` into `dst` with its top-left corner at (x, y), clipped to `dst`.
+fn replace(dst: &mut Frame, src: &Frame, x: i64, y: i64) {
+    match (dst, src) {
+        (Frame::Gray(d), Frame::Gray(s)) => imageops::replace(d, s, x, y),
+        (Frame::Rgb(d), Frame::Rgb(s)) => imageops::replace(d, s, x, y),
+        _ => {}
+    }
+}
+
+/// The tile layout of a tiled image, with the tiles decoded so far.
+pub struct TiledImage {
+    tile: (u32, u32),
+    size: (u32, u32),
+    // Top-left corner of every frame in the total pixel matrix
+    origins: Vec<(u32, u32)>,
+    // Focal plane / optical path of every frame: the k-th frame at a position is in
+    // plane k. Views show plane 0.
+    planes: Vec<usize>,
+    plane_count: usize,
+    color: bool,
+    bits_allocated: u16,
+    bits_stored: u16,
+    signed: bool,
+    photometric: String,
+    // Decoded tiles, with when they were last used
+    tiles: HashMap<usize, (Frame, u64)>,
+    clock: u64,
+    // Tiles whose pixels were redacted; these stay decoded until saved
+    modified: BTreeSet<usize>,
+    // Regions filled so far (total pixel matrix coordinates)
+    burned: Vec<AppliedRegion>,
+}
+
+impl TiledImage {
+    /// The layout of tiled `obj`, whose pixel module is `info`. Nothing is decoded yet.
+    pub fn new(obj: &InMemDicomObject, info: &PixelInfo) -> Result<Self, DCMRedactErrors> {
+        let required = |tag: Tag, name: &str| {
+            int(obj, tag).ok_or_else(|| {
+                DCMRedactErrors::ValueError(format!("Tiled image without a valid {name}"))
+            })
+        };
+        let tile = (
+            required(tags::COLUMNS, "Columns")?,
+            required(tags::ROWS, "Rows")?,
+        );
+        let size = (
+            required(tags::TOTAL_PIXEL_MATRIX_COLUMNS, "TotalPixelMatrixColumns")?,
+            required(tags::TOTAL_PIXEL_MATRIX_ROWS, "TotalPixelMatrixRows")?,
+        );
+        let frames = required(tags::NUMBER_OF_FRAMES, "NumberOfFrames")? as usize;
+
+        let sparse = obj
+            .element(tags::DIMENSION_ORGANIZATION_TYPE)
+            .ok()
+            .and_then(|e| e.to_str().ok())
+            .is_some_and(|t| t.trim() == "TILED_SPARSE");
+        let origins = if sparse {
+            sparse_origins(obj, frames).ok_or_else(|| {
+                DCMRedactErrors::ValueError(
+                    "TILED_SPARSE image without a plane position for every frame".to_string(),
+                )
+            })?
+        } else {
+            // TILED_FULL: row by row, then plane by plane
+            let across = size.0.div_ceil(tile.0) as usize;
+            let down = size.1.div_ceil(tile.1) as usize;
+            (0..frames)
+                .map(|i| {
+                    let i = i % (across * down);
+                    ((i % across) as u32 * tile.0, (i / across) as u32 * tile.1)
+                })
+                .collect()
+        };
+        let mut seen: HashMap<(u32, u32), usize> = HashMap::new();
+        let planes: Vec<usize> = origins
+            .iter()
+            .map(|origin| {
+                let count = seen.entry(*origin).or_insert(0);
+                *count += 1;
+                *count - 1
+            })
+            .collect();
+        Ok(Self {
+            tile,
+            size,
+            plane_count: planes.iter().max().map_or(1, |p| p + 1),
+            origins,
+            planes,
+            color: info.is_color(),
+            bits_allocated: info.bits_allocated,
+            bits_stored: info.bits_stored,
+            signed: info.signed && !info.is_color(),
+            photometric: info.photometric_interpretation.clone(),
+            tiles: HashMap::new(),
+            clock: 0,
+            modified: BTreeSet::new(),
+            burned: Vec::new(),
+        })
+    }
+
+    /// Size of the total pixel matrix.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Size of one tile.
+    pub fn tile_size(&self) -> (u32, u32) {
+        self.tile
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.origins.len()
+    }
+
+    /// Number of tiles with redacted pixels, to be re-encoded on save.
+    pub fn modified_count(&self) -> usize {
+        self.modified.len()
+    }
+
+    /// Regions filled so far, in total pixel matrix coordinates.
+    pub fn burned(&self) -> &[AppliedRegion] {
+        &self.burned
+    }
+
+    /// The part of the slide shown with its top-left corner at (x, y), moved back
+    /// where needed to lie within the slide.
+    pub fn view_at(&self, x: u32, y: u32) -> RedactRect {
+        let (w, h) = (self.size.0.min(VIEW_SIZE), self.size.1.min(VIEW_SIZE));
+        let x0 = x.min(self.size.0 - w);
+        let y0 = y.min(self.size.1 - h);
+        RedactRect {
+            x0,
+            y0,
+            x1: x0 + w,
+            y1: y0 + h,
+        }
+    }
+
+    fn tile_rect(&self, i: usize) -> RedactRect {
+        let (x0, y0) = self.origins[i];
+        RedactRect {
+            x0,
+            y0,
+            x1: x0 + self.tile.0,
+            y1: y0 + self.tile.1,
+        }
+    }
+
+    /// Frames of `plane` overlapping `area`.
+    fn tiles_in(&self, area: RedactRect, plane: usize) -> Vec<usize> {
+        (0..self.origins.len())
+            .filter(|&i| self.planes[i] == plane)
+            .filter(|&i| {
+                let t = self.tile_rect(i);
+                t.x0 < area.x1 && area.x0 < t.x1 && t.y0 < area.y1 && area.y0 < t.y1
+            })
+            .collect()
+    }
+
+    fn decode(
+        &self,
+        obj: &FileDicomObject<InMemDicomObject>,
+        i: usize,
+    ) -> Result<Frame, DCMRedactErrors> {
+        let context = || format!("Failed to decode tile {}", i + 1);
+        let decoded = obj
+            .decode_pixel_data_frame(i as u32)
+            .map_err(|e| DCMRedactErrors::decode(context(), e))?;
+        if self.color {
+            let img = decoded
+                .to_dynamic_image(0)
+                .map_err(|e| DCMRedactErrors::decode(context(), e))?;
+            return Ok(Frame::Rgb(img.to_rgb8()));
+        }
+        let bytes = decoded
+            .frame_data(0)
+            .map_err(|e| DCMRedactErrors::decode(context(), e))?;
+        let mut values: Vec<u16> = if self.bits_allocated == 8 {
+            bytes.iter().map(|&b| u16::from(b)).collect()
+        } else {
+            bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect()
+        };
+        if self.signed {
+            for v in &mut values {
+                *v = from_signed(*v, self.bits_stored);
+            }
+        }
+        let (w, h) = self.tile;
+        Gray16Image::from_raw(w, h, values)
+            .map(Frame::Gray)
+            .ok_or_else(|| {
+                DCMRedactErrors::ValueError(format!("Tile {} does not match {w}x{h} pixels", i + 1))
+            })
+    }
+
+    /// Tile `i`, decoded if it isn't already.
+    fn tile(
+        &mut self,
+        obj: &FileDicomObject<InMemDicomObject>,
+        i: usize,
+    ) -> Result<&Frame, DCMRedactErrors> {
+        if !self.tiles.contains_key(&i) {
+            let frame = self.decode(obj, i)?;
+            self.tiles.insert(i, (frame, 0));
+        }
+        self.clock += 1;
+        let (frame, used) = self.tiles.get_mut(&i).expect("tile was just decoded");
+        *used = self.clock;
+        Ok(frame)
+    }
+
+    /// Drop the least recently used unredacted tiles beyond [`CACHED_TILES`].
+    fn evict(&mut self) {
+        let excess = self.tiles.len().saturating_sub(CACHED_TILES);
+        if excess == 0 {
+            return;
+        }
+        let mut unmodified: Vec<(u64, usize)> = self
+            .tiles
+            .iter()
+            .filter(|(i, _)| !self.modified.contains(i))
+            .map(|(&i, (_, used))| (*used, i))
+            .collect();
+        unmodified.sort_unstable();
+        for (_, i) in unmodified.into_iter().take(excess) {
+            self.tiles.remove(&i);
+        }
+    }
+
+    fn blank(&self, w: u32, h: u32) -> Frame {
+        if self.color {
+            Frame::Rgb(Rgb8Image::new(w, h))
+        } else {
+            Frame::Gray(Gray16Image::new(w, h))
+        }
+    }
+
+    /// The tiles `hits` of one plane pasted onto a canvas covering `area`.
+    fn compose(
+        &mut self,
+        obj: &FileDicomObject<InMemDicomObject>,
+        hits: &[usize],
+        area: RedactRect,
+    ) -> Result<Frame, DCMRedactErrors> {
+        let mut canvas = self.blank(area.x1 - area.x0, area.y1 - area.y0);
+        for &i in hits {
+            let (x, y) = self.origins[i];
+            let tile = self.tile(obj, i)?;
+            replace(
+                &mut canvas,
+                tile,
+                x as i64 - area.x0 as i64,
+                y as i64 - area.y0 as i64,
+            );
+        }
+        Ok(canvas)
+    }
+
+    /// The part `view` of the slide (plane 0), decoding only the tiles it covers.
+    pub fn render(
+        &mut self,
+        obj: &FileDicomObject<InMemDicomObject>,
+        view: RedactRect,
+    ) -> Result<Frame, DCMRedactErrors> {
+        let hits = self.tiles_in(view, 0);
+        let frame = self.compose(obj, &hits, view);
+        self.evict();
+        frame
+    }
+
+    /// Write the edited `frame` of `view` (from [`TiledImage::render`]) back into the
+    /// tiles of plane 0. Only tiles whose pixels changed are marked for re-encoding.
+    pub fn store(
+        &mut self,
+        obj: &FileDicomObject<InMemDicomObject>,
+        view: RedactRect,
+        frame: &Frame,
+    ) -> Result<(), DCMRedactErrors> {
+        for i in self.tiles_in(view, 0) {
+            let t = self.tile_rect(i);
+            // The part of the tile inside the view, in view coordinates
+            let overlap = RedactRect {
+                x0: t.x0.max(view.x0) - view.x0,
+                y0: t.y0.max(view.y0) - view.y0,
+                x1: t.x1.min(view.x1) - view.x0,
+                y1: t.y1.min(view.y1) - view.y0,
+            };
+            let patch = frame.crop(overlap);
+            let mut tile = self.tile(obj, i)?.clone();
+            tile.paste(
+                &patch,
+                overlap.x0 + view.x0 - t.x0,
+                overlap.y0 + view.y0 - t.y0,
+            );
+            if let Some((cached, _)) = self.tiles.get_mut(&i)
+                && *cached != tile
+            {
+                *cached = tile;
+                self.modified.insert(i);
+            }
+        }
+        self.evict();
+        Ok(())
+    }
+
+    /// Fill `region` (total pixel matrix coordinates) with `pixels` on every plane but
+    /// plane 0, whose tiles take the view's pixels through [`TiledImage::store`].
+    /// Regions already filled are skipped.
+    pub fn burn(
+        &mut self,
+        obj: &FileDicomObject<InMemDicomObject>,
+        region: &AppliedRegion,
+        pixels: RedactFill,
+    ) -> Result<(), DCMRedactErrors> {
+        if self.burned.contains(region) {
+            return Ok(());
+        }
+        let Some(bounds) = region.region.shape.bounds() else {
+            return Ok(());
+        };
+        for plane in 1..self.plane_count {
+            let hits = self.tiles_in(bounds, plane);
+            let Some(area) = hits
+                .iter()
+                .map(|&i| self.tile_rect(i))
+                .reduce(|a, b| RedactRect {
+                    x0: a.x0.min(b.x0),
+                    y0: a.y0.min(b.y0),
+                    x1: a.x1.max(b.x1),
+                    y1: a.y1.max(b.y1),
+                })
+            else {
+                continue;
+            };
+            let pixel_count = u64::from(area.x1 - area.x0) * u64::from(area.y1 - area.y0);
+            if pixel_count > MAX_CANVAS_PIXELS {
+                return Err(DCMRedactErrors::ValueError(format!(
+                    "Region {}x{} px is too large to redact on a tiled image at once; split it up",
+                    bounds.x1 - bounds.x0,
+                    bounds.y1 - bounds.y0
+                )));
+            }
+            let mut canvas = self.compose(obj, &hits, area)?;
+            let shape = region
+                .region
+                .shape
+                .translated(-(area.x0 as i64), -(area.y0 as i64));
+            canvas.fill_shape(&shape, pixels);
+            for i in hits {
+                let t = self.tile_rect(i);
+                let redacted = canvas.crop(RedactRect {
+                    x0: t.x0 - area.x0,
+                    y0: t.y0 - area.y0,
+                    x1: t.x1 - area.x0,
+                    y1: t.y1 - area.y0,
+                });
+                if let Some((tile, _)) = self.tiles.get_mut(&i)
+                    && *tile != redacted
+                {
+                    *tile = redacted;
+                    self.modified.insert(i);
+                }
+            }
+        }
+        self.burned.push(region.clone());
+        self.evict();
+        Ok(())
+    }
+
+    /// Replace the pixel data of `out` (a copy of the source header) with the source
+    /// tiles, re-encoding only the redacted ones into the source transfer syntax.
+    pub fn write_tiles(
+        &self,
+        out: &mut FileDicomObject<InMemDicomObject>,
+    ) -> Result<(), DCMRedactErrors> {
+        if self.modified.is_empty() {
+            return Ok(());
+        }
+        let uid = out
+            .meta()
+            .transfer_syntax()
+            .trim_end_matches('\0')
+            .to_string();
+        let ts = TransferSyntaxRegistry.get(&uid).ok_or_else(|| {
+            DCMRedactErrors::ValueError(format!("Unsupported transfer syntax {uid}"))
+        })?;
+        let pixel_data = out.element(tags::PIXEL_DATA).map_err(|_| {
+            DCMRedactErrors::ValueError("Tiled image without pixel data".to_string())
+        })?;
+
+        if let Some(fragments) = pixel_data.fragments() {
+            if fragments.len() != self.origins.len() {
+                return Err(DCMRedactErrors::ValueError(format!(
+                    "Tiled image has {} fragments for {} tiles; only one fragment per tile is supported",
+                    fragments.len(),
+                    self.origins.len()
+                )));
+            }
+            let mut fragments = fragments.to_vec();
+            for &i in &self.modified {
+                fragments[i] = self.encode_tile(&self.tiles[&i].0, ts)?;
+            }
+            out.put(DataElement::new(
+                tags::PIXEL_DATA,
+                VR::OB,
+                PixelFragmentSequence::new(Vec::<u32>::new(), fragments),
+            ));
+            // Fragment sizes changed, so the offsets no longer hold
+            out.remove_element(tags::EXTENDED_OFFSET_TABLE);
+            out.remove_element(tags::EXTENDED_OFFSET_TABLE_LENGTHS);
+            return Ok(());
+        }
+
+        if self.color && self.photometric != "RGB" {
+            return Err(DCMRedactErrors::ValueError(format!(
+                "Can't write redacted tiles back into native {} pixel data",
+                self.photometric
+            )));
+        }
+        let mut bytes = pixel_data
+            .to_bytes()
+            .map_err(|e| DCMRedactErrors::encode("Failed to read native pixel data", e))?
+            .into_owned();
+        for &i in &self.modified {
+            let native = self.native_samples(&self.tiles[&i].0);
+            let start = i * native.len();
+            let Some(target) = bytes.get_mut(start..start + native.len()) else {
+                return Err(DCMRedactErrors::ValueError(format!(
+                    "Native pixel data too short for tile {}",
+                    i + 1
+                )));
+            };
+            target.copy_from_slice(&native);
+        }
+        let vr = if self.bits_allocated == 8 {
+            VR::OB
+        } else {
+            VR::OW
+        };
+        out.put(DataElement::new(
+            tags::PIXEL_DATA,
+            vr,
+            PrimitiveValue::U8(bytes.into()),
+        ));
+        Ok(())
+    }
+
+    /// Little endian samples of `frame` as stored in native pixel data.
+    fn native_samples(&self, frame: &Frame) -> Vec<u8> {
+        match frame {
+            Frame::Rgb(img) => img.as_raw().clone(),
+            Frame::Gray(img) => {
+                let stored = img.as_raw().iter().map(|&v| {
+                    if self.signed {
+                        to_signed(v, self.bits_stored)
+                    } else {
+                        v
+                    }
+                });
+                if self.bits_allocated == 8 {
+                    stored.map(|v| v as u8).collect()
+                } else {
+                    stored.flat_map(|v| v.to_le_bytes()).collect()
+                }
+            }
+        }
+    }
+
+    /// Encode one tile into `ts` through a single-frame object, returning its bytes
+    /// as one fragment.
+    fn encode_tile(&self, frame: &Frame, ts: &TransferSyntax) -> Result<Vec<u8>, DCMRedactErrors> {
+        let (w, h) = frame.dimensions();
+        let us =
+            |tag, value: u32| DataElement::new(tag, VR::US, PrimitiveValue::from(value as u16));
+        let (samples, photometric, bits_allocated, bits_stored) = match frame {
+            Frame::Rgb(_) => (3, "RGB", 8, 8),
+            Frame::Gray(_) => (
+                1,
+                "MONOCHROME2",
+                u32::from(self.bits_allocated),
+                u32::from(self.bits_stored),
+            ),
+        };
+        let vr = if bits_allocated == 8 { VR::OB } else { VR::OW };
+        let tile = InMemDicomObject::from_element_iter([
+            us(tags::SAMPLES_PER_PIXEL, samples),
+            DataElement::new(
+                tags::PHOTOMETRIC_INTERPRETATION,
+                VR::CS,
+                PrimitiveValue::from(photometric),
+            ),
+            us(tags::ROWS, h),
+            us(tags::COLUMNS, w),
+            us(tags::BITS_ALLOCATED, bits_allocated),
+            us(tags::BITS_STORED, bits_stored),
+            us(tags::HIGH_BIT, bits_stored - 1),
+            us(tags::PIXEL_REPRESENTATION, u32::from(self.signed)),
+            us(tags::PLANAR_CONFIGURATION, 0),
+            DataElement::new(
+                tags::PIXEL_DATA,
+                vr,
+                PrimitiveValue::U8(self.native_samples(frame).into()),
+            ),
+        ]);
+        let mut file = tile
+            .with_meta(
+                FileMetaTableBuilder::new()
+                    .transfer_syntax(entries::EXPLICIT_VR_LITTLE_ENDIAN.uid())
+                    .media_storage_sop_class_uid(VL_WHOLE_SLIDE_MICROSCOPY_IMAGE_STORAGE)
+                    .media_storage_sop_instance_uid("2.25.0"),
+            )
+            .map_err(|e| DCMRedactErrors::encode("Failed to build a tile for encoding", e))?;
+        file.transcode(ts).map_err(|e| {
+            DCMRedactErrors::encode(format!("Failed to encode a tile as {}", ts.name()), e)
+        })?;
+        file.element(tags::PIXEL_DATA)
+            .ok()
+            .and_then(|e| e.fragments())
+            .map(|fragments| fragments.concat())
+            .ok_or_else(|| {
+                DCMRedactErrors::ValueError(format!("{} produced no encoded tile", ts.name()))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma};
+
+    fn slide(width: u32, height: u32, frames: u32) -> InMemDicomObject {
+        let number = |tag, value: u32| DataElement::new(tag, VR::UL, PrimitiveValue::from(value));
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(256u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(256u16)),
+            number(tags::TOTAL_PIXEL_MATRIX_COLUMNS, width),
+            number(tags::TOTAL_PIXEL_MATRIX_ROWS, height),
+            DataElement::new(
+                tags::NUMBER_OF_FRAMES,
+                VR::IS,
+                PrimitiveValue::from(frames.to_string()),
+            ),
+        ])
+    }
+
+    fn info() -> PixelInfo {
+        PixelInfo {
+            photometric_interpretation: "MONOCHROME2".to_string(),
+            samples_per_pixel: 1,
+            bits_allocated: 8,
+            bits_stored: 8,
+            signed: false,
+            transfer_syntax: "1.2.840.10008.1.2.1".to_string(),
+        }
+    }
+
+    fn rect(x0: u32, y0: u32, x1: u32, y1: u32) -> RedactRect {
+        RedactRect { x0, y0, x1, y1 }
+    }
+
+    #[test]
+    fn slide_label_handling_prints_and_parses_the_same() {
+        for labels in SlideLabels::ALL {
+            assert_eq!(labels.to_string().parse::<SlideLabels>(), Ok(labels));
+        }
+        assert_eq!(" Blank ".parse::<SlideLabels>(), Ok(SlideLabels::Blank));
+        assert!("hide".parse::<SlideLabels>().is_err());
+    }
+
+    #[test]
+    fn pasting_clips_to_the_destination() {
+        let mut dst = Frame::Gray(ImageBuffer::from_pixel(4, 4, Luma([0])));
+        let src = Frame::Gray(ImageBuffer::from_pixel(2, 2, Luma([9])));
+        replace(&mut dst, &src, 3, -1);
+        let dst = dst.as_gray().unwrap();
+        assert_eq!(dst.get_pixel(3, 0)[0], 9);
+        assert_eq!(dst.get_pixel(2, 0)[0], 0);
+        assert_eq!(dst.get_pixel(3, 1)[0], 0);
+    }
+
+    #[test]
+    fn tiled_full_frames_go_row_by_row_then_plane_by_plane() {
+        let obj = slide(600, 300, 12);
+        assert!(is_tiled(&obj));
+        let tiled = TiledImage::new(&obj, &info()).unwrap();
+        // 3 x 2 tiles per plane, two planes
+        assert_eq!(tiled.tile_count(), 12);
+        assert_eq!(tiled.tile_rect(4), rect(256, 256, 512, 512));
+        assert_eq!(tiled.tile_rect(10), rect(256, 256, 512, 512));
+        assert_eq!(tiled.planes[10], 1);
+        assert_eq!(
+            tiled.tiles_in(rect(250, 250, 260, 260), 0),
+            vec![0, 1, 3, 4]
+        );
+        assert_eq!(tiled.tiles_in(rect(520, 0, 600, 10), 1), vec![8]);
+    }
+
+    #[test]
+    fn views_stay_within_the_slide() {
+        let small = TiledImage::new(&slide(600, 300, 6), &info()).unwrap();
+        assert_eq!(small.view_at(100, 100), rect(0, 0, 600, 300));
+        let large = TiledImage::new(&slide(5000, 3000, 20 * 12), &info()).unwrap();
+        assert_eq!(
+            large.view_at(4000, 10),
+            rect(5000 - VIEW_SIZE, 10, 5000, 10 + VIEW_SIZE)
+        );
+    }
+
+    #[test]
+    fn tiled_images_need_a_total_pixel_matrix() {
+        let mut obj = slide(600, 300, 6);
+        obj.remove_element(tags::TOTAL_PIXEL_MATRIX_ROWS);
+        assert!(!is_tiled(&obj));
+        assert!(TiledImage::new(&obj, &info()).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use dcm_redact::fileio::MemoryFs;
+use dcm_redact::{Frame, Redactor};
+use eframe::egui::{self, Color32, ColorImage, Pos2, Rect, Sense, Stroke, TextureHandle};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+
+/// A file picked on the page once its contents are read: name and bytes.
+type Picked = Rc<RefCell<Option<(String, Vec<u8>)>>>;
+
+#[derive(Default)]
+pub struct WebApp {
+    files: MemoryFs,
+    picked: Picked,
+    path: Option<PathBuf>,
+    redactor: Option<Redactor>,
+    texture: Option<TextureHandle>,
+    staged: Vec<[u32; 4]>,       // x0, y0, x1, y1 in image pixels, exclusive
+    drag_from: Option<[u32; 2]>, // image pixel where the drag started
+    error: Option<String>,
+}
+
+/// The first frame for display: gray stretched over its full range, MONOCHROME1
+/// inverted.
+fn display_image(redactor: &Redactor) -> ColorImage {
+    let (w, h) = redactor.dimensions();
+    let size = [w as usize, h as usize];
+    match redactor.frame(0) {
+        Frame::Rgb(img) => ColorImage::from_rgb(size, img.as_raw()),
+        Frame::Gray(img) => {
+            let raw = img.as_raw();
+            let lo = raw.iter().copied().min().unwrap_or(0) as u32;
+            let hi = (raw.iter().copied().max().unwrap_or(0) as u32).max(lo + 1);
+            let invert = redactor.photometric_interpretation() == Some("MONOCHROME1");
+            let gray: Vec<u8> = raw
+                .iter()
+                .map(|&v| {
+                    let g = ((v as u32 - lo) * 255 / (hi - lo)) as u8;
+                    if invert { 255 - g } else { g }
+                })
+                .collect();
+            ColorImage::from_gray(size, &gray)
+        }
+    }
+}
+
+/// How long a download's object URL is kept after the click that starts it.
+const REVOKE_AFTER_MS: i32 = 60_000;
+
+/// Offer `bytes` to the browser as a download named `name`.
+fn download(name: &str, bytes: &[u8]) -> Result<(), wasm_bindgen::JsValue> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+    let window = web_sys::window().ok_or("no window")?;
+    let document = window.document().ok_or("no document")?;
+    let anchor: web_sys::HtmlAnchorElement = document.create_element("a")?.dyn_into()?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    // The browser may only start reading the blob after click() returns
+    let revoke = Closure::once_into_js(move || {
+        let _ = web_sys::Url::revoke_object_url(&url);
+    });
+    window.set_timeout_with_callback_and_timeout_and_arguments_0(
+        revoke.unchecked_ref(),
+        REVOKE_AFTER_MS,
+    )?;
+    Ok(())
+}
+
+impl WebApp {
+    fn pick(&self, ctx: &egui::Context) {
+        let picked = self.picked.clone();
+        let ctx = ctx.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new().pick_file().await {
+                let bytes = file.read().await;
+                *picked.borrow_mut() = Some((file.file_name(), bytes));
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    fn open(&mut self, ctx: &egui::Context, name: String, bytes: Vec<u8>) {
+        let path = PathBuf::from(name);
+        // Only the open file is kept in memory
+        if let Some(old) = self.path.take() {
+            self.files.take(&old);
+        }
+        self.files.insert(path.clone(), bytes);
+        match Redactor::open_in(&self.files, &path) {
+            Ok(redactor) => {
+                self.texture = Some(ctx.load_texture(
+                    "image",
+                    display_image(&redactor),
+                    egui::TextureOptions::LINEAR,
+                ));
+                self.redactor = Some(redactor);
+                self.path = Some(path);
+                self.staged.clear();
+                self.error = None;
+            }
+            Err(e) => {
+                self.files.take(&path);
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Burn the staged rectangles into every frame.
+    fn apply(&mut self, ctx: &egui::Context) {
+        let Some(redactor) = self.redactor.as_mut() else {
+            return;
+        };
+        for [x0, y0, x1, y1] in self.staged.drain(..) {
+            redactor.blacken_rect(x0, y0, x1, y1);
+        }
+        self.texture = Some(ctx.load_texture(
+            "image",
+            display_image(redactor),
+            egui::TextureOptions::LINEAR,
+        ));
+    }
+
+    /// Apply the staged rectangles and download the result, de-identified as the
+    /// desktop app saves it, under the same name.
+    fn save(&mut self, ctx: &egui::Context) {
+        self.apply(ctx);
+        let (Some(redactor), Some(path)) = (self.redactor.as_mut(), &self.path) else {
+            return;
+        };
+        let out = Path::new("redacted").join(path);
+        let result = redactor
+            .save_in(&mut self.files, &out)
+            .map_err(|e| e.to_string())
+            .and_then(|()| {
+                let bytes = self.files.take(&out).unwrap_or_default();
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                download(&name, &bytes).map_err(|e| format!("Download failed: {e:?}"))
+            });
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+
+    fn image_view(&mut self, ui: &mut egui::Ui) {
+        let (Some(texture), Some(redactor)) = (&self.texture, &self.redactor) else {
+            ui.centered_and_justified(|ui| {
+                ui.label("Open a DICOM file or image. It stays in this browser tab.");
+            });
+            return;
+        };
+        let (w, h) = redactor.dimensions();
+        let available = ui.available_size();
+        let scale = (available.x / w as f32).min(available.y / h as f32);
+        let (rect, response) =
+            ui.allocate_exact_size(egui::vec2(w as f32, h as f32) * scale, Sense::drag());
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+        ui.painter().image(texture.id(), rect, uv, Color32::WHITE);
+
+        let to_image = |p: Pos2| {
+            let v = (p - rect.min) / scale;
+            [(v.x.max(0.0) as u32).min(w), (v.y.max(0.0) as u32).min(h)]
+        };
+        let to_screen = |[x, y]: [u32; 2]| rect.min + egui::vec2(x as f32, y as f32) * scale;
+        let pointer = response.interact_pointer_pos().map(to_image);
+        if response.drag_started() {
+            self.drag_from = pointer;
+        }
+        let dragged = self
+            .drag_from
+            .zip(pointer)
+            .map(|([ax, ay], [bx, by])| [ax.min(bx), ay.min(by), ax.max(bx), ay.max(by)]);
+        if response.drag_stopped() {
+            if let Some(r) = dragged.filter(|[x0, y0, x1, y1]| x1 > x0 && y1 > y0) {
+                self.staged.push(r);
+            }
+            self.drag_from = None;
+        }
+        let stroke = Stroke::new(2.0, Color32::from_rgb(255, 80, 80));
+        for [x0, y0, x1, y1] in self.staged.iter().copied().chain(dragged) {
+            let r = Rect::from_min_max(to_screen([x0, y0]), to_screen([x1, y1]));
+            ui.painter().rect_stroke(r, 0.0, stroke);
+        }
+    }
+}
+
+impl eframe::App for WebApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let picked = self.picked.borrow_mut().take();
+        if let Some((name, bytes)) = picked {
+            self.open(ctx, name, bytes);
+        }
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Open…").clicked() {
+                    self.pick(ctx);
+                }
+                let open = self.redactor.is_some();
+                if ui
+                    .add_enabled(open && !self.staged.is_empty(), egui::Button::new("Apply"))
+                    .on_hover_text("Black out the rectangles drawn so far")
+                    .clicked()
+                {
+                    self.apply(ctx);
+                }
+                if ui
+                    .add_enabled(!self.staged.is_empty(), egui::Button::new("Clear"))
+                    .clicked()
+                {
+                    self.staged.clear();
+                }
+                if ui
+                    .add_enabled(open, egui::Button::new("Download"))
+                    .on_hover_text(
+                        "Apply the rectangles and download the file, with the header \
+                         de-identified by the Basic Profile",
+                    )
+                    .clicked()
+                {
+                    self.save(ctx);
+                }
+                if let Some(path) = &self.path {
+                    ui.label(path.display().to_string());
+                }
+            });
+            if let Some(error) = &self.error {
+                ui.colored_label(Color32::from_rgb(255, 80, 80), error);
+            }
+        });
+        egui::CentralPanel::default().show(ctx, |ui| self.image_view(ui));
+    }
+}
@@ -0,0 +1,27 @@
+//! Browser build of dcm-redact: open a DICOM file or image from the page, black out
+//! rectangles and download the result. Files are read and written in memory
+//! ([`dcm_redact::fileio::MemoryFs`]); nothing is uploaded anywhere.
+//!
+//! Build with `trunk build --release` in this folder; the page is in `dist/`.
+
+#[cfg(target_arch = "wasm32")]
+mod app;
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    wasm_bindgen_futures::spawn_local(async {
+        eframe::WebRunner::new()
+            .start(
+                "dcm_redact",
+                eframe::WebOptions::default(),
+                Box::new(|_cc| Ok(Box::new(app::WebApp::default()))),
+            )
+            .await
+            .expect("failed to start dcm-redact");
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    eprintln!("dcm-redact-web runs in the browser: build it with `trunk build --release`");
+}